@@ -11,6 +11,20 @@ export enum HttpCommand{
   HttpError= 3
 };
 
+/**
+ * @enum {number}
+ */
+export enum HttpValue{
+  NONE= 0,
+  HttpInt64Value= 1,
+  HttpFloat64Value= 2,
+  HttpDecimalValue= 3,
+  HttpTimestampValue= 4,
+  HttpBoolValue= 5,
+  HttpBytesValue= 6,
+  HttpNullValue= 7
+};
+
 /**
  * @constructor
  */
@@ -388,11 +402,31 @@ rowsLength():number {
   return offset ? this.bb!.__vector_len(this.bb_pos + offset) : 0;
 };
 
+/**
+ * @param number index
+ * @param flatbuffers.Encoding= optionalEncoding
+ * @returns string|Uint8Array
+ */
+columnTypes(index: number):string
+columnTypes(index: number,optionalEncoding:flatbuffers.Encoding):string|Uint8Array
+columnTypes(index: number,optionalEncoding?:any):string|Uint8Array|null {
+  var offset = this.bb!.__offset(this.bb_pos, 8);
+  return offset ? this.bb!.__string(this.bb!.__vector(this.bb_pos + offset) + index * 4, optionalEncoding) : null;
+};
+
+/**
+ * @returns number
+ */
+columnTypesLength():number {
+  var offset = this.bb!.__offset(this.bb_pos, 8);
+  return offset ? this.bb!.__vector_len(this.bb_pos + offset) : 0;
+};
+
 /**
  * @param flatbuffers.Builder builder
  */
 static startHttpResultSet(builder:flatbuffers.Builder) {
-  builder.startObject(2);
+  builder.startObject(3);
 };
 
 /**
@@ -453,6 +487,35 @@ static startRowsVector(builder:flatbuffers.Builder, numElems:number) {
   builder.startVector(4, numElems, 4);
 };
 
+/**
+ * @param flatbuffers.Builder builder
+ * @param flatbuffers.Offset columnTypesOffset
+ */
+static addColumnTypes(builder:flatbuffers.Builder, columnTypesOffset:flatbuffers.Offset) {
+  builder.addFieldOffset(2, columnTypesOffset, 0);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param Array.<flatbuffers.Offset> data
+ * @returns flatbuffers.Offset
+ */
+static createColumnTypesVector(builder:flatbuffers.Builder, data:flatbuffers.Offset[]):flatbuffers.Offset {
+  builder.startVector(4, data.length, 4);
+  for (var i = data.length - 1; i >= 0; i--) {
+    builder.addOffset(data[i]);
+  }
+  return builder.endVector();
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param number numElems
+ */
+static startColumnTypesVector(builder:flatbuffers.Builder, numElems:number) {
+  builder.startVector(4, numElems, 4);
+};
+
 /**
  * @param flatbuffers.Builder builder
  * @returns flatbuffers.Offset
@@ -462,10 +525,11 @@ static endHttpResultSet(builder:flatbuffers.Builder):flatbuffers.Offset {
   return offset;
 };
 
-static createHttpResultSet(builder:flatbuffers.Builder, columnsOffset:flatbuffers.Offset, rowsOffset:flatbuffers.Offset):flatbuffers.Offset {
+static createHttpResultSet(builder:flatbuffers.Builder, columnsOffset:flatbuffers.Offset, rowsOffset:flatbuffers.Offset, columnTypesOffset:flatbuffers.Offset):flatbuffers.Offset {
   HttpResultSet.startHttpResultSet(builder);
   HttpResultSet.addColumns(builder, columnsOffset);
   HttpResultSet.addRows(builder, rowsOffset);
+  HttpResultSet.addColumnTypes(builder, columnTypesOffset);
   return HttpResultSet.endHttpResultSet(builder);
 }
 }
@@ -623,11 +687,28 @@ stringValue(optionalEncoding?:any):string|Uint8Array|null {
   return offset ? this.bb!.__string(this.bb_pos + offset, optionalEncoding) : null;
 };
 
+/**
+ * @returns HttpValue
+ */
+valueType():HttpValue {
+  var offset = this.bb!.__offset(this.bb_pos, 6);
+  return offset ? /**  */ (this.bb!.readUint8(this.bb_pos + offset)) : HttpValue.NONE;
+};
+
+/**
+ * @param flatbuffers.Table obj
+ * @returns ?flatbuffers.Table
+ */
+value<T extends flatbuffers.Table>(obj:T):T|null {
+  var offset = this.bb!.__offset(this.bb_pos, 8);
+  return offset ? this.bb!.__union(obj, this.bb_pos + offset) : null;
+};
+
 /**
  * @param flatbuffers.Builder builder
  */
 static startHttpColumnValue(builder:flatbuffers.Builder) {
-  builder.startObject(1);
+  builder.startObject(3);
 };
 
 /**
@@ -638,6 +719,22 @@ static addStringValue(builder:flatbuffers.Builder, stringValueOffset:flatbuffers
   builder.addFieldOffset(0, stringValueOffset, 0);
 };
 
+/**
+ * @param flatbuffers.Builder builder
+ * @param HttpValue valueType
+ */
+static addValueType(builder:flatbuffers.Builder, valueType:HttpValue) {
+  builder.addFieldInt8(1, valueType, HttpValue.NONE);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param flatbuffers.Offset valueOffset
+ */
+static addValue(builder:flatbuffers.Builder, valueOffset:flatbuffers.Offset) {
+  builder.addFieldOffset(2, valueOffset, 0);
+};
+
 /**
  * @param flatbuffers.Builder builder
  * @returns flatbuffers.Offset
@@ -647,9 +744,574 @@ static endHttpColumnValue(builder:flatbuffers.Builder):flatbuffers.Offset {
   return offset;
 };
 
-static createHttpColumnValue(builder:flatbuffers.Builder, stringValueOffset:flatbuffers.Offset):flatbuffers.Offset {
+static createHttpColumnValue(builder:flatbuffers.Builder, stringValueOffset:flatbuffers.Offset, valueType:HttpValue, valueOffset:flatbuffers.Offset):flatbuffers.Offset {
   HttpColumnValue.startHttpColumnValue(builder);
   HttpColumnValue.addStringValue(builder, stringValueOffset);
+  HttpColumnValue.addValueType(builder, valueType);
+  HttpColumnValue.addValue(builder, valueOffset);
   return HttpColumnValue.endHttpColumnValue(builder);
 }
 }
+/**
+ * @constructor
+ */
+export class HttpInt64Value {
+  bb: flatbuffers.ByteBuffer|null = null;
+
+  bb_pos:number = 0;
+/**
+ * @param number i
+ * @param flatbuffers.ByteBuffer bb
+ * @returns HttpInt64Value
+ */
+__init(i:number, bb:flatbuffers.ByteBuffer):HttpInt64Value {
+  this.bb_pos = i;
+  this.bb = bb;
+  return this;
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpInt64Value= obj
+ * @returns HttpInt64Value
+ */
+static getRootAsHttpInt64Value(bb:flatbuffers.ByteBuffer, obj?:HttpInt64Value):HttpInt64Value {
+  return (obj || new HttpInt64Value()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpInt64Value= obj
+ * @returns HttpInt64Value
+ */
+static getSizePrefixedRootAsHttpInt64Value(bb:flatbuffers.ByteBuffer, obj?:HttpInt64Value):HttpInt64Value {
+  bb.setPosition(bb.position() + flatbuffers.SIZE_PREFIX_LENGTH);
+  return (obj || new HttpInt64Value()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @returns flatbuffers.Long
+ */
+value():flatbuffers.Long {
+  var offset = this.bb!.__offset(this.bb_pos, 4);
+  return offset ? this.bb!.readInt64(this.bb_pos + offset) : this.bb!.createLong(0, 0);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ */
+static startHttpInt64Value(builder:flatbuffers.Builder) {
+  builder.startObject(1);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param flatbuffers.Long value
+ */
+static addValue(builder:flatbuffers.Builder, value:flatbuffers.Long) {
+  builder.addFieldInt64(0, value, builder.createLong(0, 0));
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @returns flatbuffers.Offset
+ */
+static endHttpInt64Value(builder:flatbuffers.Builder):flatbuffers.Offset {
+  var offset = builder.endObject();
+  return offset;
+};
+
+static createHttpInt64Value(builder:flatbuffers.Builder, value:flatbuffers.Long):flatbuffers.Offset {
+  HttpInt64Value.startHttpInt64Value(builder);
+  HttpInt64Value.addValue(builder, value);
+  return HttpInt64Value.endHttpInt64Value(builder);
+}
+}
+/**
+ * @constructor
+ */
+export class HttpFloat64Value {
+  bb: flatbuffers.ByteBuffer|null = null;
+
+  bb_pos:number = 0;
+/**
+ * @param number i
+ * @param flatbuffers.ByteBuffer bb
+ * @returns HttpFloat64Value
+ */
+__init(i:number, bb:flatbuffers.ByteBuffer):HttpFloat64Value {
+  this.bb_pos = i;
+  this.bb = bb;
+  return this;
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpFloat64Value= obj
+ * @returns HttpFloat64Value
+ */
+static getRootAsHttpFloat64Value(bb:flatbuffers.ByteBuffer, obj?:HttpFloat64Value):HttpFloat64Value {
+  return (obj || new HttpFloat64Value()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpFloat64Value= obj
+ * @returns HttpFloat64Value
+ */
+static getSizePrefixedRootAsHttpFloat64Value(bb:flatbuffers.ByteBuffer, obj?:HttpFloat64Value):HttpFloat64Value {
+  bb.setPosition(bb.position() + flatbuffers.SIZE_PREFIX_LENGTH);
+  return (obj || new HttpFloat64Value()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @returns number
+ */
+value():number {
+  var offset = this.bb!.__offset(this.bb_pos, 4);
+  return offset ? this.bb!.readFloat64(this.bb_pos + offset) : 0.0;
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ */
+static startHttpFloat64Value(builder:flatbuffers.Builder) {
+  builder.startObject(1);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param number value
+ */
+static addValue(builder:flatbuffers.Builder, value:number) {
+  builder.addFieldFloat64(0, value, 0.0);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @returns flatbuffers.Offset
+ */
+static endHttpFloat64Value(builder:flatbuffers.Builder):flatbuffers.Offset {
+  var offset = builder.endObject();
+  return offset;
+};
+
+static createHttpFloat64Value(builder:flatbuffers.Builder, value:number):flatbuffers.Offset {
+  HttpFloat64Value.startHttpFloat64Value(builder);
+  HttpFloat64Value.addValue(builder, value);
+  return HttpFloat64Value.endHttpFloat64Value(builder);
+}
+}
+/**
+ * @constructor
+ */
+export class HttpDecimalValue {
+  bb: flatbuffers.ByteBuffer|null = null;
+
+  bb_pos:number = 0;
+/**
+ * @param number i
+ * @param flatbuffers.ByteBuffer bb
+ * @returns HttpDecimalValue
+ */
+__init(i:number, bb:flatbuffers.ByteBuffer):HttpDecimalValue {
+  this.bb_pos = i;
+  this.bb = bb;
+  return this;
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpDecimalValue= obj
+ * @returns HttpDecimalValue
+ */
+static getRootAsHttpDecimalValue(bb:flatbuffers.ByteBuffer, obj?:HttpDecimalValue):HttpDecimalValue {
+  return (obj || new HttpDecimalValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpDecimalValue= obj
+ * @returns HttpDecimalValue
+ */
+static getSizePrefixedRootAsHttpDecimalValue(bb:flatbuffers.ByteBuffer, obj?:HttpDecimalValue):HttpDecimalValue {
+  bb.setPosition(bb.position() + flatbuffers.SIZE_PREFIX_LENGTH);
+  return (obj || new HttpDecimalValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @returns flatbuffers.Long
+ */
+value():flatbuffers.Long {
+  var offset = this.bb!.__offset(this.bb_pos, 4);
+  return offset ? this.bb!.readInt64(this.bb_pos + offset) : this.bb!.createLong(0, 0);
+};
+
+/**
+ * @returns number
+ */
+scale():number {
+  var offset = this.bb!.__offset(this.bb_pos, 6);
+  return offset ? this.bb!.readInt32(this.bb_pos + offset) : 0;
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ */
+static startHttpDecimalValue(builder:flatbuffers.Builder) {
+  builder.startObject(2);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param flatbuffers.Long value
+ */
+static addValue(builder:flatbuffers.Builder, value:flatbuffers.Long) {
+  builder.addFieldInt64(0, value, builder.createLong(0, 0));
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param number scale
+ */
+static addScale(builder:flatbuffers.Builder, scale:number) {
+  builder.addFieldInt32(1, scale, 0);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @returns flatbuffers.Offset
+ */
+static endHttpDecimalValue(builder:flatbuffers.Builder):flatbuffers.Offset {
+  var offset = builder.endObject();
+  return offset;
+};
+
+static createHttpDecimalValue(builder:flatbuffers.Builder, value:flatbuffers.Long, scale:number):flatbuffers.Offset {
+  HttpDecimalValue.startHttpDecimalValue(builder);
+  HttpDecimalValue.addValue(builder, value);
+  HttpDecimalValue.addScale(builder, scale);
+  return HttpDecimalValue.endHttpDecimalValue(builder);
+}
+}
+/**
+ * @constructor
+ */
+export class HttpTimestampValue {
+  bb: flatbuffers.ByteBuffer|null = null;
+
+  bb_pos:number = 0;
+/**
+ * @param number i
+ * @param flatbuffers.ByteBuffer bb
+ * @returns HttpTimestampValue
+ */
+__init(i:number, bb:flatbuffers.ByteBuffer):HttpTimestampValue {
+  this.bb_pos = i;
+  this.bb = bb;
+  return this;
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpTimestampValue= obj
+ * @returns HttpTimestampValue
+ */
+static getRootAsHttpTimestampValue(bb:flatbuffers.ByteBuffer, obj?:HttpTimestampValue):HttpTimestampValue {
+  return (obj || new HttpTimestampValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpTimestampValue= obj
+ * @returns HttpTimestampValue
+ */
+static getSizePrefixedRootAsHttpTimestampValue(bb:flatbuffers.ByteBuffer, obj?:HttpTimestampValue):HttpTimestampValue {
+  bb.setPosition(bb.position() + flatbuffers.SIZE_PREFIX_LENGTH);
+  return (obj || new HttpTimestampValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @returns flatbuffers.Long
+ */
+micros():flatbuffers.Long {
+  var offset = this.bb!.__offset(this.bb_pos, 4);
+  return offset ? this.bb!.readInt64(this.bb_pos + offset) : this.bb!.createLong(0, 0);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ */
+static startHttpTimestampValue(builder:flatbuffers.Builder) {
+  builder.startObject(1);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param flatbuffers.Long micros
+ */
+static addMicros(builder:flatbuffers.Builder, micros:flatbuffers.Long) {
+  builder.addFieldInt64(0, micros, builder.createLong(0, 0));
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @returns flatbuffers.Offset
+ */
+static endHttpTimestampValue(builder:flatbuffers.Builder):flatbuffers.Offset {
+  var offset = builder.endObject();
+  return offset;
+};
+
+static createHttpTimestampValue(builder:flatbuffers.Builder, micros:flatbuffers.Long):flatbuffers.Offset {
+  HttpTimestampValue.startHttpTimestampValue(builder);
+  HttpTimestampValue.addMicros(builder, micros);
+  return HttpTimestampValue.endHttpTimestampValue(builder);
+}
+}
+/**
+ * @constructor
+ */
+export class HttpBoolValue {
+  bb: flatbuffers.ByteBuffer|null = null;
+
+  bb_pos:number = 0;
+/**
+ * @param number i
+ * @param flatbuffers.ByteBuffer bb
+ * @returns HttpBoolValue
+ */
+__init(i:number, bb:flatbuffers.ByteBuffer):HttpBoolValue {
+  this.bb_pos = i;
+  this.bb = bb;
+  return this;
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpBoolValue= obj
+ * @returns HttpBoolValue
+ */
+static getRootAsHttpBoolValue(bb:flatbuffers.ByteBuffer, obj?:HttpBoolValue):HttpBoolValue {
+  return (obj || new HttpBoolValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpBoolValue= obj
+ * @returns HttpBoolValue
+ */
+static getSizePrefixedRootAsHttpBoolValue(bb:flatbuffers.ByteBuffer, obj?:HttpBoolValue):HttpBoolValue {
+  bb.setPosition(bb.position() + flatbuffers.SIZE_PREFIX_LENGTH);
+  return (obj || new HttpBoolValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @returns boolean
+ */
+value():boolean {
+  var offset = this.bb!.__offset(this.bb_pos, 4);
+  return offset ? !!this.bb!.readInt8(this.bb_pos + offset) : false;
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ */
+static startHttpBoolValue(builder:flatbuffers.Builder) {
+  builder.startObject(1);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param boolean value
+ */
+static addValue(builder:flatbuffers.Builder, value:boolean) {
+  builder.addFieldInt8(0, +value, +false);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @returns flatbuffers.Offset
+ */
+static endHttpBoolValue(builder:flatbuffers.Builder):flatbuffers.Offset {
+  var offset = builder.endObject();
+  return offset;
+};
+
+static createHttpBoolValue(builder:flatbuffers.Builder, value:boolean):flatbuffers.Offset {
+  HttpBoolValue.startHttpBoolValue(builder);
+  HttpBoolValue.addValue(builder, value);
+  return HttpBoolValue.endHttpBoolValue(builder);
+}
+}
+/**
+ * @constructor
+ */
+export class HttpBytesValue {
+  bb: flatbuffers.ByteBuffer|null = null;
+
+  bb_pos:number = 0;
+/**
+ * @param number i
+ * @param flatbuffers.ByteBuffer bb
+ * @returns HttpBytesValue
+ */
+__init(i:number, bb:flatbuffers.ByteBuffer):HttpBytesValue {
+  this.bb_pos = i;
+  this.bb = bb;
+  return this;
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpBytesValue= obj
+ * @returns HttpBytesValue
+ */
+static getRootAsHttpBytesValue(bb:flatbuffers.ByteBuffer, obj?:HttpBytesValue):HttpBytesValue {
+  return (obj || new HttpBytesValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpBytesValue= obj
+ * @returns HttpBytesValue
+ */
+static getSizePrefixedRootAsHttpBytesValue(bb:flatbuffers.ByteBuffer, obj?:HttpBytesValue):HttpBytesValue {
+  bb.setPosition(bb.position() + flatbuffers.SIZE_PREFIX_LENGTH);
+  return (obj || new HttpBytesValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param number index
+ * @returns number
+ */
+value(index: number):number|null {
+  var offset = this.bb!.__offset(this.bb_pos, 4);
+  return offset ? this.bb!.readUint8(this.bb!.__vector(this.bb_pos + offset) + index) : 0;
+};
+
+/**
+ * @returns number
+ */
+valueLength():number {
+  var offset = this.bb!.__offset(this.bb_pos, 4);
+  return offset ? this.bb!.__vector_len(this.bb_pos + offset) : 0;
+};
+
+/**
+ * @returns Uint8Array
+ */
+valueArray():Uint8Array|null {
+  var offset = this.bb!.__offset(this.bb_pos, 4);
+  return offset ? new Uint8Array(this.bb!.bytes().buffer, this.bb!.bytes().byteOffset + this.bb!.__vector(this.bb_pos + offset), this.bb!.__vector_len(this.bb_pos + offset)) : null;
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ */
+static startHttpBytesValue(builder:flatbuffers.Builder) {
+  builder.startObject(1);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param flatbuffers.Offset valueOffset
+ */
+static addValue(builder:flatbuffers.Builder, valueOffset:flatbuffers.Offset) {
+  builder.addFieldOffset(0, valueOffset, 0);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param Array.<number> data
+ * @returns flatbuffers.Offset
+ */
+static createValueVector(builder:flatbuffers.Builder, data:number[]|Uint8Array):flatbuffers.Offset {
+  builder.startVector(1, data.length, 1);
+  for (var i = data.length - 1; i >= 0; i--) {
+    builder.addInt8(data[i]);
+  }
+  return builder.endVector();
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @param number numElems
+ */
+static startValueVector(builder:flatbuffers.Builder, numElems:number) {
+  builder.startVector(1, numElems, 1);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @returns flatbuffers.Offset
+ */
+static endHttpBytesValue(builder:flatbuffers.Builder):flatbuffers.Offset {
+  var offset = builder.endObject();
+  return offset;
+};
+
+static createHttpBytesValue(builder:flatbuffers.Builder, valueOffset:flatbuffers.Offset):flatbuffers.Offset {
+  HttpBytesValue.startHttpBytesValue(builder);
+  HttpBytesValue.addValue(builder, valueOffset);
+  return HttpBytesValue.endHttpBytesValue(builder);
+}
+}
+/**
+ * @constructor
+ */
+export class HttpNullValue {
+  bb: flatbuffers.ByteBuffer|null = null;
+
+  bb_pos:number = 0;
+/**
+ * @param number i
+ * @param flatbuffers.ByteBuffer bb
+ * @returns HttpNullValue
+ */
+__init(i:number, bb:flatbuffers.ByteBuffer):HttpNullValue {
+  this.bb_pos = i;
+  this.bb = bb;
+  return this;
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpNullValue= obj
+ * @returns HttpNullValue
+ */
+static getRootAsHttpNullValue(bb:flatbuffers.ByteBuffer, obj?:HttpNullValue):HttpNullValue {
+  return (obj || new HttpNullValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param flatbuffers.ByteBuffer bb
+ * @param HttpNullValue= obj
+ * @returns HttpNullValue
+ */
+static getSizePrefixedRootAsHttpNullValue(bb:flatbuffers.ByteBuffer, obj?:HttpNullValue):HttpNullValue {
+  bb.setPosition(bb.position() + flatbuffers.SIZE_PREFIX_LENGTH);
+  return (obj || new HttpNullValue()).__init(bb.readInt32(bb.position()) + bb.position(), bb);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ */
+static startHttpNullValue(builder:flatbuffers.Builder) {
+  builder.startObject(0);
+};
+
+/**
+ * @param flatbuffers.Builder builder
+ * @returns flatbuffers.Offset
+ */
+static endHttpNullValue(builder:flatbuffers.Builder):flatbuffers.Offset {
+  var offset = builder.endObject();
+  return offset;
+};
+
+static createHttpNullValue(builder:flatbuffers.Builder):flatbuffers.Offset {
+  HttpNullValue.startHttpNullValue(builder);
+  return HttpNullValue.endHttpNullValue(builder);
+}
+}
@@ -50,7 +50,7 @@ export class CubeStoreDriver extends BaseDriver implements DriverInterface {
       password: config?.password || getEnv('cubeStorePass'),
     };
     this.baseUrl = (this.config.url || `ws://${this.config.host || 'localhost'}:${this.config.port || '3030'}/`).replace(/\/ws$/, '/').replace(/\/$/, '');
-    this.connection = new WebSocketConnection(`${this.baseUrl}/ws`);
+    this.connection = new WebSocketConnection(`${this.baseUrl}/ws`, { typedValues: this.config.typedValues });
   }
 
   public async testConnection() {
@@ -1,6 +1,78 @@
 import WebSocket from 'ws';
 import { flatbuffers } from 'flatbuffers';
-import { HttpCommand, HttpError, HttpMessage, HttpQuery, HttpResultSet } from '../codegen/HttpMessage';
+import {
+  HttpBoolValue,
+  HttpBytesValue,
+  HttpColumnValue,
+  HttpCommand,
+  HttpDecimalValue,
+  HttpError,
+  HttpFloat64Value,
+  HttpInt64Value,
+  HttpMessage,
+  HttpQuery,
+  HttpResultSet,
+  HttpTimestampValue,
+  HttpValue,
+} from '../codegen/HttpMessage';
+
+export type WebSocketConnectionOptions = {
+  /**
+   * Receive numbers, booleans, timestamps and bytes as typed values instead of strings.
+   */
+  typedValues?: boolean;
+};
+
+function longToBigInt(long: flatbuffers.Long): bigint {
+  return BigInt.asIntN(64, (BigInt(long.high) << BigInt(32)) | BigInt(long.low >>> 0));
+}
+
+function bigIntToValue(value: bigint): number | string {
+  if (value >= BigInt(Number.MIN_SAFE_INTEGER) && value <= BigInt(Number.MAX_SAFE_INTEGER)) {
+    return Number(value);
+  }
+  return value.toString();
+}
+
+function decimalToString(value: bigint, scale: number): string {
+  const negative = value < BigInt(0);
+  const digits = (negative ? -value : value).toString().padStart(scale + 1, '0');
+  let result = digits;
+  if (scale > 0) {
+    result = `${digits.slice(0, -scale)}.${digits.slice(-scale)}`.replace(/\.?0+$/, '');
+  }
+  return negative ? `-${result}` : result;
+}
+
+/**
+ * Values without a typed representation, e.g. strings and dates, are still sent as strings.
+ */
+export function columnValue(value: HttpColumnValue): any {
+  switch (value.valueType()) {
+    case HttpValue.NONE:
+      return value.stringValue();
+    case HttpValue.HttpNullValue:
+      return null;
+    case HttpValue.HttpInt64Value:
+      return bigIntToValue(longToBigInt(value.value(new HttpInt64Value())!.value()));
+    case HttpValue.HttpFloat64Value:
+      return value.value(new HttpFloat64Value())!.value();
+    case HttpValue.HttpDecimalValue: {
+      const decimal = value.value(new HttpDecimalValue())!;
+      return decimalToString(longToBigInt(decimal.value()), decimal.scale());
+    }
+    case HttpValue.HttpTimestampValue: {
+      const micros = longToBigInt(value.value(new HttpTimestampValue())!.micros());
+      return new Date(Number(micros / BigInt(1000))).toISOString();
+    }
+    case HttpValue.HttpBoolValue:
+      return value.value(new HttpBoolValue())!.value();
+    case HttpValue.HttpBytesValue:
+      return Buffer.from(value.value(new HttpBytesValue())!.valueArray() || []);
+    default:
+      throw new Error(`Unsupported value type: ${value.valueType()}`);
+  }
+}
 
 export class WebSocketConnection {
   protected messageCounter: number;
@@ -9,8 +81,8 @@ export class WebSocketConnection {
 
   private url: string;
 
-  public constructor(url: string) {
-    this.url = url;
+  public constructor(url: string, options: WebSocketConnectionOptions = {}) {
+    this.url = options.typedValues ? `${url}?typed_values=true` : url;
     this.messageCounter = 1;
   }
 
@@ -112,7 +184,7 @@ export class WebSocketConnection {
               const rowObj = {};
               for (let j = 0; j < valueLen; j++) {
                 const value = row.values(j);
-                rowObj[columns[j]] = value?.stringValue();
+                rowObj[columns[j]] = value && columnValue(value);
               }
               result.push(rowObj);
             }
@@ -22,4 +22,10 @@ export interface ConnectionConfig {
    * The password
    */
   password?: string;
+
+  /**
+   * Receive numbers, booleans, timestamps and bytes as typed values instead of strings.
+   * (Default: false)
+   */
+  typedValues?: boolean;
 }
@@ -0,0 +1,76 @@
+import { flatbuffers } from 'flatbuffers';
+import {
+  HttpBoolValue,
+  HttpBytesValue,
+  HttpColumnValue,
+  HttpDecimalValue,
+  HttpFloat64Value,
+  HttpInt64Value,
+  HttpNullValue,
+  HttpTimestampValue,
+  HttpValue,
+} from '../codegen/HttpMessage';
+import { columnValue } from '../src/WebSocketConnection';
+
+function long(value: bigint): flatbuffers.Long {
+  const unsigned = BigInt.asUintN(64, value);
+  return flatbuffers.Long.create(
+    Number(BigInt.asIntN(32, unsigned)),
+    Number(BigInt.asIntN(32, unsigned >> BigInt(32))),
+  );
+}
+
+function roundTrip(build: (builder: flatbuffers.Builder) => flatbuffers.Offset): any {
+  const builder = new flatbuffers.Builder(1024);
+  builder.finish(build(builder));
+  return columnValue(HttpColumnValue.getRootAsHttpColumnValue(new flatbuffers.ByteBuffer(builder.asUint8Array())));
+}
+
+function typed(valueType: HttpValue, value: (builder: flatbuffers.Builder) => flatbuffers.Offset) {
+  return roundTrip((builder) => {
+    const valueOffset = value(builder);
+    HttpColumnValue.startHttpColumnValue(builder);
+    HttpColumnValue.addValueType(builder, valueType);
+    HttpColumnValue.addValue(builder, valueOffset);
+    return HttpColumnValue.endHttpColumnValue(builder);
+  });
+}
+
+describe('WebSocketConnection', () => {
+  test('string values', () => {
+    expect(roundTrip((builder) => {
+      const stringOffset = builder.createString('foo');
+      HttpColumnValue.startHttpColumnValue(builder);
+      HttpColumnValue.addStringValue(builder, stringOffset);
+      return HttpColumnValue.endHttpColumnValue(builder);
+    })).toEqual('foo');
+  });
+
+  test('typed values', () => {
+    expect(typed(HttpValue.HttpNullValue, (b) => HttpNullValue.createHttpNullValue(b))).toEqual(null);
+    expect(typed(HttpValue.HttpInt64Value, (b) => HttpInt64Value.createHttpInt64Value(b, long(BigInt(-42))))).toEqual(-42);
+    expect(
+      typed(HttpValue.HttpInt64Value, (b) => HttpInt64Value.createHttpInt64Value(b, long(BigInt('9223372036854775807'))))
+    ).toEqual('9223372036854775807');
+    expect(typed(HttpValue.HttpFloat64Value, (b) => HttpFloat64Value.createHttpFloat64Value(b, 1.5))).toEqual(1.5);
+    expect(
+      typed(HttpValue.HttpDecimalValue, (b) => HttpDecimalValue.createHttpDecimalValue(b, long(BigInt(-12340)), 5))
+    ).toEqual('-0.1234');
+    expect(
+      typed(HttpValue.HttpDecimalValue, (b) => HttpDecimalValue.createHttpDecimalValue(b, long(BigInt(1200000)), 5))
+    ).toEqual('12');
+    expect(
+      typed(
+        HttpValue.HttpTimestampValue,
+        (b) => HttpTimestampValue.createHttpTimestampValue(b, long(BigInt('1609459200123456')))
+      )
+    ).toEqual('2021-01-01T00:00:00.123Z');
+    expect(typed(HttpValue.HttpBoolValue, (b) => HttpBoolValue.createHttpBoolValue(b, true))).toEqual(true);
+    expect(
+      typed(HttpValue.HttpBytesValue, (b) => HttpBytesValue.createHttpBytesValue(
+        b,
+        HttpBytesValue.createValueVector(b, [0x01, 0xff])
+      ))
+    ).toEqual(Buffer.from([0x01, 0xff]));
+  });
+});
@@ -17,7 +17,7 @@ use crate::error::Result;
 use crate::instance::HllInstance::{Dense, Sparse};
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 use itertools::Itertools;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 use std::cmp::{max, min};
 use std::collections::HashSet;
 use std::convert::TryInto;
@@ -67,6 +67,17 @@ impl HllInstance {
         };
     }
 
+    pub fn insert_hash(&mut self, hash: u64) {
+        match self {
+            Sparse(s) => s.insert_hash(hash),
+            Dense(d) => {
+                d.insert_hash(hash);
+                return;
+            }
+        }
+        self.make_dense_if_necessary();
+    }
+
     /// Returns true iff `self.make_dense_if_necessary` has to be run.
     /// See comments inside the function for explanation on why we need this.
     fn merge_with_prepare(&mut self, o: &HllInstance) -> bool {
@@ -112,10 +123,6 @@ impl HllInstance {
                 v
             )));
         }
-        const ENC_EMPTY: u8 = 1;
-        const ENC_EXPLICIT: u8 = 2;
-        const ENC_SPARSE: u8 = 3;
-        const ENC_FULL: u8 = 4;
         let encoding = match data[0] & 0x0F {
             0 => {
                 return Err(HllError::new(
@@ -270,6 +277,111 @@ impl HllInstance {
         }
     }
 
+    /// Writes the sketch in the format of the Postgres `hll` extension, i.e. HLL storage
+    /// specification, version 1.
+    /// Registers are 5 bits wide as in the defaults of the extension, larger values are truncated.
+    /// The extension does the same when adding elements, so sketches can still be merged there.
+    pub fn write_hll_storage_spec(&self) -> Vec<u8> {
+        const REG_WIDTH: u8 = 5;
+        const MAX_VALUE: u8 = (1 << REG_WIDTH) - 1;
+        // Sparse representation enabled, explicit cutoff chosen automatically.
+        const CUTOFF: u8 = 0x7f;
+
+        let log_num_buckets = self.index_bit_len();
+        let values = self.bucket_values();
+        let num_set = values.iter().filter(|v| **v != 0).count();
+        let sparse_entry_len = (log_num_buckets + REG_WIDTH) as usize;
+
+        let encoding;
+        let mut w = BitWriter::new();
+        if num_set == 0 {
+            encoding = ENC_EMPTY;
+        } else if num_set * sparse_entry_len < values.len() * REG_WIDTH as usize {
+            encoding = ENC_SPARSE;
+            for (bucket, v) in values.iter().enumerate() {
+                if *v != 0 {
+                    let e = (bucket as u64) << REG_WIDTH | min(*v, MAX_VALUE) as u64;
+                    w.write_bits(e, sparse_entry_len);
+                }
+            }
+        } else {
+            encoding = ENC_FULL;
+            for v in values {
+                w.write_bits(min(v, MAX_VALUE) as u64, REG_WIDTH as usize);
+            }
+        }
+
+        let mut r = vec![
+            1 << 4 | encoding,
+            (REG_WIDTH - 1) << 5 | log_num_buckets,
+            CUTOFF,
+        ];
+        r.extend_from_slice(&w.finish());
+        return r;
+    }
+
+    /// Writes the sketch in the JSON format produced by `HLL_EXPORT` in Snowflake.
+    /// Note that Snowflake itself only uses sketches with 4096 buckets.
+    pub fn write_snowflake(&self) -> String {
+        #[derive(Serialize)]
+        struct SerializedHll {
+            version: u8,
+            precision: u8,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            sparse: Option<SparseEntries>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            dense: Option<Vec<u8>>,
+        }
+        #[derive(Serialize)]
+        #[allow(non_snake_case)]
+        struct SparseEntries {
+            indices: Vec<u32>,
+            maxLzCounts: Vec<u8>,
+        }
+
+        let values = self.bucket_values();
+        let mut ser = SerializedHll {
+            version: 4,
+            precision: self.index_bit_len(),
+            sparse: None,
+            dense: None,
+        };
+        match self {
+            Sparse(_) => {
+                let mut sparse = SparseEntries {
+                    indices: Vec::new(),
+                    maxLzCounts: Vec::new(),
+                };
+                for (bucket, v) in values.into_iter().enumerate() {
+                    if v != 0 {
+                        sparse.indices.push(bucket as u32);
+                        sparse.maxLzCounts.push(v);
+                    }
+                }
+                ser.sparse = Some(sparse);
+            }
+            Dense(_) => ser.dense = Some(values),
+        }
+        return serde_json::to_string(&ser).unwrap();
+    }
+
+    /// Values of all buckets, i.e. the number of leading zeros + 1 or 0 for empty buckets.
+    fn bucket_values(&self) -> Vec<u8> {
+        let mut values = vec![0; self.num_buckets() as usize];
+        match self {
+            Sparse(s) => s.each_bucket(|bucket, v| {
+                let b = &mut values[bucket as usize];
+                *b = max(*b, v);
+            }),
+            Dense(d) => {
+                for (bucket, b) in values.iter_mut().enumerate() {
+                    *b = d.get_value(bucket as u32) as u8;
+                }
+            }
+        }
+        return values;
+    }
+
     pub fn read(data: &[u8]) -> Result<HllInstance> {
         if data.is_empty() {
             return Err(HllError::new("hll input data is empty"));
@@ -357,13 +469,21 @@ impl SparseHll {
 
         // Turn indices into the entries array inplace.
         let mut entries = indices;
+        let bits = SparseHll::EXTENDED_PREFIX_BITS - index_bit_len;
         for i in 0..entries.len() {
             // TODO: validate range of index values.
-            // High bits are bucket index, followed by zeros and the actual value.
-            // Airlift stores actual bits of the hash in the available bits, but inputs of this
-            // function do not have this information, so we set those bits to 0.
-            let bucket = entries[i];
-            entries[i] = (bucket << (32 - index_bit_len)) | (values[i] as u32);
+            // Airlift keeps the bits of the hash that follow the bucket index and only encodes the
+            // number of zeros after them, so the bucket value is only derived from the zeros when
+            // all those bits are 0. Inputs of this function only have the number of leading zeros
+            // + 1, so we recreate the bits of a hash with the same number of leading zeros.
+            // Storing the value in the low bits as is would make each bucket `bits + 1` larger.
+            let bucket = entries[i] << bits;
+            let v = values[i];
+            entries[i] = if 1 <= v && v <= bits {
+                SparseHll::encode_entry(bucket | (1 << (bits - v)), 0)
+            } else {
+                SparseHll::encode_entry(bucket, v.saturating_sub(bits + 1))
+            };
         }
 
         // Sort by bucket index.
@@ -423,6 +543,24 @@ impl SparseHll {
         self.entries = self.merge_entries(o);
     }
 
+    pub fn insert_hash(&mut self, hash: u64) {
+        // Sparse entries keep the leading EXTENDED_PREFIX_BITS of the hash as the bucket index.
+        let bucket = compute_index(hash, SparseHll::EXTENDED_PREFIX_BITS);
+        let zeros = number_of_leading_zeros(hash, SparseHll::EXTENDED_PREFIX_BITS);
+        let entry = SparseHll::encode_entry(bucket, zeros);
+        match self
+            .entries
+            .binary_search_by_key(&bucket, |e| SparseHll::decode_bucket_index(*e))
+        {
+            Ok(i) => {
+                if SparseHll::decode_bucket_value(self.entries[i]) < zeros {
+                    self.entries[i] = entry;
+                }
+            }
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
     pub fn to_dense(&self) -> DenseHll {
         // TODO: this can panic if Sparse HLL had too much precision.
         let mut d = DenseHll::new(self.index_bit_len);
@@ -827,8 +965,7 @@ impl DenseHll {
         }
     }
 
-    #[allow(dead_code)]
-    fn insert_hash(&mut self, hash: u64) {
+    pub fn insert_hash(&mut self, hash: u64) {
         let index = compute_index(hash, self.index_bit_len);
         let value = compute_value(hash, self.index_bit_len);
 
@@ -1127,7 +1264,6 @@ fn index_bit_length(n: u32) -> Result<u8> {
     }
 }
 
-#[allow(dead_code)]
 fn compute_index(hash: u64, index_bit_len: u8) -> u32 {
     return (hash >> (64 - index_bit_len)) as u32;
 }
@@ -1136,7 +1272,6 @@ fn compute_value(hash: u64, index_bit_len: u8) -> u8 {
     return number_of_leading_zeros(hash, index_bit_len) + 1;
 }
 
-#[allow(dead_code)]
 fn number_of_leading_zeros(hash: u64, index_bit_len: u8) -> u8 {
     // place a 1 in the LSB to preserve the original number of leading zeros if the hash happens to be 0.
     let value = (hash << index_bit_len) | (1 << (index_bit_len - 1));
@@ -1162,6 +1297,12 @@ fn linear_counting(zero_buckets: u32, total_buckets: u32) -> f64 {
 }
 
 // const TAG_SPARSE_V1: u8 = 0; // Unsupported.
+// Encodings of the HLL storage specification.
+const ENC_EMPTY: u8 = 1;
+const ENC_EXPLICIT: u8 = 2;
+const ENC_SPARSE: u8 = 3;
+const ENC_FULL: u8 = 4;
+
 const TAG_DENSE_V1: u8 = 1;
 const TAG_SPARSE_V2: u8 = 2;
 const TAG_DENSE_V2: u8 = 3;
@@ -1207,13 +1348,48 @@ impl BitCursor<'_> {
     }
 }
 
+/// Writes bits starting from the most significant ones, the reverse of [BitCursor].
+struct BitWriter {
+    output: Vec<u8>,
+    bit_pos: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter {
+            output: Vec::new(),
+            bit_pos: 0,
+        }
+    }
+
+    pub fn write_bits(&mut self, value: u64, mut num_bits: usize) {
+        debug_assert!(num_bits <= 64);
+        while num_bits != 0 {
+            if self.bit_pos == 0 {
+                self.output.push(0);
+            }
+            let write_bits = min(num_bits, 8 - self.bit_pos);
+            let b = (value >> (num_bits - write_bits)) & ((1 << write_bits) - 1);
+            *self.output.last_mut().unwrap() |= (b << (8 - self.bit_pos - write_bits)) as u8;
+            num_bits -= write_bits;
+
+            self.bit_pos = (self.bit_pos + write_bits) % 8;
+        }
+    }
+
+    /// The last byte is padded with zeros.
+    pub fn finish(self) -> Vec<u8> {
+        return self.output;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instance::{compute_index, compute_value, number_of_buckets};
     use std::cmp::max;
 
     mod serialization {
-        use crate::instance::HllInstance;
+        use crate::instance::{DenseHll, HllInstance};
 
         #[test]
         fn test_snowflake() {
@@ -1234,9 +1410,9 @@ mod tests {
             assert_eq!(
                 &sparse.entries,
                 &[
-                    233832449, 771751938, 1023410177, 1091567620, 1317011458, 1638924290,
-                    1898971139, 2335178753, 2440036353, 2552233986, 2647654404, 2785017858,
-                    3089104897, 3118465025, 3414163458, 3926917123, 3954180098, 4263510017
+                    234356736, 772014080, 1023934464, 1091633152, 1317273600, 1639186432,
+                    1899102208, 2335703040, 2440560640, 2552496128, 2647719936, 2785280000,
+                    3089629184, 3118989312, 3414425600, 3927048192, 3954442240, 4264034304
                 ]
             );
             assert_eq!(sparse.to_dense().cardinality(), 18);
@@ -1327,6 +1503,108 @@ mod tests {
             let h = read("148b7f21083288a4320a12086719c65108c1088422884511063388232904418c8520484184862886528c65198832106328c83114e6214831108518d03208851948511884188441908119083388661842818c43190c320ce4210a50948221083084a421c8328c632104221c4120d01284e20902318ca5214641942319101294641906228483184e128c43188e308882204a538c8328903288642102220c64094631086330c832106320c46118443886329062118a230c63108a320c23204a11852419c6528c85210a318c6308c41088842086308ce7110a418864190650884210ca631064108642a1022186518c8509862109020a0a4318671144150842400e5090631a0811848320c821888120c81114a220880290622906310d0220c83090a118c433106128c221902210cc23106029044114841104409862190c43188111063104c310c6728c8618c62290441102310c23214440882438ca2110a32908548c432110329462188a43946328842114640944320884190c928c442084228863318a2190a318c6618ca3114651886618c44190c5108e2110612144319062284641908428882314862106419883310421988619ca420cc511442104633888218c4428465288651910730c81118821088218c6418c45108452106519ce410d841904218863308622086211483198c710c83104a328c620906218864118623086418c8711423094632186420c4620c41104620a441108e40882628c6311c212046428c8319021104672888428ca320c431984418c4209043084451886510c641108310c4c20c66188472146310ca71084820c621946218c8228822190e2410861904411c27288621144328c6440c6311063190813086228ca710c2218c4718865188c2114850888608864404a3194e22882310ce53088619ca31904519503188e1118c4214cb2948110c6119c2818c843108520c43188c5204821186528c871908311086214c630c4218c8418cc3298a31888210c63110a121042198622886531082098c419c4210c6210c8338c25294610944518c442104610884104424206310c8311462288873102308c2440c451082228824310440982220c4240c622084310c642850118c641148430d0128c8228c2120c221884428863208c21a0a4190a4404c21186548865204633906308ca32086211c8319ce22146520c6120803318a518c840084519461208c21908538cc428c2110844384e40906320c44014a3204e62042408c8328c632146318c812004310c41318e3208a5308a511827104a4188c51048421446090a7088631102231484104473084318c41210860906919083190652906129c4628c45310652848221443114420084500865184a618c81198c32906418c63190e320c231882728484184671888309465188a320c83208632144318c6331c642988108c61218812144328d022844021022184a31908328c6218c2328c4528cc541428190641046418c84108443146230c6419483214232184411863290a210824318c220868194631106618c43188821048230c4128c6310c0330462094241106330c42188c321043118863046438823110a041464108e3190e4209a11902439c43188631104321008090441106218c6419064294a229463594622244320cc71184510902924421908218c62308641044328ca328882111012884120ca52882428c62184442086718c4221c8211082208a321023115270086218c4218c6528ce400482310a520c43104a520c44210811884118c4310864198263942331822").unwrap();
             assert_eq!(h.cardinality(), 9722);
         }
+
+        #[test]
+        fn test_write_hll_storage_spec() {
+            let roundtrip = |s: &str| {
+                let h = HllInstance::read_hll_storage_spec(&hex::decode(s).unwrap()).unwrap();
+                let written = h.write_hll_storage_spec();
+                let read = HllInstance::read_hll_storage_spec(&written).unwrap();
+                assert_eq!(read.bucket_values(), h.bucket_values());
+                assert_eq!(read.cardinality(), h.cardinality());
+                hex::encode(written)
+            };
+
+            assert_eq!(roundtrip("118b7f"), "118b7f");
+            // Explicit encoding is written as sparse, we do not keep the hashes.
+            assert_eq!(roundtrip("128b7fee22c470691a8134"), "138b7fee24");
+            let sparse = "138b7f04a10642078507c308e309230a420ac10c2510a2114511611363138116811848188218a119411a821ae11f0122e223a125a126632685276327a328e2296129e52b812fe23081320132c133e335a53641368236a23721374237e1382138e13a813c243e6140e341854304434148a24a034f8150c1520152e254e155a1564157e158e35ac25b265b615c615fc1620166a368226a416a626c016c816d677163728275817a637a817ac37b617c247c427d677f6180e18101826382e1846184e18541858287e1880189218a418b818bc38e018ea290a19244938295e4988198c299e29b239b419c419ce49da1a1e1a321a381a4c1aa61acc2ae01b0a1b101b142b161b443b801bd02bd61bf61c263c4a3c501c7a1caa1cb03cd03cf03cf42d123d4c3d662d744d901dd01df81e001e0a2e641e7e3edc1f0a2f1c1f203f484f5c4f763fc84fdc1fe02fea1";
+            assert_eq!(roundtrip(sparse), sparse);
+
+            // Full encoding.
+            let mut h = HllInstance::new(2048).unwrap();
+            for i in 0..10_000u64 {
+                h.insert_hash(i.wrapping_mul(0x9e3779b97f4a7c15));
+            }
+            assert!(matches!(h, HllInstance::Dense(_)));
+            let written = h.write_hll_storage_spec();
+            assert_eq!(hex::encode(&written[0..3]), "148b7f");
+            let read = HllInstance::read_hll_storage_spec(&written).unwrap();
+            assert_eq!(read.bucket_values(), h.bucket_values());
+        }
+
+        #[test]
+        fn test_write_snowflake() {
+            // Hashes with the bucket index in the high bits, followed by `value - 1` zeros.
+            let mut sparse = HllInstance::new(4096).unwrap();
+            for (bucket, value) in [(223u64, 1), (736, 2), (4066, 17)] {
+                sparse.insert_hash(bucket << 52 | 1 << (52 - value));
+            }
+            assert!(matches!(sparse, HllInstance::Sparse(_)));
+            assert_eq!(
+                sparse.write_snowflake(),
+                r#"{"version":4,"precision":12,"sparse":{"indices":[223,736,4066],"maxLzCounts":[1,2,17]}}"#
+            );
+            // Imported sparse sketches keep the bucket values.
+            let read = HllInstance::read_snowflake(&sparse.write_snowflake()).unwrap();
+            assert_eq!(read.bucket_values(), sparse.bucket_values());
+
+            let mut values = vec![0; 16];
+            values[3] = 2;
+            values[7] = 30;
+            let dense = HllInstance::Dense(DenseHll::new_from_entries(4, values).unwrap());
+            let written = dense.write_snowflake();
+            assert_eq!(
+                written,
+                r#"{"version":4,"precision":4,"dense":[0,0,0,2,0,0,0,30,0,0,0,0,0,0,0,0]}"#
+            );
+            let read = HllInstance::read_snowflake(&written).unwrap();
+            assert_eq!(read.bucket_values(), dense.bucket_values());
+        }
+    }
+
+    mod sparse {
+        use crate::instance::tests::TestingHll;
+        use crate::instance::{HllInstance, SparseHll};
+        use std::hash::Hasher;
+        use twox_hash::XxHash64;
+
+        fn hash(i: i32) -> u64 {
+            let mut hasher = XxHash64::default();
+            hasher.write_i32(i);
+            hasher.finish()
+        }
+
+        #[test]
+        fn test_insert() {
+            for prefix_bit_len in 4..17 {
+                let mut testing_hll = TestingHll::new(prefix_bit_len);
+                let mut hll = SparseHll::new(prefix_bit_len).unwrap();
+                for i in 0..1_000 {
+                    testing_hll.insert_hash(hash(i));
+                    hll.insert_hash(hash(i));
+                    // Same hash twice does not change the sketch.
+                    hll.insert_hash(hash(i));
+                }
+
+                let mut buckets = vec![0; testing_hll.buckets().len()];
+                hll.each_bucket(|bucket, value| {
+                    buckets[bucket as usize] = std::cmp::max(buckets[bucket as usize], value as u32)
+                });
+                assert_eq!(&buckets, testing_hll.buckets());
+            }
+        }
+
+        #[test]
+        fn test_becomes_dense() {
+            let mut hll = HllInstance::new(4096).unwrap();
+            for i in 0..100_000 {
+                hll.insert_hash(hash(i));
+            }
+            assert!(matches!(hll, HllInstance::Dense(_)));
+            let error = (hll.cardinality() as f64 - 100_000.).abs() / 100_000.;
+            assert!(error < 0.05, "cardinality: {}", hll.cardinality());
+        }
     }
 
     mod dense {
@@ -1559,7 +1837,7 @@ mod tests {
             assert_eq!(hll.cardinality(), 655);
         }
     }
-    // TODO: port tests for Sparse HLLs and HLLInstance.
+    // TODO: port the remaining tests for Sparse HLLs and HLLInstance.
 
     struct TestingHll {
         index_bit_length: u8,
@@ -15,8 +15,10 @@
 mod bias_correction;
 mod error;
 mod instance;
+mod murmur3;
 mod sketch;
 
 pub use error::HllError;
 pub use error::Result;
+pub use murmur3::hash64 as murmur3_hash64;
 pub use sketch::HllSketch;
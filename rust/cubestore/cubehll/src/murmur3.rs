@@ -0,0 +1,95 @@
+/*
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! 64-bit variant of MurmurHash3 x64_128 as implemented by Airlift's `Murmur3Hash128.hash64()`,
+//! i.e. the first half of the 128-bit hash with a zero seed.
+use byteorder::{ByteOrder, LittleEndian};
+
+const C1: u64 = 0x87c37b91114253d5;
+const C2: u64 = 0x4cf5ad432745937f;
+
+pub fn hash64(data: &[u8]) -> u64 {
+    let mut h1: u64 = 0;
+    let mut h2: u64 = 0;
+
+    let mut blocks = data.chunks_exact(16);
+    for b in &mut blocks {
+        let k1 = LittleEndian::read_u64(&b[0..8]);
+        let k2 = LittleEndian::read_u64(&b[8..16]);
+
+        h1 ^= mix_k1(k1);
+        h1 = h1.rotate_left(27).wrapping_add(h2);
+        h1 = h1.wrapping_mul(5).wrapping_add(0x52dce729);
+
+        h2 ^= mix_k2(k2);
+        h2 = h2.rotate_left(31).wrapping_add(h1);
+        h2 = h2.wrapping_mul(5).wrapping_add(0x38495ab5);
+    }
+
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let mut k1: u64 = 0;
+        let mut k2: u64 = 0;
+        for (i, b) in tail.iter().enumerate() {
+            if i < 8 {
+                k1 |= (*b as u64) << (8 * i);
+            } else {
+                k2 |= (*b as u64) << (8 * (i - 8));
+            }
+        }
+        h2 ^= mix_k2(k2);
+        h1 ^= mix_k1(k1);
+    }
+
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    return h1.wrapping_add(h2);
+}
+
+fn mix_k1(k1: u64) -> u64 {
+    return k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+}
+
+fn mix_k2(k2: u64) -> u64 {
+    return k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+}
+
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51afd7ed558ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ceb9fe1a85ec53);
+    k ^= k >> 33;
+    return k;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::murmur3::hash64;
+
+    #[test]
+    fn test_hash64() {
+        assert_eq!(hash64(b""), 0);
+        assert_eq!(hash64(b"a"), 0x85555565f6597889);
+        assert_eq!(hash64(b"hello"), 0xcbd8a7b341bd9b02);
+        assert_eq!(
+            hash64(b"The quick brown fox jumps over the lazy dog"),
+            0xe34bbc7bbc071b6c
+        );
+    }
+}
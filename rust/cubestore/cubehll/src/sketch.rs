@@ -14,14 +14,15 @@
 
 use crate::error::Result;
 use crate::instance::HllInstance;
+use crate::murmur3;
 
 /// HyperLogLog sketch estimates a size of a set (i.e. the number of unique elements in it) without
 /// storing all the elements in the set.
 ///
 /// Port of the HyperLogLog from Airlift.
 /// You can deserialize sketches produced by Airlift by using `read()`.
-/// In fact, the code to add new elements has not been ported yet, so reading existing sketches is
-/// the only way to produce non-empty sets at this point.
+/// Elements added with `insert()` are hashed the same way as in `approx_set` of Presto, so the
+/// resulting sketches can be merged with the ones produced there.
 #[derive(Debug, Clone)]
 pub struct HllSketch {
     instance: HllInstance,
@@ -68,11 +69,32 @@ impl HllSketch {
         return self.instance.write();
     }
 
+    /// Write in the binary format of the Postgres `hll` extension, i.e. HLL Storage Specification.
+    pub fn write_hll_storage_spec(&self) -> Vec<u8> {
+        return self.instance.write_hll_storage_spec();
+    }
+
+    /// Write in the snowflake JSON format, accepted by HLL_IMPORT.
+    pub fn write_snowflake(&self) -> String {
+        return self.instance.write_snowflake();
+    }
+
     /// Produces an estimate of the current set size.
     pub fn cardinality(&self) -> u64 {
         return self.instance.cardinality();
     }
 
+    /// Adds an element represented by its binary encoding, e.g. little-endian bytes of a number
+    /// or UTF-8 bytes of a string.
+    pub fn insert(&mut self, data: &[u8]) {
+        self.insert_hash(murmur3::hash64(data));
+    }
+
+    /// Adds an element with a precomputed 64-bit hash.
+    pub fn insert_hash(&mut self, hash: u64) {
+        self.instance.insert_hash(hash);
+    }
+
     /// Merges elements from `o` into the current sketch.
     /// Afterwards the current sketch estimates the size of the union.
     ///
@@ -6,7 +6,7 @@ use cubestore::queryplanner::pretty_printers::{pp_phys_plan, pp_phys_plan_ext, P
 use cubestore::queryplanner::MIN_TOPK_STREAM_ROWS;
 use cubestore::sql::timestamp_from_string;
 use cubestore::store::DataFrame;
-use cubestore::table::{Row, TableValue, TimestampValue};
+use cubestore::table::{DateValue, Row, TableValue, TimestampValue};
 use cubestore::util::decimal::Decimal;
 use cubestore::CubeError;
 use indoc::indoc;
@@ -75,6 +75,7 @@ pub fn sql_tests() -> Vec<(&'static str, TestFn)> {
         ),
         t("ambiguous_join_sort", ambiguous_join_sort),
         t("join_with_aliases", join_with_aliases),
+        t("hash_join", hash_join),
         t("group_by_without_aggregates", group_by_without_aggregates),
         t("create_table_with_location", create_table_with_location),
         t(
@@ -94,8 +95,16 @@ pub fn sql_tests() -> Vec<(&'static str, TestFn)> {
             "create_table_with_csv_no_header",
             create_table_with_csv_no_header,
         ),
+        t("create_table_with_ndjson", create_table_with_ndjson),
         t("create_table_with_url", create_table_with_url),
         t("create_table_fail_and_retry", create_table_fail_and_retry),
+        t("alter_table_seal", alter_table_seal),
+        t("delete_rows", delete_rows),
+        t("update_rows", update_rows),
+        t("delete_rows_aggregate_index", delete_rows_aggregate_index),
+        t("insert_select", insert_select),
+        t("alter_table_columns", alter_table_columns),
+        t("date_int32_list_types", date_int32_list_types),
         t("empty_crash", empty_crash),
         t("bytes", bytes),
         t("hyperloglog", hyperloglog),
@@ -113,6 +122,10 @@ pub fn sql_tests() -> Vec<(&'static str, TestFn)> {
         t("hyperloglog_inplace_group_by", hyperloglog_inplace_group_by),
         t("hyperloglog_postgres", hyperloglog_postgres),
         t("hyperloglog_snowflake", hyperloglog_snowflake),
+        t("tdigest", tdigest),
+        t("aggregate_index_tdigest", aggregate_index_tdigest),
+        t("hyperloglog_init", hyperloglog_init),
+        t("hyperloglog_export", hyperloglog_export),
         t("planning_inplace_aggregate", planning_inplace_aggregate),
         t("planning_hints", planning_hints),
         t("planning_inplace_aggregate2", planning_inplace_aggregate2),
@@ -1607,6 +1620,62 @@ async fn join_with_aliases(service: Box<dyn SqlClient>) {
     assert_eq!(result.get_rows()[0], Row::new(vec![TableValue::Int(50)]));
 }
 
+async fn hash_join(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA foo").await.unwrap();
+    // Neither table has an index sorted by the join columns.
+    service
+        .exec_query("CREATE TABLE foo.orders (id int, city text, amount int)")
+        .await
+        .unwrap();
+    service
+        .exec_query("CREATE TABLE foo.cities (id int, name text, state text)")
+        .await
+        .unwrap();
+
+    service
+        .exec_query(
+            "INSERT INTO foo.orders (id, city, amount) VALUES \
+             (1, 'San Francisco', 10), (2, 'New York', 2), (3, 'New York', 3), (4, 'Boston', 7)",
+        )
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO foo.cities (id, name, state) VALUES \
+             (1, 'New York', 'NY'), (2, 'San Francisco', 'CA'), (3, 'Los Angeles', 'CA')",
+        )
+        .await
+        .unwrap();
+
+    let p = service
+        .plan_query("SELECT o.id, c.state FROM foo.orders o JOIN foo.cities c ON o.city = c.name")
+        .await
+        .unwrap();
+    let worker = pp_phys_plan(p.worker.as_ref());
+    assert!(worker.contains("HashJoin"), "{}", worker);
+
+    let r = service
+        .exec_query(
+            "SELECT c.state, SUM(o.amount) FROM foo.orders o JOIN foo.cities c ON o.city = c.name \
+             GROUP BY 1 ORDER BY 1",
+        )
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[("CA", 10), ("NY", 5)]));
+
+    let r = service
+        .exec_query(
+            "SELECT o.id, c.state FROM foo.orders o LEFT JOIN foo.cities c ON o.city = c.name \
+             ORDER BY 1",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        rows(&[(1, Some("CA")), (2, Some("NY")), (3, Some("NY")), (4, None)])
+    );
+}
+
 async fn group_by_without_aggregates(service: Box<dyn SqlClient>) {
     service.exec_query("CREATE SCHEMA foo").await.unwrap();
 
@@ -1863,6 +1932,34 @@ async fn create_table_with_csv_no_header(service: Box<dyn SqlClient>) {
     );
 }
 
+async fn create_table_with_ndjson(service: Box<dyn SqlClient>) {
+    let file = write_tmp_file(indoc! {r#"
+        {"number": 2, "fruit": "apple", "color": "red"}
+        {"fruit": "banana", "number": "3"}
+    "#})
+    .unwrap();
+    let path = file.path().to_string_lossy();
+    let _ = service
+        .exec_query("CREATE SCHEMA IF NOT EXISTS test")
+        .await
+        .unwrap();
+    let _ = service
+        .exec_query(format!("CREATE TABLE test.table (`fruit` text, `number` int) WITH (input_format = 'ndjson') LOCATION '{}'", path).as_str())
+        .await
+        .unwrap();
+    let result = service
+        .exec_query("SELECT * FROM test.table")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&result),
+        vec![
+            vec![TableValue::String("apple".to_string()), TableValue::Int(2)],
+            vec![TableValue::String("banana".to_string()), TableValue::Int(3)]
+        ]
+    );
+}
+
 async fn create_table_with_url(service: Box<dyn SqlClient>) {
     let url = "https://data.wprdc.org/dataset/0b584c84-7e35-4f4d-a5a2-b01697470c0f/resource/e95dd941-8e47-4460-9bd8-1e51c194370b/download/bikepghpublic.csv";
 
@@ -1881,40 +1978,533 @@ async fn create_table_with_url(service: Box<dyn SqlClient>) {
         query_result
     );
 
-    let result = service
-        .exec_query("SELECT count(*) from foo.bikes")
+    let result = service
+        .exec_query("SELECT count(*) from foo.bikes")
+        .await
+        .unwrap();
+    assert_eq!(
+        result.get_rows(),
+        &vec![Row::new(vec![TableValue::Int(813)])]
+    );
+}
+
+async fn create_table_fail_and_retry(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query(
+            "CREATE TABLE s.Data(n int, v int) INDEX reverse (v,n) LOCATION 'non-existing-file'",
+        )
+        .await
+        .unwrap_err();
+    service
+        .exec_query("CREATE TABLE s.Data(n int, v int) INDEX reverse (v,n)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(n, v) VALUES (1, -1), (2, -2)")
+        .await
+        .unwrap();
+    let rows = service
+        .exec_query("SELECT n FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![vec![TableValue::Int(1)], vec![TableValue::Int(2)]]
+    );
+}
+
+async fn alter_table_seal(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(n int, v int)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(n, v) VALUES (1, -1), (2, -2)")
+        .await
+        .unwrap();
+    // Only streaming tables can be paused.
+    service
+        .exec_query("ALTER TABLE s.Data PAUSE")
+        .await
+        .unwrap_err();
+    service.exec_query("ALTER TABLE s.Data SEAL").await.unwrap();
+
+    service
+        .exec_query("INSERT INTO s.Data(n, v) VALUES (3, -3)")
+        .await
+        .unwrap_err();
+    service
+        .exec_query("ALTER TABLE s.Data RESUME")
+        .await
+        .unwrap_err();
+
+    let rows = service
+        .exec_query("SELECT n FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![vec![TableValue::Int(1)], vec![TableValue::Int(2)]]
+    );
+    let rows = service
+        .exec_query("SELECT table_name, state FROM system.tables")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![vec![
+            TableValue::String("Data".to_string()),
+            TableValue::String("sealed".to_string())
+        ]]
+    );
+}
+
+async fn delete_rows(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(n int, v text) INDEX by_v (v, n)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(n, v) VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd')")
+        .await
+        .unwrap();
+
+    service
+        .exec_query("DELETE FROM s.Data WHERE n >= 3 AND v <> 'd'")
+        .await
+        .unwrap();
+    let rows = service
+        .exec_query("SELECT n FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![
+            vec![TableValue::Int(1)],
+            vec![TableValue::Int(2)],
+            vec![TableValue::Int(4)]
+        ]
+    );
+
+    service
+        .exec_query("DELETE FROM s.Data WHERE v = 'a'")
+        .await
+        .unwrap();
+    let rows = service
+        .exec_query("SELECT v FROM s.Data WHERE v <= 'b'")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![vec![TableValue::String("b".to_string())]]
+    );
+
+    service
+        .exec_query("DELETE FROM s.Data WHERE unknown = 1")
+        .await
+        .unwrap_err();
+
+    service.exec_query("DELETE FROM s.Data").await.unwrap();
+    let rows = service.exec_query("SELECT n FROM s.Data").await.unwrap();
+    assert_eq!(to_rows(&rows), Vec::<Vec<TableValue>>::new());
+
+    service
+        .exec_query("INSERT INTO s.Data(n, v) VALUES (5, 'e')")
+        .await
+        .unwrap();
+    let rows = service.exec_query("SELECT n, v FROM s.Data").await.unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![vec![
+            TableValue::Int(5),
+            TableValue::String("e".to_string())
+        ]]
+    );
+}
+
+async fn update_rows(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(n int, v text)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(n, v) VALUES (1, 'a'), (2, 'b'), (3, 'c')")
+        .await
+        .unwrap();
+
+    service
+        .exec_query("UPDATE s.Data SET v = 'x', n = n * 10 WHERE n >= 2")
+        .await
+        .unwrap();
+    let rows = service
+        .exec_query("SELECT n, v FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![
+            vec![TableValue::Int(1), TableValue::String("a".to_string())],
+            vec![TableValue::Int(20), TableValue::String("x".to_string())],
+            vec![TableValue::Int(30), TableValue::String("x".to_string())],
+        ]
+    );
+}
+
+async fn delete_rows_aggregate_index(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query(
+            "CREATE TABLE s.Data(a int, b int, n int) \
+             AGGREGATIONS(sum(n)) \
+             AGGREGATE INDEX aggr_index (a)",
+        )
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(a, b, n) VALUES (1, 1, 10), (1, 2, 20), (2, 1, 30)")
+        .await
+        .unwrap();
+
+    // `b` isn't a dimension of the aggregate index, so the index is rebuilt.
+    service
+        .exec_query("DELETE FROM s.Data WHERE b = 2")
+        .await
+        .unwrap();
+    let rows = service
+        .exec_query("SELECT a, sum(n) FROM s.Data GROUP BY 1 ORDER BY 1")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![
+            vec![TableValue::Int(1), TableValue::Int(10)],
+            vec![TableValue::Int(2), TableValue::Int(30)]
+        ]
+    );
+
+    service
+        .exec_query("UPDATE s.Data SET n = n + 1 WHERE a = 2")
+        .await
+        .unwrap();
+    service
+        .exec_query("UPDATE s.Data SET a = 3 WHERE b = 1 AND a = 1")
+        .await
+        .unwrap();
+    let rows = service
+        .exec_query("SELECT a, sum(n) FROM s.Data GROUP BY 1 ORDER BY 1")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![
+            vec![TableValue::Int(2), TableValue::Int(31)],
+            vec![TableValue::Int(3), TableValue::Int(10)]
+        ]
+    );
+    let rows = service
+        .exec_query("SELECT a, b, n FROM s.Data ORDER BY 1, 2")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&rows),
+        vec![
+            vec![TableValue::Int(2), TableValue::Int(1), TableValue::Int(31)],
+            vec![TableValue::Int(3), TableValue::Int(1), TableValue::Int(10)]
+        ]
+    );
+}
+
+async fn insert_select(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(n int, v text)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(n, v) VALUES (1, 'a'), (2, 'b'), (3, 'a')")
+        .await
+        .unwrap();
+
+    service
+        .exec_query("CREATE TABLE s.Rollup AS SELECT v, sum(n) AS n FROM s.Data GROUP BY 1")
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT v, n FROM s.Rollup ORDER BY v")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[("a", 4), ("b", 2)]));
+
+    service
+        .exec_query("INSERT INTO s.Rollup(n, v) SELECT n * 10, v FROM s.Data WHERE n = 2")
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT v, sum(n) FROM s.Rollup GROUP BY 1 ORDER BY 1")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[("a", 4), ("b", 22)]));
+
+    service
+        .exec_query("INSERT INTO s.Rollup SELECT n FROM s.Data")
+        .await
+        .unwrap_err();
+    service
+        .exec_query("INSERT INTO s.Rollup(v) SELECT v FROM s.Data")
+        .await
+        .unwrap_err();
+
+    service
+        .exec_query(
+            "CREATE TABLE s.Aggregated(v text, n int) AS SELECT v, n FROM s.Data WHERE n > 0 \
+             AGGREGATIONS(sum(n)) \
+             AGGREGATE INDEX by_v (v)",
+        )
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT v, sum(n) FROM s.Aggregated GROUP BY 1 ORDER BY 1")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[("a", 4), ("b", 2)]));
+
+    service
+        .exec_query(
+            "CREATE TABLE s.Unique(v text, n int) AS SELECT v, n FROM s.Data WHERE n > 0 \
+             UNIQUE KEY (v)",
+        )
+        .await
+        .unwrap_err();
+}
+
+async fn alter_table_columns(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(n int, v text)")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(n, v) VALUES (1, 'a'), (2, 'b')")
+        .await
+        .unwrap();
+
+    service
+        .exec_query("ALTER TABLE s.Data ADD COLUMN d int DEFAULT 7")
+        .await
+        .unwrap();
+    service
+        .exec_query("ALTER TABLE s.Data ADD COLUMN e text")
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Data(n, v, d, e) VALUES (3, 'c', 3, 'x')")
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT n, v, d, e FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        vec![
+            vec![
+                TableValue::Int(1),
+                TableValue::String("a".to_string()),
+                TableValue::Int(7),
+                TableValue::Null
+            ],
+            vec![
+                TableValue::Int(2),
+                TableValue::String("b".to_string()),
+                TableValue::Int(7),
+                TableValue::Null
+            ],
+            vec![
+                TableValue::Int(3),
+                TableValue::String("c".to_string()),
+                TableValue::Int(3),
+                TableValue::String("x".to_string())
+            ],
+        ]
+    );
+
+    let r = service
+        .exec_query("SELECT n FROM s.Data WHERE d = 7 AND e IS NULL ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        vec![vec![TableValue::Int(1)], vec![TableValue::Int(2)]]
+    );
+
+    service
+        .exec_query("ALTER TABLE s.Data DROP COLUMN d")
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT * FROM s.Data ORDER BY n")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&r),
+        vec![
+            vec![
+                TableValue::Int(1),
+                TableValue::String("a".to_string()),
+                TableValue::Null
+            ],
+            vec![
+                TableValue::Int(2),
+                TableValue::String("b".to_string()),
+                TableValue::Null
+            ],
+            vec![
+                TableValue::Int(3),
+                TableValue::String("c".to_string()),
+                TableValue::String("x".to_string())
+            ],
+        ]
+    );
+
+    // Sort key columns can't be dropped.
+    service
+        .exec_query("ALTER TABLE s.Data DROP COLUMN n")
+        .await
+        .unwrap_err();
+    service
+        .exec_query("ALTER TABLE s.Data ADD COLUMN v int")
+        .await
+        .unwrap_err();
+    service
+        .exec_query("ALTER TABLE s.Data ADD COLUMN d int")
+        .await
+        .unwrap_err();
+}
+
+async fn date_int32_list_types(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query(
+            "CREATE TABLE s.Data(id int, d date32, small int32, tiny tinyint, short int16, tags list_text)",
+        )
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO s.Data(id, d, small, tiny, short, tags) VALUES \
+             (1, '2022-01-31', -3, -128, 32767, '[\"a\", \"b\"]'), \
+             (2, '1969-12-31', 300, 127, -32768, '[]'), \
+             (3, NULL, NULL, NULL, NULL, NULL)",
+        )
+        .await
+        .unwrap();
+    // Out of int32 range.
+    service
+        .exec_query("INSERT INTO s.Data(id, small) VALUES (4, 3000000000)")
+        .await
+        .unwrap_err();
+    // Out of tinyint range.
+    service
+        .exec_query("INSERT INTO s.Data(id, tiny) VALUES (4, 128)")
+        .await
+        .unwrap_err();
+    // Out of int16 range.
+    service
+        .exec_query("INSERT INTO s.Data(id, short) VALUES (4, -32769)")
+        .await
+        .unwrap_err();
+
+    let r = service
+        .exec_query("SELECT id, d, small, tiny, short, tags FROM s.Data ORDER BY id")
         .await
         .unwrap();
     assert_eq!(
-        result.get_rows(),
-        &vec![Row::new(vec![TableValue::Int(813)])]
+        to_rows(&r),
+        vec![
+            vec![
+                TableValue::Int(1),
+                TableValue::Date(DateValue::new(19023)),
+                TableValue::Int(-3),
+                TableValue::Int(-128),
+                TableValue::Int(32767),
+                TableValue::List(vec![
+                    TableValue::String("a".to_string()),
+                    TableValue::String("b".to_string())
+                ])
+            ],
+            vec![
+                TableValue::Int(2),
+                TableValue::Date(DateValue::new(-1)),
+                TableValue::Int(300),
+                TableValue::Int(127),
+                TableValue::Int(-32768),
+                TableValue::List(vec![])
+            ],
+            vec![
+                TableValue::Int(3),
+                TableValue::Null,
+                TableValue::Null,
+                TableValue::Null,
+                TableValue::Null,
+                TableValue::Null
+            ],
+        ]
     );
-}
 
-async fn create_table_fail_and_retry(service: Box<dyn SqlClient>) {
-    service.exec_query("CREATE SCHEMA s").await.unwrap();
-    service
-        .exec_query(
-            "CREATE TABLE s.Data(n int, v int) INDEX reverse (v,n) LOCATION 'non-existing-file'",
-        )
-        .await
-        .unwrap_err();
+    // Standard SQL types keep their historical mappings.
     service
-        .exec_query("CREATE TABLE s.Data(n int, v int) INDEX reverse (v,n)")
+        .exec_query("CREATE TABLE s.Legacy(d date, small smallint, tags text[], big int8)")
         .await
         .unwrap();
     service
-        .exec_query("INSERT INTO s.Data(n, v) VALUES (1, -1), (2, -2)")
+        .exec_query(
+            "INSERT INTO s.Legacy(d, small, tags, big) \
+             VALUES ('2022-01-31', 3000000000, X'ab', 3000000000)",
+        )
         .await
         .unwrap();
-    let rows = service
-        .exec_query("SELECT n FROM s.Data ORDER BY n")
+    let r = service.exec_query("SELECT * FROM s.Legacy").await.unwrap();
+    assert_eq!(
+        to_rows(&r),
+        vec![vec![
+            TableValue::String("2022-01-31".to_string()),
+            TableValue::Int(3000000000),
+            TableValue::Bytes(vec![0xab]),
+            TableValue::Int(3000000000)
+        ]]
+    );
+
+    let file = write_tmp_file(indoc! {r#"
+        id,d,nums
+        1,2021-11-01,"[1,null]"
+    "#})
+    .unwrap();
+    service
+        .exec_query(&format!(
+            "CREATE TABLE s.Csv(id int, d date32, nums list_int) LOCATION '{}'",
+            file.path().to_string_lossy()
+        ))
         .await
         .unwrap();
+    let r = service.exec_query("SELECT * FROM s.Csv").await.unwrap();
     assert_eq!(
-        to_rows(&rows),
-        vec![vec![TableValue::Int(1)], vec![TableValue::Int(2)]]
+        to_rows(&r),
+        vec![vec![
+            TableValue::Int(1),
+            TableValue::Date(DateValue::parse("2021-11-01").unwrap()),
+            TableValue::List(vec![TableValue::Int(1), TableValue::Null])
+        ]]
     );
+
+    // Arrays can't be used in sort keys.
+    service
+        .exec_query("CREATE TABLE s.Bad(id int, tags list_text) INDEX by_tags (tags)")
+        .await
+        .unwrap_err();
 }
 
 async fn empty_crash(service: Box<dyn SqlClient>) {
@@ -2327,6 +2917,321 @@ async fn hyperloglog_snowflake(service: Box<dyn SqlClient>) {
         .unwrap_err();
 }
 
+/// T-digest sketches of values 1..5 and 6..10, serialized as in Presto.
+const TDIGEST_LOW: &str = "X'0000000000000000F03F00000000000014400000000000002E400000000000005940000000000000144005000000000000000000F03F000000000000F03F000000000000F03F000000000000F03F000000000000F03F000000000000F03F0000000000000040000000000000084000000000000010400000000000001440'";
+const TDIGEST_HIGH: &str = "X'00000000000000001840000000000000244000000000000044400000000000005940000000000000144005000000000000000000F03F000000000000F03F000000000000F03F000000000000F03F000000000000F03F00000000000018400000000000001C40000000000000204000000000000022400000000000002440'";
+
+async fn tdigest(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Latencies (id int, digest tdigest)")
+        .await
+        .unwrap();
+
+    let result = service
+        .exec_query("SELECT quantile(merge_tdigest(digest), 0.5) FROM s.Latencies")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&result), vec![vec![TableValue::Null]]);
+
+    service
+        .exec_query(&format!(
+            "INSERT INTO s.Latencies (id, digest) VALUES (1, {l}), (2, {h}), (3, NULL)",
+            l = TDIGEST_LOW,
+            h = TDIGEST_HIGH
+        ))
+        .await
+        .unwrap();
+    service
+        .exec_query("INSERT INTO s.Latencies (id, digest) VALUES (4, X'0000')")
+        .await
+        .expect_err("should not allow invalid t-digest");
+    service
+        .exec_query(&format!(
+            "INSERT INTO s.Latencies (id, digest) VALUES (4, {}00')",
+            TDIGEST_LOW.trim_end_matches("'")
+        ))
+        .await
+        .expect_err("should not allow invalid t-digest (with extra bytes)");
+
+    let result = service
+        .exec_query("SELECT id, quantile(digest, 0.5) FROM s.Latencies ORDER BY 1")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&result),
+        vec![
+            vec![TableValue::Int(1), TableValue::Float(3.0.into())],
+            vec![TableValue::Int(2), TableValue::Float(8.0.into())],
+            vec![TableValue::Int(3), TableValue::Null],
+        ]
+    );
+
+    let result = service
+        .exec_query(
+            "SELECT quantile(merge_tdigest(digest), 0), quantile(merge_tdigest(digest), 0.5), \
+                    quantile(merge_tdigest(digest), 0.9) FROM s.Latencies",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&result),
+        vec![vec![
+            TableValue::Float(1.0.into()),
+            TableValue::Float(5.5.into()),
+            TableValue::Float(9.5.into()),
+        ]]
+    );
+
+    service
+        .exec_query("SELECT quantile(digest, 1.5) FROM s.Latencies")
+        .await
+        .expect_err("quantile must be between 0 and 1");
+
+    // merge() dispatches on the column type.
+    let result = service
+        .exec_query("SELECT quantile(merge(digest), 0.5) FROM s.Latencies")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&result), vec![vec![TableValue::Float(5.5.into())]]);
+
+    service
+        .exec_query("CREATE TABLE s.Visitors (id int, hll hyperloglog)")
+        .await
+        .unwrap();
+    let err = service
+        .exec_query("SELECT quantile(merge_tdigest(hll), 0.5) FROM s.Visitors")
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("MERGE_TDIGEST expects a tdigest column"),
+        "{}",
+        err
+    );
+}
+
+async fn aggregate_index_tdigest(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query(
+            "CREATE TABLE s.Orders(a int, b int, latency tdigest) \
+             AGGREGATIONS(merge(latency)) \
+             AGGREGATE INDEX aggr_index (a, b)",
+        )
+        .await
+        .unwrap();
+    service
+        .exec_query(&format!(
+            "INSERT INTO s.Orders (a, b, latency) VALUES (1, 10, {l}), (1, 10, {h}), (1, 20, {l})",
+            l = TDIGEST_LOW,
+            h = TDIGEST_HIGH
+        ))
+        .await
+        .unwrap();
+
+    let res = service
+        .exec_query(
+            "SELECT a, b, quantile(merge_tdigest(latency), 0.5) FROM s.Orders \
+             GROUP BY 1, 2 ORDER BY 1, 2",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&res),
+        vec![
+            vec![
+                TableValue::Int(1),
+                TableValue::Int(10),
+                TableValue::Float(5.5.into())
+            ],
+            vec![
+                TableValue::Int(1),
+                TableValue::Int(20),
+                TableValue::Float(3.0.into())
+            ],
+        ]
+    );
+
+    let res = service
+        .exec_query("SELECT a, quantile(merge_tdigest(latency), 0.5) FROM s.Orders GROUP BY 1")
+        .await
+        .unwrap();
+    assert_eq!(
+        to_rows(&res),
+        vec![vec![TableValue::Int(1), TableValue::Float(4.0.into())]]
+    );
+
+    service
+        .exec_query("CREATE TABLE s.Orders2(a int, latency tdigest) AGGREGATIONS(max(latency))")
+        .await
+        .expect_err("Aggregate function MAX not allowed for column type tdigest");
+}
+
+async fn hyperloglog_init(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.Data(id int, user_id int, name text)")
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO s.Data(id, user_id, name) VALUES \
+             (1, 1, 'a'), (1, 2, 'b'), (1, 2, 'b'), (2, 3, 'c'), (2, 4, NULL), (2, 1, 'a')",
+        )
+        .await
+        .unwrap();
+
+    let r = service
+        .exec_query(
+            "SELECT id, cardinality(hll_init(user_id)), cardinality(hllpp_init(name)) \
+             FROM s.Data GROUP BY 1 ORDER BY 1",
+        )
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[(1, 2, 2), (2, 3, 2)]));
+
+    let r = service
+        .exec_query(
+            "SELECT cardinality(hll_init(user_id)), cardinality(hllpp_init(name)) FROM s.Data",
+        )
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[(4, 3)]));
+
+    // Sketches built by queries can be stored in rollups and merged later.
+    service
+        .exec_query("CREATE TABLE s.Rollup(id int, users HYPERLOGLOG, names HYPERLOGLOGPP)")
+        .await
+        .unwrap();
+    service
+        .exec_query(
+            "INSERT INTO s.Rollup(id, users, names) \
+             SELECT id, hll_init(user_id), hllpp_init(name) FROM s.Data GROUP BY 1",
+        )
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT cardinality(merge(users)), cardinality(merge(names)) FROM s.Rollup")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[(4, 3)]));
+
+    // Empty sparse sketch with the 2048 buckets of `approx_set` in Presto.
+    service
+        .exec_query("INSERT INTO s.Rollup(id, users, names) VALUES (3, X'020B0000', NULL)")
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT cardinality(merge(users)) FROM s.Rollup")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[4]));
+
+    // BigQuery hashes values differently, its sketches can't be merged with the built ones.
+    // Empty ZetaSketch sketch with precision 15.
+    service
+        .exec_query(
+            "INSERT INTO s.Rollup(id, users, names) VALUES (4, NULL, X'08701802820704180F2014')",
+        )
+        .await
+        .unwrap();
+    service
+        .exec_query("SELECT cardinality(merge(names)) FROM s.Rollup")
+        .await
+        .unwrap_err();
+    let r = service
+        .exec_query("SELECT cardinality(merge(names)) FROM s.Rollup WHERE id < 3")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[3]));
+
+    // Sketch formats must match the column types.
+    service
+        .exec_query(
+            "INSERT INTO s.Rollup(id, users, names) \
+             SELECT id, hllpp_init(user_id), hllpp_init(name) FROM s.Data GROUP BY 1",
+        )
+        .await
+        .unwrap_err();
+}
+
+async fn hyperloglog_export(service: Box<dyn SqlClient>) {
+    service.exec_query("CREATE SCHEMA s").await.unwrap();
+    service
+        .exec_query("CREATE TABLE s.hlls(id int, hll HLL_POSTGRES)")
+        .await
+        .unwrap();
+    let sparse = "138b7f04a10642078507c308e309230a420ac10c2510a2114511611363138116811848188218a119411a821ae11f0122e223a125a126632685276327a328e2296129e52b812fe23081320132c133e335a53641368236a23721374237e1382138e13a813c243e6140e341854304434148a24a034f8150c1520152e254e155a1564157e158e35ac25b265b615c615fc1620166a368226a416a626c016c816d677163728275817a637a817ac37b617c247c427d677f6180e18101826382e1846184e18541858287e1880189218a418b818bc38e018ea290a19244938295e4988198c299e29b239b419c419ce49da1a1e1a321a381a4c1aa61acc2ae01b0a1b101b142b161b443b801bd02bd61bf61c263c4a3c501c7a1caa1cb03cd03cf03cf42d123d4c3d662d744d901dd01df81e001e0a2e641e7e3edc1f0a2f1c1f203f484f5c4f763fc84fdc1fe02fea1";
+    service
+        .exec_query(&format!(
+            "INSERT INTO s.hlls(id, hll) VALUES (1, X'118b7f'), (2, X'128b7fee22c470691a8134'), \
+             (3, X'{}')",
+            sparse
+        ))
+        .await
+        .unwrap();
+
+    let r = service
+        .exec_query("SELECT id, hll_export(hll, 'postgres') FROM s.hlls ORDER BY id")
+        .await
+        .unwrap();
+    // Explicit encoding is exported as sparse.
+    let sparse_text = format!("\\x{}", sparse);
+    assert_eq!(
+        to_rows(&r),
+        rows(&[
+            (1, "\\x118b7f"),
+            (2, "\\x138b7fee24"),
+            (3, sparse_text.as_str()),
+        ])
+    );
+
+    // Exported sketches can be imported back.
+    let exported = match &to_rows(&r)[2][1] {
+        TableValue::String(s) => s[2..].to_string(),
+        v => panic!("unexpected value: {:?}", v),
+    };
+    service
+        .exec_query(&format!(
+            "INSERT INTO s.hlls(id, hll) VALUES (4, X'{}')",
+            exported
+        ))
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT id, cardinality(hll) FROM s.hlls WHERE id >= 3 ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[(3, 164), (4, 164)]));
+
+    service
+        .exec_query("CREATE TABLE s.snowflake(id int, hll HLL_SNOWFLAKE)")
+        .await
+        .unwrap();
+    let json = r#"{"version":4,"precision":12,"sparse":{"indices":[223,736,976,1041,1256],"maxLzCounts":[1,2,1,4,2]}}"#;
+    service
+        .exec_query(&format!(
+            "INSERT INTO s.snowflake(id, hll) VALUES (1, '{}')",
+            json
+        ))
+        .await
+        .unwrap();
+    let r = service
+        .exec_query("SELECT hll_export(merge(hll), 'snowflake') FROM s.snowflake")
+        .await
+        .unwrap();
+    assert_eq!(to_rows(&r), rows(&[json]));
+    service
+        .exec_query("SELECT hll_export(hll, 'mysql') FROM s.snowflake")
+        .await
+        .unwrap_err();
+    service
+        .exec_query("SELECT hll_export(hllpp_init(id), 'postgres') FROM s.snowflake")
+        .await
+        .unwrap_err();
+}
+
 async fn planning_inplace_aggregate(service: Box<dyn SqlClient>) {
     service.exec_query("CREATE SCHEMA s").await.unwrap();
     service
@@ -2,7 +2,7 @@
 //! The convention is to prefix all metrics with `cs.` (short for CubeStore).
 
 use crate::util::metrics;
-use crate::util::metrics::{Counter, Histogram};
+use crate::util::metrics::{Counter, Gauge, Histogram, LabeledGauge};
 
 /// The number of process startups.
 pub static STARTUPS: Counter = metrics::counter("cs.startup");
@@ -13,3 +13,38 @@ pub static DATA_QUERY_TIME_MS: Histogram = metrics::histogram("cs.sql.query.data
 /// Incoming SQL queries that only read metadata or do trivial computations.
 pub static META_QUERIES: Counter = metrics::counter("cs.sql.query.meta");
 pub static META_QUERY_TIME_MS: Histogram = metrics::histogram("cs.sql.query.meta.ms");
+
+/// Accesses to partition and chunk files that were already on the local disk.
+pub static LOCAL_CACHE_HITS: Counter = metrics::counter("cs.local_cache.hit");
+/// Partition and chunk files downloaded from the remote storage.
+pub static LOCAL_CACHE_MISSES: Counter = metrics::counter("cs.local_cache.miss");
+pub static LOCAL_CACHE_EVICTIONS: Counter = metrics::counter("cs.local_cache.eviction");
+pub static LOCAL_CACHE_SIZE_BYTES: Gauge = metrics::gauge("cs.local_cache.size_bytes");
+
+/// Read and write operations waiting for the metastore read-write loop.
+pub static METASTORE_QUEUE_SIZE: Gauge = metrics::gauge("cs.metastore.queue_size");
+/// Jobs in the metastore, by type and status.
+pub static METASTORE_JOBS: LabeledGauge =
+    metrics::labeled_gauge("cs.metastore.jobs", &["job_type", "status"]);
+/// Active partitions and chunks of ready tables.
+pub static TABLE_PARTITIONS: LabeledGauge =
+    metrics::labeled_gauge("cs.table.partitions", &["schema", "table"]);
+pub static TABLE_CHUNKS: LabeledGauge =
+    metrics::labeled_gauge("cs.table.chunks", &["schema", "table"]);
+
+/// Exposes all metrics to Prometheus, including the ones that were never reported.
+pub fn register_all() {
+    STARTUPS.register();
+    DATA_QUERIES.register();
+    DATA_QUERY_TIME_MS.register();
+    META_QUERIES.register();
+    META_QUERY_TIME_MS.register();
+    LOCAL_CACHE_HITS.register();
+    LOCAL_CACHE_MISSES.register();
+    LOCAL_CACHE_EVICTIONS.register();
+    LOCAL_CACHE_SIZE_BYTES.register();
+    METASTORE_QUEUE_SIZE.register();
+    METASTORE_JOBS.register();
+    TABLE_PARTITIONS.register();
+    TABLE_CHUNKS.register();
+}
@@ -1,6 +1,8 @@
+use chrono::{TimeZone, Utc};
 use cubestore::app_metrics;
 use cubestore::config::{validate_config, Config, CubeServices};
 use cubestore::http::status::serve_status_probes;
+use cubestore::sql::timestamp_from_string;
 use cubestore::telemetry::{init_agent_sender, track_event};
 use cubestore::util::logger::init_cube_logger;
 use cubestore::util::metrics::init_metrics;
@@ -56,6 +58,10 @@ fn main() {
         tokio_builder.worker_threads(var.parse().unwrap());
     }
     let runtime = tokio_builder.build().unwrap();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(|a| a.as_str()) == Some("metastore") {
+        std::process::exit(runtime.block_on(metastore_command(&config, &args[1..])));
+    }
     runtime.block_on(async move {
         init_agent_sender().await;
 
@@ -74,6 +80,49 @@ fn main() {
     });
 }
 
+/// `cubestored metastore list-backups` and `cubestored metastore restore <timestamp>`.
+/// Restore should be done while Cube Store is stopped.
+async fn metastore_command(config: &Config, args: &[String]) -> i32 {
+    let res = match args
+        .iter()
+        .map(|a| a.as_str())
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["list-backups"] => config.meta_store_backups().await,
+        ["restore", point] => match timestamp_from_string(point) {
+            Ok(point) => config
+                .restore_meta_store(Utc.timestamp_nanos(point.get_time_stamp()))
+                .await
+                .map(|b| vec![b]),
+            Err(e) => Err(e),
+        },
+        _ => {
+            eprintln!(
+                "Usage: cubestored metastore list-backups | cubestored metastore restore <timestamp>"
+            );
+            return 2;
+        }
+    };
+    match res {
+        Ok(backups) => {
+            for b in backups {
+                println!(
+                    "{}\t{}{}",
+                    b.remote_path(),
+                    b.created_at().to_rfc3339(),
+                    if b.current() { "\tcurrent" } else { "" }
+                );
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
 async fn stop_on_ctrl_c(s: &CubeServices) {
     let s = s.clone();
     cube_ext::spawn(async move {
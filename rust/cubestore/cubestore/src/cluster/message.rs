@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use std::io::ErrorKind;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum NetworkMessage {
@@ -16,6 +17,8 @@ pub enum NetworkMessage {
     /// Partial select on the worker.
     Select(SerializedPlan),
     SelectResult(Result<(SchemaRef, Vec<SerializedRecordBatchStream>), CubeError>),
+    /// Sent over the connection of a running [RouterSelect] or [Select] to abort it.
+    CancelSelect,
 
     //Perform explain analyze of worker query part and return it pretty printed physical plan
     ExplainAnalyze(SerializedPlan),
@@ -62,6 +65,30 @@ impl NetworkMessage {
         }
     }
 
+    /// Requests that are aborted when the client sends [CancelSelect] or closes the connection.
+    pub fn is_cancellable_request(&self) -> bool {
+        match self {
+            NetworkMessage::RouterSelect(..)
+            | NetworkMessage::Select(..)
+            | NetworkMessage::SelectStart(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Attaches the token to the plans of cancellable requests.
+    pub fn with_cancel_token(self, cancel_token: CancellationToken) -> Self {
+        match self {
+            NetworkMessage::RouterSelect(p) => {
+                NetworkMessage::RouterSelect(p.with_cancel_token(cancel_token))
+            }
+            NetworkMessage::Select(p) => NetworkMessage::Select(p.with_cancel_token(cancel_token)),
+            NetworkMessage::SelectStart(p) => {
+                NetworkMessage::SelectStart(p.with_cancel_token(cancel_token))
+            }
+            m => m,
+        }
+    }
+
     /// Returns true iff the client accepted the message.
     pub async fn maybe_send(&self, socket: &mut TcpStream) -> Result<bool, CubeError> {
         match self.send_impl(socket).await {
@@ -28,6 +28,7 @@ use crate::metastore::{
 };
 use crate::queryplanner::query_executor::{QueryExecutor, SerializedRecordBatchStream};
 use crate::queryplanner::serialized_plan::SerializedPlan;
+use crate::remotefs::cache::FilePin;
 use crate::remotefs::RemoteFs;
 use crate::store::compaction::CompactionService;
 use crate::store::ChunkDataStore;
@@ -294,8 +295,9 @@ impl Cluster for ClusterImpl {
         node_name: &str,
         plan: SerializedPlan,
     ) -> Result<(SchemaRef, Vec<SerializedRecordBatchStream>), CubeError> {
+        let cancel = plan.cancel_token().clone();
         let response = self
-            .send_or_process_locally(&node_name, NetworkMessage::RouterSelect(plan))
+            .send_select_or_process_locally(&node_name, NetworkMessage::RouterSelect(plan), &cancel)
             .await?;
         match response {
             NetworkMessage::SelectResult(r) => r,
@@ -309,8 +311,9 @@ impl Cluster for ClusterImpl {
         node_name: &str,
         plan_node: SerializedPlan,
     ) -> Result<Vec<RecordBatch>, CubeError> {
+        let cancel = plan_node.cancel_token().clone();
         let response = self
-            .send_or_process_locally(node_name, NetworkMessage::Select(plan_node))
+            .send_select_or_process_locally(node_name, NetworkMessage::Select(plan_node), &cancel)
             .await?;
         match response {
             NetworkMessage::SelectResult(r) => {
@@ -504,6 +507,9 @@ impl Cluster for ClusterImpl {
                     .await;
                 NetworkMessage::WarmupDownloadResult(res.map(|_| ()))
             }
+            NetworkMessage::CancelSelect => NetworkMessage::SelectResult(Err(CubeError::internal(
+                "CancelSelect sent without a running select".to_string(),
+            ))),
             NetworkMessage::SelectResult(_)
             | NetworkMessage::WarmupDownloadResult(_)
             | NetworkMessage::ExplainAnalyzeResult(_) => {
@@ -845,6 +851,20 @@ impl JobRunner {
                     Self::fail_job_row_key(job)
                 }
             }
+            JobType::PartitionRowsDeletion(predicate) => {
+                if let RowKey::Table(TableId::Partitions, partition_id) = job.row_reference() {
+                    let compaction_service = self.compaction_service.clone();
+                    let partition_id = *partition_id;
+                    let predicate = predicate.to_string();
+                    Ok(cube_ext::spawn(async move {
+                        compaction_service
+                            .delete_rows(partition_id, predicate)
+                            .await
+                    }))
+                } else {
+                    Self::fail_job_row_key(job)
+                }
+            }
             JobType::MultiPartitionSplit => {
                 if let RowKey::Table(TableId::MultiPartitions, id) = job.row_reference() {
                     let compaction_service = self.compaction_service.clone();
@@ -1062,14 +1082,22 @@ impl ClusterImpl {
                     }
                 };
 
-                if !m.is_streaming_request() {
-                    let response = c.process_message_on_worker(m).await;
-                    if let Err(e) = response.send(&mut socket).await {
-                        error!("Network error: {}", e);
-                        return;
-                    }
-                } else {
-                    let mut p = c.start_stream_on_worker(m).await;
+                if m.is_streaming_request() {
+                    // Results are only streamed once the select has finished, closed connection
+                    // stops the streaming itself.
+                    let cancel = CancellationToken::new();
+                    let m = m.with_cancel_token(cancel.clone());
+                    let p = tokio::select! {
+                        p = c.start_stream_on_worker(m) => Some(p),
+                        _ = ClusterImpl::wait_for_cancel(&mut socket) => None,
+                    };
+                    let mut p = match p {
+                        Some(p) => p,
+                        None => {
+                            cancel.cancel();
+                            return;
+                        }
+                    };
                     loop {
                         let (response, finished) = p.next().await;
                         match response.maybe_send(&mut socket).await {
@@ -1088,12 +1116,45 @@ impl ClusterImpl {
                             break;
                         }
                     }
+                } else if m.is_cancellable_request() {
+                    let cancel = CancellationToken::new();
+                    let m = m.with_cancel_token(cancel.clone());
+                    let response = tokio::select! {
+                        r = c.process_message_on_worker(m) => Some(r),
+                        _ = ClusterImpl::wait_for_cancel(&mut socket) => None,
+                    };
+                    let response = match response {
+                        Some(r) => r,
+                        None => {
+                            cancel.cancel();
+                            return;
+                        }
+                    };
+                    if let Err(e) = response.send(&mut socket).await {
+                        error!("Network error: {}", e);
+                        return;
+                    }
+                } else {
+                    let response = c.process_message_on_worker(m).await;
+                    if let Err(e) = response.send(&mut socket).await {
+                        error!("Network error: {}", e);
+                        return;
+                    }
                 }
             },
         )
         .await
     }
 
+    /// Resolves once the client sends [NetworkMessage::CancelSelect] or closes the connection.
+    async fn wait_for_cancel(socket: &mut TcpStream) {
+        match NetworkMessage::maybe_receive(socket).await {
+            Ok(None) | Ok(Some(NetworkMessage::CancelSelect)) => {}
+            Ok(Some(m)) => error!("Unexpected message while running select: {:?}", m),
+            Err(e) => error!("Network error: {}", e),
+        }
+    }
+
     pub async fn listen_on_metastore_port(
         cluster: Arc<ClusterImpl>,
         on_socket_bound: oneshot::Sender<()>,
@@ -1196,7 +1257,7 @@ impl ClusterImpl {
     ) -> Result<(SchemaRef, Vec<SerializedRecordBatchStream>), CubeError> {
         let start = SystemTime::now();
         debug!("Running select");
-        let remote_to_local_names = self.warmup_select_worker_files(&plan_node).await?;
+        let (remote_to_local_names, _pins) = self.warmup_select_worker_files(&plan_node).await?;
         let warmup = start.elapsed()?;
         if warmup.as_millis() > 200 {
             warn!("Warmup download for select ({:?})", warmup);
@@ -1247,11 +1308,14 @@ impl ClusterImpl {
                     )
                     .collect::<Result<HashMap<_, _>, _>>()?;
                 res = Some(
-                    pool.process(WorkerMessage::Select(
-                        plan_node.clone(),
-                        remote_to_local_names.clone(),
-                        chunk_id_to_record_batches,
-                    ))
+                    pool.process(
+                        WorkerMessage::Select(
+                            plan_node.clone(),
+                            remote_to_local_names.clone(),
+                            chunk_id_to_record_batches,
+                        ),
+                        plan_node.cancel_token().clone(),
+                    )
                     .instrument(tracing::span!(
                         tracing::Level::TRACE,
                         "execute_worker_plan_on_pool"
@@ -1263,14 +1327,14 @@ impl ClusterImpl {
 
         if res.is_none() {
             // TODO optimize for no double conversion
-            let (schema, records) = self
-                .query_executor
-                .execute_worker_plan(
+            let (schema, records) = tokio::select! {
+                r = self.query_executor.execute_worker_plan(
                     plan_node.clone(),
                     remote_to_local_names,
                     chunk_id_to_record_batches,
-                )
-                .await?;
+                ) => r?,
+                _ = plan_node.cancel_token().cancelled() => return Err(query_cancelled_error()),
+            };
             let records = SerializedRecordBatchStream::write(schema.as_ref(), records);
             res = Some(Ok((schema, records?)))
         }
@@ -1283,7 +1347,7 @@ impl ClusterImpl {
         &self,
         plan_node: SerializedPlan,
     ) -> Result<String, CubeError> {
-        let remote_to_local_names = self.warmup_select_worker_files(&plan_node).await?;
+        let (remote_to_local_names, _pins) = self.warmup_select_worker_files(&plan_node).await?;
         let in_memory_chunks_to_load = plan_node.in_memory_chunks_to_load();
         let chunk_id_to_record_batches = in_memory_chunks_to_load
             .clone()
@@ -1299,11 +1363,17 @@ impl ClusterImpl {
         res
     }
 
+    /// Downloads files of the plan. Returned pins keep them in the local cache, hold them until
+    /// the plan is executed.
     async fn warmup_select_worker_files(
         &self,
         plan_node: &SerializedPlan,
-    ) -> Result<HashMap<String, String>, CubeError> {
+    ) -> Result<(HashMap<String, String>, Vec<FilePin>), CubeError> {
         let to_download = plan_node.files_to_download();
+        let pins = to_download
+            .iter()
+            .map(|(_, remote, _)| self.remote_fs.pin_file(remote))
+            .collect::<Vec<_>>();
         let file_futures = to_download
             .iter()
             .map(|(partition, remote, file_size)| {
@@ -1333,7 +1403,7 @@ impl ClusterImpl {
             .map(|((_, remote_path, _), path)| (remote_path, path))
             .collect::<HashMap<_, _>>();
 
-        Ok(remote_to_local_names)
+        Ok((remote_to_local_names, pins))
     }
 
     pub async fn try_to_connect(&mut self) -> Result<(), CubeError> {
@@ -1371,6 +1441,30 @@ impl ClusterImpl {
         }
     }
 
+    /// Like [send_or_process_locally], but aborts the select once `cancel` is triggered.
+    #[instrument(level = "trace", skip(self, m, cancel))]
+    async fn send_select_or_process_locally(
+        &self,
+        node_name: &str,
+        m: NetworkMessage,
+        cancel: &CancellationToken,
+    ) -> Result<NetworkMessage, CubeError> {
+        let query_timeout = Duration::from_secs(self.config_obj.query_timeout());
+        if self.server_name == node_name || is_self_reference(node_name) {
+            tokio::select! {
+                r = timeout(query_timeout, self.process_message_on_worker(m)) => Ok(r?),
+                _ = cancel.cancelled() => Err(query_cancelled_error()),
+            }
+        } else {
+            timeout(
+                query_timeout,
+                self.cluster_transport
+                    .send_to_worker_cancellable(node_name.to_string(), m, cancel),
+            )
+            .await?
+        }
+    }
+
     #[instrument(level = "trace", skip(self, m))]
     async fn call_streaming(
         self: &Arc<Self>,
@@ -1411,17 +1505,29 @@ impl ClusterImpl {
         node_name: &str,
         plan: SerializedPlan,
     ) -> Result<SendableRecordBatchStream, CubeError> {
+        // Dropping the connection on cancellation makes the worker stop the select.
+        let cancel = plan.cancel_token().clone();
         let init_message = NetworkMessage::SelectStart(plan);
-        let mut c = self.call_streaming(node_name, init_message).await?;
-        let schema = match c.receive().await? {
-            NetworkMessage::SelectResultSchema(s) => s,
-            _ => panic!("unexpected response to select stream"),
-        }?;
+        let schema = tokio::select! {
+            r = async {
+                let mut c = self.call_streaming(node_name, init_message).await?;
+                let schema = match c.receive().await? {
+                    NetworkMessage::SelectResultSchema(s) => s,
+                    _ => return Err(CubeError::internal(
+                        "unexpected response to select stream".to_string(),
+                    )),
+                }?;
+                Ok::<_, CubeError>((c, schema))
+            } => r,
+            _ = cancel.cancelled() => Err(query_cancelled_error()),
+        };
+        let (c, schema) = schema?;
         return Ok(Box::pin(SelectStream {
             schema,
             connection: Some(c),
             pending: Mutex::new(None),
             finished: false,
+            cancel,
         }));
 
         type ConnPtr = Box<dyn WorkerConnection>;
@@ -1439,6 +1545,7 @@ impl ClusterImpl {
                 >,
             >,
             finished: bool,
+            cancel: CancellationToken,
         }
 
         impl Stream for SelectStream {
@@ -1454,8 +1561,12 @@ impl ClusterImpl {
 
                 if self.pending.lock().unwrap().is_none() {
                     let mut connection = self.as_mut().connection.take().unwrap();
+                    let cancel = self.cancel.clone();
                     *self.pending.lock().unwrap() = Some(Box::pin(async move {
-                        let res = connection.receive().await;
+                        let res = tokio::select! {
+                            r = connection.receive() => r,
+                            _ = cancel.cancelled() => Err(query_cancelled_error()),
+                        };
                         (res, connection)
                     }));
                 }
@@ -1499,6 +1610,8 @@ impl ClusterImpl {
                 e: ArrowError,
             ) -> Poll<Option<Result<T, ArrowError>>> {
                 self.as_mut().finished = true;
+                // Closes the connection, so the worker stops sending the results.
+                self.as_mut().connection = None;
                 return Poll::Ready(Some(Err(e)));
             }
         }
@@ -1655,6 +1768,11 @@ impl MessageStream for QueryStream {
     }
 }
 
+/// Returned to the clients of killed queries.
+pub fn query_cancelled_error() -> CubeError {
+    CubeError::user("Query was cancelled".to_string())
+}
+
 fn is_self_reference(name: &str) -> bool {
     name.starts_with("@loop:")
 }
@@ -1,4 +1,5 @@
 use crate::cluster::message::NetworkMessage;
+use crate::cluster::query_cancelled_error;
 use crate::config::injection::DIService;
 use crate::config::ConfigObj;
 use crate::CubeError;
@@ -6,6 +7,7 @@ use async_trait::async_trait;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
 
 /// Client-side connection for exchanging messages between the server and the client.
 /// Created by [ClusterTransport].
@@ -55,6 +57,30 @@ impl dyn ClusterTransport {
         c.send(m).await?;
         c.receive().await
     }
+
+    /// Like [send_to_worker], but stops waiting for the response once `cancel` is triggered and
+    /// asks the worker to abort the request with [NetworkMessage::CancelSelect].
+    pub async fn send_to_worker_cancellable(
+        &self,
+        worker_node: String,
+        m: NetworkMessage,
+        cancel: &CancellationToken,
+    ) -> Result<NetworkMessage, CubeError> {
+        let mut c = self.connect_to_worker(worker_node).await?;
+        c.send(m).await?;
+        let response = tokio::select! {
+            r = c.receive() => Some(r),
+            _ = cancel.cancelled() => None,
+        };
+        match response {
+            Some(r) => r,
+            None => {
+                // The worker also stops once the connection is closed, so errors are not fatal.
+                let _ = c.maybe_send(NetworkMessage::CancelSelect).await;
+                Err(query_cancelled_error())
+            }
+        }
+    }
 }
 
 pub struct ClusterTransportImpl {
@@ -16,9 +16,11 @@ use serde::{Deserialize, Serialize};
 use tokio::runtime::Builder;
 use tokio::sync::oneshot::Sender;
 use tokio::sync::{oneshot, watch, Notify, RwLock};
+use tokio_util::sync::CancellationToken;
 use tracing::{instrument, Instrument};
 use tracing_futures::WithSubscriber;
 
+use crate::cluster::query_cancelled_error;
 use crate::config::{Config, WorkerServices};
 use crate::util::respawn::respawn;
 use crate::CubeError;
@@ -42,6 +44,7 @@ pub struct Message<
 > {
     message: T,
     sender: Sender<Result<R, CubeError>>,
+    cancel: CancellationToken,
     span: tracing::Span,
     dispatcher: tracing::dispatcher::Dispatch,
 }
@@ -94,11 +97,14 @@ impl<
         join_all(futures).await;
     }
 
-    pub async fn process(&self, message: T) -> Result<R, CubeError> {
+    /// Once `cancel` is triggered, the worker process running the message is killed and
+    /// replaced with a new one.
+    pub async fn process(&self, message: T, cancel: CancellationToken) -> Result<R, CubeError> {
         let (tx, rx) = oneshot::channel();
         self.queue.push(Message {
             message,
             sender: tx,
+            cancel,
             span: tracing::Span::current(),
             dispatcher: tracing::dispatcher::get_default(|d| d.clone()),
         });
@@ -158,6 +164,7 @@ impl<
                         let Message {
                             message,
                             sender,
+                            cancel,
                             span,
                             dispatcher,
                         } = tokio::select! {
@@ -172,19 +179,28 @@ impl<
                                 message
                             }
                         };
+                        if cancel.is_cancelled() {
+                            if sender.send(Err(query_cancelled_error())).is_err() {
+                                error!("Error during worker message processing: Send Error");
+                            }
+                            continue;
+                        }
                         let process_message_res_timeout = tokio::time::timeout(
                             self.timeout,
                             self.process_message(message, args_tx, res_rx),
                         )
                         .instrument(span)
-                        .with_subscriber(dispatcher)
-                        .await;
-                        let process_message_res = match process_message_res_timeout {
-                            Ok(r) => r,
-                            Err(e) => Err(CubeError::internal(format!(
-                                "Timed out after waiting for {}",
-                                e
-                            ))),
+                        .with_subscriber(dispatcher);
+                        let process_message_res = tokio::select! {
+                            r = process_message_res_timeout => match r {
+                                Ok(r) => r,
+                                Err(e) => Err(CubeError::internal(format!(
+                                    "Timed out after waiting for {}",
+                                    e
+                                ))),
+                            },
+                            // Leads to the restart of the worker process below.
+                            _ = cancel.cancelled() => Err(query_cancelled_error()),
                         };
                         match process_message_res {
                             Ok((res, a, r)) => {
@@ -320,7 +336,9 @@ mod tests {
     use futures_timer::Delay;
     use serde::{Deserialize, Serialize};
     use tokio::runtime::Builder;
+    use tokio_util::sync::CancellationToken;
 
+    use crate::cluster::query_cancelled_error;
     use crate::cluster::worker_pool::{worker_main, MessageProcessor, WorkerPool};
     use crate::config::WorkerServices;
     use crate::queryplanner::serialized_plan::SerializedLogicalPlan;
@@ -374,7 +392,9 @@ mod tests {
             let pool_to_move = pool.clone();
             cube_ext::spawn(async move { pool_to_move.wait_processing_loops().await });
             assert_eq!(
-                pool.process(Message::Delay(100)).await.unwrap(),
+                pool.process(Message::Delay(100), CancellationToken::new())
+                    .await
+                    .unwrap(),
                 Response::Foo(100)
             );
             pool.stop_workers().await.unwrap();
@@ -394,7 +414,10 @@ mod tests {
             cube_ext::spawn(async move { pool_to_move.wait_processing_loops().await });
             let mut futures = Vec::new();
             for i in 0..10 {
-                futures.push((i, pool.process(Message::Delay(i * 100))));
+                futures.push((
+                    i,
+                    pool.process(Message::Delay(i * 100), CancellationToken::new()),
+                ));
             }
             for (i, f) in futures {
                 println!("Testing {} future", i);
@@ -417,7 +440,10 @@ mod tests {
             cube_ext::spawn(async move { pool_to_move.wait_processing_loops().await });
             let mut futures = Vec::new();
             for i in 0..5 {
-                futures.push((i, pool.process(Message::Delay(i * 300))));
+                futures.push((
+                    i,
+                    pool.process(Message::Delay(i * 300), CancellationToken::new()),
+                ));
             }
             for (i, f) in futures {
                 println!("Testing {} future", i);
@@ -443,13 +469,47 @@ mod tests {
             let pool_to_move = pool.clone();
             cube_ext::spawn(async move { pool_to_move.wait_processing_loops().await });
             assert_eq!(
-                pool.process(Message::Panic).await,
+                pool.process(Message::Panic, CancellationToken::new()).await,
                 Err(CubeError::panic("oops".to_string()))
             );
             pool.stop_workers().await.unwrap();
         });
     }
 
+    #[test]
+    fn test_cancel() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+
+        runtime.block_on(async move {
+            let pool = Arc::new(WorkerPool::<Message, Response, Processor>::new(
+                1,
+                Duration::from_millis(10000),
+            ));
+            let pool_to_move = pool.clone();
+            cube_ext::spawn(async move { pool_to_move.wait_processing_loops().await });
+            let cancel = CancellationToken::new();
+            let cancel_to_move = cancel.clone();
+            cube_ext::spawn(async move {
+                Delay::new(Duration::from_millis(200)).await;
+                cancel_to_move.cancel();
+            });
+            let start = std::time::Instant::now();
+            assert_eq!(
+                pool.process(Message::Delay(5000), cancel).await,
+                Err(query_cancelled_error())
+            );
+            assert!(start.elapsed() < Duration::from_millis(5000));
+            // The restarted process serves new messages.
+            assert_eq!(
+                pool.process(Message::Delay(100), CancellationToken::new())
+                    .await
+                    .unwrap(),
+                Response::Foo(100)
+            );
+            pool.stop_workers().await.unwrap();
+        });
+    }
+
     #[tokio::test]
     async fn serialize_plan() -> Result<(), CubeError> {
         let schema = Schema::new(vec![
@@ -71,6 +71,84 @@ pub fn enum_name_http_command(e: HttpCommand) -> &'static str {
 }
 
 pub struct HttpCommandUnionTableOffset {}
+#[allow(non_camel_case_types)]
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum HttpValue {
+    NONE = 0,
+    HttpInt64Value = 1,
+    HttpFloat64Value = 2,
+    HttpDecimalValue = 3,
+    HttpTimestampValue = 4,
+    HttpBoolValue = 5,
+    HttpBytesValue = 6,
+    HttpNullValue = 7,
+}
+
+pub const ENUM_MIN_HTTP_VALUE: u8 = 0;
+pub const ENUM_MAX_HTTP_VALUE: u8 = 7;
+
+impl<'a> flatbuffers::Follow<'a> for HttpValue {
+    type Inner = Self;
+    #[inline]
+    fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        flatbuffers::read_scalar_at::<Self>(buf, loc)
+    }
+}
+
+impl flatbuffers::EndianScalar for HttpValue {
+    #[inline]
+    fn to_little_endian(self) -> Self {
+        let n = u8::to_le(self as u8);
+        let p = &n as *const u8 as *const HttpValue;
+        unsafe { *p }
+    }
+    #[inline]
+    fn from_little_endian(self) -> Self {
+        let n = u8::from_le(self as u8);
+        let p = &n as *const u8 as *const HttpValue;
+        unsafe { *p }
+    }
+}
+
+impl flatbuffers::Push for HttpValue {
+    type Output = HttpValue;
+    #[inline]
+    fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+        flatbuffers::emplace_scalar::<HttpValue>(dst, *self);
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub const ENUM_VALUES_HTTP_VALUE: [HttpValue; 8] = [
+    HttpValue::NONE,
+    HttpValue::HttpInt64Value,
+    HttpValue::HttpFloat64Value,
+    HttpValue::HttpDecimalValue,
+    HttpValue::HttpTimestampValue,
+    HttpValue::HttpBoolValue,
+    HttpValue::HttpBytesValue,
+    HttpValue::HttpNullValue,
+];
+
+#[allow(non_camel_case_types)]
+pub const ENUM_NAMES_HTTP_VALUE: [&'static str; 8] = [
+    "NONE",
+    "HttpInt64Value",
+    "HttpFloat64Value",
+    "HttpDecimalValue",
+    "HttpTimestampValue",
+    "HttpBoolValue",
+    "HttpBytesValue",
+    "HttpNullValue",
+];
+
+pub fn enum_name_http_value(e: HttpValue) -> &'static str {
+    let index = e as u8;
+    ENUM_NAMES_HTTP_VALUE[index as usize]
+}
+
+pub struct HttpValueUnionTableOffset {}
 pub enum HttpMessageOffset {}
 #[derive(Copy, Clone, Debug, PartialEq)]
 
@@ -415,6 +493,9 @@ impl<'a> HttpResultSet<'a> {
         args: &'args HttpResultSetArgs<'args>,
     ) -> flatbuffers::WIPOffset<HttpResultSet<'bldr>> {
         let mut builder = HttpResultSetBuilder::new(_fbb);
+        if let Some(x) = args.column_types {
+            builder.add_column_types(x);
+        }
         if let Some(x) = args.rows {
             builder.add_rows(x);
         }
@@ -426,6 +507,7 @@ impl<'a> HttpResultSet<'a> {
 
     pub const VT_COLUMNS: flatbuffers::VOffsetT = 4;
     pub const VT_ROWS: flatbuffers::VOffsetT = 6;
+    pub const VT_COLUMN_TYPES: flatbuffers::VOffsetT = 8;
 
     #[inline]
     pub fn columns(
@@ -443,6 +525,14 @@ impl<'a> HttpResultSet<'a> {
             flatbuffers::Vector<flatbuffers::ForwardsUOffset<HttpRow<'a>>>,
         >>(HttpResultSet::VT_ROWS, None)
     }
+    #[inline]
+    pub fn column_types(
+        &self,
+    ) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<&'a str>>> {
+        self._tab.get::<flatbuffers::ForwardsUOffset<
+            flatbuffers::Vector<flatbuffers::ForwardsUOffset<&'a str>>,
+        >>(HttpResultSet::VT_COLUMN_TYPES, None)
+    }
 }
 
 pub struct HttpResultSetArgs<'a> {
@@ -452,6 +542,9 @@ pub struct HttpResultSetArgs<'a> {
     pub rows: Option<
         flatbuffers::WIPOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<HttpRow<'a>>>>,
     >,
+    pub column_types: Option<
+        flatbuffers::WIPOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<&'a str>>>,
+    >,
 }
 impl<'a> Default for HttpResultSetArgs<'a> {
     #[inline]
@@ -459,6 +552,7 @@ impl<'a> Default for HttpResultSetArgs<'a> {
         HttpResultSetArgs {
             columns: None,
             rows: None,
+            column_types: None,
         }
     }
 }
@@ -488,6 +582,18 @@ impl<'a: 'b, 'b> HttpResultSetBuilder<'a, 'b> {
             .push_slot_always::<flatbuffers::WIPOffset<_>>(HttpResultSet::VT_ROWS, rows);
     }
     #[inline]
+    pub fn add_column_types(
+        &mut self,
+        column_types: flatbuffers::WIPOffset<
+            flatbuffers::Vector<'b, flatbuffers::ForwardsUOffset<&'b str>>,
+        >,
+    ) {
+        self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(
+            HttpResultSet::VT_COLUMN_TYPES,
+            column_types,
+        );
+    }
+    #[inline]
     pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> HttpResultSetBuilder<'a, 'b> {
         let start = _fbb.start_table();
         HttpResultSetBuilder {
@@ -619,28 +725,123 @@ impl<'a> HttpColumnValue<'a> {
         args: &'args HttpColumnValueArgs<'args>,
     ) -> flatbuffers::WIPOffset<HttpColumnValue<'bldr>> {
         let mut builder = HttpColumnValueBuilder::new(_fbb);
+        if let Some(x) = args.value {
+            builder.add_value(x);
+        }
         if let Some(x) = args.string_value {
             builder.add_string_value(x);
         }
+        builder.add_value_type(args.value_type);
         builder.finish()
     }
 
     pub const VT_STRING_VALUE: flatbuffers::VOffsetT = 4;
+    pub const VT_VALUE_TYPE: flatbuffers::VOffsetT = 6;
+    pub const VT_VALUE: flatbuffers::VOffsetT = 8;
 
     #[inline]
     pub fn string_value(&self) -> Option<&'a str> {
         self._tab
             .get::<flatbuffers::ForwardsUOffset<&str>>(HttpColumnValue::VT_STRING_VALUE, None)
     }
+    #[inline]
+    pub fn value_type(&self) -> HttpValue {
+        self._tab
+            .get::<HttpValue>(HttpColumnValue::VT_VALUE_TYPE, Some(HttpValue::NONE))
+            .unwrap()
+    }
+    #[inline]
+    pub fn value(&self) -> Option<flatbuffers::Table<'a>> {
+        self._tab
+            .get::<flatbuffers::ForwardsUOffset<flatbuffers::Table<'a>>>(
+                HttpColumnValue::VT_VALUE,
+                None,
+            )
+    }
+    #[inline]
+    #[allow(non_snake_case)]
+    pub fn value_as_http_int64_value(&self) -> Option<HttpInt64Value<'a>> {
+        if self.value_type() == HttpValue::HttpInt64Value {
+            self.value().map(|u| HttpInt64Value::init_from_table(u))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    #[allow(non_snake_case)]
+    pub fn value_as_http_float64_value(&self) -> Option<HttpFloat64Value<'a>> {
+        if self.value_type() == HttpValue::HttpFloat64Value {
+            self.value().map(|u| HttpFloat64Value::init_from_table(u))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    #[allow(non_snake_case)]
+    pub fn value_as_http_decimal_value(&self) -> Option<HttpDecimalValue<'a>> {
+        if self.value_type() == HttpValue::HttpDecimalValue {
+            self.value().map(|u| HttpDecimalValue::init_from_table(u))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    #[allow(non_snake_case)]
+    pub fn value_as_http_timestamp_value(&self) -> Option<HttpTimestampValue<'a>> {
+        if self.value_type() == HttpValue::HttpTimestampValue {
+            self.value().map(|u| HttpTimestampValue::init_from_table(u))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    #[allow(non_snake_case)]
+    pub fn value_as_http_bool_value(&self) -> Option<HttpBoolValue<'a>> {
+        if self.value_type() == HttpValue::HttpBoolValue {
+            self.value().map(|u| HttpBoolValue::init_from_table(u))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    #[allow(non_snake_case)]
+    pub fn value_as_http_bytes_value(&self) -> Option<HttpBytesValue<'a>> {
+        if self.value_type() == HttpValue::HttpBytesValue {
+            self.value().map(|u| HttpBytesValue::init_from_table(u))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    #[allow(non_snake_case)]
+    pub fn value_as_http_null_value(&self) -> Option<HttpNullValue<'a>> {
+        if self.value_type() == HttpValue::HttpNullValue {
+            self.value().map(|u| HttpNullValue::init_from_table(u))
+        } else {
+            None
+        }
+    }
 }
 
 pub struct HttpColumnValueArgs<'a> {
     pub string_value: Option<flatbuffers::WIPOffset<&'a str>>,
+    pub value_type: HttpValue,
+    pub value: Option<flatbuffers::WIPOffset<flatbuffers::UnionWIPOffset>>,
 }
 impl<'a> Default for HttpColumnValueArgs<'a> {
     #[inline]
     fn default() -> Self {
-        HttpColumnValueArgs { string_value: None }
+        HttpColumnValueArgs {
+            string_value: None,
+            value_type: HttpValue::NONE,
+            value: None,
+        }
     }
 }
 pub struct HttpColumnValueBuilder<'a: 'b, 'b> {
@@ -656,6 +857,19 @@ impl<'a: 'b, 'b> HttpColumnValueBuilder<'a, 'b> {
         );
     }
     #[inline]
+    pub fn add_value_type(&mut self, value_type: HttpValue) {
+        self.fbb_.push_slot::<HttpValue>(
+            HttpColumnValue::VT_VALUE_TYPE,
+            value_type,
+            HttpValue::NONE,
+        );
+    }
+    #[inline]
+    pub fn add_value(&mut self, value: flatbuffers::WIPOffset<flatbuffers::UnionWIPOffset>) {
+        self.fbb_
+            .push_slot_always::<flatbuffers::WIPOffset<_>>(HttpColumnValue::VT_VALUE, value);
+    }
+    #[inline]
     pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> HttpColumnValueBuilder<'a, 'b> {
         let start = _fbb.start_table();
         HttpColumnValueBuilder {
@@ -670,6 +884,546 @@ impl<'a: 'b, 'b> HttpColumnValueBuilder<'a, 'b> {
     }
 }
 
+pub enum HttpInt64ValueOffset {}
+#[derive(Copy, Clone, Debug, PartialEq)]
+
+pub struct HttpInt64Value<'a> {
+    pub _tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> flatbuffers::Follow<'a> for HttpInt64Value<'a> {
+    type Inner = HttpInt64Value<'a>;
+    #[inline]
+    fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        Self {
+            _tab: flatbuffers::Table { buf: buf, loc: loc },
+        }
+    }
+}
+
+impl<'a> HttpInt64Value<'a> {
+    #[inline]
+    pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+        HttpInt64Value { _tab: table }
+    }
+    #[allow(unused_mut)]
+    pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+        _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+        args: &'args HttpInt64ValueArgs,
+    ) -> flatbuffers::WIPOffset<HttpInt64Value<'bldr>> {
+        let mut builder = HttpInt64ValueBuilder::new(_fbb);
+        builder.add_value(args.value);
+        builder.finish()
+    }
+
+    pub const VT_VALUE: flatbuffers::VOffsetT = 4;
+
+    #[inline]
+    pub fn value(&self) -> i64 {
+        self._tab
+            .get::<i64>(HttpInt64Value::VT_VALUE, Some(0))
+            .unwrap()
+    }
+}
+
+pub struct HttpInt64ValueArgs {
+    pub value: i64,
+}
+impl<'a> Default for HttpInt64ValueArgs {
+    #[inline]
+    fn default() -> Self {
+        HttpInt64ValueArgs { value: 0 }
+    }
+}
+pub struct HttpInt64ValueBuilder<'a: 'b, 'b> {
+    fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+}
+impl<'a: 'b, 'b> HttpInt64ValueBuilder<'a, 'b> {
+    #[inline]
+    pub fn add_value(&mut self, value: i64) {
+        self.fbb_
+            .push_slot::<i64>(HttpInt64Value::VT_VALUE, value, 0);
+    }
+    #[inline]
+    pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> HttpInt64ValueBuilder<'a, 'b> {
+        let start = _fbb.start_table();
+        HttpInt64ValueBuilder {
+            fbb_: _fbb,
+            start_: start,
+        }
+    }
+    #[inline]
+    pub fn finish(self) -> flatbuffers::WIPOffset<HttpInt64Value<'a>> {
+        let o = self.fbb_.end_table(self.start_);
+        flatbuffers::WIPOffset::new(o.value())
+    }
+}
+
+pub enum HttpFloat64ValueOffset {}
+#[derive(Copy, Clone, Debug, PartialEq)]
+
+pub struct HttpFloat64Value<'a> {
+    pub _tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> flatbuffers::Follow<'a> for HttpFloat64Value<'a> {
+    type Inner = HttpFloat64Value<'a>;
+    #[inline]
+    fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        Self {
+            _tab: flatbuffers::Table { buf: buf, loc: loc },
+        }
+    }
+}
+
+impl<'a> HttpFloat64Value<'a> {
+    #[inline]
+    pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+        HttpFloat64Value { _tab: table }
+    }
+    #[allow(unused_mut)]
+    pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+        _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+        args: &'args HttpFloat64ValueArgs,
+    ) -> flatbuffers::WIPOffset<HttpFloat64Value<'bldr>> {
+        let mut builder = HttpFloat64ValueBuilder::new(_fbb);
+        builder.add_value(args.value);
+        builder.finish()
+    }
+
+    pub const VT_VALUE: flatbuffers::VOffsetT = 4;
+
+    #[inline]
+    pub fn value(&self) -> f64 {
+        self._tab
+            .get::<f64>(HttpFloat64Value::VT_VALUE, Some(0.0))
+            .unwrap()
+    }
+}
+
+pub struct HttpFloat64ValueArgs {
+    pub value: f64,
+}
+impl<'a> Default for HttpFloat64ValueArgs {
+    #[inline]
+    fn default() -> Self {
+        HttpFloat64ValueArgs { value: 0.0 }
+    }
+}
+pub struct HttpFloat64ValueBuilder<'a: 'b, 'b> {
+    fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+}
+impl<'a: 'b, 'b> HttpFloat64ValueBuilder<'a, 'b> {
+    #[inline]
+    pub fn add_value(&mut self, value: f64) {
+        self.fbb_
+            .push_slot::<f64>(HttpFloat64Value::VT_VALUE, value, 0.0);
+    }
+    #[inline]
+    pub fn new(
+        _fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    ) -> HttpFloat64ValueBuilder<'a, 'b> {
+        let start = _fbb.start_table();
+        HttpFloat64ValueBuilder {
+            fbb_: _fbb,
+            start_: start,
+        }
+    }
+    #[inline]
+    pub fn finish(self) -> flatbuffers::WIPOffset<HttpFloat64Value<'a>> {
+        let o = self.fbb_.end_table(self.start_);
+        flatbuffers::WIPOffset::new(o.value())
+    }
+}
+
+pub enum HttpDecimalValueOffset {}
+#[derive(Copy, Clone, Debug, PartialEq)]
+
+pub struct HttpDecimalValue<'a> {
+    pub _tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> flatbuffers::Follow<'a> for HttpDecimalValue<'a> {
+    type Inner = HttpDecimalValue<'a>;
+    #[inline]
+    fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        Self {
+            _tab: flatbuffers::Table { buf: buf, loc: loc },
+        }
+    }
+}
+
+impl<'a> HttpDecimalValue<'a> {
+    #[inline]
+    pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+        HttpDecimalValue { _tab: table }
+    }
+    #[allow(unused_mut)]
+    pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+        _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+        args: &'args HttpDecimalValueArgs,
+    ) -> flatbuffers::WIPOffset<HttpDecimalValue<'bldr>> {
+        let mut builder = HttpDecimalValueBuilder::new(_fbb);
+        builder.add_value(args.value);
+        builder.add_scale(args.scale);
+        builder.finish()
+    }
+
+    pub const VT_VALUE: flatbuffers::VOffsetT = 4;
+    pub const VT_SCALE: flatbuffers::VOffsetT = 6;
+
+    #[inline]
+    pub fn value(&self) -> i64 {
+        self._tab
+            .get::<i64>(HttpDecimalValue::VT_VALUE, Some(0))
+            .unwrap()
+    }
+    #[inline]
+    pub fn scale(&self) -> i32 {
+        self._tab
+            .get::<i32>(HttpDecimalValue::VT_SCALE, Some(0))
+            .unwrap()
+    }
+}
+
+pub struct HttpDecimalValueArgs {
+    pub value: i64,
+    pub scale: i32,
+}
+impl<'a> Default for HttpDecimalValueArgs {
+    #[inline]
+    fn default() -> Self {
+        HttpDecimalValueArgs { value: 0, scale: 0 }
+    }
+}
+pub struct HttpDecimalValueBuilder<'a: 'b, 'b> {
+    fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+}
+impl<'a: 'b, 'b> HttpDecimalValueBuilder<'a, 'b> {
+    #[inline]
+    pub fn add_value(&mut self, value: i64) {
+        self.fbb_
+            .push_slot::<i64>(HttpDecimalValue::VT_VALUE, value, 0);
+    }
+    #[inline]
+    pub fn add_scale(&mut self, scale: i32) {
+        self.fbb_
+            .push_slot::<i32>(HttpDecimalValue::VT_SCALE, scale, 0);
+    }
+    #[inline]
+    pub fn new(
+        _fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    ) -> HttpDecimalValueBuilder<'a, 'b> {
+        let start = _fbb.start_table();
+        HttpDecimalValueBuilder {
+            fbb_: _fbb,
+            start_: start,
+        }
+    }
+    #[inline]
+    pub fn finish(self) -> flatbuffers::WIPOffset<HttpDecimalValue<'a>> {
+        let o = self.fbb_.end_table(self.start_);
+        flatbuffers::WIPOffset::new(o.value())
+    }
+}
+
+pub enum HttpTimestampValueOffset {}
+#[derive(Copy, Clone, Debug, PartialEq)]
+
+pub struct HttpTimestampValue<'a> {
+    pub _tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> flatbuffers::Follow<'a> for HttpTimestampValue<'a> {
+    type Inner = HttpTimestampValue<'a>;
+    #[inline]
+    fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        Self {
+            _tab: flatbuffers::Table { buf: buf, loc: loc },
+        }
+    }
+}
+
+impl<'a> HttpTimestampValue<'a> {
+    #[inline]
+    pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+        HttpTimestampValue { _tab: table }
+    }
+    #[allow(unused_mut)]
+    pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+        _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+        args: &'args HttpTimestampValueArgs,
+    ) -> flatbuffers::WIPOffset<HttpTimestampValue<'bldr>> {
+        let mut builder = HttpTimestampValueBuilder::new(_fbb);
+        builder.add_micros(args.micros);
+        builder.finish()
+    }
+
+    pub const VT_MICROS: flatbuffers::VOffsetT = 4;
+
+    #[inline]
+    pub fn micros(&self) -> i64 {
+        self._tab
+            .get::<i64>(HttpTimestampValue::VT_MICROS, Some(0))
+            .unwrap()
+    }
+}
+
+pub struct HttpTimestampValueArgs {
+    pub micros: i64,
+}
+impl<'a> Default for HttpTimestampValueArgs {
+    #[inline]
+    fn default() -> Self {
+        HttpTimestampValueArgs { micros: 0 }
+    }
+}
+pub struct HttpTimestampValueBuilder<'a: 'b, 'b> {
+    fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+}
+impl<'a: 'b, 'b> HttpTimestampValueBuilder<'a, 'b> {
+    #[inline]
+    pub fn add_micros(&mut self, micros: i64) {
+        self.fbb_
+            .push_slot::<i64>(HttpTimestampValue::VT_MICROS, micros, 0);
+    }
+    #[inline]
+    pub fn new(
+        _fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    ) -> HttpTimestampValueBuilder<'a, 'b> {
+        let start = _fbb.start_table();
+        HttpTimestampValueBuilder {
+            fbb_: _fbb,
+            start_: start,
+        }
+    }
+    #[inline]
+    pub fn finish(self) -> flatbuffers::WIPOffset<HttpTimestampValue<'a>> {
+        let o = self.fbb_.end_table(self.start_);
+        flatbuffers::WIPOffset::new(o.value())
+    }
+}
+
+pub enum HttpBoolValueOffset {}
+#[derive(Copy, Clone, Debug, PartialEq)]
+
+pub struct HttpBoolValue<'a> {
+    pub _tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> flatbuffers::Follow<'a> for HttpBoolValue<'a> {
+    type Inner = HttpBoolValue<'a>;
+    #[inline]
+    fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        Self {
+            _tab: flatbuffers::Table { buf: buf, loc: loc },
+        }
+    }
+}
+
+impl<'a> HttpBoolValue<'a> {
+    #[inline]
+    pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+        HttpBoolValue { _tab: table }
+    }
+    #[allow(unused_mut)]
+    pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+        _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+        args: &'args HttpBoolValueArgs,
+    ) -> flatbuffers::WIPOffset<HttpBoolValue<'bldr>> {
+        let mut builder = HttpBoolValueBuilder::new(_fbb);
+        builder.add_value(args.value);
+        builder.finish()
+    }
+
+    pub const VT_VALUE: flatbuffers::VOffsetT = 4;
+
+    #[inline]
+    pub fn value(&self) -> bool {
+        self._tab
+            .get::<bool>(HttpBoolValue::VT_VALUE, Some(false))
+            .unwrap()
+    }
+}
+
+pub struct HttpBoolValueArgs {
+    pub value: bool,
+}
+impl<'a> Default for HttpBoolValueArgs {
+    #[inline]
+    fn default() -> Self {
+        HttpBoolValueArgs { value: false }
+    }
+}
+pub struct HttpBoolValueBuilder<'a: 'b, 'b> {
+    fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+}
+impl<'a: 'b, 'b> HttpBoolValueBuilder<'a, 'b> {
+    #[inline]
+    pub fn add_value(&mut self, value: bool) {
+        self.fbb_
+            .push_slot::<bool>(HttpBoolValue::VT_VALUE, value, false);
+    }
+    #[inline]
+    pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> HttpBoolValueBuilder<'a, 'b> {
+        let start = _fbb.start_table();
+        HttpBoolValueBuilder {
+            fbb_: _fbb,
+            start_: start,
+        }
+    }
+    #[inline]
+    pub fn finish(self) -> flatbuffers::WIPOffset<HttpBoolValue<'a>> {
+        let o = self.fbb_.end_table(self.start_);
+        flatbuffers::WIPOffset::new(o.value())
+    }
+}
+
+pub enum HttpBytesValueOffset {}
+#[derive(Copy, Clone, Debug, PartialEq)]
+
+pub struct HttpBytesValue<'a> {
+    pub _tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> flatbuffers::Follow<'a> for HttpBytesValue<'a> {
+    type Inner = HttpBytesValue<'a>;
+    #[inline]
+    fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        Self {
+            _tab: flatbuffers::Table { buf: buf, loc: loc },
+        }
+    }
+}
+
+impl<'a> HttpBytesValue<'a> {
+    #[inline]
+    pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+        HttpBytesValue { _tab: table }
+    }
+    #[allow(unused_mut)]
+    pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+        _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+        args: &'args HttpBytesValueArgs<'args>,
+    ) -> flatbuffers::WIPOffset<HttpBytesValue<'bldr>> {
+        let mut builder = HttpBytesValueBuilder::new(_fbb);
+        if let Some(x) = args.value {
+            builder.add_value(x);
+        }
+        builder.finish()
+    }
+
+    pub const VT_VALUE: flatbuffers::VOffsetT = 4;
+
+    #[inline]
+    pub fn value(&self) -> Option<&'a [u8]> {
+        self._tab
+            .get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(
+                HttpBytesValue::VT_VALUE,
+                None,
+            )
+            .map(|v| v.safe_slice())
+    }
+}
+
+pub struct HttpBytesValueArgs<'a> {
+    pub value: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a, u8>>>,
+}
+impl<'a> Default for HttpBytesValueArgs<'a> {
+    #[inline]
+    fn default() -> Self {
+        HttpBytesValueArgs { value: None }
+    }
+}
+pub struct HttpBytesValueBuilder<'a: 'b, 'b> {
+    fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+}
+impl<'a: 'b, 'b> HttpBytesValueBuilder<'a, 'b> {
+    #[inline]
+    pub fn add_value(&mut self, value: flatbuffers::WIPOffset<flatbuffers::Vector<'b, u8>>) {
+        self.fbb_
+            .push_slot_always::<flatbuffers::WIPOffset<_>>(HttpBytesValue::VT_VALUE, value);
+    }
+    #[inline]
+    pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> HttpBytesValueBuilder<'a, 'b> {
+        let start = _fbb.start_table();
+        HttpBytesValueBuilder {
+            fbb_: _fbb,
+            start_: start,
+        }
+    }
+    #[inline]
+    pub fn finish(self) -> flatbuffers::WIPOffset<HttpBytesValue<'a>> {
+        let o = self.fbb_.end_table(self.start_);
+        flatbuffers::WIPOffset::new(o.value())
+    }
+}
+
+pub enum HttpNullValueOffset {}
+#[derive(Copy, Clone, Debug, PartialEq)]
+
+pub struct HttpNullValue<'a> {
+    pub _tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> flatbuffers::Follow<'a> for HttpNullValue<'a> {
+    type Inner = HttpNullValue<'a>;
+    #[inline]
+    fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        Self {
+            _tab: flatbuffers::Table { buf: buf, loc: loc },
+        }
+    }
+}
+
+impl<'a> HttpNullValue<'a> {
+    #[inline]
+    pub fn init_from_table(table: flatbuffers::Table<'a>) -> Self {
+        HttpNullValue { _tab: table }
+    }
+    #[allow(unused_mut)]
+    pub fn create<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
+        _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
+        _args: &'args HttpNullValueArgs,
+    ) -> flatbuffers::WIPOffset<HttpNullValue<'bldr>> {
+        let mut builder = HttpNullValueBuilder::new(_fbb);
+        builder.finish()
+    }
+}
+
+pub struct HttpNullValueArgs {}
+impl<'a> Default for HttpNullValueArgs {
+    #[inline]
+    fn default() -> Self {
+        HttpNullValueArgs {}
+    }
+}
+pub struct HttpNullValueBuilder<'a: 'b, 'b> {
+    fbb_: &'b mut flatbuffers::FlatBufferBuilder<'a>,
+    start_: flatbuffers::WIPOffset<flatbuffers::TableUnfinishedWIPOffset>,
+}
+impl<'a: 'b, 'b> HttpNullValueBuilder<'a, 'b> {
+    #[inline]
+    pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> HttpNullValueBuilder<'a, 'b> {
+        let start = _fbb.start_table();
+        HttpNullValueBuilder {
+            fbb_: _fbb,
+            start_: start,
+        }
+    }
+    #[inline]
+    pub fn finish(self) -> flatbuffers::WIPOffset<HttpNullValue<'a>> {
+        let o = self.fbb_.end_table(self.start_);
+        flatbuffers::WIPOffset::new(o.value())
+    }
+}
+
 #[inline]
 pub fn get_root_as_http_message<'a>(buf: &'a [u8]) -> HttpMessage<'a> {
     flatbuffers::get_root::<HttpMessage<'a>>(buf)
@@ -8,19 +8,26 @@ use crate::cluster::transport::{
 use crate::cluster::{Cluster, ClusterImpl, ClusterMetaStoreClient};
 use crate::config::injection::{DIService, Injector};
 use crate::config::processing_loop::ProcessingLoop;
+use crate::flight::FlightServer;
 use crate::http::HttpServer;
 use crate::import::limits::ConcurrencyLimits;
 use crate::import::{ImportService, ImportServiceImpl};
+use crate::metastore::backup::MetaStoreBackup;
+use crate::metastore::table::ParquetCompression;
 use crate::metastore::{MetaStore, MetaStoreRpcClient, RocksMetaStore};
 use crate::mysql::{MySqlServer, SqlAuthDefaultImpl, SqlAuthService};
 use crate::queryplanner::query_executor::{QueryExecutor, QueryExecutorImpl};
 use crate::queryplanner::{QueryPlanner, QueryPlannerImpl};
+use crate::remotefs::azure::AzureBlobRemoteFs;
+use crate::remotefs::cache::CacheEvictionPolicy;
+use crate::remotefs::encrypted::{EncryptedRemoteFs, LocalKeyProvider};
 use crate::remotefs::gcs::GCSRemoteFs;
 use crate::remotefs::minio::MINIORemoteFs;
 use crate::remotefs::queue::QueueRemoteFs;
 use crate::remotefs::s3::S3RemoteFs;
 use crate::remotefs::{LocalDirRemoteFs, RemoteFs};
 use crate::scheduler::SchedulerImpl;
+use crate::sql::query_log::QueryLog;
 use crate::sql::{SqlService, SqlServiceImpl};
 use crate::store::compaction::{CompactionService, CompactionServiceImpl};
 use crate::store::{ChunkDataStore, ChunkStore, WALDataStore, WALStore};
@@ -30,6 +37,7 @@ use crate::telemetry::{
     start_agent_event_loop, start_track_event_loop, stop_agent_event_loop, stop_track_event_loop,
 };
 use crate::CubeError;
+use chrono::{DateTime, Utc};
 use datafusion::cube_ext;
 use datafusion::physical_plan::parquet::{LruParquetMetadataCache, NoopParquetMetadataCache};
 use futures::future::join_all;
@@ -99,10 +107,15 @@ impl CubeServices {
         }));
         if !self.cluster.is_select_worker() {
             let rocks_meta_store = self.rocks_meta_store.clone().unwrap();
+            let rocks_meta_store2 = rocks_meta_store.clone();
             futures.push(cube_ext::spawn(async move {
                 RocksMetaStore::wait_upload_loop(rocks_meta_store).await;
                 Ok(())
             }));
+            futures.push(cube_ext::spawn(async move {
+                RocksMetaStore::wait_metrics_loop(rocks_meta_store2).await;
+                Ok(())
+            }));
             let cluster = self.cluster.clone();
             let (started_tx, started_rx) = tokio::sync::oneshot::channel();
             futures.push(cube_ext::spawn(async move {
@@ -125,6 +138,12 @@ impl CubeServices {
                     async move { http_server.run_server().await },
                 ));
             }
+            if self.injector.has_service_typed::<FlightServer>().await {
+                let flight_server = self.injector.get_service_typed::<FlightServer>().await;
+                futures.push(cube_ext::spawn(
+                    async move { flight_server.run_server().await },
+                ));
+            }
         } else {
             let cluster = self.cluster.clone();
             let (started_tx, started_rx) = tokio::sync::oneshot::channel();
@@ -174,6 +193,13 @@ impl CubeServices {
                 .stop_processing()
                 .await;
         }
+        if self.injector.has_service_typed::<FlightServer>().await {
+            self.injector
+                .get_service_typed::<FlightServer>()
+                .await
+                .stop_processing()
+                .await;
+        }
         self.scheduler.stop_processing_loops()?;
         stop_track_event_loop().await;
         stop_agent_event_loop().await;
@@ -238,6 +264,7 @@ pub fn validate_config(c: &dyn ConfigObj) -> ValidationMessages {
         "CUBESTORE_MINIO_BUCKET",
         "CUBESTORE_S3_BUCKET",
         "CUBESTORE_GCS_BUCKET",
+        "CUBESTORE_AZURE_CONTAINER",
         "CUBESTORE_REMOTE_DIR",
     ];
     remote_vars.retain(|v| env::var(v).is_ok());
@@ -271,6 +298,11 @@ pub enum FileStoreProvider {
         bucket_name: String,
         sub_path: Option<String>,
     },
+    Azure {
+        account: String,
+        container: String,
+        sub_path: Option<String>,
+    },
 }
 
 #[derive(Clone)]
@@ -311,6 +343,8 @@ pub trait ConfigObj: DIService {
 
     fn http_bind_address(&self) -> &Option<String>;
 
+    fn flight_bind_address(&self) -> &Option<String>;
+
     fn query_timeout(&self) -> u64;
 
     fn not_used_timeout(&self) -> u64;
@@ -319,10 +353,16 @@ pub trait ConfigObj: DIService {
 
     fn meta_store_snapshot_interval(&self) -> u64;
 
+    fn meta_store_snapshot_retention(&self) -> u64;
+
+    fn meta_store_snapshots_keep(&self) -> u64;
+
     fn meta_store_log_upload_interval(&self) -> u64;
 
     fn gc_loop_interval(&self) -> u64;
 
+    fn ttl_check_interval(&self) -> u64;
+
     fn stale_stream_timeout(&self) -> u64;
 
     fn select_workers(&self) -> &Vec<String>;
@@ -349,6 +389,17 @@ pub trait ConfigObj: DIService {
 
     fn enable_topk(&self) -> bool;
 
+    fn broadcast_join_max_rows(&self) -> u64;
+
+    fn query_memory_limit_bytes(&self) -> u64;
+
+    fn query_log_size(&self) -> usize;
+
+    fn parquet_compression(&self) -> ParquetCompression;
+
+    /// None means dictionary encoding is picked automatically for string columns.
+    fn parquet_dictionary(&self) -> Option<bool>;
+
     fn enable_startup_warmup(&self) -> bool;
 
     fn malloc_trim_every_secs(&self) -> u64;
@@ -360,6 +411,22 @@ pub trait ConfigObj: DIService {
     fn metadata_cache_time_to_idle_secs(&self) -> u64;
 
     fn dump_dir(&self) -> &Option<PathBuf>;
+
+    /// Files uploaded to the remote storage are encrypted with keys from this file if set.
+    fn encryption_key_file(&self) -> &Option<PathBuf>;
+
+    /// Whether files without the encryption header are read as is when encryption is on. Allows
+    /// turning encryption on for storage with files uploaded before. Off unless set explicitly.
+    fn encryption_allow_plaintext(&self) -> bool;
+
+    /// Max size of partition and chunk files kept on the local disk. None means no limit.
+    fn local_cache_max_size(&self) -> Option<u64>;
+
+    /// Whether uploaded files are kept on the remote storage. Otherwise the local directory holds
+    /// the only copy.
+    fn has_remote_storage(&self) -> bool;
+
+    fn local_cache_eviction_policy(&self) -> CacheEvictionPolicy;
 }
 
 #[derive(Debug, Clone)]
@@ -376,19 +443,25 @@ pub struct ConfigObjImpl {
     pub wal_split_threshold: u64,
     pub data_dir: PathBuf,
     pub dump_dir: Option<PathBuf>,
+    pub encryption_key_file: Option<PathBuf>,
+    pub encryption_allow_plaintext: bool,
     pub store_provider: FileStoreProvider,
     pub select_worker_pool_size: usize,
     pub job_runners_count: usize,
     pub bind_address: Option<String>,
     pub status_bind_address: Option<String>,
     pub http_bind_address: Option<String>,
+    pub flight_bind_address: Option<String>,
     pub query_timeout: u64,
     /// Must be set to 2*query_timeout in prod, only for overrides in tests.
     pub not_used_timeout: u64,
     pub import_job_timeout: u64,
     pub meta_store_log_upload_interval: u64,
     pub meta_store_snapshot_interval: u64,
+    pub meta_store_snapshot_retention: u64,
+    pub meta_store_snapshots_keep: u64,
     pub gc_loop_interval: u64,
+    pub ttl_check_interval: u64,
     pub stale_stream_timeout: u64,
     pub select_workers: Vec<String>,
     pub worker_bind_address: Option<String>,
@@ -401,11 +474,18 @@ pub struct ConfigObjImpl {
     pub max_ingestion_data_frames: usize,
     pub upload_to_remote: bool,
     pub enable_topk: bool,
+    pub broadcast_join_max_rows: u64,
+    pub query_memory_limit_bytes: u64,
+    pub query_log_size: usize,
+    pub parquet_compression: ParquetCompression,
+    pub parquet_dictionary: Option<bool>,
     pub enable_startup_warmup: bool,
     pub malloc_trim_every_secs: u64,
     pub max_cached_queries: usize,
     pub metadata_cache_max_capacity_bytes: u64,
     pub metadata_cache_time_to_idle_secs: u64,
+    pub local_cache_max_size: Option<u64>,
+    pub local_cache_eviction_policy: CacheEvictionPolicy,
 }
 
 crate::di_service!(ConfigObjImpl, [ConfigObj]);
@@ -472,6 +552,10 @@ impl ConfigObj for ConfigObjImpl {
         &self.http_bind_address
     }
 
+    fn flight_bind_address(&self) -> &Option<String> {
+        &self.flight_bind_address
+    }
+
     fn query_timeout(&self) -> u64 {
         self.query_timeout
     }
@@ -488,6 +572,14 @@ impl ConfigObj for ConfigObjImpl {
         self.meta_store_snapshot_interval
     }
 
+    fn meta_store_snapshot_retention(&self) -> u64 {
+        self.meta_store_snapshot_retention
+    }
+
+    fn meta_store_snapshots_keep(&self) -> u64 {
+        self.meta_store_snapshots_keep
+    }
+
     fn meta_store_log_upload_interval(&self) -> u64 {
         self.meta_store_log_upload_interval
     }
@@ -496,6 +588,10 @@ impl ConfigObj for ConfigObjImpl {
         self.gc_loop_interval
     }
 
+    fn ttl_check_interval(&self) -> u64 {
+        self.ttl_check_interval
+    }
+
     fn stale_stream_timeout(&self) -> u64 {
         self.stale_stream_timeout
     }
@@ -548,6 +644,26 @@ impl ConfigObj for ConfigObjImpl {
         self.enable_topk
     }
 
+    fn broadcast_join_max_rows(&self) -> u64 {
+        self.broadcast_join_max_rows
+    }
+
+    fn query_memory_limit_bytes(&self) -> u64 {
+        self.query_memory_limit_bytes
+    }
+
+    fn query_log_size(&self) -> usize {
+        self.query_log_size
+    }
+
+    fn parquet_compression(&self) -> ParquetCompression {
+        self.parquet_compression
+    }
+
+    fn parquet_dictionary(&self) -> Option<bool> {
+        self.parquet_dictionary
+    }
+
     fn enable_startup_warmup(&self) -> bool {
         self.enable_startup_warmup
     }
@@ -567,6 +683,43 @@ impl ConfigObj for ConfigObjImpl {
     fn dump_dir(&self) -> &Option<PathBuf> {
         &self.dump_dir
     }
+
+    fn encryption_key_file(&self) -> &Option<PathBuf> {
+        &self.encryption_key_file
+    }
+
+    fn encryption_allow_plaintext(&self) -> bool {
+        self.encryption_allow_plaintext
+    }
+
+    fn local_cache_max_size(&self) -> Option<u64> {
+        self.local_cache_max_size
+    }
+
+    fn has_remote_storage(&self) -> bool {
+        self.upload_to_remote
+            && !matches!(
+                self.store_provider,
+                FileStoreProvider::Local | FileStoreProvider::Filesystem { remote_dir: None }
+            )
+    }
+
+    fn local_cache_eviction_policy(&self) -> CacheEvictionPolicy {
+        self.local_cache_eviction_policy
+    }
+}
+
+fn with_encryption<T: RemoteFs + 'static>(
+    remote_fs: Arc<T>,
+    encryption: Option<(PathBuf, PathBuf, bool)>,
+) -> Arc<dyn DIService> {
+    match encryption {
+        Some((key_file, data_dir, allow_plaintext)) => {
+            let key_provider = LocalKeyProvider::from_file(&key_file).unwrap();
+            EncryptedRemoteFs::new(remote_fs, Arc::new(key_provider), data_dir, allow_plaintext)
+        }
+        None => remote_fs,
+    }
 }
 
 lazy_static! {
@@ -585,6 +738,18 @@ fn env_bool(name: &str, default: bool) -> bool {
         .unwrap_or(default)
 }
 
+fn env_parquet_dictionary(name: &str) -> Option<bool> {
+    match env::var(name).ok().as_deref() {
+        None | Some("auto") => None,
+        Some("true") => Some(true),
+        Some("false") => Some(false),
+        Some(x) => panic!(
+            "expected 'auto', 'true' or 'false' for '{}', found '{}'",
+            name, x
+        ),
+    }
+}
+
 pub fn env_parse<T>(name: &str, default: T) -> T
 where
     T: FromStr,
@@ -620,6 +785,10 @@ impl Config {
                 dump_dir: env::var("CUBESTORE_DUMP_DIR")
                     .ok()
                     .map(|v| PathBuf::from(v)),
+                encryption_key_file: env::var("CUBESTORE_ENCRYPTION_KEY_FILE")
+                    .ok()
+                    .map(|v| PathBuf::from(v)),
+                encryption_allow_plaintext: env_bool("CUBESTORE_ENCRYPTION_ALLOW_PLAINTEXT", false),
                 partition_split_threshold: env_parse(
                     "CUBESTORE_PARTITION_SPLIT_THRESHOLD",
                     1048576 * 2,
@@ -672,6 +841,14 @@ impl Config {
                             bucket_name,
                             sub_path: env::var("CUBESTORE_GCS_SUB_PATH").ok(),
                         }
+                    } else if let Ok(container) = env::var("CUBESTORE_AZURE_CONTAINER") {
+                        FileStoreProvider::Azure {
+                            account: env::var("CUBESTORE_AZURE_ACCOUNT").expect(
+                                "CUBESTORE_AZURE_ACCOUNT required when CUBESTORE_AZURE_CONTAINER is set",
+                            ),
+                            container,
+                            sub_path: env::var("CUBESTORE_AZURE_SUB_PATH").ok(),
+                        }
                     } else if let Ok(remote_dir) = env::var("CUBESTORE_REMOTE_DIR") {
                         FileStoreProvider::Filesystem {
                             remote_dir: Some(PathBuf::from(remote_dir)),
@@ -692,12 +869,24 @@ impl Config {
                 http_bind_address: Some(env::var("CUBESTORE_HTTP_BIND_ADDR").ok().unwrap_or(
                     format!("0.0.0.0:{}", env_parse("CUBESTORE_HTTP_PORT", 3030)),
                 )),
+                // Flight endpoint is disabled unless an address or a port is set.
+                flight_bind_address: env::var("CUBESTORE_FLIGHT_BIND_ADDR").ok().or_else(|| {
+                    env::var("CUBESTORE_FLIGHT_PORT")
+                        .ok()
+                        .map(|_| format!("0.0.0.0:{}", env_parse("CUBESTORE_FLIGHT_PORT", 3033)))
+                }),
                 query_timeout,
                 not_used_timeout: 2 * query_timeout,
                 import_job_timeout: env_parse("CUBESTORE_IMPORT_JOB_TIMEOUT", 600),
                 meta_store_log_upload_interval: 30,
                 meta_store_snapshot_interval: 300,
+                meta_store_snapshot_retention: env_parse(
+                    "CUBESTORE_METASTORE_SNAPSHOTS_RETENTION_SECS",
+                    3 * 60,
+                ),
+                meta_store_snapshots_keep: env_parse("CUBESTORE_METASTORE_SNAPSHOTS_KEEP", 10),
                 gc_loop_interval: 60,
+                ttl_check_interval: env_parse("CUBESTORE_TTL_CHECK_INTERVAL_SECS", 60 * 60),
                 stale_stream_timeout: 60,
                 select_workers: env::var("CUBESTORE_WORKERS")
                     .ok()
@@ -721,6 +910,14 @@ impl Config {
                     .unwrap_or("localhost".to_string()),
                 upload_to_remote: !env::var("CUBESTORE_NO_UPLOAD").ok().is_some(),
                 enable_topk: env_bool("CUBESTORE_ENABLE_TOPK", true),
+                broadcast_join_max_rows: env_parse("CUBESTORE_BROADCAST_JOIN_MAX_ROWS", 100_000),
+                query_memory_limit_bytes: env_parse("CUBESTORE_QUERY_MEMORY_LIMIT_BYTES", 0),
+                query_log_size: env_parse("CUBESTORE_QUERY_LOG_SIZE", 1000),
+                parquet_compression: env_parse(
+                    "CUBESTORE_PARQUET_COMPRESSION",
+                    ParquetCompression::Uncompressed,
+                ),
+                parquet_dictionary: env_parquet_dictionary("CUBESTORE_PARQUET_DICTIONARY"),
                 enable_startup_warmup: env_bool("CUBESTORE_STARTUP_WARMUP", true),
                 malloc_trim_every_secs: env_parse("CUBESTORE_MALLOC_TRIM_EVERY_SECS", 30),
                 max_cached_queries: env_parse("CUBESTORE_MAX_CACHED_QUERIES", 10_000),
@@ -732,6 +929,11 @@ impl Config {
                     "CUBESTORE_METADATA_CACHE_TIME_TO_IDLE_SECS",
                     0,
                 ),
+                local_cache_max_size: env_optparse("CUBESTORE_LOCAL_CACHE_MAX_SIZE"),
+                local_cache_eviction_policy: env_parse(
+                    "CUBESTORE_LOCAL_CACHE_EVICTION_POLICY",
+                    CacheEvictionPolicy::Lru,
+                ),
             }),
         }
     }
@@ -745,6 +947,8 @@ impl Config {
                     .unwrap()
                     .join(format!("{}-local-store", name)),
                 dump_dir: None,
+                encryption_key_file: None,
+                encryption_allow_plaintext: false,
                 partition_split_threshold: 20,
                 max_partition_split_threshold: 20,
                 compaction_chunks_count_threshold: 1,
@@ -766,6 +970,7 @@ impl Config {
                 bind_address: None,
                 status_bind_address: None,
                 http_bind_address: None,
+                flight_bind_address: None,
                 query_timeout,
                 not_used_timeout: 2 * query_timeout,
                 import_job_timeout: 600,
@@ -782,14 +987,24 @@ impl Config {
                 server_name: "localhost".to_string(),
                 upload_to_remote: true,
                 enable_topk: true,
+                broadcast_join_max_rows: 100_000,
+                query_memory_limit_bytes: 0,
+                query_log_size: 1000,
+                parquet_compression: ParquetCompression::Uncompressed,
+                parquet_dictionary: None,
                 enable_startup_warmup: true,
                 malloc_trim_every_secs: 0,
                 max_cached_queries: 10_000,
                 metadata_cache_max_capacity_bytes: 0,
                 metadata_cache_time_to_idle_secs: 1_000,
+                local_cache_max_size: None,
+                local_cache_eviction_policy: CacheEvictionPolicy::Lru,
                 meta_store_log_upload_interval: 30,
                 meta_store_snapshot_interval: 300,
+                meta_store_snapshot_retention: 3 * 60,
+                meta_store_snapshots_keep: 10,
                 gc_loop_interval: 60,
+                ttl_check_interval: 60 * 60,
             }),
         }
     }
@@ -947,11 +1162,10 @@ impl Config {
         match &self.config_obj.store_provider {
             FileStoreProvider::Filesystem { remote_dir } => {
                 let remote_dir = remote_dir.clone();
-                let data_dir = self.config_obj.data_dir.clone();
+                let (backend_dir, encryption) = self.remote_fs_encryption();
                 self.injector
                     .register("original_remote_fs", async move |_| {
-                        let arc: Arc<dyn DIService> = LocalDirRemoteFs::new(remote_dir, data_dir);
-                        arc
+                        with_encryption(LocalDirRemoteFs::new(remote_dir, backend_dir), encryption)
                     })
                     .await;
             }
@@ -960,15 +1174,16 @@ impl Config {
                 bucket_name,
                 sub_path,
             } => {
-                let data_dir = self.config_obj.data_dir.clone();
+                let (backend_dir, encryption) = self.remote_fs_encryption();
                 let region = region.to_string();
                 let bucket_name = bucket_name.to_string();
                 let sub_path = sub_path.clone();
                 self.injector
                     .register("original_remote_fs", async move |_| {
-                        let arc: Arc<dyn DIService> =
-                            S3RemoteFs::new(data_dir, region, bucket_name, sub_path).unwrap();
-                        arc
+                        with_encryption(
+                            S3RemoteFs::new(backend_dir, region, bucket_name, sub_path).unwrap(),
+                            encryption,
+                        )
                     })
                     .await;
             }
@@ -976,14 +1191,15 @@ impl Config {
                 bucket_name,
                 sub_path,
             } => {
-                let data_dir = self.config_obj.data_dir.clone();
+                let (backend_dir, encryption) = self.remote_fs_encryption();
                 let bucket_name = bucket_name.to_string();
                 let sub_path = sub_path.clone();
                 self.injector
                     .register("original_remote_fs", async move |_| {
-                        let arc: Arc<dyn DIService> =
-                            GCSRemoteFs::new(data_dir, bucket_name, sub_path).unwrap();
-                        arc
+                        with_encryption(
+                            GCSRemoteFs::new(backend_dir, bucket_name, sub_path).unwrap(),
+                            encryption,
+                        )
                     })
                     .await;
             }
@@ -991,14 +1207,34 @@ impl Config {
                 bucket_name,
                 sub_path,
             } => {
-                let data_dir = self.config_obj.data_dir.clone();
+                let (backend_dir, encryption) = self.remote_fs_encryption();
                 let bucket_name = bucket_name.to_string();
                 let sub_path = sub_path.clone();
                 self.injector
                     .register("original_remote_fs", async move |_| {
-                        let arc: Arc<dyn DIService> =
-                            MINIORemoteFs::new(data_dir, bucket_name, sub_path).unwrap();
-                        arc
+                        with_encryption(
+                            MINIORemoteFs::new(backend_dir, bucket_name, sub_path).unwrap(),
+                            encryption,
+                        )
+                    })
+                    .await;
+            }
+            FileStoreProvider::Azure {
+                account,
+                container,
+                sub_path,
+            } => {
+                let (backend_dir, encryption) = self.remote_fs_encryption();
+                let account = account.to_string();
+                let container = container.to_string();
+                let sub_path = sub_path.clone();
+                self.injector
+                    .register("original_remote_fs", async move |_| {
+                        with_encryption(
+                            AzureBlobRemoteFs::new(backend_dir, account, container, sub_path)
+                                .unwrap(),
+                            encryption,
+                        )
                     })
                     .await;
             }
@@ -1006,6 +1242,24 @@ impl Config {
         };
     }
 
+    /// Returns the local directory for the remote storage backend and, if encryption is on, the key
+    /// file, the directory for decrypted files and whether plaintext files can be read. Backends
+    /// keep encrypted files in a separate directory while transferring them.
+    fn remote_fs_encryption(&self) -> (PathBuf, Option<(PathBuf, PathBuf, bool)>) {
+        let data_dir = self.config_obj.data_dir.clone();
+        match &self.config_obj.encryption_key_file {
+            Some(key_file) => (
+                data_dir.join(".encrypted"),
+                Some((
+                    key_file.clone(),
+                    data_dir,
+                    self.config_obj.encryption_allow_plaintext,
+                )),
+            ),
+            None => (data_dir, None),
+        }
+    }
+
     async fn remote_fs(&self) -> Result<Arc<dyn RemoteFs + 'static>, CubeError> {
         self.configure_remote_fs().await;
         Ok(self.injector.get_service("original_remote_fs").await)
@@ -1015,6 +1269,27 @@ impl Config {
         self.injector.clone()
     }
 
+    /// Lists metastore snapshots on the remote storage. Doesn't require services to be started.
+    pub async fn meta_store_backups(&self) -> Result<Vec<MetaStoreBackup>, CubeError> {
+        RocksMetaStore::list_remote_backups(&self.remote_fs().await?).await
+    }
+
+    /// Restores the metastore to `point` on the remote storage. Local copy of the metastore is
+    /// replaced on the next start.
+    pub async fn restore_meta_store(
+        &self,
+        point: DateTime<Utc>,
+    ) -> Result<MetaStoreBackup, CubeError> {
+        let backup = RocksMetaStore::restore_remote_backup(
+            &self.remote_fs().await?,
+            self.config_obj(),
+            point,
+        )
+        .await?;
+        RocksMetaStore::mark_restored(&self.meta_store_path()).await?;
+        Ok(backup)
+    }
+
     pub async fn configure_injector(&self) {
         self.configure_remote_fs().await;
 
@@ -1165,15 +1440,29 @@ impl Config {
             })
             .await;
 
+        self.injector
+            .register_typed::<QueryLog, _, _, _>(async move |i| {
+                QueryLog::new(
+                    i.get_service_typed::<dyn ConfigObj>()
+                        .await
+                        .query_log_size(),
+                )
+            })
+            .await;
+
         self.injector
             .register_typed::<dyn QueryPlanner, _, _, _>(async move |i| {
-                QueryPlannerImpl::new(i.get_service_typed().await, i.get_service_typed().await)
+                QueryPlannerImpl::new(
+                    i.get_service_typed().await,
+                    i.get_service_typed().await,
+                    i.get_service_typed().await,
+                )
             })
             .await;
 
         self.injector
             .register_typed_with_default::<dyn QueryExecutor, _, _, _>(async move |i| {
-                QueryExecutorImpl::new(i.get_service_typed().await)
+                QueryExecutorImpl::new(i.get_service_typed().await, i.get_service_typed().await)
             })
             .await;
 
@@ -1212,10 +1501,12 @@ impl Config {
                     i.get_service_typed().await,
                     i.get_service_typed().await,
                     i.get_service_typed().await,
+                    i.get_service_typed().await,
                     c.wal_split_threshold() as usize,
                     Duration::from_secs(c.query_timeout()),
                     Duration::from_secs(c.import_job_timeout() * 2),
                     c.max_cached_queries(),
+                    i.get_service_typed().await,
                 )
             })
             .await;
@@ -1268,6 +1559,23 @@ impl Config {
                     )
                 })
                 .await;
+
+            if self.config_obj.flight_bind_address().is_some() {
+                self.injector
+                    .register_typed::<FlightServer, _, _, _>(async move |i| {
+                        FlightServer::new(
+                            i.get_service_typed::<dyn ConfigObj>()
+                                .await
+                                .flight_bind_address()
+                                .as_ref()
+                                .unwrap()
+                                .to_string(),
+                            i.get_service_typed().await,
+                            i.get_service_typed().await,
+                        )
+                    })
+                    .await;
+            }
         }
     }
 
@@ -0,0 +1,596 @@
+//! Arrow Flight SQL endpoint. Results of queries are sent as Arrow record batches, so clients like
+//! the Flight SQL JDBC driver or ADBC can read them without any conversions.
+//!
+//! Only statement queries are supported: `GetFlightInfo` with a `CommandStatementQuery` plans the
+//! query and returns a ticket referencing the plan, `DoGet` with this ticket runs it once and
+//! streams the results. Tickets expire after [STATEMENT_TTL] and can be used only once.
+//!
+//! Credentials are passed in the `authorization` header using the basic scheme, same as in the
+//! HTTP protocol. The optional `trace-obj` header is recorded with the query as in the HTTP
+//! protocol.
+use crate::http::HttpServer;
+use crate::metastore::Column;
+use crate::mysql::SqlAuthService;
+use crate::sql::{PlannedSelect, SqlQueryContext, SqlService};
+use crate::{CubeError, CubeErrorCauseType};
+use arrow::compute::cast;
+use arrow::datatypes::Schema;
+use arrow::ipc::writer::IpcWriteOptions;
+use arrow::record_batch::RecordBatch;
+use arrow_flight::flight_descriptor::DescriptorType;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::utils::flight_data_from_arrow_batch;
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use datafusion::cube_ext;
+use futures::{Stream, StreamExt};
+use log::{error, info};
+use prost::Message;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+use uuid::Uuid;
+
+/// Planned statements not fetched with `DoGet` during this time are dropped.
+pub const STATEMENT_TTL: Duration = Duration::from_secs(60);
+
+/// Flight messages buffered before the client reads them.
+const MESSAGES_BUFFER: usize = 16;
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/arrow.flight.protocol.sql.";
+
+/// `google.protobuf.Any`, Flight SQL commands and tickets are wrapped into it.
+#[derive(Clone, PartialEq, Message)]
+pub struct Any {
+    #[prost(string, tag = "1")]
+    pub type_url: String,
+    #[prost(bytes, tag = "2")]
+    pub value: Vec<u8>,
+}
+
+/// `arrow.flight.protocol.sql.CommandStatementQuery`, transactions are not supported.
+#[derive(Clone, PartialEq, Message)]
+pub struct CommandStatementQuery {
+    #[prost(string, tag = "1")]
+    pub query: String,
+}
+
+/// `arrow.flight.protocol.sql.TicketStatementQuery`.
+#[derive(Clone, PartialEq, Message)]
+pub struct TicketStatementQuery {
+    #[prost(bytes, tag = "1")]
+    pub statement_handle: Vec<u8>,
+}
+
+impl Any {
+    pub fn pack(type_name: &str, message: &impl Message) -> Any {
+        Any {
+            type_url: format!("{}{}", TYPE_URL_PREFIX, type_name),
+            value: encode(message),
+        }
+    }
+
+    fn unpack<M: Message + Default>(bytes: &[u8], type_name: &str) -> Result<M, Status> {
+        let any = Any::decode(bytes)
+            .map_err(|e| Status::invalid_argument(format!("Invalid Flight SQL message: {}", e)))?;
+        if any.type_url.strip_prefix(TYPE_URL_PREFIX) != Some(type_name) {
+            return Err(Status::invalid_argument(format!(
+                "Only {} is supported, but got {}",
+                type_name, any.type_url
+            )));
+        }
+        M::decode(any.value.as_slice())
+            .map_err(|e| Status::invalid_argument(format!("Invalid {}: {}", type_name, e)))
+    }
+}
+
+pub struct FlightServer {
+    bind_address: String,
+    sql_service: Arc<dyn SqlService>,
+    auth: Arc<dyn SqlAuthService>,
+    cancel_token: CancellationToken,
+    local_address: Mutex<Option<SocketAddr>>,
+}
+
+crate::di_service!(FlightServer, []);
+
+impl FlightServer {
+    pub fn new(
+        bind_address: String,
+        auth: Arc<dyn SqlAuthService>,
+        sql_service: Arc<dyn SqlService>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            bind_address,
+            auth,
+            sql_service,
+            cancel_token: CancellationToken::new(),
+            local_address: Mutex::new(None),
+        })
+    }
+
+    pub async fn run_server(&self) -> Result<(), CubeError> {
+        let addr: SocketAddr = self.bind_address.parse().map_err(|e| {
+            CubeError::user(format!(
+                "Invalid flight bind address '{}': {}",
+                self.bind_address, e
+            ))
+        })?;
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        *self.local_address.lock().unwrap() = Some(addr);
+        info!("Flight port open on {}", addr);
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            let socket = listener.accept().await.map(|(socket, _)| socket);
+            Some((socket, listener))
+        });
+        let service = FlightServiceImpl {
+            sql_service: self.sql_service.clone(),
+            auth: self.auth.clone(),
+            statements: Mutex::new(HashMap::new()),
+        };
+        let cancel_token = self.cancel_token.clone();
+        Server::builder()
+            .add_service(FlightServiceServer::new(service))
+            .serve_with_incoming_shutdown(incoming, async move { cancel_token.cancelled().await })
+            .await
+            .map_err(|e| CubeError::internal(format!("Flight server error: {}", e)))
+    }
+
+    /// Address the server listens on once it is started. Differs from the bind address if the
+    /// latter has port 0.
+    pub fn local_address(&self) -> Option<SocketAddr> {
+        *self.local_address.lock().unwrap()
+    }
+
+    pub async fn stop_processing(&self) {
+        self.cancel_token.cancel();
+    }
+}
+
+struct PlannedStatement {
+    user: Option<String>,
+    planned_at: SystemTime,
+    select: PlannedSelect,
+}
+
+impl PlannedStatement {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        now.duration_since(self.planned_at)
+            .map(|d| d > STATEMENT_TTL)
+            .unwrap_or(false)
+    }
+}
+
+struct FlightServiceImpl {
+    sql_service: Arc<dyn SqlService>,
+    auth: Arc<dyn SqlAuthService>,
+    /// Statements planned by `get_flight_info` by their handles.
+    statements: Mutex<HashMap<Uuid, PlannedStatement>>,
+}
+
+type FlightStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send + Sync + 'static>>;
+
+impl FlightServiceImpl {
+    async fn context<T>(&self, request: &Request<T>) -> Result<SqlQueryContext, Status> {
+        let user = HttpServer::authorize(self.auth.clone(), header(request, "authorization")?)
+            .await
+            .map_err(|e| Status::unauthenticated(e.message))?;
+        Ok(SqlQueryContext {
+            user,
+            trace_obj: header(request, "trace-obj")?,
+            connection_id: None,
+            cancel_token: CancellationToken::new(),
+        })
+    }
+
+    async fn plan(&self, descriptor: &FlightDescriptor) -> Result<PlannedSelect, Status> {
+        if descriptor.r#type != DescriptorType::Cmd as i32 {
+            return Err(Status::invalid_argument(
+                "Only CMD descriptors with Flight SQL commands are supported",
+            ));
+        }
+        let command: CommandStatementQuery = Any::unpack(&descriptor.cmd, "CommandStatementQuery")?;
+        self.sql_service
+            .plan_select(&command.query)
+            .await
+            .map_err(to_status)
+    }
+
+    fn add_statement(&self, user: Option<String>, select: PlannedSelect) -> Uuid {
+        let now = SystemTime::now();
+        let handle = Uuid::new_v4();
+        let mut statements = self.statements.lock().unwrap();
+        statements.retain(|_, s| !s.is_expired(now));
+        statements.insert(
+            handle,
+            PlannedStatement {
+                user,
+                planned_at: now,
+                select,
+            },
+        );
+        handle
+    }
+
+    fn take_statement(
+        &self,
+        ticket: &Ticket,
+        user: &Option<String>,
+    ) -> Result<PlannedSelect, Status> {
+        let ticket: TicketStatementQuery = Any::unpack(&ticket.ticket, "TicketStatementQuery")?;
+        let handle = Uuid::from_slice(&ticket.statement_handle)
+            .map_err(|_| Status::invalid_argument("Invalid statement handle"))?;
+        let statement = self
+            .statements
+            .lock()
+            .unwrap()
+            .remove(&handle)
+            .filter(|s| !s.is_expired(SystemTime::now()))
+            .ok_or_else(|| {
+                Status::not_found("Statement is expired or was already fetched, plan it again")
+            })?;
+        if &statement.user != user {
+            return Err(Status::permission_denied(
+                "Statement was planned by another user",
+            ));
+        }
+        Ok(statement.select)
+    }
+}
+
+pub fn encode(message: &impl Message) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(message.encoded_len());
+    message
+        .encode(&mut bytes)
+        .expect("Vec<u8> provides sufficient capacity");
+    bytes
+}
+
+fn header<T>(request: &Request<T>, name: &str) -> Result<Option<String>, Status> {
+    request
+        .metadata()
+        .get(name)
+        .map(|h| h.to_str().map(|h| h.to_string()))
+        .transpose()
+        .map_err(|_| Status::invalid_argument(format!("Invalid {} header", name)))
+}
+
+fn to_status(e: CubeError) -> Status {
+    match e.cause {
+        CubeErrorCauseType::User => Status::invalid_argument(e.message),
+        _ => {
+            error!("Error during flight request: {}", e);
+            Status::internal(e.message)
+        }
+    }
+}
+
+fn schema_of(columns: &[Column]) -> Arc<Schema> {
+    Arc::new(Schema::new(columns.iter().map(|c| c.into()).collect()))
+}
+
+/// Converts `batch` to flight messages, casting its columns to the types of `schema`.
+fn data_messages(
+    batch: RecordBatch,
+    schema: &Arc<Schema>,
+    options: &IpcWriteOptions,
+) -> Result<Vec<FlightData>, CubeError> {
+    let columns = batch
+        .columns()
+        .iter()
+        .zip(schema.fields())
+        .map(|(c, f)| {
+            if c.data_type() == f.data_type() {
+                Ok(c.clone())
+            } else {
+                cast(c, f.data_type())
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let (mut messages, batch) = flight_data_from_arrow_batch(&batch, options);
+    messages.push(batch);
+    Ok(messages)
+}
+
+#[tonic::async_trait]
+impl FlightService for FlightServiceImpl {
+    type HandshakeStream = FlightStream<HandshakeResponse>;
+    type ListFlightsStream = FlightStream<FlightInfo>;
+    type DoGetStream = FlightStream<FlightData>;
+    type DoPutStream = FlightStream<PutResult>;
+    type DoActionStream = FlightStream<arrow_flight::Result>;
+    type ListActionsStream = FlightStream<ActionType>;
+    type DoExchangeStream = FlightStream<FlightData>;
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented(
+            "Handshake is not required, pass credentials in the authorization header",
+        ))
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("list_flights is not supported"))
+    }
+
+    /// Plans the query without running it. The returned endpoint has a ticket referencing the
+    /// plan, use it in `do_get` to run the query and get the data.
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let context = self.context(&request).await?;
+        let descriptor = request.into_inner();
+        let select = self.plan(&descriptor).await?;
+
+        let options = IpcWriteOptions::default();
+        let schema = schema_of(select.columns());
+        let schema_result: SchemaResult = SchemaAsIpc::new(schema.as_ref(), &options).into();
+        let handle = self.add_statement(context.user, select);
+        let ticket = Any::pack(
+            "TicketStatementQuery",
+            &TicketStatementQuery {
+                statement_handle: handle.as_bytes().to_vec(),
+            },
+        );
+        Ok(Response::new(FlightInfo {
+            schema: schema_result.schema,
+            endpoint: vec![FlightEndpoint {
+                ticket: Some(Ticket {
+                    ticket: encode(&ticket),
+                }),
+                location: vec![],
+            }],
+            total_records: -1,
+            total_bytes: -1,
+            flight_descriptor: Some(descriptor),
+        }))
+    }
+
+    async fn get_schema(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        self.context(&request).await?;
+        let select = self.plan(request.get_ref()).await?;
+        let options = IpcWriteOptions::default();
+        Ok(Response::new(
+            SchemaAsIpc::new(schema_of(select.columns()).as_ref(), &options).into(),
+        ))
+    }
+
+    /// Runs the statement referenced by the ticket and streams its results: the schema first,
+    /// record batches after it.
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        let context = self.context(&request).await?;
+        let select = self.take_statement(request.get_ref(), &context.user)?;
+        let schema = schema_of(select.columns());
+        let mut records = self
+            .sql_service
+            .exec_planned_select(context, select)
+            .await
+            .map_err(to_status)?;
+
+        let options = IpcWriteOptions::default();
+        let schema_message = SchemaAsIpc::new(schema.as_ref(), &options).into();
+        // Record batch streams are not `Sync`, so they are read in a separate task. Once the
+        // client goes away, the task stops and dropping `records` cancels the query.
+        let (tx, rx) = mpsc::channel(MESSAGES_BUFFER);
+        cube_ext::spawn(async move {
+            if tx.send(Ok(schema_message)).await.is_err() {
+                return;
+            }
+            while let Some(batch) = records.next().await {
+                let messages = match batch
+                    .map_err(CubeError::from)
+                    .and_then(|b| data_messages(b, &schema, &options))
+                {
+                    Ok(messages) => messages.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(to_status(e))],
+                };
+                let failed = messages.last().map(|m| m.is_err()).unwrap_or(false);
+                for m in messages {
+                    if tx.send(m).await.is_err() {
+                        return;
+                    }
+                }
+                if failed {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn do_put(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        Err(Status::unimplemented("do_put is not supported"))
+    }
+
+    async fn do_action(
+        &self,
+        _request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("do_action is not supported"))
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Ok(Response::new(Box::pin(futures::stream::empty())))
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("do_exchange is not supported"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::table::{Row, TableValue};
+    use arrow_flight::flight_service_client::FlightServiceClient;
+    use arrow_flight::utils::flight_data_to_arrow_batch;
+    use std::convert::TryFrom;
+    use tonic::transport::Channel;
+    use tonic::Code;
+
+    async fn connect(flight: &FlightServer) -> FlightServiceClient<Channel> {
+        for _ in 0..50 {
+            if let Some(address) = flight.local_address() {
+                if let Ok(client) =
+                    FlightServiceClient::connect(format!("http://{}", address)).await
+                {
+                    return client;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Flight server is not available")
+    }
+
+    fn statement_query(query: &str) -> Request<FlightDescriptor> {
+        let command = Any::pack(
+            "CommandStatementQuery",
+            &CommandStatementQuery {
+                query: query.to_string(),
+            },
+        );
+        let mut request = Request::new(FlightDescriptor {
+            r#type: DescriptorType::Cmd as i32,
+            cmd: encode(&command),
+            path: vec![],
+        });
+        request
+            .metadata_mut()
+            .insert("trace-obj", "{}".parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn statement_query_runs_once() {
+        Config::test("flight_statement_query")
+            .update_config(|mut c| {
+                c.flight_bind_address = Some("127.0.0.1:0".to_string());
+                c
+            })
+            .start_test(async move |services| {
+                let flight = services.injector.get_service_typed::<FlightServer>().await;
+                let service = services.sql_service;
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
+                service
+                    .exec_query("CREATE TABLE s.Data (id int, n int)")
+                    .await
+                    .unwrap();
+                service
+                    .exec_query("INSERT INTO s.Data (id, n) VALUES (1, 1), (2, 2), (1, 3)")
+                    .await
+                    .unwrap();
+
+                let mut client = connect(&flight).await;
+                let query = "SELECT id, SUM(n) s FROM s.Data GROUP BY 1 ORDER BY 1";
+                let info = client
+                    .get_flight_info(statement_query(query))
+                    .await
+                    .unwrap()
+                    .into_inner();
+                let schema = Arc::new(
+                    Schema::try_from(&SchemaResult {
+                        schema: info.schema.clone(),
+                    })
+                    .unwrap(),
+                );
+                assert_eq!(
+                    schema
+                        .fields()
+                        .iter()
+                        .map(|f| f.name().as_str())
+                        .collect::<Vec<_>>(),
+                    vec!["id", "s"]
+                );
+                let ticket = info.endpoint[0].ticket.clone().unwrap();
+
+                // Planning doesn't run the query.
+                let r = service
+                    .exec_query("SELECT count(*) FROM system.query_log")
+                    .await
+                    .unwrap();
+                assert_eq!(r.get_rows()[0].values()[0], TableValue::Int(0));
+
+                let mut messages = client
+                    .do_get(Request::new(ticket.clone()))
+                    .await
+                    .unwrap()
+                    .into_inner();
+                let mut rows = Vec::new();
+                let schema_message = messages.message().await.unwrap().unwrap();
+                assert_eq!(Schema::try_from(&schema_message).unwrap(), *schema);
+                while let Some(data) = messages.message().await.unwrap() {
+                    let batch = flight_data_to_arrow_batch(&data, schema.clone(), &[]).unwrap();
+                    for i in 0..batch.num_rows() {
+                        rows.push(Row::new(TableValue::from_columns(batch.columns(), i)));
+                    }
+                }
+                assert_eq!(
+                    rows,
+                    vec![
+                        Row::new(vec![TableValue::Int(1), TableValue::Int(4)]),
+                        Row::new(vec![TableValue::Int(2), TableValue::Int(2)]),
+                    ]
+                );
+
+                // Tickets can be used only once.
+                let e = client.do_get(Request::new(ticket)).await.err().unwrap();
+                assert_eq!(e.code(), Code::NotFound);
+
+                let r = service
+                    .exec_query("SELECT query, trace_obj, status, rows FROM system.query_log")
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    r.get_rows(),
+                    &vec![Row::new(vec![
+                        TableValue::String(query.to_string()),
+                        TableValue::String("{}".to_string()),
+                        TableValue::String("Success".to_string()),
+                        TableValue::Int(2),
+                    ])]
+                );
+
+                let e = client
+                    .get_flight_info(statement_query("CREATE SCHEMA s2"))
+                    .await
+                    .err()
+                    .unwrap();
+                assert_eq!(e.code(), Code::InvalidArgument);
+            })
+            .await
+    }
+}
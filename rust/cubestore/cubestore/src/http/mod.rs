@@ -5,10 +5,13 @@ use std::sync::Arc;
 use warp::{Filter, Rejection, Reply};
 
 use crate::codegen::http_message_generated::{
-    get_root_as_http_message, HttpColumnValue, HttpColumnValueArgs, HttpError, HttpErrorArgs,
-    HttpMessageArgs, HttpQuery, HttpQueryArgs, HttpResultSet, HttpResultSetArgs, HttpRow,
-    HttpRowArgs,
+    get_root_as_http_message, HttpBoolValue, HttpBoolValueArgs, HttpBytesValue, HttpBytesValueArgs,
+    HttpColumnValue, HttpColumnValueArgs, HttpDecimalValue, HttpDecimalValueArgs, HttpError,
+    HttpErrorArgs, HttpFloat64Value, HttpFloat64ValueArgs, HttpInt64Value, HttpInt64ValueArgs,
+    HttpMessageArgs, HttpNullValue, HttpNullValueArgs, HttpQuery, HttpQueryArgs, HttpResultSet,
+    HttpResultSetArgs, HttpRow, HttpRowArgs, HttpTimestampValue, HttpTimestampValueArgs, HttpValue,
 };
+use crate::metastore::ColumnType;
 use crate::mysql::SqlAuthService;
 use crate::sql::{SqlQueryContext, SqlService};
 use crate::store::DataFrame;
@@ -61,6 +64,13 @@ pub struct UploadQuery {
     name: String,
 }
 
+/// Parameters of the web socket connection, e.g. `/ws?typed_values=true`.
+#[derive(Deserialize)]
+pub struct WsQuery {
+    #[serde(default)]
+    typed_values: bool,
+}
+
 impl Reject for CubeRejection {}
 
 impl HttpServer {
@@ -94,6 +104,8 @@ impl HttpServer {
                         Ok(user) => Ok(SqlQueryContext {
                             user,
                             trace_obj: None,
+                            connection_id: None,
+                            cancel_token: CancellationToken::new(),
                         }),
                         Err(_) => Err(warp::reject::custom(CubeRejection::NotAuthorized)),
                     }
@@ -106,8 +118,10 @@ impl HttpServer {
 
         let query_route = warp::path!("ws")
             .and(context_filter_to_move)
+            .and(warp::query::query::<WsQuery>())
             .and(warp::ws::ws())
-            .and_then(|tx: mpsc::Sender<(mpsc::Sender<HttpMessage>, SqlQueryContext, HttpMessage)>, sql_query_context: SqlQueryContext, ws: Ws| async move {
+            .and_then(|tx: mpsc::Sender<(mpsc::Sender<HttpMessage>, SqlQueryContext, HttpMessage)>, sql_query_context: SqlQueryContext, ws_query: WsQuery, ws: Ws| async move {
+                let typed_values = ws_query.typed_values;
                 let tx_to_move = tx.clone();
                 let sql_query_context = sql_query_context.clone();
                 Result::<_, Rejection>::Ok(ws.on_upgrade(async move |mut web_socket| {
@@ -116,7 +130,7 @@ impl HttpServer {
                         tokio::select! {
                             Some(res) = response_rx.recv() => {
                                 trace!("Sending web socket response");
-                                let send_res = web_socket.send(Message::binary(res.bytes())).await;
+                                let send_res = web_socket.send(Message::binary(res.bytes(typed_values))).await;
                                 if let Err(e) = send_res {
                                     error!("Websocket message send error: {:?}", e)
                                 }
@@ -138,7 +152,7 @@ impl HttpServer {
                                                     if let Err(e) = tx_to_move.try_send((response_tx.clone(), sql_query_context.clone(), msg)) {
                                                         error!("Websocket channel error: {:?}", e);
                                                         let send_res = web_socket.send(
-                                                            Message::binary(HttpMessage { message_id, command: HttpCommand::Error { error: e.to_string() } }.bytes())
+                                                            Message::binary(HttpMessage { message_id, command: HttpCommand::Error { error: e.to_string() } }.bytes(typed_values))
                                                         ).await;
                                                         if let Err(e) = send_res {
                                                             error!("Websocket message send error: {:?}", e)
@@ -163,6 +177,8 @@ impl HttpServer {
                             }
                         };
                     };
+                    // Queries of the closed connection are not needed anymore.
+                    sql_query_context.cancel_token.cancel();
                 }))
             });
 
@@ -363,7 +379,8 @@ pub enum HttpCommand {
 }
 
 impl HttpMessage {
-    pub fn bytes(&self) -> Vec<u8> {
+    /// Typed values are only sent to clients that asked for them when opening the connection.
+    pub fn bytes(&self, typed_values: bool) -> Vec<u8> {
         let mut builder = flatbuffers::FlatBufferBuilder::new_with_capacity(1024);
         let args = HttpMessageArgs {
             message_id: self.message_id,
@@ -412,75 +429,28 @@ impl HttpMessage {
                         .map(|c| c.get_name().as_str())
                         .collect::<Vec<_>>();
                     let columns_vec = builder.create_vector_of_strings(columns.as_slice());
+                    let column_types_vec = if typed_values {
+                        let column_types = data_frame
+                            .get_columns()
+                            .iter()
+                            .map(|c| c.get_column_type().to_string())
+                            .collect::<Vec<_>>();
+                        let column_types =
+                            column_types.iter().map(|t| t.as_str()).collect::<Vec<_>>();
+                        Some(builder.create_vector_of_strings(column_types.as_slice()))
+                    } else {
+                        None
+                    };
 
                     let mut row_offsets = Vec::with_capacity(data_frame.get_rows().len());
                     for row in data_frame.get_rows().iter() {
                         let mut value_offsets = Vec::with_capacity(row.values().len());
                         for (i, value) in row.values().iter().enumerate() {
-                            let value = match value {
-                                TableValue::Null => HttpColumnValue::create(
-                                    &mut builder,
-                                    &HttpColumnValueArgs { string_value: None },
-                                ),
-                                TableValue::String(v) => {
-                                    let string_value = Some(builder.create_string(v));
-                                    HttpColumnValue::create(
-                                        &mut builder,
-                                        &HttpColumnValueArgs { string_value },
-                                    )
-                                }
-                                TableValue::Int(v) => {
-                                    let string_value = Some(builder.create_string(&v.to_string()));
-                                    HttpColumnValue::create(
-                                        &mut builder,
-                                        &HttpColumnValueArgs { string_value },
-                                    )
-                                }
-                                TableValue::Decimal(v) => {
-                                    let scale = u8::try_from(
-                                        data_frame.get_columns()[i]
-                                            .get_column_type()
-                                            .target_scale(),
-                                    )
-                                    .unwrap();
-                                    let string_value =
-                                        Some(builder.create_string(&v.to_string(scale)));
-                                    HttpColumnValue::create(
-                                        &mut builder,
-                                        &HttpColumnValueArgs { string_value },
-                                    )
-                                }
-                                TableValue::Float(v) => {
-                                    let string_value = Some(builder.create_string(&v.to_string()));
-                                    HttpColumnValue::create(
-                                        &mut builder,
-                                        &HttpColumnValueArgs { string_value },
-                                    )
-                                }
-                                TableValue::Bytes(v) => {
-                                    let string_value = Some(builder.create_string(&format!(
-                                        "0x{}",
-                                        v.encode_hex_upper::<String>()
-                                    )));
-                                    HttpColumnValue::create(
-                                        &mut builder,
-                                        &HttpColumnValueArgs { string_value },
-                                    )
-                                }
-                                TableValue::Timestamp(v) => {
-                                    let string_value = Some(builder.create_string(&v.to_string()));
-                                    HttpColumnValue::create(
-                                        &mut builder,
-                                        &HttpColumnValueArgs { string_value },
-                                    )
-                                }
-                                TableValue::Boolean(v) => {
-                                    let string_value = Some(builder.create_string(&v.to_string()));
-                                    HttpColumnValue::create(
-                                        &mut builder,
-                                        &HttpColumnValueArgs { string_value },
-                                    )
-                                }
+                            let column_type = data_frame.get_columns()[i].get_column_type();
+                            let value = if typed_values {
+                                HttpMessage::typed_value(&mut builder, value, column_type)
+                            } else {
+                                HttpMessage::string_value(&mut builder, value, column_type)
                             };
                             value_offsets.push(value);
                         }
@@ -497,6 +467,7 @@ impl HttpMessage {
                             &HttpResultSetArgs {
                                 columns: Some(columns_vec),
                                 rows,
+                                column_types: column_types_vec,
                             },
                         )
                         .as_union_value(),
@@ -510,6 +481,187 @@ impl HttpMessage {
         builder.finished_data().to_vec() // TODO copy
     }
 
+    /// Legacy encoding: every value is sent as a string.
+    fn string_value<'a>(
+        builder: &mut flatbuffers::FlatBufferBuilder<'a>,
+        value: &TableValue,
+        column_type: &ColumnType,
+    ) -> flatbuffers::WIPOffset<HttpColumnValue<'a>> {
+        match value {
+            TableValue::Null => HttpColumnValue::create(
+                builder,
+                &HttpColumnValueArgs {
+                    string_value: None,
+                    ..Default::default()
+                },
+            ),
+            TableValue::String(v) => {
+                let string_value = Some(builder.create_string(v));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+            TableValue::Int(v) => {
+                let string_value = Some(builder.create_string(&v.to_string()));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+            TableValue::Decimal(v) => {
+                let scale = u8::try_from(column_type.target_scale()).unwrap();
+                let string_value = Some(builder.create_string(&v.to_string(scale)));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+            TableValue::Float(v) => {
+                let string_value = Some(builder.create_string(&v.to_string()));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+            TableValue::Bytes(v) => {
+                let string_value =
+                    Some(builder.create_string(&format!("0x{}", v.encode_hex_upper::<String>())));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+            TableValue::Timestamp(v) => {
+                let string_value = Some(builder.create_string(&v.to_string()));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+            TableValue::Boolean(v) => {
+                let string_value = Some(builder.create_string(&v.to_string()));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+            TableValue::Date(v) => {
+                let string_value = Some(builder.create_string(&v.to_string()));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+            TableValue::List(v) => {
+                let s = match column_type {
+                    ColumnType::List(t) => TableValue::list_to_string(v, t),
+                    t => panic!("list value for {} column", t),
+                };
+                let string_value = Some(builder.create_string(&s));
+                HttpColumnValue::create(
+                    builder,
+                    &HttpColumnValueArgs {
+                        string_value,
+                        ..Default::default()
+                    },
+                )
+            }
+        }
+    }
+
+    /// Sends numbers, timestamps, booleans and bytes in the typed `value` union.
+    /// Strings, dates and lists are still sent as `string_value`.
+    fn typed_value<'a>(
+        builder: &mut flatbuffers::FlatBufferBuilder<'a>,
+        value: &TableValue,
+        column_type: &ColumnType,
+    ) -> flatbuffers::WIPOffset<HttpColumnValue<'a>> {
+        let (value_type, value) = match value {
+            TableValue::Null => (
+                HttpValue::HttpNullValue,
+                HttpNullValue::create(builder, &HttpNullValueArgs {}).as_union_value(),
+            ),
+            TableValue::Int(v) => (
+                HttpValue::HttpInt64Value,
+                HttpInt64Value::create(builder, &HttpInt64ValueArgs { value: *v }).as_union_value(),
+            ),
+            TableValue::Decimal(v) => (
+                HttpValue::HttpDecimalValue,
+                HttpDecimalValue::create(
+                    builder,
+                    &HttpDecimalValueArgs {
+                        value: v.raw_value(),
+                        scale: column_type.target_scale(),
+                    },
+                )
+                .as_union_value(),
+            ),
+            TableValue::Float(v) => (
+                HttpValue::HttpFloat64Value,
+                HttpFloat64Value::create(builder, &HttpFloat64ValueArgs { value: v.0 })
+                    .as_union_value(),
+            ),
+            TableValue::Timestamp(v) => (
+                HttpValue::HttpTimestampValue,
+                HttpTimestampValue::create(
+                    builder,
+                    &HttpTimestampValueArgs {
+                        micros: v.get_time_stamp() / 1000,
+                    },
+                )
+                .as_union_value(),
+            ),
+            TableValue::Boolean(v) => (
+                HttpValue::HttpBoolValue,
+                HttpBoolValue::create(builder, &HttpBoolValueArgs { value: *v }).as_union_value(),
+            ),
+            TableValue::Bytes(v) => {
+                let bytes = Some(builder.create_vector(v.as_slice()));
+                (
+                    HttpValue::HttpBytesValue,
+                    HttpBytesValue::create(builder, &HttpBytesValueArgs { value: bytes })
+                        .as_union_value(),
+                )
+            }
+            TableValue::String(_) | TableValue::Date(_) | TableValue::List(_) => {
+                return HttpMessage::string_value(builder, value, column_type)
+            }
+        };
+        HttpColumnValue::create(
+            builder,
+            &HttpColumnValueArgs {
+                string_value: None,
+                value_type,
+                value: Some(value),
+            },
+        )
+    }
+
     pub fn read(buffer: Vec<u8>) -> Result<Self, CubeError> {
         let http_message = get_root_as_http_message(buffer.as_slice());
         Ok(HttpMessage {
@@ -532,3 +684,153 @@ impl HttpMessage {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metastore::Column;
+    use crate::table::{DateValue, Row, TimestampValue};
+    use crate::util::decimal::Decimal;
+    use datafusion::cube_ext::ordfloat::OrdF64;
+
+    fn result_set() -> HttpMessage {
+        let columns = vec![
+            Column::new("int".to_string(), ColumnType::Int, 0),
+            Column::new(
+                "decimal".to_string(),
+                ColumnType::Decimal {
+                    scale: 5,
+                    precision: 18,
+                },
+                1,
+            ),
+            Column::new("float".to_string(), ColumnType::Float, 2),
+            Column::new("timestamp".to_string(), ColumnType::Timestamp, 3),
+            Column::new("boolean".to_string(), ColumnType::Boolean, 4),
+            Column::new("bytes".to_string(), ColumnType::Bytes, 5),
+            Column::new("string".to_string(), ColumnType::String, 6),
+            Column::new("date".to_string(), ColumnType::Date, 7),
+        ];
+        let rows = vec![
+            Row::new(vec![
+                TableValue::Int(-42),
+                TableValue::Decimal(Decimal::new(-12340)),
+                TableValue::Float(OrdF64(1.5)),
+                TableValue::Timestamp(TimestampValue::new(1_609_459_200_123_456_000)),
+                TableValue::Boolean(true),
+                TableValue::Bytes(vec![0x01, 0xff]),
+                TableValue::String("foo".to_string()),
+                TableValue::Date(DateValue::new(1)),
+            ]),
+            Row::new(vec![TableValue::Null; 8]),
+        ];
+        HttpMessage {
+            message_id: 7,
+            command: HttpCommand::ResultSet {
+                data_frame: Arc::new(DataFrame::new(columns, rows)),
+            },
+        }
+    }
+
+    fn read_result_set<'a>(bytes: &'a [u8]) -> HttpResultSet<'a> {
+        let message = get_root_as_http_message(bytes);
+        assert_eq!(message.message_id(), 7);
+        message.command_as_http_result_set().unwrap()
+    }
+
+    #[test]
+    fn typed_values_round_trip() {
+        let bytes = result_set().bytes(true);
+        let result_set = read_result_set(&bytes);
+        assert_eq!(
+            result_set
+                .column_types()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![
+                "int",
+                "decimal(5)",
+                "float",
+                "timestamp",
+                "boolean",
+                "bytes",
+                "text",
+                "date32"
+            ]
+        );
+
+        let rows = result_set.rows().unwrap();
+        let values = rows.get(0).values().unwrap();
+        assert_eq!(
+            values.get(0).value_as_http_int64_value().unwrap().value(),
+            -42
+        );
+        let decimal = values.get(1).value_as_http_decimal_value().unwrap();
+        assert_eq!((decimal.value(), decimal.scale()), (-12340, 5));
+        assert_eq!(
+            values.get(2).value_as_http_float64_value().unwrap().value(),
+            1.5
+        );
+        assert_eq!(
+            values
+                .get(3)
+                .value_as_http_timestamp_value()
+                .unwrap()
+                .micros(),
+            1_609_459_200_123_456
+        );
+        assert_eq!(
+            values.get(4).value_as_http_bool_value().unwrap().value(),
+            true
+        );
+        assert_eq!(
+            values.get(5).value_as_http_bytes_value().unwrap().value(),
+            Some(&[0x01u8, 0xff][..])
+        );
+        assert_eq!(values.get(6).value_type(), HttpValue::NONE);
+        assert_eq!(values.get(6).string_value(), Some("foo"));
+        assert_eq!(values.get(7).value_type(), HttpValue::NONE);
+        assert_eq!(values.get(7).string_value(), Some("1970-01-02"));
+
+        let nulls = rows.get(1).values().unwrap();
+        for i in 0..nulls.len() {
+            assert_eq!(nulls.get(i).value_type(), HttpValue::HttpNullValue);
+            assert_eq!(nulls.get(i).string_value(), None);
+        }
+    }
+
+    #[test]
+    fn string_values_by_default() {
+        let bytes = result_set().bytes(false);
+        let result_set = read_result_set(&bytes);
+        assert!(result_set.column_types().is_none());
+
+        let rows = result_set.rows().unwrap();
+        let values = rows.get(0).values().unwrap();
+        let strings = (0..values.len())
+            .map(|i| {
+                assert_eq!(values.get(i).value_type(), HttpValue::NONE);
+                values.get(i).string_value()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            strings,
+            vec![
+                Some("-42"),
+                Some("-0.1234"),
+                Some("1.5"),
+                Some("2021-01-01T00:00:00.123Z"),
+                Some("true"),
+                Some("0x01FF"),
+                Some("foo"),
+                Some("1970-01-02"),
+            ]
+        );
+        let nulls = rows.get(1).values().unwrap();
+        for i in 0..nulls.len() {
+            assert_eq!(nulls.get(i).value_type(), HttpValue::NONE);
+            assert_eq!(nulls.get(i).string_value(), None);
+        }
+    }
+}
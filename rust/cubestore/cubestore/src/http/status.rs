@@ -1,13 +1,16 @@
+use crate::app_metrics;
 use crate::config::injection::Injector;
 use crate::config::{is_router, uses_remote_metastore, Config};
 use crate::metastore::MetaStore;
 use crate::sql::SqlService;
+use crate::util::metrics::{write_prometheus, PrometheusWriter};
 use crate::CubeError;
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use warp::filters::BoxedFilter;
 use warp::http::StatusCode;
-use warp::Filter;
+use warp::{Filter, Reply};
 
 pub fn serve_status_probes(c: &Config) {
     let addr = match c.config_obj().status_bind_address() {
@@ -15,23 +18,35 @@ pub fn serve_status_probes(c: &Config) {
         None => return,
     };
 
-    let p = match RouterProbes::try_new(c) {
-        Some(p) => p,
-        None => return,
-    };
+    app_metrics::register_all();
+    let m = warp::path!("metrics")
+        .and_then(metrics_reply)
+        .map(|r| Box::new(r) as Box<dyn Reply>);
 
-    let pc = p.clone();
-    let l = warp::path!("livez").and_then(move || {
-        let pc = pc.clone();
-        async move { status_probe_reply("liveness", pc.is_live().await) }
-    });
-    let r = warp::path!("readyz").and_then(move || {
-        let p = p.clone();
-        async move { status_probe_reply("readiness", p.is_ready().await) }
-    });
+    // Workers only expose metrics.
+    let routes: BoxedFilter<(Box<dyn Reply>,)> = match RouterProbes::try_new(c) {
+        Some(p) => {
+            let pc = p.clone();
+            let l = warp::path!("livez").and_then(move || {
+                let pc = pc.clone();
+                async move { status_probe_reply("liveness", pc.is_live().await) }
+            });
+            let r = warp::path!("readyz").and_then(move || {
+                let p = p.clone();
+                async move { status_probe_reply("readiness", p.is_ready().await) }
+            });
+            l.or(r)
+                .unify()
+                .map(|r| Box::new(r) as Box<dyn Reply>)
+                .or(m)
+                .unify()
+                .boxed()
+        }
+        None => m.boxed(),
+    };
 
     let addr: SocketAddr = addr.parse().expect("cannot parse status probe address");
-    match warp::serve(l.or(r)).try_bind_ephemeral(addr) {
+    match warp::serve(routes).try_bind_ephemeral(addr) {
         Ok((addr, f)) => {
             log::info!("Serving status probes at {}", addr);
             tokio::spawn(f);
@@ -52,6 +67,16 @@ pub fn status_probe_reply(probe: &str, r: Result<(), CubeError>) -> Result<Statu
     }
 }
 
+async fn metrics_reply() -> Result<impl Reply, Infallible> {
+    let mut out = PrometheusWriter::new();
+    write_prometheus(&mut out);
+    Ok(warp::reply::with_header(
+        out.finish(),
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
+
 #[derive(Clone)]
 struct RouterProbes {
     services: Arc<Injector>,
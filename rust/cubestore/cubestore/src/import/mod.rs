@@ -1,11 +1,20 @@
 use core::mem;
 use core::slice::memchr;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use arrow::array::{ArrayBuilder, ArrayRef};
+use arrow::array::{
+    Array, ArrayBuilder, ArrayRef, BinaryArray, BooleanArray, Date32Array, Date64Array,
+    Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, LargeBinaryArray,
+    LargeStringArray, StringArray, TimestampMicrosecondArray, TimestampMillisecondArray,
+    TimestampNanosecondArray, TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array,
+    UInt8Array,
+};
+use arrow::datatypes::{DataType, TimeUnit};
+use arrow::util::display::array_value_to_string;
 use async_compression::tokio::bufread::GzipDecoder;
 use async_std::io::SeekFrom;
 use async_std::task::{Context, Poll};
@@ -17,11 +26,15 @@ use futures::{Stream, StreamExt};
 use itertools::Itertools;
 use mockall::automock;
 use num::ToPrimitive;
+use parquet::arrow::{ArrowReader, ParquetFileArrowReader};
+use parquet::file::reader::{FileReader, SerializedFileReader};
 use pin_project_lite::pin_project;
 use tempfile::TempPath;
 use tokio::fs::File;
-use tokio::io::{AsyncBufRead, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio_stream::wrappers::LinesStream;
 
 use cubehll::HllSketch;
 
@@ -31,12 +44,14 @@ use crate::import::limits::ConcurrencyLimits;
 use crate::metastore::table::Table;
 use crate::metastore::{is_valid_plain_binary_hll, HllFlavour, IdRow};
 use crate::metastore::{Column, ColumnType, ImportFormat, MetaStore};
+use crate::queryplanner::tdigest::TDigest;
 use crate::remotefs::RemoteFs;
 use crate::sql::timestamp_from_string;
-use crate::store::ChunkDataStore;
+use crate::store::{ChunkDataStore, ROW_GROUP_SIZE};
+use crate::streaming::avro::AvroValue;
 use crate::streaming::StreamingService;
 use crate::table::data::{append_row, create_array_builders};
-use crate::table::{Row, TableValue};
+use crate::table::{DateValue, Row, TableValue, TimestampValue};
 use crate::util::decimal::Decimal;
 use crate::util::maybe_owned::MaybeOwnedStr;
 use crate::CubeError;
@@ -138,9 +153,181 @@ impl ImportFormat {
                 });
                 Ok(rows.boxed())
             }
+            ImportFormat::NDJSON => {
+                let lines_stream: Pin<
+                    Box<dyn Stream<Item = Result<String, std::io::Error>> + Send>,
+                > = if location.contains(".gz") {
+                    let reader = BufReader::new(GzipDecoder::new(BufReader::new(file)));
+                    Box::pin(LinesStream::new(reader.lines()))
+                } else {
+                    let reader = BufReader::new(file);
+                    Box::pin(LinesStream::new(reader.lines()))
+                };
+
+                let rows = lines_stream.map(move |line| -> Result<Option<Row>, CubeError> {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        return Ok(None);
+                    }
+                    let object = match serde_json::from_str::<serde_json::Value>(&line)? {
+                        serde_json::Value::Object(o) => o,
+                        _ => {
+                            return Err(CubeError::user(format!(
+                                "Expected JSON object on each line but found: {}",
+                                line
+                            )))
+                        }
+                    };
+                    Ok(Some(Row::new(json_object_to_values(&columns, object)?)))
+                });
+                Ok(rows.boxed())
+            }
+            ImportFormat::Parquet => {
+                let file = file.into_std().await;
+                let (tx, rx) = mpsc::channel(2);
+                cube_ext::spawn_blocking(move || {
+                    if let Err(e) = ImportFormat::read_parquet(file, &columns, &tx) {
+                        let _ = tx.blocking_send(Err(e));
+                    }
+                });
+                let batches = futures::stream::unfold(rx, |mut rx| async move {
+                    rx.recv().await.map(|batch| (batch, rx))
+                });
+                let rows = batches
+                    .map(|batch: Result<Vec<Row>, CubeError>| {
+                        futures::stream::iter(match batch {
+                            Ok(rows) => rows.into_iter().map(|r| Ok(Some(r))).collect_vec(),
+                            Err(e) => vec![Err(e)],
+                        })
+                    })
+                    .flatten();
+                Ok(rows.boxed())
+            }
         }
     }
 
+    /// Reads Parquet file row groups and sends converted rows in batches.
+    /// Columns are mapped by name: table columns missing in the file are filled with nulls and
+    /// file columns missing in the table are ignored.
+    fn read_parquet(
+        file: std::fs::File,
+        columns: &[Column],
+        tx: &mpsc::Sender<Result<Vec<Row>, CubeError>>,
+    ) -> Result<(), CubeError> {
+        let mut reader = ParquetFileArrowReader::new(Arc::new(SerializedFileReader::new(file)?));
+        let schema = reader.get_schema()?;
+        let mapping = columns
+            .iter()
+            .enumerate()
+            .filter_map(|(insert_pos, c)| {
+                schema
+                    .fields()
+                    .iter()
+                    .position(|f| f.name() == c.get_name())
+                    .map(|field_pos| (insert_pos, field_pos))
+            })
+            .collect_vec();
+
+        for batch in reader.get_record_reader(ROW_GROUP_SIZE)? {
+            let batch = batch?;
+            let mut rows = Vec::with_capacity(batch.num_rows());
+            for i in 0..batch.num_rows() {
+                let mut row = vec![TableValue::Null; columns.len()];
+                for (insert_pos, field_pos) in mapping.iter() {
+                    let value = ImportValue::from_array(batch.column(*field_pos), i)?;
+                    row[*insert_pos] = ImportFormat::coerce_value(&columns[*insert_pos], value)?;
+                }
+                rows.push(Row::new(row));
+            }
+            if tx.blocking_send(Ok(rows)).is_err() {
+                // Receiver is gone, import has been cancelled.
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts a value of self-describing input format into the column type.
+    fn coerce_value(column: &Column, value: ImportValue) -> Result<TableValue, CubeError> {
+        let value_type = value.type_name();
+        let res = match (column.get_column_type(), value) {
+            (_, ImportValue::Null) => Ok(TableValue::Null),
+            (_, ImportValue::Str(s)) => {
+                let mut value_buf_opt = Some(s);
+                ImportFormat::parse_column_value(column, &mut value_buf_opt)
+            }
+            (ColumnType::String, ImportValue::Bool(b)) => Ok(TableValue::String(b.to_string())),
+            (ColumnType::String, ImportValue::Int(i)) => Ok(TableValue::String(i.to_string())),
+            (ColumnType::String, ImportValue::Float(f)) => Ok(TableValue::String(f.to_string())),
+            (ColumnType::String, ImportValue::TimestampMicros(t)) => Ok(TableValue::String(
+                TimestampValue::new(t * 1000).to_string(),
+            )),
+            (ColumnType::Int, ImportValue::Int(i)) => Ok(TableValue::Int(i)),
+            (ColumnType::Int, ImportValue::Float(f)) if f.fract() == 0.0 => {
+                Ok(TableValue::Int(f as i64))
+            }
+            (ColumnType::Int, ImportValue::Bool(b)) => Ok(TableValue::Int(b as i64)),
+            (ColumnType::Int32, ImportValue::Int(i)) => match i32::try_from(i) {
+                Ok(_) => Ok(TableValue::Int(i)),
+                Err(_) => Err(CubeError::user(format!("{} is out of int32 range", i))),
+            },
+            (ColumnType::Int16, ImportValue::Int(i)) => match i16::try_from(i) {
+                Ok(_) => Ok(TableValue::Int(i)),
+                Err(_) => Err(CubeError::user(format!("{} is out of int16 range", i))),
+            },
+            (ColumnType::Int8, ImportValue::Int(i)) => match i8::try_from(i) {
+                Ok(_) => Ok(TableValue::Int(i)),
+                Err(_) => Err(CubeError::user(format!("{} is out of tinyint range", i))),
+            },
+            (ColumnType::Date, ImportValue::TimestampMicros(t)) => Ok(TableValue::Date(
+                DateValue::new(t.div_euclid(86_400_000_000) as i32),
+            )),
+            (ColumnType::Float, ImportValue::Int(i)) => Ok(TableValue::Float(OrdF64(i as f64))),
+            (ColumnType::Float, ImportValue::Float(f)) => Ok(TableValue::Float(OrdF64(f))),
+            (t @ ColumnType::Decimal { .. }, ImportValue::Int(i)) => Ok(TableValue::Decimal(
+                parse_decimal(&i.to_string(), u8::try_from(t.target_scale()).unwrap())?,
+            )),
+            (t @ ColumnType::Decimal { .. }, ImportValue::Float(f)) => Ok(TableValue::Decimal(
+                parse_decimal(&f.to_string(), u8::try_from(t.target_scale()).unwrap())?,
+            )),
+            (ColumnType::Boolean, ImportValue::Bool(b)) => Ok(TableValue::Boolean(b)),
+            (ColumnType::Boolean, ImportValue::Int(i)) => Ok(TableValue::Boolean(i != 0)),
+            (ColumnType::Timestamp, ImportValue::TimestampMicros(t)) => {
+                Ok(TableValue::Timestamp(TimestampValue::new(t * 1000)))
+            }
+            (ColumnType::Bytes, ImportValue::Bytes(b)) => Ok(TableValue::Bytes(b.to_vec())),
+            (ColumnType::HyperLogLog(HllFlavour::Snowflake), ImportValue::Bytes(b)) => {
+                let json = std::str::from_utf8(b).map_err(|e| CubeError::user(e.to_string()))?;
+                Ok(TableValue::Bytes(HllSketch::read_snowflake(json)?.write()))
+            }
+            (ColumnType::HyperLogLog(HllFlavour::Postgres), ImportValue::Bytes(b)) => Ok(
+                TableValue::Bytes(HllSketch::read_hll_storage_spec(b)?.write()),
+            ),
+            (
+                ColumnType::HyperLogLog(f @ (HllFlavour::Airlift | HllFlavour::ZetaSketch)),
+                ImportValue::Bytes(b),
+            ) => {
+                is_valid_plain_binary_hll(b, *f)?;
+                Ok(TableValue::Bytes(b.to_vec()))
+            }
+            (ColumnType::TDigest, ImportValue::Bytes(b)) => {
+                TDigest::read(b)?;
+                Ok(TableValue::Bytes(b.to_vec()))
+            }
+            (t, _) => Err(CubeError::user(format!(
+                "Can't convert {} value to {}",
+                value_type, t
+            ))),
+        };
+        res.map_err(|e| {
+            CubeError::user(format!(
+                "Can't parse column value for '{}' column: {}",
+                column.get_name(),
+                e
+            ))
+        })
+    }
+
     fn parse_column_value(
         column: &Column,
         value_buf: &mut Option<MaybeOwnedStr>,
@@ -152,6 +339,34 @@ impl ImportFormat {
                 .parse()
                 .map(|v| TableValue::Int(v))
                 .unwrap_or(TableValue::Null),
+            ColumnType::Int32 => value
+                .parse::<i32>()
+                .map(|v| TableValue::Int(v as i64))
+                .unwrap_or(TableValue::Null),
+            ColumnType::Int16 => value
+                .parse::<i16>()
+                .map(|v| TableValue::Int(v as i64))
+                .unwrap_or(TableValue::Null),
+            ColumnType::Int8 => value
+                .parse::<i8>()
+                .map(|v| TableValue::Int(v as i64))
+                .unwrap_or(TableValue::Null),
+            ColumnType::Date => TableValue::Date(DateValue::parse(value)?),
+            // Arrays are expected to be in JSON format, e.g. `["a","b"]` or `[1,2]`.
+            ColumnType::List(item) => {
+                let items: Vec<serde_json::Value> = serde_json::from_str(value).map_err(|e| {
+                    CubeError::user(format!("Can't parse array '{}': {}", value, e))
+                })?;
+                let item_column = Column::new(column.get_name().clone(), item.as_ref().clone(), 0);
+                TableValue::List(
+                    items
+                        .into_iter()
+                        .map(|v| {
+                            ImportFormat::coerce_value(&item_column, ImportValue::from_json(v))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                )
+            }
             t @ ColumnType::Decimal { .. } => TableValue::Decimal(parse_decimal(
                 value,
                 u8::try_from(t.target_scale()).unwrap(),
@@ -181,6 +396,17 @@ impl ImportFormat {
                 is_valid_plain_binary_hll(&data, *f)?;
                 TableValue::Bytes(data)
             }
+            // Same encodings as for HLL, e.g. base64 of the `tdigest` varbinary from Presto.
+            ColumnType::TDigest => {
+                let mut data = Vec::new();
+                if value.contains(' ') {
+                    parse_space_separated_binstring(&mut data, value)?;
+                } else {
+                    base64::decode_config_buf(value, base64::STANDARD, &mut data)?;
+                };
+                TDigest::read(&data)?;
+                TableValue::Bytes(data)
+            }
             ColumnType::Timestamp => TableValue::Timestamp(timestamp_from_string(value)?),
             ColumnType::Float => TableValue::Float(OrdF64(value.parse::<f64>()?)),
             ColumnType::Boolean => TableValue::Boolean(value.to_lowercase() == "true"),
@@ -188,6 +414,153 @@ impl ImportFormat {
     }
 }
 
+/// Maps JSON object fields onto table columns by name.
+/// Columns missing in the object are filled with nulls and unknown fields are ignored.
+pub(crate) fn json_object_to_values(
+    columns: &[Column],
+    mut object: serde_json::Map<String, serde_json::Value>,
+) -> Result<Vec<TableValue>, CubeError> {
+    let mut values = vec![TableValue::Null; columns.len()];
+    for (insert_pos, column) in columns.iter().enumerate() {
+        if let Some(value) = object.remove(column.get_name()) {
+            values[insert_pos] = ImportFormat::coerce_value(column, ImportValue::from_json(value))?;
+        }
+    }
+    Ok(values)
+}
+
+/// Maps Avro record fields onto table columns by name, the same way as JSON object fields.
+pub(crate) fn avro_record_to_values(
+    columns: &[Column],
+    fields: Vec<(String, AvroValue)>,
+) -> Result<Vec<TableValue>, CubeError> {
+    let mut fields = fields.into_iter().collect::<HashMap<_, _>>();
+    let mut values = vec![TableValue::Null; columns.len()];
+    for (insert_pos, column) in columns.iter().enumerate() {
+        if let Some(value) = fields.remove(column.get_name()) {
+            values[insert_pos] =
+                ImportFormat::coerce_value(column, ImportValue::from_avro(&value))?;
+        }
+    }
+    Ok(values)
+}
+
+/// Value read from self-describing formats before it's coerced into the column type.
+enum ImportValue<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(MaybeOwnedStr<'a>),
+    Bytes(&'a [u8]),
+    TimestampMicros(i64),
+}
+
+impl<'a> ImportValue<'a> {
+    fn from_json(value: serde_json::Value) -> ImportValue<'static> {
+        match value {
+            serde_json::Value::Null => ImportValue::Null,
+            serde_json::Value::Bool(b) => ImportValue::Bool(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => ImportValue::Int(i),
+                None => ImportValue::Float(n.as_f64().unwrap()),
+            },
+            serde_json::Value::String(s) => ImportValue::Str(MaybeOwnedStr::Owned(s)),
+            v @ (serde_json::Value::Array(_) | serde_json::Value::Object(_)) => {
+                ImportValue::Str(MaybeOwnedStr::Owned(v.to_string()))
+            }
+        }
+    }
+
+    fn from_avro(value: &'a AvroValue) -> ImportValue<'a> {
+        match value {
+            AvroValue::Null => ImportValue::Null,
+            AvroValue::Boolean(b) => ImportValue::Bool(*b),
+            AvroValue::Int(i) => ImportValue::Int(*i as i64),
+            AvroValue::Long(i) => ImportValue::Int(*i),
+            AvroValue::Float(f) => ImportValue::Float(*f as f64),
+            AvroValue::Double(f) => ImportValue::Float(*f),
+            AvroValue::Bytes(b) | AvroValue::Fixed(b) => ImportValue::Bytes(b),
+            AvroValue::String(s) | AvroValue::Enum(s) => {
+                ImportValue::Str(MaybeOwnedStr::Borrowed(s))
+            }
+            AvroValue::Date(d) => ImportValue::TimestampMicros(*d as i64 * 86_400_000_000),
+            AvroValue::TimestampMicros(t) => ImportValue::TimestampMicros(*t),
+            AvroValue::Decimal(d) => ImportValue::Str(MaybeOwnedStr::Owned(d.to_string())),
+            v @ (AvroValue::Array(_) | AvroValue::Map(_) | AvroValue::Record(_)) => {
+                ImportValue::Str(MaybeOwnedStr::Owned(v.to_json().to_string()))
+            }
+        }
+    }
+
+    fn from_array(a: &'a ArrayRef, row: usize) -> Result<ImportValue<'a>, CubeError> {
+        macro_rules! value {
+            ($array_type: ty) => {
+                a.as_any().downcast_ref::<$array_type>().unwrap().value(row)
+            };
+        }
+        if !a.is_valid(row) {
+            return Ok(ImportValue::Null);
+        }
+        Ok(match a.data_type() {
+            DataType::Boolean => ImportValue::Bool(value!(BooleanArray)),
+            DataType::Int8 => ImportValue::Int(value!(Int8Array) as i64),
+            DataType::Int16 => ImportValue::Int(value!(Int16Array) as i64),
+            DataType::Int32 => ImportValue::Int(value!(Int32Array) as i64),
+            DataType::Int64 => ImportValue::Int(value!(Int64Array)),
+            DataType::UInt8 => ImportValue::Int(value!(UInt8Array) as i64),
+            DataType::UInt16 => ImportValue::Int(value!(UInt16Array) as i64),
+            DataType::UInt32 => ImportValue::Int(value!(UInt32Array) as i64),
+            DataType::UInt64 => {
+                ImportValue::Int(i64::try_from(value!(UInt64Array)).map_err(|_| {
+                    CubeError::user(format!(
+                        "UInt64 value is out of range: {}",
+                        value!(UInt64Array)
+                    ))
+                })?)
+            }
+            DataType::Float32 => ImportValue::Float(value!(Float32Array) as f64),
+            DataType::Float64 => ImportValue::Float(value!(Float64Array)),
+            DataType::Utf8 => ImportValue::Str(MaybeOwnedStr::Borrowed(value!(StringArray))),
+            DataType::LargeUtf8 => {
+                ImportValue::Str(MaybeOwnedStr::Borrowed(value!(LargeStringArray)))
+            }
+            DataType::Binary => ImportValue::Bytes(value!(BinaryArray)),
+            DataType::LargeBinary => ImportValue::Bytes(value!(LargeBinaryArray)),
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                ImportValue::TimestampMicros(value!(TimestampSecondArray) * 1_000_000)
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                ImportValue::TimestampMicros(value!(TimestampMillisecondArray) * 1000)
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                ImportValue::TimestampMicros(value!(TimestampMicrosecondArray))
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                ImportValue::TimestampMicros(value!(TimestampNanosecondArray) / 1000)
+            }
+            DataType::Date32 => {
+                ImportValue::TimestampMicros(value!(Date32Array) as i64 * 86_400_000_000)
+            }
+            DataType::Date64 => ImportValue::TimestampMicros(value!(Date64Array) * 1000),
+            // Decimals and other types go through their textual representation.
+            _ => ImportValue::Str(MaybeOwnedStr::Owned(array_value_to_string(a, row)?)),
+        })
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            ImportValue::Null => "null",
+            ImportValue::Bool(_) => "boolean",
+            ImportValue::Int(_) => "integer",
+            ImportValue::Float(_) => "float",
+            ImportValue::Str(_) => "string",
+            ImportValue::Bytes(_) => "binary",
+            ImportValue::TimestampMicros(_) => "timestamp",
+        }
+    }
+}
+
 pub(crate) fn parse_decimal(value: &str, scale: u8) -> Result<Decimal, CubeError> {
     // TODO: parse into Decimal directly.
     let bd = BigDecimal::from_str_radix(value, 10)?;
@@ -408,7 +781,11 @@ impl<R: AsyncBufRead> Stream for CsvLineStream<R> {
 pub trait ImportService: DIService + Send + Sync {
     async fn import_table(&self, table_id: u64) -> Result<(), CubeError>;
     async fn import_table_part(&self, table_id: u64, location: &str) -> Result<(), CubeError>;
-    async fn estimate_location_row_count(&self, location: &str) -> Result<u64, CubeError>;
+    async fn estimate_location_row_count(
+        &self,
+        location: &str,
+        format: ImportFormat,
+    ) -> Result<u64, CubeError>;
 }
 
 crate::di_service!(MockImportService, [ImportService]);
@@ -557,15 +934,15 @@ impl ImportServiceImpl {
         };
 
         let table_cols = table.get_row().get_columns().as_slice();
-        let mut builders = create_array_builders(table_cols);
+        let mut builders = create_array_builders(table_cols)?;
         let mut num_rows = 0;
         while let Some(row) = row_stream.next().await {
             if let Some(row) = row? {
-                append_row(&mut builders, table_cols, &row);
+                append_row(&mut builders, table_cols, &row)?;
                 num_rows += 1;
 
                 if num_rows >= self.config_obj.wal_split_threshold() as usize {
-                    let mut to_add = create_array_builders(table_cols);
+                    let mut to_add = create_array_builders(table_cols)?;
                     mem::swap(&mut builders, &mut to_add);
                     num_rows = 0;
 
@@ -580,19 +957,36 @@ impl ImportServiceImpl {
         ingestion.wait_completion().await
     }
 
-    fn estimate_rows(location: &str, size: Option<u64>) -> u64 {
+    fn estimate_rows(location: &str, format: ImportFormat, size: Option<u64>) -> u64 {
         if let Some(size) = size {
-            let uncompressed_size = if location.contains(".gz") {
+            let uncompressed_size = if location.contains(".gz") || format == ImportFormat::Parquet {
                 size * 5
             } else {
                 size
             };
-            let average_row_length = 256;
+            let average_row_length = match format {
+                // Every row repeats column names.
+                ImportFormat::NDJSON => 512,
+                ImportFormat::CSV | ImportFormat::CSVNoHeader | ImportFormat::Parquet => 256,
+            };
             uncompressed_size / average_row_length
         } else {
             7_000_000
         }
     }
+
+    async fn parquet_row_count(location: &str) -> Result<u64, CubeError> {
+        let file = File::open(location)
+            .await
+            .map_err(|e| CubeError::internal(format!("Open location {}: {}", location, e)))?
+            .into_std()
+            .await;
+        cube_ext::spawn_blocking(move || -> Result<u64, CubeError> {
+            let reader = SerializedFileReader::new(file)?;
+            Ok(reader.metadata().file_metadata().num_rows() as u64)
+        })
+        .await?
+    }
 }
 
 #[async_trait]
@@ -659,7 +1053,11 @@ impl ImportService for ImportServiceImpl {
         Ok(())
     }
 
-    async fn estimate_location_row_count(&self, location: &str) -> Result<u64, CubeError> {
+    async fn estimate_location_row_count(
+        &self,
+        location: &str,
+        format: ImportFormat,
+    ) -> Result<u64, CubeError> {
         if location.starts_with("http") {
             let client = reqwest::Client::new();
             let res = client.head(location).send().await?;
@@ -670,15 +1068,19 @@ impl ImportService for ImportServiceImpl {
             } else {
                 None
             };
-            Ok(ImportServiceImpl::estimate_rows(location, size))
+            Ok(ImportServiceImpl::estimate_rows(location, format, size))
         } else if location.starts_with("temp://") {
             // TODO do the actual estimation
-            Ok(ImportServiceImpl::estimate_rows(location, None))
+            Ok(ImportServiceImpl::estimate_rows(location, format, None))
         } else if location.starts_with("stream://") {
-            Ok(ImportServiceImpl::estimate_rows(location, None))
+            Ok(ImportServiceImpl::estimate_rows(location, format, None))
+        } else if format == ImportFormat::Parquet {
+            // Parquet footer has the exact row count.
+            ImportServiceImpl::parquet_row_count(location).await
         } else {
             Ok(ImportServiceImpl::estimate_rows(
                 location,
+                format,
                 Some(tokio::fs::metadata(location).await?.len()),
             ))
         }
@@ -756,6 +1158,16 @@ mod tests {
     extern crate test;
 
     use crate::import::parse_decimal;
+    use crate::metastore::{Column, ColumnType, ImportFormat};
+    use crate::table::{Row, TableValue, TimestampValue};
+    use arrow::array::{Int32Array, StringArray, TimestampMillisecondArray};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::cube_ext::ordfloat::OrdF64;
+    use futures::TryStreamExt;
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+    use tempfile::NamedTempFile;
 
     #[test]
     fn parse_decimal_test() {
@@ -776,4 +1188,126 @@ mod tests {
             "-200.04",
         );
     }
+
+    #[tokio::test]
+    async fn parquet_row_stream() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("extra", DataType::Utf8, true),
+            Field::new("number", DataType::Int32, true),
+            Field::new("ts", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+            Field::new("fruit", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("x"), Some("y")])),
+                Arc::new(Int32Array::from(vec![Some(2), None])),
+                Arc::new(TimestampMillisecondArray::from(vec![
+                    Some(1000),
+                    Some(2000),
+                ])),
+                Arc::new(StringArray::from(vec![Some("apple"), Some("banana")])),
+            ],
+        )
+        .unwrap();
+        let file = NamedTempFile::new().unwrap();
+        let mut w = ArrowWriter::try_new(file.reopen().unwrap(), schema, None).unwrap();
+        w.write(&batch).unwrap();
+        w.close().unwrap();
+
+        let columns = vec![
+            Column::new("fruit".to_string(), ColumnType::String, 0),
+            Column::new("number".to_string(), ColumnType::Float, 1),
+            Column::new("ts".to_string(), ColumnType::Timestamp, 2),
+            Column::new("missing".to_string(), ColumnType::Int, 3),
+        ];
+        let rows = ImportFormat::Parquet
+            .row_stream(
+                tokio::fs::File::open(file.path()).await.unwrap(),
+                file.path().to_string_lossy().to_string(),
+                columns,
+            )
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                Some(Row::new(vec![
+                    TableValue::String("apple".to_string()),
+                    TableValue::Float(OrdF64(2.0)),
+                    TableValue::Timestamp(TimestampValue::new(1_000_000_000)),
+                    TableValue::Null,
+                ])),
+                Some(Row::new(vec![
+                    TableValue::String("banana".to_string()),
+                    TableValue::Null,
+                    TableValue::Timestamp(TimestampValue::new(2_000_000_000)),
+                    TableValue::Null,
+                ])),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ndjson_type_coercion() {
+        let file = NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            concat!(
+                r#"{"id": 1, "amount": 1.5, "active": true, "tags": ["a", "b"]}"#,
+                "\n\n",
+                r#"{"id": "2", "amount": 3, "active": null, "unknown": 5}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+
+        let columns = vec![
+            Column::new("id".to_string(), ColumnType::Int, 0),
+            Column::new(
+                "amount".to_string(),
+                ColumnType::Decimal {
+                    scale: 2,
+                    precision: 10,
+                },
+                1,
+            ),
+            Column::new("active".to_string(), ColumnType::Boolean, 2),
+            Column::new("tags".to_string(), ColumnType::String, 3),
+        ];
+        let rows = ImportFormat::NDJSON
+            .row_stream(
+                tokio::fs::File::open(file.path()).await.unwrap(),
+                file.path().to_string_lossy().to_string(),
+                columns,
+            )
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                Some(Row::new(vec![
+                    TableValue::Int(1),
+                    TableValue::Decimal(parse_decimal("1.5", 2).unwrap()),
+                    TableValue::Boolean(true),
+                    TableValue::String(r#"["a","b"]"#.to_string()),
+                ])),
+                None,
+                Some(Row::new(vec![
+                    TableValue::Int(2),
+                    TableValue::Decimal(parse_decimal("3", 2).unwrap()),
+                    TableValue::Null,
+                    TableValue::Null,
+                ])),
+            ]
+        );
+    }
 }
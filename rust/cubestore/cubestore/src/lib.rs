@@ -41,6 +41,7 @@ pub mod app_metrics;
 pub mod cluster;
 pub mod codegen;
 pub mod config;
+pub mod flight;
 pub mod http;
 pub mod import;
 pub mod metastore;
@@ -476,12 +477,24 @@ impl From<url::ParseError> for CubeError {
     }
 }
 
+impl From<serde_xml_rs::Error> for CubeError {
+    fn from(v: serde_xml_rs::Error) -> Self {
+        CubeError::from_error(v)
+    }
+}
+
 impl From<tokio_tungstenite::tungstenite::Error> for CubeError {
     fn from(v: tokio_tungstenite::tungstenite::Error) -> Self {
         CubeError::from_error(v)
     }
 }
 
+impl From<rdkafka::error::KafkaError> for CubeError {
+    fn from(v: rdkafka::error::KafkaError) -> Self {
+        CubeError::from_error(v)
+    }
+}
+
 impl Into<ArrowError> for CubeError {
     fn into(self) -> ArrowError {
         ArrowError::ExternalError(Box::new(self))
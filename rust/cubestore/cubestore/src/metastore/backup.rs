@@ -0,0 +1,240 @@
+//! Point-in-time backups of the metastore.
+//!
+//! The router uploads a RocksDB checkpoint as `metastore-<millis>/` every
+//! `meta_store_snapshot_interval` seconds and points `metastore-current` to it. Changes made after
+//! the checkpoint are uploaded to `metastore-<millis>-logs/` every `meta_store_log_upload_interval`
+//! seconds. Restoring to a point in time takes the last snapshot created before it and replays
+//! the logs uploaded before it, so the precision is limited by the log upload interval. Data files
+//! replaced after the point are soon removed from the remote storage, restoring fails if the
+//! restored metastore references any of them.
+//!
+//! Restored metastore is uploaded as a new snapshot. Running router keeps its local copy until
+//! restart, so it stops uploading changes and discards the local copy on the next start.
+use crate::config::ConfigObj;
+use crate::metastore::{Column, ColumnType, MetaStore, MetaStoreTable, RocksMetaStore};
+use crate::remotefs::RemoteFs;
+use crate::store::DataFrame;
+use crate::table::{Row, TableValue, TimestampValue};
+use crate::CubeError;
+use chrono::{DateTime, TimeZone, Utc};
+use itertools::Itertools;
+use log::{info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::fs;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MetaStoreBackup {
+    snapshot_id: u64,
+    created_at: DateTime<Utc>,
+    current: bool,
+}
+
+impl MetaStoreBackup {
+    fn new(snapshot_id: u64, current: bool) -> Self {
+        Self {
+            snapshot_id,
+            created_at: Utc.timestamp_millis(snapshot_id as i64),
+            current,
+        }
+    }
+
+    pub fn snapshot_id(&self) -> u64 {
+        self.snapshot_id
+    }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+
+    pub fn current(&self) -> bool {
+        self.current
+    }
+
+    pub fn remote_path(&self) -> String {
+        format!("metastore-{}", self.snapshot_id)
+    }
+}
+
+impl From<Vec<MetaStoreBackup>> for DataFrame {
+    fn from(backups: Vec<MetaStoreBackup>) -> Self {
+        DataFrame::new(
+            vec![
+                Column::new("snapshot_id".to_string(), ColumnType::Int, 0),
+                Column::new("created_at".to_string(), ColumnType::Timestamp, 1),
+                Column::new("current".to_string(), ColumnType::Boolean, 2),
+            ],
+            backups
+                .into_iter()
+                .map(|b| {
+                    Row::new(vec![
+                        TableValue::Int(b.snapshot_id as i64),
+                        TableValue::Timestamp(TimestampValue::new(b.created_at.timestamp_nanos())),
+                        TableValue::Boolean(b.current),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+impl RocksMetaStore {
+    /// Snapshots available on the remote storage, oldest first.
+    pub async fn list_remote_backups(
+        remote_fs: &Arc<dyn RemoteFs>,
+    ) -> Result<Vec<MetaStoreBackup>, CubeError> {
+        let current = RocksMetaStore::current_snapshot(remote_fs).await?;
+        let re = Regex::new(r"^metastore-(\d+)/").unwrap();
+        let snapshots = remote_fs
+            .list("metastore-")
+            .await?
+            .iter()
+            .filter_map(|f| re.captures(f))
+            .filter_map(|c| u64::from_str(c.get(1).unwrap().as_str()).ok())
+            .collect::<BTreeSet<_>>();
+        Ok(snapshots
+            .into_iter()
+            .map(|id| MetaStoreBackup::new(id, Some(id) == current))
+            .collect())
+    }
+
+    /// Builds the metastore state at `point` from the remote snapshots, verifies it and uploads
+    /// it as the new current snapshot. Doesn't affect metastores that are already loaded.
+    pub async fn restore_remote_backup(
+        remote_fs: &Arc<dyn RemoteFs>,
+        config: Arc<dyn ConfigObj>,
+        point: DateTime<Utc>,
+    ) -> Result<MetaStoreBackup, CubeError> {
+        let snapshot = RocksMetaStore::list_remote_backups(remote_fs)
+            .await?
+            .into_iter()
+            .filter(|b| b.created_at() <= &point)
+            .last()
+            .ok_or_else(|| {
+                CubeError::user(format!("No metastore backups found before {}", point))
+            })?;
+        info!(
+            "Restoring metastore to {} from {}",
+            point,
+            snapshot.remote_path()
+        );
+
+        let restore_path = PathBuf::from(remote_fs.local_file("metastore-restore").await?);
+        if fs::metadata(&restore_path).await.is_ok() {
+            fs::remove_dir_all(&restore_path).await?;
+        }
+        let meta_store = RocksMetaStore::load_snapshot(
+            &restore_path,
+            snapshot.snapshot_id(),
+            Some(point),
+            remote_fs.clone(),
+            config,
+        )
+        .await?;
+        RocksMetaStore::verify_all_indexes(&meta_store)
+            .await
+            .map_err(|e| {
+                CubeError::user(format!(
+                    "Restored metastore is inconsistent, try an earlier point: {}",
+                    e
+                ))
+            })?;
+        RocksMetaStore::verify_remote_files(&meta_store, remote_fs).await?;
+
+        let checkpoint_time = SystemTime::now();
+        let (remote_path, checkpoint_path) =
+            RocksMetaStore::prepare_checkpoint(meta_store.db.clone(), &checkpoint_time).await?;
+        RocksMetaStore::upload_checkpoint(
+            remote_fs.clone(),
+            remote_path,
+            checkpoint_path,
+            meta_store.config.meta_store_snapshot_retention(),
+            meta_store.config.meta_store_snapshots_keep(),
+        )
+        .await?;
+        drop(meta_store);
+        fs::remove_dir_all(&restore_path).await?;
+
+        let snapshot_id = checkpoint_time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        info!("Metastore restored as metastore-{}", snapshot_id);
+        Ok(MetaStoreBackup::new(snapshot_id, true))
+    }
+
+    /// Fails if files of active partitions or chunks are missing on the remote storage. Files
+    /// deactivated after the restore point are removed by the data GC soon after that.
+    async fn verify_remote_files(
+        meta_store: &RocksMetaStore,
+        remote_fs: &Arc<dyn RemoteFs>,
+    ) -> Result<(), CubeError> {
+        let mut referenced = Vec::new();
+        for p in meta_store.partition_table().all_rows().await? {
+            if let Some(name) = p.get_row().get_full_name(p.get_id()) {
+                referenced.push(name);
+            }
+        }
+        for c in meta_store.chunks_table().all_rows().await? {
+            let chunk = c.get_row();
+            if chunk.active() && chunk.uploaded() && !chunk.in_memory() {
+                referenced.push(chunk.get_full_name(c.get_id()));
+            }
+        }
+        if referenced.is_empty() {
+            return Ok(());
+        }
+
+        let existing = remote_fs
+            .list("")
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let missing = referenced
+            .into_iter()
+            .filter(|f| !existing.contains(f))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(CubeError::user(format!(
+                "Restored metastore references {} files that were already removed from the \
+                 remote storage, try a later point. Missing files: {}",
+                missing.len(),
+                missing.iter().take(10).join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Makes the next `load_from_remote` discard the local metastore at `path` and download
+    /// the current remote snapshot instead.
+    pub async fn mark_restored(path: &Path) -> Result<(), CubeError> {
+        if fs::metadata(path).await.is_ok() {
+            fs::write(Self::restored_marker_path(path), b"").await?;
+        }
+        Ok(())
+    }
+
+    pub(super) async fn is_restored(path: &Path) -> bool {
+        fs::metadata(Self::restored_marker_path(path)).await.is_ok()
+    }
+
+    pub(super) async fn discard_if_restored(path: &Path) -> Result<(), CubeError> {
+        if Self::is_restored(path).await {
+            warn!(
+                "Metastore was restored from a backup, discarding local copy in {}",
+                path.as_os_str().to_string_lossy()
+            );
+            fs::remove_dir_all(path).await?;
+        }
+        Ok(())
+    }
+
+    fn restored_marker_path(path: &Path) -> PathBuf {
+        path.join("RESTORED_FROM_BACKUP")
+    }
+}
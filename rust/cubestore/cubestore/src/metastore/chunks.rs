@@ -1,6 +1,8 @@
 use super::{BaseRocksSecondaryIndex, Chunk, IndexId, RocksSecondaryIndex, RocksTable, TableId};
+use crate::metastore::table::ParquetCompression;
 use crate::metastore::{IdRow, MetaStoreEvent};
 use crate::rocks_table_impl;
+use crate::table::stats::ColumnStats;
 use crate::{base_rocks_secondary_index, CubeError};
 use byteorder::{BigEndian, WriteBytesExt};
 use chrono::{DateTime, Utc};
@@ -27,6 +29,8 @@ impl Chunk {
                     .to_lowercase(),
             ),
             file_size: None,
+            compression: None,
+            column_stats: None,
         }
     }
 
@@ -76,6 +80,26 @@ impl Chunk {
         Ok(c)
     }
 
+    pub fn compression(&self) -> Option<ParquetCompression> {
+        self.compression
+    }
+
+    pub fn set_compression(&self, compression: Option<ParquetCompression>) -> Chunk {
+        let mut to_update = self.clone();
+        to_update.compression = compression;
+        to_update
+    }
+
+    pub fn column_stats(&self) -> &Option<Vec<ColumnStats>> {
+        &self.column_stats
+    }
+
+    pub fn set_column_stats(&self, column_stats: Option<Vec<ColumnStats>>) -> Chunk {
+        let mut to_update = self.clone();
+        to_update.column_stats = column_stats;
+        to_update
+    }
+
     pub fn deactivate(&self) -> Chunk {
         let mut to_update = self.clone();
         to_update.active = false;
@@ -1,6 +1,6 @@
 use super::{
-    BaseRocksSecondaryIndex, Column, Index, IndexId, IndexType, RocksSecondaryIndex, RocksTable,
-    TableId,
+    BaseRocksSecondaryIndex, Column, ColumnType, Index, IndexId, IndexType, RocksSecondaryIndex,
+    RocksTable, TableId,
 };
 use crate::metastore::{IdRow, MetaStoreEvent};
 use crate::{rocks_table_impl, CubeError};
@@ -25,6 +25,17 @@ impl Index {
                 name, columns
             )));
         }
+        if let Some(c) = columns
+            .iter()
+            .take(sort_key_size as usize)
+            .find(|c| matches!(c.get_column_type(), ColumnType::List(_)))
+        {
+            return Err(CubeError::user(format!(
+                "Array column '{}' can't be used in the sort key of index '{}'",
+                c.get_name(),
+                name
+            )));
+        }
         Ok(Index {
             name,
             table_id,
@@ -69,6 +80,37 @@ impl Index {
         self.multi_index_id
     }
 
+    /// Regular indexes store all columns of the table, so added columns are appended to them.
+    pub fn add_column(&self, column: &Column) -> Index {
+        let mut index = self.clone();
+        if self.index_type == IndexType::Regular {
+            index.columns.push(column.replace_index(self.columns.len()));
+        }
+        index
+    }
+
+    pub fn drop_column(&self, name: &str) -> Result<Index, CubeError> {
+        let position = match self.columns.iter().position(|c| c.get_name() == name) {
+            Some(position) => position,
+            None => return Ok(self.clone()),
+        };
+        if position < self.sort_key_size as usize || self.index_type == IndexType::Aggregate {
+            return Err(CubeError::user(format!(
+                "Column '{}' is used by index '{}' and can't be dropped",
+                name, self.name
+            )));
+        }
+        let mut index = self.clone();
+        index.columns = self
+            .columns
+            .iter()
+            .filter(|c| c.get_name() != name)
+            .enumerate()
+            .map(|(i, c)| c.replace_index(i))
+            .collect();
+        Ok(index)
+    }
+
     pub fn index_type_default() -> IndexType {
         IndexType::Regular
     }
@@ -19,6 +19,8 @@ pub enum JobType {
     FinishMultiSplit,
     RepartitionChunk,
     InMemoryChunksCompaction,
+    /// Rewrites the partition without rows matching the predicate.
+    PartitionRowsDeletion(/*predicate*/ String),
 }
 
 fn get_job_type_index(j: &JobType) -> u32 {
@@ -32,6 +34,7 @@ fn get_job_type_index(j: &JobType) -> u32 {
         JobType::FinishMultiSplit => 7,
         JobType::RepartitionChunk => 8,
         JobType::InMemoryChunksCompaction => 9,
+        JobType::PartitionRowsDeletion(_) => 10,
     }
 }
 
@@ -148,6 +151,10 @@ impl RocksSecondaryIndex<Job, JobIndexKey> for JobRocksIndex {
                         buf.write_u64::<BigEndian>(l.len() as u64).unwrap();
                         buf.write(l.as_bytes()).unwrap();
                     }
+                    JobType::PartitionRowsDeletion(predicate) => {
+                        buf.write_u64::<BigEndian>(predicate.len() as u64).unwrap();
+                        buf.write(predicate.as_bytes()).unwrap();
+                    }
                     _ => {}
                 }
                 buf.into_inner()
@@ -1,3 +1,4 @@
+pub mod backup;
 pub mod chunks;
 pub mod index;
 pub mod job;
@@ -22,6 +23,7 @@ use std::{collections::hash_map::DefaultHasher, env, io::Cursor, sync::Arc, time
 use tokio::fs;
 use tokio::sync::{oneshot, Notify, RwLock};
 
+use crate::app_metrics;
 use crate::config::injection::DIService;
 use crate::config::{Config, ConfigObj};
 use crate::metastore::chunks::{ChunkIndexKey, ChunkRocksIndex};
@@ -35,9 +37,13 @@ use crate::metastore::partition::PartitionIndexKey;
 use crate::metastore::source::{
     Source, SourceCredentials, SourceIndexKey, SourceRocksIndex, SourceRocksTable,
 };
-use crate::metastore::table::{AggregateColumnIndex, TableIndexKey, TablePath};
+use crate::metastore::table::{
+    AggregateColumnIndex, ParquetCompression, StreamOffsets, TableIndexKey, TablePath, TableState,
+    TableTtl,
+};
 use crate::metastore::wal::{WALIndexKey, WALRocksIndex};
 use crate::remotefs::{LocalDirRemoteFs, RemoteFs};
+use crate::table::stats::{supports_bloom_filter, ColumnStats};
 use crate::table::{Row, TableValue};
 use crate::util::aborting_join_handle::AbortingJoinHandle;
 use crate::util::time_span::warn_long;
@@ -45,6 +51,7 @@ use crate::util::WorkerLoop;
 use crate::CubeError;
 use arrow::datatypes::TimeUnit::Microsecond;
 use arrow::datatypes::{DataType, Field};
+use backup::MetaStoreBackup;
 use chrono::{DateTime, Utc};
 use chunks::ChunkRocksTable;
 use core::{fmt, mem};
@@ -66,11 +73,12 @@ use rocksdb::checkpoint::Checkpoint;
 use schema::{SchemaRocksIndex, SchemaRocksTable};
 use smallvec::alloc::fmt::Formatter;
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Display};
 use std::mem::take;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
 use std::time::{Duration, SystemTime};
 use table::Table;
@@ -292,6 +300,22 @@ impl DataFrameValue<String> for Option<u64> {
     }
 }
 
+impl DataFrameValue<String> for Option<Vec<ColumnStats>> {
+    fn value(v: &Self) -> String {
+        v.as_ref()
+            .map(|v| format!("{:?}", v))
+            .unwrap_or("NULL".to_string())
+    }
+}
+
+impl DataFrameValue<String> for Option<bool> {
+    fn value(v: &Self) -> String {
+        v.as_ref()
+            .map(|v| format!("{:?}", v))
+            .unwrap_or("NULL".to_string())
+    }
+}
+
 impl DataFrameValue<String> for Option<Vec<u64>> {
     fn value(v: &Self) -> String {
         v.as_ref()
@@ -317,6 +341,8 @@ impl DataFrameValue<String> for Option<Row> {
                             TableValue::Boolean(b) => format!("{:?}", b),
                             TableValue::Decimal(v) => format!("{}", v.raw_value()),
                             TableValue::Float(v) => format!("{}", v),
+                            TableValue::Date(d) => format!("{:?}", d),
+                            TableValue::List(l) => format!("{:?}", l),
                         })
                         .join(", ")
                 )
@@ -364,15 +390,27 @@ pub enum ColumnType {
     Bytes,
     HyperLogLog(HllFlavour), // HLL Sketches, compatible with presto.
     Timestamp,
-    Decimal { scale: i32, precision: i32 },
+    Decimal {
+        scale: i32,
+        precision: i32,
+    },
     Float,
     Boolean,
+    Int32,
+    Date,
+    /// Arrays of scalar values of the item type.
+    List(Box<ColumnType>),
+    /// T-digest sketches for approximate quantiles, compatible with presto.
+    TDigest,
+    Int16,
+    Int8,
 }
 
 impl Display for ColumnType {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         let s = match self {
             ColumnType::Decimal { scale, .. } => return write!(f, "decimal({})", scale),
+            ColumnType::List(item) => return write!(f, "list_{}", item),
             ColumnType::String => "text",
             ColumnType::Int => "int",
             ColumnType::Bytes => "bytes",
@@ -383,6 +421,11 @@ impl Display for ColumnType {
             ColumnType::Timestamp => "timestamp",
             ColumnType::Float => "float",
             ColumnType::Boolean => "boolean",
+            ColumnType::Int32 => "int32",
+            ColumnType::Int16 => "int16",
+            ColumnType::Int8 => "tinyint",
+            ColumnType::Date => "date32",
+            ColumnType::TDigest => "tdigest",
         };
         f.write_str(s)
     }
@@ -401,61 +444,124 @@ impl ColumnType {
             x => panic!("target_scale called on {:?}", x),
         }
     }
+
+    pub fn arrow_type(&self) -> DataType {
+        match self {
+            ColumnType::String => DataType::Utf8,
+            ColumnType::Int => DataType::Int64,
+            ColumnType::Int32 => DataType::Int32,
+            ColumnType::Int16 => DataType::Int16,
+            ColumnType::Int8 => DataType::Int8,
+            ColumnType::Timestamp => DataType::Timestamp(Microsecond, None),
+            ColumnType::Date => DataType::Date32,
+            ColumnType::Boolean => DataType::Boolean,
+            ColumnType::Decimal { .. } => DataType::Int64Decimal(self.target_scale() as usize),
+            ColumnType::Bytes => DataType::Binary,
+            ColumnType::HyperLogLog(_) | ColumnType::TDigest => DataType::Binary,
+            ColumnType::Float => DataType::Float64,
+            ColumnType::List(item) => {
+                DataType::List(Box::new(Field::new("item", item.arrow_type(), true)))
+            }
+        }
+    }
+
+    /// Lists can only hold plain scalar values.
+    pub fn is_valid_list_item(&self) -> bool {
+        match self {
+            ColumnType::List(_) | ColumnType::HyperLogLog(_) | ColumnType::TDigest => false,
+            _ => true,
+        }
+    }
 }
 
 impl From<&Column> for parquet::schema::types::Type {
     fn from(column: &Column) -> Self {
-        match column.get_column_type() {
-            crate::metastore::ColumnType::String => {
-                types::Type::primitive_type_builder(&column.get_name(), Type::BYTE_ARRAY)
-                    .with_converted_type(ConvertedType::UTF8)
-                    .with_repetition(Repetition::OPTIONAL)
-                    .build()
-                    .unwrap()
-            }
-            crate::metastore::ColumnType::Int => {
-                types::Type::primitive_type_builder(&column.get_name(), Type::INT64)
-                    .with_converted_type(ConvertedType::INT_64)
-                    .with_repetition(Repetition::OPTIONAL)
-                    .build()
-                    .unwrap()
-            }
-            crate::metastore::ColumnType::Decimal { precision, .. } => {
-                types::Type::primitive_type_builder(&column.get_name(), Type::INT64)
-                    .with_converted_type(ConvertedType::DECIMAL)
-                    .with_precision(*precision)
-                    .with_scale(column.get_column_type().target_scale())
-                    .with_repetition(Repetition::OPTIONAL)
-                    .build()
-                    .unwrap()
-            }
-            crate::metastore::ColumnType::Bytes | ColumnType::HyperLogLog(_) => {
-                types::Type::primitive_type_builder(&column.get_name(), Type::BYTE_ARRAY)
-                    .with_converted_type(ConvertedType::NONE)
-                    .with_repetition(Repetition::OPTIONAL)
-                    .build()
-                    .unwrap()
-            }
-            crate::metastore::ColumnType::Timestamp => {
-                types::Type::primitive_type_builder(&column.get_name(), Type::INT64)
-                    //TODO MICROS?
-                    .with_converted_type(ConvertedType::TIMESTAMP_MICROS)
-                    .with_repetition(Repetition::OPTIONAL)
-                    .build()
-                    .unwrap()
-            }
-            crate::metastore::ColumnType::Boolean => {
-                types::Type::primitive_type_builder(&column.get_name(), Type::BOOLEAN)
-                    .with_repetition(Repetition::OPTIONAL)
-                    .build()
-                    .unwrap()
-            }
-            ColumnType::Float => {
-                types::Type::primitive_type_builder(&column.get_name(), Type::DOUBLE)
-                    .with_repetition(Repetition::OPTIONAL)
-                    .build()
-                    .unwrap()
-            }
+        parquet_type(
+            column.get_name(),
+            column.get_column_type(),
+            Repetition::OPTIONAL,
+        )
+    }
+}
+
+fn parquet_type(name: &str, column_type: &ColumnType, repetition: Repetition) -> types::Type {
+    match column_type {
+        ColumnType::String => types::Type::primitive_type_builder(name, Type::BYTE_ARRAY)
+            .with_converted_type(ConvertedType::UTF8)
+            .with_repetition(repetition)
+            .build()
+            .unwrap(),
+        ColumnType::Int => types::Type::primitive_type_builder(name, Type::INT64)
+            .with_converted_type(ConvertedType::INT_64)
+            .with_repetition(repetition)
+            .build()
+            .unwrap(),
+        ColumnType::Int32 => types::Type::primitive_type_builder(name, Type::INT32)
+            .with_converted_type(ConvertedType::INT_32)
+            .with_repetition(repetition)
+            .build()
+            .unwrap(),
+        ColumnType::Int16 => types::Type::primitive_type_builder(name, Type::INT32)
+            .with_converted_type(ConvertedType::INT_16)
+            .with_repetition(repetition)
+            .build()
+            .unwrap(),
+        ColumnType::Int8 => types::Type::primitive_type_builder(name, Type::INT32)
+            .with_converted_type(ConvertedType::INT_8)
+            .with_repetition(repetition)
+            .build()
+            .unwrap(),
+        ColumnType::Decimal { precision, .. } => {
+            types::Type::primitive_type_builder(name, Type::INT64)
+                .with_converted_type(ConvertedType::DECIMAL)
+                .with_precision(*precision)
+                .with_scale(column_type.target_scale())
+                .with_repetition(repetition)
+                .build()
+                .unwrap()
+        }
+        ColumnType::Bytes | ColumnType::HyperLogLog(_) | ColumnType::TDigest => {
+            types::Type::primitive_type_builder(name, Type::BYTE_ARRAY)
+                .with_converted_type(ConvertedType::NONE)
+                .with_repetition(repetition)
+                .build()
+                .unwrap()
+        }
+        ColumnType::Timestamp => {
+            types::Type::primitive_type_builder(name, Type::INT64)
+                //TODO MICROS?
+                .with_converted_type(ConvertedType::TIMESTAMP_MICROS)
+                .with_repetition(repetition)
+                .build()
+                .unwrap()
+        }
+        ColumnType::Date => types::Type::primitive_type_builder(name, Type::INT32)
+            .with_converted_type(ConvertedType::DATE)
+            .with_repetition(repetition)
+            .build()
+            .unwrap(),
+        ColumnType::Boolean => types::Type::primitive_type_builder(name, Type::BOOLEAN)
+            .with_repetition(repetition)
+            .build()
+            .unwrap(),
+        ColumnType::Float => types::Type::primitive_type_builder(name, Type::DOUBLE)
+            .with_repetition(repetition)
+            .build()
+            .unwrap(),
+        // Standard three-level list representation, same as the one produced by ArrowWriter.
+        ColumnType::List(item) => {
+            let element = parquet_type("item", item, Repetition::OPTIONAL);
+            let list = types::Type::group_type_builder("list")
+                .with_repetition(Repetition::REPEATED)
+                .with_fields(&mut vec![Arc::new(element)])
+                .build()
+                .unwrap();
+            types::Type::group_type_builder(name)
+                .with_converted_type(ConvertedType::LIST)
+                .with_repetition(repetition)
+                .with_fields(&mut vec![Arc::new(list)])
+                .build()
+                .unwrap()
         }
     }
 }
@@ -465,6 +571,8 @@ pub struct Column {
     name: String,
     column_type: ColumnType,
     column_index: usize,
+    #[serde(default)]
+    default_value: Option<TableValue>,
 }
 
 impl Into<Field> for Column {
@@ -475,28 +583,23 @@ impl Into<Field> for Column {
 
 impl<'a> Into<Field> for &'a Column {
     fn into(self) -> Field {
-        Field::new(
-            self.name.as_str(),
-            match self.column_type {
-                ColumnType::String => DataType::Utf8,
-                ColumnType::Int => DataType::Int64,
-                ColumnType::Timestamp => DataType::Timestamp(Microsecond, None),
-                ColumnType::Boolean => DataType::Boolean,
-                ColumnType::Decimal { .. } => {
-                    DataType::Int64Decimal(self.column_type.target_scale() as usize)
-                }
-                ColumnType::Bytes => DataType::Binary,
-                ColumnType::HyperLogLog(_) => DataType::Binary,
-                ColumnType::Float => DataType::Float64,
-            },
-            true,
-        )
+        Field::new(self.name.as_str(), self.column_type.arrow_type(), true)
     }
 }
 
 impl fmt::Display for Column {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let column_type = match &self.column_type {
+        f.write_fmt(format_args!(
+            "{} {}",
+            self.name,
+            Column::type_sql_name(&self.column_type)
+        ))
+    }
+}
+
+impl Column {
+    fn type_sql_name(column_type: &ColumnType) -> String {
+        match column_type {
             ColumnType::String => "STRING".to_string(),
             ColumnType::Int => "INT".to_string(),
             ColumnType::Timestamp => "TIMESTAMP".to_string(),
@@ -510,8 +613,13 @@ impl fmt::Display for Column {
             ColumnType::HyperLogLog(HllFlavour::Postgres) => "HLL_POSTGRES".to_string(),
             ColumnType::HyperLogLog(HllFlavour::Snowflake) => "HLL_SNOWFLAKE".to_string(),
             ColumnType::Float => "FLOAT".to_string(),
-        };
-        f.write_fmt(format_args!("{} {}", self.name, column_type))
+            ColumnType::Int32 => "INT32".to_string(),
+            ColumnType::Int16 => "INT16".to_string(),
+            ColumnType::Int8 => "TINYINT".to_string(),
+            ColumnType::Date => "DATE32".to_string(),
+            ColumnType::List(item) => format!("LIST_{}", Column::type_sql_name(item)),
+            ColumnType::TDigest => "TDIGEST".to_string(),
+        }
     }
 }
 
@@ -519,6 +627,8 @@ impl fmt::Display for Column {
 pub enum ImportFormat {
     CSV,
     CSVNoHeader,
+    Parquet,
+    NDJSON,
 }
 
 data_frame_from! {
@@ -592,7 +702,7 @@ impl AggregateFunction {
     pub fn allowed_for_type(&self, col_type: &ColumnType) -> bool {
         match self {
             Self::MAX | Self::MIN => match col_type {
-                ColumnType::HyperLogLog(_) => false,
+                ColumnType::HyperLogLog(_) | ColumnType::List(_) | ColumnType::TDigest => false,
                 _ => true,
             },
             Self::SUM => match col_type {
@@ -600,7 +710,7 @@ impl AggregateFunction {
                 _ => false,
             },
             Self::MERGE => match col_type {
-                ColumnType::HyperLogLog(_) => true,
+                ColumnType::HyperLogLog(_) | ColumnType::TDigest => true,
                 _ => false,
             },
         }
@@ -634,7 +744,11 @@ pub struct Partition {
     #[serde(default)]
     suffix: Option<String>,
     #[serde(default)]
-    file_size: Option<u64>
+    file_size: Option<u64>,
+    /// Per-column stats of the main table file used to skip partitions during queries and to
+    /// expire rows by TTL. Not set for partitions written by older versions.
+    #[serde(default)]
+    column_stats: Option<Vec<ColumnStats>>
 }
 }
 
@@ -657,7 +771,14 @@ pub struct Chunk {
     #[serde(default)]
     suffix: Option<String>,
     #[serde(default)]
-    file_size: Option<u64>
+    file_size: Option<u64>,
+    /// Not set for in-memory chunks and chunks written by older versions.
+    #[serde(default)]
+    compression: Option<ParquetCompression>,
+    /// Per-column stats used to skip chunks during queries. Not set for chunks produced by
+    /// compactions of multiple partitions and chunks written by older versions.
+    #[serde(default)]
+    column_stats: Option<Vec<ColumnStats>>
 }
 }
 
@@ -803,6 +924,16 @@ pub struct PartitionData {
     pub chunks: Vec<IdRow<Chunk>>,
 }
 
+/// Partition written without deleted rows, but not activated yet.
+/// See [MetaStore::swap_rewritten_partitions].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PartitionRewrite {
+    pub current_active: (IdRow<Partition>, Vec<IdRow<Chunk>>),
+    /// Empty if there were no rows to rewrite, the current partition stays active then.
+    pub new_active: Vec<(IdRow<Partition>, u64)>,
+    pub new_active_min_max: Vec<(u64, (Option<Row>, Option<Row>))>,
+}
+
 #[cuberpc::service]
 pub trait MetaStore: DIService + Send + Sync {
     async fn wait_for_current_seq_to_sync(&self) -> Result<(), CubeError>;
@@ -844,8 +975,24 @@ pub trait MetaStore: DIService + Send + Sync {
         unique_key_column_names: Option<Vec<String>>,
         aggregates: Option<Vec<(String, String)>>,
         partition_split_threshold: Option<u64>,
+        ttl: Option<(String, u64)>,
+        compression: Option<ParquetCompression>,
+        dictionary: Option<bool>,
+        bloom_filter_columns: Option<Vec<String>>,
     ) -> Result<IdRow<Table>, CubeError>;
     async fn table_ready(&self, id: u64, is_ready: bool) -> Result<IdRow<Table>, CubeError>;
+    async fn update_table_state(
+        &self,
+        id: u64,
+        state: TableState,
+    ) -> Result<IdRow<Table>, CubeError>;
+    /// Adds the column to the table and all its regular indexes without rewriting existing data.
+    async fn add_table_column(&self, id: u64, column: Column) -> Result<IdRow<Table>, CubeError>;
+    async fn drop_table_column(
+        &self,
+        id: u64,
+        column_name: String,
+    ) -> Result<IdRow<Table>, CubeError>;
     async fn update_location_download_size(
         &self,
         id: u64,
@@ -894,6 +1041,7 @@ pub trait MetaStore: DIService + Send + Sync {
         old_chunk_ids: Vec<u64>,
         new_chunk: u64,
         new_chunk_file_size: u64,
+        new_chunk_column_stats: Option<Vec<ColumnStats>>,
     ) -> Result<bool, CubeError>;
     async fn swap_active_partitions(
         &self,
@@ -901,8 +1049,23 @@ pub trait MetaStore: DIService + Send + Sync {
         new_active: Vec<(IdRow<Partition>, u64)>,
         new_active_min_max: Vec<(u64, (Option<Row>, Option<Row>))>,
     ) -> Result<(), CubeError>;
+    /// Same as `swap_active_partitions` for partitions rewritten without deleted rows, so row
+    /// counts aren't expected to match. `new_chunks` with rows added by the rewrite, e.g. updated
+    /// ones, are activated in the same transaction. Nothing is changed if any of the partitions
+    /// or their chunks were changed concurrently.
+    async fn swap_rewritten_partitions(
+        &self,
+        rewrites: Vec<PartitionRewrite>,
+        new_chunks: Vec<(u64, Option<u64>)>,
+    ) -> Result<(), CubeError>;
     async fn delete_partition(&self, partition_id: u64) -> Result<IdRow<Partition>, CubeError>;
     async fn mark_partition_warmed_up(&self, partition_id: u64) -> Result<(), CubeError>;
+    /// Sets stats of the main table file of a partition before it is activated.
+    async fn set_partition_column_stats(
+        &self,
+        partition_id: u64,
+        column_stats: Option<Vec<ColumnStats>>,
+    ) -> Result<(), CubeError>;
     async fn delete_middle_man_partition(
         &self,
         partition_id: u64,
@@ -995,6 +1158,8 @@ pub trait MetaStore: DIService + Send + Sync {
         partition_id: u64,
         row_count: usize,
         in_memory: bool,
+        compression: Option<ParquetCompression>,
+        column_stats: Option<Vec<ColumnStats>>,
     ) -> Result<IdRow<Chunk>, CubeError>;
     async fn get_chunk(&self, chunk_id: u64) -> Result<IdRow<Chunk>, CubeError>;
     async fn get_chunks_by_partition(
@@ -1025,6 +1190,15 @@ pub trait MetaStore: DIService + Send + Sync {
         table_id: u64,
         uploaded_chunk_ids: Vec<(u64, Option<u64>)>,
     ) -> Result<(), CubeError>;
+    /// Activates chunks ingested from the stream `location` and stores the stream position
+    /// in the same transaction, so ingestion can be resumed from it.
+    async fn activate_stream_chunks(
+        &self,
+        table_id: u64,
+        location: String,
+        uploaded_chunk_ids: Vec<(u64, Option<u64>)>,
+        stream_offsets: StreamOffsets,
+    ) -> Result<(), CubeError>;
     async fn delete_chunk(&self, chunk_id: u64) -> Result<IdRow<Chunk>, CubeError>;
     async fn all_inactive_chunks(&self) -> Result<Vec<IdRow<Chunk>>, CubeError>;
     async fn all_inactive_not_uploaded_chunks(&self) -> Result<Vec<IdRow<Chunk>>, CubeError>;
@@ -1071,6 +1245,12 @@ pub trait MetaStore: DIService + Send + Sync {
     ) -> Result<Vec<(IdRow<Schema>, IdRow<Table>, Vec<IdRow<Index>>)>, CubeError>;
 
     async fn debug_dump(&self, out_path: String) -> Result<(), CubeError>;
+
+    /// Uploads a new snapshot of the metastore. Returns all snapshots available for restore.
+    async fn create_backup(&self) -> Result<Vec<MetaStoreBackup>, CubeError>;
+    /// Uploads the metastore state at `point` as the new current snapshot. The router uses it
+    /// after restart, changes made before that are lost.
+    async fn restore_backup(&self, point: DateTime<Utc>) -> Result<MetaStoreBackup, CubeError>;
 }
 
 crate::di_service!(RocksMetaStore, [MetaStore]);
@@ -1261,6 +1441,34 @@ impl MemorySequence {
     }
 }
 
+/// How often [RocksMetaStore::report_metrics] runs.
+const METRICS_INTERVAL_SECS: u64 = 15;
+
+fn job_type_name(t: &JobType) -> &'static str {
+    match t {
+        JobType::WalPartitioning => "WalPartitioning",
+        JobType::PartitionCompaction => "PartitionCompaction",
+        JobType::TableImport => "TableImport",
+        JobType::Repartition => "Repartition",
+        JobType::TableImportCSV(_) => "TableImportCSV",
+        JobType::MultiPartitionSplit => "MultiPartitionSplit",
+        JobType::FinishMultiSplit => "FinishMultiSplit",
+        JobType::RepartitionChunk => "RepartitionChunk",
+        JobType::InMemoryChunksCompaction => "InMemoryChunksCompaction",
+        JobType::PartitionRowsDeletion(_) => "PartitionRowsDeletion",
+    }
+}
+
+fn job_status_name(s: &JobStatus) -> &'static str {
+    match s {
+        JobStatus::Scheduled(_) => "Scheduled",
+        JobStatus::ProcessingBy(_) => "ProcessingBy",
+        JobStatus::Completed => "Completed",
+        JobStatus::Timeout => "Timeout",
+        JobStatus::Error(_) => "Error",
+    }
+}
+
 #[derive(Clone)]
 pub struct RocksMetaStore {
     pub db: Arc<DB>,
@@ -1273,11 +1481,13 @@ pub struct RocksMetaStore {
     last_upload_seq: Arc<RwLock<u64>>,
     last_check_seq: Arc<RwLock<u64>>,
     upload_loop: Arc<WorkerLoop>,
+    metrics_loop: Arc<WorkerLoop>,
     config: Arc<dyn ConfigObj>,
     cached_tables: Arc<Mutex<Option<Arc<Vec<TablePath>>>>>,
     rw_loop_tx: std::sync::mpsc::SyncSender<
         Box<dyn FnOnce() -> Result<(), CubeError> + Send + Sync + 'static>,
     >,
+    rw_loop_queue_size: Arc<AtomicUsize>,
     _rw_loop_join_handle: Arc<AbortingJoinHandle<()>>,
 }
 
@@ -2076,9 +2286,12 @@ impl RocksMetaStore {
             Box<dyn FnOnce() -> Result<(), CubeError> + Send + Sync + 'static>,
         >(32_768);
 
+        let rw_loop_queue_size = Arc::new(AtomicUsize::new(0));
+        let queue_size = rw_loop_queue_size.clone();
         let join_handle = cube_ext::spawn_blocking(move || loop {
             match rw_loop_rx.recv() {
                 Ok(fun) => {
+                    queue_size.fetch_sub(1, Ordering::Relaxed);
                     if let Err(e) = fun() {
                         log::error!("Error during read write loop execution: {}", e);
                     }
@@ -2100,9 +2313,11 @@ impl RocksMetaStore {
             last_upload_seq: Arc::new(RwLock::new(db_arc.latest_sequence_number())),
             last_check_seq: Arc::new(RwLock::new(db_arc.latest_sequence_number())),
             upload_loop: Arc::new(WorkerLoop::new("Meta Store Upload")),
+            metrics_loop: Arc::new(WorkerLoop::new("Meta Store Metrics")),
             config,
             cached_tables: Arc::new(Mutex::new(None)),
             rw_loop_tx,
+            rw_loop_queue_size,
             _rw_loop_join_handle: Arc::new(AbortingJoinHandle::new(join_handle)),
         };
         meta_store
@@ -2116,6 +2331,11 @@ impl RocksMetaStore {
         Self::with_listener(path, vec![], remote_fs, config)
     }
 
+    /// The number of read and write operations waiting for the read-write loop.
+    pub fn rw_loop_queue_size(&self) -> usize {
+        self.rw_loop_queue_size.load(Ordering::Relaxed)
+    }
+
     pub async fn load_from_dump(
         path: impl AsRef<Path>,
         dump_path: impl AsRef<Path>,
@@ -2149,75 +2369,17 @@ impl RocksMetaStore {
         remote_fs: Arc<dyn RemoteFs>,
         config: Arc<dyn ConfigObj>,
     ) -> Result<Arc<RocksMetaStore>, CubeError> {
+        RocksMetaStore::discard_if_restored(path.as_ref()).await?;
         if !fs::metadata(path.as_ref()).await.is_ok() {
-            let re = Regex::new(r"^metastore-(\d+)").unwrap();
-
-            if remote_fs.list("metastore-current").await?.iter().len() > 0 {
+            if let Some(snapshot) = RocksMetaStore::current_snapshot(&remote_fs).await? {
                 info!("Downloading remote metastore");
-                let current_metastore_file = remote_fs.local_file("metastore-current").await?;
-                if fs::metadata(current_metastore_file.as_str()).await.is_ok() {
-                    fs::remove_file(current_metastore_file.as_str()).await?;
-                }
-                remote_fs.download_file("metastore-current", None).await?;
-
-                let mut file = File::open(current_metastore_file.as_str()).await?;
-                let mut buffer = Vec::new();
-                tokio::io::AsyncReadExt::read_to_end(&mut file, &mut buffer).await?;
-                let last_metastore_snapshot = {
-                    let parse_result = re
-                        .captures(&String::from_utf8(buffer)?)
-                        .map(|c| c.get(1).unwrap().as_str())
-                        .map(|p| u128::from_str(p));
-                    if let Some(Ok(millis)) = parse_result {
-                        Some(millis)
-                    } else {
-                        None
-                    }
-                };
-
-                if let Some(snapshot) = last_metastore_snapshot {
-                    let to_load = remote_fs.list(&format!("metastore-{}", snapshot)).await?;
-                    let meta_store_path = remote_fs.local_file("metastore").await?;
-                    fs::create_dir_all(meta_store_path.to_string()).await?;
-                    for file in to_load.iter() {
-                        // TODO check file size
-                        remote_fs.download_file(file, None).await?;
-                        let local = remote_fs.local_file(file).await?;
-                        let path = Path::new(&local);
-                        fs::copy(
-                            path,
-                            PathBuf::from(&meta_store_path)
-                                .join(path.file_name().unwrap().to_str().unwrap()),
-                        )
+                let meta_store =
+                    RocksMetaStore::load_snapshot(path.as_ref(), snapshot, None, remote_fs, config)
                         .await?;
-                    }
-
-                    let meta_store = Self::new(path.as_ref(), remote_fs.clone(), config);
-
-                    let logs_to_batch = remote_fs
-                        .list(&format!("metastore-{}-logs", snapshot))
-                        .await?;
-                    for log_file in logs_to_batch.iter() {
-                        let path_to_log = remote_fs.local_file(log_file).await?;
-                        let batch = WriteBatchContainer::read_from_file(&path_to_log).await;
-                        if let Ok(batch) = batch {
-                            let db = meta_store.db.clone();
-                            db.write(batch.write_batch())?;
-                        } else if let Err(e) = batch {
-                            error!(
-                                "Corrupted metastore WAL file. Discarding: {:?} {}",
-                                log_file, e
-                            );
-                            break;
-                        }
-                    }
 
-                    RocksMetaStore::check_all_indexes(&meta_store).await?;
+                RocksMetaStore::check_all_indexes(&meta_store).await?;
 
-                    return Ok(meta_store);
-                }
-            } else {
-                trace!("Can't find metastore-current in {:?}", remote_fs);
+                return Ok(meta_store);
             }
             info!(
                 "Creating metastore from scratch in {}",
@@ -2237,7 +2399,88 @@ impl RocksMetaStore {
         Ok(meta_store)
     }
 
+    /// Reads the name of the last uploaded snapshot from `metastore-current`.
+    async fn current_snapshot(remote_fs: &Arc<dyn RemoteFs>) -> Result<Option<u64>, CubeError> {
+        if remote_fs.list("metastore-current").await?.is_empty() {
+            trace!("Can't find metastore-current in {:?}", remote_fs);
+            return Ok(None);
+        }
+        let current_metastore_file = remote_fs.local_file("metastore-current").await?;
+        if fs::metadata(current_metastore_file.as_str()).await.is_ok() {
+            fs::remove_file(current_metastore_file.as_str()).await?;
+        }
+        remote_fs.download_file("metastore-current", None).await?;
+
+        let mut file = File::open(current_metastore_file.as_str()).await?;
+        let mut buffer = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut file, &mut buffer).await?;
+        let re = Regex::new(r"^metastore-(\d+)").unwrap();
+        Ok(re
+            .captures(&String::from_utf8(buffer)?)
+            .and_then(|c| u64::from_str(c.get(1).unwrap().as_str()).ok()))
+    }
+
+    /// Downloads the snapshot into `path` and replays its logs. Only logs uploaded before
+    /// `logs_until` are replayed if it's set.
+    async fn load_snapshot(
+        path: &Path,
+        snapshot: u64,
+        logs_until: Option<DateTime<Utc>>,
+        remote_fs: Arc<dyn RemoteFs>,
+        config: Arc<dyn ConfigObj>,
+    ) -> Result<Arc<RocksMetaStore>, CubeError> {
+        let to_load = remote_fs.list(&format!("metastore-{}/", snapshot)).await?;
+        fs::create_dir_all(path).await?;
+        for file in to_load.iter() {
+            // TODO check file size
+            let local = remote_fs.download_file(file, None).await?;
+            let local = Path::new(&local);
+            fs::copy(local, path.join(local.file_name().unwrap())).await?;
+        }
+
+        let meta_store = Self::new(path, remote_fs.clone(), config);
+
+        let mut logs_to_batch = remote_fs
+            .list_with_metadata(&format!("metastore-{}-logs/", snapshot))
+            .await?;
+        if let Some(logs_until) = logs_until {
+            logs_to_batch.retain(|f| f.updated() <= &logs_until);
+        }
+        // Logs are named after the first sequence number they contain.
+        logs_to_batch.sort_by_key(|f| {
+            Path::new(f.remote_path())
+                .file_stem()
+                .and_then(|s| u64::from_str(&s.to_string_lossy()).ok())
+                .unwrap_or(u64::MAX)
+        });
+        for log_file in logs_to_batch.iter() {
+            let path_to_log = remote_fs
+                .download_file(log_file.remote_path(), None)
+                .await?;
+            let batch = WriteBatchContainer::read_from_file(&path_to_log).await;
+            if let Ok(batch) = batch {
+                let db = meta_store.db.clone();
+                db.write(batch.write_batch())?;
+            } else if let Err(e) = batch {
+                error!(
+                    "Corrupted metastore WAL file. Discarding: {:?} {}",
+                    log_file.remote_path(),
+                    e
+                );
+                break;
+            }
+        }
+        Ok(meta_store)
+    }
+
     async fn check_all_indexes(meta_store: &Arc<RocksMetaStore>) -> Result<(), CubeError> {
+        if let Err(e) = RocksMetaStore::verify_all_indexes(meta_store).await {
+            log::error!("Error during checking indexes: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn verify_all_indexes(meta_store: &Arc<RocksMetaStore>) -> Result<(), CubeError> {
         let meta_store_to_move = meta_store.clone();
 
         cube_ext::spawn_blocking(move || {
@@ -2249,13 +2492,9 @@ impl RocksMetaStore {
                 },
             };
 
-            if let Err(e) = check_indexes_for_all_tables(table_ref) {
-                log::error!("Error during checking indexes: {}", e);
-            }
+            check_indexes_for_all_tables(table_ref)
         })
-        .await?;
-
-        Ok(())
+        .await?
     }
 
     pub async fn add_listener(&self, listener: Sender<MetaStoreEvent>) {
@@ -2277,8 +2516,10 @@ impl RocksMetaStore {
         let db_to_send = db.clone();
         let cached_tables = self.cached_tables.clone();
         let rw_loop_sender = self.rw_loop_tx.clone();
+        let rw_loop_queue_size = self.rw_loop_queue_size.clone();
         let (tx, rx) = oneshot::channel::<Result<(R, Vec<MetaStoreEvent>), CubeError>>();
         cube_ext::spawn_blocking(move || {
+            rw_loop_queue_size.fetch_add(1, Ordering::Relaxed);
             let res = rw_loop_sender.send(Box::new(move || {
                 let db_span = warn_long("metastore write operation", Duration::from_millis(100));
 
@@ -2318,6 +2559,7 @@ impl RocksMetaStore {
                 Ok(())
             }));
             if let Err(e) = res {
+                rw_loop_queue_size.fetch_sub(1, Ordering::Relaxed);
                 log::error!("Error during read write loop send: {}", e);
             }
         })
@@ -2350,8 +2592,105 @@ impl RocksMetaStore {
             .await;
     }
 
+    /// Periodically reports [RocksMetaStore::report_metrics], so metric scrapes never touch the
+    /// metastore.
+    pub async fn wait_metrics_loop(meta_store: Arc<Self>) {
+        meta_store
+            .metrics_loop
+            .process(
+                meta_store.clone(),
+                async move |_| Ok(Delay::new(Duration::from_secs(METRICS_INTERVAL_SECS)).await),
+                async move |m, _| {
+                    if let Err(e) = m.report_metrics().await {
+                        log::warn!("Failed to report metastore metrics: {}", e);
+                    }
+                    Ok(())
+                },
+            )
+            .await;
+    }
+
     pub async fn stop_processing_loops(&self) {
         self.upload_loop.stop();
+        self.metrics_loop.stop();
+    }
+
+    /// Reports the queue size, job counts and active partitions and chunks of each ready table
+    /// in a single pass over the metastore.
+    pub async fn report_metrics(&self) -> Result<(), CubeError> {
+        app_metrics::METASTORE_QUEUE_SIZE.report(self.rw_loop_queue_size() as i64);
+        let tables = self.get_tables_with_path(false).await?;
+        let (jobs, table_partitions, table_chunks) = self
+            .read_operation_out_of_queue(move |db_ref| {
+                let mut jobs = BTreeMap::<_, i64>::new();
+                for j in JobRocksTable::new(db_ref.clone()).scan_all_rows()? {
+                    let j = j?;
+                    let key = (
+                        job_type_name(j.get_row().job_type()),
+                        job_status_name(j.get_row().status()),
+                    );
+                    *jobs.entry(key).or_default() += 1;
+                }
+
+                let mut index_to_table = HashMap::new();
+                for i in IndexRocksTable::new(db_ref.clone()).scan_all_rows()? {
+                    let i = i?;
+                    index_to_table.insert(i.get_id(), i.get_row().table_id());
+                }
+                let mut partition_to_table = HashMap::new();
+                let mut table_partitions = HashMap::<u64, i64>::new();
+                for p in PartitionRocksTable::new(db_ref.clone()).scan_all_rows()? {
+                    let p = p?;
+                    if !p.get_row().is_active() {
+                        continue;
+                    }
+                    if let Some(table_id) = index_to_table.get(&p.get_row().get_index_id()) {
+                        partition_to_table.insert(p.get_id(), *table_id);
+                        *table_partitions.entry(*table_id).or_default() += 1;
+                    }
+                }
+                let mut table_chunks = HashMap::<u64, i64>::new();
+                for c in ChunkRocksTable::new(db_ref).scan_all_rows()? {
+                    let c = c?;
+                    if !c.get_row().active() {
+                        continue;
+                    }
+                    if let Some(table_id) = partition_to_table.get(&c.get_row().get_partition_id())
+                    {
+                        *table_chunks.entry(*table_id).or_default() += 1;
+                    }
+                }
+                Ok((jobs, table_partitions, table_chunks))
+            })
+            .await?;
+
+        app_metrics::METASTORE_JOBS.report_all(
+            jobs.into_iter()
+                .map(|((job_type, status), count)| {
+                    (vec![job_type.to_string(), status.to_string()], count)
+                })
+                .collect(),
+        );
+        for (metric, counts) in [
+            (&app_metrics::TABLE_PARTITIONS, table_partitions),
+            (&app_metrics::TABLE_CHUNKS, table_chunks),
+        ] {
+            metric.report_all(
+                tables
+                    .iter()
+                    .map(|t| {
+                        (
+                            vec![
+                                t.schema.get_row().get_name().to_string(),
+                                t.table.get_row().get_table_name().to_string(),
+                            ],
+                            counts.get(&t.table.get_id()).cloned().unwrap_or(0),
+                        )
+                    })
+                    .collect(),
+            );
+        }
+        Ok(())
     }
 
     pub async fn run_upload(&self) -> Result<(), CubeError> {
@@ -2427,11 +2766,32 @@ impl RocksMetaStore {
             RocksMetaStore::prepare_checkpoint(db, &check_point_time).await?
         };
 
-        RocksMetaStore::upload_checkpoint(remote_fs, remote_path, checkpoint_path).await?;
+        RocksMetaStore::upload_checkpoint(
+            remote_fs,
+            remote_path,
+            checkpoint_path,
+            self.config.meta_store_snapshot_retention(),
+            self.config.meta_store_snapshots_keep(),
+        )
+        .await?;
         self.write_completed_notify.notify_waiters();
         Ok(())
     }
 
+    async fn check_backups_enabled(&self) -> Result<(), CubeError> {
+        if !self.config.upload_to_remote() {
+            return Err(CubeError::user(
+                "Metastore backups are not available when uploads are disabled".to_string(),
+            ));
+        }
+        if RocksMetaStore::is_restored(self.db.path()).await {
+            return Err(CubeError::user(
+                "Metastore was restored from a backup, restart Cube Store first".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     async fn last_upload_seq(&self) -> u64 {
         *self.last_upload_seq.read().await
     }
@@ -2444,6 +2804,8 @@ impl RocksMetaStore {
         remote_fs: Arc<dyn RemoteFs>,
         remote_path: String,
         checkpoint_path: PathBuf,
+        retention_secs: u64,
+        keep_snapshots: u64,
     ) -> Result<(), CubeError> {
         let mut dir = fs::read_dir(checkpoint_path).await?;
 
@@ -2472,27 +2834,16 @@ impl RocksMetaStore {
         }
 
         let existing_metastore_files = remote_fs.list("metastore-").await?;
-        let to_delete = existing_metastore_files
-            .into_iter()
-            .filter_map(|existing| {
-                let path = existing
-                    .split("/")
-                    .nth(0)
-                    .map(|p| u128::from_str(&p.replace("metastore-", "").replace("-logs", "")));
-                if let Some(Ok(millis)) = path {
-                    if SystemTime::now()
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis()
-                        - millis
-                        > 3 * 60 * 1000
-                    {
-                        return Some(existing);
-                    }
-                }
-                None
-            })
-            .collect::<Vec<_>>();
+        let now_millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let to_delete = RocksMetaStore::expired_snapshot_files(
+            existing_metastore_files,
+            now_millis,
+            retention_secs,
+            keep_snapshots,
+        );
         for v in join_all(
             to_delete
                 .iter()
@@ -2524,6 +2875,42 @@ impl RocksMetaStore {
         Ok(())
     }
 
+    /// Snapshot and log files older than `retention_secs`, except the ones belonging to the
+    /// newest `keep_snapshots` snapshots.
+    fn expired_snapshot_files(
+        files: Vec<String>,
+        now_millis: u128,
+        retention_secs: u64,
+        keep_snapshots: u64,
+    ) -> Vec<String> {
+        let snapshot_millis = |file: &str| {
+            file.split("/").nth(0).and_then(|p| {
+                u128::from_str(&p.replace("metastore-", "").replace("-logs", "")).ok()
+            })
+        };
+        let mut snapshots = files
+            .iter()
+            .filter(|f| !f.split("/").nth(0).unwrap_or("").ends_with("-logs"))
+            .filter_map(|f| snapshot_millis(f.as_str()))
+            .collect::<Vec<_>>();
+        snapshots.sort_unstable_by(|a, b| b.cmp(a));
+        snapshots.dedup();
+        let kept = snapshots
+            .into_iter()
+            .take(keep_snapshots as usize)
+            .collect::<HashSet<_>>();
+        files
+            .into_iter()
+            .filter(|f| match snapshot_millis(f.as_str()) {
+                Some(millis) => {
+                    !kept.contains(&millis)
+                        && now_millis.saturating_sub(millis) > retention_secs as u128 * 1000
+                }
+                None => false,
+            })
+            .collect()
+    }
+
     async fn prepare_checkpoint(
         db: Arc<DB>,
         checkpoint_time: &SystemTime,
@@ -2561,8 +2948,10 @@ impl RocksMetaStore {
         let db_to_send = self.db.clone();
 
         let rw_loop_sender = self.rw_loop_tx.clone();
+        let rw_loop_queue_size = self.rw_loop_queue_size.clone();
         let (tx, rx) = oneshot::channel::<Result<R, CubeError>>();
         cube_ext::spawn_blocking(move || {
+            rw_loop_queue_size.fetch_add(1, Ordering::Relaxed);
             let res = rw_loop_sender.send(Box::new(move || {
                 let db_span = warn_long("metastore read operation", Duration::from_millis(100));
 
@@ -2584,6 +2973,7 @@ impl RocksMetaStore {
                 Ok(())
             }));
             if let Err(e) = res {
+                rw_loop_queue_size.fetch_sub(1, Ordering::Relaxed);
                 log::error!("Error during read write loop send: {}", e);
             }
         })
@@ -2676,6 +3066,27 @@ impl RocksMetaStore {
 }
 
 impl RocksMetaStore {
+    /// Partitions of partitioned indexes are shared between tables, so their columns can't be altered.
+    fn get_alterable_indexes(
+        rocks_index: &IndexRocksTable,
+        table: &IdRow<Table>,
+    ) -> Result<Vec<IdRow<Index>>, CubeError> {
+        let indexes = rocks_index.get_rows_by_index(
+            &IndexIndexKey::TableId(table.get_id()),
+            &IndexRocksIndex::TableID,
+        )?;
+        if indexes
+            .iter()
+            .any(|i| i.get_row().multi_index_id().is_some())
+        {
+            return Err(CubeError::user(format!(
+                "Columns of table '{}' can't be altered as it's a part of partitioned index",
+                table.get_row().get_table_name()
+            )));
+        }
+        Ok(indexes)
+    }
+
     fn add_index(
         batch_pipe: &mut BatchPipe,
         rocks_index: &IndexRocksTable,
@@ -2990,6 +3401,26 @@ impl RocksMetaStore {
         }
         return Ok((activated_row_count, partitions));
     }
+
+    fn activate_table_chunks_impl(
+        db: DbTableRef,
+        pipe: &mut BatchPipe,
+        uploaded_chunk_ids: &[(u64, Option<u64>)],
+    ) -> Result<(), CubeError> {
+        let (_, partition_rows) = Self::activate_chunks_impl(db.clone(), pipe, uploaded_chunk_ids)?;
+        let partition = PartitionRocksTable::new(db.clone());
+        let mut mpartition_rows = HashMap::new();
+        for (p, rows) in partition_rows {
+            if let Some(mp) = partition.get_row_or_not_found(p)?.row.multi_partition_id {
+                *mpartition_rows.entry(mp).or_default() += rows;
+            }
+        }
+        let mpartition = MultiPartitionRocksTable::new(db.clone());
+        for (mp, rows) in mpartition_rows {
+            mpartition.update_with_fn(mp, |p| p.add_rows(rows), pipe)?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -3177,6 +3608,10 @@ impl MetaStore for RocksMetaStore {
         unique_key_column_names: Option<Vec<String>>,
         aggregates: Option<Vec<(String, String)>>,
         partition_split_threshold: Option<u64>,
+        ttl: Option<(String, u64)>,
+        compression: Option<ParquetCompression>,
+        dictionary: Option<bool>,
+        bloom_filter_columns: Option<Vec<String>>,
     ) -> Result<IdRow<Table>, CubeError> {
         self.write_operation(move |db_ref, batch_pipe| {
             batch_pipe.invalidate_tables_cache();
@@ -3257,6 +3692,42 @@ impl MetaStore for RocksMetaStore {
             } else {
                 vec![]
             };
+            let ttl = ttl
+                .map(|(ttl_column, duration_secs)| {
+                    let column = columns
+                        .iter()
+                        .find(|c| c.name == ttl_column)
+                        .ok_or_else(|| {
+                            CubeError::user(format!(
+                                "TTL column {} not found among column definitions {:?}",
+                                ttl_column, columns
+                            ))
+                        })?;
+                    if column.column_type != ColumnType::Timestamp {
+                        return Err(CubeError::user(format!(
+                            "TTL column {} should be a timestamp but {} found",
+                            ttl_column, column.column_type
+                        )));
+                    }
+                    Ok(TableTtl::new(column.column_index as u64, duration_secs))
+                })
+                .transpose()?;
+            if let Some(bloom_filter_columns) = &bloom_filter_columns {
+                for name in bloom_filter_columns {
+                    let column = columns.iter().find(|c| &c.name == name).ok_or_else(|| {
+                        CubeError::user(format!(
+                            "Bloom filter column {} not found among column definitions {:?}",
+                            name, columns
+                        ))
+                    })?;
+                    if !supports_bloom_filter(&column.column_type) {
+                        return Err(CubeError::user(format!(
+                            "Bloom filters are not supported for column {} of type {}",
+                            name, column.column_type
+                        )));
+                    }
+                }
+            }
             let table = Table::new(
                 table_name,
                 schema_id.get_id(),
@@ -3268,6 +3739,10 @@ impl MetaStore for RocksMetaStore {
                 aggregate_column_indices,
                 seq_column_index,
                 partition_split_threshold,
+                ttl,
+                compression,
+                dictionary,
+                bloom_filter_columns,
             );
             let table_id = rocks_table.insert(table, batch_pipe)?;
             for index_def in indexes.into_iter() {
@@ -3309,7 +3784,7 @@ impl MetaStore for RocksMetaStore {
                 .unwrap_or(table_columns.clone())
                 .iter()
                 .filter_map(|c| match c.get_column_type() {
-                    ColumnType::Bytes => None,
+                    ColumnType::Bytes | ColumnType::List(_) | ColumnType::TDigest => None,
                     _ => {
                         if seq_column_index.is_none()
                             || seq_column_index.is_some()
@@ -3352,46 +3827,97 @@ impl MetaStore for RocksMetaStore {
         .await
     }
 
-    async fn update_location_download_size(
+    async fn update_table_state(
         &self,
         id: u64,
-        location: String,
-        download_size: u64,
+        state: TableState,
     ) -> Result<IdRow<Table>, CubeError> {
         self.write_operation(move |db_ref, batch_pipe| {
             batch_pipe.invalidate_tables_cache();
             let rocks_table = TableRocksTable::new(db_ref.clone());
-            Ok(rocks_table.update_with_res_fn(
-                id,
-                |r| r.update_location_download_size(&location, download_size),
-                batch_pipe,
-            )?)
+            Ok(rocks_table.update_with_res_fn(id, |r| r.update_state(state), batch_pipe)?)
         })
         .await
     }
 
-    async fn get_table(
-        &self,
-        schema_name: String,
-        table_name: String,
-    ) -> Result<IdRow<Table>, CubeError> {
-        self.read_operation(move |db_ref| get_table_impl(db_ref, schema_name, table_name))
-            .await
-    }
-
-    async fn get_table_by_id(&self, table_id: u64) -> Result<IdRow<Table>, CubeError> {
-        self.read_operation(move |db_ref| {
-            TableRocksTable::new(db_ref.clone()).get_row_or_not_found(table_id)
+    async fn add_table_column(&self, id: u64, column: Column) -> Result<IdRow<Table>, CubeError> {
+        self.write_operation(move |db_ref, batch_pipe| {
+            batch_pipe.invalidate_tables_cache();
+            let rocks_table = TableRocksTable::new(db_ref.clone());
+            let rocks_index = IndexRocksTable::new(db_ref.clone());
+            let table = rocks_table.update_with_res_fn(id, |t| t.add_column(column), batch_pipe)?;
+            let column = table.get_row().get_columns().last().unwrap();
+            for index in Self::get_alterable_indexes(&rocks_index, &table)? {
+                rocks_index.update_with_fn(index.get_id(), |i| i.add_column(column), batch_pipe)?;
+            }
+            Ok(table)
         })
         .await
     }
 
-    async fn get_tables(&self) -> Result<Vec<IdRow<Table>>, CubeError> {
-        self.read_operation_out_of_queue(|db_ref| TableRocksTable::new(db_ref).all_rows())
-            .await
-    }
-
-    async fn get_tables_with_path(
+    async fn drop_table_column(
+        &self,
+        id: u64,
+        column_name: String,
+    ) -> Result<IdRow<Table>, CubeError> {
+        self.write_operation(move |db_ref, batch_pipe| {
+            batch_pipe.invalidate_tables_cache();
+            let rocks_table = TableRocksTable::new(db_ref.clone());
+            let rocks_index = IndexRocksTable::new(db_ref.clone());
+            let table =
+                rocks_table.update_with_res_fn(id, |t| t.drop_column(&column_name), batch_pipe)?;
+            for index in Self::get_alterable_indexes(&rocks_index, &table)? {
+                rocks_index.update_with_res_fn(
+                    index.get_id(),
+                    |i| i.drop_column(&column_name),
+                    batch_pipe,
+                )?;
+            }
+            Ok(table)
+        })
+        .await
+    }
+
+    async fn update_location_download_size(
+        &self,
+        id: u64,
+        location: String,
+        download_size: u64,
+    ) -> Result<IdRow<Table>, CubeError> {
+        self.write_operation(move |db_ref, batch_pipe| {
+            batch_pipe.invalidate_tables_cache();
+            let rocks_table = TableRocksTable::new(db_ref.clone());
+            Ok(rocks_table.update_with_res_fn(
+                id,
+                |r| r.update_location_download_size(&location, download_size),
+                batch_pipe,
+            )?)
+        })
+        .await
+    }
+
+    async fn get_table(
+        &self,
+        schema_name: String,
+        table_name: String,
+    ) -> Result<IdRow<Table>, CubeError> {
+        self.read_operation(move |db_ref| get_table_impl(db_ref, schema_name, table_name))
+            .await
+    }
+
+    async fn get_table_by_id(&self, table_id: u64) -> Result<IdRow<Table>, CubeError> {
+        self.read_operation(move |db_ref| {
+            TableRocksTable::new(db_ref.clone()).get_row_or_not_found(table_id)
+        })
+        .await
+    }
+
+    async fn get_tables(&self) -> Result<Vec<IdRow<Table>>, CubeError> {
+        self.read_operation_out_of_queue(|db_ref| TableRocksTable::new(db_ref).all_rows())
+            .await
+    }
+
+    async fn get_tables_with_path(
         &self,
         include_non_ready: bool,
     ) -> Result<Arc<Vec<TablePath>>, CubeError> {
@@ -3564,6 +4090,7 @@ impl MetaStore for RocksMetaStore {
         old_chunk_ids: Vec<u64>,
         new_chunk: u64,
         new_chunk_file_size: u64,
+        new_chunk_column_stats: Option<Vec<ColumnStats>>,
     ) -> Result<bool, CubeError> {
         self.write_operation(move |db, pipe| {
             let p = PartitionRocksTable::new(db.clone()).get_row_or_not_found(partition_id)?;
@@ -3577,6 +4104,7 @@ impl MetaStore for RocksMetaStore {
             RocksMetaStore::swap_chunks_impl(
                 old_chunk_ids,
                 vec![(new_chunk, Some(new_chunk_file_size))],
+                move |c| c.set_column_stats(new_chunk_column_stats.clone()),
                 db,
                 pipe,
             )?;
@@ -3618,11 +4146,131 @@ impl MetaStore for RocksMetaStore {
                     )))
                 },
                 |_| panic!("error from current partition must propagate before this call"),
+                true,
             )
         })
         .await
     }
 
+    async fn swap_rewritten_partitions(
+        &self,
+        rewrites: Vec<PartitionRewrite>,
+        new_chunks: Vec<(u64, Option<u64>)>,
+    ) -> Result<(), CubeError> {
+        trace!(
+            "Swapping rewritten partitions: deactivating ({}), deactivating chunks ({}), activating ({}), activating chunks ({})",
+            rewrites
+                .iter()
+                .filter(|r| !r.new_active.is_empty())
+                .map(|r| r.current_active.0.id)
+                .join(", "),
+            rewrites
+                .iter()
+                .flat_map(|r| r.current_active.1.iter())
+                .map(|c| c.id)
+                .join(", "),
+            rewrites
+                .iter()
+                .flat_map(|r| r.new_active.iter())
+                .map(|(p, _)| p.id)
+                .join(", "),
+            new_chunks.iter().map(|(id, _)| id).join(", ")
+        );
+        self.write_operation(move |db, pipe| {
+            let partitions = PartitionRocksTable::new(db.clone());
+            let indexes = IndexRocksTable::new(db.clone());
+            // Partition new chunks go to, by the partition they were written for.
+            let mut chunk_partitions = HashMap::new();
+            let mut table_ids = HashSet::new();
+            for rewrite in rewrites {
+                let PartitionRewrite {
+                    current_active,
+                    new_active,
+                    mut new_active_min_max,
+                } = rewrite;
+                let current = &current_active.0;
+                table_ids.insert(
+                    indexes
+                        .get_row_or_not_found(current.get_row().get_index_id())?
+                        .get_row()
+                        .table_id(),
+                );
+                if new_active.is_empty() {
+                    let partition = partitions.get_row_or_not_found(current.get_id())?;
+                    if !partition.get_row().is_active() {
+                        return Err(CubeError::internal(format!(
+                            "Current partition is not active: {:?}",
+                            partition.get_row()
+                        )));
+                    }
+                    chunk_partitions.insert(current.get_id(), current.get_id());
+                    continue;
+                }
+                // Chunks of the partition are moved to the new one if its range doesn't change.
+                let chunk_partition = match new_active.as_slice() {
+                    [(new, _)]
+                        if current.get_row().get_min_val() == new.get_row().get_min_val()
+                            && current.get_row().get_max_val() == new.get_row().get_max_val() =>
+                    {
+                        new.get_id()
+                    }
+                    _ => current.get_id(),
+                };
+                chunk_partitions.insert(current.get_id(), chunk_partition);
+                let current_active = vec![current_active];
+                swap_active_partitions_impl(
+                    db.clone(),
+                    pipe,
+                    &current_active,
+                    &new_active,
+                    move |i, p| {
+                        let (rows, (min, max)) = take(&mut new_active_min_max[i]);
+                        p.update_min_max_and_row_count(min, max, rows)
+                    },
+                    |_| {
+                        Err(CubeError::internal(format!(
+                            "Current partition is not found during swap active: {}",
+                            current_active[0].0.id
+                        )))
+                    },
+                    |_| panic!("error from current partition must propagate before this call"),
+                    false,
+                )?;
+            }
+            if new_chunks.is_empty() {
+                return Ok(());
+            }
+            // New chunks are written once here as their rows read from the db are stale.
+            let chunks = ChunkRocksTable::new(db.clone());
+            for (id, file_size) in new_chunks.iter() {
+                let chunk = chunks.get_row_or_not_found(*id)?;
+                let partition_id = match chunk_partitions.get(&chunk.get_row().get_partition_id()) {
+                    Some(partition_id) if !chunk.get_row().active() => *partition_id,
+                    _ => {
+                        return Err(CubeError::internal(format!(
+                            "Chunk {:?} can't be activated with rewritten partitions",
+                            chunk
+                        )))
+                    }
+                };
+                let mut updated = chunk
+                    .get_row()
+                    .set_uploaded(true)
+                    .set_partition_id(partition_id);
+                if let Some(file_size) = file_size {
+                    updated = updated.set_file_size(*file_size)?;
+                }
+                chunks.update(*id, updated, chunk.get_row(), pipe)?;
+            }
+            let tables = TableRocksTable::new(db);
+            for table_id in table_ids {
+                tables.update_with_fn(table_id, |t| t.update_has_data(true), pipe)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
     async fn delete_partition(&self, partition_id: u64) -> Result<IdRow<Partition>, CubeError> {
         self.write_operation(move |db_ref, batch_pipe| {
             let partitions_table = PartitionRocksTable::new(db_ref.clone());
@@ -3681,6 +4329,31 @@ impl MetaStore for RocksMetaStore {
         .await
     }
 
+    async fn set_partition_column_stats(
+        &self,
+        partition_id: u64,
+        column_stats: Option<Vec<ColumnStats>>,
+    ) -> Result<(), CubeError> {
+        self.write_operation(move |db_ref, batch_pipe| {
+            let table = PartitionRocksTable::new(db_ref);
+            let partition = table.get_row_or_not_found(partition_id)?;
+            if partition.get_row().is_active() {
+                return Err(CubeError::internal(format!(
+                    "Can't change column stats of active partition: {:?}",
+                    partition
+                )));
+            }
+            table.update(
+                partition_id,
+                partition.row.set_column_stats(column_stats),
+                &partition.row,
+                batch_pipe,
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
     async fn delete_middle_man_partition(
         &self,
         partition_id: u64,
@@ -4201,11 +4874,15 @@ impl MetaStore for RocksMetaStore {
         partition_id: u64,
         row_count: usize,
         in_memory: bool,
+        compression: Option<ParquetCompression>,
+        column_stats: Option<Vec<ColumnStats>>,
     ) -> Result<IdRow<Chunk>, CubeError> {
         self.write_operation(move |db_ref, batch_pipe| {
             let rocks_chunk = ChunkRocksTable::new(db_ref.clone());
 
-            let chunk = Chunk::new(partition_id, row_count, in_memory);
+            let chunk = Chunk::new(partition_id, row_count, in_memory)
+                .set_compression(compression)
+                .set_column_stats(column_stats);
             let id_row = rocks_chunk.insert(chunk, batch_pipe)?;
 
             Ok(id_row)
@@ -4318,20 +4995,44 @@ impl MetaStore for RocksMetaStore {
                 |t| t.update_has_data(true),
                 pipe,
             )?;
-            let (_, partition_rows) =
-                Self::activate_chunks_impl(db.clone(), pipe, &uploaded_chunk_ids)?;
-            let partition = PartitionRocksTable::new(db.clone());
-            let mut mpartition_rows = HashMap::new();
-            for (p, rows) in partition_rows {
-                if let Some(mp) = partition.get_row_or_not_found(p)?.row.multi_partition_id {
-                    *mpartition_rows.entry(mp).or_default() += rows;
-                }
-            }
-            let mpartition = MultiPartitionRocksTable::new(db.clone());
-            for (mp, rows) in mpartition_rows {
-                mpartition.update_with_fn(mp, |p| p.add_rows(rows), pipe)?;
-            }
-            Ok(())
+            Self::activate_table_chunks_impl(db, pipe, &uploaded_chunk_ids)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn activate_stream_chunks(
+        &self,
+        table_id: u64,
+        location: String,
+        uploaded_chunk_ids: Vec<(u64, Option<u64>)>,
+        stream_offsets: StreamOffsets,
+    ) -> Result<(), CubeError> {
+        trace!(
+            "Activating stream chunks ({}) for {} at {:?}",
+            uploaded_chunk_ids.iter().map(|(id, _)| id).join(", "),
+            location,
+            stream_offsets
+        );
+        self.write_operation(move |db, pipe| {
+            TableRocksTable::new(db.clone()).update_with_res_fn(
+                table_id,
+                |t| {
+                    // Chunks received after the stream was stopped are discarded along with
+                    // their position, so resumed stream reads them again.
+                    if t.state() != TableState::Active {
+                        return Err(CubeError::user(format!(
+                            "Can't activate stream chunks for {} table '{}'",
+                            t.state().as_str(),
+                            t.get_table_name()
+                        )));
+                    }
+                    t.update_has_data(true)
+                        .update_stream_offsets(&location, stream_offsets)
+                },
+                pipe,
+            )?;
+            Self::activate_table_chunks_impl(db, pipe, &uploaded_chunk_ids)
         })
         .await?;
         Ok(())
@@ -4352,6 +5053,7 @@ impl MetaStore for RocksMetaStore {
             RocksMetaStore::swap_chunks_impl(
                 deactivate_ids,
                 uploaded_ids_and_sizes,
+                |c| c,
                 db_ref,
                 batch_pipe,
             )
@@ -4695,6 +5397,27 @@ impl MetaStore for RocksMetaStore {
         .await
     }
 
+    async fn create_backup(&self) -> Result<Vec<MetaStoreBackup>, CubeError> {
+        self.check_backups_enabled().await?;
+        self.upload_check_point().await?;
+        RocksMetaStore::list_remote_backups(&self.remote_fs).await
+    }
+
+    async fn restore_backup(&self, point: DateTime<Utc>) -> Result<MetaStoreBackup, CubeError> {
+        self.check_backups_enabled().await?;
+        let backup =
+            RocksMetaStore::restore_remote_backup(&self.remote_fs, self.config.clone(), point)
+                .await?;
+        // Uploads of this metastore would overwrite the restored snapshot.
+        self.upload_loop.stop();
+        RocksMetaStore::mark_restored(self.db.path()).await?;
+        log::warn!(
+            "Metastore was restored to {}, restart Cube Store to start using it",
+            point
+        );
+        Ok(backup)
+    }
+
     async fn get_multi_partition(&self, id: u64) -> Result<IdRow<MultiPartition>, CubeError> {
         self.read_operation(move |db| MultiPartitionRocksTable::new(db).get_row_or_not_found(id))
             .await
@@ -4841,7 +5564,8 @@ impl MetaStore for RocksMetaStore {
                             "{} <= {}", new_partition_rows[new_i], new_multi_partition_rows[mi]);
                     new_multi_partition_rows[mi] -= new_partition_rows[new_i];
                     Ok(())
-                }
+                },
+                true,
             )?;
 
             let total_new_rows = new_multi_partition_rows.iter().sum();
@@ -5055,6 +5779,7 @@ fn swap_active_partitions_impl(
     mut update_new_partition_stats: impl FnMut(/*index*/ usize, &Partition) -> Partition,
     mut on_dropped_current_partition: impl FnMut(/*index*/ usize) -> Result<(), CubeError>,
     mut on_dropped_new_partition: impl FnMut(/*index*/ usize) -> Result<(), CubeError>,
+    check_row_count: bool,
 ) -> Result<(), CubeError> {
     let index_table = IndexRocksTable::new(db_ref.clone());
     let table_table = TableRocksTable::new(db_ref.clone());
@@ -5185,7 +5910,10 @@ fn swap_active_partitions_impl(
         }
     }
 
-    if !skip_row_count_sanity_check && activated_row_count != deactivated_row_count {
+    if check_row_count
+        && !skip_row_count_sanity_check
+        && activated_row_count != deactivated_row_count
+    {
         return Err(CubeError::internal(format!(
             "Deactivated row count ({}) doesn't match activated row count ({}) during swap of partition ({}) and ({}) chunks to new partitions ({})",
             deactivated_row_count,
@@ -5205,6 +5933,8 @@ mod tests {
     use super::*;
     use crate::config::Config;
     use crate::remotefs::LocalDirRemoteFs;
+    use crate::util::metrics::{write_prometheus, PrometheusWriter};
+    use chrono::TimeZone;
     use futures_timer::Delay;
     use std::thread::sleep;
     use std::time::Duration;
@@ -5218,6 +5948,36 @@ mod tests {
         assert_eq!(format_table_value!(s, name, String), "foo");
     }
 
+    #[test]
+    fn expired_snapshot_files() {
+        let day = 24 * 60 * 60 * 1000;
+        let files = vec![
+            "metastore-current".to_string(),
+            format!("metastore-{}/CURRENT", day),
+            format!("metastore-{}-logs/1-0.flex", day),
+            format!("metastore-{}/CURRENT", 2 * day),
+            format!("metastore-{}/CURRENT", 9 * day),
+            format!("metastore-{}/CURRENT", 10 * day),
+        ];
+        assert_eq!(
+            RocksMetaStore::expired_snapshot_files(files.clone(), 10 * day, 7 * 24 * 60 * 60, 2),
+            vec![
+                format!("metastore-{}/CURRENT", day),
+                format!("metastore-{}-logs/1-0.flex", day),
+                format!("metastore-{}/CURRENT", 2 * day),
+            ]
+        );
+        // The newest snapshots are kept even when they are past retention.
+        assert_eq!(
+            RocksMetaStore::expired_snapshot_files(files, 20 * day, 7 * 24 * 60 * 60, 2),
+            vec![
+                format!("metastore-{}/CURRENT", day),
+                format!("metastore-{}-logs/1-0.flex", day),
+                format!("metastore-{}/CURRENT", 2 * day),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn schema_test() {
         let config = Config::test("schema_test");
@@ -5445,6 +6205,10 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
+                None,
+                None,
+                None,
             )
             .await
             .unwrap();
@@ -5461,6 +6225,10 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
+                None,
+                None,
+                None,
             )
             .await
             .unwrap();
@@ -5567,6 +6335,10 @@ mod tests {
                     None,
                     None,
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .unwrap();
@@ -5585,6 +6357,10 @@ mod tests {
                     None,
                     None,
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .is_err());
@@ -5614,6 +6390,144 @@ mod tests {
         let _ = fs::remove_dir_all(remote_store_path.clone());
     }
 
+    #[tokio::test]
+    async fn report_metrics_test() {
+        let config = Config::test("report_metrics_test");
+        let store_path = env::current_dir()
+            .unwrap()
+            .join("test-report-metrics-local");
+        let remote_store_path = env::current_dir()
+            .unwrap()
+            .join("test-report-metrics-remote");
+        let _ = fs::remove_dir_all(store_path.clone());
+        let _ = fs::remove_dir_all(remote_store_path.clone());
+        let remote_fs = LocalDirRemoteFs::new(Some(remote_store_path.clone()), store_path.clone());
+        {
+            let meta_store = RocksMetaStore::new(
+                store_path.clone().join("metastore").as_path(),
+                remote_fs,
+                config.config_obj(),
+            );
+
+            meta_store
+                .create_schema("metrics_foo".to_string(), false)
+                .await
+                .unwrap();
+            let table = meta_store
+                .create_table(
+                    "metrics_foo".to_string(),
+                    "boo".to_string(),
+                    vec![Column::new("col1".to_string(), ColumnType::Int, 0)],
+                    None,
+                    None,
+                    vec![],
+                    true,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+            meta_store
+                .add_job(Job::new(
+                    RowKey::Table(TableId::Tables, table.get_id()),
+                    JobType::TableImport,
+                    "".to_string(),
+                ))
+                .await
+                .unwrap();
+
+            meta_store.report_metrics().await.unwrap();
+
+            let mut w = PrometheusWriter::new();
+            write_prometheus(&mut w);
+            let text = w.finish();
+            assert!(
+                text.contains("cs_table_partitions{schema=\"metrics_foo\",table=\"boo\"} 1\n"),
+                "{}",
+                text
+            );
+            assert!(
+                text.contains("cs_table_chunks{schema=\"metrics_foo\",table=\"boo\"} 0\n"),
+                "{}",
+                text
+            );
+            assert!(
+                text.contains("# TYPE cs_metastore_jobs gauge\n"),
+                "{}",
+                text
+            );
+            assert!(
+                text.contains("# TYPE cs_metastore_queue_size gauge\n"),
+                "{}",
+                text
+            );
+        }
+        let _ = fs::remove_dir_all(store_path.clone());
+        let _ = fs::remove_dir_all(remote_store_path.clone());
+    }
+
+    #[tokio::test]
+    async fn stream_offsets_test() {
+        let (_, meta_store) = RocksMetaStore::prepare_test_metastore("stream_offsets_test");
+        {
+            meta_store
+                .create_schema("foo".to_string(), false)
+                .await
+                .unwrap();
+            let location = "stream://source/topic".to_string();
+            let table = meta_store
+                .create_table(
+                    "foo".to_string(),
+                    "boo".to_string(),
+                    vec![Column::new("col1".to_string(), ColumnType::Int, 0)],
+                    Some(vec![location.clone()]),
+                    Some(ImportFormat::CSV),
+                    vec![],
+                    true,
+                    Some(vec!["col1".to_string()]),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+            assert_eq!(table.get_row().stream_offsets(&location), None);
+
+            let mut offsets = StreamOffsets::new();
+            offsets.set(0, 10);
+            offsets.set(1, 5);
+            meta_store
+                .activate_stream_chunks(table.get_id(), location.clone(), vec![], offsets.clone())
+                .await
+                .unwrap();
+            let table = meta_store.get_table_by_id(table.get_id()).await.unwrap();
+            assert_eq!(table.get_row().stream_offsets(&location), Some(&offsets));
+            assert_eq!(
+                table.get_row().stream_offsets(&location).unwrap().get(1),
+                Some(5)
+            );
+
+            assert!(meta_store
+                .activate_stream_chunks(
+                    table.get_id(),
+                    "stream://source/other".to_string(),
+                    vec![],
+                    offsets
+                )
+                .await
+                .is_err());
+        }
+        RocksMetaStore::cleanup_test_metastore("stream_offsets_test");
+    }
+
     #[tokio::test]
     async fn table_with_aggregate_index_test() {
         let config = Config::test("table_with_aggregate_index_test");
@@ -5666,6 +6580,10 @@ mod tests {
                         ("max".to_string(), "aggr_col1".to_string()),
                     ]),
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .unwrap();
@@ -5732,6 +6650,10 @@ mod tests {
                         ("max".to_string(), "col1".to_string()),
                     ]),
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .is_err());
@@ -5748,6 +6670,10 @@ mod tests {
                     Some(vec!["col1".to_string()]),
                     None,
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .is_err());
@@ -5767,6 +6693,10 @@ mod tests {
                         ("max".to_string(), "aggr_col1".to_string()),
                     ]),
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .is_err());
@@ -5979,6 +6909,85 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn backup_and_restore() {
+        let point;
+        {
+            let config = Config::test("backup_and_restore");
+
+            let _ = fs::remove_dir_all(config.local_dir());
+            let _ = fs::remove_dir_all(config.remote_dir());
+
+            let services = config.configure().await;
+            services
+                .meta_store
+                .create_schema("foo".to_string(), false)
+                .await
+                .unwrap();
+            let backups = services.meta_store.create_backup().await.unwrap();
+            assert_eq!(backups.len(), 1);
+            assert!(backups[0].current());
+
+            Delay::new(Duration::from_millis(100)).await;
+            point = Utc::now();
+            Delay::new(Duration::from_millis(100)).await;
+
+            services
+                .meta_store
+                .create_schema("bar".to_string(), false)
+                .await
+                .unwrap();
+            services
+                .rocks_meta_store
+                .as_ref()
+                .unwrap()
+                .run_upload()
+                .await
+                .unwrap();
+
+            let restored = services.meta_store.restore_backup(point).await.unwrap();
+            assert!(restored.current());
+            assert!(restored.created_at() > &point);
+            // Running metastore is outdated until restart.
+            services.meta_store.create_backup().await.unwrap_err();
+
+            services.stop_processing_loops().await.unwrap();
+            Delay::new(Duration::from_millis(1000)).await; // TODO logger init conflict
+        }
+
+        {
+            let config = Config::test("backup_and_restore");
+
+            let services = config.configure().await;
+            services
+                .meta_store
+                .get_schema("foo".to_string())
+                .await
+                .unwrap();
+            services
+                .meta_store
+                .get_schema("bar".to_string())
+                .await
+                .unwrap_err();
+
+            let backups = services.meta_store.create_backup().await.unwrap();
+            assert_eq!(backups.len(), 3);
+            assert!(backups.iter().filter(|b| b.current()).count() == 1);
+            assert!(backups[2].current());
+
+            let err = services
+                .meta_store
+                .restore_backup(Utc.timestamp_millis(0))
+                .await
+                .unwrap_err();
+            assert!(err.message.contains("No metastore backups found"));
+
+            services.stop_processing_loops().await.unwrap();
+            fs::remove_dir_all(config.local_dir()).unwrap();
+            fs::remove_dir_all(config.remote_dir()).unwrap();
+        }
+    }
+
     #[tokio::test]
     async fn swap_chunks() {
         let config = Config::test("swap_chunks");
@@ -6010,6 +7019,10 @@ mod tests {
                     None,
                     None,
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .unwrap();
@@ -6019,27 +7032,27 @@ mod tests {
 
             let mut source_ids: Vec<u64> = Vec::new();
             let ch = meta_store
-                .create_chunk(partition.get_id(), 10, true)
+                .create_chunk(partition.get_id(), 10, true, None, None)
                 .await
                 .unwrap();
             source_ids.push(ch.get_id());
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
 
             let ch = meta_store
-                .create_chunk(partition.get_id(), 16, true)
+                .create_chunk(partition.get_id(), 16, true, None, None)
                 .await
                 .unwrap();
             source_ids.push(ch.get_id());
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
 
             let dest_chunk = meta_store
-                .create_chunk(partition.get_id(), 26, true)
+                .create_chunk(partition.get_id(), 26, true, None, None)
                 .await
                 .unwrap();
             assert_eq!(dest_chunk.get_row().active(), false);
 
             let dest_chunk2 = meta_store
-                .create_chunk(partition.get_id(), 26, true)
+                .create_chunk(partition.get_id(), 26, true, None, None)
                 .await
                 .unwrap();
             assert_eq!(dest_chunk2.get_row().active(), false);
@@ -6065,14 +7078,14 @@ mod tests {
             //============= trying to use already active chunk as destination of swap ==============
             let mut source_ids: Vec<u64> = Vec::new();
             let ch = meta_store
-                .create_chunk(partition.get_id(), 10, true)
+                .create_chunk(partition.get_id(), 10, true, None, None)
                 .await
                 .unwrap();
             source_ids.push(ch.get_id());
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
 
             let ch = meta_store
-                .create_chunk(partition.get_id(), 16, true)
+                .create_chunk(partition.get_id(), 16, true, None, None)
                 .await
                 .unwrap();
             source_ids.push(ch.get_id());
@@ -6131,6 +7144,10 @@ mod tests {
                     None,
                     None,
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .unwrap();
@@ -6138,14 +7155,14 @@ mod tests {
 
             let mut source_chunks: Vec<IdRow<Chunk>> = Vec::new();
             let ch = meta_store
-                .create_chunk(partition.get_id(), 10, true)
+                .create_chunk(partition.get_id(), 10, true, None, None)
                 .await
                 .unwrap();
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
             source_chunks.push(ch);
 
             let ch = meta_store
-                .create_chunk(partition.get_id(), 16, true)
+                .create_chunk(partition.get_id(), 16, true, None, None)
                 .await
                 .unwrap();
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
@@ -6198,14 +7215,14 @@ mod tests {
 
             let mut source_chunks: Vec<IdRow<Chunk>> = Vec::new();
             let ch = meta_store
-                .create_chunk(partition.clone().get_id(), 10, true)
+                .create_chunk(partition.clone().get_id(), 10, true, None, None)
                 .await
                 .unwrap();
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
             source_chunks.push(ch);
 
             let ch = meta_store
-                .create_chunk(partition.get_id(), 16, true)
+                .create_chunk(partition.get_id(), 16, true, None, None)
                 .await
                 .unwrap();
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
@@ -6242,13 +7259,13 @@ mod tests {
                 .unwrap()
                 .to_owned();
             let ch = meta_store
-                .create_chunk(partition.clone().get_id(), 10, true)
+                .create_chunk(partition.clone().get_id(), 10, true, None, None)
                 .await
                 .unwrap();
             source_chunks.push(ch);
 
             let ch = meta_store
-                .create_chunk(partition.get_id(), 16, true)
+                .create_chunk(partition.get_id(), 16, true, None, None)
                 .await
                 .unwrap();
             source_chunks.push(ch);
@@ -6285,14 +7302,14 @@ mod tests {
                 .unwrap()
                 .to_owned();
             let ch = meta_store
-                .create_chunk(partition.clone().get_id(), 10, true)
+                .create_chunk(partition.clone().get_id(), 10, true, None, None)
                 .await
                 .unwrap();
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
             source_chunks.push(ch);
 
             let ch = meta_store
-                .create_chunk(partition.get_id(), 16, true)
+                .create_chunk(partition.get_id(), 16, true, None, None)
                 .await
                 .unwrap();
             meta_store.chunk_uploaded(ch.get_id()).await.unwrap();
@@ -6325,6 +7342,7 @@ impl RocksMetaStore {
     fn swap_chunks_impl(
         deactivate_ids: Vec<u64>,
         uploaded_ids_and_sizes: Vec<(u64, Option<u64>)>,
+        mut update_uploaded_chunk: impl FnMut(Chunk) -> Chunk,
         db_ref: DbTableRef,
         batch_pipe: &mut BatchPipe,
     ) -> Result<(), CubeError> {
@@ -6370,7 +7388,7 @@ impl RocksMetaStore {
             chunks.update_with_res_fn(
                 *id,
                 |row| {
-                    let mut updated = row.set_uploaded(true);
+                    let mut updated = update_uploaded_chunk(row.set_uploaded(true));
                     if let Some(file_size) = file_size {
                         updated = updated.set_file_size(*file_size)?;
                     }
@@ -3,6 +3,7 @@ use super::{
 };
 use crate::metastore::{IdRow, MetaStoreEvent};
 use crate::rocks_table_impl;
+use crate::table::stats::ColumnStats;
 use crate::table::Row;
 use crate::{base_rocks_secondary_index, CubeError};
 use byteorder::{BigEndian, WriteBytesExt};
@@ -34,6 +35,7 @@ impl Partition {
                     .to_lowercase(),
             ),
             file_size: None,
+            column_stats: None,
         }
     }
 
@@ -54,6 +56,7 @@ impl Partition {
                     .to_lowercase(),
             ),
             file_size: None,
+            column_stats: None,
         }
     }
     pub fn get_min_val(&self) -> &Option<Row> {
@@ -121,6 +124,17 @@ impl Partition {
         Ok(p)
     }
 
+    /// Stats of the main table file.
+    pub fn column_stats(&self) -> &Option<Vec<ColumnStats>> {
+        &self.column_stats
+    }
+
+    pub fn set_column_stats(&self, column_stats: Option<Vec<ColumnStats>>) -> Partition {
+        let mut p = self.clone();
+        p.column_stats = column_stats;
+        p
+    }
+
     pub fn get_index_id(&self) -> u64 {
         self.index_id
     }
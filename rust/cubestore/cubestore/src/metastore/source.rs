@@ -14,6 +14,36 @@ pub enum SourceCredentials {
         password: Option<String>,
         url: String,
     },
+    Kafka {
+        brokers: String,
+        topic: Option<String>,
+        consumer_group: Option<String>,
+        user: Option<String>,
+        password: Option<String>,
+        use_ssl: bool,
+        #[serde(default)]
+        format: KafkaMessageFormat,
+        /// Messages that can't be decoded are skipped instead of failing the stream.
+        #[serde(default)]
+        skip_invalid_messages: bool,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Hash, PartialEq, Eq)]
+pub enum KafkaMessageFormat {
+    Json,
+    /// Avro binary encoding. Messages are either written with the given `schema` or in the
+    /// Confluent wire format with the schema id looked up in the `schema_registry_url`.
+    Avro {
+        schema: Option<String>,
+        schema_registry_url: Option<String>,
+    },
+}
+
+impl Default for KafkaMessageFormat {
+    fn default() -> Self {
+        KafkaMessageFormat::Json
+    }
 }
 
 impl DataFrameValue<String> for SourceCredentials {
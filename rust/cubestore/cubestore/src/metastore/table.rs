@@ -7,6 +7,7 @@ use crate::metastore::{IdRow, ImportFormat, MetaStoreEvent, Schema};
 use crate::queryplanner::udfs::aggregate_udf_by_kind;
 use crate::queryplanner::udfs::CubeAggregateUDFKind;
 use crate::rocks_table_impl;
+use crate::table::TableValue;
 use crate::{base_rocks_secondary_index, CubeError};
 use arrow::datatypes::Schema as ArrowSchema;
 use byteorder::{BigEndian, WriteBytesExt};
@@ -17,7 +18,9 @@ use datafusion::physical_plan::{udaf, AggregateExpr, PhysicalExpr};
 use itertools::Itertools;
 use rocksdb::DB;
 use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
 use std::io::Write;
+use std::str::FromStr;
 use std::sync::Arc;
 
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
@@ -48,6 +51,168 @@ impl DataFrameValue<String> for Vec<AggregateColumnIndex> {
     }
 }
 
+/// Position of a streaming location: the last ingested sequence value for every source partition.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash, Default)]
+pub struct StreamOffsets {
+    partitions: Vec<(i32, i64)>,
+}
+
+impl StreamOffsets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, partition: i32) -> Option<i64> {
+        self.partitions
+            .iter()
+            .find(|(p, _)| *p == partition)
+            .map(|(_, seq)| *seq)
+    }
+
+    pub fn set(&mut self, partition: i32, seq: i64) {
+        match self.partitions.iter_mut().find(|(p, _)| *p == partition) {
+            Some((_, s)) => *s = seq,
+            None => self.partitions.push((partition, seq)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+}
+
+impl DataFrameValue<String> for Option<Vec<StreamOffsets>> {
+    fn value(v: &Self) -> String {
+        v.as_ref()
+            .map(|v| format!("{:?}", v))
+            .unwrap_or("NULL".to_string())
+    }
+}
+
+/// Ingestion state of a table which is controlled through `ALTER TABLE ... SEAL|PAUSE|RESUME`.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub enum TableState {
+    Active,
+    /// Streaming is stopped and can be resumed from the stored position.
+    Paused,
+    /// Table doesn't accept any new data.
+    Sealed,
+}
+
+impl Default for TableState {
+    fn default() -> Self {
+        TableState::Active
+    }
+}
+
+impl TableState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TableState::Active => "active",
+            TableState::Paused => "paused",
+            TableState::Sealed => "sealed",
+        }
+    }
+}
+
+impl DataFrameValue<String> for TableState {
+    fn value(v: &Self) -> String {
+        v.as_str().to_string()
+    }
+}
+
+/// Rows with the value of the column older than `duration_secs` are expired and removed by the
+/// scheduler. Rows with NULL values never expire.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub struct TableTtl {
+    column_index: u64,
+    duration_secs: u64,
+}
+
+impl TableTtl {
+    pub fn new(column_index: u64, duration_secs: u64) -> Self {
+        Self {
+            column_index,
+            duration_secs,
+        }
+    }
+
+    pub fn column_index(&self) -> u64 {
+        self.column_index
+    }
+
+    pub fn duration_secs(&self) -> u64 {
+        self.duration_secs
+    }
+
+    /// Rows older than the returned time are expired at `now`.
+    pub fn expiration_cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - chrono::Duration::seconds(self.duration_secs as i64)
+    }
+}
+
+impl DataFrameValue<String> for Option<TableTtl> {
+    fn value(v: &Self) -> String {
+        v.as_ref()
+            .map(|v| format!("{:?}", v))
+            .unwrap_or("NULL".to_string())
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub enum ParquetCompression {
+    Uncompressed,
+    Snappy,
+    Gzip,
+    Lz4,
+    Zstd,
+    Brotli,
+}
+
+impl ParquetCompression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParquetCompression::Uncompressed => "uncompressed",
+            ParquetCompression::Snappy => "snappy",
+            ParquetCompression::Gzip => "gzip",
+            ParquetCompression::Lz4 => "lz4",
+            ParquetCompression::Zstd => "zstd",
+            ParquetCompression::Brotli => "brotli",
+        }
+    }
+}
+
+impl FromStr for ParquetCompression {
+    type Err = CubeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "uncompressed" | "none" => Ok(ParquetCompression::Uncompressed),
+            "snappy" => Ok(ParquetCompression::Snappy),
+            "gzip" => Ok(ParquetCompression::Gzip),
+            "lz4" => Ok(ParquetCompression::Lz4),
+            "zstd" => Ok(ParquetCompression::Zstd),
+            "brotli" => Ok(ParquetCompression::Brotli),
+            _ => Err(CubeError::user(format!(
+                "Unknown compression '{}', expected one of: uncompressed, snappy, gzip, lz4, zstd, brotli",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ParquetCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl DataFrameValue<String> for Option<ParquetCompression> {
+    fn value(v: &Self) -> String {
+        v.map(|v| v.to_string()).unwrap_or("NULL".to_string())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct AggregateColumn {
     column: Column,
@@ -86,7 +251,11 @@ impl AggregateColumn {
                 Arc::new(Min::new(col.clone(), col.name(), col.data_type(schema)?))
             }
             AggregateFunction::MERGE => {
-                let fun = aggregate_udf_by_kind(CubeAggregateUDFKind::MergeHll).descriptor();
+                let kind = match self.column.get_column_type() {
+                    ColumnType::TDigest => CubeAggregateUDFKind::MergeTDigest,
+                    _ => CubeAggregateUDFKind::MergeHll,
+                };
+                let fun = aggregate_udf_by_kind(kind).descriptor();
                 udaf::create_aggregate_expr(&fun, &[col.clone()], schema, col.name())?
             }
         };
@@ -128,7 +297,21 @@ pub struct Table {
     #[serde(default)]
     location_download_sizes: Option<Vec<u64>>,
     #[serde(default)]
-    partition_split_threshold: Option<u64>
+    partition_split_threshold: Option<u64>,
+    #[serde(default)]
+    stream_offsets: Option<Vec<StreamOffsets>>,
+    #[serde(default)]
+    state: TableState,
+    #[serde(default)]
+    dropped_columns: Option<Vec<String>>,
+    #[serde(default)]
+    ttl: Option<TableTtl>,
+    #[serde(default)]
+    compression: Option<ParquetCompression>,
+    #[serde(default)]
+    dictionary: Option<bool>,
+    #[serde(default)]
+    bloom_filter_columns: Option<Vec<String>>
 }
 }
 
@@ -158,6 +341,10 @@ impl Table {
         aggregate_column_indices: Vec<AggregateColumnIndex>,
         seq_column_index: Option<u64>,
         partition_split_threshold: Option<u64>,
+        ttl: Option<TableTtl>,
+        compression: Option<ParquetCompression>,
+        dictionary: Option<bool>,
+        bloom_filter_columns: Option<Vec<String>>,
     ) -> Table {
         let location_download_sizes = locations.as_ref().map(|locations| vec![0; locations.len()]);
         Table {
@@ -174,6 +361,13 @@ impl Table {
             seq_column_index,
             location_download_sizes,
             partition_split_threshold,
+            stream_offsets: None,
+            state: TableState::Active,
+            dropped_columns: None,
+            ttl,
+            compression,
+            dictionary,
+            bloom_filter_columns,
         }
     }
     pub fn get_columns(&self) -> &Vec<Column> {
@@ -216,14 +410,132 @@ impl Table {
         table
     }
 
-    pub fn update_location_download_size(
-        &self,
-        location: &str,
-        download_size: u64,
-    ) -> Result<Self, CubeError> {
+    pub fn state(&self) -> TableState {
+        self.state
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.state == TableState::Sealed
+    }
+
+    /// Validates the transition as sealed table can't be reopened.
+    pub fn update_state(&self, state: TableState) -> Result<Self, CubeError> {
+        if self.state == TableState::Sealed && state != TableState::Sealed {
+            return Err(CubeError::user(format!(
+                "Table '{}' is sealed and can't be {}",
+                self.table_name,
+                match state {
+                    TableState::Active => "resumed",
+                    _ => "paused",
+                }
+            )));
+        }
+        if state == TableState::Paused && !self.is_stream() {
+            return Err(CubeError::user(format!(
+                "Table '{}' doesn't have stream locations and can't be paused",
+                self.table_name
+            )));
+        }
+        let mut table = self.clone();
+        table.state = state;
+        Ok(table)
+    }
+
+    /// Appends `column` to the table. Existing data is read with the column's default value.
+    /// Data files refer to columns by name, so names of dropped columns can't be reused: files
+    /// written before the drop would provide their old values for the new column.
+    pub fn add_column(&self, column: Column) -> Result<Self, CubeError> {
+        if self.columns.iter().any(|c| c.name == column.name) {
+            return Err(CubeError::user(format!(
+                "Column '{}' already exists in table '{}'",
+                column.name, self.table_name
+            )));
+        }
+        // Data of dropped columns stays in files until they're compacted.
+        if self
+            .dropped_columns
+            .iter()
+            .flatten()
+            .any(|c| c == &column.name)
+        {
+            return Err(CubeError::user(format!(
+                "Column '{}' was dropped from table '{}' and its name can't be reused as stored data may still have its values. Please choose another name.",
+                column.name, self.table_name
+            )));
+        }
         let mut table = self.clone();
-        let locations = table.locations.as_ref().ok_or(CubeError::internal(format!(
-            "Can't update location size for table without locations: {:?}",
+        table.columns.push(column.replace_index(self.columns.len()));
+        Ok(table)
+    }
+
+    pub fn drop_column(&self, name: &str) -> Result<Self, CubeError> {
+        let column = self
+            .columns
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| {
+                CubeError::user(format!(
+                    "Column '{}' is not found in table '{}'",
+                    name, self.table_name
+                ))
+            })?;
+        let dropped = column.column_index as u64;
+        if self.ttl.as_ref().map(|t| t.column_index) == Some(dropped) {
+            return Err(CubeError::user(format!(
+                "Column '{}' of table '{}' is used for TTL and can't be dropped",
+                name, self.table_name
+            )));
+        }
+        if self.seq_column_index == Some(dropped)
+            || self
+                .unique_key_column_indices
+                .iter()
+                .flatten()
+                .any(|i| *i == dropped)
+            || self
+                .aggregate_column_indices
+                .iter()
+                .any(|a| a.index == dropped)
+        {
+            return Err(CubeError::user(format!(
+                "Column '{}' of table '{}' is a part of unique key or aggregations and can't be dropped",
+                name, self.table_name
+            )));
+        }
+        let shift = |i: u64| if i > dropped { i - 1 } else { i };
+
+        let mut table = self.clone();
+        table.columns = self
+            .columns
+            .iter()
+            .filter(|c| c.name != name)
+            .enumerate()
+            .map(|(i, c)| c.replace_index(i))
+            .collect();
+        table.seq_column_index = self.seq_column_index.map(shift);
+        table.ttl = self
+            .ttl
+            .as_ref()
+            .map(|t| TableTtl::new(shift(t.column_index), t.duration_secs));
+        table.unique_key_column_indices = self
+            .unique_key_column_indices
+            .as_ref()
+            .map(|indices| indices.iter().map(|i| shift(*i)).collect());
+        table.aggregate_column_indices = self
+            .aggregate_column_indices
+            .iter()
+            .map(|a| AggregateColumnIndex::new(shift(a.index), a.function.clone()))
+            .collect();
+        table
+            .dropped_columns
+            .get_or_insert_with(Vec::new)
+            .push(name.to_string());
+        Ok(table)
+    }
+
+    fn location_position(&self, location: &str) -> Result<(usize, usize), CubeError> {
+        let locations = self.locations.as_ref().ok_or(CubeError::internal(format!(
+            "Can't update location for table without locations: {:?}",
             self
         )))?;
         let (pos, _) =
@@ -231,16 +543,49 @@ impl Table {
                 .iter()
                 .find_position(|l| l == &location)
                 .ok_or(CubeError::internal(format!(
-                    "Can't update location size: location '{}' not found in {:?}",
+                    "Can't update location: location '{}' not found in {:?}",
                     location, locations
                 )))?;
+        Ok((pos, locations.len()))
+    }
+
+    pub fn update_location_download_size(
+        &self,
+        location: &str,
+        download_size: u64,
+    ) -> Result<Self, CubeError> {
+        let mut table = self.clone();
+        let (pos, locations_len) = self.location_position(location)?;
         if table.location_download_sizes.is_none() {
-            table.location_download_sizes = Some(vec![0; locations.len()]);
+            table.location_download_sizes = Some(vec![0; locations_len]);
         }
         table.location_download_sizes.as_mut().unwrap()[pos] = download_size;
         Ok(table)
     }
 
+    /// Stream position ingested so far for the location. None if nothing was ingested yet.
+    pub fn stream_offsets(&self, location: &str) -> Option<&StreamOffsets> {
+        let pos = self.location_position(location).ok()?.0;
+        self.stream_offsets
+            .as_ref()
+            .map(|offsets| &offsets[pos])
+            .filter(|offsets| !offsets.is_empty())
+    }
+
+    pub fn update_stream_offsets(
+        &self,
+        location: &str,
+        offsets: StreamOffsets,
+    ) -> Result<Self, CubeError> {
+        let mut table = self.clone();
+        let (pos, locations_len) = self.location_position(location)?;
+        if table.stream_offsets.is_none() {
+            table.stream_offsets = Some(vec![StreamOffsets::new(); locations_len]);
+        }
+        table.stream_offsets.as_mut().unwrap()[pos] = offsets;
+        Ok(table)
+    }
+
     pub fn total_download_size(&self) -> u64 {
         self.location_download_sizes
             .as_ref()
@@ -281,10 +626,43 @@ impl Table {
             .map(|c| &self.columns[*c as usize])
     }
 
+    pub fn ttl(&self) -> Option<&TableTtl> {
+        self.ttl.as_ref()
+    }
+
+    pub fn ttl_column(&self) -> Option<&Column> {
+        self.ttl
+            .as_ref()
+            .map(|t| &self.columns[t.column_index as usize])
+    }
+
+    /// Parquet compression of the table files, the server default is used if not set.
+    pub fn compression(&self) -> Option<ParquetCompression> {
+        self.compression
+    }
+
+    /// Whether to use dictionary encoding for all columns. If not set, it is picked automatically
+    /// for string columns.
+    pub fn dictionary(&self) -> Option<bool> {
+        self.dictionary
+    }
+
+    /// Names of the columns that get bloom filters in chunk stats.
+    pub fn bloom_filter_columns(&self) -> &Option<Vec<String>> {
+        &self.bloom_filter_columns
+    }
+
     pub fn in_memory_ingest(&self) -> bool {
         self.seq_column_index.is_some()
     }
 
+    pub fn is_stream(&self) -> bool {
+        self.locations
+            .iter()
+            .flatten()
+            .any(|l| Table::is_stream_location(l))
+    }
+
     pub fn is_stream_location(location: &str) -> bool {
         location.starts_with("stream:")
     }
@@ -310,6 +688,7 @@ impl Column {
             name,
             column_type,
             column_index,
+            default_value: None,
         }
     }
     pub fn get_name(&self) -> &String {
@@ -324,13 +703,25 @@ impl Column {
         self.column_index
     }
 
+    /// Value of the column for rows written before the column was added.
+    pub fn get_default_value(&self) -> Option<&TableValue> {
+        self.default_value.as_ref()
+    }
+
     pub fn replace_index(&self, column_index: usize) -> Column {
         Column {
             name: self.name.clone(),
             column_type: self.column_type.clone(),
             column_index,
+            default_value: self.default_value.clone(),
         }
     }
+
+    pub fn with_default_value(&self, default_value: Option<TableValue>) -> Column {
+        let mut column = self.clone();
+        column.default_value = default_value.filter(|v| v != &TableValue::Null);
+        column
+    }
 }
 
 rocks_table_impl!(Table, TableRocksTable, TableId::Tables, {
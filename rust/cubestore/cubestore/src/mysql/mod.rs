@@ -10,22 +10,33 @@ use log::{error, info, warn};
 use msql_srv::*;
 use std::convert::TryFrom;
 use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::SystemTime;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{watch, RwLock};
+use tokio_util::sync::CancellationToken;
 
 struct Backend {
     sql_service: Arc<dyn SqlService>,
     auth: Arc<dyn SqlAuthService>,
     user: Option<String>,
+    connection_id: u32,
+    cancel_token: CancellationToken,
+    /// Duplicate of the connection socket, used only to notice that the client has gone.
+    client: TcpStream,
 }
 
 #[async_trait]
 impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
     type Error = io::Error;
 
+    /// Sent in the handshake, `KILL QUERY` and `CONNECTION_ID()` use the same id.
+    fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
     async fn on_prepare<'a>(
         &'a mut self,
         _query: &'a str,
@@ -55,16 +66,25 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
         results: QueryResultWriter<'a, W>,
     ) -> Result<(), Self::Error> {
         let start = SystemTime::now();
-        let res = self
-            .sql_service
-            .exec_query_with_context(
+        let res = tokio::select! {
+            res = self.sql_service.exec_query_with_context(
                 SqlQueryContext {
                     user: self.user.clone(),
                     trace_obj: None,
+                    connection_id: Some(self.connection_id),
+                    cancel_token: self.cancel_token.clone(),
                 },
                 query,
-            )
-            .await;
+            ) => res,
+            _ = client_closed(&self.client) => {
+                // Dropping the query stops its remaining work on the router and workers.
+                warn!("Client has disconnected while running {}", query);
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "client has disconnected during the query",
+                ));
+            }
+        };
         if let Err(e) = res {
             error!(
                 "Error during processing {}: {}",
@@ -90,7 +110,13 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
                     metastore::ColumnType::Boolean => ColumnType::MYSQL_TYPE_STRING,
                     metastore::ColumnType::Bytes => ColumnType::MYSQL_TYPE_STRING,
                     metastore::ColumnType::HyperLogLog(_) => ColumnType::MYSQL_TYPE_STRING,
+                    metastore::ColumnType::TDigest => ColumnType::MYSQL_TYPE_STRING,
                     metastore::ColumnType::Float => ColumnType::MYSQL_TYPE_STRING,
+                    metastore::ColumnType::Int32 => ColumnType::MYSQL_TYPE_LONG,
+                    metastore::ColumnType::Int16 => ColumnType::MYSQL_TYPE_SHORT,
+                    metastore::ColumnType::Int8 => ColumnType::MYSQL_TYPE_TINY,
+                    metastore::ColumnType::Date => ColumnType::MYSQL_TYPE_STRING,
+                    metastore::ColumnType::List(_) => ColumnType::MYSQL_TYPE_STRING,
                 },
                 colflags: ColumnFlags::empty(),
             })
@@ -115,6 +141,13 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for Backend {
                     TableValue::Bytes(b) => {
                         rw.write_col(format!("0x{}", b.encode_hex_upper::<String>()))?
                     }
+                    TableValue::Date(d) => rw.write_col(d.to_string())?,
+                    TableValue::List(l) => match data_frame.get_columns()[i].get_column_type() {
+                        metastore::ColumnType::List(t) => {
+                            rw.write_col(TableValue::list_to_string(l, t))?
+                        }
+                        t => panic!("list value for {} column", t),
+                    },
                     TableValue::Null => rw.write_col(Option::<String>::None)?,
                 }
             }
@@ -155,6 +188,7 @@ pub struct MySqlServer {
     auth: Arc<dyn SqlAuthService>,
     close_socket_rx: RwLock<watch::Receiver<bool>>,
     close_socket_tx: watch::Sender<bool>,
+    next_connection_id: AtomicU32,
 }
 
 crate::di_service!(MySqlServer, []);
@@ -187,22 +221,13 @@ impl ProcessingLoop for MySqlServer {
                 }
             };
 
-            let sql_service = self.sql_service.clone();
-            let auth = self.auth.clone();
-            cube_ext::spawn(async move {
-                if let Err(e) = AsyncMysqlIntermediary::run_on(
-                    Backend {
-                        sql_service,
-                        auth,
-                        user: None,
-                    },
-                    socket,
-                )
-                .await
-                {
-                    error!("Error during processing MySQL connection: {}", e);
-                }
-            });
+            let connection_id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+            cube_ext::spawn(run_connection(
+                self.sql_service.clone(),
+                self.auth.clone(),
+                connection_id,
+                socket,
+            ));
         }
     }
 
@@ -225,10 +250,64 @@ impl MySqlServer {
             auth,
             close_socket_rx: RwLock::new(close_socket_rx),
             close_socket_tx,
+            next_connection_id: AtomicU32::new(1),
         })
     }
 }
 
+async fn run_connection(
+    sql_service: Arc<dyn SqlService>,
+    auth: Arc<dyn SqlAuthService>,
+    connection_id: u32,
+    socket: TcpStream,
+) {
+    let (socket, client) = match duplicate_socket(socket) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Error during processing MySQL connection: {}", e);
+            return;
+        }
+    };
+    let cancel_token = CancellationToken::new();
+    if let Err(e) = AsyncMysqlIntermediary::run_on(
+        Backend {
+            sql_service,
+            auth,
+            user: None,
+            connection_id,
+            cancel_token: cancel_token.clone(),
+            client,
+        },
+        socket,
+    )
+    .await
+    {
+        error!("Error during processing MySQL connection: {}", e);
+    }
+    // Stops queries that are still running on workers after client has disconnected.
+    cancel_token.cancel();
+}
+
+fn duplicate_socket(socket: TcpStream) -> io::Result<(TcpStream, TcpStream)> {
+    let socket = socket.into_std()?;
+    let duplicate = socket.try_clone()?;
+    Ok((
+        TcpStream::from_std(socket)?,
+        TcpStream::from_std(duplicate)?,
+    ))
+}
+
+/// Resolves once the client closes the connection. Nothing is read from the socket while a
+/// query is running, so it's safe to peek into it. If the client sends more data instead, its
+/// disconnect can't be noticed and this never resolves.
+async fn client_closed(client: &TcpStream) {
+    let mut buf = [0; 1];
+    match client.peek(&mut buf).await {
+        Ok(0) | Err(_) => {}
+        Ok(_) => futures::future::pending().await,
+    }
+}
+
 #[async_trait]
 pub trait SqlAuthService: Send + Sync {
     async fn authenticate(&self, user: Option<String>) -> Result<Option<String>, CubeError>;
@@ -244,3 +323,136 @@ impl SqlAuthService for SqlAuthDefaultImpl {
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::{PlannedSelect, QueryPlans};
+    use crate::store::DataFrame;
+    use datafusion::physical_plan::SendableRecordBatchStream;
+    use std::path::Path;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::Notify;
+    use tokio::time::timeout;
+
+    /// Runs every query until it is dropped.
+    struct HangingSqlService {
+        started: Notify,
+        dropped: Arc<Notify>,
+    }
+
+    crate::di_service!(HangingSqlService, [SqlService]);
+
+    struct NotifyOnDrop(Arc<Notify>);
+
+    impl Drop for NotifyOnDrop {
+        fn drop(&mut self) {
+            self.0.notify_one();
+        }
+    }
+
+    #[async_trait]
+    impl SqlService for HangingSqlService {
+        async fn exec_query(&self, _query: &str) -> Result<Arc<DataFrame>, CubeError> {
+            unimplemented!()
+        }
+
+        async fn exec_query_with_context(
+            &self,
+            _context: SqlQueryContext,
+            _query: &str,
+        ) -> Result<Arc<DataFrame>, CubeError> {
+            let _dropped = NotifyOnDrop(self.dropped.clone());
+            self.started.notify_one();
+            futures::future::pending().await
+        }
+
+        async fn plan_select(&self, _query: &str) -> Result<PlannedSelect, CubeError> {
+            unimplemented!()
+        }
+
+        async fn exec_planned_select(
+            &self,
+            _context: SqlQueryContext,
+            _planned: PlannedSelect,
+        ) -> Result<SendableRecordBatchStream, CubeError> {
+            unimplemented!()
+        }
+
+        async fn plan_query(&self, _query: &str) -> Result<QueryPlans, CubeError> {
+            unimplemented!()
+        }
+
+        async fn upload_temp_file(
+            &self,
+            _context: SqlQueryContext,
+            _name: String,
+            _file_path: &Path,
+        ) -> Result<(), CubeError> {
+            unimplemented!()
+        }
+
+        async fn temp_uploads_dir(&self, _context: SqlQueryContext) -> Result<String, CubeError> {
+            unimplemented!()
+        }
+    }
+
+    async fn read_packet(client: &mut TcpStream) -> Vec<u8> {
+        let mut header = [0; 4];
+        client.read_exact(&mut header).await.unwrap();
+        let len = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+        let mut payload = vec![0; len];
+        client.read_exact(&mut payload).await.unwrap();
+        payload
+    }
+
+    async fn write_packet(client: &mut TcpStream, seq: u8, payload: &[u8]) {
+        let mut packet = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+        packet.push(seq);
+        packet.extend_from_slice(payload);
+        client.write_all(&packet).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn disconnect_cancels_query() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let dropped = Arc::new(Notify::new());
+        let service = Arc::new(HangingSqlService {
+            started: Notify::new(),
+            dropped: dropped.clone(),
+        });
+        cube_ext::spawn(run_connection(
+            service.clone(),
+            Arc::new(SqlAuthDefaultImpl),
+            1,
+            socket,
+        ));
+
+        // Server greeting.
+        read_packet(&mut client).await;
+        // Handshake response with CLIENT_PROTOCOL_41 and CLIENT_SECURE_CONNECTION.
+        let mut handshake = 0x8200u32.to_le_bytes().to_vec();
+        handshake.extend_from_slice(&(1u32 << 24).to_le_bytes());
+        handshake.push(33);
+        handshake.extend_from_slice(&[0; 23]);
+        handshake.extend_from_slice(b"root\0");
+        handshake.push(0);
+        write_packet(&mut client, 1, &handshake).await;
+        let ok = read_packet(&mut client).await;
+        assert_eq!(ok[0], 0, "handshake failed: {:?}", ok);
+
+        // COM_QUERY.
+        write_packet(&mut client, 0, b"\x03SELECT 1").await;
+        timeout(Duration::from_secs(5), service.started.notified())
+            .await
+            .unwrap();
+        drop(client);
+        timeout(Duration::from_secs(5), dropped.notified())
+            .await
+            .expect("query was not stopped after client disconnect");
+    }
+}
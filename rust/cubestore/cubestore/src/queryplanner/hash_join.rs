@@ -0,0 +1,446 @@
+use crate::table::TableValue;
+use arrow::array::{Array, BooleanArray, Int64Array, StringArray};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::{DataType, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::cube_ext::stream::StreamWithSchema;
+use datafusion::error::DataFusionError;
+use datafusion::logical_plan::{
+    Column, DFSchemaRef, Expr, JoinType, LogicalPlan, UserDefinedLogicalNode,
+};
+use datafusion::physical_plan::expressions::Column as PhysicalColumn;
+use datafusion::physical_plan::hash_join::{HashJoinExec, PartitionMode};
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::{
+    ExecutionPlan, OptimizerHints, Partitioning, PhysicalExpr, SendableRecordBatchStream,
+};
+use futures::StreamExt;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt::Formatter;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// Join of inputs that are not sorted on the join keys. Produced by the planner when no index
+/// sorted by the join columns is available on one of the sides.
+#[derive(Debug, Clone)]
+pub struct HashJoinNode {
+    pub left: Arc<LogicalPlan>,
+    pub right: Arc<LogicalPlan>,
+    pub on: Vec<(Column, Column)>,
+    pub join_type: JoinType,
+    pub schema: DFSchemaRef,
+    /// Build the hash table on the right input, which is either sent to every worker or smaller
+    /// than the left one. Only set for join types allowed by [HashJoinNode::can_build_right].
+    pub build_right: bool,
+    /// Set on workers of a hash partitioned join. Both inputs are filtered to the rows with join
+    /// keys from the bucket.
+    pub hash_bucket: Option<HashBucket>,
+}
+
+/// Share of join keys joined by a single worker when rows of both join sides are split between
+/// workers by hashes of the join keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashBucket {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl HashJoinNode {
+    pub fn into_plan(self) -> LogicalPlan {
+        LogicalPlan::Extension {
+            node: Arc::new(self),
+        }
+    }
+
+    pub fn can_build_right(join_type: &JoinType) -> bool {
+        swap_join_type(join_type).is_some()
+    }
+}
+
+impl UserDefinedLogicalNode for HashJoinNode {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.schema
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        vec![]
+    }
+
+    fn prevent_predicate_push_down_columns(&self) -> HashSet<String> {
+        HashSet::new()
+    }
+
+    fn fmt_for_explain<'a>(&self, f: &mut Formatter<'a>) -> std::fmt::Result {
+        write!(
+            f,
+            "HashJoin on: [{}]",
+            self.on
+                .iter()
+                .map(|(l, r)| format!("{} = {}", l, r))
+                .join(", ")
+        )
+    }
+
+    fn from_template(
+        &self,
+        exprs: &[Expr],
+        inputs: &[LogicalPlan],
+    ) -> Arc<dyn UserDefinedLogicalNode + Send + Sync> {
+        assert!(exprs.is_empty());
+        assert_eq!(inputs.len(), 2);
+
+        Arc::new(HashJoinNode {
+            left: Arc::new(inputs[0].clone()),
+            right: Arc::new(inputs[1].clone()),
+            on: self.on.clone(),
+            join_type: self.join_type.clone(),
+            schema: self.schema.clone(),
+            build_right: self.build_right,
+            hash_bucket: self.hash_bucket,
+        })
+    }
+}
+
+/// Join type that gives the same rows after swapping the join inputs.
+fn swap_join_type(join_type: &JoinType) -> Option<JoinType> {
+    match join_type {
+        JoinType::Inner => Some(JoinType::Inner),
+        JoinType::Left => Some(JoinType::Right),
+        JoinType::Right => Some(JoinType::Left),
+        JoinType::Full => Some(JoinType::Full),
+        _ => None,
+    }
+}
+
+pub fn plan_hash_join(
+    node: &HashJoinNode,
+    left: Arc<dyn ExecutionPlan>,
+    right: Arc<dyn ExecutionPlan>,
+) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+    let (left, right) = match node.hash_bucket {
+        Some(bucket) if bucket.count > 1 => (
+            HashBucketFilterExec::try_new(left, node.on.iter().map(|(l, _)| l), bucket)?,
+            HashBucketFilterExec::try_new(right, node.on.iter().map(|(_, r)| r), bucket)?,
+        ),
+        _ => (left, right),
+    };
+    hash_join_exec(left, right, &node.on, &node.join_type, node.build_right)
+}
+
+/// Keeps the rows with join keys hashing to the bucket. Equal keys of both join sides get the
+/// same hash, so each worker joins its own share of the rows and every row is joined once.
+#[derive(Debug)]
+struct HashBucketFilterExec {
+    input: Arc<dyn ExecutionPlan>,
+    keys: Vec<usize>,
+    bucket: HashBucket,
+}
+
+impl HashBucketFilterExec {
+    fn try_new<'a>(
+        input: Arc<dyn ExecutionPlan>,
+        keys: impl Iterator<Item = &'a Column>,
+        bucket: HashBucket,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let schema = input.schema();
+        let keys = keys
+            .map(|c| schema.index_of(&c.name))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Arc::new(HashBucketFilterExec {
+            input,
+            keys,
+            bucket,
+        }))
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for HashBucketFilterExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        assert_eq!(children.len(), 1);
+        Ok(Arc::new(HashBucketFilterExec {
+            input: children.remove(0),
+            keys: self.keys.clone(),
+            bucket: self.bucket,
+        }))
+    }
+
+    fn output_hints(&self) -> OptimizerHints {
+        self.input.output_hints()
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<SendableRecordBatchStream, DataFusionError> {
+        let input = self.input.execute(partition).await?;
+        let schema = input.schema();
+        let keys = self.keys.clone();
+        let bucket = self.bucket;
+        Ok(Box::pin(StreamWithSchema::wrap(
+            schema,
+            input.map(move |b| filter_hash_bucket(b?, &keys, bucket)),
+        )))
+    }
+}
+
+fn filter_hash_bucket(
+    batch: RecordBatch,
+    keys: &[usize],
+    bucket: HashBucket,
+) -> Result<RecordBatch, ArrowError> {
+    let in_bucket = (0..batch.num_rows())
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            for k in keys {
+                hash_key(&mut hasher, batch.column(*k).as_ref(), row);
+            }
+            Some(hasher.finish() % bucket.count as u64 == bucket.index as u64)
+        })
+        .collect::<BooleanArray>();
+    filter_record_batch(&batch, &in_bucket)
+}
+
+/// Integers are hashed the same regardless of their width.
+fn hash_key(hasher: &mut impl Hasher, a: &dyn Array, row: usize) {
+    if !a.is_valid(row) {
+        return TableValue::Null.hash(hasher);
+    }
+    match a.data_type() {
+        DataType::Int64 => a
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap()
+            .value(row)
+            .hash(hasher),
+        DataType::Utf8 => a
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap()
+            .value(row)
+            .hash(hasher),
+        _ => match TableValue::from_array(a, row) {
+            TableValue::Int(v) => v.hash(hasher),
+            v => v.hash(hasher),
+        },
+    }
+}
+
+/// [HashJoinExec] collects its whole left input into the hash table, so the inputs are swapped
+/// when building on the right one. Output columns are then reordered back by a projection.
+fn hash_join_exec(
+    left: Arc<dyn ExecutionPlan>,
+    right: Arc<dyn ExecutionPlan>,
+    on: &[(Column, Column)],
+    join_type: &JoinType,
+    build_right: bool,
+) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+    let on = on
+        .iter()
+        .map(|(l, r)| -> Result<_, DataFusionError> {
+            Ok((
+                PhysicalColumn::new(&l.name, left.schema().index_of(&l.name)?),
+                PhysicalColumn::new(&r.name, right.schema().index_of(&r.name)?),
+            ))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if !build_right {
+        return Ok(Arc::new(HashJoinExec::try_new(
+            left,
+            right,
+            on,
+            join_type,
+            PartitionMode::CollectLeft,
+        )?));
+    }
+
+    let swapped_type = swap_join_type(join_type).ok_or_else(|| {
+        DataFusionError::Internal(format!(
+            "Can't build hash table on the right side of {:?} join",
+            join_type
+        ))
+    })?;
+    let left_len = left.schema().fields().len();
+    let right_len = right.schema().fields().len();
+    let join = Arc::new(HashJoinExec::try_new(
+        right,
+        left,
+        on.into_iter().map(|(l, r)| (r, l)).collect(),
+        &swapped_type,
+        PartitionMode::CollectLeft,
+    )?);
+    let schema = join.schema();
+    let projection = (right_len..right_len + left_len)
+        .chain(0..right_len)
+        .map(|i| {
+            let name = schema.field(i).name();
+            (
+                Arc::new(PhysicalColumn::new(name, i)) as Arc<dyn PhysicalExpr>,
+                name.clone(),
+            )
+        })
+        .collect();
+    Ok(Arc::new(ProjectionExec::try_new(projection, join)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::physical_plan::collect;
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    fn input(names: (&str, &str), keys: Vec<i64>, values: Vec<&str>) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(names.0, DataType::Int64, true),
+            Field::new(names.1, DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(keys)),
+                Arc::new(StringArray::from(values)),
+            ],
+        )
+        .unwrap();
+        Arc::new(MemoryExec::try_new(&vec![vec![batch]], schema, None).unwrap())
+    }
+
+    async fn join_rows(
+        join_type: JoinType,
+        build_right: bool,
+        bucket: Option<HashBucket>,
+    ) -> Vec<Vec<Option<String>>> {
+        let left = input(("id", "name"), vec![1, 2, 3], vec!["a", "b", "c"]);
+        let right = input(("rid", "value"), vec![2, 3, 3, 4], vec!["x", "y", "z", "w"]);
+        let on = vec![(
+            Column {
+                relation: None,
+                name: "id".to_string(),
+            },
+            Column {
+                relation: None,
+                name: "rid".to_string(),
+            },
+        )];
+        let (left, right) = match bucket {
+            Some(bucket) => (
+                HashBucketFilterExec::try_new(left, on.iter().map(|(l, _)| l), bucket).unwrap(),
+                HashBucketFilterExec::try_new(right, on.iter().map(|(_, r)| r), bucket).unwrap(),
+            ),
+            None => (left, right),
+        };
+        let join = hash_join_exec(left, right, &on, &join_type, build_right).unwrap();
+        let schema = join.schema();
+        assert_eq!(
+            schema.fields().iter().map(|f| f.name()).collect_vec(),
+            vec!["id", "name", "rid", "value"]
+        );
+
+        let mut rows = Vec::new();
+        for batch in collect(join).await.unwrap() {
+            for row in 0..batch.num_rows() {
+                let mut r = Vec::new();
+                for c in batch.columns() {
+                    if c.is_null(row) {
+                        r.push(None);
+                    } else if let Some(a) = c.as_any().downcast_ref::<Int64Array>() {
+                        r.push(Some(a.value(row).to_string()));
+                    } else {
+                        let a = c.as_any().downcast_ref::<StringArray>().unwrap();
+                        r.push(Some(a.value(row).to_string()));
+                    }
+                }
+                rows.push(r);
+            }
+        }
+        rows.sort();
+        rows
+    }
+
+    #[tokio::test]
+    async fn build_right_keeps_rows_and_columns() {
+        for join_type in vec![
+            JoinType::Inner,
+            JoinType::Left,
+            JoinType::Right,
+            JoinType::Full,
+        ] {
+            assert_eq!(
+                join_rows(join_type.clone(), true, None).await,
+                join_rows(join_type.clone(), false, None).await,
+                "{:?}",
+                join_type
+            );
+        }
+
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(
+            join_rows(JoinType::Left, true, None).await,
+            vec![
+                vec![s("1"), s("a"), None, None],
+                vec![s("2"), s("b"), s("2"), s("x")],
+                vec![s("3"), s("c"), s("3"), s("y")],
+                vec![s("3"), s("c"), s("3"), s("z")],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn hash_buckets_join_every_row_once() {
+        for join_type in vec![
+            JoinType::Inner,
+            JoinType::Left,
+            JoinType::Right,
+            JoinType::Full,
+        ] {
+            let mut rows = Vec::new();
+            for index in 0..3 {
+                let bucket = HashBucket { index, count: 3 };
+                rows.extend(join_rows(join_type.clone(), false, Some(bucket)).await);
+            }
+            rows.sort();
+            assert_eq!(
+                rows,
+                join_rows(join_type.clone(), false, None).await,
+                "{:?}",
+                join_type
+            );
+        }
+    }
+}
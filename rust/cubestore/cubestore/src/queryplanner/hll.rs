@@ -9,6 +9,19 @@ pub enum Hll {
 }
 
 impl Hll {
+    /// Number of buckets used by `approx_set` in Presto by default, gives 2.3% standard error.
+    pub const AIRLIFT_DEFAULT_NUM_BUCKETS: u32 = 2048;
+    /// Precision used by `HLL_COUNT.INIT` in BigQuery by default.
+    pub const ZETA_DEFAULT_PRECISION: i32 = 15;
+
+    pub fn new_airlift(num_buckets: u32) -> Result<Hll, CubeError> {
+        return Ok(Hll::Airlift(HllSketch::new(num_buckets)?));
+    }
+
+    pub fn new_zeta(precision: i32) -> Result<Hll, CubeError> {
+        return Ok(Hll::ZetaSketch(HyperLogLogPlusPlus::new(precision)?));
+    }
+
     pub fn read(data: &[u8]) -> Result<Hll, CubeError> {
         if data.is_empty() {
             return Err(CubeError::internal(
@@ -47,6 +60,15 @@ impl Hll {
         }
     }
 
+    /// Adds an element with a precomputed 64-bit hash, see [hash_value].
+    pub fn insert_hash(&mut self, hash: u64) -> Result<(), CubeError> {
+        match self {
+            Hll::Airlift(h) => h.insert_hash(hash),
+            Hll::ZetaSketch(h) => h.add_hash(hash)?,
+        }
+        return Ok(());
+    }
+
     /// Clients are responsible for calling `is_compatible` before running this function.
     /// On error, `self` may end up in inconsistent state and must be discarded.
     pub fn merge_with(&mut self, other: &Hll) -> Result<(), CubeError> {
@@ -59,3 +81,12 @@ impl Hll {
         return Ok(());
     }
 }
+
+/// Hash used to add elements to the sketches, 64-bit Murmur3 as in `approx_set` of Presto.
+/// Elements are encoded as little-endian bytes of 64-bit integers or as UTF-8 bytes of strings.
+///
+/// ZetaSketch uses Fingerprint2011 instead, so sketches built by Cube Store in that format are
+/// marked with a distinct value type and can't be merged with the ones imported from BigQuery.
+pub fn hash_value(data: &[u8]) -> u64 {
+    return cubehll::murmur3_hash64(data);
+}
@@ -4,4 +4,5 @@ pub mod system_chunks;
 pub mod system_indexes;
 pub mod system_jobs;
 pub mod system_partitions;
+pub mod system_queries;
 pub mod system_tables;
@@ -0,0 +1,180 @@
+use crate::queryplanner::{InfoSchemaTableDef, InfoSchemaTableDefContext};
+use crate::sql::query_log::QueryLogEntry;
+use crate::CubeError;
+use arrow::array::{ArrayRef, BooleanArray, StringArray, TimestampNanosecondArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, TimeUnit};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+pub struct SystemQueriesTableDef;
+
+#[async_trait]
+impl InfoSchemaTableDef for SystemQueriesTableDef {
+    type T = QueryLogEntry;
+
+    async fn rows(&self, ctx: InfoSchemaTableDefContext) -> Result<Arc<Vec<Self::T>>, CubeError> {
+        Ok(Arc::new(ctx.query_log.running()))
+    }
+
+    fn columns(&self) -> Vec<(Field, Box<dyn Fn(Arc<Vec<Self::T>>) -> ArrayRef>)> {
+        query_columns()
+    }
+}
+
+crate::base_info_schema_table_def!(SystemQueriesTableDef);
+
+pub struct SystemQueryLogTableDef;
+
+#[async_trait]
+impl InfoSchemaTableDef for SystemQueryLogTableDef {
+    type T = QueryLogEntry;
+
+    async fn rows(&self, ctx: InfoSchemaTableDefContext) -> Result<Arc<Vec<Self::T>>, CubeError> {
+        Ok(Arc::new(ctx.query_log.completed()))
+    }
+
+    fn columns(&self) -> Vec<(Field, Box<dyn Fn(Arc<Vec<Self::T>>) -> ArrayRef>)> {
+        query_columns()
+    }
+}
+
+crate::base_info_schema_table_def!(SystemQueryLogTableDef);
+
+fn query_columns() -> Vec<(Field, Box<dyn Fn(Arc<Vec<QueryLogEntry>>) -> ArrayRef>)> {
+    vec![
+        (
+            Field::new("id", DataType::UInt64, false),
+            Box::new(|queries| {
+                Arc::new(UInt64Array::from(
+                    queries.iter().map(|q| q.id).collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("connection_id", DataType::UInt64, true),
+            Box::new(|queries| {
+                Arc::new(UInt64Array::from(
+                    queries
+                        .iter()
+                        .map(|q| q.connection_id.map(|id| id as u64))
+                        .collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("query", DataType::Utf8, false),
+            Box::new(|queries| {
+                Arc::new(StringArray::from(
+                    queries.iter().map(|q| q.query.as_str()).collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("trace_obj", DataType::Utf8, true),
+            Box::new(|queries| {
+                Arc::new(StringArray::from(
+                    queries
+                        .iter()
+                        .map(|q| q.trace_obj.as_deref())
+                        .collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new(
+                "start_time",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+            Box::new(|queries| {
+                Arc::new(TimestampNanosecondArray::from(
+                    queries
+                        .iter()
+                        .map(|q| q.start_time.timestamp_nanos())
+                        .collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("duration_ms", DataType::UInt64, true),
+            Box::new(|queries| {
+                Arc::new(UInt64Array::from(
+                    queries
+                        .iter()
+                        .map(|q| q.duration.map(|d| d.as_millis() as u64))
+                        .collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("status", DataType::Utf8, false),
+            Box::new(|queries| {
+                Arc::new(StringArray::from(
+                    queries
+                        .iter()
+                        .map(|q| format!("{:?}", q.status))
+                        .collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("error", DataType::Utf8, true),
+            Box::new(|queries| {
+                Arc::new(StringArray::from(
+                    queries
+                        .iter()
+                        .map(|q| q.error.as_deref())
+                        .collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("rows", DataType::UInt64, true),
+            Box::new(|queries| {
+                Arc::new(UInt64Array::from(
+                    queries.iter().map(|q| q.rows).collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("bytes_scanned", DataType::UInt64, false),
+            Box::new(|queries| {
+                Arc::new(UInt64Array::from(
+                    queries.iter().map(|q| q.bytes_scanned).collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("partitions", DataType::UInt64, false),
+            Box::new(|queries| {
+                Arc::new(UInt64Array::from(
+                    queries.iter().map(|q| q.partitions).collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("chunks", DataType::UInt64, false),
+            Box::new(|queries| {
+                Arc::new(UInt64Array::from(
+                    queries.iter().map(|q| q.chunks).collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("workers", DataType::UInt64, false),
+            Box::new(|queries| {
+                Arc::new(UInt64Array::from(
+                    queries.iter().map(|q| q.workers).collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+        (
+            Field::new("cache_hit", DataType::Boolean, true),
+            Box::new(|queries| {
+                Arc::new(BooleanArray::from(
+                    queries.iter().map(|q| q.cache_hit).collect::<Vec<_>>(),
+                ))
+            }),
+        ),
+    ]
+}
@@ -1,6 +1,5 @@
 use crate::metastore::table::TablePath;
-use crate::metastore::MetaStore;
-use crate::queryplanner::InfoSchemaTableDef;
+use crate::queryplanner::{InfoSchemaTableDef, InfoSchemaTableDefContext};
 use crate::CubeError;
 use arrow::array::{ArrayRef, BooleanArray, StringArray, TimestampNanosecondArray, UInt64Array};
 use arrow::datatypes::{DataType, Field, TimeUnit};
@@ -13,8 +12,8 @@ pub struct SystemTablesTableDef;
 impl InfoSchemaTableDef for SystemTablesTableDef {
     type T = TablePath;
 
-    async fn rows(&self, meta_store: Arc<dyn MetaStore>) -> Result<Arc<Vec<Self::T>>, CubeError> {
-        meta_store.get_tables_with_path(true).await
+    async fn rows(&self, ctx: InfoSchemaTableDefContext) -> Result<Arc<Vec<Self::T>>, CubeError> {
+        ctx.meta_store.get_tables_with_path(true).await
     }
 
     fn columns(&self) -> Vec<(Field, Box<dyn Fn(Arc<Vec<Self::T>>) -> ArrayRef>)> {
@@ -118,6 +117,17 @@ impl InfoSchemaTableDef for SystemTablesTableDef {
                     ))
                 }),
             ),
+            (
+                Field::new("state", DataType::Utf8, false),
+                Box::new(|tables| {
+                    Arc::new(StringArray::from(
+                        tables
+                            .iter()
+                            .map(|row| row.table.get_row().state().as_str())
+                            .collect::<Vec<_>>(),
+                    ))
+                }),
+            ),
             (
                 Field::new("unique_key_column_indices", DataType::Utf8, true),
                 Box::new(|tables| {
@@ -182,6 +192,76 @@ impl InfoSchemaTableDef for SystemTablesTableDef {
                     Arc::new(UInt64Array::from(array))
                 }),
             ),
+            (
+                Field::new("ttl_column", DataType::Utf8, true),
+                Box::new(|tables| {
+                    Arc::new(StringArray::from(
+                        tables
+                            .iter()
+                            .map(|row| {
+                                row.table
+                                    .get_row()
+                                    .ttl_column()
+                                    .map(|c| c.get_name().as_str())
+                            })
+                            .collect::<Vec<_>>(),
+                    ))
+                }),
+            ),
+            (
+                Field::new("ttl_secs", DataType::UInt64, true),
+                Box::new(|tables| {
+                    Arc::new(UInt64Array::from(
+                        tables
+                            .iter()
+                            .map(|row| row.table.get_row().ttl().map(|t| t.duration_secs()))
+                            .collect::<Vec<_>>(),
+                    ))
+                }),
+            ),
+            (
+                Field::new("compression", DataType::Utf8, true),
+                Box::new(|tables| {
+                    Arc::new(StringArray::from(
+                        tables
+                            .iter()
+                            .map(|row| row.table.get_row().compression().map(|c| c.as_str()))
+                            .collect::<Vec<_>>(),
+                    ))
+                }),
+            ),
+            (
+                Field::new("dictionary", DataType::Boolean, true),
+                Box::new(|tables| {
+                    Arc::new(BooleanArray::from(
+                        tables
+                            .iter()
+                            .map(|row| row.table.get_row().dictionary())
+                            .collect::<Vec<_>>(),
+                    ))
+                }),
+            ),
+            (
+                Field::new("bloom_filter_columns", DataType::Utf8, true),
+                Box::new(|tables| {
+                    let columns = tables
+                        .iter()
+                        .map(|row| {
+                            row.table
+                                .get_row()
+                                .bloom_filter_columns()
+                                .as_ref()
+                                .map(|c| c.join(", "))
+                        })
+                        .collect::<Vec<_>>();
+                    Arc::new(StringArray::from(
+                        columns
+                            .iter()
+                            .map(|v| v.as_ref().map(|v| v.as_str()))
+                            .collect::<Vec<_>>(),
+                    ))
+                }),
+            ),
             (
                 Field::new(
                     "created_at",
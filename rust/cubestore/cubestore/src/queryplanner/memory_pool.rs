@@ -0,0 +1,566 @@
+use crate::queryplanner::optimizations::rewrite_plan::rewrite_physical_plan;
+use crate::queryplanner::query_executor::CubeTableExec;
+use crate::queryplanner::topk::AggregateTopKExec;
+use crate::CubeError;
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::cube_ext::stream::StreamWithSchema;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::group_scalar::GroupByScalar;
+use datafusion::physical_plan::hash_aggregate::{
+    create_group_by_values, AggregateStrategy, HashAggregateExec,
+};
+use datafusion::physical_plan::hash_join::{HashJoinExec, PartitionMode};
+use datafusion::physical_plan::merge_sort::MergeSortExec;
+use datafusion::physical_plan::sort::SortExec;
+use datafusion::physical_plan::{
+    Distribution, ExecutionPlan, OptimizerHints, Partitioning, PhysicalExpr,
+    SendableRecordBatchStream,
+};
+use datafusion::scalar::ScalarValue;
+use futures::StreamExt;
+use smallvec::{smallvec, SmallVec};
+use std::any::Any;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Accounts memory held by the operators of a single query. Operators reserve memory for the
+/// record batches they produce and the query fails with a user error once the total goes over
+/// the limit. Zero limit disables the checks.
+#[derive(Debug)]
+pub struct QueryMemoryPool {
+    limit: usize,
+    used: AtomicUsize,
+    exceeded: AtomicBool,
+}
+
+impl QueryMemoryPool {
+    pub fn new(limit: usize) -> Arc<QueryMemoryPool> {
+        Arc::new(QueryMemoryPool {
+            limit,
+            used: AtomicUsize::new(0),
+            exceeded: AtomicBool::new(false),
+        })
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.limit != 0
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn reservation(self: &Arc<Self>, consumer: &'static str) -> MemoryReservation {
+        MemoryReservation {
+            pool: self.clone(),
+            consumer,
+            size: 0,
+        }
+    }
+
+    /// Errors produced by the reservations lose their type while they travel through DataFusion
+    /// operators. Use this to report the original user error once the query has failed.
+    pub fn limit_error(&self) -> Option<CubeError> {
+        if self.exceeded.load(Ordering::Relaxed) {
+            Some(self.error(None))
+        } else {
+            None
+        }
+    }
+
+    fn error(&self, consumer: Option<&str>) -> CubeError {
+        CubeError::user(format!(
+            "Query exceeded memory limit of {} bytes{}. Consider adding filters to the query or \
+             increasing CUBESTORE_QUERY_MEMORY_LIMIT_BYTES",
+            self.limit,
+            consumer.map(|c| format!(" in {}", c)).unwrap_or_default()
+        ))
+    }
+
+    fn try_grow(&self, consumer: &str, bytes: usize) -> Result<(), CubeError> {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if self.limit != 0 && self.limit < used {
+            self.used.fetch_sub(bytes, Ordering::Relaxed);
+            self.exceeded.store(true, Ordering::Relaxed);
+            return Err(self.error(Some(consumer)));
+        }
+        Ok(())
+    }
+
+    fn shrink(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Memory reserved by a single operator. Returned back to the pool on drop.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    pool: Arc<QueryMemoryPool>,
+    consumer: &'static str,
+    size: usize,
+}
+
+impl MemoryReservation {
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn try_grow(&mut self, bytes: usize) -> Result<(), CubeError> {
+        self.pool.try_grow(self.consumer, bytes)?;
+        self.size += bytes;
+        Ok(())
+    }
+
+    pub fn try_resize(&mut self, bytes: usize) -> Result<(), CubeError> {
+        if self.size < bytes {
+            self.try_grow(bytes - self.size)
+        } else {
+            self.pool.shrink(self.size - bytes);
+            self.size = bytes;
+            Ok(())
+        }
+    }
+
+    pub fn free(&mut self) {
+        self.pool.shrink(self.size);
+        self.size = 0;
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.free()
+    }
+}
+
+pub fn batch_memory_size(b: &RecordBatch) -> usize {
+    b.columns().iter().map(|c| c.get_array_memory_size()).sum()
+}
+
+/// Wraps operators that hold considerable amounts of data into [MemoryTrackingExec] or
+/// [BufferTrackingExec], build sides of hash joins into [InputTrackingExec]. Plans are left
+/// untouched when the pool is not limited.
+pub fn track_query_memory(
+    p: Arc<dyn ExecutionPlan>,
+    pool: &Arc<QueryMemoryPool>,
+) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+    if !pool.is_limited() {
+        return Ok(p);
+    }
+    rewrite_physical_plan(p.as_ref(), &mut |p| {
+        // Sorts and hash aggregations keep their data until they produce the results, so their
+        // inputs are accounted while they are consumed.
+        if p.as_any().is::<SortExec>() {
+            return Ok(Arc::new(BufferTrackingExec {
+                input: p,
+                pool: pool.clone(),
+                consumer: "sort",
+                buffer: Buffer::Batches,
+            }));
+        }
+        if let Some(a) = p.as_any().downcast_ref::<HashAggregateExec>() {
+            if a.strategy() == AggregateStrategy::Hash && !a.group_expr().is_empty() {
+                let mut state_fields = 0;
+                for e in a.aggr_expr() {
+                    state_fields += e.state_fields()?.len();
+                }
+                let group_expr = a.group_expr().iter().map(|(e, _)| e.clone()).collect();
+                return Ok(Arc::new(BufferTrackingExec {
+                    input: p,
+                    pool: pool.clone(),
+                    consumer: "aggregation",
+                    buffer: Buffer::Groups {
+                        group_expr,
+                        state_fields,
+                    },
+                }));
+            }
+        }
+        // Hash joins collect the left input into the hash table once and share it between the
+        // output partitions, so the plan node is kept and the memory is held until it's dropped.
+        if let Some(j) = p.as_any().downcast_ref::<HashJoinExec>() {
+            if let PartitionMode::CollectLeft = j.partition_mode() {
+                let left = Arc::new(InputTrackingExec {
+                    input: j.left().clone(),
+                    reservation: Arc::new(Mutex::new(pool.reservation("hash join"))),
+                    buffer: Buffer::Batches,
+                });
+                return p.with_new_children(vec![left, j.right().clone()]);
+            }
+        }
+
+        let tracking;
+        if p.as_any().is::<CubeTableExec>() {
+            tracking = Some(("table scan", false));
+        } else if p.as_any().is::<MergeSortExec>() {
+            tracking = Some(("merge sort", false));
+        } else if p.as_any().is::<AggregateTopKExec>() {
+            tracking = Some(("top-k aggregation", true));
+        } else if p.as_any().is::<HashAggregateExec>() {
+            // Sorted aggregation and aggregation without groups stream their results.
+            tracking = Some(("aggregation", false));
+        } else {
+            tracking = None;
+        }
+        let (consumer, retain) = match tracking {
+            Some(t) => t,
+            None => return Ok(p),
+        };
+        Ok(Arc::new(MemoryTrackingExec {
+            input: p,
+            pool: pool.clone(),
+            consumer,
+            retain,
+        }))
+    })
+}
+
+/// Replaces errors of `s` with the user error of `pool` once the limit is exceeded. Streamed
+/// results do not go through [QueryMemoryPool::limit_error] of the collecting code.
+pub fn with_limit_error(
+    s: SendableRecordBatchStream,
+    pool: Arc<QueryMemoryPool>,
+) -> SendableRecordBatchStream {
+    if !pool.is_limited() {
+        return s;
+    }
+    let schema = s.schema();
+    Box::pin(StreamWithSchema::wrap(
+        schema,
+        s.map(move |b| match (b, pool.limit_error()) {
+            (Err(_), Some(e)) => Err(ArrowError::ExternalError(Box::new(e))),
+            (b, _) => b,
+        }),
+    ))
+}
+
+/// Reports the batches produced by the input into the query memory pool. When `retain` is set,
+/// memory is held until the stream is dropped, as the input operator buffers all of its data.
+/// Otherwise only the last produced batch is accounted.
+#[derive(Debug)]
+pub struct MemoryTrackingExec {
+    input: Arc<dyn ExecutionPlan>,
+    pool: Arc<QueryMemoryPool>,
+    consumer: &'static str,
+    retain: bool,
+}
+
+#[async_trait]
+impl ExecutionPlan for MemoryTrackingExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn required_child_distribution(&self) -> Distribution {
+        self.input.required_child_distribution()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        assert_eq!(children.len(), 1);
+        Ok(Arc::new(MemoryTrackingExec {
+            input: children.remove(0),
+            pool: self.pool.clone(),
+            consumer: self.consumer,
+            retain: self.retain,
+        }))
+    }
+
+    fn output_hints(&self) -> OptimizerHints {
+        self.input.output_hints()
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<SendableRecordBatchStream, DataFusionError> {
+        let i = self.input.execute(partition).await?;
+        let s = i.schema();
+        let mut reservation = self.pool.reservation(self.consumer);
+        let retain = self.retain;
+        Ok(Box::pin(StreamWithSchema::wrap(
+            s,
+            i.map(move |b| -> Result<RecordBatch, ArrowError> {
+                let b = b?;
+                let size = batch_memory_size(&b);
+                let r = if retain {
+                    reservation.try_grow(size)
+                } else {
+                    reservation.try_resize(size)
+                };
+                r.map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+                Ok(b)
+            }),
+        )))
+    }
+}
+
+/// Data kept by the operator wrapped into [BufferTrackingExec].
+#[derive(Debug, Clone)]
+enum Buffer {
+    /// All input batches, e.g. in sorts or in the build side of hash joins.
+    Batches,
+    /// Groups of the hash aggregation. Only distinct groups take memory, so these are estimated
+    /// from hashes of the group keys rather than from the input size.
+    Groups {
+        group_expr: Vec<Arc<dyn PhysicalExpr>>,
+        state_fields: usize,
+    },
+}
+
+/// Approximate size of the hash table entry and the accumulators of a group.
+const GROUP_OVERHEAD_BYTES: usize = 64;
+
+impl Buffer {
+    fn group_size(key: &[GroupByScalar], state_fields: usize) -> usize {
+        let mut size = GROUP_OVERHEAD_BYTES + state_fields * size_of::<ScalarValue>();
+        for k in key {
+            size += size_of::<GroupByScalar>();
+            if let GroupByScalar::Utf8(s) = k {
+                size += s.len();
+            }
+        }
+        size
+    }
+}
+
+/// Accounts the input consumed by an operator that keeps it until the results are produced. The
+/// memory is held until the output stream of the operator is dropped.
+#[derive(Debug)]
+pub struct BufferTrackingExec {
+    input: Arc<dyn ExecutionPlan>,
+    pool: Arc<QueryMemoryPool>,
+    consumer: &'static str,
+    buffer: Buffer,
+}
+
+#[async_trait]
+impl ExecutionPlan for BufferTrackingExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn required_child_distribution(&self) -> Distribution {
+        self.input.required_child_distribution()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        assert_eq!(children.len(), 1);
+        Ok(Arc::new(BufferTrackingExec {
+            input: children.remove(0),
+            pool: self.pool.clone(),
+            consumer: self.consumer,
+            buffer: self.buffer.clone(),
+        }))
+    }
+
+    fn output_hints(&self) -> OptimizerHints {
+        self.input.output_hints()
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<SendableRecordBatchStream, DataFusionError> {
+        let reservation = Arc::new(Mutex::new(self.pool.reservation(self.consumer)));
+        let mut inputs = self.input.children();
+        assert_eq!(inputs.len(), 1);
+        let tracked_input = Arc::new(InputTrackingExec {
+            input: inputs.remove(0),
+            reservation: reservation.clone(),
+            buffer: self.buffer.clone(),
+        });
+        let output = self
+            .input
+            .with_new_children(vec![tracked_input])?
+            .execute(partition)
+            .await?;
+        let s = output.schema();
+        Ok(Box::pin(StreamWithSchema::wrap(
+            s,
+            output.map(move |b| {
+                // Keeps the memory reserved while the results are produced.
+                let _ = &reservation;
+                b
+            }),
+        )))
+    }
+}
+
+/// Input of the operator wrapped into [BufferTrackingExec] or the build side of a hash join,
+/// reserves memory for its batches.
+#[derive(Debug)]
+struct InputTrackingExec {
+    input: Arc<dyn ExecutionPlan>,
+    reservation: Arc<Mutex<MemoryReservation>>,
+    buffer: Buffer,
+}
+
+#[async_trait]
+impl ExecutionPlan for InputTrackingExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn required_child_distribution(&self) -> Distribution {
+        self.input.required_child_distribution()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        assert_eq!(children.len(), 1);
+        Ok(Arc::new(InputTrackingExec {
+            input: children.remove(0),
+            reservation: self.reservation.clone(),
+            buffer: self.buffer.clone(),
+        }))
+    }
+
+    fn output_hints(&self) -> OptimizerHints {
+        self.input.output_hints()
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<SendableRecordBatchStream, DataFusionError> {
+        let i = self.input.execute(partition).await?;
+        let s = i.schema();
+        let reservation = self.reservation.clone();
+        let buffer = self.buffer.clone();
+        let mut groups = HashSet::new();
+        Ok(Box::pin(StreamWithSchema::wrap(
+            s,
+            i.map(move |b| -> Result<RecordBatch, ArrowError> {
+                let b = b?;
+                let size = match &buffer {
+                    Buffer::Batches => batch_memory_size(&b),
+                    Buffer::Groups {
+                        group_expr,
+                        state_fields,
+                    } => new_groups_size(&b, group_expr, *state_fields, &mut groups)
+                        .map_err(|e| ArrowError::ExternalError(Box::new(e)))?,
+                };
+                reservation
+                    .lock()
+                    .unwrap()
+                    .try_grow(size)
+                    .map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+                Ok(b)
+            }),
+        )))
+    }
+}
+
+/// Estimated memory taken by the groups of `b` that are not in `seen` yet.
+fn new_groups_size(
+    b: &RecordBatch,
+    group_expr: &[Arc<dyn PhysicalExpr>],
+    state_fields: usize,
+    seen: &mut HashSet<u64>,
+) -> Result<usize, DataFusionError> {
+    let keys = group_expr
+        .iter()
+        .map(|e| Ok(e.evaluate(b)?.into_array(b.num_rows())))
+        .collect::<Result<Vec<_>, DataFusionError>>()?;
+    let mut key: SmallVec<[GroupByScalar; 2]> = smallvec![GroupByScalar::Int8(0); keys.len()];
+    let mut size = 0;
+    for row in 0..b.num_rows() {
+        create_group_by_values(&keys, row, &mut key)?;
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        if seen.insert(hasher.finish()) {
+            size += Buffer::group_size(&key, state_fields);
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations() {
+        let pool = QueryMemoryPool::new(100);
+        let mut r1 = pool.reservation("r1");
+        let mut r2 = pool.reservation("r2");
+        r1.try_grow(60).unwrap();
+        r2.try_grow(30).unwrap();
+        assert_eq!(pool.used(), 90);
+        assert!(pool.limit_error().is_none());
+
+        let e = r2.try_grow(20).unwrap_err();
+        assert!(e.message.contains("in r2"), "{}", e);
+        assert_eq!(r2.size(), 30);
+        assert_eq!(pool.used(), 90);
+        assert!(pool.limit_error().is_some());
+
+        r1.try_resize(10).unwrap();
+        r2.try_resize(80).unwrap();
+        assert_eq!(pool.used(), 90);
+
+        drop(r1);
+        assert_eq!(pool.used(), 80);
+        r2.free();
+        assert_eq!(pool.used(), 0);
+    }
+
+    #[test]
+    fn unlimited() {
+        let pool = QueryMemoryPool::new(0);
+        let mut r = pool.reservation("r");
+        r.try_grow(usize::MAX / 2).unwrap();
+        assert!(pool.limit_error().is_none());
+    }
+}
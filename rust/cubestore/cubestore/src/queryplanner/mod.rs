@@ -1,12 +1,15 @@
+mod hash_join;
 pub mod hll;
+pub mod memory_pool;
 mod optimizations;
 pub mod panic;
-mod partition_filter;
+pub mod partition_filter;
 mod planning;
 pub use planning::PlanningMeta;
 pub mod pretty_printers;
 pub mod query_executor;
 pub mod serialized_plan;
+pub mod tdigest;
 mod topk;
 pub use topk::MIN_TOPK_STREAM_ROWS;
 mod coalesce;
@@ -26,6 +29,9 @@ use crate::queryplanner::info_schema::system_chunks::SystemChunksTableDef;
 use crate::queryplanner::info_schema::system_indexes::SystemIndexesTableDef;
 use crate::queryplanner::info_schema::system_jobs::SystemJobsTableDef;
 use crate::queryplanner::info_schema::system_partitions::SystemPartitionsTableDef;
+use crate::queryplanner::info_schema::system_queries::{
+    SystemQueriesTableDef, SystemQueryLogTableDef,
+};
 use crate::queryplanner::info_schema::system_tables::SystemTablesTableDef;
 use crate::queryplanner::now::MaterializeNow;
 use crate::queryplanner::planning::{choose_index_ext, ClusterSendNode};
@@ -34,6 +40,7 @@ use crate::queryplanner::serialized_plan::SerializedPlan;
 use crate::queryplanner::topk::ClusterAggregateTopK;
 use crate::queryplanner::udfs::aggregate_udf_by_kind;
 use crate::queryplanner::udfs::{scalar_udf_by_kind, CubeAggregateUDFKind, CubeScalarUDFKind};
+use crate::sql::query_log::QueryLog;
 use crate::store::DataFrame;
 use crate::{app_metrics, metastore, CubeError};
 use arrow::array::ArrayRef;
@@ -45,7 +52,7 @@ use core::fmt;
 use datafusion::catalog::TableReference;
 use datafusion::datasource::datasource::{Statistics, TableProviderFilterPushDown};
 use datafusion::error::DataFusionError;
-use datafusion::logical_plan::{Expr, LogicalPlan, PlanVisitor};
+use datafusion::logical_plan::{Expr, LogicalPlan, PlanVisitor, ToDFSchema};
 use datafusion::physical_plan::memory::MemoryExec;
 use datafusion::physical_plan::udaf::AggregateUDF;
 use datafusion::physical_plan::udf::ScalarUDF;
@@ -58,6 +65,7 @@ use log::{debug, trace};
 use mockall::automock;
 use serde_derive::{Deserialize, Serialize};
 use smallvec::alloc::fmt::Formatter;
+use sqlparser::ast::Expr as SQLExpr;
 use std::any::Any;
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
@@ -76,6 +84,7 @@ crate::di_service!(MockQueryPlanner, [QueryPlanner]);
 pub struct QueryPlannerImpl {
     meta_store: Arc<dyn MetaStore>,
     config: Arc<dyn ConfigObj>,
+    query_log: Arc<QueryLog>,
 }
 
 crate::di_service!(QueryPlannerImpl, [QueryPlanner]);
@@ -93,6 +102,7 @@ impl QueryPlanner for QueryPlannerImpl {
         let schema_provider = MetaStoreSchemaProvider::new(
             self.meta_store.get_tables_with_path(false).await?,
             self.meta_store.clone(),
+            self.query_log.clone(),
         );
 
         let query_planner = SqlToRel::new(&schema_provider);
@@ -106,6 +116,7 @@ impl QueryPlanner for QueryPlannerImpl {
                 &logical_plan,
                 &self.meta_store.as_ref(),
                 self.config.enable_topk(),
+                self.config.broadcast_join_max_rows(),
             )
             .await?;
             let workers = compute_workers(
@@ -144,8 +155,13 @@ impl QueryPlannerImpl {
     pub fn new(
         meta_store: Arc<dyn MetaStore>,
         config: Arc<dyn ConfigObj>,
+        query_log: Arc<QueryLog>,
     ) -> Arc<QueryPlannerImpl> {
-        Arc::new(QueryPlannerImpl { meta_store, config })
+        Arc::new(QueryPlannerImpl {
+            meta_store,
+            config,
+            query_log,
+        })
     }
 }
 
@@ -162,6 +178,7 @@ struct MetaStoreSchemaProvider {
     _data: Arc<Vec<TablePath>>,
     by_name: HashSet<TableKey>,
     meta_store: Arc<dyn MetaStore>,
+    query_log: Arc<QueryLog>,
 }
 
 /// Points into [MetaStoreSchemaProvider::data], never null.
@@ -192,12 +209,26 @@ impl Hash for TableKey {
 }
 
 impl MetaStoreSchemaProvider {
-    pub fn new(tables: Arc<Vec<TablePath>>, meta_store: Arc<dyn MetaStore>) -> Self {
+    pub fn new(
+        tables: Arc<Vec<TablePath>>,
+        meta_store: Arc<dyn MetaStore>,
+        query_log: Arc<QueryLog>,
+    ) -> Self {
         let by_name = tables.iter().map(|t| TableKey(t)).collect();
         Self {
             _data: tables,
             by_name,
             meta_store,
+            query_log,
+        }
+    }
+}
+
+impl MetaStoreSchemaProvider {
+    fn context(&self) -> InfoSchemaTableDefContext {
+        InfoSchemaTableDefContext {
+            meta_store: self.meta_store.clone(),
+            query_log: self.query_log.clone(),
         }
     }
 }
@@ -223,6 +254,10 @@ impl ContextProvider for MetaStoreSchemaProvider {
                     Vec::new(),
                     None,
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 ),
             ),
             schema: Arc::new(IdRow::new(0, metastore::Schema::new(schema.to_string()))),
@@ -249,33 +284,41 @@ impl ContextProvider for MetaStoreSchemaProvider {
             });
         res.or_else(|| match (schema, table) {
             ("information_schema", "tables") => Some(Arc::new(InfoSchemaTableProvider::new(
-                self.meta_store.clone(),
+                self.context(),
                 InfoSchemaTable::Tables,
             ))),
             ("information_schema", "schemata") => Some(Arc::new(InfoSchemaTableProvider::new(
-                self.meta_store.clone(),
+                self.context(),
                 InfoSchemaTable::Schemata,
             ))),
             ("system", "tables") => Some(Arc::new(InfoSchemaTableProvider::new(
-                self.meta_store.clone(),
+                self.context(),
                 InfoSchemaTable::SystemTables,
             ))),
             ("system", "indexes") => Some(Arc::new(InfoSchemaTableProvider::new(
-                self.meta_store.clone(),
+                self.context(),
                 InfoSchemaTable::SystemIndexes,
             ))),
             ("system", "partitions") => Some(Arc::new(InfoSchemaTableProvider::new(
-                self.meta_store.clone(),
+                self.context(),
                 InfoSchemaTable::SystemPartitions,
             ))),
             ("system", "chunks") => Some(Arc::new(InfoSchemaTableProvider::new(
-                self.meta_store.clone(),
+                self.context(),
                 InfoSchemaTable::SystemChunks,
             ))),
             ("system", "jobs") => Some(Arc::new(InfoSchemaTableProvider::new(
-                self.meta_store.clone(),
+                self.context(),
                 InfoSchemaTable::SystemJobs,
             ))),
+            ("system", "queries") => Some(Arc::new(InfoSchemaTableProvider::new(
+                self.context(),
+                InfoSchemaTable::SystemQueries,
+            ))),
+            ("system", "query_log") => Some(Arc::new(InfoSchemaTableProvider::new(
+                self.context(),
+                InfoSchemaTable::SystemQueryLog,
+            ))),
             _ => None,
         })
     }
@@ -288,22 +331,52 @@ impl ContextProvider for MetaStoreSchemaProvider {
             "unix_timestamp" | "UNIX_TIMESTAMP" => CubeScalarUDFKind::UnixTimestamp,
             "date_add" | "DATE_ADD" => CubeScalarUDFKind::DateAdd,
             "date_sub" | "DATE_SUB" => CubeScalarUDFKind::DateSub,
+            "quantile" | "QUANTILE" => CubeScalarUDFKind::TDigestQuantile,
+            "hll_export" | "HLL_EXPORT" => CubeScalarUDFKind::HllExport,
             _ => return None,
         };
         return Some(Arc::new(scalar_udf_by_kind(kind).descriptor()));
     }
 
     fn get_aggregate_meta(&self, name: &str) -> Option<Arc<AggregateUDF>> {
-        // HyperLogLog.
+        // HyperLogLog and t-digest.
         // TODO: case-insensitive names.
         let kind = match name {
             "merge" | "MERGE" => CubeAggregateUDFKind::MergeHll,
+            "merge_tdigest" | "MERGE_TDIGEST" => CubeAggregateUDFKind::MergeTDigest,
+            "hll_init" | "HLL_INIT" => CubeAggregateUDFKind::HllInit,
+            "hllpp_init" | "HLLPP_INIT" => CubeAggregateUDFKind::HllppInit,
             _ => return None,
         };
         return Some(Arc::new(aggregate_udf_by_kind(kind).descriptor()));
     }
 }
 
+/// Plans a standalone filter, e.g. a `DELETE` predicate, over columns of the `schema`.
+/// Tables and Cube Store specific functions aren't available there.
+pub fn plan_filter_expr(expr: &SQLExpr, schema: &Schema) -> Result<Expr, CubeError> {
+    Ok(
+        SqlToRel::new(&FilterContextProvider {})
+            .sql_to_rex(expr, &schema.clone().to_dfschema()?)?,
+    )
+}
+
+struct FilterContextProvider {}
+
+impl ContextProvider for FilterContextProvider {
+    fn get_table_provider(&self, _name: TableReference) -> Option<Arc<dyn TableProvider>> {
+        None
+    }
+
+    fn get_function_meta(&self, _name: &str) -> Option<Arc<ScalarUDF>> {
+        None
+    }
+
+    fn get_aggregate_meta(&self, _name: &str) -> Option<Arc<AggregateUDF>> {
+        None
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum InfoSchemaTable {
     Tables,
@@ -313,13 +386,22 @@ pub enum InfoSchemaTable {
     SystemIndexes,
     SystemPartitions,
     SystemChunks,
+    SystemQueries,
+    SystemQueryLog,
+}
+
+/// Sources of the info schema table rows.
+#[derive(Clone)]
+pub struct InfoSchemaTableDefContext {
+    pub meta_store: Arc<dyn MetaStore>,
+    pub query_log: Arc<QueryLog>,
 }
 
 #[async_trait]
 pub trait InfoSchemaTableDef {
     type T: Send + Sync;
 
-    async fn rows(&self, meta_store: Arc<dyn MetaStore>) -> Result<Arc<Vec<Self::T>>, CubeError>;
+    async fn rows(&self, ctx: InfoSchemaTableDefContext) -> Result<Arc<Vec<Self::T>>, CubeError>;
 
     fn columns(&self) -> Vec<(Field, Box<dyn Fn(Arc<Vec<Self::T>>) -> ArrayRef>)>;
 }
@@ -328,7 +410,7 @@ pub trait InfoSchemaTableDef {
 pub trait BaseInfoSchemaTableDef {
     fn schema(&self) -> SchemaRef;
 
-    async fn scan(&self, meta_store: Arc<dyn MetaStore>) -> Result<RecordBatch, CubeError>;
+    async fn scan(&self, ctx: InfoSchemaTableDefContext) -> Result<RecordBatch, CubeError>;
 }
 
 #[macro_export]
@@ -347,9 +429,9 @@ macro_rules! base_info_schema_table_def {
 
             async fn scan(
                 &self,
-                meta_store: Arc<dyn crate::metastore::MetaStore>,
+                ctx: crate::queryplanner::InfoSchemaTableDefContext,
             ) -> Result<arrow::record_batch::RecordBatch, crate::CubeError> {
-                let rows = self.rows(meta_store).await?;
+                let rows = self.rows(ctx).await?;
                 let schema = self.schema();
                 let columns = self.columns();
                 let columns = columns
@@ -372,6 +454,8 @@ impl InfoSchemaTable {
             InfoSchemaTable::SystemChunks => Box::new(SystemChunksTableDef),
             InfoSchemaTable::SystemPartitions => Box::new(SystemPartitionsTableDef),
             InfoSchemaTable::SystemJobs => Box::new(SystemJobsTableDef),
+            InfoSchemaTable::SystemQueries => Box::new(SystemQueriesTableDef),
+            InfoSchemaTable::SystemQueryLog => Box::new(SystemQueryLogTableDef),
         }
     }
 
@@ -379,19 +463,19 @@ impl InfoSchemaTable {
         self.table_def().schema()
     }
 
-    async fn scan(&self, meta_store: Arc<dyn MetaStore>) -> Result<RecordBatch, CubeError> {
-        self.table_def().scan(meta_store).await
+    async fn scan(&self, ctx: InfoSchemaTableDefContext) -> Result<RecordBatch, CubeError> {
+        self.table_def().scan(ctx).await
     }
 }
 
 pub struct InfoSchemaTableProvider {
-    meta_store: Arc<dyn MetaStore>,
+    ctx: InfoSchemaTableDefContext,
     table: InfoSchemaTable,
 }
 
 impl InfoSchemaTableProvider {
-    fn new(meta_store: Arc<dyn MetaStore>, table: InfoSchemaTable) -> InfoSchemaTableProvider {
-        InfoSchemaTableProvider { meta_store, table }
+    fn new(ctx: InfoSchemaTableDefContext, table: InfoSchemaTable) -> InfoSchemaTableProvider {
+        InfoSchemaTableProvider { ctx, table }
     }
 }
 
@@ -412,7 +496,7 @@ impl TableProvider for InfoSchemaTableProvider {
         _limit: Option<usize>,
     ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
         let exec = InfoSchemaTableExec {
-            meta_store: self.meta_store.clone(),
+            ctx: self.ctx.clone(),
             table: self.table.clone(),
             projection: projection.clone(),
             projected_schema: project_schema(&self.schema(), projection.as_deref()),
@@ -443,7 +527,7 @@ fn project_schema(s: &Schema, projection: Option<&[usize]>) -> SchemaRef {
 
 #[derive(Clone)]
 pub struct InfoSchemaTableExec {
-    meta_store: Arc<dyn MetaStore>,
+    ctx: InfoSchemaTableDefContext,
     table: InfoSchemaTable,
     projected_schema: SchemaRef,
     projection: Option<Vec<usize>>,
@@ -484,7 +568,7 @@ impl ExecutionPlan for InfoSchemaTableExec {
         &self,
         partition: usize,
     ) -> Result<SendableRecordBatchStream, DataFusionError> {
-        let batch = self.table.scan(self.meta_store.clone()).await?;
+        let batch = self.table.scan(self.ctx.clone()).await?;
         let mem_exec =
             MemoryExec::try_new(&vec![vec![batch]], self.schema(), self.projection.clone())?;
         mem_exec.execute(partition).await
@@ -550,16 +634,20 @@ fn compute_workers(
             match plan {
                 LogicalPlan::Extension { node } => {
                     let snapshots;
+                    let distribution;
                     if let Some(cs) = node.as_any().downcast_ref::<ClusterSendNode>() {
                         snapshots = &cs.snapshots;
+                        distribution = &cs.distribution;
                     } else if let Some(cs) = node.as_any().downcast_ref::<ClusterAggregateTopK>() {
                         snapshots = &cs.snapshots;
+                        distribution = &cs.distribution;
                     } else {
                         return Ok(true);
                     }
                     let workers = ClusterSendExec::distribute_to_workers(
                         self.config,
                         snapshots.as_slice(),
+                        distribution,
                         self.tree,
                     );
                     self.workers = workers.into_iter().map(|w| w.0).collect();
@@ -1,7 +1,9 @@
+use crate::metastore::{Chunk, Column, Partition};
+use crate::table::stats::ColumnStats;
 use crate::table::{cmp_same_types, TableValue};
 use crate::util::decimal::Decimal;
 use arrow::datatypes::{DataType, Schema};
-use datafusion::logical_plan::{Column, Expr, Operator};
+use datafusion::logical_plan::{Column as FusionColumn, Expr, Operator};
 use datafusion::scalar::ScalarValue;
 use std::cmp::Ordering;
 
@@ -44,6 +46,147 @@ impl PartitionFilter {
             (None, None) => true,
         }
     }
+
+    pub fn matches_everything(&self) -> bool {
+        self.min_max.is_empty()
+    }
+
+    /// Returns all values of the first column that can match when each condition pins it to a
+    /// single value, e.g. for `a = 1 OR a IN (2, 3)`.
+    pub fn point_values(&self) -> Option<Vec<&TableValue>> {
+        if self.min_max.is_empty() {
+            return None;
+        }
+        self.min_max
+            .iter()
+            .map(|mm| match (&mm.min[0], &mm.max[0]) {
+                (Some(min), Some(max)) if min == max => Some(min),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Skips chunks and partitions using [ColumnStats] recorded in the metastore. Unlike
+/// [PartitionFilter], which only looks at the sort key prefix, conditions are extracted for each
+/// column separately.
+#[derive(Debug)]
+pub struct ChunkFilter {
+    columns: Vec<ColumnCondition>,
+}
+
+#[derive(Debug)]
+struct ColumnCondition {
+    column: Column,
+    filter: PartitionFilter,
+    /// Set to true by `IS NULL` and to false by `IS NOT NULL`.
+    is_null: Option<bool>,
+}
+
+impl ChunkFilter {
+    pub fn extract(columns: &[Column], filters: &[Expr]) -> ChunkFilter {
+        let mut null_checks = Vec::new();
+        for f in filters {
+            extract_null_checks(f, &mut null_checks);
+        }
+
+        let columns = columns
+            .iter()
+            .filter_map(|c| {
+                let filter = PartitionFilter::extract(&Schema::new(vec![c.into()]), filters);
+                let is_null = null_checks
+                    .iter()
+                    .find(|(name, _)| name == c.get_name())
+                    .map(|(_, is_null)| *is_null);
+                if filter.matches_everything() && is_null.is_none() {
+                    return None;
+                }
+                Some(ColumnCondition {
+                    column: c.clone(),
+                    filter,
+                    is_null,
+                })
+            })
+            .collect();
+        ChunkFilter { columns }
+    }
+
+    pub fn matches_everything(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Returns whether any rows of the chunk could potentially match the filter. Chunks without
+    /// stats always match.
+    pub fn can_match(&self, chunk: &Chunk) -> bool {
+        self.can_match_stats(chunk.column_stats(), chunk.get_row_count())
+    }
+
+    /// Same as [ChunkFilter::can_match] for the main table file of a partition. Partitions
+    /// without a main table file never match.
+    pub fn can_match_main_table(&self, partition: &Partition) -> bool {
+        if !partition.has_main_table_file() {
+            return false;
+        }
+        self.can_match_stats(partition.column_stats(), partition.main_table_row_count())
+    }
+
+    fn can_match_stats(&self, stats: &Option<Vec<ColumnStats>>, row_count: u64) -> bool {
+        let stats = match stats {
+            Some(stats) => stats,
+            None => return true,
+        };
+        self.columns.iter().all(|c| {
+            // Columns could have been dropped and added again with a different type.
+            match stats.iter().find(|s| {
+                s.name() == c.column.get_name() && s.column_type() == c.column.get_column_type()
+            }) {
+                Some(s) => c.can_match(s, row_count),
+                None => true,
+            }
+        })
+    }
+}
+
+impl ColumnCondition {
+    fn can_match(&self, stats: &ColumnStats, row_count: u64) -> bool {
+        match self.is_null {
+            Some(true) if stats.null_count() == 0 => return false,
+            Some(false) if stats.null_count() == row_count => return false,
+            _ => {}
+        }
+        if self.filter.matches_everything() {
+            return true;
+        }
+        // Comparisons never match NULLs, so we can skip chunks with NULLs only.
+        let (min, max) = match (stats.min(), stats.max()) {
+            (Some(min), Some(max)) => (min.clone(), max.clone()),
+            _ => return stats.null_count() != row_count,
+        };
+        if !self.filter.can_match(Some(&[min]), Some(&[max])) {
+            return false;
+        }
+        match self.filter.point_values() {
+            Some(values) => values.iter().any(|v| stats.may_contain(v)),
+            None => true,
+        }
+    }
+}
+
+/// Collects `<column> IS [NOT] NULL` conditions from the top-level conjunction.
+fn extract_null_checks(e: &Expr, r: &mut Vec<(String, bool)>) {
+    match e {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => {
+            extract_null_checks(left, r);
+            extract_null_checks(right, r);
+        }
+        Expr::IsNull(box Expr::Column(c)) => r.push((c.name.clone(), true)),
+        Expr::IsNotNull(box Expr::Column(c)) => r.push((c.name.clone(), false)),
+        _ => {}
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -281,7 +424,7 @@ impl Builder<'_> {
 
     fn extract_column_compare(
         &self,
-        col: &Column,
+        col: &FusionColumn,
         op: Operator,
         value: &Expr,
     ) -> Option<ColumnStat> {
@@ -532,7 +675,9 @@ impl Builder<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::metastore::ColumnType;
     use crate::sql::parser::{CubeStoreParser, Statement as CubeStatement};
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
     use arrow::datatypes::Field;
     use datafusion::catalog::TableReference;
     use datafusion::datasource::TableProvider;
@@ -1104,6 +1249,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_chunk_filter() {
+        let columns = vec![
+            Column::new("t".to_string(), ColumnType::Int, 0),
+            Column::new("country".to_string(), ColumnType::String, 1),
+        ];
+        let chunk = |t: Vec<Option<i64>>, country: Vec<Option<&str>>| {
+            let data: Vec<ArrayRef> = vec![
+                Arc::new(Int64Array::from(t.clone())),
+                Arc::new(StringArray::from(country)),
+            ];
+            let stats = ColumnStats::compute(&columns, &data, &["country".to_string()]);
+            Chunk::new(1, t.len(), false).set_column_stats(Some(stats))
+        };
+        let chunks = vec![
+            chunk(vec![Some(1), Some(2)], vec![Some("DE"), Some("DE")]),
+            chunk(vec![Some(3), None], vec![Some("FR"), Some("US")]),
+            chunk(vec![None, None], vec![None, None]),
+            Chunk::new(1, 2, false),
+        ];
+
+        let s = schema(&[("t", DataType::Int64), ("country", DataType::Utf8)]);
+        let matches = |sql: &str| {
+            let f = ChunkFilter::extract(&columns, &[parse(sql, &s)]);
+            chunks.iter().map(|c| f.can_match(c)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(matches("true"), vec![true, true, true, true]);
+        assert_eq!(matches("t > 2"), vec![false, true, false, true]);
+        assert_eq!(matches("t <= 2"), vec![true, false, false, true]);
+        assert_eq!(matches("country = 'DE'"), vec![true, false, false, true]);
+        // Within min/max of the second chunk, rejected by the bloom filter.
+        assert_eq!(matches("country = 'GB'"), vec![false, false, false, true]);
+        assert_eq!(
+            matches("country IN ('GB', 'US')"),
+            vec![false, true, false, true]
+        );
+        assert_eq!(matches("country > 'E'"), vec![false, true, false, true]);
+        assert_eq!(
+            matches("country = 'DE' AND t > 2"),
+            vec![false, false, false, true]
+        );
+        assert_eq!(
+            matches("country = 'DE' OR t > 2"),
+            vec![true, true, true, true]
+        );
+        assert_eq!(matches("t IS NULL"), vec![false, true, true, true]);
+        assert_eq!(matches("t IS NOT NULL"), vec![true, true, false, true]);
+        assert_eq!(
+            matches("t IS NULL AND country = 'US'"),
+            vec![false, true, false, true]
+        );
+
+        // Stats of a column with another type are ignored.
+        let other_type = vec![Column::new("t".to_string(), ColumnType::String, 0)];
+        let s = schema(&[("t", DataType::Utf8)]);
+        let f = ChunkFilter::extract(&other_type, &[parse("t = 'a'", &s)]);
+        assert!(!f.matches_everything());
+        assert!(chunks.iter().all(|c| f.can_match(c)));
+    }
+
     fn schema(s: &[(&str, DataType)]) -> Schema {
         Schema::new(
             s.iter()
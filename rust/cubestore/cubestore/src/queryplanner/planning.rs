@@ -24,7 +24,9 @@ use arrow::datatypes::{Field, SchemaRef};
 use async_trait::async_trait;
 use datafusion::error::DataFusionError;
 use datafusion::execution::context::ExecutionContextState;
-use datafusion::logical_plan::{DFSchemaRef, Expr, LogicalPlan, Operator, UserDefinedLogicalNode};
+use datafusion::logical_plan::{
+    DFSchemaRef, Expr, JoinType, LogicalPlan, Operator, PlanVisitor, UserDefinedLogicalNode,
+};
 use datafusion::physical_plan::aggregates::AggregateFunction as FusionAggregateFunction;
 use datafusion::physical_plan::empty::EmptyExec;
 use datafusion::physical_plan::planner::ExtensionPlanner;
@@ -39,14 +41,21 @@ use crate::cluster::Cluster;
 use crate::metastore::multi_index::MultiPartition;
 use crate::metastore::table::{Table, TablePath};
 use crate::metastore::{
-    AggregateFunction, Chunk, Column, IdRow, Index, IndexType, MetaStore, Partition, Schema,
+    AggregateFunction, Chunk, Column, ColumnType, IdRow, Index, IndexType, MetaStore, Partition,
+    Schema,
 };
+use crate::queryplanner::hash_join::{plan_hash_join, HashJoinNode};
 use crate::queryplanner::optimizations::rewrite_plan::{rewrite_plan, PlanRewriter};
 use crate::queryplanner::panic::{plan_panic_worker, PanicWorkerNode};
-use crate::queryplanner::partition_filter::PartitionFilter;
+use crate::queryplanner::partition_filter::{ChunkFilter, PartitionFilter};
 use crate::queryplanner::query_executor::{ClusterSendExec, CubeTable};
-use crate::queryplanner::serialized_plan::{IndexSnapshot, PartitionSnapshot, SerializedPlan};
+use crate::queryplanner::serialized_plan::{
+    IndexSnapshot, PartitionSnapshot, PruningStats, SerializedPlan,
+};
 use crate::queryplanner::topk::{materialize_topk, plan_topk, ClusterAggregateTopK};
+use crate::queryplanner::udfs::{
+    aggregate_kind_by_name, aggregate_udf_by_kind, CubeAggregateUDFKind,
+};
 use crate::queryplanner::CubeTableLogical;
 use crate::CubeError;
 use datafusion::logical_plan;
@@ -62,7 +71,7 @@ pub async fn choose_index(
     p: &LogicalPlan,
     metastore: &dyn PlanIndexStore,
 ) -> Result<(LogicalPlan, PlanningMeta), DataFusionError> {
-    choose_index_ext(p, metastore, true).await
+    choose_index_ext(p, metastore, true, 100_000).await
 }
 
 /// Information required to distribute the logical plan into multiple workers.
@@ -90,7 +99,9 @@ pub async fn choose_index_ext(
     p: &LogicalPlan,
     metastore: &dyn PlanIndexStore,
     enable_topk: bool,
+    broadcast_join_max_rows: u64,
 ) -> Result<(LogicalPlan, PlanningMeta), DataFusionError> {
+    let p = &resolve_sketch_merges(p)?;
     // Prepare information to choose the index.
     let mut collector = CollectConstraints::default();
     rewrite_plan(p, &ConstraintsContext::default(), &mut collector)?;
@@ -123,11 +134,7 @@ pub async fn choose_index_ext(
             .into_iter()
             .map(|c| c.partitioned_index.unwrap())
             .collect(),
-        // We sometimes propagate 'index for join not found' error here.
-        false => candidates
-            .into_iter()
-            .map(|c| c.ordinary_index)
-            .collect::<Result<_, DataFusionError>>()?,
+        false => candidates.into_iter().map(|c| c.ordinary_index).collect(),
     };
 
     // TODO should be single snapshot read to ensure read consistency here
@@ -142,7 +149,9 @@ pub async fn choose_index_ext(
         .zip(collector.constraints.iter())
         .zip(partitions)
     {
-        i.partitions = pick_partitions(i, c, ps)?
+        let (partitions, pruning) = pick_partitions(i, c, ps)?;
+        i.partitions = partitions;
+        i.pruning = pruning;
     }
 
     // We have enough information to finalize the logical plan.
@@ -150,6 +159,7 @@ pub async fn choose_index_ext(
         chosen_indices: &indices,
         next_index: 0,
         enable_topk,
+        broadcast_join_max_rows,
     };
     let plan = rewrite_plan(p, &(), &mut r)?;
     assert_eq!(r.next_index, indices.len());
@@ -428,6 +438,7 @@ struct ChooseIndex<'a> {
     next_index: usize,
     chosen_indices: &'a [IndexSnapshot],
     enable_topk: bool,
+    broadcast_join_max_rows: u64,
 }
 
 impl PlanRewriter for ChooseIndex<'_> {
@@ -439,6 +450,7 @@ impl PlanRewriter for ChooseIndex<'_> {
         _: &Self::Context,
     ) -> Result<LogicalPlan, DataFusionError> {
         let p = self.choose_table_index(n)?;
+        let p = try_hash_join(p, self.broadcast_join_max_rows)?;
         let mut p = pull_up_cluster_send(p)?;
         if self.enable_topk {
             p = materialize_topk(p)?;
@@ -493,6 +505,7 @@ impl ChooseIndex<'_> {
                 return Ok(ClusterSendNode {
                     input: Arc::new(p),
                     snapshots: vec![vec![snapshot]],
+                    distribution: JoinDistribution::CoPartitioned,
                 }
                 .into_plan());
             }
@@ -502,8 +515,7 @@ impl ChooseIndex<'_> {
 }
 
 struct IndexCandidate {
-    /// May contain for unmatched index.
-    pub ordinary_index: Result<IndexSnapshot, DataFusionError>,
+    pub ordinary_index: IndexSnapshot,
     pub partitioned_index: Option<IndexSnapshot>,
 }
 
@@ -511,6 +523,11 @@ fn check_aggregates_expr(table: &IdRow<Table>, aggregates: &Vec<Expr>) -> bool {
     let table_aggregates = table.get_row().aggregate_columns();
 
     for aggr in aggregates.iter() {
+        // Merges of t-digest columns are aliased by [resolve_sketch_merges].
+        let aggr = match aggr {
+            Expr::Alias(e, _) => e.as_ref(),
+            e => e,
+        };
         match aggr {
             Expr::AggregateFunction { fun, args, .. } => {
                 if args.len() != 1 {
@@ -547,7 +564,7 @@ fn check_aggregates_expr(table: &IdRow<Table>, aggregates: &Vec<Expr>) -> bool {
                 }
 
                 let aggr_fun = match fun.name.to_uppercase().as_str() {
-                    "MERGE" => Some(AggregateFunction::MERGE),
+                    "MERGE" | "MERGE_TDIGEST" => Some(AggregateFunction::MERGE),
                     _ => None,
                 };
 
@@ -576,6 +593,123 @@ fn check_aggregates_expr(table: &IdRow<Table>, aggregates: &Vec<Expr>) -> bool {
     true
 }
 
+/// `merge()` is resolved to the HyperLogLog merge by name, here it's replaced by the t-digest
+/// merge for t-digest columns. The result keeps the original name, so the plan schema does not
+/// change. `merge_tdigest()` is only allowed on t-digest columns.
+fn resolve_sketch_merges(p: &LogicalPlan) -> Result<LogicalPlan, DataFusionError> {
+    struct ResolveMerges {}
+    impl PlanRewriter for ResolveMerges {
+        type Context = ();
+
+        fn rewrite(&mut self, n: LogicalPlan, _: &()) -> Result<LogicalPlan, DataFusionError> {
+            match n {
+                LogicalPlan::Aggregate {
+                    input,
+                    group_expr,
+                    aggr_expr,
+                    schema,
+                } => {
+                    let aggr_expr = aggr_expr
+                        .into_iter()
+                        .map(|e| resolve_sketch_merge(e, &input))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(LogicalPlan::Aggregate {
+                        input,
+                        group_expr,
+                        aggr_expr,
+                        schema,
+                    })
+                }
+                n => Ok(n),
+            }
+        }
+    }
+    rewrite_plan(p, &(), &mut ResolveMerges {})
+}
+
+fn resolve_sketch_merge(e: Expr, input: &LogicalPlan) -> Result<Expr, DataFusionError> {
+    let (kind, column) = match &e {
+        Expr::AggregateUDF { fun, args } => match (
+            aggregate_kind_by_name(&fun.name.to_uppercase()),
+            args.as_slice(),
+        ) {
+            (Some(kind), [Expr::Column(c)]) => (kind, c),
+            _ => return Ok(e),
+        },
+        _ => return Ok(e),
+    };
+    let column_type = match scan_column_type(input, column) {
+        Some(t) => t,
+        // Computed values are not checked.
+        None => return Ok(e),
+    };
+    match (kind, column_type) {
+        (CubeAggregateUDFKind::MergeHll, ColumnType::TDigest) => {
+            let name = e.name(input.schema())?;
+            Ok(Expr::Alias(
+                Box::new(Expr::AggregateUDF {
+                    fun: Arc::new(
+                        aggregate_udf_by_kind(CubeAggregateUDFKind::MergeTDigest).descriptor(),
+                    ),
+                    args: vec![Expr::Column(column.clone())],
+                }),
+                name,
+            ))
+        }
+        (CubeAggregateUDFKind::MergeTDigest, t) if t != ColumnType::TDigest => {
+            Err(DataFusionError::Plan(format!(
+                "MERGE_TDIGEST expects a tdigest column, but {} has type {}",
+                column.name, t
+            )))
+        }
+        _ => Ok(e),
+    }
+}
+
+/// Type of the table column read as `column` by a scan in `p`.
+fn scan_column_type(p: &LogicalPlan, column: &logical_plan::Column) -> Option<ColumnType> {
+    struct FindColumn<'a> {
+        column: &'a logical_plan::Column,
+        column_type: Option<ColumnType>,
+    }
+    impl PlanVisitor for FindColumn<'_> {
+        type Error = ();
+
+        fn pre_visit(&mut self, p: &LogicalPlan) -> Result<bool, ()> {
+            match p {
+                LogicalPlan::TableScan {
+                    source,
+                    projected_schema,
+                    ..
+                } if projected_schema.field_from_column(self.column).is_ok() => {
+                    self.column_type =
+                        source
+                            .as_any()
+                            .downcast_ref::<CubeTableLogical>()
+                            .and_then(|t| {
+                                t.table
+                                    .table
+                                    .get_row()
+                                    .get_columns()
+                                    .iter()
+                                    .find(|c| c.get_name() == &self.column.name)
+                                    .map(|c| c.get_column_type().clone())
+                            });
+                    Ok(false)
+                }
+                _ => Ok(true),
+            }
+        }
+    }
+
+    let mut v = FindColumn {
+        column,
+        column_type: None,
+    };
+    let _ = p.accept(&mut v);
+    v.column_type
+}
+
 // Picks the index, but not partitions snapshots.
 async fn pick_index(
     c: &IndexConstraints,
@@ -680,38 +814,28 @@ async fn pick_index(
             optimal_index_by_score(filtered_by_sort_on, &projection_columns, &filter_columns);
         if let Some(index) = optimal_with_partitioned_index.or(optimal) {
             (
-                Ok(index),
+                index,
                 index.get_row().multi_index_id().map(|_| index),
                 sort_on,
             )
         } else {
             if let Some((join_on_columns, true)) = sort_on.as_ref() {
-                let table_name = c.table.table_name();
-                let err = Err(DataFusionError::Plan(format!(
-                    "Can't find index to join table {} on {}. Consider creating index: CREATE INDEX {}_{} ON {} ({})",
-                    table_name,
-                    join_on_columns.join(", "),
-                    table.get_row().get_table_name(),
-                    join_on_columns.join("_"),
-                    table_name,
+                // The snapshot without `sort_on` makes [try_hash_join] plan a hash join.
+                log::trace!(
+                    "No index to join table {} on {}, falling back to hash join",
+                    c.table.table_name(),
                     join_on_columns.join(", ")
-                )));
-                (err, None, sort_on)
-            } else {
-                let optimal = optimal_index_by_score(
-                    // Skipping default index
-                    indices.iter().skip(1),
-                    &projection_columns,
-                    &filter_columns,
                 );
-
-                let index = optimal.unwrap_or(default_index);
-                (
-                    Ok(index),
-                    index.get_row().multi_index_id().map(|_| index),
-                    None,
-                )
             }
+            let optimal = optimal_index_by_score(
+                // Skipping default index
+                indices.iter().skip(1),
+                &projection_columns,
+                &filter_columns,
+            );
+
+            let index = optimal.unwrap_or(default_index);
+            (index, index.get_row().multi_index_id().map(|_| index), None)
         }
     } else {
         if let Some((join_on_columns, _)) = sort_on {
@@ -721,7 +845,7 @@ async fn pick_index(
                 join_on_columns.join(", ")
             )));
         }
-        (Ok(default_index), None, None)
+        (default_index, None, None)
     };
 
     // Only use partitioned index for joins. Joins are indicated by the required flag.
@@ -752,10 +876,11 @@ async fn pick_index(
                 schema: schema.clone(),
             },
             sort_on: index_sort_on,
+            pruning: PruningStats::default(),
         }
     };
     Ok(IndexCandidate {
-        ordinary_index: index.map(create_snapshot),
+        ordinary_index: create_snapshot(index),
         partitioned_index: partitioned_index.map(create_snapshot),
     })
 }
@@ -844,11 +969,15 @@ fn pick_partitions(
     i: &IndexSnapshot,
     c: &IndexConstraints,
     partitions: Vec<(IdRow<Partition>, Vec<IdRow<Chunk>>)>,
-) -> Result<Vec<PartitionSnapshot>, DataFusionError> {
+) -> Result<(Vec<PartitionSnapshot>, PruningStats), DataFusionError> {
     let partition_filter = PartitionFilter::extract(&partition_filter_schema(&i.index), &c.filters);
     log::trace!("Extracted partition filter is {:?}", partition_filter);
-    let candidate_partitions = partitions.len();
-    let mut pruned_partitions = 0;
+    let chunk_filter = chunk_filter(i, &c.filters);
+    log::trace!("Extracted chunk filter is {:?}", chunk_filter);
+    let mut pruning = PruningStats {
+        total_partitions: partitions.len(),
+        ..PruningStats::default()
+    };
 
     let mut partition_snapshots = Vec::new();
     for (partition, chunks) in partitions.into_iter() {
@@ -864,19 +993,82 @@ fn pick_partitions(
             .map(|r| r.values().as_slice());
 
         if !partition_filter.can_match(min_row, max_row) {
-            pruned_partitions += 1;
+            pruning.pruned_partitions += 1;
             continue;
         }
 
-        partition_snapshots.push(PartitionSnapshot { chunks, partition });
+        let candidate_chunks = chunks.len();
+        let chunks = match &chunk_filter {
+            Some(f) => chunks
+                .into_iter()
+                .filter(|c| f.can_match(c.get_row()))
+                .collect_vec(),
+            None => chunks,
+        };
+        if let Some(f) = &chunk_filter {
+            if chunks.is_empty() && !f.can_match_main_table(partition.get_row()) {
+                pruning.pruned_partitions += 1;
+                continue;
+            }
+        }
+        pruning.total_chunks += candidate_chunks;
+        pruning.pruned_chunks += candidate_chunks - chunks.len();
+        partition_snapshots.push(snapshot_without_stats(partition, chunks));
     }
     log::trace!(
-        "Pruned {} of {} partitions",
-        pruned_partitions,
-        candidate_partitions
+        "Pruned {} of {} partitions and {} of {} chunks",
+        pruning.pruned_partitions,
+        pruning.total_partitions,
+        pruning.pruned_chunks,
+        pruning.total_chunks
     );
 
-    Ok(partition_snapshots)
+    Ok((partition_snapshots, pruning))
+}
+
+/// Column stats are only needed for pruning, so they are not sent to workers with the plan.
+fn snapshot_without_stats(
+    partition: IdRow<Partition>,
+    chunks: Vec<IdRow<Chunk>>,
+) -> PartitionSnapshot {
+    PartitionSnapshot {
+        partition: IdRow::new(
+            partition.get_id(),
+            partition.get_row().set_column_stats(None),
+        ),
+        chunks: chunks
+            .into_iter()
+            .map(|c| IdRow::new(c.get_id(), c.get_row().set_column_stats(None)))
+            .collect(),
+    }
+}
+
+/// Chunks and partitions are not skipped by column stats for tables with unique keys: that could
+/// hide the latest version of a row and expose an older one from another file. Aggregate columns
+/// are ignored as their values are merged across files.
+fn chunk_filter(i: &IndexSnapshot, filters: &[Expr]) -> Option<ChunkFilter> {
+    let table = i.table().get_row();
+    if table.unique_key_columns().is_some() {
+        return None;
+    }
+    let aggregate_columns = table.aggregate_columns();
+    let columns = i
+        .index
+        .get_row()
+        .columns()
+        .iter()
+        .filter(|c| {
+            !aggregate_columns
+                .iter()
+                .any(|a| a.column().get_name() == c.get_name())
+        })
+        .cloned()
+        .collect_vec();
+    let filter = ChunkFilter::extract(&columns, filters);
+    if filter.matches_everything() {
+        return None;
+    }
+    Some(filter)
 }
 
 fn partition_filter_schema(index: &IdRow<Index>) -> arrow::datatypes::Schema {
@@ -895,6 +1087,34 @@ fn partition_filter_schema(index: &IdRow<Index>) -> arrow::datatypes::Schema {
 pub struct ClusterSendNode {
     pub input: Arc<LogicalPlan>,
     pub snapshots: Vec<Vec<IndexSnapshot>>,
+    pub distribution: JoinDistribution,
+}
+
+/// Describes how partitions of [ClusterSendNode::snapshots] are distributed between workers.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinDistribution {
+    /// Partitions of different snapshot groups are multiplied. Used for plain scans and for merge
+    /// joins on indices sorted by the join columns.
+    CoPartitioned,
+    /// Snapshot groups at these positions are sent to every worker in full, the rest are
+    /// distributed as in [JoinDistribution::CoPartitioned].
+    Broadcast(Vec<usize>),
+    /// All partitions are sent to a single worker.
+    SingleWorker,
+    /// All partitions are sent to every worker, which joins only the rows with join keys hashing
+    /// to its own bucket. Used for hash joins where neither side can be sent to every worker.
+    HashPartitioned,
+}
+
+impl JoinDistribution {
+    /// Workers don't get all rows of the hash partitioned inputs, so any join on top of them has
+    /// to run on a single worker.
+    fn is_single_worker_only(&self) -> bool {
+        match self {
+            JoinDistribution::SingleWorker | JoinDistribution::HashPartitioned => true,
+            JoinDistribution::CoPartitioned | JoinDistribution::Broadcast(_) => false,
+        }
+    }
 }
 
 impl ClusterSendNode {
@@ -941,12 +1161,155 @@ impl UserDefinedLogicalNode for ClusterSendNode {
         Arc::new(ClusterSendNode {
             input: Arc::new(inputs[0].clone()),
             snapshots: self.snapshots.clone(),
+            distribution: self.distribution.clone(),
         })
     }
 }
 
+/// Replaces a join that can't use indices sorted by the join columns with a [HashJoinNode].
+/// The smaller side is sent to all workers if it's small enough and the join type allows it,
+/// while partitions of the other side are split between workers. Otherwise rows of both sides
+/// are split between workers by hashes of the join keys. The hash table is built on the
+/// broadcast or, failing that, smaller side.
+fn try_hash_join(
+    p: LogicalPlan,
+    broadcast_join_max_rows: u64,
+) -> Result<LogicalPlan, DataFusionError> {
+    let (left, right, on, join_type, schema) = match &p {
+        LogicalPlan::Join {
+            left,
+            right,
+            on,
+            join_type,
+            schema,
+            ..
+        } => (left, right, on, join_type, schema),
+        _ => return Ok(p),
+    };
+    let (lsend, rsend) = match (
+        try_extract_cluster_send(left),
+        try_extract_cluster_send(right),
+    ) {
+        (Some(l), Some(r)) => (l, r),
+        // Reported by [pull_up_cluster_send].
+        _ => return Ok(p),
+    };
+    if is_sorted_for_join(lsend) && is_sorted_for_join(rsend) {
+        return Ok(p);
+    }
+
+    let (can_broadcast_left, can_broadcast_right) = match join_type {
+        JoinType::Inner => (true, true),
+        JoinType::Right => (true, false),
+        JoinType::Full => (false, false),
+        // Other join types preserve the rows of the left side.
+        _ => (false, true),
+    };
+    let left_rows = estimate_rows(lsend);
+    let right_rows = estimate_rows(rsend);
+    let single_worker =
+        lsend.distribution.is_single_worker_only() || rsend.distribution.is_single_worker_only();
+    let broadcast_left = can_broadcast_left && left_rows <= broadcast_join_max_rows;
+    let broadcast_right = can_broadcast_right && right_rows <= broadcast_join_max_rows;
+
+    let left_groups = lsend.snapshots.len();
+    let all_groups = left_groups + rsend.snapshots.len();
+    let build_right;
+    let distribution;
+    if broadcast_left && (!broadcast_right || left_rows <= right_rows) {
+        build_right = false;
+        distribution = if single_worker {
+            JoinDistribution::SingleWorker
+        } else {
+            JoinDistribution::Broadcast(
+                (0..left_groups)
+                    .chain(broadcast_groups(rsend, left_groups))
+                    .collect(),
+            )
+        };
+    } else if broadcast_right {
+        build_right = true;
+        distribution = if single_worker {
+            JoinDistribution::SingleWorker
+        } else {
+            JoinDistribution::Broadcast(
+                broadcast_groups(lsend, 0)
+                    .into_iter()
+                    .chain(left_groups..all_groups)
+                    .collect(),
+            )
+        };
+    } else {
+        build_right = right_rows < left_rows && HashJoinNode::can_build_right(join_type);
+        distribution = if single_worker {
+            JoinDistribution::SingleWorker
+        } else {
+            JoinDistribution::HashPartitioned
+        };
+    }
+    log::trace!(
+        "Planning hash join with estimated rows {} and {}, distribution: {:?}, build right: {}",
+        left_rows,
+        right_rows,
+        distribution,
+        build_right
+    );
+
+    Ok(ClusterSendNode {
+        input: Arc::new(
+            HashJoinNode {
+                left: lsend.input.clone(),
+                right: rsend.input.clone(),
+                on: on.clone(),
+                join_type: join_type.clone(),
+                schema: schema.clone(),
+                build_right,
+                hash_bucket: None,
+            }
+            .into_plan(),
+        ),
+        snapshots: lsend
+            .snapshots
+            .iter()
+            .chain(rsend.snapshots.iter())
+            .cloned()
+            .collect(),
+        distribution,
+    }
+    .into_plan())
+}
+
+/// Snapshots without `sort_on` come from tables that have no index sorted by the join columns.
+fn is_sorted_for_join(send: &ClusterSendNode) -> bool {
+    send.snapshots.iter().flatten().all(|i| i.sort_on.is_some())
+}
+
+fn estimate_rows(send: &ClusterSendNode) -> u64 {
+    send.snapshots
+        .iter()
+        .flatten()
+        .flat_map(|i| i.partitions.iter())
+        .map(|p| {
+            p.partition.get_row().main_table_row_count()
+                + p.chunks
+                    .iter()
+                    .map(|c| c.get_row().get_row_count())
+                    .sum::<u64>()
+        })
+        .sum()
+}
+
+/// Positions of the snapshot groups already sent to all workers, shifted by `offset`.
+fn broadcast_groups(send: &ClusterSendNode, offset: usize) -> Vec<usize> {
+    match &send.distribution {
+        JoinDistribution::Broadcast(groups) => groups.iter().map(|g| g + offset).collect(),
+        _ => Vec::new(),
+    }
+}
+
 fn pull_up_cluster_send(mut p: LogicalPlan) -> Result<LogicalPlan, DataFusionError> {
     let snapshots;
+    let distribution;
     match &mut p {
         // These nodes have no children, return unchanged.
         LogicalPlan::TableScan { .. }
@@ -970,6 +1333,7 @@ fn pull_up_cluster_send(mut p: LogicalPlan) -> Result<LogicalPlan, DataFusionErr
                 return Ok(p);
             }
             snapshots = send.snapshots.clone();
+            distribution = send.distribution.clone();
             // Code after 'match' will wrap `p` in ClusterSend.
             *input = send.input.clone();
         }
@@ -981,18 +1345,20 @@ fn pull_up_cluster_send(mut p: LogicalPlan) -> Result<LogicalPlan, DataFusionErr
             let mut union_snapshots = Vec::new();
             for i in inputs {
                 let send;
-                if let Some(s) = try_extract_cluster_send(i) {
-                    send = s;
-                } else {
-                    return Err(DataFusionError::Plan(
-                        "UNION argument not supported".to_string(),
-                    ));
+                match try_extract_cluster_send(i) {
+                    Some(s) if s.distribution == JoinDistribution::CoPartitioned => send = s,
+                    _ => {
+                        return Err(DataFusionError::Plan(
+                            "UNION argument not supported".to_string(),
+                        ))
+                    }
                 }
                 union_snapshots.extend(send.snapshots.concat());
                 // Code after 'match' will wrap `p` in ClusterSend.
                 *i = send.input.as_ref().clone();
             }
             snapshots = vec![union_snapshots];
+            distribution = JoinDistribution::CoPartitioned;
         }
         LogicalPlan::Join { left, right, .. } => {
             let lsend;
@@ -1008,6 +1374,19 @@ fn pull_up_cluster_send(mut p: LogicalPlan) -> Result<LogicalPlan, DataFusionErr
                     "JOIN argument not supported".to_string(),
                 ));
             }
+            let broadcast = broadcast_groups(lsend, 0)
+                .into_iter()
+                .chain(broadcast_groups(rsend, lsend.snapshots.len()))
+                .collect_vec();
+            distribution = if lsend.distribution.is_single_worker_only()
+                || rsend.distribution.is_single_worker_only()
+            {
+                JoinDistribution::SingleWorker
+            } else if broadcast.is_empty() {
+                JoinDistribution::CoPartitioned
+            } else {
+                JoinDistribution::Broadcast(broadcast)
+            };
             snapshots = lsend
                 .snapshots
                 .iter()
@@ -1028,6 +1407,7 @@ fn pull_up_cluster_send(mut p: LogicalPlan) -> Result<LogicalPlan, DataFusionErr
     Ok(ClusterSendNode {
         input: Arc::new(p),
         snapshots,
+        distribution,
     }
     .into_plan())
 }
@@ -1053,6 +1433,7 @@ impl ExtensionPlanner for CubeExtensionPlanner {
             Ok(Some(self.plan_cluster_send(
                 input.clone(),
                 &cs.snapshots,
+                &cs.distribution,
                 input.schema(),
                 false,
                 usize::MAX,
@@ -1064,6 +1445,13 @@ impl ExtensionPlanner for CubeExtensionPlanner {
         } else if let Some(_) = node.as_any().downcast_ref::<PanicWorkerNode>() {
             assert_eq!(inputs.len(), 0);
             Ok(Some(plan_panic_worker()?))
+        } else if let Some(join) = node.as_any().downcast_ref::<HashJoinNode>() {
+            assert_eq!(inputs.len(), 2);
+            Ok(Some(plan_hash_join(
+                join,
+                inputs[0].clone(),
+                inputs[1].clone(),
+            )?))
         } else {
             Ok(None)
         }
@@ -1075,6 +1463,7 @@ impl CubeExtensionPlanner {
         &self,
         input: Arc<dyn ExecutionPlan>,
         snapshots: &Vec<Vec<IndexSnapshot>>,
+        distribution: &JoinDistribution,
         schema: SchemaRef,
         use_streaming: bool,
         max_batch_rows: usize,
@@ -1089,6 +1478,7 @@ impl CubeExtensionPlanner {
                 c.clone(),
                 self.serialized_plan.clone(),
                 snapshots,
+                distribution,
                 input,
                 use_streaming,
             )))
@@ -1192,7 +1582,9 @@ pub mod tests {
     use crate::metastore::multi_index::MultiPartition;
     use crate::metastore::table::{Table, TablePath};
     use crate::metastore::{Chunk, Column, ColumnType, IdRow, Index, Partition, Schema};
-    use crate::queryplanner::planning::{choose_index, try_extract_cluster_send, PlanIndexStore};
+    use crate::queryplanner::planning::{
+        choose_index, choose_index_ext, try_extract_cluster_send, JoinDistribution, PlanIndexStore,
+    };
     use crate::queryplanner::pretty_printers::PPOptions;
     use crate::queryplanner::query_executor::ClusterSendExec;
     use crate::queryplanner::serialized_plan::RowRange;
@@ -1564,10 +1956,11 @@ pub mod tests {
             c.select_workers = vec!["worker1".to_string(), "worker2".to_string()];
             c
         });
-        let cs = &try_extract_cluster_send(&with_index).unwrap().snapshots;
+        let cs = try_extract_cluster_send(&with_index).unwrap();
         let assigned = ClusterSendExec::distribute_to_workers(
             c.config_obj().as_ref(),
-            &cs,
+            &cs.snapshots,
+            &cs.distribution,
             &meta.multi_part_subtree,
         );
 
@@ -1609,6 +2002,114 @@ pub mod tests {
         );
     }
 
+    #[tokio::test]
+    pub async fn test_hash_join() {
+        let mut indices = default_indices();
+        // Orders.
+        for _ in 0..2 {
+            indices
+                .partitions
+                .push(Partition::new(2, None, None, None).update_row_count(1000));
+        }
+        // Customers.
+        indices
+            .partitions
+            .push(Partition::new(0, None, None, None).update_row_count(10));
+
+        // No index is sorted by the join columns, the small side is sent to all workers.
+        let plan = initial_plan(
+            "SELECT order_id, customer_name FROM s.Orders \
+             JOIN s.Customers ON order_amount = customer_registered_date",
+            &indices,
+        );
+        let (with_index, meta) = choose_index(&plan, &indices).await.unwrap();
+        assert_eq!(pretty_printers::pp_plan(&with_index), "ClusterSend, indices: [[2], [0]], broadcast: [1]\
+                      \n  Projection, [s.Orders.order_id, s.Customers.customer_name]\
+                      \n    HashJoin on: [#s.Orders.order_amount = #s.Customers.customer_registered_date], build: right\
+                      \n      Scan s.Orders, source: CubeTable(index: default:2:[0, 1]), fields: [order_id, order_amount]\
+                      \n      Scan s.Customers, source: CubeTable(index: default:0:[2]), fields: [customer_name, customer_registered_date]");
+
+        let c = Config::test("hash_join").update_config(|mut c| {
+            c.server_name = "router".to_string();
+            c.select_workers = vec!["worker1".to_string(), "worker2".to_string()];
+            c
+        });
+        let cs = try_extract_cluster_send(&with_index).unwrap();
+        let assigned = ClusterSendExec::distribute_to_workers(
+            c.config_obj().as_ref(),
+            &cs.snapshots,
+            &cs.distribution,
+            &meta.multi_part_subtree,
+        );
+        let mut orders_partitions = Vec::new();
+        for (_, ps) in &assigned {
+            assert!(ps.contains(&(2, RowRange::default())), "{:?}", assigned);
+            orders_partitions.extend(ps.iter().map(|(id, _)| *id).filter(|id| *id != 2));
+        }
+        orders_partitions.sort();
+        assert_eq!(orders_partitions, vec![0, 1]);
+
+        // Preserved side of the outer join is never sent to all workers.
+        let plan = initial_plan(
+            "SELECT order_id, customer_name FROM s.Customers \
+             LEFT JOIN s.Orders ON customer_registered_date = order_amount",
+            &indices,
+        );
+        let with_index = choose_index(&plan, &indices).await.unwrap().0;
+        assert_eq!(
+            try_extract_cluster_send(&with_index).unwrap().distribution,
+            JoinDistribution::Broadcast(vec![1])
+        );
+
+        // Both sides are too large, every worker reads all partitions and joins its share of keys.
+        let plan = initial_plan(
+            "SELECT order_id, customer_name FROM s.Orders \
+             JOIN s.Customers ON order_amount = customer_registered_date",
+            &indices,
+        );
+        let (with_index, meta) = choose_index_ext(&plan, &indices, true, 5).await.unwrap();
+        assert_eq!(pretty_printers::pp_plan(&with_index), "ClusterSend, indices: [[2], [0]], hash partitioned\
+                      \n  Projection, [s.Orders.order_id, s.Customers.customer_name]\
+                      \n    HashJoin on: [#s.Orders.order_amount = #s.Customers.customer_registered_date], build: right\
+                      \n      Scan s.Orders, source: CubeTable(index: default:2:[0, 1]), fields: [order_id, order_amount]\
+                      \n      Scan s.Customers, source: CubeTable(index: default:0:[2]), fields: [customer_name, customer_registered_date]");
+
+        let cs = try_extract_cluster_send(&with_index).unwrap();
+        let assigned = ClusterSendExec::distribute_to_workers(
+            c.config_obj().as_ref(),
+            &cs.snapshots,
+            &cs.distribution,
+            &meta.multi_part_subtree,
+        );
+        assert_eq!(assigned.len(), 2, "{:?}", assigned);
+        for (_, ps) in &assigned {
+            let mut partitions = ps.clone();
+            partitions.sort_unstable_by_key(|(id, _)| *id);
+            assert_eq!(
+                partitions,
+                vec![
+                    (0, RowRange::default()),
+                    (1, RowRange::default()),
+                    (2, RowRange::default()),
+                ]
+            );
+        }
+
+        // Sorted indices are still joined without hashing.
+        let plan = initial_plan(
+            "SELECT order_id, customer_name FROM s.Orders \
+             JOIN s.Customers ON order_customer = customer_id",
+            &indices,
+        );
+        let pp =
+            pretty_printers::pp_plan(&choose_index_ext(&plan, &indices, true, 5).await.unwrap().0);
+        assert!(
+            !pp.contains("HashJoin"),
+            "plan contained hash join:\n{}",
+            pp
+        );
+    }
+
     fn default_indices() -> TestIndices {
         make_test_indices(false)
     }
@@ -1666,6 +2167,10 @@ pub mod tests {
             Vec::new(),
             None,
             None,
+            None,
+            None,
+            None,
+            None,
         ));
         i.indices.push(
             Index::try_new(
@@ -1712,6 +2217,10 @@ pub mod tests {
             Vec::new(),
             None,
             None,
+            None,
+            None,
+            None,
+            None,
         ));
 
         i.indices.push(
@@ -1764,6 +2273,10 @@ pub mod tests {
             Vec::new(),
             None,
             None,
+            None,
+            None,
+            None,
+            None,
         ));
 
         i
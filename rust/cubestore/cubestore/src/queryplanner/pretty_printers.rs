@@ -17,8 +17,9 @@ use datafusion::physical_plan::ExecutionPlan;
 use itertools::{repeat_n, Itertools};
 
 use crate::queryplanner::filter_by_key_range::FilterByKeyRangeExec;
+use crate::queryplanner::hash_join::HashJoinNode;
 use crate::queryplanner::panic::{PanicWorkerExec, PanicWorkerNode};
-use crate::queryplanner::planning::{ClusterSendNode, WorkerExec};
+use crate::queryplanner::planning::{ClusterSendNode, JoinDistribution, WorkerExec};
 use crate::queryplanner::query_executor::{ClusterSendExec, CubeTable, CubeTableExec};
 use crate::queryplanner::serialized_plan::{IndexSnapshot, RowRange};
 use crate::queryplanner::topk::ClusterAggregateTopK;
@@ -43,6 +44,8 @@ pub struct PPOptions {
     pub show_aggregations: bool,
     // Applies only to physical plan.
     pub show_output_hints: bool,
+    // Applies only to physical plan.
+    pub show_pruning: bool,
 }
 
 pub fn pp_phys_plan(p: &dyn ExecutionPlan) -> String {
@@ -172,7 +175,17 @@ pub fn pp_plan_ext(p: &LogicalPlan, opts: &PPOptions) -> String {
                                 .iter()
                                 .map(|is| is.iter().map(|i| i.index.get_id()).collect_vec())
                                 .collect_vec()
-                        )
+                        );
+                        match &cs.distribution {
+                            JoinDistribution::CoPartitioned => {}
+                            JoinDistribution::Broadcast(groups) => {
+                                self.output += &format!(", broadcast: {:?}", groups)
+                            }
+                            JoinDistribution::SingleWorker => self.output += ", single worker",
+                            JoinDistribution::HashPartitioned => {
+                                self.output += ", hash partitioned"
+                            }
+                        }
                     } else if let Some(topk) = node.as_any().downcast_ref::<ClusterAggregateTopK>()
                     {
                         self.output += &format!("ClusterAggregateTopK, limit: {}", topk.limit);
@@ -187,6 +200,16 @@ pub fn pp_plan_ext(p: &LogicalPlan, opts: &PPOptions) -> String {
                         }
                     } else if let Some(_) = node.as_any().downcast_ref::<PanicWorkerNode>() {
                         self.output += &format!("PanicWorker")
+                    } else if let Some(j) = node.as_any().downcast_ref::<HashJoinNode>() {
+                        self.output += &format!(
+                            "HashJoin on: [{}]",
+                            j.on.iter()
+                                .map(|(l, r)| format!("{} = {}", l, r))
+                                .join(", ")
+                        );
+                        if j.build_right {
+                            self.output += ", build: right";
+                        }
                     } else {
                         panic!("unknown extension node");
                     }
@@ -282,6 +305,13 @@ fn pp_phys_plan_indented(p: &dyn ExecutionPlan, indent: usize, o: &PPOptions, ou
             if o.show_filters && t.filter.is_some() {
                 *out += &format!(", predicate: {:?}", t.filter.as_ref().unwrap())
             }
+            if o.show_pruning {
+                let p = t.index_snapshot.pruning();
+                *out += &format!(
+                    ", pruned: {}/{} partitions, {}/{} chunks",
+                    p.pruned_partitions, p.total_partitions, p.pruned_chunks, p.total_chunks
+                );
+            }
         } else if let Some(_) = a.downcast_ref::<EmptyExec>() {
             *out += "Empty";
         } else if let Some(p) = a.downcast_ref::<ProjectionExec>() {
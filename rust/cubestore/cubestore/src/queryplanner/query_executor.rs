@@ -5,11 +5,14 @@ use crate::metastore::multi_index::MultiPartition;
 use crate::metastore::table::Table;
 use crate::metastore::{Column, ColumnType, IdRow, Index, Partition};
 use crate::queryplanner::filter_by_key_range::FilterByKeyRangeExec;
+use crate::queryplanner::hash_join::HashBucket;
+use crate::queryplanner::memory_pool::{track_query_memory, with_limit_error, QueryMemoryPool};
 use crate::queryplanner::optimizations::CubeQueryPlanner;
-use crate::queryplanner::planning::get_worker_plan;
-use crate::queryplanner::pretty_printers::{pp_phys_plan, pp_plan};
+use crate::queryplanner::planning::{get_worker_plan, JoinDistribution};
+use crate::queryplanner::pretty_printers::{pp_phys_plan, pp_phys_plan_ext, pp_plan, PPOptions};
 use crate::queryplanner::serialized_plan::{IndexSnapshot, RowFilter, RowRange, SerializedPlan};
 use crate::store::DataFrame;
+use crate::table::data::{evolve_batch, evolve_plan, evolve_predicate};
 use crate::table::parquet::CubestoreParquetMetadataCache;
 use crate::table::{Row, TableValue, TimestampValue};
 use crate::{app_metrics, CubeError};
@@ -46,6 +49,7 @@ use datafusion::physical_plan::{
 use itertools::Itertools;
 use log::{debug, error, trace, warn};
 use mockall::automock;
+use parquet::arrow::{ArrowReader, ParquetFileArrowReader};
 use serde_derive::{Deserialize, Serialize};
 use std::any::Any;
 use std::cmp::min;
@@ -66,6 +70,13 @@ pub trait QueryExecutor: DIService + Send + Sync {
         cluster: Arc<dyn Cluster>,
     ) -> Result<(SchemaRef, Vec<RecordBatch>), CubeError>;
 
+    /// Same as `execute_router_plan`, but results are streamed instead of being collected.
+    async fn execute_router_plan_stream(
+        &self,
+        plan: SerializedPlan,
+        cluster: Arc<dyn Cluster>,
+    ) -> Result<SendableRecordBatchStream, CubeError>;
+
     async fn execute_worker_plan(
         &self,
         plan: SerializedPlan,
@@ -97,6 +108,7 @@ pub trait QueryExecutor: DIService + Send + Sync {
 crate::di_service!(MockQueryExecutor, [QueryExecutor]);
 
 pub struct QueryExecutorImpl {
+    config: Arc<dyn ConfigObj>,
     parquet_metadata_cache: Arc<dyn CubestoreParquetMetadataCache>,
 }
 
@@ -121,7 +133,10 @@ impl QueryExecutor for QueryExecutorImpl {
 
         let execution_time = SystemTime::now();
 
-        let results = collect(split_plan.clone()).instrument(collect_span).await;
+        let memory_pool = self.query_memory_pool();
+        let results = collect(track_query_memory(split_plan.clone(), &memory_pool)?)
+            .instrument(collect_span)
+            .await;
         let execution_time = execution_time.elapsed()?;
         debug!("Query data processing time: {:?}", execution_time,);
         app_metrics::DATA_QUERY_TIME_MS.report(execution_time.as_millis() as i64);
@@ -149,9 +164,31 @@ impl QueryExecutor for QueryExecutorImpl {
                 pp_phys_plan(split_plan.as_ref())
             );
         }
+        if results.is_err() {
+            if let Some(e) = memory_pool.limit_error() {
+                return Err(e);
+            }
+        }
         Ok((split_plan.schema(), results?))
     }
 
+    async fn execute_router_plan_stream(
+        &self,
+        plan: SerializedPlan,
+        cluster: Arc<dyn Cluster>,
+    ) -> Result<SendableRecordBatchStream, CubeError> {
+        let (physical_plan, _) = self.router_plan(plan, cluster).await?;
+        let memory_pool = self.query_memory_pool();
+        let mut physical_plan = track_query_memory(physical_plan, &memory_pool)?;
+        if physical_plan.output_partitioning().partition_count() != 1 {
+            physical_plan = Arc::new(MergeExec::new(physical_plan));
+        }
+        Ok(with_limit_error(
+            physical_plan.execute(0).await?,
+            memory_pool,
+        ))
+    }
+
     #[instrument(level = "trace", skip(self, plan, remote_to_local_names))]
     async fn execute_worker_plan(
         &self,
@@ -181,7 +218,8 @@ impl QueryExecutor for QueryExecutorImpl {
         );
 
         let execution_time = SystemTime::now();
-        let results = collect(worker_plan.clone())
+        let memory_pool = self.query_memory_pool();
+        let results = collect(track_query_memory(worker_plan.clone(), &memory_pool)?)
             .instrument(tracing::span!(
                 tracing::Level::TRACE,
                 "collect_physical_plan"
@@ -215,6 +253,11 @@ impl QueryExecutor for QueryExecutorImpl {
                 pp_phys_plan(worker_plan.as_ref())
             );
         }
+        if results.is_err() {
+            if let Some(e) = memory_pool.limit_error() {
+                return Err(e);
+            }
+        }
         // TODO: stream results as they become available.
         let results = regroup_batches(results?, max_batch_rows)?;
         Ok((worker_plan.schema(), results))
@@ -277,17 +320,31 @@ impl QueryExecutor for QueryExecutorImpl {
             ));
         }
 
-        Ok(pp_phys_plan(worker_plan.as_ref()))
+        Ok(pp_phys_plan_ext(
+            worker_plan.as_ref(),
+            &PPOptions {
+                show_pruning: true,
+                ..PPOptions::default()
+            },
+        ))
     }
 }
 
 impl QueryExecutorImpl {
-    pub fn new(parquet_metadata_cache: Arc<dyn CubestoreParquetMetadataCache>) -> Arc<Self> {
+    pub fn new(
+        config: Arc<dyn ConfigObj>,
+        parquet_metadata_cache: Arc<dyn CubestoreParquetMetadataCache>,
+    ) -> Arc<Self> {
         Arc::new(QueryExecutorImpl {
+            config,
             parquet_metadata_cache,
         })
     }
 
+    fn query_memory_pool(&self) -> Arc<QueryMemoryPool> {
+        QueryMemoryPool::new(self.config.query_memory_limit_bytes() as usize)
+    }
+
     fn router_context(
         &self,
         cluster: Arc<dyn Cluster>,
@@ -506,15 +563,14 @@ impl CubeTable {
                     .remote_to_local_names
                     .get(remote_path.as_str())
                     .expect(format!("Missing remote path {}", remote_path).as_str());
-                let arc: Arc<dyn ExecutionPlan> = Arc::new(ParquetExec::try_from_path_with_cache(
+                let arc = self.parquet_scan(
                     &local_path,
-                    index_projection_or_none_on_schema_match.clone(),
+                    index_cols,
+                    &index_projection,
+                    &index_projection_or_none_on_schema_match,
                     predicate.clone(),
                     batch_size,
-                    1,
-                    None, // TODO: propagate limit
-                    self.parquet_metadata_cache.clone(),
-                )?);
+                )?;
                 let arc = FilterByKeyRangeExec::issue_filters(arc, filter.clone(), key_len);
                 partition_execs.push(arc);
             }
@@ -529,6 +585,10 @@ impl CubeTable {
                             "Record batch for in memory chunk {:?} is not provided",
                             chunk
                         )))?;
+                    let record_batches = record_batches
+                        .iter()
+                        .map(|b| evolve_batch(b.clone(), index_cols))
+                        .collect::<Result<Vec<_>, _>>()?;
                     if let Some(batch) = record_batches.iter().next() {
                         if batch.schema() != index_schema {
                             return Err(CubeError::internal(format!(
@@ -539,7 +599,7 @@ impl CubeTable {
                         }
                     }
                     Arc::new(MemoryExec::try_new(
-                        &[record_batches.clone()],
+                        &[record_batches],
                         index_projection_schema.clone(),
                         index_projection_or_none_on_schema_match.clone(),
                     )?)
@@ -549,15 +609,14 @@ impl CubeTable {
                         .remote_to_local_names
                         .get(&remote_path)
                         .expect(format!("Missing remote path {}", remote_path).as_str());
-                    Arc::new(ParquetExec::try_from_path_with_cache(
+                    self.parquet_scan(
                         local_path,
-                        index_projection_or_none_on_schema_match.clone(),
+                        index_cols,
+                        &index_projection,
+                        &index_projection_or_none_on_schema_match,
                         predicate.clone(),
                         batch_size,
-                        1,
-                        None, // TODO: propagate limit
-                        self.parquet_metadata_cache.clone(),
-                    )?)
+                    )?
                 };
 
                 let node = FilterByKeyRangeExec::issue_filters(node, filter.clone(), key_len);
@@ -710,6 +769,61 @@ impl CubeTable {
         Ok(plan)
     }
 
+    /// Files written before the columns of the index were altered are read by column names,
+    /// missing columns are filled with their default values.
+    fn parquet_scan(
+        &self,
+        local_path: &str,
+        index_cols: &Vec<Column>,
+        index_projection: &Vec<usize>,
+        index_projection_or_none: &Option<Vec<usize>>,
+        predicate: Option<Expr>,
+        batch_size: usize,
+    ) -> Result<Arc<dyn ExecutionPlan>, CubeError> {
+        let file_reader = self.parquet_metadata_cache.file_reader(local_path)?;
+        // Compare top-level fields, parquet leaves of nested columns have names of their own.
+        let file_schema = ParquetFileArrowReader::new(file_reader).get_schema()?;
+        if file_schema
+            .fields()
+            .iter()
+            .map(|f| f.name())
+            .eq(index_cols.iter().map(|c| c.get_name()))
+        {
+            return Ok(Arc::new(ParquetExec::try_from_path_with_cache(
+                local_path,
+                index_projection_or_none.clone(),
+                predicate,
+                batch_size,
+                1,
+                None, // TODO: propagate limit
+                self.parquet_metadata_cache.clone(),
+            )?));
+        }
+
+        let projected_cols = index_projection
+            .iter()
+            .map(|i| index_cols[*i].clone())
+            .collect_vec();
+        let file_projection = projected_cols
+            .iter()
+            .filter_map(|c| file_schema.index_of(c.get_name()).ok())
+            .sorted()
+            .collect_vec();
+        let predicate = predicate
+            .map(|p| evolve_predicate(p, &file_schema, index_cols))
+            .transpose()?;
+        let exec = Arc::new(ParquetExec::try_from_path_with_cache(
+            local_path,
+            Some(file_projection),
+            predicate,
+            batch_size,
+            1,
+            None, // TODO: propagate limit
+            self.parquet_metadata_cache.clone(),
+        )?);
+        evolve_plan(exec, &projected_cols)
+    }
+
     pub fn project_to_index_positions(
         projection_columns: &Vec<String>,
         i: &IdRow<Index>,
@@ -834,6 +948,8 @@ pub struct ClusterSendExec {
     pub cluster: Arc<dyn Cluster>,
     pub serialized_plan: Arc<SerializedPlan>,
     pub use_streaming: bool,
+    /// Every worker gets its own [HashBucket] of join keys.
+    hash_partitioned: bool,
 }
 
 impl ClusterSendExec {
@@ -842,12 +958,14 @@ impl ClusterSendExec {
         cluster: Arc<dyn Cluster>,
         serialized_plan: Arc<SerializedPlan>,
         union_snapshots: &[Vec<IndexSnapshot>],
+        distribution: &JoinDistribution,
         input_for_optimizations: Arc<dyn ExecutionPlan>,
         use_streaming: bool,
     ) -> Self {
         let partitions = Self::distribute_to_workers(
             cluster.config().as_ref(),
             union_snapshots,
+            distribution,
             &serialized_plan.planning_meta().multi_part_subtree,
         );
         Self {
@@ -857,16 +975,72 @@ impl ClusterSendExec {
             serialized_plan,
             input_for_optimizations,
             use_streaming,
+            hash_partitioned: *distribution == JoinDistribution::HashPartitioned,
         }
     }
 
     pub(crate) fn distribute_to_workers(
         config: &dyn ConfigObj,
         snapshots: &[Vec<IndexSnapshot>],
+        distribution: &JoinDistribution,
         tree: &HashMap<u64, MultiPartition>,
     ) -> Vec<(String, Vec<(u64, RowRange)>)> {
-        let partitions = Self::logical_partitions(snapshots, tree);
-        Self::assign_nodes(config, partitions)
+        match distribution {
+            JoinDistribution::CoPartitioned => {
+                let partitions = Self::logical_partitions(snapshots, tree);
+                Self::assign_nodes(config, partitions)
+            }
+            JoinDistribution::Broadcast(broadcast) => {
+                let mut rest = Vec::new();
+                let mut replicated = Vec::new();
+                for (i, union) in snapshots.iter().enumerate() {
+                    if broadcast.contains(&i) {
+                        replicated.extend(Self::all_partitions(union));
+                    } else {
+                        rest.push(union.clone());
+                    }
+                }
+                let partitions = Self::logical_partitions(&rest, tree);
+                let mut r = Self::assign_nodes(config, partitions);
+                for (_, ps) in r.iter_mut() {
+                    ps.extend(replicated.iter().cloned());
+                }
+                r
+            }
+            JoinDistribution::SingleWorker => {
+                let all = Self::all_partitions(snapshots.iter().flatten());
+                if all.is_empty() {
+                    return Vec::new();
+                }
+                let node = pick_worker_by_ids(config, all.iter().map(|(id, _)| *id));
+                vec![(node.to_string(), all)]
+            }
+            JoinDistribution::HashPartitioned => {
+                let all = Self::all_partitions(snapshots.iter().flatten());
+                if all.is_empty() {
+                    return Vec::new();
+                }
+                let workers = config.select_workers();
+                if workers.is_empty() {
+                    return vec![(config.server_name().to_string(), all)];
+                }
+                workers
+                    .iter()
+                    .map(|w| (w.to_string(), all.clone()))
+                    .collect()
+            }
+        }
+    }
+
+    /// All partitions of the indices without any row filters.
+    fn all_partitions<'a>(
+        snapshots: impl IntoIterator<Item = &'a IndexSnapshot>,
+    ) -> Vec<(u64, RowRange)> {
+        snapshots
+            .into_iter()
+            .flat_map(|i| i.partitions.iter())
+            .map(|p| (p.partition.get_id(), RowRange::default()))
+            .collect()
     }
 
     fn logical_partitions(
@@ -1008,21 +1182,20 @@ impl ClusterSendExec {
             serialized_plan: self.serialized_plan.clone(),
             input_for_optimizations,
             use_streaming: self.use_streaming,
+            hash_partitioned: self.hash_partitioned,
         }
     }
 
     pub fn worker_plans(&self) -> Vec<(String, SerializedPlan)> {
         let mut res = Vec::new();
-        for (node_name, partitions) in self.partitions.iter() {
-            res.push((
-                node_name.clone(),
-                self.serialized_plan_for_partitions(partitions),
-            ));
+        for (i, (node_name, _)) in self.partitions.iter().enumerate() {
+            res.push((node_name.clone(), self.serialized_plan_for_partitions(i)));
         }
         res
     }
 
-    fn serialized_plan_for_partitions(&self, partitions: &Vec<(u64, RowRange)>) -> SerializedPlan {
+    fn serialized_plan_for_partitions(&self, partition: usize) -> SerializedPlan {
+        let (_, partitions) = &self.partitions[partition];
         let mut ps = HashMap::<_, RowFilter>::new();
         for (id, range) in partitions {
             ps.entry(*id).or_default().append_or(range.clone())
@@ -1030,7 +1203,15 @@ impl ClusterSendExec {
         let mut ps = ps.into_iter().collect_vec();
         ps.sort_unstable_by_key(|(id, _)| *id);
 
-        self.serialized_plan.with_partition_id_to_execute(ps)
+        let plan = self.serialized_plan.with_partition_id_to_execute(ps);
+        if self.hash_partitioned {
+            plan.with_hash_bucket(HashBucket {
+                index: partition,
+                count: self.partitions.len(),
+            })
+        } else {
+            plan
+        }
     }
 }
 
@@ -1067,6 +1248,7 @@ impl ExecutionPlan for ClusterSendExec {
             serialized_plan: self.serialized_plan.clone(),
             input_for_optimizations,
             use_streaming: self.use_streaming,
+            hash_partitioned: self.hash_partitioned,
         }))
     }
 
@@ -1079,9 +1261,9 @@ impl ExecutionPlan for ClusterSendExec {
         &self,
         partition: usize,
     ) -> Result<SendableRecordBatchStream, DataFusionError> {
-        let (node_name, partitions) = &self.partitions[partition];
+        let (node_name, _) = &self.partitions[partition];
 
-        let plan = self.serialized_plan_for_partitions(partitions);
+        let plan = self.serialized_plan_for_partitions(partition);
 
         if self.use_streaming {
             Ok(self.cluster.run_select_stream(node_name, plan).await?)
@@ -1311,6 +1493,15 @@ pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeE
                         });
                     }
                 }
+                DataType::Int32
+                | DataType::Int16
+                | DataType::Int8
+                | DataType::Date32
+                | DataType::List(_) => {
+                    for i in 0..num_rows {
+                        rows[i].push(TableValue::from_array(array.as_ref(), i));
+                    }
+                }
                 x => panic!("Unsupported data type: {:?}", x),
             }
         }
@@ -1330,10 +1521,21 @@ pub fn arrow_to_column_type(arrow_type: DataType) -> Result<ColumnType, CubeErro
             precision: 18,
         }),
         DataType::Boolean => Ok(ColumnType::Boolean),
-        DataType::Int8
-        | DataType::Int16
-        | DataType::Int32
-        | DataType::Int64
+        DataType::Int32 => Ok(ColumnType::Int32),
+        DataType::Int16 => Ok(ColumnType::Int16),
+        DataType::Int8 => Ok(ColumnType::Int8),
+        DataType::Date32 => Ok(ColumnType::Date),
+        DataType::List(item) => {
+            let item = arrow_to_column_type(item.data_type().clone())?;
+            if !item.is_valid_list_item() {
+                return Err(CubeError::internal(format!(
+                    "unsupported list item type {}",
+                    item
+                )));
+            }
+            Ok(ColumnType::List(Box::new(item)))
+        }
+        DataType::Int64
         | DataType::UInt8
         | DataType::UInt16
         | DataType::UInt32
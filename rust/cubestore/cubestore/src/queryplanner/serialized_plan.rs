@@ -1,7 +1,8 @@
 use crate::metastore::table::{Table, TablePath};
 use crate::metastore::{Chunk, IdRow, Index, Partition};
+use crate::queryplanner::hash_join::{HashBucket, HashJoinNode};
 use crate::queryplanner::panic::PanicWorkerNode;
-use crate::queryplanner::planning::{ClusterSendNode, PlanningMeta};
+use crate::queryplanner::planning::{ClusterSendNode, JoinDistribution, PlanningMeta};
 use crate::queryplanner::query_executor::CubeTable;
 use crate::queryplanner::topk::{ClusterAggregateTopK, SortColumn};
 use crate::queryplanner::udfs::aggregate_udf_by_kind;
@@ -30,6 +31,7 @@ use sqlparser::ast::RollingOffset;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Clone, Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
 pub struct RowRange {
@@ -73,6 +75,13 @@ pub struct SerializedPlan {
     logical_plan: Arc<SerializedLogicalPlan>,
     schema_snapshot: Arc<SchemaSnapshot>,
     partition_ids_to_execute: Vec<(u64, RowFilter)>,
+    /// Join keys joined by the worker of hash partitioned joins.
+    #[serde(default)]
+    hash_bucket: Option<HashBucket>,
+    /// Triggered when the query is killed or times out. Not sent over the network, nodes that
+    /// receive the plan set up their own token.
+    #[serde(skip)]
+    cancel_token: CancellationToken,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -86,6 +95,18 @@ pub struct IndexSnapshot {
     pub index: IdRow<Index>,
     pub partitions: Vec<PartitionSnapshot>,
     pub sort_on: Option<Vec<String>>,
+    #[serde(default)]
+    pub pruning: PruningStats,
+}
+
+/// Number of partitions and chunks of the index skipped by the router because they can't match
+/// the query filters. Chunks are counted only inside the partitions that were not skipped.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct PruningStats {
+    pub pruned_partitions: usize,
+    pub total_partitions: usize,
+    pub pruned_chunks: usize,
+    pub total_chunks: usize,
 }
 
 impl IndexSnapshot {
@@ -108,6 +129,10 @@ impl IndexSnapshot {
     pub fn sort_on(&self) -> Option<&Vec<String>> {
         self.sort_on.as_ref()
     }
+
+    pub fn pruning(&self) -> &PruningStats {
+        &self.pruning
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -193,6 +218,7 @@ pub enum SerializedLogicalPlan {
     ClusterSend {
         input: Arc<SerializedLogicalPlan>,
         snapshots: Vec<Vec<IndexSnapshot>>,
+        distribution: JoinDistribution,
     },
     ClusterAggregateTopK {
         limit: usize,
@@ -202,6 +228,7 @@ pub enum SerializedLogicalPlan {
         sort_columns: Vec<SortColumn>,
         schema: DFSchemaRef,
         snapshots: Vec<Vec<IndexSnapshot>>,
+        distribution: JoinDistribution,
     },
     CrossJoin {
         left: Arc<SerializedLogicalPlan>,
@@ -232,6 +259,14 @@ pub enum SerializedLogicalPlan {
         aggs: Vec<SerializedExpr>,
     },
     Panic {},
+    HashJoin {
+        left: Arc<SerializedLogicalPlan>,
+        right: Arc<SerializedLogicalPlan>,
+        on: Vec<(Column, Column)>,
+        join_type: JoinType,
+        schema: DFSchemaRef,
+        build_right: bool,
+    },
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -243,6 +278,7 @@ pub enum SerializePartitioning {
 pub struct WorkerContext {
     remote_to_local_names: HashMap<String, String>,
     worker_partition_ids: Vec<(u64, RowFilter)>,
+    hash_bucket: Option<HashBucket>,
     chunk_id_to_record_batches: HashMap<u64, Vec<RecordBatch>>,
     parquet_metadata_cache: Arc<dyn ParquetMetadataCache>,
 }
@@ -372,9 +408,14 @@ impl SerializedLogicalPlan {
                     schema: schema.clone(),
                 }),
             },
-            SerializedLogicalPlan::ClusterSend { input, snapshots } => ClusterSendNode {
+            SerializedLogicalPlan::ClusterSend {
+                input,
+                snapshots,
+                distribution,
+            } => ClusterSendNode {
                 input: Arc::new(input.logical_plan(worker_context)?),
                 snapshots: snapshots.clone(),
+                distribution: distribution.clone(),
             }
             .into_plan(),
             SerializedLogicalPlan::ClusterAggregateTopK {
@@ -385,6 +426,7 @@ impl SerializedLogicalPlan {
                 sort_columns,
                 schema,
                 snapshots,
+                distribution,
             } => ClusterAggregateTopK {
                 limit: *limit,
                 input: Arc::new(input.logical_plan(worker_context)?),
@@ -393,6 +435,7 @@ impl SerializedLogicalPlan {
                 order_by: sort_columns.clone(),
                 schema: schema.clone(),
                 snapshots: snapshots.clone(),
+                distribution: distribution.clone(),
             }
             .into_plan(),
             SerializedLogicalPlan::CrossJoin {
@@ -457,6 +500,23 @@ impl SerializedLogicalPlan {
             SerializedLogicalPlan::Panic {} => LogicalPlan::Extension {
                 node: Arc::new(PanicWorkerNode {}),
             },
+            SerializedLogicalPlan::HashJoin {
+                left,
+                right,
+                on,
+                join_type,
+                schema,
+                build_right,
+            } => HashJoinNode {
+                left: Arc::new(left.logical_plan(worker_context)?),
+                right: Arc::new(right.logical_plan(worker_context)?),
+                on: on.clone(),
+                join_type: join_type.clone(),
+                schema: schema.clone(),
+                build_right: *build_right,
+                hash_bucket: worker_context.hash_bucket,
+            }
+            .into_plan(),
         })
     }
 }
@@ -657,6 +717,8 @@ impl SerializedPlan {
             logical_plan: Arc::new(serialized_logical_plan),
             schema_snapshot: Arc::new(SchemaSnapshot { index_snapshots }),
             partition_ids_to_execute: Vec::new(),
+            hash_bucket: None,
+            cancel_token: CancellationToken::new(),
         })
     }
 
@@ -668,9 +730,25 @@ impl SerializedPlan {
             logical_plan: self.logical_plan.clone(),
             schema_snapshot: self.schema_snapshot.clone(),
             partition_ids_to_execute,
+            hash_bucket: self.hash_bucket,
+            cancel_token: self.cancel_token.clone(),
         }
     }
 
+    pub fn with_hash_bucket(mut self, hash_bucket: HashBucket) -> Self {
+        self.hash_bucket = Some(hash_bucket);
+        self
+    }
+
+    pub fn with_cancel_token(mut self, cancel_token: CancellationToken) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    pub fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel_token
+    }
+
     pub fn logical_plan(
         &self,
         remote_to_local_names: HashMap<String, String>,
@@ -680,6 +758,7 @@ impl SerializedPlan {
         self.logical_plan.logical_plan(&WorkerContext {
             remote_to_local_names,
             worker_partition_ids: self.partition_ids_to_execute.clone(),
+            hash_bucket: self.hash_bucket,
             chunk_id_to_record_batches,
             parquet_metadata_cache,
         })
@@ -884,6 +963,7 @@ impl SerializedPlan {
                     SerializedLogicalPlan::ClusterSend {
                         input: Arc::new(Self::serialized_logical_plan(&cs.input)),
                         snapshots: cs.snapshots.clone(),
+                        distribution: cs.distribution.clone(),
                     }
                 } else if let Some(topk) = node.as_any().downcast_ref::<ClusterAggregateTopK>() {
                     SerializedLogicalPlan::ClusterAggregateTopK {
@@ -902,6 +982,7 @@ impl SerializedPlan {
                         sort_columns: topk.order_by.clone(),
                         schema: topk.schema.clone(),
                         snapshots: topk.snapshots.clone(),
+                        distribution: topk.distribution.clone(),
                     }
                 } else if let Some(j) = node.as_any().downcast_ref::<CrossJoinAgg>() {
                     SerializedLogicalPlan::CrossJoinAgg {
@@ -944,6 +1025,15 @@ impl SerializedPlan {
                     }
                 } else if let Some(_) = node.as_any().downcast_ref::<PanicWorkerNode>() {
                     SerializedLogicalPlan::Panic {}
+                } else if let Some(j) = node.as_any().downcast_ref::<HashJoinNode>() {
+                    SerializedLogicalPlan::HashJoin {
+                        left: Arc::new(Self::serialized_logical_plan(&j.left)),
+                        right: Arc::new(Self::serialized_logical_plan(&j.right)),
+                        on: j.on.clone(),
+                        join_type: j.join_type.clone(),
+                        schema: j.schema.clone(),
+                        build_right: j.build_right,
+                    }
                 } else {
                     panic!("unknown extension");
                 }
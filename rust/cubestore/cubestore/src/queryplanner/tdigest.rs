@@ -0,0 +1,302 @@
+use crate::CubeError;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::f64::consts::PI;
+use std::io::Cursor;
+
+/// Compression used for digests created by Cube Store itself. Merged digests keep the compression
+/// of their inputs.
+pub const DEFAULT_COMPRESSION: f64 = 100.;
+
+const SERIALIZATION_FORMAT: u8 = 0;
+const DOUBLE_TYPE: u8 = 0;
+/// Format, type, min, max, sum, compression, total weight and the number of centroids.
+const HEADER_SIZE: usize = 2 + 5 * 8 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// Merging t-digest for approximate quantiles.
+/// Serialized layout follows the `tdigest` type of Presto, so sketches built there can be
+/// imported as is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TDigest {
+    compression: f64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    total_weight: f64,
+    /// Sorted by mean after [compress].
+    centroids: Vec<Centroid>,
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> TDigest {
+        TDigest {
+            compression,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.,
+            total_weight: 0.,
+            centroids: Vec::new(),
+        }
+    }
+
+    pub fn read(data: &[u8]) -> Result<TDigest, CubeError> {
+        if data.len() < HEADER_SIZE {
+            return Err(CubeError::user(format!(
+                "invalid serialized t-digest ({} bytes is too short)",
+                data.len()
+            )));
+        }
+        let mut r = Cursor::new(data);
+        let format = r.read_u8()?;
+        let value_type = r.read_u8()?;
+        if format != SERIALIZATION_FORMAT || value_type != DOUBLE_TYPE {
+            return Err(CubeError::user(format!(
+                "unsupported t-digest format {} with value type {}",
+                format, value_type
+            )));
+        }
+        let min = r.read_f64::<LittleEndian>()?;
+        let max = r.read_f64::<LittleEndian>()?;
+        let sum = r.read_f64::<LittleEndian>()?;
+        let compression = r.read_f64::<LittleEndian>()?;
+        let total_weight = r.read_f64::<LittleEndian>()?;
+        let num_centroids = r.read_i32::<LittleEndian>()?;
+        if num_centroids < 0
+            || data.len() != HEADER_SIZE + 2 * 8 * num_centroids as usize
+            || !(compression > 0.)
+        {
+            return Err(CubeError::user(
+                "invalid serialized t-digest (corrupted header)".to_string(),
+            ));
+        }
+        let num_centroids = num_centroids as usize;
+        let mut weights = Vec::with_capacity(num_centroids);
+        for _ in 0..num_centroids {
+            weights.push(r.read_f64::<LittleEndian>()?);
+        }
+        let mut centroids = Vec::with_capacity(num_centroids);
+        let mut actual_weight = 0.;
+        for weight in weights {
+            let mean = r.read_f64::<LittleEndian>()?;
+            if !(weight > 0.) || !mean.is_finite() {
+                return Err(CubeError::user(
+                    "invalid serialized t-digest (corrupted centroids)".to_string(),
+                ));
+            }
+            actual_weight += weight;
+            centroids.push(Centroid { mean, weight });
+        }
+        if (actual_weight - total_weight).abs() > 1e-6 * total_weight.max(1.) {
+            return Err(CubeError::user(format!(
+                "invalid serialized t-digest (total weight is {}, centroids sum up to {})",
+                total_weight, actual_weight
+            )));
+        }
+        let mut d = TDigest {
+            compression,
+            min,
+            max,
+            sum,
+            total_weight,
+            centroids,
+        };
+        d.centroids
+            .sort_unstable_by(|l, r| l.mean.partial_cmp(&r.mean).unwrap());
+        Ok(d)
+    }
+
+    pub fn write(&mut self) -> Vec<u8> {
+        self.compress();
+        let mut r = Vec::with_capacity(HEADER_SIZE + 2 * 8 * self.centroids.len());
+        // Writes into a vector never fail.
+        r.write_u8(SERIALIZATION_FORMAT).unwrap();
+        r.write_u8(DOUBLE_TYPE).unwrap();
+        r.write_f64::<LittleEndian>(self.min).unwrap();
+        r.write_f64::<LittleEndian>(self.max).unwrap();
+        r.write_f64::<LittleEndian>(self.sum).unwrap();
+        r.write_f64::<LittleEndian>(self.compression).unwrap();
+        r.write_f64::<LittleEndian>(self.total_weight).unwrap();
+        r.write_i32::<LittleEndian>(self.centroids.len() as i32)
+            .unwrap();
+        for c in &self.centroids {
+            r.write_f64::<LittleEndian>(c.weight).unwrap();
+        }
+        for c in &self.centroids {
+            r.write_f64::<LittleEndian>(c.mean).unwrap();
+        }
+        r
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.total_weight += 1.;
+        self.centroids.push(Centroid {
+            mean: value,
+            weight: 1.,
+        });
+        if self.centroids.len() as f64 > 10. * self.compression {
+            self.compress();
+        }
+    }
+
+    /// On error, `self` is left unchanged.
+    pub fn merge_with(&mut self, other: &TDigest) -> Result<(), CubeError> {
+        if self.compression != other.compression {
+            return Err(CubeError::user(format!(
+                "cannot merge t-digests with different compression ({} and {})",
+                self.compression, other.compression
+            )));
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.total_weight += other.total_weight;
+        self.centroids.extend_from_slice(&other.centroids);
+        self.compress();
+        Ok(())
+    }
+
+    /// Returns None for empty digests. `q` must be in range [0, 1].
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        debug_assert!(0. <= q && q <= 1.);
+        self.compress();
+        let cs = &self.centroids;
+        if cs.is_empty() {
+            return None;
+        }
+        if q <= 0. {
+            return Some(self.min);
+        }
+        if 1. <= q {
+            return Some(self.max);
+        }
+        if cs.len() == 1 {
+            return Some(cs[0].mean);
+        }
+
+        let index = q * self.total_weight;
+        // Each centroid is assumed to cover the range around its mean, half of its weight on
+        // each side. Values are interpolated linearly between the neighbouring centroids.
+        let first = cs[0];
+        if index < first.weight / 2. {
+            return Some(self.min + (first.mean - self.min) * index / (first.weight / 2.));
+        }
+        let mut weight_so_far = first.weight / 2.;
+        for i in 0..cs.len() - 1 {
+            let dw = (cs[i].weight + cs[i + 1].weight) / 2.;
+            if index < weight_so_far + dw {
+                let z = (index - weight_so_far) / dw;
+                return Some(cs[i].mean + (cs[i + 1].mean - cs[i].mean) * z);
+            }
+            weight_so_far += dw;
+        }
+        let last = cs[cs.len() - 1];
+        let z = ((index - weight_so_far) / (last.weight / 2.)).min(1.);
+        Some(last.mean + (self.max - last.mean) * z)
+    }
+
+    /// Merges adjacent centroids while the k1 scale function allows it. This keeps the centroids
+    /// near the tails small, so extreme quantiles stay accurate.
+    fn compress(&mut self) {
+        self.centroids
+            .sort_unstable_by(|l, r| l.mean.partial_cmp(&r.mean).unwrap());
+        if self.centroids.len() <= 1 {
+            return;
+        }
+        let total = self.total_weight;
+        let compression = self.compression;
+        let k = |q: f64| compression / (2. * PI) * (2. * q.max(0.).min(1.) - 1.).asin();
+
+        let mut merged: Vec<Centroid> = Vec::with_capacity(self.centroids.len());
+        let mut weight_before_last = 0.;
+        for c in self.centroids.drain(..) {
+            if let Some(last) = merged.last_mut() {
+                let q_left = weight_before_last / total;
+                let q_right = (weight_before_last + last.weight + c.weight) / total;
+                if k(q_right) - k(q_left) <= 1. {
+                    let weight = last.weight + c.weight;
+                    last.mean += (c.mean - last.mean) * c.weight / weight;
+                    last.weight = weight;
+                    continue;
+                }
+                weight_before_last += last.weight;
+            }
+            merged.push(c);
+        }
+        self.centroids = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(values: impl Iterator<Item = f64>) -> TDigest {
+        let mut d = TDigest::new(DEFAULT_COMPRESSION);
+        for v in values {
+            d.add(v);
+        }
+        d
+    }
+
+    #[test]
+    fn small_digests_are_exact() {
+        let mut d = digest((1..=5).map(|v| v as f64));
+        d.merge_with(&digest((6..=10).map(|v| v as f64))).unwrap();
+        assert_eq!(d.quantile(0.), Some(1.));
+        assert_eq!(d.quantile(0.5), Some(5.5));
+        assert_eq!(d.quantile(0.9), Some(9.5));
+        assert_eq!(d.quantile(1.), Some(10.));
+        assert_eq!(TDigest::new(DEFAULT_COMPRESSION).quantile(0.5), None);
+    }
+
+    #[test]
+    fn merged_quantiles_are_close() {
+        // Merge shuffled parts, like partial aggregates computed on different workers.
+        let mut d = TDigest::new(DEFAULT_COMPRESSION);
+        for part in 0..10 {
+            d.merge_with(&digest(
+                (0..10_000).map(|v| ((v * 10 + part) % 100_000) as f64),
+            ))
+            .unwrap();
+        }
+        assert!(d.centroids.len() < 2 * DEFAULT_COMPRESSION as usize);
+        for q in [0.01, 0.25, 0.5, 0.95, 0.99] {
+            let actual = d.quantile(q).unwrap();
+            let expected = q * 100_000.;
+            assert!(
+                (actual - expected).abs() < 1_000.,
+                "quantile {}: expected {}, got {}",
+                q,
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn serialization() {
+        let mut d = digest((0..1000).map(|v| (v % 37) as f64));
+        let data = d.write();
+        let mut read = TDigest::read(&data).unwrap();
+        assert_eq!(read, d);
+        assert_eq!(read.write(), data);
+
+        assert!(TDigest::read(&[]).is_err());
+        assert!(TDigest::read(&data[0..data.len() - 1]).is_err());
+        let mut corrupted = data.clone();
+        corrupted[0] = 1;
+        assert!(TDigest::read(&corrupted).is_err());
+        // Total weight does not match the centroids.
+        let mut corrupted = data.clone();
+        corrupted[34..42].copy_from_slice(&1.0f64.to_le_bytes());
+        assert!(TDigest::read(&corrupted).is_err());
+    }
+}
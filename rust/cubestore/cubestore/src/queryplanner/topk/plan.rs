@@ -74,6 +74,7 @@ pub fn materialize_topk(p: LogicalPlan) -> Result<LogicalPlan, DataFusionError>
                                         order_by: sort_columns,
                                         schema: aggregate_schema.clone(),
                                         snapshots: cs.snapshots.clone(),
+                                        distribution: cs.distribution.clone(),
                                     }),
                                 };
                                 if let Some(p) = projection {
@@ -309,6 +310,7 @@ pub fn plan_topk(
     let cluster = ext_planner.plan_cluster_send(
         sort,
         &node.snapshots,
+        &node.distribution,
         schema.clone(),
         /*use_streaming*/ true,
         /*max_batch_rows*/ max(2 * node.limit, MIN_TOPK_STREAM_ROWS),
@@ -1,7 +1,11 @@
 use crate::queryplanner::coalesce::{coalesce, SUPPORTED_COALESCE_TYPES};
-use crate::queryplanner::hll::Hll;
+use crate::queryplanner::hll::{hash_value, Hll};
+use crate::queryplanner::tdigest::TDigest;
 use crate::CubeError;
-use arrow::array::{Array, BinaryArray, TimestampNanosecondArray, UInt64Builder};
+use arrow::array::{
+    Array, BinaryArray, Float64Array, Float64Builder, StringArray, StringBuilder,
+    TimestampNanosecondArray, UInt64Builder,
+};
 use arrow::datatypes::{DataType, IntervalUnit, TimeUnit};
 use chrono::{TimeZone, Utc};
 use datafusion::cube_ext::datetime::{date_addsub_array, date_addsub_scalar};
@@ -24,6 +28,8 @@ pub enum CubeScalarUDFKind {
     UnixTimestamp,
     DateAdd,
     DateSub,
+    TDigestQuantile, // quantile(), accepting the t-digest sketches.
+    HllExport,       // hll_export(), converting HyperLogLog sketches to external formats.
 }
 
 pub trait CubeScalarUDF {
@@ -40,6 +46,8 @@ pub fn scalar_udf_by_kind(k: CubeScalarUDFKind) -> Box<dyn CubeScalarUDF> {
         CubeScalarUDFKind::UnixTimestamp => Box::new(UnixTimestamp {}),
         CubeScalarUDFKind::DateAdd => Box::new(DateAddSub { is_add: true }),
         CubeScalarUDFKind::DateSub => Box::new(DateAddSub { is_add: false }),
+        CubeScalarUDFKind::TDigestQuantile => Box::new(TDigestQuantile {}),
+        CubeScalarUDFKind::HllExport => Box::new(HllExport {}),
     }
 }
 
@@ -63,12 +71,21 @@ pub fn scalar_kind_by_name(n: &str) -> Option<CubeScalarUDFKind> {
     if n == "DATE_SUB" {
         return Some(CubeScalarUDFKind::DateSub);
     }
+    if n == "QUANTILE" {
+        return Some(CubeScalarUDFKind::TDigestQuantile);
+    }
+    if n == "HLL_EXPORT" {
+        return Some(CubeScalarUDFKind::HllExport);
+    }
     return None;
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum CubeAggregateUDFKind {
-    MergeHll, // merge(), accepting the HyperLogLog sketches.
+    MergeHll,     // merge(), accepting the HyperLogLog sketches.
+    MergeTDigest, // merge_tdigest(), accepting the t-digest sketches.
+    HllInit,      // hll_init(), building Airlift HyperLogLog sketches from values.
+    HllppInit,    // hllpp_init(), building ZetaSketch HyperLogLog++ sketches from values.
 }
 
 pub trait CubeAggregateUDF {
@@ -81,6 +98,10 @@ pub trait CubeAggregateUDF {
 pub fn aggregate_udf_by_kind(k: CubeAggregateUDFKind) -> Box<dyn CubeAggregateUDF> {
     match k {
         CubeAggregateUDFKind::MergeHll => Box::new(HllMergeUDF {}),
+        CubeAggregateUDFKind::MergeTDigest => Box::new(TDigestMergeUDF {}),
+        CubeAggregateUDFKind::HllInit | CubeAggregateUDFKind::HllppInit => {
+            Box::new(HllInitUDF { kind: k })
+        }
     }
 }
 
@@ -89,6 +110,15 @@ pub fn aggregate_kind_by_name(n: &str) -> Option<CubeAggregateUDFKind> {
     if n == "MERGE" {
         return Some(CubeAggregateUDFKind::MergeHll);
     }
+    if n == "MERGE_TDIGEST" {
+        return Some(CubeAggregateUDFKind::MergeTDigest);
+    }
+    if n == "HLL_INIT" {
+        return Some(CubeAggregateUDFKind::HllInit);
+    }
+    if n == "HLLPP_INIT" {
+        return Some(CubeAggregateUDFKind::HllppInit);
+    }
     return None;
 }
 
@@ -327,6 +357,76 @@ impl CubeScalarUDF for HllCardinality {
     }
 }
 
+/// Converts Airlift sketches to the formats of other databases:
+///   - 'postgres' produces the text form of the `hll` extension binary, i.e. '\x' and hex digits,
+///   - 'snowflake' produces the JSON accepted by `HLL_IMPORT`.
+struct HllExport {}
+impl CubeScalarUDF for HllExport {
+    fn kind(&self) -> CubeScalarUDFKind {
+        return CubeScalarUDFKind::HllExport;
+    }
+
+    fn name(&self) -> &str {
+        return "HLL_EXPORT";
+    }
+
+    fn descriptor(&self) -> ScalarUDF {
+        return ScalarUDF {
+            name: self.name().to_string(),
+            signature: Signature::Exact(vec![DataType::Binary, DataType::Utf8]),
+            return_type: Arc::new(|_| Ok(Arc::new(DataType::Utf8))),
+            fun: Arc::new(|a| {
+                assert_eq!(a.len(), 2);
+                let len = match (&a[0], &a[1]) {
+                    (ColumnarValue::Array(s), _) | (_, ColumnarValue::Array(s)) => s.len(),
+                    _ => 1,
+                };
+                let sketches = a[0].clone().into_array(len);
+                let sketches = sketches
+                    .as_any()
+                    .downcast_ref::<BinaryArray>()
+                    .expect("expected binary data");
+                let formats = a[1].clone().into_array(len);
+                let formats = formats
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .expect("expected string data");
+
+                let mut r = StringBuilder::new(len);
+                for i in 0..len {
+                    // Empty sketches come from merging empty inputs.
+                    if sketches.is_null(i) || formats.is_null(i) || sketches.value(i).is_empty() {
+                        r.append_null()?;
+                        continue;
+                    }
+                    let s = match read_sketch(sketches.value(i))? {
+                        Hll::Airlift(s) => s,
+                        Hll::ZetaSketch(_) => {
+                            return Err(DataFusionError::Execution(
+                                "HLL_EXPORT does not support HyperLogLog++ sketches".to_string(),
+                            ))
+                        }
+                    };
+                    match formats.value(i).to_lowercase().as_str() {
+                        "postgres" => r.append_value(format!(
+                            "\\x{}",
+                            hex::encode(s.write_hll_storage_spec())
+                        ))?,
+                        "snowflake" => r.append_value(s.write_snowflake())?,
+                        f => {
+                            return Err(DataFusionError::Execution(format!(
+                                "HLL_EXPORT expects 'postgres' or 'snowflake' format, got '{}'",
+                                f
+                            )))
+                        }
+                    }
+                }
+                return Ok(ColumnarValue::Array(Arc::new(r.finish())));
+            }),
+        };
+    }
+}
+
 struct HllMergeUDF {}
 impl CubeAggregateUDF for HllMergeUDF {
     fn kind(&self) -> CubeAggregateUDFKind {
@@ -436,3 +536,292 @@ impl HllMergeAccumulator {
 fn read_sketch(data: &[u8]) -> Result<Hll, DataFusionError> {
     return Hll::read(&data).map_err(|e| DataFusionError::Execution(e.message));
 }
+
+/// Builds HyperLogLog sketches from raw values, e.g. to fill `HYPERLOGLOG` columns with
+/// `INSERT ... SELECT`. `HLL_INIT` uses the bucket count and the hash of `approx_set` in Presto,
+/// so its sketches of integers, doubles and strings can be merged with the ones built by Presto.
+/// Other types, e.g. timestamps and decimals, are encoded differently there. `HLLPP_INIT` uses
+/// the same hash, so its sketches are marked to fail merges with the ones built by BigQuery.
+struct HllInitUDF {
+    kind: CubeAggregateUDFKind,
+}
+impl CubeAggregateUDF for HllInitUDF {
+    fn kind(&self) -> CubeAggregateUDFKind {
+        return self.kind;
+    }
+    fn name(&self) -> &str {
+        match self.kind {
+            CubeAggregateUDFKind::HllInit => "HLL_INIT",
+            CubeAggregateUDFKind::HllppInit => "HLLPP_INIT",
+            _ => panic!("unexpected kind for HllInitUDF: {:?}", self.kind),
+        }
+    }
+    fn descriptor(&self) -> AggregateUDF {
+        let kind = self.kind;
+        return AggregateUDF {
+            name: self.name().to_string(),
+            signature: Signature::Any(1),
+            return_type: Arc::new(|_| Ok(Arc::new(DataType::Binary))),
+            accumulator: Arc::new(move || Ok(Box::new(HllInitAccumulator::new(kind)?))),
+            state_type: Arc::new(|_| Ok(Arc::new(vec![DataType::Binary]))),
+        };
+    }
+    fn accumulator(&self) -> Box<dyn Accumulator> {
+        return Box::new(HllInitAccumulator::new(self.kind).unwrap());
+    }
+}
+
+#[derive(Debug)]
+struct HllInitAccumulator {
+    kind: CubeAggregateUDFKind,
+    acc: Hll,
+}
+
+impl HllInitAccumulator {
+    fn new(kind: CubeAggregateUDFKind) -> Result<HllInitAccumulator, CubeError> {
+        let acc = match kind {
+            CubeAggregateUDFKind::HllInit => Hll::new_airlift(Hll::AIRLIFT_DEFAULT_NUM_BUCKETS)?,
+            CubeAggregateUDFKind::HllppInit => Hll::new_zeta(Hll::ZETA_DEFAULT_PRECISION)?,
+            _ => panic!("unexpected kind for HllInitAccumulator: {:?}", kind),
+        };
+        return Ok(HllInitAccumulator { kind, acc });
+    }
+}
+
+impl Accumulator for HllInitAccumulator {
+    fn reset(&mut self) {
+        *self = HllInitAccumulator::new(self.kind).unwrap();
+    }
+
+    fn state(&self) -> Result<SmallVec<[ScalarValue; 2]>, DataFusionError> {
+        return Ok(smallvec![self.evaluate()?]);
+    }
+
+    fn update(&mut self, row: &[ScalarValue]) -> Result<(), DataFusionError> {
+        assert_eq!(row.len(), 1);
+        if let Some(hash) = hash_scalar(&row[0])? {
+            self.acc.insert_hash(hash)?;
+        }
+        return Ok(());
+    }
+
+    fn merge(&mut self, states: &[ScalarValue]) -> Result<(), DataFusionError> {
+        assert_eq!(states.len(), 1);
+
+        let data;
+        if let ScalarValue::Binary(Some(d)) = &states[0] {
+            data = d
+        } else {
+            return Err(
+                CubeError::internal("invalid state in HLL_INIT or HLLPP_INIT".to_string()).into(),
+            );
+        }
+        let s = read_sketch(&data)?;
+        if !self.acc.is_compatible(&s) {
+            return Err(CubeError::internal(
+                "cannot merge two incompatible HLL sketches".to_string(),
+            )
+            .into());
+        }
+        self.acc.merge_with(&s)?;
+        return Ok(());
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue, DataFusionError> {
+        return Ok(ScalarValue::Binary(Some(self.acc.write())));
+    }
+}
+
+/// Returns `None` for NULL values, they are not added to the sketches.
+/// Integers, decimals and timestamps are hashed as 64-bit integers, floats by their bits.
+fn hash_scalar(v: &ScalarValue) -> Result<Option<u64>, DataFusionError> {
+    let int = |v: Option<i64>| v.map(|v| hash_value(&v.to_le_bytes()));
+    let hash = match v {
+        ScalarValue::Boolean(v) => int(v.map(|v| v as i64)),
+        ScalarValue::Int8(v) => int(v.map(|v| v as i64)),
+        ScalarValue::Int16(v) => int(v.map(|v| v as i64)),
+        ScalarValue::Int32(v) => int(v.map(|v| v as i64)),
+        ScalarValue::Int64(v) => int(*v),
+        ScalarValue::UInt8(v) => int(v.map(|v| v as i64)),
+        ScalarValue::UInt16(v) => int(v.map(|v| v as i64)),
+        ScalarValue::UInt32(v) => int(v.map(|v| v as i64)),
+        ScalarValue::UInt64(v) => int(v.map(|v| v as i64)),
+        ScalarValue::Int64Decimal(v, _) => int(*v),
+        ScalarValue::TimestampNanosecond(v) => int(*v),
+        ScalarValue::Float32(v) => int(v.map(|v| (v as f64).to_bits() as i64)),
+        ScalarValue::Float64(v) => int(v.map(|v| v.to_bits() as i64)),
+        ScalarValue::Utf8(v) | ScalarValue::LargeUtf8(v) => {
+            v.as_ref().map(|v| hash_value(v.as_bytes()))
+        }
+        ScalarValue::Binary(v) | ScalarValue::LargeBinary(v) => v.as_ref().map(|v| hash_value(v)),
+        v => {
+            return Err(DataFusionError::Execution(format!(
+                "unsupported value passed to HLL_INIT or HLLPP_INIT: {:?}",
+                v
+            )))
+        }
+    };
+    return Ok(hash);
+}
+
+struct TDigestQuantile {}
+impl CubeScalarUDF for TDigestQuantile {
+    fn kind(&self) -> CubeScalarUDFKind {
+        return CubeScalarUDFKind::TDigestQuantile;
+    }
+
+    fn name(&self) -> &str {
+        return "QUANTILE";
+    }
+
+    fn descriptor(&self) -> ScalarUDF {
+        return ScalarUDF {
+            name: self.name().to_string(),
+            signature: Signature::Exact(vec![DataType::Binary, DataType::Float64]),
+            return_type: Arc::new(|_| Ok(Arc::new(DataType::Float64))),
+            fun: Arc::new(|a| {
+                assert_eq!(a.len(), 2);
+                let len = match (&a[0], &a[1]) {
+                    (ColumnarValue::Array(s), _) | (_, ColumnarValue::Array(s)) => s.len(),
+                    _ => 1,
+                };
+                let sketches = a[0].clone().into_array(len);
+                let sketches = sketches
+                    .as_any()
+                    .downcast_ref::<BinaryArray>()
+                    .expect("expected binary data");
+                let qs = a[1].clone().into_array(len);
+                let qs = qs
+                    .as_any()
+                    .downcast_ref::<Float64Array>()
+                    .expect("expected float data");
+
+                let mut r = Float64Builder::new(len);
+                for i in 0..len {
+                    if sketches.is_null(i) || qs.is_null(i) {
+                        r.append_null()?;
+                        continue;
+                    }
+                    let q = qs.value(i);
+                    if !(0. <= q && q <= 1.) {
+                        return Err(DataFusionError::Execution(format!(
+                            "QUANTILE expects a value between 0 and 1, got {}",
+                            q
+                        )));
+                    }
+                    let d = sketches.value(i);
+                    // Empty sketches come from merging empty inputs.
+                    if d.len() == 0 {
+                        r.append_null()?;
+                        continue;
+                    }
+                    match read_tdigest(d)?.quantile(q) {
+                        None => r.append_null()?,
+                        Some(v) => r.append_value(v)?,
+                    }
+                }
+                return Ok(ColumnarValue::Array(Arc::new(r.finish())));
+            }),
+        };
+    }
+}
+
+struct TDigestMergeUDF {}
+impl CubeAggregateUDF for TDigestMergeUDF {
+    fn kind(&self) -> CubeAggregateUDFKind {
+        return CubeAggregateUDFKind::MergeTDigest;
+    }
+    fn name(&self) -> &str {
+        return "MERGE_TDIGEST";
+    }
+    fn descriptor(&self) -> AggregateUDF {
+        return AggregateUDF {
+            name: self.name().to_string(),
+            signature: Signature::Exact(vec![DataType::Binary]),
+            return_type: Arc::new(|_| Ok(Arc::new(DataType::Binary))),
+            accumulator: Arc::new(|| Ok(Box::new(TDigestMergeAccumulator { acc: None }))),
+            state_type: Arc::new(|_| Ok(Arc::new(vec![DataType::Binary]))),
+        };
+    }
+    fn accumulator(&self) -> Box<dyn Accumulator> {
+        return Box::new(TDigestMergeAccumulator { acc: None });
+    }
+}
+
+#[derive(Debug)]
+struct TDigestMergeAccumulator {
+    // Compression is only known from the inputs, so there is no digest for the empty set.
+    acc: Option<TDigest>,
+}
+
+impl Accumulator for TDigestMergeAccumulator {
+    fn reset(&mut self) {
+        self.acc = None;
+    }
+
+    fn state(&self) -> Result<SmallVec<[ScalarValue; 2]>, DataFusionError> {
+        return Ok(smallvec![self.evaluate()?]);
+    }
+
+    fn update(&mut self, row: &[ScalarValue]) -> Result<(), DataFusionError> {
+        assert_eq!(row.len(), 1);
+        let data;
+        if let ScalarValue::Binary(v) = &row[0] {
+            if let Some(d) = v {
+                data = d
+            } else {
+                return Ok(()); // ignore NULL.
+            }
+        } else {
+            return Err(CubeError::internal(
+                "invalid scalar value passed to MERGE_TDIGEST, expecting t-digest sketch"
+                    .to_string(),
+            )
+            .into());
+        }
+        return self.merge_sketch(read_tdigest(&data)?);
+    }
+
+    fn merge(&mut self, states: &[ScalarValue]) -> Result<(), DataFusionError> {
+        assert_eq!(states.len(), 1);
+
+        let data;
+        if let ScalarValue::Binary(v) = &states[0] {
+            if let Some(d) = v {
+                data = d
+            } else {
+                return Ok(()); // ignore NULL.
+            }
+        } else {
+            return Err(CubeError::internal("invalid state in MERGE_TDIGEST".to_string()).into());
+        }
+        // empty state is ok, this means an empty sketch.
+        if data.len() == 0 {
+            return Ok(());
+        }
+        return self.merge_sketch(read_tdigest(&data)?);
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue, DataFusionError> {
+        let v;
+        match &self.acc {
+            None => v = Vec::new(),
+            Some(s) => v = s.clone().write(),
+        }
+        return Ok(ScalarValue::Binary(Some(v)));
+    }
+}
+
+impl TDigestMergeAccumulator {
+    fn merge_sketch(&mut self, s: TDigest) -> Result<(), DataFusionError> {
+        match &mut self.acc {
+            None => self.acc = Some(s),
+            Some(acc_s) => acc_s.merge_with(&s)?,
+        }
+        return Ok(());
+    }
+}
+
+fn read_tdigest(data: &[u8]) -> Result<TDigest, DataFusionError> {
+    return TDigest::read(&data).map_err(|e| DataFusionError::Execution(e.message));
+}
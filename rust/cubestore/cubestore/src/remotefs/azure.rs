@@ -0,0 +1,473 @@
+use crate::di_service;
+use crate::remotefs::{LocalDirRemoteFs, RemoteFile, RemoteFs};
+use crate::util::lock::acquire_lock;
+use crate::CubeError;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use datafusion::cube_ext;
+use log::{debug, info};
+use reqwest::{Method, Response};
+use ring::hmac;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tempfile::{NamedTempFile, PathPersistError};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+use url::Url;
+
+const API_VERSION: &str = "2020-04-08";
+/// Files larger than this are uploaded in blocks of this size, so we never keep a whole file in
+/// memory.
+const BLOCK_SIZE: u64 = 32 * 1024 * 1024;
+
+enum AzureCredentials {
+    SharedKey(Vec<u8>),
+    SasToken(String),
+}
+
+pub struct AzureBlobRemoteFs {
+    dir: PathBuf,
+    account: String,
+    container: String,
+    sub_path: Option<String>,
+    endpoint: Url,
+    credentials: AzureCredentials,
+    client: reqwest::Client,
+    delete_mut: Mutex<()>,
+}
+
+impl fmt::Debug for AzureBlobRemoteFs {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Do not expose Azure (secret) credentials.
+        f.debug_struct("AzureBlobRemoteFs")
+            .field("dir", &self.dir)
+            .field("account", &self.account)
+            .field("container", &self.container)
+            .field("sub_path", &self.sub_path)
+            .field("endpoint", &self.endpoint)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AzureBlobRemoteFs {
+    pub fn new(
+        dir: PathBuf,
+        account: String,
+        container: String,
+        sub_path: Option<String>,
+    ) -> Result<Arc<Self>, CubeError> {
+        let credentials = if let Ok(key) = env::var("CUBESTORE_AZURE_ACCESS_KEY") {
+            AzureCredentials::SharedKey(base64::decode(key.trim())?)
+        } else if let Ok(token) = env::var("CUBESTORE_AZURE_SAS_TOKEN") {
+            AzureCredentials::SasToken(token.trim_start_matches('?').to_string())
+        } else {
+            return Err(CubeError::user(
+                "CUBESTORE_AZURE_ACCESS_KEY or CUBESTORE_AZURE_SAS_TOKEN is required for Azure Blob Storage".to_string(),
+            ));
+        };
+        // Custom endpoints are used with emulators, e.g. http://127.0.0.1:10000/devstoreaccount1
+        // for Azurite.
+        let endpoint = Url::parse(
+            &env::var("CUBESTORE_AZURE_ENDPOINT")
+                .unwrap_or_else(|_| format!("https://{}.blob.core.windows.net", account)),
+        )?;
+        if endpoint.cannot_be_a_base() {
+            return Err(CubeError::user(format!(
+                "Invalid Azure Blob Storage endpoint: {}",
+                endpoint
+            )));
+        }
+        Ok(Arc::new(Self {
+            dir,
+            account,
+            container,
+            sub_path,
+            endpoint,
+            credentials,
+            client: reqwest::Client::new(),
+            delete_mut: Mutex::new(()),
+        }))
+    }
+
+    /// Creates the container unless it already exists.
+    pub async fn create_container(&self) -> Result<(), CubeError> {
+        let mut url = self.container_url();
+        url.query_pairs_mut().append_pair("restype", "container");
+        let response = self.send(Method::PUT, url, &[], Some(Vec::new())).await?;
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            return Ok(());
+        }
+        Self::check_status(response, "Create container", &self.container).await?;
+        Ok(())
+    }
+}
+
+di_service!(AzureBlobRemoteFs, [RemoteFs]);
+
+#[async_trait]
+impl RemoteFs for AzureBlobRemoteFs {
+    async fn upload_file(
+        &self,
+        temp_upload_path: &str,
+        remote_path: &str,
+    ) -> Result<u64, CubeError> {
+        let time = SystemTime::now();
+        debug!("Uploading {}", remote_path);
+        let size = fs::metadata(temp_upload_path).await?.len();
+        if size <= BLOCK_SIZE {
+            let data = fs::read(temp_upload_path).await?;
+            let response = self
+                .send(
+                    Method::PUT,
+                    self.blob_url(remote_path),
+                    &[("x-ms-blob-type", "BlockBlob".to_string())],
+                    Some(data),
+                )
+                .await?;
+            Self::check_status(response, "Upload", remote_path).await?;
+        } else {
+            let mut file = fs::File::open(temp_upload_path).await?;
+            let mut block_ids = Vec::new();
+            loop {
+                let mut block = Vec::with_capacity(BLOCK_SIZE as usize);
+                (&mut file).take(BLOCK_SIZE).read_to_end(&mut block).await?;
+                if block.is_empty() {
+                    break;
+                }
+                // All block ids of a blob must have the same length.
+                let block_id = base64::encode(format!("{:08}", block_ids.len()));
+                let mut url = self.blob_url(remote_path);
+                url.query_pairs_mut()
+                    .append_pair("comp", "block")
+                    .append_pair("blockid", &block_id);
+                let response = self.send(Method::PUT, url, &[], Some(block)).await?;
+                Self::check_status(response, "Upload block", remote_path).await?;
+                block_ids.push(block_id);
+            }
+            let block_list = format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?><BlockList>{}</BlockList>",
+                block_ids
+                    .iter()
+                    .map(|id| format!("<Latest>{}</Latest>", id))
+                    .collect::<String>()
+            );
+            let mut url = self.blob_url(remote_path);
+            url.query_pairs_mut().append_pair("comp", "blocklist");
+            let response = self
+                .send(Method::PUT, url, &[], Some(block_list.into_bytes()))
+                .await?;
+            Self::check_status(response, "Commit blocks", remote_path).await?;
+        }
+
+        let local_path = self.dir.as_path().join(remote_path);
+        if Path::new(temp_upload_path) != local_path {
+            fs::create_dir_all(local_path.parent().unwrap())
+                .await
+                .map_err(|e| {
+                    CubeError::internal(format!(
+                        "Create dir {}: {}",
+                        local_path.parent().as_ref().unwrap().to_string_lossy(),
+                        e
+                    ))
+                })?;
+            fs::rename(&temp_upload_path, local_path.clone()).await?;
+        }
+        info!("Uploaded {} ({:?})", remote_path, time.elapsed()?);
+        Ok(size)
+    }
+
+    async fn download_file(
+        &self,
+        remote_path: &str,
+        _expected_file_size: Option<u64>,
+    ) -> Result<String, CubeError> {
+        let mut local_file = self.dir.as_path().join(remote_path);
+        let local_dir = local_file.parent().unwrap();
+        let downloads_dirs = local_dir.join("downloads");
+
+        fs::create_dir_all(&downloads_dirs).await?;
+        if !local_file.exists() {
+            let time = SystemTime::now();
+            debug!("Downloading {}", remote_path);
+            let (temp_file, temp_path) =
+                cube_ext::spawn_blocking(move || NamedTempFile::new_in(downloads_dirs))
+                    .await??
+                    .into_parts();
+            let mut writer = BufWriter::new(tokio::fs::File::from_std(temp_file));
+            let response = self
+                .send(Method::GET, self.blob_url(remote_path), &[], None)
+                .await?;
+            let mut response = Self::check_status(response, "Download", remote_path).await?;
+
+            let mut size = 0;
+            while let Some(chunk) = response.chunk().await? {
+                writer.write_all(&chunk).await?;
+                size += chunk.len();
+            }
+            writer.flush().await?;
+
+            local_file = cube_ext::spawn_blocking(move || -> Result<PathBuf, PathPersistError> {
+                temp_path.persist(&local_file)?;
+                Ok(local_file)
+            })
+            .await??;
+
+            info!(
+                "Downloaded {} ({:?}) ({} bytes)",
+                remote_path,
+                time.elapsed()?,
+                size
+            );
+        }
+        Ok(local_file.into_os_string().into_string().unwrap())
+    }
+
+    async fn delete_file(&self, remote_path: &str) -> Result<(), CubeError> {
+        let time = SystemTime::now();
+        debug!("Deleting {}", remote_path);
+        let response = self
+            .send(Method::DELETE, self.blob_url(remote_path), &[], None)
+            .await?;
+        Self::check_status(response, "Delete", remote_path).await?;
+        info!("Deleting {} ({:?})", remote_path, time.elapsed()?);
+
+        let _guard = acquire_lock("delete file", self.delete_mut.lock()).await?;
+        let local = self.dir.as_path().join(remote_path);
+        if fs::metadata(local.clone()).await.is_ok() {
+            fs::remove_file(local.clone()).await?;
+            LocalDirRemoteFs::remove_empty_paths(self.dir.as_path().to_path_buf(), local.clone())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self, remote_prefix: &str) -> Result<Vec<String>, CubeError> {
+        Ok(self
+            .list_with_metadata(remote_prefix)
+            .await?
+            .into_iter()
+            .map(|f| f.remote_path)
+            .collect::<Vec<_>>())
+    }
+
+    async fn list_with_metadata(&self, remote_prefix: &str) -> Result<Vec<RemoteFile>, CubeError> {
+        let sub_path_prefix = self.blob_name("");
+        let mut result = Vec::new();
+        let mut marker = None;
+        loop {
+            let mut url = self.container_url();
+            url.query_pairs_mut()
+                .append_pair("restype", "container")
+                .append_pair("comp", "list")
+                .append_pair("prefix", &self.blob_name(remote_prefix));
+            if let Some(marker) = &marker {
+                url.query_pairs_mut().append_pair("marker", marker);
+            }
+            let response = self.send(Method::GET, url, &[], None).await?;
+            let response = Self::check_status(response, "List", remote_prefix).await?;
+            let list: EnumerationResults = serde_xml_rs::from_str(&response.text().await?)?;
+            for blob in list.blobs.blobs {
+                result.push(RemoteFile {
+                    remote_path: blob
+                        .name
+                        .strip_prefix(&sub_path_prefix)
+                        .unwrap_or(&blob.name)
+                        .to_string(),
+                    updated: DateTime::parse_from_rfc2822(&blob.properties.last_modified)?
+                        .with_timezone(&Utc),
+                    file_size: blob.properties.content_length,
+                });
+            }
+            marker = list.next_marker.filter(|m| !m.is_empty());
+            if marker.is_none() {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    async fn local_path(&self) -> String {
+        self.dir.to_str().unwrap().to_owned()
+    }
+
+    async fn local_file(&self, remote_path: &str) -> Result<String, CubeError> {
+        let buf = self.dir.join(remote_path);
+        fs::create_dir_all(buf.parent().unwrap()).await?;
+        Ok(buf.to_str().unwrap().to_string())
+    }
+}
+
+impl AzureBlobRemoteFs {
+    fn blob_name(&self, remote_path: &str) -> String {
+        match &self.sub_path {
+            Some(sub_path) => format!("{}/{}", sub_path.trim_end_matches('/'), remote_path),
+            None => remote_path.to_string(),
+        }
+    }
+
+    fn container_url(&self) -> Url {
+        let mut url = self.endpoint.clone();
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .push(&self.container);
+        url
+    }
+
+    fn blob_url(&self, remote_path: &str) -> Url {
+        let mut url = self.container_url();
+        url.path_segments_mut()
+            .unwrap()
+            .extend(self.blob_name(remote_path).split('/'));
+        url
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        mut url: Url,
+        headers: &[(&str, String)],
+        body: Option<Vec<u8>>,
+    ) -> Result<Response, CubeError> {
+        let mut headers = headers.to_vec();
+        headers.push((
+            "x-ms-date",
+            Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        ));
+        headers.push(("x-ms-version", API_VERSION.to_string()));
+
+        let mut authorization = None;
+        match &self.credentials {
+            AzureCredentials::SharedKey(key) => {
+                let content_length = body.as_ref().map(|b| b.len()).unwrap_or(0);
+                let string_to_sign = self.string_to_sign(&method, &url, &headers, content_length);
+                let signature = hmac::sign(
+                    &hmac::Key::new(hmac::HMAC_SHA256, key),
+                    string_to_sign.as_bytes(),
+                );
+                authorization = Some(format!(
+                    "SharedKey {}:{}",
+                    self.account,
+                    base64::encode(signature.as_ref())
+                ));
+            }
+            AzureCredentials::SasToken(token) => {
+                let query = match url.query() {
+                    Some(q) => format!("{}&{}", q, token),
+                    None => token.clone(),
+                };
+                url.set_query(Some(&query));
+            }
+        }
+
+        let mut request = self.client.request(method, url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if let Some(authorization) = authorization {
+            request = request.header("Authorization", authorization);
+        }
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+        Ok(request.send().await?)
+    }
+
+    /// See https://docs.microsoft.com/en-us/rest/api/storageservices/authorize-with-shared-key.
+    fn string_to_sign(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &[(&str, String)],
+        content_length: usize,
+    ) -> String {
+        let content_length = if content_length == 0 {
+            "".to_string()
+        } else {
+            content_length.to_string()
+        };
+        let mut ms_headers = headers
+            .iter()
+            .map(|(n, v)| (n.to_lowercase(), v.trim()))
+            .filter(|(n, _)| n.starts_with("x-ms-"))
+            .collect::<Vec<_>>();
+        ms_headers.sort();
+        let canonical_headers = ms_headers
+            .iter()
+            .map(|(n, v)| format!("{}:{}\n", n, v))
+            .collect::<String>();
+
+        let mut params = BTreeMap::<String, Vec<String>>::new();
+        for (n, v) in url.query_pairs() {
+            params
+                .entry(n.to_lowercase())
+                .or_default()
+                .push(v.to_string());
+        }
+        let mut canonical_resource = format!("/{}{}", self.account, url.path());
+        for (n, mut values) in params {
+            values.sort();
+            canonical_resource.push_str(&format!("\n{}:{}", n, values.join(",")));
+        }
+
+        // Content-Encoding, Content-Language, Content-MD5, Content-Type, Date, If-* and Range
+        // headers are never sent.
+        format!(
+            "{}\n\n\n{}\n\n\n\n\n\n\n\n\n{}{}",
+            method, content_length, canonical_headers, canonical_resource
+        )
+    }
+
+    async fn check_status(
+        response: Response,
+        operation: &str,
+        remote_path: &str,
+    ) -> Result<Response, CubeError> {
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        Err(CubeError::internal(format!(
+            "{} {} failed with {}: {}",
+            operation, remote_path, status, text
+        )))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EnumerationResults {
+    #[serde(default)]
+    blobs: Blobs,
+    next_marker: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Blobs {
+    #[serde(rename = "Blob", default)]
+    blobs: Vec<Blob>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Blob {
+    name: String,
+    properties: BlobProperties,
+}
+
+#[derive(Deserialize, Debug)]
+struct BlobProperties {
+    #[serde(rename = "Last-Modified")]
+    last_modified: String,
+    #[serde(rename = "Content-Length")]
+    content_length: u64,
+}
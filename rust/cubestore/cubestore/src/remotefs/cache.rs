@@ -0,0 +1,447 @@
+use crate::app_metrics;
+use crate::CubeError;
+use datafusion::cube_ext;
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::io::ErrorKind;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CacheEvictionPolicy {
+    /// Evict files that were not accessed for the longest time.
+    Lru,
+    /// Evict files with the least number of accesses, least recently used first among them.
+    Lfu,
+}
+
+impl FromStr for CacheEvictionPolicy {
+    type Err = CubeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lru" => Ok(CacheEvictionPolicy::Lru),
+            "lfu" => Ok(CacheEvictionPolicy::Lfu),
+            _ => Err(CubeError::user(format!(
+                "Unknown eviction policy '{}', expected one of: lru, lfu",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedFile {
+    local_path: String,
+    size: u64,
+    /// Logical time of the last access, used to order files for eviction.
+    last_access_tick: u64,
+    accesses: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    files: HashMap<String, CachedFile>,
+    total_size: u64,
+    tick: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, remote_path: &str, local_path: &str, size: u64) {
+        self.tick += 1;
+        let tick = self.tick;
+        let file = self
+            .files
+            .entry(remote_path.to_string())
+            .or_insert_with(|| CachedFile {
+                local_path: local_path.to_string(),
+                size: 0,
+                last_access_tick: tick,
+                accesses: 0,
+            });
+        self.total_size = self.total_size - file.size + size;
+        file.size = size;
+        file.last_access_tick = tick;
+        file.accesses += 1;
+    }
+
+    fn remove(&mut self, remote_path: &str) {
+        if let Some(f) = self.files.remove(remote_path) {
+            self.total_size -= f.size;
+        }
+    }
+}
+
+/// Number of pins of each file.
+type Pins = Arc<std::sync::Mutex<HashMap<String, usize>>>;
+
+/// Keeps the local copy of a file from eviction while alive. See [LocalFileCache::pin].
+#[derive(Debug)]
+pub struct FilePin {
+    pins: Option<Pins>,
+    remote_path: String,
+}
+
+impl FilePin {
+    /// Pin that does nothing, for files that are never evicted.
+    pub fn none() -> FilePin {
+        FilePin {
+            pins: None,
+            remote_path: String::new(),
+        }
+    }
+}
+
+impl Drop for FilePin {
+    fn drop(&mut self) {
+        if let Some(pins) = &self.pins {
+            let mut pins = pins.lock().unwrap();
+            let count = pins.get_mut(&self.remote_path).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&self.remote_path);
+            }
+        }
+    }
+}
+
+/// Keeps the size of chunk and partition files in the local directory under `max_size` by
+/// removing local copies of files, which stay available on the remote storage.
+///
+/// Files are evicted only if they are not pinned. Queries and compactions pin their files before
+/// downloading and hold the pins until they are done reading. The cache may exceed `max_size` if
+/// all files are pinned.
+#[derive(Debug)]
+pub struct LocalFileCache {
+    max_size: Option<u64>,
+    policy: CacheEvictionPolicy,
+    state: Mutex<CacheState>,
+    pins: Pins,
+}
+
+impl LocalFileCache {
+    pub fn new(max_size: Option<u64>, policy: CacheEvictionPolicy) -> LocalFileCache {
+        LocalFileCache {
+            max_size,
+            policy,
+            state: Mutex::new(CacheState::default()),
+            pins: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Only partition and chunk files are evicted. Other files, e.g. metastore snapshots or
+    /// imports, are managed by their owners.
+    pub fn is_cached(remote_path: &str) -> bool {
+        !remote_path.contains('/') && remote_path.ends_with(".parquet")
+    }
+
+    /// Starts tracking files left in `local_dir` by previous runs, least recently modified first,
+    /// and evicts them if the cache is full.
+    pub async fn load_local_files(&self, local_dir: &str) -> Result<(), CubeError> {
+        let local_dir = local_dir.to_string();
+        let mut files = cube_ext::spawn_blocking(move || -> Result<_, CubeError> {
+            let mut files = Vec::new();
+            let entries = match std::fs::read_dir(&local_dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(files),
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries {
+                let entry = entry?;
+                let name = match entry.file_name().into_string() {
+                    Ok(name) if Self::is_cached(&name) => name,
+                    _ => continue,
+                };
+                let metadata = entry.metadata()?;
+                if !metadata.is_file() {
+                    continue;
+                }
+                let local_path = entry.path().to_string_lossy().to_string();
+                files.push((metadata.modified()?, name, local_path, metadata.len()));
+            }
+            Ok(files)
+        })
+        .await??;
+        files.sort();
+
+        let mut state = self.state.lock().await;
+        for (_, remote_path, local_path, size) in files {
+            state.touch(&remote_path, &local_path, size);
+        }
+        log::debug!(
+            "Found {} bytes of cached files in the local directory",
+            state.total_size
+        );
+        self.evict(&mut state, None).await;
+        app_metrics::LOCAL_CACHE_SIZE_BYTES.report(state.total_size as i64);
+        Ok(())
+    }
+
+    /// Keeps the local copy of `remote_path` from eviction until the returned pin is dropped.
+    /// Pin files before downloading them, so they can't be evicted before they are read.
+    pub fn pin(&self, remote_path: &str) -> FilePin {
+        if !Self::is_cached(remote_path) {
+            return FilePin::none();
+        }
+        *self
+            .pins
+            .lock()
+            .unwrap()
+            .entry(remote_path.to_string())
+            .or_default() += 1;
+        FilePin {
+            pins: Some(self.pins.clone()),
+            remote_path: remote_path.to_string(),
+        }
+    }
+
+    /// Returns the metadata of the local copy of `remote_path`, if any, and records the access.
+    /// The check and the access happen under the lock, so the file can't be evicted in between.
+    pub async fn get(&self, remote_path: &str, local_path: &str) -> Option<Metadata> {
+        let mut state = self.state.lock().await;
+        let metadata = fs::metadata(local_path).await.ok()?;
+        if Self::is_cached(remote_path) {
+            state.touch(remote_path, local_path, metadata.len());
+            app_metrics::LOCAL_CACHE_HITS.increment();
+        }
+        Some(metadata)
+    }
+
+    /// Records a file that was just downloaded and evicts other files if the cache is full.
+    pub async fn insert(&self, remote_path: &str, local_path: &str, size: u64) {
+        if !Self::is_cached(remote_path) {
+            return;
+        }
+        app_metrics::LOCAL_CACHE_MISSES.increment();
+        self.add(remote_path, local_path, size).await
+    }
+
+    /// Records a file uploaded by this node. Its local copy stays in the local directory after
+    /// upload.
+    pub async fn insert_uploaded(&self, remote_path: &str, local_path: &str, size: u64) {
+        if !Self::is_cached(remote_path) {
+            return;
+        }
+        self.add(remote_path, local_path, size).await
+    }
+
+    async fn add(&self, remote_path: &str, local_path: &str, size: u64) {
+        let mut state = self.state.lock().await;
+        state.touch(remote_path, local_path, size);
+        self.evict(&mut state, Some(remote_path)).await;
+        app_metrics::LOCAL_CACHE_SIZE_BYTES.report(state.total_size as i64);
+    }
+
+    /// Stops tracking the file, e.g. after it was deleted.
+    pub async fn remove(&self, remote_path: &str) {
+        let mut state = self.state.lock().await;
+        state.remove(remote_path);
+        app_metrics::LOCAL_CACHE_SIZE_BYTES.report(state.total_size as i64);
+    }
+
+    pub async fn size(&self) -> u64 {
+        self.state.lock().await.total_size
+    }
+
+    async fn evict(&self, state: &mut CacheState, keep_remote_path: Option<&str>) {
+        let max_size = match self.max_size {
+            Some(s) => s,
+            None => return,
+        };
+        if state.total_size <= max_size {
+            return;
+        }
+
+        let mut candidates = state
+            .files
+            .iter()
+            .filter(|(p, _)| Some(p.as_str()) != keep_remote_path)
+            .map(|(p, f)| (p.clone(), f.accesses, f.last_access_tick))
+            .collect::<Vec<_>>();
+        match self.policy {
+            CacheEvictionPolicy::Lru => candidates.sort_by_key(|(_, _, tick)| *tick),
+            CacheEvictionPolicy::Lfu => {
+                candidates.sort_by_key(|(_, accesses, tick)| (*accesses, *tick))
+            }
+        }
+
+        for (remote_path, _, _) in candidates {
+            if state.total_size <= max_size {
+                break;
+            }
+            // New pins are taken without the state lock, so check them right before removal.
+            if self.pins.lock().unwrap().contains_key(&remote_path) {
+                continue;
+            }
+            let local_path = &state.files[&remote_path].local_path;
+            match fs::remove_file(local_path).await {
+                Ok(()) => {
+                    log::debug!("Evicted {} from the local cache", remote_path);
+                    app_metrics::LOCAL_CACHE_EVICTIONS.increment();
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    log::error!("Could not evict {} from the local cache: {}", local_path, e);
+                    continue;
+                }
+            }
+            state.remove(&remote_path);
+        }
+
+        if max_size < state.total_size {
+            log::warn!(
+                "Local cache size of {} bytes exceeds the limit of {} bytes, remaining files are pinned",
+                state.total_size,
+                max_size
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::current_dir()
+            .unwrap()
+            .join(".cubestore")
+            .join("fs-test")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn download(cache: &LocalFileCache, dir: &PathBuf, name: &str, size: usize) {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0; size]).unwrap();
+        cache
+            .insert(name, path.to_str().unwrap(), size as u64)
+            .await;
+    }
+
+    async fn read(cache: &LocalFileCache, dir: &PathBuf, name: &str) -> bool {
+        cache
+            .get(name, dir.join(name).to_str().unwrap())
+            .await
+            .is_some()
+    }
+
+    #[tokio::test]
+    async fn lru() {
+        let dir = test_dir("cache-lru");
+        let cache = LocalFileCache::new(Some(30), CacheEvictionPolicy::Lru);
+        download(&cache, &dir, "1.parquet", 10).await;
+        download(&cache, &dir, "2.parquet", 10).await;
+        download(&cache, &dir, "3.parquet", 10).await;
+        assert!(read(&cache, &dir, "1.parquet").await);
+        assert_eq!(cache.size().await, 30);
+
+        download(&cache, &dir, "4.parquet", 10).await;
+        assert!(!read(&cache, &dir, "2.parquet").await);
+        assert!(read(&cache, &dir, "1.parquet").await);
+        assert!(read(&cache, &dir, "3.parquet").await);
+        assert!(read(&cache, &dir, "4.parquet").await);
+        assert_eq!(cache.size().await, 30);
+
+        download(&cache, &dir, "5.parquet", 25).await;
+        assert_eq!(cache.size().await, 25);
+        assert!(read(&cache, &dir, "5.parquet").await);
+
+        // Other files are not tracked.
+        std::fs::create_dir_all(dir.join("metastore")).unwrap();
+        download(&cache, &dir, "metastore/1.parquet", 100).await;
+        assert_eq!(cache.size().await, 25);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn lfu() {
+        let dir = test_dir("cache-lfu");
+        let cache = LocalFileCache::new(Some(20), CacheEvictionPolicy::Lfu);
+        download(&cache, &dir, "1.parquet", 10).await;
+        download(&cache, &dir, "2.parquet", 10).await;
+        // The first file is more recently used, but the second one is more frequently used.
+        assert!(read(&cache, &dir, "2.parquet").await);
+        assert!(read(&cache, &dir, "2.parquet").await);
+        assert!(read(&cache, &dir, "1.parquet").await);
+        download(&cache, &dir, "3.parquet", 10).await;
+        assert!(!read(&cache, &dir, "1.parquet").await);
+        assert!(read(&cache, &dir, "2.parquet").await);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn pinned_files_are_kept() {
+        let dir = test_dir("cache-pinned");
+        let cache = LocalFileCache::new(Some(10), CacheEvictionPolicy::Lru);
+        let pin = cache.pin("1.parquet");
+        let pin_copy = cache.pin("1.parquet");
+        download(&cache, &dir, "1.parquet", 10).await;
+        download(&cache, &dir, "2.parquet", 10).await;
+        assert!(read(&cache, &dir, "1.parquet").await);
+        assert!(read(&cache, &dir, "2.parquet").await);
+        assert_eq!(cache.size().await, 20);
+
+        // Files are evicted once all of their pins are dropped, regardless of the last access.
+        drop(pin);
+        download(&cache, &dir, "3.parquet", 10).await;
+        assert!(read(&cache, &dir, "1.parquet").await);
+        assert!(!read(&cache, &dir, "2.parquet").await);
+        drop(pin_copy);
+        download(&cache, &dir, "4.parquet", 10).await;
+        assert!(!read(&cache, &dir, "1.parquet").await);
+        assert!(!read(&cache, &dir, "3.parquet").await);
+        assert_eq!(cache.size().await, 10);
+
+        cache.remove("4.parquet").await;
+        assert_eq!(cache.size().await, 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn local_files() {
+        let dir = test_dir("cache-local-files");
+        std::fs::write(dir.join("1.parquet"), vec![0; 10]).unwrap();
+        std::fs::write(dir.join("2.parquet"), vec![0; 10]).unwrap();
+        std::fs::write(dir.join("metastore-current"), vec![0; 10]).unwrap();
+        std::fs::create_dir_all(dir.join("uploads")).unwrap();
+        std::fs::write(dir.join("uploads/3.parquet"), vec![0; 10]).unwrap();
+
+        let cache = LocalFileCache::new(Some(20), CacheEvictionPolicy::Lru);
+        cache.load_local_files(dir.to_str().unwrap()).await.unwrap();
+        assert_eq!(cache.size().await, 20);
+
+        // Files uploaded by this node are tracked too.
+        std::fs::write(dir.join("4.parquet"), vec![0; 10]).unwrap();
+        cache
+            .insert_uploaded("4.parquet", dir.join("4.parquet").to_str().unwrap(), 10)
+            .await;
+        assert_eq!(cache.size().await, 20);
+        assert_eq!(
+            [1, 2, 4]
+                .iter()
+                .filter(|i| dir.join(format!("{}.parquet", i)).exists())
+                .count(),
+            2
+        );
+        assert!(dir.join("4.parquet").exists());
+        assert!(dir.join("metastore-current").exists());
+        assert!(dir.join("uploads/3.parquet").exists());
+
+        // Files found on startup are evicted when over the limit.
+        let cache = LocalFileCache::new(Some(10), CacheEvictionPolicy::Lru);
+        cache.load_local_files(dir.to_str().unwrap()).await.unwrap();
+        assert_eq!(cache.size().await, 10);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,779 @@
+use crate::di_service;
+use crate::remotefs::{ensure_temp_file_is_dropped, LocalDirRemoteFs, RemoteFile, RemoteFs};
+use crate::util::lock::acquire_lock;
+use crate::CubeError;
+use async_trait::async_trait;
+use datafusion::cube_ext;
+use log::{debug, warn};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::fmt::Formatter;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio::sync::Mutex;
+
+/// Size of data keys and keys in [LocalKeyProvider], AES-256.
+pub const KEY_LEN: usize = 32;
+
+/// Data key of a single file, wrapped by a [KeyProvider].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrappedKey {
+    /// Identifies the key used for wrapping, so providers can still unwrap after key rotation.
+    pub key_id: String,
+    pub wrapped: Vec<u8>,
+}
+
+/// Source of customer-managed keys, e.g. a local key file or a KMS. [EncryptedRemoteFs] generates
+/// a random data key for each file and keeps it in the file header, wrapped by the provider.
+#[async_trait]
+pub trait KeyProvider: Send + Sync + fmt::Debug {
+    /// Wraps the data key with the current key.
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<WrappedKey, CubeError>;
+
+    /// Unwraps the data key wrapped by the current key or any of the previous ones.
+    async fn unwrap_key(&self, key: &WrappedKey) -> Result<Vec<u8>, CubeError>;
+}
+
+/// Reads base64-encoded 256-bit keys from a file, one per line. The first key wraps data keys of
+/// new files, the rest are only used to read files written before key rotation.
+pub struct LocalKeyProvider {
+    keys: Vec<(String, LessSafeKey)>,
+    rng: SystemRandom,
+}
+
+impl fmt::Debug for LocalKeyProvider {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Do not expose the keys.
+        f.debug_struct("LocalKeyProvider")
+            .field(
+                "key_ids",
+                &self.keys.iter().map(|k| &k.0).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl LocalKeyProvider {
+    pub fn from_file(path: &Path) -> Result<LocalKeyProvider, CubeError> {
+        let keys = std::fs::read_to_string(path).map_err(|e| {
+            CubeError::user(format!(
+                "Can't read encryption key file {}: {}",
+                path.to_string_lossy(),
+                e
+            ))
+        })?;
+        Self::parse(&keys)
+    }
+
+    pub fn parse(keys: &str) -> Result<LocalKeyProvider, CubeError> {
+        let keys = keys
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| {
+                let key = base64::decode(l)?;
+                if key.len() != KEY_LEN {
+                    return Err(CubeError::user(format!(
+                        "Encryption key should be {} bytes but {} found",
+                        KEY_LEN,
+                        key.len()
+                    )));
+                }
+                // Ids are derived from the keys, so that reordering the file does not break reads.
+                let key_id = hex::encode(&digest::digest(&digest::SHA256, &key).as_ref()[..8]);
+                Ok((key_id, aes_key(&key)?))
+            })
+            .collect::<Result<Vec<_>, CubeError>>()?;
+        if keys.is_empty() {
+            return Err(CubeError::user(
+                "Encryption key file contains no keys".to_string(),
+            ));
+        }
+        Ok(LocalKeyProvider {
+            keys,
+            rng: SystemRandom::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl KeyProvider for LocalKeyProvider {
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<WrappedKey, CubeError> {
+        let (key_id, key) = &self.keys[0];
+        let mut nonce = [0; NONCE_LEN];
+        fill_random(&self.rng, &mut nonce)?;
+        let mut wrapped = data_key.to_vec();
+        let tag = key
+            .seal_in_place_separate_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key_id.as_bytes()),
+                &mut wrapped,
+            )
+            .map_err(|_| CubeError::internal("Can't wrap data key".to_string()))?;
+        wrapped.extend_from_slice(tag.as_ref());
+        let mut r = nonce.to_vec();
+        r.extend_from_slice(&wrapped);
+        Ok(WrappedKey {
+            key_id: key_id.clone(),
+            wrapped: r,
+        })
+    }
+
+    async fn unwrap_key(&self, key: &WrappedKey) -> Result<Vec<u8>, CubeError> {
+        let (key_id, aes) = self
+            .keys
+            .iter()
+            .find(|(id, _)| id == &key.key_id)
+            .ok_or_else(|| {
+                CubeError::user(format!("Encryption key {} is not available", key.key_id))
+            })?;
+        if key.wrapped.len() < NONCE_LEN {
+            return Err(CubeError::corrupt_data(
+                "Wrapped key is too short".to_string(),
+            ));
+        }
+        let (nonce, wrapped) = key.wrapped.split_at(NONCE_LEN);
+        let mut wrapped = wrapped.to_vec();
+        let data_key = aes
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(nonce).unwrap(),
+                Aad::from(key_id.as_bytes()),
+                &mut wrapped,
+            )
+            .map_err(|_| CubeError::corrupt_data("Can't unwrap data key".to_string()))?;
+        Ok(data_key.to_vec())
+    }
+}
+
+/// Encrypts files on upload and decrypts them on download, on top of any other [RemoteFs].
+/// Only the remote side is encrypted, local files stay readable by the query engine.
+///
+/// File layout:
+/// - header of [HEADER_SIZE] bytes: magic, wrapped data key and nonce prefix, zero padded;
+/// - segments of up to [SEGMENT_SIZE] bytes encrypted with AES-256-GCM, each followed by a tag.
+///
+/// Segment nonces contain the segment number and the last segment flag, so reordering or
+/// truncating segments fails decryption. The header is authenticated as associated data.
+///
+/// Files that don't start with the magic were uploaded before encryption was turned on. They are
+/// downloaded as is if `allow_plaintext` is set.
+#[derive(Debug)]
+pub struct EncryptedRemoteFs {
+    remote_fs: Arc<dyn RemoteFs>,
+    key_provider: Arc<dyn KeyProvider>,
+    dir: PathBuf,
+    allow_plaintext: bool,
+    dir_delete_mut: Mutex<()>,
+    rng: SystemRandom,
+}
+
+const MAGIC: &[u8; 8] = b"CUBEENC1";
+/// Headers are padded to the fixed size, so encrypted file sizes only depend on plaintext sizes.
+pub const HEADER_SIZE: usize = 1024;
+pub const SEGMENT_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const NONCE_PREFIX_LEN: usize = 7;
+
+/// Size of the encrypted file with `plaintext_size` bytes of data.
+pub fn encrypted_size(plaintext_size: u64) -> u64 {
+    let segments = ((plaintext_size + SEGMENT_SIZE as u64 - 1) / SEGMENT_SIZE as u64).max(1);
+    HEADER_SIZE as u64 + plaintext_size + segments * TAG_LEN as u64
+}
+
+impl EncryptedRemoteFs {
+    /// `remote_fs` must use a local directory different from `dir`. It only keeps encrypted
+    /// files there while they are transferred, decrypted files are placed in `dir`.
+    pub fn new(
+        remote_fs: Arc<dyn RemoteFs>,
+        key_provider: Arc<dyn KeyProvider>,
+        dir: PathBuf,
+        allow_plaintext: bool,
+    ) -> Arc<EncryptedRemoteFs> {
+        Arc::new(EncryptedRemoteFs {
+            remote_fs,
+            key_provider,
+            dir,
+            allow_plaintext,
+            dir_delete_mut: Mutex::new(()),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Encrypted copies of files on the local disk are not needed once the transfer is done.
+    async fn remove_encrypted_copy(&self, remote_path: &str) -> Result<(), CubeError> {
+        let encrypted = self.remote_fs.local_file(remote_path).await?;
+        if fs::metadata(&encrypted).await.is_ok() {
+            fs::remove_file(&encrypted).await?;
+        }
+        Ok(())
+    }
+}
+
+di_service!(EncryptedRemoteFs, [RemoteFs]);
+
+#[async_trait]
+impl RemoteFs for EncryptedRemoteFs {
+    async fn upload_file(
+        &self,
+        temp_upload_path: &str,
+        remote_path: &str,
+    ) -> Result<u64, CubeError> {
+        let mut data_key = [0; KEY_LEN];
+        fill_random(&self.rng, &mut data_key)?;
+        let mut nonce_prefix = [0; NONCE_PREFIX_LEN];
+        fill_random(&self.rng, &mut nonce_prefix)?;
+        let wrapped_key = self.key_provider.wrap_key(&data_key).await?;
+        let header = encode_header(&wrapped_key, &nonce_prefix)?;
+
+        let encrypted_path = self.remote_fs.temp_upload_path(remote_path).await?;
+        let encrypted_path = scopeguard::guard(encrypted_path, ensure_temp_file_is_dropped);
+        let plaintext_path = temp_upload_path.to_string();
+        let encrypted_path_copy = encrypted_path.clone();
+        let size = cube_ext::spawn_blocking(move || {
+            encrypt_file(
+                &data_key,
+                &header,
+                &nonce_prefix,
+                Path::new(&plaintext_path),
+                Path::new(&encrypted_path_copy),
+            )
+        })
+        .await??;
+        self.remote_fs
+            .upload_file(&encrypted_path, remote_path)
+            .await?;
+        self.remove_encrypted_copy(remote_path).await?;
+
+        let local_path = self.dir.as_path().join(remote_path);
+        if Path::new(temp_upload_path) != local_path {
+            fs::create_dir_all(local_path.parent().unwrap()).await?;
+            fs::rename(temp_upload_path, &local_path).await?;
+        }
+        Ok(size)
+    }
+
+    async fn download_file(
+        &self,
+        remote_path: &str,
+        _expected_file_size: Option<u64>,
+    ) -> Result<String, CubeError> {
+        let local_file = self.dir.as_path().join(remote_path);
+        let local_file_str = local_file.to_str().unwrap().to_string(); // return value.
+        if local_file.exists() {
+            return Ok(local_file_str);
+        }
+
+        debug!("Downloading and decrypting {}", remote_path);
+        // Sizes in the metastore are the sizes of decrypted files, so we can't check them here.
+        let encrypted = self.remote_fs.download_file(remote_path, None).await?;
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        fs::File::open(&encrypted)
+            .await?
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut header)
+            .await
+            .map_err(|e| {
+                CubeError::corrupt_data(format!("Can't read header of {}: {}", remote_path, e))
+            })?;
+        if !header.starts_with(MAGIC) {
+            if !self.allow_plaintext {
+                return Err(CubeError::corrupt_data(format!(
+                    "File {} is not encrypted and reading plaintext files is disabled, set \
+                     CUBESTORE_ENCRYPTION_ALLOW_PLAINTEXT=1 to read files uploaded before \
+                     encryption was turned on",
+                    remote_path
+                )));
+            }
+            warn!("{} is not encrypted, using it as is", remote_path);
+            fs::create_dir_all(local_file.parent().unwrap()).await?;
+            fs::rename(&encrypted, &local_file).await?;
+            return Ok(local_file_str);
+        }
+        let header: [u8; HEADER_SIZE] = header.try_into().map_err(|_| {
+            CubeError::corrupt_data(format!("Encrypted file {} is truncated", remote_path))
+        })?;
+        let (wrapped_key, nonce_prefix) = decode_header(&header)?;
+        let data_key = self.key_provider.unwrap_key(&wrapped_key).await?;
+
+        let downloads_dir = local_file.parent().unwrap().join("downloads");
+        fs::create_dir_all(&downloads_dir).await?;
+        let remote_path_copy = remote_path.to_string();
+        cube_ext::spawn_blocking(move || -> Result<(), CubeError> {
+            let (mut temp_file, temp_path) = NamedTempFile::new_in(&downloads_dir)?.into_parts();
+            decrypt_file(
+                &data_key,
+                &header,
+                &nonce_prefix,
+                Path::new(&encrypted),
+                &mut temp_file,
+            )
+            .map_err(|e| {
+                CubeError::corrupt_data(format!("Can't decrypt {}: {}", remote_path_copy, e))
+            })?;
+            temp_file.flush()?;
+            temp_path.persist(local_file)?;
+            Ok(())
+        })
+        .await??;
+        self.remove_encrypted_copy(remote_path).await?;
+
+        Ok(local_file_str)
+    }
+
+    async fn delete_file(&self, remote_path: &str) -> Result<(), CubeError> {
+        self.remote_fs.delete_file(remote_path).await?;
+
+        let _local_guard = acquire_lock("delete file", self.dir_delete_mut.lock()).await?;
+        let local = self.dir.as_path().join(remote_path);
+        if fs::metadata(local.clone()).await.is_ok() {
+            fs::remove_file(local.clone()).await?;
+            LocalDirRemoteFs::remove_empty_paths(self.dir.as_path().to_path_buf(), local.clone())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, remote_prefix: &str) -> Result<Vec<String>, CubeError> {
+        self.remote_fs.list(remote_prefix).await
+    }
+
+    async fn list_with_metadata(&self, remote_prefix: &str) -> Result<Vec<RemoteFile>, CubeError> {
+        self.remote_fs.list_with_metadata(remote_prefix).await
+    }
+
+    async fn local_path(&self) -> String {
+        self.dir.to_str().unwrap().to_owned()
+    }
+
+    async fn local_file(&self, remote_path: &str) -> Result<String, CubeError> {
+        let buf = self.dir.join(remote_path);
+        fs::create_dir_all(buf.parent().unwrap()).await?;
+        Ok(buf.to_str().unwrap().to_string())
+    }
+
+    fn remote_file_size(&self, local_file_size: u64) -> u64 {
+        encrypted_size(local_file_size)
+    }
+}
+
+fn aes_key(key: &[u8]) -> Result<LessSafeKey, CubeError> {
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| CubeError::internal(format!("Bad AES key of {} bytes", key.len())))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn fill_random(rng: &SystemRandom, dest: &mut [u8]) -> Result<(), CubeError> {
+    rng.fill(dest)
+        .map_err(|_| CubeError::internal("Can't generate random bytes".to_string()))
+}
+
+fn segment_nonce(prefix: &[u8; NONCE_PREFIX_LEN], segment: u32, is_last: bool) -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&segment.to_be_bytes());
+    nonce[NONCE_LEN - 1] = is_last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+fn encode_header(
+    key: &WrappedKey,
+    nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+) -> Result<[u8; HEADER_SIZE], CubeError> {
+    let key_id = key.key_id.as_bytes();
+    if key_id.len() > u8::MAX as usize || key.wrapped.len() > u16::MAX as usize {
+        return Err(CubeError::internal(format!(
+            "Key id or wrapped key is too large: {} and {} bytes",
+            key_id.len(),
+            key.wrapped.len()
+        )));
+    }
+    let mut r = Vec::with_capacity(HEADER_SIZE);
+    r.extend_from_slice(MAGIC);
+    r.push(key_id.len() as u8);
+    r.extend_from_slice(key_id);
+    r.extend_from_slice(&(key.wrapped.len() as u16).to_le_bytes());
+    r.extend_from_slice(&key.wrapped);
+    r.extend_from_slice(nonce_prefix);
+    if HEADER_SIZE < r.len() {
+        return Err(CubeError::internal(format!(
+            "Encryption header of {} bytes exceeds {} bytes",
+            r.len(),
+            HEADER_SIZE
+        )));
+    }
+    let mut header = [0; HEADER_SIZE];
+    header[..r.len()].copy_from_slice(&r);
+    Ok(header)
+}
+
+fn decode_header(
+    header: &[u8; HEADER_SIZE],
+) -> Result<(WrappedKey, [u8; NONCE_PREFIX_LEN]), CubeError> {
+    let err = || CubeError::corrupt_data("Bad encrypted file header".to_string());
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(CubeError::corrupt_data(
+            "File is not encrypted or has unknown format".to_string(),
+        ));
+    }
+    let mut pos = MAGIC.len();
+    let mut take = move |n: usize| -> Result<&[u8], CubeError> {
+        let r = header.get(pos..pos + n).ok_or_else(err)?;
+        pos += n;
+        Ok(r)
+    };
+    let key_id_len = take(1)?[0] as usize;
+    let key_id = String::from_utf8(take(key_id_len)?.to_vec()).map_err(|_| err())?;
+    let wrapped_len = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+    let wrapped = take(wrapped_len)?.to_vec();
+    let nonce_prefix = take(NONCE_PREFIX_LEN)?.try_into().unwrap();
+    Ok((WrappedKey { key_id, wrapped }, nonce_prefix))
+}
+
+/// Returns the size of the plaintext.
+fn encrypt_file(
+    data_key: &[u8],
+    header: &[u8; HEADER_SIZE],
+    nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+    src: &Path,
+    dest: &Path,
+) -> Result<u64, CubeError> {
+    let key = aes_key(data_key)?;
+    let mut src = std::fs::File::open(src)?;
+    let size = src.metadata()?.len();
+    let mut dest = std::io::BufWriter::new(std::fs::File::create(dest)?);
+    dest.write_all(header)?;
+
+    let segments = ((size + SEGMENT_SIZE as u64 - 1) / SEGMENT_SIZE as u64).max(1);
+    let mut remaining = size;
+    let mut buf = Vec::with_capacity(SEGMENT_SIZE + TAG_LEN);
+    for i in 0..segments {
+        let len = remaining.min(SEGMENT_SIZE as u64) as usize;
+        buf.resize(len, 0);
+        src.read_exact(&mut buf)?;
+        remaining -= len as u64;
+        let tag = key
+            .seal_in_place_separate_tag(
+                segment_nonce(nonce_prefix, i as u32, i + 1 == segments),
+                Aad::from(&header[..]),
+                &mut buf,
+            )
+            .map_err(|_| CubeError::internal("Can't encrypt file segment".to_string()))?;
+        dest.write_all(&buf)?;
+        dest.write_all(tag.as_ref())?;
+    }
+    dest.flush()?;
+    Ok(size)
+}
+
+fn decrypt_file(
+    data_key: &[u8],
+    header: &[u8; HEADER_SIZE],
+    nonce_prefix: &[u8; NONCE_PREFIX_LEN],
+    src: &Path,
+    dest: &mut std::fs::File,
+) -> Result<(), CubeError> {
+    let key = aes_key(data_key)?;
+    let mut src = std::io::BufReader::new(std::fs::File::open(src)?);
+    let mut remaining = src
+        .get_ref()
+        .metadata()?
+        .len()
+        .saturating_sub(HEADER_SIZE as u64);
+    if remaining < TAG_LEN as u64 {
+        return Err(CubeError::corrupt_data("File is truncated".to_string()));
+    }
+    src.read_exact(&mut [0; HEADER_SIZE])?;
+
+    let encrypted_segment_size = (SEGMENT_SIZE + TAG_LEN) as u64;
+    let segments = (remaining + encrypted_segment_size - 1) / encrypted_segment_size;
+    let mut buf = Vec::with_capacity(SEGMENT_SIZE + TAG_LEN);
+    for i in 0..segments {
+        let len = remaining.min(encrypted_segment_size) as usize;
+        buf.resize(len, 0);
+        src.read_exact(&mut buf)?;
+        remaining -= len as u64;
+        let plaintext = key
+            .open_in_place(
+                segment_nonce(nonce_prefix, i as u32, i + 1 == segments),
+                Aad::from(&header[..]),
+                &mut buf,
+            )
+            .map_err(|_| {
+                CubeError::corrupt_data(
+                    "Data is corrupt or was encrypted with another key".to_string(),
+                )
+            })?;
+        dest.write_all(plaintext)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::remotefs::queue::QueueRemoteFs;
+    use std::env;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::current_dir()
+            .unwrap()
+            .join(".cubestore")
+            .join("fs-test")
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn key(b: u8) -> String {
+        base64::encode([b; KEY_LEN])
+    }
+
+    async fn upload(remote_fs: &Arc<EncryptedRemoteFs>, remote_path: &str, data: &[u8]) -> u64 {
+        let temp_upload_path = remote_fs.temp_upload_path(remote_path).await.unwrap();
+        std::fs::write(&temp_upload_path, data).unwrap();
+        remote_fs
+            .upload_file(&temp_upload_path, remote_path)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn sizes() {
+        let h = HEADER_SIZE as u64;
+        let s = SEGMENT_SIZE as u64;
+        assert_eq!(encrypted_size(0), h + 16);
+        assert_eq!(encrypted_size(1), h + 17);
+        assert_eq!(encrypted_size(s), h + s + 16);
+        assert_eq!(encrypted_size(s + 1), h + s + 1 + 32);
+    }
+
+    #[test]
+    fn local_key_provider_parse() {
+        assert!(LocalKeyProvider::parse("").is_err());
+        assert!(LocalKeyProvider::parse(&base64::encode([1; 16])).is_err());
+        assert!(LocalKeyProvider::parse("not base64").is_err());
+        let p = LocalKeyProvider::parse(&format!("# comment\n{}\n\n{}\n", key(1), key(2))).unwrap();
+        assert_eq!(p.keys.len(), 2);
+        assert_ne!(p.keys[0].0, p.keys[1].0);
+        assert!(!format!("{:?}", p).contains(&key(1)));
+    }
+
+    #[tokio::test]
+    async fn upload_and_download() {
+        let upstream = test_dir("encrypted-upstream");
+        let local = test_dir("encrypted-local");
+        let staging = test_dir("encrypted-staging");
+        let provider = Arc::new(LocalKeyProvider::parse(&key(1)).unwrap());
+        let remote_fs = EncryptedRemoteFs::new(
+            LocalDirRemoteFs::new(Some(upstream.clone()), staging.clone()),
+            provider,
+            local.clone(),
+            true,
+        );
+
+        let data = (0..2 * SEGMENT_SIZE + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let size = upload(&remote_fs, "data/1.parquet", &data).await;
+        assert_eq!(size, data.len() as u64);
+
+        // Local files are plaintext, remote ones are encrypted.
+        assert_eq!(std::fs::read(local.join("data/1.parquet")).unwrap(), data);
+        assert!(!staging.join("data/1.parquet").exists());
+        let encrypted = std::fs::read(upstream.join("data/1.parquet")).unwrap();
+        assert_eq!(encrypted.len() as u64, encrypted_size(size));
+        assert_eq!(remote_fs.remote_file_size(size), encrypted_size(size));
+        assert!(!encrypted
+            .windows(100)
+            .any(|w| w == &data[SEGMENT_SIZE..SEGMENT_SIZE + 100]));
+        let listed = remote_fs.list_with_metadata("data/").await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].file_size, encrypted_size(size));
+
+        std::fs::remove_file(local.join("data/1.parquet")).unwrap();
+        let path = remote_fs
+            .download_file("data/1.parquet", None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert!(!staging.join("data/1.parquet").exists());
+
+        // Empty files.
+        upload(&remote_fs, "empty", &[]).await;
+        std::fs::remove_file(local.join("empty")).unwrap();
+        let path = remote_fs.download_file("empty", None).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), Vec::<u8>::new());
+
+        // Files written with the old key are readable after rotation, but not without the key.
+        let rotated =
+            Arc::new(LocalKeyProvider::parse(&format!("{}\n{}", key(2), key(1))).unwrap());
+        let remote_fs = EncryptedRemoteFs::new(
+            LocalDirRemoteFs::new(Some(upstream.clone()), staging.clone()),
+            rotated,
+            local.clone(),
+            true,
+        );
+        std::fs::remove_file(local.join("data/1.parquet")).unwrap();
+        let path = remote_fs
+            .download_file("data/1.parquet", None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+
+        let other_key = Arc::new(LocalKeyProvider::parse(&key(3)).unwrap());
+        let remote_fs = EncryptedRemoteFs::new(
+            LocalDirRemoteFs::new(Some(upstream.clone()), staging.clone()),
+            other_key,
+            local.clone(),
+            true,
+        );
+        std::fs::remove_file(local.join("data/1.parquet")).unwrap();
+        let e = remote_fs
+            .download_file("data/1.parquet", None)
+            .await
+            .unwrap_err();
+        assert!(e.message.contains("is not available"), "{}", e);
+
+        // Tampering with the data fails decryption.
+        let mut tampered = encrypted.clone();
+        tampered[HEADER_SIZE + SEGMENT_SIZE + 20] ^= 1;
+        std::fs::write(upstream.join("data/1.parquet"), &tampered).unwrap();
+        let remote_fs = EncryptedRemoteFs::new(
+            LocalDirRemoteFs::new(Some(upstream.clone()), staging.clone()),
+            Arc::new(LocalKeyProvider::parse(&key(1)).unwrap()),
+            local.clone(),
+            true,
+        );
+        let e = remote_fs
+            .download_file("data/1.parquet", None)
+            .await
+            .unwrap_err();
+        assert!(e.message.contains("Data is corrupt"), "{}", e);
+        assert!(!local.join("data/1.parquet").exists());
+
+        // Truncated files are detected too.
+        std::fs::write(
+            upstream.join("data/1.parquet"),
+            &encrypted[..HEADER_SIZE + SEGMENT_SIZE + TAG_LEN],
+        )
+        .unwrap();
+        remote_fs
+            .download_file("data/1.parquet", None)
+            .await
+            .unwrap_err();
+
+        remote_fs.delete_file("data/1.parquet").await.unwrap();
+        assert!(!upstream.join("data/1.parquet").exists());
+
+        for d in [upstream, local, staging] {
+            let _ = std::fs::remove_dir_all(d);
+        }
+    }
+
+    #[tokio::test]
+    async fn plaintext_files() {
+        let upstream = test_dir("plaintext-upstream");
+        let local = test_dir("plaintext-local");
+        let staging = test_dir("plaintext-staging");
+        let remote_fs = |allow_plaintext| {
+            EncryptedRemoteFs::new(
+                LocalDirRemoteFs::new(Some(upstream.clone()), staging.clone()),
+                Arc::new(LocalKeyProvider::parse(&key(1)).unwrap()),
+                local.clone(),
+                allow_plaintext,
+            )
+        };
+
+        // Files uploaded before encryption was turned on, including ones shorter than the header.
+        let data = (0..SEGMENT_SIZE)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        std::fs::create_dir_all(upstream.join("data")).unwrap();
+        std::fs::write(upstream.join("data/1.parquet"), &data).unwrap();
+        std::fs::write(upstream.join("metastore-current"), b"metastore-1").unwrap();
+
+        let e = remote_fs(false)
+            .download_file("data/1.parquet", None)
+            .await
+            .unwrap_err();
+        assert!(e.message.contains("is not encrypted"), "{}", e);
+        assert!(!local.join("data/1.parquet").exists());
+
+        let fs = remote_fs(true);
+        let path = fs.download_file("data/1.parquet", None).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert!(!staging.join("data/1.parquet").exists());
+        let path = fs.download_file("metastore-current", None).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"metastore-1");
+
+        // New files are encrypted, truncated headers are not mistaken for plaintext.
+        upload(&fs, "data/2.parquet", &data).await;
+        let encrypted = std::fs::read(upstream.join("data/2.parquet")).unwrap();
+        assert_eq!(&encrypted[..MAGIC.len()], MAGIC);
+        std::fs::write(
+            upstream.join("data/2.parquet"),
+            &encrypted[..HEADER_SIZE / 2],
+        )
+        .unwrap();
+        std::fs::remove_file(local.join("data/2.parquet")).unwrap();
+        let e = fs.download_file("data/2.parquet", None).await.unwrap_err();
+        assert!(e.message.contains("truncated"), "{}", e);
+
+        for d in [upstream, local, staging] {
+            let _ = std::fs::remove_dir_all(d);
+        }
+    }
+
+    #[tokio::test]
+    async fn queue_upload_size_check() {
+        let upstream = test_dir("queue-encrypted-upstream");
+        let local = test_dir("queue-encrypted-local");
+        let staging = test_dir("queue-encrypted-staging");
+        let encrypted_fs = EncryptedRemoteFs::new(
+            LocalDirRemoteFs::new(Some(upstream.clone()), staging.clone()),
+            Arc::new(LocalKeyProvider::parse(&key(1)).unwrap()),
+            local.clone(),
+            true,
+        );
+        let queue_fs = QueueRemoteFs::new(
+            Config::test("queue_upload_size_check").config_obj(),
+            encrypted_fs,
+        );
+        let loops = tokio::spawn(QueueRemoteFs::wait_processing_loops(queue_fs.clone()));
+
+        // The queue compares sizes of listed files with encrypted sizes, not the plaintext ones.
+        let data = vec![7; SEGMENT_SIZE + 1];
+        let temp_upload_path = queue_fs.temp_upload_path("1.parquet").await.unwrap();
+        std::fs::write(&temp_upload_path, &data).unwrap();
+        let size = queue_fs
+            .upload_file(&temp_upload_path, "1.parquet")
+            .await
+            .unwrap();
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(
+            std::fs::metadata(upstream.join("1.parquet")).unwrap().len(),
+            queue_fs.remote_file_size(size)
+        );
+
+        // Downloads check sizes of decrypted files.
+        std::fs::remove_file(local.join("1.parquet")).unwrap();
+        let path = queue_fs
+            .download_file("1.parquet", Some(size))
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+
+        queue_fs.stop_processing_loops().unwrap();
+        loops.await.unwrap().unwrap();
+        for d in [upstream, local, staging] {
+            let _ = std::fs::remove_dir_all(d);
+        }
+    }
+}
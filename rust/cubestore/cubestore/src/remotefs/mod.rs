@@ -1,3 +1,6 @@
+pub mod azure;
+pub mod cache;
+pub mod encrypted;
 pub mod gcs;
 pub mod minio;
 pub mod queue;
@@ -5,6 +8,7 @@ pub mod s3;
 
 use crate::config::injection::DIService;
 use crate::di_service;
+use crate::remotefs::cache::FilePin;
 use crate::util::lock::acquire_lock;
 use crate::CubeError;
 use async_trait::async_trait;
@@ -83,6 +87,18 @@ pub trait RemoteFs: DIService + Send + Sync + Debug {
     async fn local_path(&self) -> String;
 
     async fn local_file(&self, remote_path: &str) -> Result<String, CubeError>;
+
+    /// Size reported by [RemoteFs::list_with_metadata] for an uploaded file of `local_file_size`
+    /// bytes. Differs from the local size when files are transformed on upload, e.g. encrypted.
+    fn remote_file_size(&self, local_file_size: u64) -> u64 {
+        local_file_size
+    }
+
+    /// Keeps the local copy of `remote_path` from eviction until the returned pin is dropped.
+    /// Take pins before downloading files and hold them while the files are read.
+    fn pin_file(&self, _remote_path: &str) -> FilePin {
+        FilePin::none()
+    }
 }
 
 pub fn ensure_temp_file_is_dropped(path: String) {
@@ -350,6 +366,7 @@ impl LocalDirRemoteFs {
 
 #[cfg(test)]
 mod tests {
+    use super::azure::AzureBlobRemoteFs;
     use super::s3::S3RemoteFs;
     use super::*;
     use std::io::prelude::*;
@@ -554,4 +571,41 @@ mod tests {
 
         clear_test_dir("aws_s3");
     }
+
+    /// Runs against Azurite with `CUBESTORE_AZURE_ACCOUNT=devstoreaccount1`,
+    /// `CUBESTORE_AZURE_ENDPOINT=http://127.0.0.1:10000/devstoreaccount1` and the well-known
+    /// Azurite key in `CUBESTORE_AZURE_ACCESS_KEY`.
+    #[tokio::test]
+    async fn azure_blob() {
+        let account = match env::var("CUBESTORE_AZURE_ACCOUNT") {
+            Ok(account) => account,
+            Err(_) => return,
+        };
+        let container = "cube-store-ci-test".to_string();
+
+        clear_test_dir("azure_blob");
+        let local_path = get_test_local_dir("azure_blob");
+
+        let remote_fs =
+            AzureBlobRemoteFs::new(local_path.clone(), account.clone(), container.clone(), None)
+                .unwrap();
+        remote_fs.create_container().await.unwrap();
+
+        let name_maker = NameMaker::new(Uuid::new_v4().to_string());
+        test_remote_filesystem(remote_fs, local_path.as_ref(), name_maker.clone(), true).await;
+
+        clear_test_dir("azure_blob");
+
+        let remote_fs = AzureBlobRemoteFs::new(
+            local_path.clone(),
+            account.clone(),
+            container.clone(),
+            Some("remotefs_test_subpathdir".to_string()),
+        )
+        .unwrap();
+
+        test_remote_filesystem(remote_fs, local_path.as_ref(), name_maker.clone(), true).await;
+
+        clear_test_dir("azure_blob");
+    }
 }
@@ -1,5 +1,6 @@
 use crate::config::ConfigObj;
 use crate::di_service;
+use crate::remotefs::cache::{FilePin, LocalFileCache};
 use crate::remotefs::{RemoteFile, RemoteFs};
 use crate::util::lock::acquire_lock;
 use crate::CubeError;
@@ -26,6 +27,7 @@ pub struct QueueRemoteFs {
     // TODO not used
     deleted: RwLock<HashSet<String>>,
     downloading: RwLock<HashSet<String>>,
+    cache: LocalFileCache,
     _result_receiver: broadcast::Receiver<RemoteFsOpResult>,
     result_sender: broadcast::Sender<RemoteFsOpResult>,
     stopped_rx: watch::Receiver<bool>,
@@ -63,6 +65,13 @@ impl QueueRemoteFs {
     pub fn new(config: Arc<dyn ConfigObj>, remote_fs: Arc<dyn RemoteFs>) -> Arc<Self> {
         let (stopped_tx, stopped_rx) = watch::channel(false);
         let (tx, rx) = broadcast::channel(16384);
+        let mut cache_max_size = config.local_cache_max_size();
+        if cache_max_size.is_some() && !config.has_remote_storage() {
+            // Evicted files could not be downloaded again.
+            error!("Local cache max size is ignored without remote storage");
+            cache_max_size = None;
+        }
+        let cache = LocalFileCache::new(cache_max_size, config.local_cache_eviction_policy());
         Arc::new(Self {
             config,
             remote_fs,
@@ -70,6 +79,7 @@ impl QueueRemoteFs {
             download_queue: unlimited::Queue::new(),
             deleted: RwLock::new(HashSet::new()),
             downloading: RwLock::new(HashSet::new()),
+            cache,
             result_sender: tx,
             _result_receiver: rx,
             stopped_tx,
@@ -78,6 +88,10 @@ impl QueueRemoteFs {
     }
 
     pub async fn wait_processing_loops(queue_remote_fs: Arc<Self>) -> Result<(), CubeError> {
+        let local_dir = queue_remote_fs.local_path().await;
+        if let Err(e) = queue_remote_fs.cache.load_local_files(&local_dir).await {
+            error!("Error while loading local cache from {}: {}", local_dir, e);
+        }
         let mut futures = Vec::new();
         for _ in 0..queue_remote_fs.config.upload_concurrency() {
             let to_move = queue_remote_fs.clone();
@@ -166,13 +180,21 @@ impl QueueRemoteFs {
                                 ));
                                 match list_res {
                                     Ok(file) => {
-                                        if file.file_size != size {
+                                        let expected_size = self.remote_fs.remote_file_size(size);
+                                        if file.file_size != expected_size {
                                             res = Err(CubeError::internal(format!(
                                                 "File sizes for {} doesn't match after upload. Expected to be {} but {} uploaded",
                                                 remote_path,
-                                                size,
+                                                expected_size,
                                                 file.file_size
                                             )));
+                                        } else if let Ok(local_path) =
+                                            self.remote_fs.local_file(&remote_path).await
+                                        {
+                                            // Uploaded files stay in the local directory.
+                                            self.cache
+                                                .insert_uploaded(&remote_path, &local_path, size)
+                                                .await;
                                         }
                                     }
                                     Err(e) => {
@@ -190,10 +212,12 @@ impl QueueRemoteFs {
                 }
             }
             RemoteFsOp::Delete(file) => {
-                self.result_sender.send(RemoteFsOpResult::Delete(
-                    file.to_string(),
-                    self.remote_fs.delete_file(file.as_str()).await,
-                ))?;
+                let result = self.remote_fs.delete_file(file.as_str()).await;
+                if result.is_ok() {
+                    self.cache.remove(&file).await;
+                }
+                self.result_sender
+                    .send(RemoteFsOpResult::Delete(file.to_string(), result))?;
             }
             x => panic!("Unexpected operation: {:?}", x),
         }
@@ -207,6 +231,11 @@ impl QueueRemoteFs {
                     .remote_fs
                     .download_file(file.as_str(), expected_file_size)
                     .await;
+                if let Ok(local_path) = &result {
+                    if let Ok(metadata) = tokio::fs::metadata(local_path).await {
+                        self.cache.insert(&file, local_path, metadata.len()).await;
+                    }
+                }
                 let mut downloading =
                     acquire_lock("download loop downloading", self.downloading.write()).await?;
                 self.result_sender
@@ -305,6 +334,9 @@ impl QueueRemoteFs {
                 log::trace!("The files being removed are {:?}", local_files);
             }
 
+            for f in local_files.iter() {
+                self.cache.remove(f).await;
+            }
             let local_dir_copy = local_dir.clone();
             cube_ext::spawn_blocking(move || {
                 for f in local_files {
@@ -350,8 +382,8 @@ impl RemoteFs for QueueRemoteFs {
     ) -> Result<String, CubeError> {
         // We might be lucky and the file has already been downloaded.
         if let Ok(local_path) = self.local_file(remote_path).await {
-            let metadata = tokio::fs::metadata(&local_path).await;
-            if metadata.is_ok() {
+            let metadata = self.cache.get(remote_path, &local_path).await;
+            if metadata.is_some() {
                 if let Err(e) = QueueRemoteFs::check_file_size(
                     remote_path,
                     expected_file_size,
@@ -432,6 +464,14 @@ impl RemoteFs for QueueRemoteFs {
     async fn local_file(&self, remote_path: &str) -> Result<String, CubeError> {
         self.remote_fs.local_file(remote_path).await
     }
+
+    fn remote_file_size(&self, local_file_size: u64) -> u64 {
+        self.remote_fs.remote_file_size(local_file_size)
+    }
+
+    fn pin_file(&self, remote_path: &str) -> FilePin {
+        self.cache.pin(remote_path)
+    }
 }
 
 impl QueueRemoteFs {
@@ -454,3 +494,52 @@ impl QueueRemoteFs {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, FileStoreProvider};
+    use crate::remotefs::LocalDirRemoteFs;
+    use std::env;
+
+    #[tokio::test]
+    async fn no_eviction_without_remote_storage() {
+        let dir = env::current_dir()
+            .unwrap()
+            .join(".cubestore")
+            .join("fs-test")
+            .join("queue-no-remote");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for f in ["1.parquet", "2.parquet"] {
+            std::fs::write(dir.join(f), vec![0; 10]).unwrap();
+        }
+
+        let config = Config::test("no_eviction_without_remote_storage").update_config(|mut c| {
+            c.store_provider = FileStoreProvider::Filesystem { remote_dir: None };
+            c.local_cache_max_size = Some(10);
+            c
+        });
+        let queue_fs = QueueRemoteFs::new(
+            config.config_obj(),
+            LocalDirRemoteFs::new(None, dir.clone()),
+        );
+        queue_fs
+            .cache
+            .load_local_files(dir.to_str().unwrap())
+            .await
+            .unwrap();
+        std::fs::write(dir.join("3.parquet"), vec![0; 10]).unwrap();
+        queue_fs
+            .cache
+            .insert_uploaded("3.parquet", dir.join("3.parquet").to_str().unwrap(), 10)
+            .await;
+
+        assert_eq!(queue_fs.cache.size().await, 30);
+        for f in ["1.parquet", "2.parquet", "3.parquet"] {
+            assert!(dir.join(f).exists(), "{}", f);
+        }
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
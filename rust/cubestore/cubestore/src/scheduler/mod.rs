@@ -2,12 +2,15 @@ use crate::cluster::{pick_worker_by_ids, Cluster};
 use crate::config::ConfigObj;
 use crate::metastore::job::{Job, JobType};
 use crate::metastore::partition::partition_file_name;
-use crate::metastore::table::Table;
+use crate::metastore::table::{Table, TableState};
 use crate::metastore::{
-    deactivate_table_on_corrupt_data, IdRow, MetaStore, MetaStoreEvent, Partition, RowKey, TableId,
+    deactivate_table_on_corrupt_data, Chunk, ColumnType, IdRow, MetaStore, MetaStoreEvent,
+    Partition, RowKey, TableId,
 };
 use crate::remotefs::RemoteFs;
 use crate::store::{ChunkStore, WALStore};
+use crate::table::stats::ColumnStats;
+use crate::table::{cmp_same_types, Row, TableValue, TimestampValue};
 use crate::util::time_span::warn_long_fut;
 use crate::util::WorkerLoop;
 use crate::CubeError;
@@ -34,6 +37,7 @@ pub struct SchedulerImpl {
     gc_loop: Arc<DataGCLoop>,
     config: Arc<dyn ConfigObj>,
     reconcile_loop: WorkerLoop,
+    ttl_loop: WorkerLoop,
 }
 
 crate::di_service!(SchedulerImpl, []);
@@ -62,6 +66,7 @@ impl SchedulerImpl {
             gc_loop,
             config,
             reconcile_loop: WorkerLoop::new("Reconcile"),
+            ttl_loop: WorkerLoop::new("TTL"),
         }
     }
 
@@ -70,6 +75,7 @@ impl SchedulerImpl {
     ) -> Vec<JoinHandle<Result<(), CubeError>>> {
         let scheduler2 = scheduler.clone();
         let scheduler3 = scheduler.clone();
+        let scheduler4 = scheduler.clone();
         vec![
             cube_ext::spawn(async move {
                 let gc_loop = scheduler.gc_loop.clone();
@@ -91,6 +97,23 @@ impl SchedulerImpl {
                     .await;
                 Ok(())
             }),
+            cube_ext::spawn(async move {
+                let interval = scheduler4.config.ttl_check_interval();
+                scheduler4
+                    .ttl_loop
+                    .process(
+                        scheduler4.clone(),
+                        async move |_| Ok(Delay::new(Duration::from_secs(interval)).await),
+                        async move |s, _| {
+                            if let Err(e) = s.expire_ttl_rows().await {
+                                error!("Error expiring rows by TTL: {}", e);
+                            }
+                            Ok(())
+                        },
+                    )
+                    .await;
+                Ok(())
+            }),
         ]
     }
 
@@ -218,6 +241,149 @@ impl SchedulerImpl {
         Ok(())
     }
 
+    /// Removes rows that are past the TTL of their tables. Expiration is decided by the min and max
+    /// of the TTL column in column stats of main table files and chunks. Main table files without
+    /// stats fall back to the key range if the index is sorted by the TTL column. Partitions with
+    /// only expired rows are dropped without reading their data, the ones that can contain expired
+    /// rows are rewritten without them unless a deletion is already pending. Partitions where no
+    /// rows turn out to expire keep their files and get column stats instead.
+    pub async fn expire_ttl_rows(&self) -> Result<(), CubeError> {
+        let now = Utc::now();
+        // Predicates include the cutoff, so `add_job` doesn't dedup them with deletions scheduled
+        // by the previous runs.
+        let pending_deletions = self
+            .meta_store
+            .all_jobs()
+            .await?
+            .into_iter()
+            .filter_map(
+                |j| match (j.get_row().row_reference(), j.get_row().job_type()) {
+                    (RowKey::Table(TableId::Partitions, id), JobType::PartitionRowsDeletion(_)) => {
+                        Some(*id)
+                    }
+                    _ => None,
+                },
+            )
+            .collect::<HashSet<_>>();
+        let tables = self.meta_store.get_tables_with_path(false).await?;
+        for table in tables.iter() {
+            let table_id = table.table.get_id();
+            let table = table.table.get_row();
+            let (ttl, ttl_column) = match (table.ttl(), table.ttl_column()) {
+                (Some(ttl), Some(column)) => (ttl, column),
+                _ => continue,
+            };
+            let cutoff = ttl.expiration_cutoff(now);
+            let predicate = format!(
+                "`{}` < to_timestamp('{}')",
+                ttl_column.get_name(),
+                cutoff.format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            );
+            let cutoff = TableValue::Timestamp(TimestampValue::new(cutoff.timestamp_nanos()));
+
+            for index in self.meta_store.get_table_indexes(table_id).await? {
+                let index_columns = index.get_row().columns();
+                // Rows can't be deleted from partitioned indexes and aggregate indexes without
+                // the TTL column, so these keep their data.
+                if index.get_row().multi_index_id().is_some()
+                    || !index_columns
+                        .iter()
+                        .any(|c| c.get_name() == ttl_column.get_name())
+                {
+                    continue;
+                }
+                let sorted_by_ttl = index_columns[0].get_name() == ttl_column.get_name();
+                for partition in self
+                    .meta_store
+                    .get_active_partitions_by_index_id(index.get_id())
+                    .await?
+                {
+                    let chunks = self
+                        .meta_store
+                        .get_chunks_by_partition(partition.get_id(), false)
+                        .await?;
+                    let main_table_expiration = if partition.get_row().main_table_row_count() == 0 {
+                        None
+                    } else if let Some(e) = stats_expiration(
+                        partition.get_row().column_stats(),
+                        partition.get_row().main_table_row_count(),
+                        ttl_column.get_name(),
+                        &cutoff,
+                    ) {
+                        Some(e)
+                    } else if sorted_by_ttl {
+                        Some(partition_expiration(partition.get_row(), &cutoff))
+                    } else {
+                        Some(PartitionExpiration::Partial)
+                    };
+                    let expiration = main_table_expiration
+                        .into_iter()
+                        .chain(chunks.iter().map(|c| {
+                            stats_expiration(
+                                c.get_row().column_stats(),
+                                c.get_row().get_row_count(),
+                                ttl_column.get_name(),
+                                &cutoff,
+                            )
+                            .unwrap_or(PartitionExpiration::Partial)
+                        }))
+                        .reduce(PartitionExpiration::combine);
+                    let expiration = match expiration {
+                        Some(expiration) => expiration,
+                        None => continue,
+                    };
+                    match expiration {
+                        PartitionExpiration::None => {}
+                        PartitionExpiration::Full => {
+                            let partition_id = partition.get_id();
+                            if let Err(e) = self.drop_expired_partition(partition, chunks).await {
+                                error!("Error dropping expired partition {}: {}", partition_id, e);
+                            }
+                        }
+                        PartitionExpiration::Partial => {
+                            if !pending_deletions.contains(&partition.get_id()) {
+                                self.schedule_rows_deletion(&partition, predicate.clone())
+                                    .await?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the partition with an empty one that keeps its key range.
+    async fn drop_expired_partition(
+        &self,
+        partition: IdRow<Partition>,
+        chunks: Vec<IdRow<Chunk>>,
+    ) -> Result<(), CubeError> {
+        let empty_partition = self
+            .meta_store
+            .create_partition(Partition::new_child(&partition, None))
+            .await?;
+        let empty_partition_id = empty_partition.get_id();
+        let min_max = (
+            partition.get_row().get_min_val().clone(),
+            partition.get_row().get_max_val().clone(),
+        );
+        let swapped = self
+            .meta_store
+            .swap_active_partitions(
+                vec![(partition, chunks)],
+                vec![(empty_partition, 0)],
+                vec![(0, min_max)],
+            )
+            .await;
+        if let Err(e) = swapped {
+            // The replacement was never activated, so nothing else refers to it.
+            self.meta_store.delete_partition(empty_partition_id).await?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
     async fn schedule_all_pending_repartitions(&self) -> Result<(), CubeError> {
         let all_inactive_partitions_to_repartition = self
             .meta_store
@@ -309,7 +475,10 @@ impl SchedulerImpl {
         // Using get_tables_with_path due to it's cached
         let tables = self.meta_store.get_tables_with_path(true).await?;
         for table in tables.iter() {
-            if table.table.get_row().is_ready() {
+            // Paused and sealed tables don't ingest streams until resumed.
+            if table.table.get_row().is_ready()
+                && table.table.get_row().state() == TableState::Active
+            {
                 if let Some(locations) = table.table.get_row().locations() {
                     for location in locations.iter() {
                         if Table::is_stream_location(location) {
@@ -356,6 +525,7 @@ impl SchedulerImpl {
     pub fn stop_processing_loops(&self) -> Result<(), CubeError> {
         self.cancel_token.cancel();
         self.reconcile_loop.stop();
+        self.ttl_loop.stop();
         Ok(())
     }
 
@@ -427,6 +597,25 @@ impl SchedulerImpl {
                 self.schedule_table_import(row_id, &locations).await?;
             }
         }
+        if let MetaStoreEvent::UpdateTable(old, new) = &event {
+            if old.get_row().state() != new.get_row().state() {
+                match new.get_row().state() {
+                    TableState::Active => {
+                        if let Some(locations) = new.get_row().locations() {
+                            let stream_locations = locations
+                                .into_iter()
+                                .filter(|l| Table::is_stream_location(l))
+                                .collect::<Vec<_>>();
+                            self.schedule_table_import(new.get_id(), &stream_locations)
+                                .await?;
+                        }
+                    }
+                    // Sealed table won't receive new chunks so in-memory ones are persisted right away.
+                    TableState::Sealed => self.schedule_table_flush(new.get_id()).await?,
+                    TableState::Paused => {}
+                }
+            }
+        }
         if let MetaStoreEvent::Delete(TableId::WALs, row_id) = event {
             let file = self
                 .remote_fs
@@ -581,6 +770,27 @@ impl SchedulerImpl {
         Ok(())
     }
 
+    async fn schedule_table_flush(&self, table_id: u64) -> Result<(), CubeError> {
+        for index in self.meta_store.get_table_indexes(table_id).await? {
+            let partitions = self
+                .meta_store
+                .get_active_partitions_by_index_id(index.get_id())
+                .await?;
+            for partition in partitions {
+                let has_in_memory_chunks = self
+                    .meta_store
+                    .get_chunks_by_partition(partition.get_id(), false)
+                    .await?
+                    .iter()
+                    .any(|c| c.get_row().in_memory() && c.get_row().active());
+                if has_in_memory_chunks {
+                    self.schedule_partition_to_compact(&partition).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn schedule_compaction_in_memory_chunks_if_needed(
         &self,
         partition: &IdRow<Partition>,
@@ -737,6 +947,26 @@ impl SchedulerImpl {
         Ok(())
     }
 
+    async fn schedule_rows_deletion(
+        &self,
+        p: &IdRow<Partition>,
+        predicate: String,
+    ) -> Result<(), CubeError> {
+        let node = self.cluster.node_name_by_partition(p);
+        let job = self
+            .meta_store
+            .add_job(Job::new(
+                RowKey::Table(TableId::Partitions, p.get_id()),
+                JobType::PartitionRowsDeletion(predicate),
+                node.clone(),
+            ))
+            .await?;
+        if job.is_some() {
+            self.cluster.notify_job_runner(node).await?;
+        }
+        Ok(())
+    }
+
     pub async fn schedule_partition_to_compact(
         &self,
         p: &IdRow<Partition>,
@@ -774,6 +1004,83 @@ impl SchedulerImpl {
     }
 }
 
+#[derive(Debug, Eq, PartialEq)]
+enum PartitionExpiration {
+    None,
+    Partial,
+    Full,
+}
+
+impl PartitionExpiration {
+    /// Expiration of data made of two parts with expirations `self` and `other`.
+    fn combine(self, other: PartitionExpiration) -> PartitionExpiration {
+        match (self, other) {
+            (PartitionExpiration::None, PartitionExpiration::None) => PartitionExpiration::None,
+            (PartitionExpiration::Full, PartitionExpiration::Full) => PartitionExpiration::Full,
+            _ => PartitionExpiration::Partial,
+        }
+    }
+}
+
+/// Decides by the min and max of the TTL column in the stats of a chunk or a main table file
+/// whether its rows are older than `cutoff`. Returns `None` if there are no stats for the column.
+fn stats_expiration(
+    stats: &Option<Vec<ColumnStats>>,
+    row_count: u64,
+    ttl_column: &str,
+    cutoff: &TableValue,
+) -> Option<PartitionExpiration> {
+    let stats = stats.as_ref().and_then(|stats| {
+        stats
+            .iter()
+            .find(|s| s.name() == ttl_column && s.column_type() == &ColumnType::Timestamp)
+    })?;
+    Some(match (stats.min(), stats.max()) {
+        (Some(min), Some(max)) => {
+            if cmp_same_types(min, cutoff) != Ordering::Less {
+                PartitionExpiration::None
+            } else if stats.null_count() == 0 && cmp_same_types(max, cutoff) == Ordering::Less {
+                PartitionExpiration::Full
+            } else {
+                PartitionExpiration::Partial
+            }
+        }
+        // Only NULLs, these never expire.
+        _ if stats.null_count() == row_count => PartitionExpiration::None,
+        // Stats written before min and max were collected for the column type.
+        _ => return None,
+    })
+}
+
+/// Decides by the key range of a partition sorted by the TTL column whether its rows are older
+/// than `cutoff`. Partition ranges include min and exclude max, NULLs are sorted first and never
+/// expire.
+fn partition_expiration(p: &Partition, cutoff: &TableValue) -> PartitionExpiration {
+    let first_value = |row: &Option<Row>| {
+        row.as_ref()
+            .and_then(|r| r.values().first().cloned())
+            .filter(|v| v != &TableValue::Null)
+    };
+    let min = first_value(p.get_min_val());
+    let max = first_value(p.get_max_val());
+    if min
+        .as_ref()
+        .map(|min| cmp_same_types(min, cutoff) != Ordering::Less)
+        .unwrap_or(false)
+    {
+        PartitionExpiration::None
+    } else if min.is_some()
+        && max
+            .as_ref()
+            .map(|max| cmp_same_types(max, cutoff) == Ordering::Less)
+            .unwrap_or(false)
+    {
+        PartitionExpiration::Full
+    } else {
+        PartitionExpiration::Partial
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 struct GCTimedTask {
     pub deadline: Instant,
@@ -64,47 +64,59 @@ impl SqlResultCache {
         F: Future<Output = Result<DataFrame, CubeError>> + Send + 'static,
     {
         let key = SqlResultCacheKey::from_plan(query, &plan);
-        let (sender, mut receiver) = {
-            let key = key.clone();
-            let mut cache = self.cache.write().await;
-            if !cache.contains(&key) {
-                let (tx, rx) = watch::channel(None);
-                cache.put(key, rx);
-                (Some(tx), None)
-            } else {
-                (None, cache.get(&key).cloned())
-            }
-        };
+        loop {
+            let (sender, mut receiver) = {
+                let key = key.clone();
+                let mut cache = self.cache.write().await;
+                if !cache.contains(&key) {
+                    let (tx, rx) = watch::channel(None);
+                    cache.put(key, rx);
+                    (Some(tx), None)
+                } else {
+                    (None, cache.get(&key).cloned())
+                }
+            };
 
-        if let Some(sender) = sender {
-            trace!("Missing cache for '{}'", query);
-            let result = exec(plan).await.map(|d| Arc::new(d));
-            if let Err(e) = sender.send(Some(result.clone())) {
-                trace!(
-                    "Failed to set cached query result, possibly flushed from LRU cache: {}",
-                    e
-                );
-            }
-            if result.is_err() {
-                trace!("Removing error result from cache");
-                self.cache.write().await.pop(&key);
+            if let Some(sender) = sender {
+                trace!("Missing cache for '{}'", query);
+                let result = exec(plan).await.map(|d| Arc::new(d));
+                if let Err(e) = sender.send(Some(result.clone())) {
+                    trace!(
+                        "Failed to set cached query result, possibly flushed from LRU cache: {}",
+                        e
+                    );
+                }
+                if result.is_err() {
+                    trace!("Removing error result from cache");
+                    self.cache.write().await.pop(&key);
+                }
+                return result;
             }
-            return result;
-        }
 
-        if let Some(receiver) = &mut receiver {
-            loop {
-                receiver.changed().await?;
-                let x = receiver.borrow();
-                let value = x.as_ref();
-                if let Some(value) = value {
-                    trace!("Using cache for '{}'", query);
-                    return value.clone();
+            if let Some(receiver) = &mut receiver {
+                loop {
+                    if receiver.changed().await.is_err() {
+                        break;
+                    }
+                    let x = receiver.borrow();
+                    let value = x.as_ref();
+                    if let Some(value) = value {
+                        trace!("Using cache for '{}'", query);
+                        return value.clone();
+                    }
+                }
+                // Query that was computing the result has been cancelled without sending it.
+                // Remove the abandoned entry and compute the result again.
+                let mut cache = self.cache.write().await;
+                if cache.peek(&key).map(|r| r.borrow().is_none()) == Some(true) {
+                    trace!("Removing abandoned result from cache");
+                    cache.pop(&key);
                 }
+                continue;
             }
-        }
 
-        panic!("Unexpected state: wait receiver expected but cache was empty")
+            panic!("Unexpected state: wait receiver expected but cache was empty")
+        }
     }
 }
 
@@ -1,22 +1,28 @@
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use arrow::array::*;
+use arrow::compute::cast;
 use arrow::compute::kernels::cast_utils::string_to_timestamp_nanos;
+use arrow::datatypes::{DataType as ArrowDataType, Field, Schema as ArrowSchema, SchemaRef};
+use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
 use chrono::format::Fixed::Nanosecond3;
 use chrono::format::Item::{Fixed, Literal, Numeric, Space};
 use chrono::format::Numeric::{Day, Hour, Minute, Month, Second, Year};
 use chrono::format::Pad::Zero;
 use chrono::format::Parsed;
-use chrono::{ParseResult, Utc};
+use chrono::{ParseResult, TimeZone, Utc};
 use datafusion::cube_ext;
-use datafusion::physical_plan::ExecutionPlan;
+use datafusion::physical_plan::memory::MemoryStream;
+use datafusion::physical_plan::{ExecutionPlan, RecordBatchStream, SendableRecordBatchStream};
 use datafusion::sql::parser::Statement as DFStatement;
 use futures::future::join_all;
+use futures::StreamExt;
 use hex::FromHex;
 use itertools::Itertools;
 use log::trace;
@@ -29,34 +35,45 @@ use tempfile::TempDir;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
 use tracing::instrument;
 use tracing_futures::WithSubscriber;
 
 use cubehll::HllSketch;
 use parser::Statement as CubeStoreStatement;
 
-use crate::cluster::{Cluster, JobEvent, JobResultListener};
+use crate::cluster::{query_cancelled_error, Cluster, JobEvent, JobResultListener};
 use crate::config::injection::DIService;
 use crate::config::ConfigObj;
 use crate::import::limits::ConcurrencyLimits;
 use crate::import::{parse_space_separated_binstring, ImportService, Ingestion};
 use crate::metastore::job::JobType;
 use crate::metastore::multi_index::MultiIndex;
-use crate::metastore::source::SourceCredentials;
+use crate::metastore::source::{KafkaMessageFormat, SourceCredentials};
+use crate::metastore::table::{ParquetCompression, TableState};
 use crate::metastore::{
     is_valid_plain_binary_hll, table::Table, HllFlavour, IdRow, ImportFormat, Index, IndexDef,
     IndexType, MetaStoreTable, RowKey, Schema, TableId,
 };
 use crate::queryplanner::panic::PanicWorkerNode;
+use crate::queryplanner::partition_filter::PartitionFilter;
 use crate::queryplanner::pretty_printers::{pp_phys_plan, pp_plan};
-use crate::queryplanner::query_executor::{batch_to_dataframe, ClusterSendExec, QueryExecutor};
+use crate::queryplanner::query_executor::{
+    arrow_to_column_type, batch_to_dataframe, ClusterSendExec, QueryExecutor,
+};
 use crate::queryplanner::serialized_plan::{RowFilter, SerializedPlan};
-use crate::queryplanner::{PlanningMeta, QueryPlan, QueryPlanner};
+use crate::queryplanner::tdigest::TDigest;
+use crate::queryplanner::{plan_filter_expr, PlanningMeta, QueryPlan, QueryPlanner};
 use crate::remotefs::RemoteFs;
 use crate::sql::cache::SqlResultCache;
-use crate::sql::parser::{CubeStoreParser, PartitionedIndexRef, SystemCommand};
+use crate::sql::parser::{AlterTableCommand, CubeStoreParser, PartitionedIndexRef, SystemCommand};
+use crate::sql::query_log::{LoggedStream, QueryLog};
+use crate::sql::running_queries::RunningQueries;
+use crate::store::compaction::CompactionService;
 use crate::store::ChunkDataStore;
-use crate::table::{data, Row, TableValue, TimestampValue};
+use crate::streaming::avro::AvroSchema;
+use crate::table::parquet::arrow_schema;
+use crate::table::{data, DateValue, Row, TableValue, TimestampValue};
 use crate::telemetry::incoming_traffic_agent_event;
 use crate::util::decimal::Decimal;
 use crate::util::strings::path_to_string;
@@ -66,13 +83,17 @@ use crate::{
     metastore::{Column, ColumnType, MetaStore},
     store::DataFrame,
 };
-use data::create_array_builder;
+use data::{append_value, create_array_builder};
 use datafusion::cube_ext::catch_unwind::async_try_with_catch_unwind;
 use datafusion::physical_plan::parquet::NoopParquetMetadataCache;
+use std::fmt::Display;
 use std::mem::take;
+use std::str::FromStr;
 
 pub mod cache;
 pub(crate) mod parser;
+pub mod query_log;
+pub mod running_queries;
 
 #[async_trait]
 pub trait SqlService: DIService + Send + Sync {
@@ -84,6 +105,17 @@ pub trait SqlService: DIService + Send + Sync {
         query: &str,
     ) -> Result<Arc<DataFrame>, CubeError>;
 
+    /// Plans the SELECT in `query` without running it.
+    async fn plan_select(&self, query: &str) -> Result<PlannedSelect, CubeError>;
+
+    /// Runs a SELECT planned by [plan_select] and streams its results as the router produces
+    /// them. The query is recorded in the query log, but its results bypass the result cache.
+    async fn exec_planned_select(
+        &self,
+        context: SqlQueryContext,
+        planned: PlannedSelect,
+    ) -> Result<SendableRecordBatchStream, CubeError>;
+
     /// Exposed only for tests. Worker plan created as if all partitions are on the same worker.
     async fn plan_query(&self, query: &str) -> Result<QueryPlans, CubeError>;
 
@@ -102,10 +134,30 @@ pub struct QueryPlans {
     pub worker: Arc<dyn ExecutionPlan>,
 }
 
+pub struct PlannedSelect {
+    query: String,
+    plan: QueryPlan,
+    columns: Vec<Column>,
+}
+
+impl PlannedSelect {
+    /// Columns of the results. Record batches streamed by [SqlService::exec_planned_select] have
+    /// the same fields, but their types may differ.
+    pub fn columns(&self) -> &Vec<Column> {
+        &self.columns
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct SqlQueryContext {
     pub user: Option<String>,
     pub trace_obj: Option<String>,
+    /// Identifies the MySQL connection for `KILL QUERY`.
+    #[serde(default)]
+    pub connection_id: Option<u32>,
+    /// Cancelled when the client connection is closed.
+    #[serde(skip)]
+    pub cancel_token: CancellationToken,
 }
 
 impl SqlQueryContext {
@@ -125,11 +177,14 @@ pub struct SqlServiceImpl {
     query_executor: Arc<dyn QueryExecutor>,
     cluster: Arc<dyn Cluster>,
     import_service: Arc<dyn ImportService>,
+    compaction_service: Arc<dyn CompactionService>,
     config_obj: Arc<dyn ConfigObj>,
     rows_per_chunk: usize,
     query_timeout: Duration,
     create_table_timeout: Duration,
     cache: SqlResultCache,
+    running_queries: RunningQueries,
+    query_log: Arc<QueryLog>,
 }
 
 crate::di_service!(SqlServiceImpl, [SqlService]);
@@ -143,12 +198,14 @@ impl SqlServiceImpl {
         query_executor: Arc<dyn QueryExecutor>,
         cluster: Arc<dyn Cluster>,
         import_service: Arc<dyn ImportService>,
+        compaction_service: Arc<dyn CompactionService>,
         config_obj: Arc<dyn ConfigObj>,
         remote_fs: Arc<dyn RemoteFs>,
         rows_per_chunk: usize,
         query_timeout: Duration,
         create_table_timeout: Duration,
         max_cached_queries: usize,
+        query_log: Arc<QueryLog>,
     ) -> Arc<SqlServiceImpl> {
         Arc::new(SqlServiceImpl {
             db,
@@ -158,12 +215,15 @@ impl SqlServiceImpl {
             query_executor,
             cluster,
             import_service,
+            compaction_service,
             config_obj,
             rows_per_chunk,
             query_timeout,
             create_table_timeout,
             remote_fs,
             cache: SqlResultCache::new(max_cached_queries),
+            running_queries: RunningQueries::new(),
+            query_log,
         })
     }
 
@@ -179,7 +239,7 @@ impl SqlServiceImpl {
         &self,
         schema_name: String,
         table_name: String,
-        columns: &Vec<ColumnDef>,
+        columns_to_set: Vec<Column>,
         external: bool,
         locations: Option<Vec<String>>,
         import_format: Option<ImportFormat>,
@@ -187,9 +247,12 @@ impl SqlServiceImpl {
         unique_key: Option<Vec<Ident>>,
         aggregates: Option<Vec<(Ident, Ident)>>,
         partitioned_index: Option<PartitionedIndexRef>,
+        ttl: Option<(String, u64)>,
+        compression: Option<ParquetCompression>,
+        dictionary: Option<bool>,
+        bloom_filter_columns: Option<Vec<String>>,
         trace_obj: &Option<String>,
     ) -> Result<IdRow<Table>, CubeError> {
-        let columns_to_set = convert_columns_type(columns)?;
         let mut indexes_to_create = Vec::new();
         if let Some(mut p) = partitioned_index {
             let part_index_name = match p.name.0.as_mut_slice() {
@@ -272,6 +335,10 @@ impl SqlServiceImpl {
                             .collect()
                     }),
                     None,
+                    ttl,
+                    compression,
+                    dictionary,
+                    bloom_filter_columns,
                 )
                 .await;
         }
@@ -285,8 +352,11 @@ impl SqlServiceImpl {
                     .map(|location| {
                         let location = location.to_string();
                         let import_service = self.import_service.clone();
+                        let import_format = import_format.unwrap_or(ImportFormat::CSV);
                         return async move {
-                            import_service.estimate_location_row_count(&location).await
+                            import_service
+                                .estimate_location_row_count(&location, import_format)
+                                .await
                         };
                     })
                     .collect::<Vec<_>>(),
@@ -327,6 +397,10 @@ impl SqlServiceImpl {
                         .collect()
                 }),
                 partition_split_threshold,
+                ttl,
+                compression,
+                dictionary,
+                bloom_filter_columns,
             )
             .await?;
 
@@ -443,6 +517,24 @@ impl SqlServiceImpl {
             .await?)
     }
 
+    async fn table_for_insert(
+        &self,
+        schema_name: &str,
+        table_name: &str,
+    ) -> Result<IdRow<Table>, CubeError> {
+        let table = self
+            .db
+            .get_table(schema_name.to_string(), table_name.to_string())
+            .await?;
+        if table.get_row().is_sealed() {
+            return Err(CubeError::user(format!(
+                "Can't insert into sealed table {}.{}",
+                schema_name, table_name
+            )));
+        }
+        Ok(table)
+    }
+
     async fn insert_data<'a>(
         &'a self,
         schema_name: String,
@@ -450,27 +542,10 @@ impl SqlServiceImpl {
         columns: &'a Vec<Ident>,
         data: &'a Vec<Vec<Expr>>,
     ) -> Result<u64, CubeError> {
-        let table = self
-            .db
-            .get_table(schema_name.clone(), table_name.clone())
-            .await?;
+        let table = self.table_for_insert(&schema_name, &table_name).await?;
         let table_columns = table.get_row().clone();
         let table_columns = table_columns.get_columns();
-        let mut real_col: Vec<&Column> = Vec::new();
-        for column in columns {
-            let c = if let Some(item) = table_columns
-                .iter()
-                .find(|voc| *voc.get_name() == column.value)
-            {
-                item
-            } else {
-                return Err(CubeError::user(format!(
-                    "Column {} is not present in table {}.{}.",
-                    column.value, schema_name, table_name
-                )));
-            };
-            real_col.push(c);
-        }
+        let real_col = insert_columns(table_columns, columns, &schema_name, &table_name)?;
 
         let mut ingestion = Ingestion::new(
             self.db.clone(),
@@ -486,6 +561,568 @@ impl SqlServiceImpl {
         Ok(data.len() as u64)
     }
 
+    async fn exec_select_plan(
+        &self,
+        context: SqlQueryContext,
+        query: &str,
+        logical_plan: QueryPlan,
+    ) -> Result<Arc<DataFrame>, CubeError> {
+        // TODO distribute and combine
+        let res = match logical_plan {
+            QueryPlan::Meta(logical_plan) => {
+                app_metrics::META_QUERIES.increment();
+                Arc::new(self.query_planner.execute_meta_plan(logical_plan).await?)
+            }
+            QueryPlan::Select(serialized, workers) => {
+                app_metrics::DATA_QUERIES.increment();
+                let cluster = self.cluster.clone();
+                let executor = self.query_executor.clone();
+                let logged_query = self.query_log.start(query, &context, &serialized, &workers);
+                let running_query = self.running_queries.start(&context);
+                let cancel_token = running_query.cancel_token().clone();
+                let serialized = serialized.with_cancel_token(cancel_token.clone());
+                let cache_hit = Arc::new(AtomicBool::new(true));
+                let cache_hit_to_move = cache_hit.clone();
+                let select = timeout(
+                    self.query_timeout,
+                    self.cache
+                        .get(query, serialized, async move |plan| {
+                            cache_hit_to_move.store(false, Ordering::Relaxed);
+                            let records = execute_select(cluster, executor, plan, workers).await?.1;
+                            Ok(
+                                cube_ext::spawn_blocking(
+                                    move || -> Result<DataFrame, CubeError> {
+                                        let df = batch_to_dataframe(&records)?;
+                                        Ok(df)
+                                    },
+                                )
+                                .await??,
+                            )
+                        })
+                        .with_current_subscriber(),
+                );
+                let res = tokio::select! {
+                    r = select => r.map_err(CubeError::from).and_then(|r| r),
+                    _ = cancel_token.cancelled() => Err(query_cancelled_error()),
+                };
+                logged_query.finish(&res, cache_hit.load(Ordering::Relaxed));
+                res?
+            }
+        };
+        Ok(res)
+    }
+
+    /// Runs `q` bypassing the result cache, so the data written back is always up to date.
+    /// Results are streamed, so selects of any size can be written back. The plan is run on this
+    /// node as it consumes the results.
+    async fn select_stream(&self, q: Box<Query>) -> Result<SendableRecordBatchStream, CubeError> {
+        let logical_plan = self
+            .query_planner
+            .logical_plan(DFStatement::Statement(Statement::Query(q)))
+            .await?;
+        match logical_plan {
+            QueryPlan::Meta(logical_plan) => {
+                let df = self.query_planner.execute_meta_plan(logical_plan).await?;
+                let (schema, records) = dataframe_to_records(&df)?;
+                Ok(Box::pin(MemoryStream::try_new(records, schema, None)?))
+            }
+            QueryPlan::Select(serialized, _) => {
+                self.query_executor
+                    .execute_router_plan_stream(serialized, self.cluster.clone())
+                    .await
+            }
+        }
+    }
+
+    /// Writes `records` to the `columns` of `table` matching them by position.
+    async fn insert_records(
+        &self,
+        table: IdRow<Table>,
+        columns: &Vec<&Column>,
+        records: SendableRecordBatchStream,
+    ) -> Result<u64, CubeError> {
+        timeout(
+            self.query_timeout,
+            self.insert_records_impl(table, columns, records),
+        )
+        .await?
+    }
+
+    async fn insert_records_impl(
+        &self,
+        table: IdRow<Table>,
+        columns: &Vec<&Column>,
+        mut records: SendableRecordBatchStream,
+    ) -> Result<u64, CubeError> {
+        let mut order = (0..columns.len()).collect_vec();
+        order.sort_unstable_by_key(|i| columns[*i].get_index());
+
+        let mut ingestion = Ingestion::new(
+            self.db.clone(),
+            self.chunk_store.clone(),
+            self.limits.clone(),
+            table,
+        );
+        let mut rows = 0;
+        while let Some(batch) = records.next().await {
+            let batch = batch?;
+            if batch.num_rows() == 0 {
+                continue;
+            }
+            let mut arrays = Vec::with_capacity(columns.len());
+            for i in order.iter() {
+                let field: Field = columns[*i].into();
+                let array = batch.column(*i);
+                let array = cast(array, field.data_type()).map_err(|e| {
+                    CubeError::user(format!(
+                        "Can't insert {:?} into column {}: {}",
+                        array.data_type(),
+                        field.name(),
+                        e
+                    ))
+                })?;
+                if let ColumnType::HyperLogLog(f) = columns[*i].get_column_type() {
+                    validate_hll_array(array.as_ref(), *f)?;
+                }
+                arrays.push(array);
+            }
+            rows += batch.num_rows() as u64;
+            ingestion.queue_data_frame(arrays).await?;
+        }
+        ingestion.wait_completion().await?;
+        Ok(rows)
+    }
+
+    async fn insert_select(
+        &self,
+        schema_name: String,
+        table_name: String,
+        columns: &Vec<Ident>,
+        q: Box<Query>,
+    ) -> Result<u64, CubeError> {
+        let table = self.table_for_insert(&schema_name, &table_name).await?;
+        let table_columns = table.get_row().clone();
+        let table_columns = table_columns.get_columns();
+        let real_col = insert_columns(table_columns, columns, &schema_name, &table_name)?;
+        if real_col.iter().map(|c| c.get_index()).unique().count() != table_columns.len() {
+            return Err(CubeError::user(format!(
+                "All columns of {}.{} should be present in INSERT ... SELECT",
+                schema_name, table_name
+            )));
+        }
+
+        let records = self.select_stream(q).await?;
+        let schema = records.schema();
+        if schema.fields().len() != real_col.len() {
+            return Err(CubeError::user(format!(
+                "INSERT into {}.{} expects {} columns, but SELECT returns {}",
+                schema_name,
+                table_name,
+                real_col.len(),
+                schema.fields().len()
+            )));
+        }
+        self.insert_records(table, &real_col, records).await
+    }
+
+    /// Creates a regular table with the data of `q`.
+    /// Column types are derived from the query unless they're specified explicitly.
+    async fn create_table_as_select(
+        &self,
+        schema_name: String,
+        table_name: String,
+        columns: Vec<Column>,
+        q: Box<Query>,
+        indexes: Vec<Statement>,
+        unique_key: Option<Vec<Ident>>,
+        aggregates: Option<Vec<(Ident, Ident)>>,
+        partitioned_index: Option<PartitionedIndexRef>,
+        ttl: Option<(String, u64)>,
+        compression: Option<ParquetCompression>,
+        dictionary: Option<bool>,
+        bloom_filter_columns: Option<Vec<String>>,
+        trace_obj: &Option<String>,
+    ) -> Result<IdRow<Table>, CubeError> {
+        let records = self.select_stream(q).await?;
+        let schema = records.schema();
+        let columns = if columns.is_empty() {
+            columns_from_schema(&schema)?
+        } else if columns.len() == schema.fields().len() {
+            columns
+        } else {
+            return Err(CubeError::user(format!(
+                "Table {}.{} has {} columns, but SELECT returns {}",
+                schema_name,
+                table_name,
+                columns.len(),
+                schema.fields().len()
+            )));
+        };
+
+        let table = self
+            .create_table(
+                schema_name,
+                table_name,
+                columns,
+                false,
+                None,
+                None,
+                indexes,
+                unique_key,
+                aggregates,
+                partitioned_index,
+                ttl,
+                compression,
+                dictionary,
+                bloom_filter_columns,
+                trace_obj,
+            )
+            .await?;
+        let table_columns = table.get_row().get_columns().iter().collect_vec();
+        if let Err(e) = self
+            .insert_records(table.clone(), &table_columns, records)
+            .await
+        {
+            if let Err(inner) = self.db.drop_table(table.get_id()).await {
+                log::error!(
+                    "Drop table ({}) after error failed: {}",
+                    table.get_id(),
+                    inner
+                );
+            }
+            return Err(e);
+        }
+        Ok(table)
+    }
+
+    /// Only nullable columns can be added as rows already stored don't have values for them.
+    async fn add_column(
+        &self,
+        table: IdRow<Table>,
+        column_def: ColumnDef,
+    ) -> Result<IdRow<Table>, CubeError> {
+        let mut default = None;
+        for option in column_def.options.iter() {
+            match &option.option {
+                ColumnOption::Null => {}
+                ColumnOption::Default(expr) => default = Some(expr.clone()),
+                o => {
+                    return Err(CubeError::user(format!(
+                        "Column option '{}' is not supported in ADD COLUMN",
+                        o
+                    )))
+                }
+            }
+        }
+        let column = convert_columns_type(&vec![column_def])?.remove(0);
+        let default_value = match default {
+            Some(expr) => {
+                let array = parse_chunk(&[vec![expr]], &vec![&column])?.remove(0);
+                Some(TableValue::from_array(array.as_ref(), 0))
+            }
+            None => None,
+        };
+        self.db
+            .add_table_column(table.get_id(), column.with_default_value(default_value))
+            .await
+    }
+
+    /// Deletes rows matching `selection` by rewriting every partition that can contain them.
+    async fn delete_data(
+        &self,
+        context: SqlQueryContext,
+        schema_name: String,
+        table_name: String,
+        selection: Option<Expr>,
+    ) -> Result<(), CubeError> {
+        let table = self
+            .db
+            .get_table(schema_name.clone(), table_name.clone())
+            .await?;
+        if table.get_row().is_sealed() {
+            return Err(CubeError::user(format!(
+                "Can't delete from sealed table {}.{}",
+                schema_name, table_name
+            )));
+        }
+        let predicate = selection.unwrap_or(Expr::Value(Value::Boolean(true)));
+        let remaining = format!(
+            "SELECT * FROM `{}`.`{}` WHERE NOT ({}) OR ({}) IS NULL",
+            schema_name, table_name, predicate, predicate
+        );
+        self.rewrite_rows(context, &table, &predicate, None, remaining)
+            .await
+    }
+
+    /// Updated rows are selected with new values and replace the old ones along with the
+    /// partition rewrite, so concurrent queries see either old or new rows.
+    async fn update_data(
+        &self,
+        context: SqlQueryContext,
+        schema_name: String,
+        table_name: String,
+        assignments: Vec<Assignment>,
+        selection: Option<Expr>,
+    ) -> Result<u64, CubeError> {
+        let table = self
+            .db
+            .get_table(schema_name.clone(), table_name.clone())
+            .await?;
+        if table.get_row().is_sealed() {
+            return Err(CubeError::user(format!(
+                "Can't update sealed table {}.{}",
+                schema_name, table_name
+            )));
+        }
+        let columns = table.get_row().get_columns();
+        for a in assignments.iter() {
+            if !columns.iter().any(|c| c.get_name() == &a.id.value) {
+                return Err(CubeError::user(format!(
+                    "Column {} is not present in table {}.{}.",
+                    a.id.value, schema_name, table_name
+                )));
+            }
+        }
+        let predicate = selection.unwrap_or(Expr::Value(Value::Boolean(true)));
+        let mut projection = Vec::with_capacity(columns.len());
+        let mut rebuild_projection = Vec::with_capacity(columns.len());
+        for c in columns.iter() {
+            let column = Ident::with_quote('`', c.get_name());
+            match assignments.iter().find(|a| &a.id.value == c.get_name()) {
+                Some(a) => {
+                    let data_type = match c.get_column_type() {
+                        ColumnType::String => "VARCHAR",
+                        ColumnType::Int => "BIGINT",
+                        ColumnType::Int32 | ColumnType::Int16 | ColumnType::Int8 => "INT",
+                        ColumnType::Date => "DATE",
+                        ColumnType::Float => "DOUBLE",
+                        ColumnType::Boolean => "BOOLEAN",
+                        ColumnType::Timestamp => "TIMESTAMP",
+                        t => {
+                            return Err(CubeError::user(format!(
+                                "Column {} of {:?} type can't be updated",
+                                c.get_name(),
+                                t
+                            )))
+                        }
+                    };
+                    let value = format!("CAST({} AS {})", a.value, data_type);
+                    rebuild_projection.push(format!(
+                        "CASE WHEN {} THEN {} ELSE {} END AS {}",
+                        predicate, value, column, column
+                    ));
+                    projection.push(format!("{} AS {}", value, column));
+                }
+                None => {
+                    projection.push(column.to_string());
+                    rebuild_projection.push(column.to_string());
+                }
+            }
+        }
+        let select = format!(
+            "SELECT {} FROM `{}`.`{}` WHERE {}",
+            projection.join(", "),
+            schema_name,
+            table_name,
+            predicate
+        );
+        let updated = self
+            .exec_query_with_context(context.clone(), &select)
+            .await?;
+        let updated_count = updated.get_rows().len() as u64;
+        let rebuild_select = format!(
+            "SELECT {} FROM `{}`.`{}`",
+            rebuild_projection.join(", "),
+            schema_name,
+            table_name
+        );
+        self.rewrite_rows(context, &table, &predicate, Some(updated), rebuild_select)
+            .await?;
+        Ok(updated_count)
+    }
+
+    /// Replaces rows matching `predicate` with `updated` ones in every index of the `table`.
+    ///
+    /// New rows are written as inactive chunks first. Every partition that can contain matching
+    /// rows is then rewritten without them. Rewritten partitions are swapped and new chunks are
+    /// activated in a single metastore transaction, so rows moved between partitions are never
+    /// lost or duplicated and a failed rewrite changes nothing. Chunks and partitions that are
+    /// never activated are removed by the scheduler as any other inactive ones.
+    ///
+    /// Measures of aggregate indexes can't be filtered as rows are already aggregated. Aggregate
+    /// indexes that don't have all predicate columns among their dimensions are rebuilt from
+    /// `rebuild_select` rows, i.e. table contents after the change. Rows ingested while such an
+    /// index is rebuilt might be missing from it.
+    async fn rewrite_rows(
+        &self,
+        context: SqlQueryContext,
+        table: &IdRow<Table>,
+        predicate: &Expr,
+        updated: Option<Arc<DataFrame>>,
+        rebuild_select: String,
+    ) -> Result<(), CubeError> {
+        let columns = table.get_row().get_columns();
+        let indexes = self.db.get_table_indexes(table.get_id()).await?;
+        // `None` stands for indexes that are rebuilt.
+        let mut partition_filters = Vec::with_capacity(indexes.len());
+        for index in indexes.iter() {
+            let index_row = index.get_row();
+            if index_row.multi_index_id().is_some() {
+                return Err(CubeError::user(format!(
+                    "Can't change rows of {} as it's a part of partitioned index",
+                    table.get_row().get_table_name()
+                )));
+            }
+            let index_schema = arrow_schema(index_row);
+            let sort_key_schema = ArrowSchema::new(
+                index_schema.fields()[0..index_row.sort_key_size() as usize].to_vec(),
+            );
+            let filter = match index_row.get_type() {
+                IndexType::Regular => plan_filter_expr(predicate, &index_schema).map_err(|e| {
+                    CubeError::user(format!(
+                        "Can't use '{}' to change rows of '{}' index: {}",
+                        predicate,
+                        index_row.get_name(),
+                        e
+                    ))
+                })?,
+                IndexType::Aggregate => match plan_filter_expr(predicate, &sort_key_schema) {
+                    Ok(filter) => filter,
+                    Err(_) => {
+                        partition_filters.push(None);
+                        continue;
+                    }
+                },
+            };
+            partition_filters.push(Some(PartitionFilter::extract(&sort_key_schema, &[filter])));
+        }
+        let index_ids = indexes.iter().map(|i| i.get_id()).collect_vec();
+
+        let (filtered, rebuilt): (Vec<_>, Vec<_>) = indexes
+            .into_iter()
+            .zip(partition_filters.iter())
+            .partition(|(_, f)| f.is_some());
+        let mut new_rows = Vec::new();
+        if let Some(updated) = updated {
+            if !filtered.is_empty() {
+                new_rows.push((filtered.into_iter().map(|(i, _)| i).collect_vec(), updated));
+            }
+        }
+        if !rebuilt.is_empty() {
+            let remaining = self
+                .exec_query_with_context(context, &rebuild_select)
+                .await?;
+            new_rows.push((rebuilt.into_iter().map(|(i, _)| i).collect_vec(), remaining));
+        }
+        let mut uploads = Vec::new();
+        for (indexes, frame) in new_rows {
+            for rows_chunk in frame.get_rows().chunks(self.rows_per_chunk) {
+                uploads.extend(
+                    self.chunk_store
+                        .partition_index_data(
+                            indexes.clone(),
+                            data::rows_to_columns(columns, rows_chunk)?,
+                            columns,
+                        )
+                        .await?,
+                );
+            }
+        }
+        let mut new_chunks = HashMap::<u64, Vec<(u64, Option<u64>)>>::new();
+        for r in join_all(uploads).await {
+            let (chunk, file_size) = r??;
+            new_chunks
+                .entry(chunk.get_row().get_partition_id())
+                .or_default()
+                .push((chunk.get_id(), file_size));
+        }
+
+        // Chunks of compacted partitions are moved to the active ones first,
+        // so the rewrite doesn't have to deal with partitions pending repartition.
+        let listener = self.cluster.job_result_listener();
+        let mut repartitions = Vec::new();
+        for (partition, chunks) in self
+            .db
+            .get_active_partitions_and_chunks_by_index_id_for_select(index_ids.clone())
+            .await?
+            .into_iter()
+            .flatten()
+        {
+            if !partition.get_row().is_active() && !chunks.is_empty() {
+                repartitions.extend(chunks.iter().map(|c| {
+                    (
+                        RowKey::Table(TableId::Chunks, c.get_id()),
+                        JobType::RepartitionChunk,
+                    )
+                }));
+                self.cluster.schedule_repartition(&partition).await?;
+            }
+        }
+        for r in listener.wait_for_job_results(repartitions).await? {
+            if let JobEvent::Error(_, _, e) = r {
+                return Err(CubeError::user(format!("Rows rewrite failed: {}", e)));
+            }
+        }
+
+        let mut to_rewrite = Vec::new();
+        let mut to_activate = Vec::new();
+        for (partitions, partition_filter) in self
+            .db
+            .get_active_partitions_and_chunks_by_index_id_for_select(index_ids)
+            .await?
+            .into_iter()
+            .zip(partition_filters.iter())
+        {
+            for (partition, chunks) in partitions {
+                let p = partition.get_row();
+                if !p.is_active() {
+                    continue;
+                }
+                let partition_chunks = new_chunks.remove(&partition.get_id()).unwrap_or_default();
+                let has_rows = p.main_table_row_count() != 0 || !chunks.is_empty();
+                let predicate = match partition_filter {
+                    Some(filter) => {
+                        let can_match = filter.can_match(
+                            p.get_min_val().as_ref().map(|r| r.values().as_slice()),
+                            p.get_max_val().as_ref().map(|r| r.values().as_slice()),
+                        );
+                        if partition_chunks.is_empty() && !(has_rows && can_match) {
+                            continue;
+                        }
+                        predicate.to_string()
+                    }
+                    None => {
+                        if partition_chunks.is_empty() && !has_rows {
+                            continue;
+                        }
+                        "TRUE".to_string()
+                    }
+                };
+                to_activate.extend(partition_chunks);
+                to_rewrite.push((partition.get_id(), predicate));
+            }
+        }
+        if !new_chunks.is_empty() {
+            return Err(CubeError::user(format!(
+                "Partitions of {} were changed concurrently with rows rewrite. Please retry.",
+                table.get_row().get_table_name()
+            )));
+        }
+
+        let mut rewrites = Vec::with_capacity(to_rewrite.len());
+        for (partition_id, predicate) in to_rewrite {
+            rewrites.push(
+                self.compaction_service
+                    .prepare_rows_deletion(partition_id, predicate)
+                    .await?,
+            );
+        }
+        self.db
+            .swap_rewritten_partitions(rewrites, to_activate)
+            .await
+            .map_err(|e| CubeError::user(format!("Rows rewrite failed: {}", e)))
+    }
+
     async fn dump_select_inputs(
         &self,
         query: &str,
@@ -676,7 +1313,7 @@ impl SqlService for SqlServiceImpl {
         {
             trace!("Query: '{}'", query);
         }
-        if let Some(data_frame) = SqlServiceImpl::handle_workbench_queries(query) {
+        if let Some(data_frame) = SqlServiceImpl::handle_workbench_queries(query, &context) {
             return Ok(Arc::new(data_frame));
         }
         let ast = {
@@ -748,7 +1385,26 @@ impl SqlService for SqlServiceImpl {
                     }
                     panic!("worker did not panic")
                 }
+                SystemCommand::BackupMetaStore => {
+                    let backups = self.db.create_backup().await?;
+                    Ok(Arc::new(DataFrame::from(backups)))
+                }
+                SystemCommand::RestoreMetaStore { point } => {
+                    let point =
+                        Utc.timestamp_nanos(timestamp_from_string(&point)?.get_time_stamp());
+                    let backup = self.db.restore_backup(point).await?;
+                    Ok(Arc::new(DataFrame::from(vec![backup])))
+                }
             },
+            CubeStoreStatement::KillQuery { connection_id } => {
+                if !self.running_queries.kill(connection_id) {
+                    return Err(CubeError::user(format!(
+                        "No running query on connection {}",
+                        connection_id
+                    )));
+                }
+                Ok(Arc::new(DataFrame::new(vec![], vec![])))
+            }
             CubeStoreStatement::Statement(Statement::SetVariable { .. }) => {
                 Ok(Arc::new(DataFrame::new(vec![], vec![])))
             }
@@ -767,6 +1423,7 @@ impl SqlService for SqlServiceImpl {
                         columns,
                         external,
                         with_options,
+                        query,
                         ..
                     },
                 indexes,
@@ -784,6 +1441,48 @@ impl SqlService for SqlServiceImpl {
                 }
                 let schema_name = &nv[0].value;
                 let table_name = &nv[1].value;
+                let columns = convert_columns_type(&columns)?;
+                let ttl = ttl_from_options(&with_options)?;
+                let (compression, dictionary) = parquet_options_from_options(&with_options)?;
+                let bloom_filter_columns = bloom_filter_columns_from_options(&with_options)?;
+                if let Some(query) = query {
+                    if external || locations.is_some() {
+                        return Err(CubeError::user(
+                            "CREATE TABLE ... AS SELECT can't be used with LOCATION".to_string(),
+                        ));
+                    }
+                    // Unique key tables order rows by the sequence of their source,
+                    // and a select doesn't have one.
+                    if unique_key.is_some() {
+                        return Err(CubeError::user(
+                            "CREATE TABLE ... AS SELECT can't be used with UNIQUE KEY".to_string(),
+                        ));
+                    }
+                    if with_options.iter().any(|o| o.name.value == "input_format") {
+                        return Err(CubeError::user(
+                            "CREATE TABLE ... AS SELECT can't be used with input_format"
+                                .to_string(),
+                        ));
+                    }
+                    let res = self
+                        .create_table_as_select(
+                            schema_name.clone(),
+                            table_name.clone(),
+                            columns,
+                            query,
+                            indexes,
+                            unique_key,
+                            aggregates,
+                            partitioned_index,
+                            ttl,
+                            compression,
+                            dictionary,
+                            bloom_filter_columns,
+                            &context.trace_obj,
+                        )
+                        .await?;
+                    return Ok(Arc::new(DataFrame::from(vec![res])));
+                }
                 let import_format = with_options
                     .iter()
                     .find(|&opt| opt.name.value == "input_format")
@@ -793,6 +1492,8 @@ impl SqlService for SqlServiceImpl {
                                 match input_format.as_str() {
                                     "csv" => Result::Ok(ImportFormat::CSV),
                                     "csv_no_header" => Result::Ok(ImportFormat::CSVNoHeader),
+                                    "parquet" => Result::Ok(ImportFormat::Parquet),
+                                    "ndjson" => Result::Ok(ImportFormat::NDJSON),
                                     _ => Err(CubeError::user(format!(
                                         "Bad input format {}",
                                         option.value
@@ -810,7 +1511,7 @@ impl SqlService for SqlServiceImpl {
                     .create_table(
                         schema_name.clone(),
                         table_name.clone(),
-                        &columns,
+                        columns,
                         external,
                         locations,
                         Some(import_format),
@@ -818,6 +1519,10 @@ impl SqlService for SqlServiceImpl {
                         unique_key,
                         aggregates,
                         partitioned_index,
+                        ttl,
+                        compression,
+                        dictionary,
+                        bloom_filter_columns,
                         &context.trace_obj,
                     )
                     .await?;
@@ -866,39 +1571,38 @@ impl SqlService for SqlServiceImpl {
                 or_update,
             } => {
                 if or_update {
+                    let string_option = |name: &str| {
+                        credentials
+                            .iter()
+                            .find(|o| o.name.value == name)
+                            .and_then(|x| {
+                                if let Value::SingleQuotedString(v) = &x.value {
+                                    Some(v.to_string())
+                                } else {
+                                    None
+                                }
+                            })
+                    };
+                    let bool_option = |name: &str| {
+                        credentials
+                            .iter()
+                            .find(|o| o.name.value == name)
+                            .map(|x| match &x.value {
+                                Value::Boolean(v) => Ok(*v),
+                                Value::SingleQuotedString(v) => Ok(v == "true"),
+                                v => Err(CubeError::user(format!(
+                                    "{} should be boolean but found: {}",
+                                    name, v
+                                ))),
+                            })
+                            .transpose()
+                            .map(|v| v.unwrap_or(false))
+                    };
                     let creds = match source_type.as_str() {
                         "ksql" => {
-                            let user = credentials
-                                .iter()
-                                .find(|o| o.name.value == "user")
-                                .and_then(|x| {
-                                    if let Value::SingleQuotedString(v) = &x.value {
-                                        Some(v.to_string())
-                                    } else {
-                                        None
-                                    }
-                                });
-                            let password = credentials
-                                .iter()
-                                .find(|o| o.name.value == "password")
-                                .and_then(|x| {
-                                    if let Value::SingleQuotedString(v) = &x.value {
-                                        Some(v.to_string())
-                                    } else {
-                                        None
-                                    }
-                                });
-                            let url =
-                                credentials
-                                    .iter()
-                                    .find(|o| o.name.value == "url")
-                                    .and_then(|x| {
-                                        if let Value::SingleQuotedString(v) = &x.value {
-                                            Some(v.to_string())
-                                        } else {
-                                            None
-                                        }
-                                    });
+                            let user = string_option("user");
+                            let password = string_option("password");
+                            let url = string_option("url");
                             Ok(SourceCredentials::KSql {
                                 user,
                                 password,
@@ -907,6 +1611,53 @@ impl SqlService for SqlServiceImpl {
                                 ))?,
                             })
                         }
+                        "kafka" => {
+                            let brokers = string_option("brokers").ok_or(CubeError::user(
+                                "brokers is required as credential for kafka source".to_string(),
+                            ))?;
+                            let use_ssl = bool_option("use_ssl")?;
+                            let format = match string_option("format")
+                                .map(|f| f.to_lowercase())
+                                .as_deref()
+                            {
+                                None | Some("json") => KafkaMessageFormat::Json,
+                                Some("avro") => {
+                                    let schema = string_option("avro_schema");
+                                    let schema_registry_url = string_option("schema_registry_url");
+                                    match (&schema, &schema_registry_url) {
+                                        (Some(schema), None) => {
+                                            AvroSchema::parse(schema)?;
+                                        }
+                                        (None, Some(_)) => {}
+                                        _ => {
+                                            return Err(CubeError::user(
+                                                "Either avro_schema or schema_registry_url is required for avro format".to_string(),
+                                            ));
+                                        }
+                                    }
+                                    KafkaMessageFormat::Avro {
+                                        schema,
+                                        schema_registry_url,
+                                    }
+                                }
+                                Some(f) => {
+                                    return Err(CubeError::user(format!(
+                                        "Unsupported kafka message format '{}', expected one of: json, avro",
+                                        f
+                                    )))
+                                }
+                            };
+                            Ok(SourceCredentials::Kafka {
+                                brokers,
+                                topic: string_option("topic"),
+                                consumer_group: string_option("consumer_group"),
+                                user: string_option("user"),
+                                password: string_option("password"),
+                                use_ssl,
+                                format,
+                                skip_invalid_messages: bool_option("skip_invalid_messages")?,
+                            })
+                        }
                         x => Err(CubeError::user(format!("Not supported stream type: {}", x))),
                     };
                     let source = self
@@ -920,6 +1671,57 @@ impl SqlService for SqlServiceImpl {
                     ))
                 }
             }
+            CubeStoreStatement::AlterTable {
+                table_name,
+                command,
+            } => {
+                if table_name.0.len() != 2 {
+                    return Err(CubeError::user(format!(
+                        "Schema's name should be present in table name but found: {}",
+                        table_name
+                    )));
+                }
+                let table = self
+                    .db
+                    .get_table(table_name.0[0].value.clone(), table_name.0[1].value.clone())
+                    .await?;
+                let state = match command {
+                    AlterTableCommand::Seal => TableState::Sealed,
+                    AlterTableCommand::Pause => TableState::Paused,
+                    AlterTableCommand::Resume => TableState::Active,
+                };
+                let table = self.db.update_table_state(table.get_id(), state).await?;
+                Ok(Arc::new(DataFrame::from(vec![table])))
+            }
+            CubeStoreStatement::Statement(Statement::AlterTable { name, operation }) => {
+                if name.0.len() != 2 {
+                    return Err(CubeError::user(format!(
+                        "Schema's name should be present in table name but found: {}",
+                        name
+                    )));
+                }
+                let table = self
+                    .db
+                    .get_table(name.0[0].value.clone(), name.0[1].value.clone())
+                    .await?;
+                let table = match operation {
+                    AlterTableOperation::AddColumn { column_def } => {
+                        self.add_column(table, column_def).await?
+                    }
+                    AlterTableOperation::DropColumn { column_name, .. } => {
+                        self.db
+                            .drop_table_column(table.get_id(), column_name.value)
+                            .await?
+                    }
+                    operation => {
+                        return Err(CubeError::user(format!(
+                            "Unsupported ALTER TABLE operation: {}",
+                            operation
+                        )))
+                    }
+                };
+                Ok(Arc::new(DataFrame::from(vec![table])))
+            }
             CubeStoreStatement::Statement(Statement::CreatePartitionedIndex {
                 name,
                 columns,
@@ -972,15 +1774,6 @@ impl SqlService for SqlServiceImpl {
                 source,
                 ..
             }) => {
-                let data = if let SetExpr::Values(Values(data_series)) = &source.body {
-                    data_series
-                } else {
-                    return Err(CubeError::user(format!(
-                        "Data should be present in query. Your query was '{}'",
-                        query
-                    )));
-                };
-
                 let nv = &table_name.0;
                 if nv.len() != 2 {
                     return Err(CubeError::user(format!("Schema's name should be present in query (boo.table1). Your query was '{}'", query)));
@@ -988,56 +1781,54 @@ impl SqlService for SqlServiceImpl {
                 let schema_name = &nv[0].value;
                 let table_name = &nv[1].value;
 
-                self.insert_data(schema_name.clone(), table_name.clone(), &columns, data)
+                if let SetExpr::Values(Values(data)) = &source.body {
+                    self.insert_data(schema_name.clone(), table_name.clone(), &columns, data)
+                        .await?;
+                } else {
+                    self.insert_select(schema_name.clone(), table_name.clone(), &columns, source)
+                        .await?;
+                }
+                Ok(Arc::new(DataFrame::new(vec![], vec![])))
+            }
+            CubeStoreStatement::Statement(Statement::Delete {
+                table_name,
+                selection,
+                ..
+            }) => {
+                let nv = &table_name.0;
+                if nv.len() != 2 {
+                    return Err(CubeError::user(format!("Schema's name should be present in query (boo.table1). Your query was '{}'", query)));
+                }
+                self.delete_data(context, nv[0].value.clone(), nv[1].value.clone(), selection)
                     .await?;
                 Ok(Arc::new(DataFrame::new(vec![], vec![])))
             }
+            CubeStoreStatement::Statement(Statement::Update {
+                table_name,
+                assignments,
+                selection,
+                ..
+            }) => {
+                let nv = &table_name.0;
+                if nv.len() != 2 {
+                    return Err(CubeError::user(format!("Schema's name should be present in query (boo.table1). Your query was '{}'", query)));
+                }
+                self.update_data(
+                    context,
+                    nv[0].value.clone(),
+                    nv[1].value.clone(),
+                    assignments,
+                    selection,
+                )
+                .await?;
+                Ok(Arc::new(DataFrame::new(vec![], vec![])))
+            }
             CubeStoreStatement::Statement(Statement::Query(q)) => {
                 let logical_plan = self
                     .query_planner
                     .logical_plan(DFStatement::Statement(Statement::Query(q)))
                     .await?;
-                // TODO distribute and combine
-                let res = match logical_plan {
-                    QueryPlan::Meta(logical_plan) => {
-                        app_metrics::META_QUERIES.increment();
-                        Arc::new(self.query_planner.execute_meta_plan(logical_plan).await?)
-                    }
-                    QueryPlan::Select(serialized, workers) => {
-                        app_metrics::DATA_QUERIES.increment();
-                        let cluster = self.cluster.clone();
-                        let executor = self.query_executor.clone();
-                        timeout(
-                            self.query_timeout,
-                            self.cache
-                                .get(query, serialized, async move |plan| {
-                                    let records;
-                                    if workers.len() == 0 {
-                                        records =
-                                            executor.execute_router_plan(plan, cluster).await?.1;
-                                    } else {
-                                        // Pick one of the workers to run as main for the request.
-                                        let i = thread_rng().sample(Uniform::new(0, workers.len()));
-                                        let rs = cluster.route_select(&workers[i], plan).await?.1;
-                                        records = rs
-                                            .into_iter()
-                                            .map(|r| r.read())
-                                            .collect::<Result<Vec<_>, _>>()?;
-                                    }
-                                    Ok(cube_ext::spawn_blocking(
-                                        move || -> Result<DataFrame, CubeError> {
-                                            let df = batch_to_dataframe(&records)?;
-                                            Ok(df)
-                                        },
-                                    )
-                                    .await??)
-                                })
-                                .with_current_subscriber(),
-                        )
-                        .await??
-                    }
-                };
-                Ok(res)
+                self.exec_select_plan(context, query, logical_plan).await
             }
             CubeStoreStatement::Statement(Statement::Explain {
                 analyze,
@@ -1056,6 +1847,97 @@ impl SqlService for SqlServiceImpl {
         }
     }
 
+    async fn plan_select(&self, query: &str) -> Result<PlannedSelect, CubeError> {
+        let query = query.replace("\\'", "''");
+        let ast = {
+            let mut parser = CubeStoreParser::new(&query)?;
+            parser.parse_statement()?
+        };
+        let q = match ast {
+            CubeStoreStatement::Statement(Statement::Query(q)) => q,
+            _ => {
+                return Err(CubeError::user(format!(
+                    "Only SELECT queries can be planned, but got: '{}'",
+                    query
+                )))
+            }
+        };
+        let plan = self
+            .query_planner
+            .logical_plan(DFStatement::Statement(Statement::Query(q)))
+            .await?;
+        let schema = match &plan {
+            QueryPlan::Meta(logical_plan) => logical_plan.schema().clone(),
+            QueryPlan::Select(serialized, _) => serialized
+                .logical_plan(
+                    HashMap::new(),
+                    HashMap::new(),
+                    NoopParquetMetadataCache::new(),
+                )?
+                .schema()
+                .clone(),
+        };
+        let columns = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                Ok(Column::new(
+                    f.name().clone(),
+                    arrow_to_column_type(f.data_type().clone())?,
+                    i,
+                ))
+            })
+            .collect::<Result<Vec<_>, CubeError>>()?;
+        Ok(PlannedSelect {
+            query,
+            plan,
+            columns,
+        })
+    }
+
+    async fn exec_planned_select(
+        &self,
+        context: SqlQueryContext,
+        planned: PlannedSelect,
+    ) -> Result<SendableRecordBatchStream, CubeError> {
+        trace!("Query: '{}'", planned.query);
+        match planned.plan {
+            QueryPlan::Meta(logical_plan) => {
+                app_metrics::META_QUERIES.increment();
+                let df = self.query_planner.execute_meta_plan(logical_plan).await?;
+                let (schema, records) = dataframe_to_records(&df)?;
+                Ok(Box::pin(MemoryStream::try_new(records, schema, None)?))
+            }
+            QueryPlan::Select(serialized, workers) => {
+                app_metrics::DATA_QUERIES.increment();
+                let logged_query =
+                    self.query_log
+                        .start(&planned.query, &context, &serialized, &workers);
+                // Not registered in running queries, streams are cancelled by dropping them.
+                let cancel_token = context.cancel_token.child_token();
+                let serialized = serialized.with_cancel_token(cancel_token.clone());
+                let records = self
+                    .query_executor
+                    .execute_router_plan_stream(serialized, self.cluster.clone())
+                    .await;
+                let records = match records {
+                    Ok(records) => records,
+                    Err(e) => {
+                        cancel_token.cancel();
+                        logged_query.finish_rows(Err(&e), false);
+                        return Err(e);
+                    }
+                };
+                Ok(Box::pin(LoggedStream::new(
+                    records,
+                    logged_query,
+                    cancel_token,
+                )))
+            }
+        }
+    }
+
     async fn plan_query(&self, q: &str) -> Result<QueryPlans, CubeError> {
         let ast = {
             let replaced_quote = q.replace("\\'", "''");
@@ -1141,73 +2023,223 @@ impl SqlService for SqlServiceImpl {
     }
 }
 
+async fn execute_select(
+    cluster: Arc<dyn Cluster>,
+    executor: Arc<dyn QueryExecutor>,
+    plan: SerializedPlan,
+    workers: Vec<String>,
+) -> Result<(SchemaRef, Vec<RecordBatch>), CubeError> {
+    if workers.len() == 0 {
+        executor.execute_router_plan(plan, cluster).await
+    } else {
+        // Pick one of the workers to run as main for the request.
+        let i = thread_rng().sample(Uniform::new(0, workers.len()));
+        let (schema, rs) = cluster.route_select(&workers[i], plan).await?;
+        let records = rs
+            .into_iter()
+            .map(|r| r.read())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((schema, records))
+    }
+}
+
+fn dataframe_to_records(df: &DataFrame) -> Result<(SchemaRef, Vec<RecordBatch>), CubeError> {
+    let schema = Arc::new(ArrowSchema::new(
+        df.get_columns().iter().map(|c| c.into()).collect(),
+    ));
+    let columns = data::rows_to_columns(df.get_columns(), df.get_rows())?;
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    Ok((schema, vec![batch]))
+}
+
+/// Resolves INSERT target columns. All table columns are used if none are specified.
+fn insert_columns<'a>(
+    table_columns: &'a Vec<Column>,
+    columns: &Vec<Ident>,
+    schema_name: &str,
+    table_name: &str,
+) -> Result<Vec<&'a Column>, CubeError> {
+    if columns.is_empty() {
+        return Ok(table_columns.iter().collect());
+    }
+    let mut real_col: Vec<&Column> = Vec::new();
+    for column in columns {
+        let c = if let Some(item) = table_columns
+            .iter()
+            .find(|voc| *voc.get_name() == column.value)
+        {
+            item
+        } else {
+            return Err(CubeError::user(format!(
+                "Column {} is not present in table {}.{}.",
+                column.value, schema_name, table_name
+            )));
+        };
+        real_col.push(c);
+    }
+    Ok(real_col)
+}
+
+fn columns_from_schema(schema: &ArrowSchema) -> Result<Vec<Column>, CubeError> {
+    let mut columns = Vec::with_capacity(schema.fields().len());
+    for (i, field) in schema.fields().iter().enumerate() {
+        let column_type = column_type_from_arrow(field.data_type()).ok_or_else(|| {
+            CubeError::user(format!(
+                "Column {} of {:?} type can't be stored in a table",
+                field.name(),
+                field.data_type()
+            ))
+        })?;
+        columns.push(Column::new(field.name().clone(), column_type, i));
+    }
+    Ok(columns)
+}
+
+fn column_type_from_arrow(t: &ArrowDataType) -> Option<ColumnType> {
+    Some(match t {
+        ArrowDataType::Utf8 | ArrowDataType::LargeUtf8 => ColumnType::String,
+        ArrowDataType::Int8 => ColumnType::Int8,
+        ArrowDataType::Int16 => ColumnType::Int16,
+        ArrowDataType::Int32 => ColumnType::Int32,
+        ArrowDataType::Int64
+        | ArrowDataType::UInt8
+        | ArrowDataType::UInt16
+        | ArrowDataType::UInt32
+        | ArrowDataType::UInt64 => ColumnType::Int,
+        ArrowDataType::Float32 | ArrowDataType::Float64 => ColumnType::Float,
+        ArrowDataType::Boolean => ColumnType::Boolean,
+        ArrowDataType::Timestamp(_, _) => ColumnType::Timestamp,
+        ArrowDataType::Date32 => ColumnType::Date,
+        ArrowDataType::Int64Decimal(scale) => ColumnType::Decimal {
+            precision: 18,
+            scale: *scale as i32,
+        },
+        ArrowDataType::Binary | ArrowDataType::LargeBinary => ColumnType::Bytes,
+        ArrowDataType::List(item) => {
+            let item = column_type_from_arrow(item.data_type())?;
+            if !item.is_valid_list_item() {
+                return None;
+            }
+            ColumnType::List(Box::new(item))
+        }
+        _ => return None,
+    })
+}
+
+/// Reads `WITH (ttl = '90 days', ttl_column = 'ts')` as the column name and TTL in seconds.
+fn ttl_from_options(options: &[SqlOption]) -> Result<Option<(String, u64)>, CubeError> {
+    let option = |name: &str| -> Result<Option<&String>, CubeError> {
+        match options.iter().find(|o| o.name.value == name) {
+            None => Ok(None),
+            Some(SqlOption {
+                value: Value::SingleQuotedString(v),
+                ..
+            }) => Ok(Some(v)),
+            Some(o) => Err(CubeError::user(format!(
+                "Bad {} {}, string expected",
+                name, o.value
+            ))),
+        }
+    };
+    match (option("ttl")?, option("ttl_column")?) {
+        (None, None) => Ok(None),
+        (Some(ttl), Some(column)) => Ok(Some((column.to_string(), parse_ttl_duration(ttl)?))),
+        _ => Err(CubeError::user(
+            "Both ttl and ttl_column should be specified".to_string(),
+        )),
+    }
+}
+
+/// Reads `WITH (compression = 'zstd', dictionary = true)`. Server defaults are used for the
+/// options which are not set.
+fn parquet_options_from_options(
+    options: &[SqlOption],
+) -> Result<(Option<ParquetCompression>, Option<bool>), CubeError> {
+    let compression = match options.iter().find(|o| o.name.value == "compression") {
+        None => None,
+        Some(SqlOption {
+            value: Value::SingleQuotedString(v),
+            ..
+        }) => Some(v.parse::<ParquetCompression>()?),
+        Some(o) => {
+            return Err(CubeError::user(format!(
+                "Bad compression {}, string expected",
+                o.value
+            )))
+        }
+    };
+    let dictionary = match options.iter().find(|o| o.name.value == "dictionary") {
+        None => None,
+        Some(SqlOption {
+            value: Value::Boolean(v),
+            ..
+        }) => Some(*v),
+        Some(o) => {
+            return Err(CubeError::user(format!(
+                "Bad dictionary {}, boolean expected",
+                o.value
+            )))
+        }
+    };
+    Ok((compression, dictionary))
+}
+
+/// Reads `WITH (bloom_filter_columns = 'country, city')`.
+fn bloom_filter_columns_from_options(
+    options: &[SqlOption],
+) -> Result<Option<Vec<String>>, CubeError> {
+    match options
+        .iter()
+        .find(|o| o.name.value == "bloom_filter_columns")
+    {
+        None => Ok(None),
+        Some(SqlOption {
+            value: Value::SingleQuotedString(v),
+            ..
+        }) => Ok(Some(
+            v.split(',')
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect(),
+        )),
+        Some(o) => Err(CubeError::user(format!(
+            "Bad bloom_filter_columns {}, string expected",
+            o.value
+        ))),
+    }
+}
+
+/// Parses durations like '90 days' or '12 hours' to seconds.
+fn parse_ttl_duration(s: &str) -> Result<u64, CubeError> {
+    let err = || {
+        CubeError::user(format!(
+            "Bad ttl '{}', expected a number of seconds, minutes, hours, days or weeks, e.g. '90 days'",
+            s
+        ))
+    };
+    let (n, unit) = s.trim().split_once(' ').ok_or_else(err)?;
+    let n = n.parse::<u64>().map_err(|_| err())?;
+    let unit_secs = match unit.trim().to_lowercase().trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        _ => return Err(err()),
+    };
+    match n.checked_mul(unit_secs) {
+        Some(secs) if secs > 0 => Ok(secs),
+        _ => Err(err()),
+    }
+}
+
 fn convert_columns_type(columns: &Vec<ColumnDef>) -> Result<Vec<Column>, CubeError> {
     let mut rolupdb_columns = Vec::new();
 
     for (i, col) in columns.iter().enumerate() {
         let cube_col = Column::new(
             col.name.value.clone(),
-            match &col.data_type {
-                DataType::Date
-                | DataType::Time
-                | DataType::Char(_)
-                | DataType::Varchar(_)
-                | DataType::Clob(_)
-                | DataType::Text
-                | DataType::String => ColumnType::String,
-                DataType::Uuid
-                | DataType::Binary(_)
-                | DataType::Varbinary(_)
-                | DataType::Blob(_)
-                | DataType::Bytea
-                | DataType::Array(_) => ColumnType::Bytes,
-                DataType::Decimal(precision, scale) => {
-                    let mut precision = precision.unwrap_or(18);
-                    let mut scale = scale.unwrap_or(5);
-                    if precision > 18 {
-                        precision = 18;
-                    }
-                    if scale > 5 {
-                        scale = 10;
-                    }
-                    if scale > precision {
-                        precision = scale;
-                    }
-                    ColumnType::Decimal {
-                        precision: precision as i32,
-                        scale: scale as i32,
-                    }
-                }
-                DataType::SmallInt | DataType::Int | DataType::BigInt | DataType::Interval => {
-                    ColumnType::Int
-                }
-                DataType::Boolean => ColumnType::Boolean,
-                DataType::Float(_) | DataType::Real | DataType::Double => ColumnType::Float,
-                DataType::Timestamp => ColumnType::Timestamp,
-                DataType::Custom(custom) => {
-                    let custom_type_name = custom.to_string().to_lowercase();
-                    match custom_type_name.as_str() {
-                        "mediumint" => ColumnType::Int,
-                        "bytes" => ColumnType::Bytes,
-                        "varbinary" => ColumnType::Bytes,
-                        "hyperloglog" => ColumnType::HyperLogLog(HllFlavour::Airlift),
-                        "hyperloglogpp" => ColumnType::HyperLogLog(HllFlavour::ZetaSketch),
-                        "hll_snowflake" => ColumnType::HyperLogLog(HllFlavour::Snowflake),
-                        "hll_postgres" => ColumnType::HyperLogLog(HllFlavour::Postgres),
-                        _ => {
-                            return Err(CubeError::user(format!(
-                                "Custom type '{}' is not supported",
-                                custom
-                            )))
-                        }
-                    }
-                }
-                DataType::Regclass => {
-                    return Err(CubeError::user(
-                        "Type 'RegClass' is not suppored.".to_string(),
-                    ));
-                }
-            },
+            convert_column_type(&col.data_type)?,
             i,
         );
         rolupdb_columns.push(cube_col);
@@ -1215,12 +2247,95 @@ fn convert_columns_type(columns: &Vec<ColumnDef>) -> Result<Vec<Column>, CubeErr
     Ok(rolupdb_columns)
 }
 
+/// Standard SQL types keep their historical mappings, e.g. `DATE` is stored as a string and arrays
+/// are stored as bytes. `int8` is an 8-byte integer as `bigint` in Postgres. Narrower integers, dates
+/// and lists are declared with explicit type names: `tinyint` or `int1`, `int16`, `int32`, `date32`
+/// and `list_<item type>`, e.g. `list_text`.
+fn convert_column_type(data_type: &DataType) -> Result<ColumnType, CubeError> {
+    Ok(match data_type {
+        DataType::Date
+        | DataType::Time
+        | DataType::Char(_)
+        | DataType::Varchar(_)
+        | DataType::Clob(_)
+        | DataType::Text
+        | DataType::String => ColumnType::String,
+        DataType::Uuid
+        | DataType::Binary(_)
+        | DataType::Varbinary(_)
+        | DataType::Blob(_)
+        | DataType::Bytea
+        | DataType::Array(_) => ColumnType::Bytes,
+        DataType::Decimal(precision, scale) => {
+            let mut precision = precision.unwrap_or(18);
+            let mut scale = scale.unwrap_or(5);
+            if precision > 18 {
+                precision = 18;
+            }
+            if scale > 5 {
+                scale = 10;
+            }
+            if scale > precision {
+                precision = scale;
+            }
+            ColumnType::Decimal {
+                precision: precision as i32,
+                scale: scale as i32,
+            }
+        }
+        DataType::SmallInt | DataType::Int | DataType::BigInt | DataType::Interval => {
+            ColumnType::Int
+        }
+        DataType::Boolean => ColumnType::Boolean,
+        DataType::Float(_) | DataType::Real | DataType::Double => ColumnType::Float,
+        DataType::Timestamp => ColumnType::Timestamp,
+        DataType::Custom(custom) => {
+            let custom_type_name = custom.to_string().to_lowercase();
+            if let Some(item) = custom_type_name.strip_prefix("list_") {
+                let item = convert_column_type(&CubeStoreParser::new(item)?.parse_data_type()?)?;
+                if !item.is_valid_list_item() {
+                    return Err(CubeError::user(format!(
+                        "Type '{}' can't be used as a list item",
+                        item
+                    )));
+                }
+                return Ok(ColumnType::List(Box::new(item)));
+            }
+            match custom_type_name.as_str() {
+                "mediumint" | "int8" => ColumnType::Int,
+                "tinyint" | "int1" => ColumnType::Int8,
+                "int16" => ColumnType::Int16,
+                "int32" => ColumnType::Int32,
+                "date32" => ColumnType::Date,
+                "bytes" => ColumnType::Bytes,
+                "varbinary" => ColumnType::Bytes,
+                "hyperloglog" => ColumnType::HyperLogLog(HllFlavour::Airlift),
+                "hyperloglogpp" => ColumnType::HyperLogLog(HllFlavour::ZetaSketch),
+                "hll_snowflake" => ColumnType::HyperLogLog(HllFlavour::Snowflake),
+                "hll_postgres" => ColumnType::HyperLogLog(HllFlavour::Postgres),
+                "tdigest" => ColumnType::TDigest,
+                _ => {
+                    return Err(CubeError::user(format!(
+                        "Custom type '{}' is not supported",
+                        custom
+                    )))
+                }
+            }
+        }
+        DataType::Regclass => {
+            return Err(CubeError::user(
+                "Type 'RegClass' is not suppored.".to_string(),
+            ));
+        }
+    })
+}
+
 fn parse_chunk(chunk: &[Vec<Expr>], column: &Vec<&Column>) -> Result<Vec<ArrayRef>, CubeError> {
     let mut buffer = Vec::new();
     let mut builders = column
         .iter()
         .map(|c| create_array_builder(c.get_column_type()))
-        .collect_vec();
+        .collect::<Result<Vec<_>, _>>()?;
     for r in chunk {
         for i in 0..r.len() {
             extract_data(&r[i], &column[i], &mut buffer, builders[i].as_mut())?;
@@ -1279,6 +2394,23 @@ fn parse_hyper_log_log<'a>(
     }
 }
 
+/// Sketches produced by queries must match the format of the column, e.g. `HLLPP_INIT` results
+/// can't be inserted into `HYPERLOGLOG` columns.
+fn validate_hll_array(a: &dyn Array, f: HllFlavour) -> Result<(), CubeError> {
+    // Snowflake and Postgres sketches are stored in the Airlift format.
+    let f = match f {
+        HllFlavour::Snowflake | HllFlavour::Postgres => HllFlavour::Airlift,
+        f => f,
+    };
+    let a = a.as_any().downcast_ref::<BinaryArray>().unwrap();
+    for i in 0..a.len() {
+        if a.is_valid(i) {
+            is_valid_plain_binary_hll(a.value(i), f)?;
+        }
+    }
+    Ok(())
+}
+
 fn parse_binary_string<'a>(buffer: &'a mut Vec<u8>, v: &'a Value) -> Result<&'a [u8], CubeError> {
     match v {
         Value::Number(s, _) => Ok(s.as_bytes()),
@@ -1496,10 +2628,110 @@ fn extract_data<'a>(
             let v = parse_float(cell)?;
             builder.append_value(v)?;
         }
+        ColumnType::Int32 => {
+            let builder = builder.as_any_mut().downcast_mut::<Int32Builder>().unwrap();
+            if is_null {
+                builder.append_null()?;
+                return Ok(());
+            }
+            builder.append_value(parse_narrow_int(cell, "int32")?)?;
+        }
+        ColumnType::Int16 => {
+            let builder = builder.as_any_mut().downcast_mut::<Int16Builder>().unwrap();
+            if is_null {
+                builder.append_null()?;
+                return Ok(());
+            }
+            builder.append_value(parse_narrow_int(cell, "int16")?)?;
+        }
+        ColumnType::Int8 => {
+            let builder = builder.as_any_mut().downcast_mut::<Int8Builder>().unwrap();
+            if is_null {
+                builder.append_null()?;
+                return Ok(());
+            }
+            builder.append_value(parse_narrow_int(cell, "tinyint")?)?;
+        }
+        ColumnType::Date => {
+            let builder = builder
+                .as_any_mut()
+                .downcast_mut::<Date32Builder>()
+                .unwrap();
+            if is_null {
+                builder.append_null()?;
+                return Ok(());
+            }
+            match cell {
+                Expr::Value(Value::SingleQuotedString(v)) => {
+                    builder.append_value(DateValue::parse(v)?.get_days_since_epoch())?;
+                }
+                x => return Err(CubeError::user(format!("Can't parse date from, {:?}", x))),
+            }
+        }
+        ColumnType::List(item) => {
+            let value = match cell {
+                Expr::Value(Value::Null) => TableValue::Null,
+                Expr::Value(Value::SingleQuotedString(v)) => parse_list(v, item, buffer)?,
+                x => return Err(CubeError::user(format!("Can't parse array from, {:?}", x))),
+            };
+            append_value(builder, column.get_column_type(), &value)?;
+        }
+        ColumnType::TDigest => {
+            let builder = builder
+                .as_any_mut()
+                .downcast_mut::<BinaryBuilder>()
+                .unwrap();
+            if is_null {
+                builder.append_null()?;
+                return Ok(());
+            }
+            let val;
+            if let Expr::Value(v) = cell {
+                val = parse_binary_string(buffer, v)?
+            } else {
+                return Err(CubeError::user("Corrupted data in query.".to_string()));
+            };
+            TDigest::read(val)?;
+            builder.append_value(val)?;
+        }
     }
     Ok(())
 }
 
+/// Parses arrays passed as JSON strings, e.g. `'["a", "b"]'` or `'[1, null]'`.
+/// Items are parsed the same way as values of `item_type` columns.
+fn parse_list(
+    s: &str,
+    item_type: &ColumnType,
+    buffer: &mut Vec<u8>,
+) -> Result<TableValue, CubeError> {
+    let items: Vec<serde_json::Value> = serde_json::from_str(s)
+        .map_err(|e| CubeError::user(format!("Can't parse array '{}': {}", s, e)))?;
+    let item_column = Column::new("item".to_string(), item_type.clone(), 0);
+    let mut builder = create_array_builder(item_type)?;
+    for item in items {
+        let cell = match item {
+            serde_json::Value::Null => Expr::Value(Value::Null),
+            serde_json::Value::Bool(b) => Expr::Value(Value::Boolean(b)),
+            serde_json::Value::Number(n) => Expr::Value(Value::Number(n.to_string(), false)),
+            serde_json::Value::String(s) => Expr::Value(Value::SingleQuotedString(s)),
+            v => {
+                return Err(CubeError::user(format!(
+                    "Nested value {} is not allowed in array '{}'",
+                    v, s
+                )))
+            }
+        };
+        extract_data(&cell, &item_column, buffer, builder.as_mut())?;
+    }
+    let items = builder.finish();
+    Ok(TableValue::List(
+        (0..items.len())
+            .map(|i| TableValue::from_array(items.as_ref(), i))
+            .collect(),
+    ))
+}
+
 pub fn timestamp_from_string(v: &str) -> Result<TimestampValue, CubeError> {
     let nanos;
     if v.ends_with("UTC") {
@@ -1525,7 +2757,37 @@ fn parse_time(s: &str, format: &[chrono::format::Item]) -> ParseResult<Parsed> {
     Ok(p)
 }
 
-fn parse_float(cell: &Expr) -> Result<f64, CubeError> {
+fn parse_narrow_int<T: FromStr>(cell: &Expr, type_name: &str) -> Result<T, CubeError>
+where
+    T::Err: Display,
+{
+    let v = match cell {
+        Expr::Value(Value::Number(v, _)) | Expr::Value(Value::SingleQuotedString(v)) => {
+            v.parse::<T>()
+        }
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => match expr.as_ref() {
+            Expr::Value(Value::Number(v, _)) => format!("-{}", v).parse::<T>(),
+            _ => {
+                return Err(CubeError::user(format!(
+                    "Can't parse {} from, {:?}",
+                    type_name, cell
+                )))
+            }
+        },
+        _ => {
+            return Err(CubeError::user(format!(
+                "Can't parse {} from, {:?}",
+                type_name, cell
+            )))
+        }
+    };
+    v.map_err(|e| CubeError::user(format!("Can't parse {} from, {:?}: {}", type_name, cell, e)))
+}
+
+fn parse_float(cell: &Expr) -> Result<f64, CubeError> {
     match cell {
         Expr::Value(Value::Number(v, _)) | Expr::Value(Value::SingleQuotedString(v)) => {
             Ok(v.parse::<f64>()?)
@@ -1577,10 +2839,11 @@ mod tests {
     use crate::cluster::MockCluster;
     use crate::config::{Config, FileStoreProvider};
     use crate::import::MockImportService;
-    use crate::metastore::RocksMetaStore;
+    use crate::metastore::{Partition, RocksMetaStore};
     use crate::queryplanner::query_executor::MockQueryExecutor;
     use crate::queryplanner::MockQueryPlanner;
     use crate::remotefs::{LocalDirRemoteFs, RemoteFile, RemoteFs};
+    use crate::store::compaction::CompactionServiceImpl;
     use crate::store::ChunkStore;
 
     use super::*;
@@ -1617,19 +2880,26 @@ mod tests {
             );
             let limits = Arc::new(ConcurrencyLimits::new(4));
             let service = SqlServiceImpl::new(
-                meta_store,
-                store,
+                meta_store.clone(),
+                store.clone(),
                 limits,
                 Arc::new(MockQueryPlanner::new()),
                 Arc::new(MockQueryExecutor::new()),
                 Arc::new(MockCluster::new()),
                 Arc::new(MockImportService::new()),
+                CompactionServiceImpl::new(
+                    meta_store,
+                    store,
+                    remote_fs.clone(),
+                    config.config_obj(),
+                ),
                 config.config_obj(),
                 remote_fs.clone(),
                 rows_per_chunk,
                 query_timeout,
                 query_timeout,
                 10_000, // max_cached_queries
+                QueryLog::new(0),
             );
             let i = service.exec_query("CREATE SCHEMA foo").await.unwrap();
             assert_eq!(
@@ -1672,18 +2942,25 @@ mod tests {
             let limits = Arc::new(ConcurrencyLimits::new(4));
             let service = SqlServiceImpl::new(
                 meta_store.clone(),
-                chunk_store,
+                chunk_store.clone(),
                 limits,
                 Arc::new(MockQueryPlanner::new()),
                 Arc::new(MockQueryExecutor::new()),
                 Arc::new(MockCluster::new()),
                 Arc::new(MockImportService::new()),
+                CompactionServiceImpl::new(
+                    meta_store.clone(),
+                    chunk_store.clone(),
+                    remote_fs.clone(),
+                    config.config_obj(),
+                ),
                 config.config_obj(),
                 remote_fs.clone(),
                 rows_per_chunk,
                 query_timeout,
                 query_timeout,
                 10_000, // max_cached_queries
+                QueryLog::new(0),
             );
             let i = service.exec_query("CREATE SCHEMA Foo").await.unwrap();
             assert_eq!(
@@ -2381,178 +3658,761 @@ mod tests {
                         )
                         .await;
 
-                        let result = first_query.unwrap();
-                        assert_eq!(
-                            result.get_rows()[0],
-                            Row::new(vec![TableValue::Int(300000)])
-                        );
+                        let result = first_query.unwrap();
+                        assert_eq!(
+                            result.get_rows()[0],
+                            Row::new(vec![TableValue::Int(300000)])
+                        );
+
+                        let result = second_query.unwrap();
+                        assert_eq!(
+                            result.get_rows()[0],
+                            Row::new(vec![TableValue::Int(300000 / 2 * 99999)])
+                        );
+                    })
+                    .await;
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn inactive_partitions_cleanup() {
+        Config::test("inactive_partitions_cleanup")
+            .update_config(|mut c| {
+                c.partition_split_threshold = 1000000;
+                c.compaction_chunks_count_threshold = 0;
+                c.not_used_timeout = 0;
+                c.meta_store_log_upload_interval = 1;
+                c.gc_loop_interval = 1;
+                c
+            })
+            .start_test(async move |services| {
+                let service = services.sql_service;
+
+                service.exec_query("CREATE SCHEMA foo").await.unwrap();
+
+                service
+                    .exec_query("CREATE TABLE foo.numbers (num int)")
+                    .await
+                    .unwrap();
+
+                for i in 0..10_u64 {
+                    service
+                        .exec_query(&format!("INSERT INTO foo.numbers (num) VALUES ({})", i))
+                        .await
+                        .unwrap();
+                }
+
+                // let listener = services.cluster.job_result_listener();
+                // let active_partitions = services
+                //     .meta_store
+                //     .get_active_partitions_by_index_id(1)
+                //     .await
+                //     .unwrap();
+                // let mut last_active_partition = active_partitions.iter().next().unwrap();
+                // listener
+                //     .wait_for_job_results(vec![(
+                //         RowKey::Table(TableId::Partitions, last_active_partition.get_id()),
+                //         JobType::Repartition,
+                //     )])
+                //     .await
+                //     .unwrap();
+
+                // TODO API to wait for all jobs to be completed and all events processed
+                Delay::new(Duration::from_millis(500)).await;
+
+                let result = service
+                    .exec_query("SELECT count(*) from foo.numbers")
+                    .await
+                    .unwrap();
+                assert_eq!(result.get_rows()[0], Row::new(vec![TableValue::Int(10)]));
+
+                let active_partitions = services
+                    .meta_store
+                    .get_active_partitions_by_index_id(1)
+                    .await
+                    .unwrap();
+                let last_active_partition = active_partitions.iter().next().unwrap();
+
+                // Wait for GC tasks to drop files
+                Delay::new(Duration::from_millis(3000)).await;
+
+                let remote_fs = services.injector.get_service_typed::<dyn RemoteFs>().await;
+                let files = remote_fs
+                    .list("")
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .filter(|r| r.ends_with(".parquet"))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    files,
+                    vec![format!(
+                        "{}-{}.parquet",
+                        last_active_partition.get_id(),
+                        last_active_partition.get_row().suffix().as_ref().unwrap()
+                    )]
+                )
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn in_memory_compaction() {
+        Config::test("inmemory_compaction")
+            .update_config(|mut c| {
+                c.partition_split_threshold = 1000000;
+                c.compaction_chunks_count_threshold = 10;
+                c.not_used_timeout = 0;
+                c.compaction_in_memory_chunks_count_threshold = 5;
+                c.compaction_in_memory_chunks_max_lifetime_threshold = 1;
+                c
+            })
+            .start_test(async move |services| {
+                let service = services.sql_service;
+
+                service.exec_query("CREATE SCHEMA foo").await.unwrap();
+
+                service
+                    .exec_query("CREATE TABLE foo.numbers (a int, num int) UNIQUE KEY (a)")
+                    .await
+                    .unwrap();
+
+                for i in 0..6 {
+                    service
+                        .exec_query(&format!(
+                            "INSERT INTO foo.numbers (a, num, __seq) VALUES ({}, {}, {})",
+                            i, i, i
+                        ))
+                        .await
+                        .unwrap();
+                }
+
+                Delay::new(Duration::from_millis(500)).await;
+
+                let active_partitions = services
+                    .meta_store
+                    .get_active_partitions_by_index_id(1)
+                    .await
+                    .unwrap();
+                assert_eq!(active_partitions.len(), 1);
+                let partition = active_partitions.first().unwrap();
+                assert_eq!(partition.get_row().main_table_row_count(), 0);
+                let chunks = services
+                    .meta_store
+                    .get_chunks_by_partition(partition.get_id(), false)
+                    .await
+                    .unwrap();
+                assert_eq!(chunks.len(), 1);
+                assert_eq!(chunks.first().unwrap().get_row().get_row_count(), 6);
+                //waiting for more then compaction_chunks_count_threshold
+                Delay::new(Duration::from_millis(2000)).await;
+                service
+                    .exec_query(&format!(
+                        "INSERT INTO foo.numbers (a, num, __seq) VALUES ({}, {}, {})",
+                        7, 7, 7
+                    ))
+                    .await
+                    .unwrap();
+                Delay::new(Duration::from_millis(1000)).await;
+                let active_partitions = services
+                    .meta_store
+                    .get_active_partitions_by_index_id(1)
+                    .await
+                    .unwrap();
+                assert_eq!(active_partitions.len(), 1);
+                let partition = active_partitions.first().unwrap();
+                assert_eq!(partition.get_row().main_table_row_count(), 6);
+                let chunks = services
+                    .meta_store
+                    .get_chunks_by_partition(partition.get_id(), false)
+                    .await
+                    .unwrap();
+                assert_eq!(chunks.len(), 1);
+                assert_eq!(chunks.first().unwrap().get_row().get_row_count(), 1);
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn table_ttl() {
+        Config::test("table_ttl")
+            .update_config(|mut c| {
+                c.partition_split_threshold = 2;
+                c
+            })
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
+
+                let e = service
+                    .exec_query("CREATE TABLE s.Bad (ts timestamp) WITH (ttl = '90 days')")
+                    .await
+                    .unwrap_err();
+                assert!(e.message.contains("Both ttl and ttl_column"), "{}", e);
+                let e = service
+                    .exec_query(
+                        "CREATE TABLE s.Bad (ts timestamp) WITH (ttl = '90 parsecs', ttl_column = 'ts')",
+                    )
+                    .await
+                    .unwrap_err();
+                assert!(e.message.contains("Bad ttl"), "{}", e);
+                let e = service
+                    .exec_query(
+                        "CREATE TABLE s.Bad (n int) WITH (ttl = '90 days', ttl_column = 'n')",
+                    )
+                    .await
+                    .unwrap_err();
+                assert!(e.message.contains("should be a timestamp"), "{}", e);
+
+                // Chunks with only fresh rows are not rewritten, even if not sorted by ts.
+                service
+                    .exec_query(
+                        "CREATE TABLE s.Fresh (n int, ts timestamp) WITH (ttl = '1 day', ttl_column = 'ts')",
+                    )
+                    .await
+                    .unwrap();
+                service
+                    .exec_query(&format!(
+                        "INSERT INTO s.Fresh (n, ts) VALUES (1, '{}'), (2, NULL)",
+                        Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                    ))
+                    .await
+                    .unwrap();
+                services.scheduler.expire_ttl_rows().await.unwrap();
+                let deletions = services
+                    .meta_store
+                    .all_jobs()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .filter(|j| match j.get_row().job_type() {
+                        JobType::PartitionRowsDeletion(_) => true,
+                        _ => false,
+                    })
+                    .count();
+                assert_eq!(deletions, 0);
+
+                service
+                    .exec_query(
+                        "CREATE TABLE s.Data (ts timestamp, n int) WITH (ttl = '1 day', ttl_column = 'ts')",
+                    )
+                    .await
+                    .unwrap();
+                let result = service
+                    .exec_query("SELECT ttl_column, ttl_secs FROM system.tables WHERE table_name = 'Data'")
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    result.get_rows(),
+                    &vec![Row::new(vec![
+                        TableValue::String("ts".to_string()),
+                        TableValue::Int(24 * 60 * 60),
+                    ])]
+                );
+
+                service
+                    .exec_query(
+                        "INSERT INTO s.Data (ts, n) VALUES \
+                         ('2020-01-01T00:00:00.000Z', 1), ('2020-01-02T00:00:00.000Z', 2), \
+                         ('2020-01-03T00:00:00.000Z', 3), ('2020-01-04T00:00:00.000Z', 4), \
+                         ('2020-01-05T00:00:00.000Z', 5), ('2020-01-06T00:00:00.000Z', 6)",
+                    )
+                    .await
+                    .unwrap();
+                // Split old rows into several partitions, so some of them expire entirely.
+                for p in services
+                    .meta_store
+                    .get_partitions_with_chunks_created_seconds_ago(0)
+                    .await
+                    .unwrap()
+                {
+                    services.scheduler.schedule_partition_to_compact(&p).await.unwrap();
+                }
+                Delay::new(Duration::from_millis(1000)).await;
+                service
+                    .exec_query(&format!(
+                        "INSERT INTO s.Data (ts, n) VALUES ('{}', 7), (NULL, 8)",
+                        Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                    ))
+                    .await
+                    .unwrap();
+
+                services.scheduler.expire_ttl_rows().await.unwrap();
+                Delay::new(Duration::from_millis(1000)).await;
+
+                let result = service
+                    .exec_query("SELECT n FROM s.Data ORDER BY n")
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    result.get_rows(),
+                    &vec![
+                        Row::new(vec![TableValue::Int(7)]),
+                        Row::new(vec![TableValue::Int(8)]),
+                    ]
+                );
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn table_compression() {
+        Config::test("table_compression")
+            .update_config(|mut c| {
+                c.parquet_compression = ParquetCompression::Snappy;
+                c
+            })
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
+
+                let e = service
+                    .exec_query("CREATE TABLE s.Bad (n int) WITH (compression = 'lzma')")
+                    .await
+                    .unwrap_err();
+                assert!(e.message.contains("Unknown compression"), "{}", e);
+                let e = service
+                    .exec_query("CREATE TABLE s.Bad (n int) WITH (dictionary = 'yes')")
+                    .await
+                    .unwrap_err();
+                assert!(e.message.contains("Bad dictionary"), "{}", e);
+
+                service
+                    .exec_query(
+                        "CREATE TABLE s.Compressed (s text, n int) WITH (compression = 'zstd', dictionary = false)",
+                    )
+                    .await
+                    .unwrap();
+                service
+                    .exec_query("CREATE TABLE s.Plain (s text, n int)")
+                    .await
+                    .unwrap();
+                let result = service
+                    .exec_query(
+                        "SELECT table_name, compression, dictionary FROM system.tables ORDER BY table_name",
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    result.get_rows(),
+                    &vec![
+                        Row::new(vec![
+                            TableValue::String("Compressed".to_string()),
+                            TableValue::String("zstd".to_string()),
+                            TableValue::Boolean(false),
+                        ]),
+                        Row::new(vec![
+                            TableValue::String("Plain".to_string()),
+                            TableValue::Null,
+                            TableValue::Null,
+                        ]),
+                    ]
+                );
+
+                for table in ["Compressed", "Plain"] {
+                    service
+                        .exec_query(&format!(
+                            "INSERT INTO s.{} (s, n) VALUES ('a', 1), ('a', 2), ('b', 3)",
+                            table
+                        ))
+                        .await
+                        .unwrap();
+                }
+                let result = service
+                    .exec_query(
+                        "SELECT DISTINCT compression FROM system.chunks ORDER BY compression",
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    result.get_rows(),
+                    &vec![
+                        Row::new(vec![TableValue::String("snappy".to_string())]),
+                        Row::new(vec![TableValue::String("zstd".to_string())]),
+                    ]
+                );
+
+                let result = service
+                    .exec_query("SELECT s, sum(n) FROM s.Compressed GROUP BY 1 ORDER BY 1")
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    result.get_rows(),
+                    &vec![
+                        Row::new(vec![TableValue::String("a".to_string()), TableValue::Int(3)]),
+                        Row::new(vec![TableValue::String("b".to_string()), TableValue::Int(3)]),
+                    ]
+                );
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn chunk_stats_pruning() {
+        Config::test("chunk_stats_pruning").start_test(async move |services| {
+            let service = services.sql_service;
+            service.exec_query("CREATE SCHEMA s").await.unwrap();
+
+            let e = service
+                .exec_query("CREATE TABLE s.Bad (n int) WITH (bloom_filter_columns = 'm')")
+                .await
+                .unwrap_err();
+            assert!(e.message.contains("Bloom filter column m not found"), "{}", e);
+            let e = service
+                .exec_query("CREATE TABLE s.Bad (f float) WITH (bloom_filter_columns = 'f')")
+                .await
+                .unwrap_err();
+            assert!(e.message.contains("Bloom filters are not supported"), "{}", e);
+
+            service
+                .exec_query(
+                    "CREATE TABLE s.Events (id int, country text) WITH (bloom_filter_columns = 'country')",
+                )
+                .await
+                .unwrap();
+            let result = service
+                .exec_query("SELECT bloom_filter_columns FROM system.tables")
+                .await
+                .unwrap();
+            assert_eq!(
+                result.get_rows(),
+                &vec![Row::new(vec![TableValue::String("country".to_string())])]
+            );
+
+            for values in [
+                "(1, 'DE'), (2, 'DE')",
+                "(3, 'FR'), (4, 'US')",
+                "(5, NULL), (6, 'US')",
+            ] {
+                service
+                    .exec_query(&format!(
+                        "INSERT INTO s.Events (id, country) VALUES {}",
+                        values
+                    ))
+                    .await
+                    .unwrap();
+            }
+
+            let ids = |rows: &[Row]| {
+                rows.iter()
+                    .map(|r| match &r.values()[0] {
+                        TableValue::Int(id) => *id,
+                        v => panic!("unexpected value {:?}", v),
+                    })
+                    .collect::<Vec<_>>()
+            };
+            for compacted in [false, true] {
+                if compacted {
+                    // Stats of the chunks move to the main table file of the partition.
+                    for p in services
+                        .meta_store
+                        .get_partitions_with_chunks_created_seconds_ago(0)
+                        .await
+                        .unwrap()
+                    {
+                        services.scheduler.schedule_partition_to_compact(&p).await.unwrap();
+                    }
+                    Delay::new(Duration::from_millis(1000)).await;
+
+                    let table = services
+                        .meta_store
+                        .get_table("s".to_string(), "Events".to_string())
+                        .await
+                        .unwrap();
+                    let index = &services
+                        .meta_store
+                        .get_table_indexes(table.get_id())
+                        .await
+                        .unwrap()[0];
+                    let partitions = services
+                        .meta_store
+                        .get_active_partitions_by_index_id(index.get_id())
+                        .await
+                        .unwrap();
+                    assert!(partitions.iter().any(|p| p.get_row().has_main_table_file()));
+                    for p in partitions {
+                        let p = p.get_row();
+                        if p.has_main_table_file() {
+                            let stats = p.column_stats().as_ref().unwrap();
+                            assert_eq!(stats.len(), 2);
+                            assert!(stats[1].bloom_filter().is_some());
+                        }
+                    }
+                }
+
+                for (filter, expected) in [
+                    ("country = 'DE'", vec![1, 2]),
+                    ("country = 'GB'", vec![]),
+                    ("country IN ('FR', 'US')", vec![3, 4, 6]),
+                    ("country IS NULL", vec![5]),
+                    ("id > 4", vec![5, 6]),
+                    ("id <= 2 OR country = 'FR'", vec![1, 2, 3]),
+                ] {
+                    let result = service
+                        .exec_query(&format!(
+                            "SELECT id FROM s.Events WHERE {} ORDER BY id",
+                            filter
+                        ))
+                        .await
+                        .unwrap();
+                    assert_eq!(ids(result.get_rows()), expected, "{}", filter);
+                }
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn query_memory_limit() {
+        Config::test("query_memory_limit")
+            .update_config(|mut c| {
+                c.query_memory_limit_bytes = 1;
+                c
+            })
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
+                service
+                    .exec_query("CREATE TABLE s.Data (id int, n int)")
+                    .await
+                    .unwrap();
+                service
+                    .exec_query("INSERT INTO s.Data (id, n) VALUES (1, 1), (2, 2), (1, 3)")
+                    .await
+                    .unwrap();
 
-                        let result = second_query.unwrap();
-                        assert_eq!(
-                            result.get_rows()[0],
-                            Row::new(vec![TableValue::Int(300000 / 2 * 99999)])
-                        );
-                    })
-                    .await;
+                let e = service
+                    .exec_query("SELECT id, SUM(n) FROM s.Data GROUP BY 1")
+                    .await
+                    .unwrap_err();
+                assert!(
+                    e.message.contains("Query exceeded memory limit of 1 bytes"),
+                    "{}",
+                    e
+                );
             })
-            .await;
+            .await
     }
 
     #[tokio::test]
-    async fn inactive_partitions_cleanup() {
-        Config::test("inactive_partitions_cleanup")
+    async fn query_memory_limit_aggregation_state() {
+        Config::test("query_memory_limit_aggregation_state")
             .update_config(|mut c| {
-                c.partition_split_threshold = 1000000;
-                c.compaction_chunks_count_threshold = 0;
-                c.not_used_timeout = 0;
-                c.meta_store_log_upload_interval = 1;
-                c.gc_loop_interval = 1;
+                c.query_memory_limit_bytes = 100_000;
                 c
             })
             .start_test(async move |services| {
                 let service = services.sql_service;
-
-                service.exec_query("CREATE SCHEMA foo").await.unwrap();
-
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
                 service
-                    .exec_query("CREATE TABLE foo.numbers (num int)")
+                    .exec_query("CREATE TABLE s.Data (id int, n int)")
                     .await
                     .unwrap();
-
-                for i in 0..10_u64 {
-                    service
-                        .exec_query(&format!("INSERT INTO foo.numbers (num) VALUES ({})", i))
-                        .await
-                        .unwrap();
-                }
-
-                // let listener = services.cluster.job_result_listener();
-                // let active_partitions = services
-                //     .meta_store
-                //     .get_active_partitions_by_index_id(1)
-                //     .await
-                //     .unwrap();
-                // let mut last_active_partition = active_partitions.iter().next().unwrap();
-                // listener
-                //     .wait_for_job_results(vec![(
-                //         RowKey::Table(TableId::Partitions, last_active_partition.get_id()),
-                //         JobType::Repartition,
-                //     )])
-                //     .await
-                //     .unwrap();
-
-                // TODO API to wait for all jobs to be completed and all events processed
-                Delay::new(Duration::from_millis(500)).await;
-
-                let result = service
-                    .exec_query("SELECT count(*) from foo.numbers")
+                let values = (0..2000).map(|i| format!("({}, {})", i, i)).join(", ");
+                service
+                    .exec_query(&format!("INSERT INTO s.Data (id, n) VALUES {}", values))
                     .await
                     .unwrap();
-                assert_eq!(result.get_rows()[0], Row::new(vec![TableValue::Int(10)]));
 
-                let active_partitions = services
-                    .meta_store
-                    .get_active_partitions_by_index_id(1)
+                // Scanned batches and the results fit into the limit.
+                let r = service
+                    .exec_query("SELECT SUM(n) FROM s.Data")
                     .await
                     .unwrap();
-                let last_active_partition = active_partitions.iter().next().unwrap();
-
-                // Wait for GC tasks to drop files
-                Delay::new(Duration::from_millis(3000)).await;
+                assert_eq!(
+                    r.get_rows(),
+                    &vec![Row::new(vec![TableValue::Int(1999000)])]
+                );
 
-                let remote_fs = services.injector.get_service_typed::<dyn RemoteFs>().await;
-                let files = remote_fs
-                    .list("")
+                // A single row is returned, but all 2000 groups are kept by the aggregation. Data
+                // is not sorted by `n`, so hash aggregation is used.
+                let e = service
+                    .exec_query("SELECT n, SUM(id) FROM s.Data GROUP BY 1 LIMIT 1")
                     .await
-                    .unwrap()
-                    .into_iter()
-                    .filter(|r| r.ends_with(".parquet"))
-                    .collect::<Vec<_>>();
-                assert_eq!(
-                    files,
-                    vec![format!(
-                        "{}-{}.parquet",
-                        last_active_partition.get_id(),
-                        last_active_partition.get_row().suffix().as_ref().unwrap()
-                    )]
-                )
+                    .unwrap_err();
+                assert!(
+                    e.message
+                        .contains("Query exceeded memory limit of 100000 bytes in aggregation"),
+                    "{}",
+                    e
+                );
             })
             .await
     }
 
     #[tokio::test]
-    async fn in_memory_compaction() {
-        Config::test("inmemory_compaction")
+    async fn query_memory_limit_hash_join() {
+        Config::test("query_memory_limit_hash_join")
             .update_config(|mut c| {
-                c.partition_split_threshold = 1000000;
-                c.compaction_chunks_count_threshold = 10;
-                c.not_used_timeout = 0;
-                c.compaction_in_memory_chunks_count_threshold = 5;
-                c.compaction_in_memory_chunks_max_lifetime_threshold = 1;
+                c.query_memory_limit_bytes = 200_000;
                 c
             })
             .start_test(async move |services| {
                 let service = services.sql_service;
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
+                service
+                    .exec_query("CREATE TABLE s.Orders (id int, city text)")
+                    .await
+                    .unwrap();
+                service
+                    .exec_query("CREATE TABLE s.Cities (id int, name text)")
+                    .await
+                    .unwrap();
+                // Small inserts keep the scanned batches under the limit, while the hash table
+                // built on all cities doesn't fit into it.
+                let name = |i: i64| format!("{:0>100}", i);
+                for (table, column, inserts) in [("Orders", "city", 41), ("Cities", "name", 40)] {
+                    for i in 0..inserts {
+                        let values = (i * 100..(i + 1) * 100)
+                            .map(|j| format!("({}, '{}')", j, name(j)))
+                            .join(", ");
+                        service
+                            .exec_query(&format!(
+                                "INSERT INTO s.{} (id, {}) VALUES {}",
+                                table, column, values
+                            ))
+                            .await
+                            .unwrap();
+                    }
+                }
 
-                service.exec_query("CREATE SCHEMA foo").await.unwrap();
+                let e = service
+                    .exec_query(
+                        "SELECT COUNT(*) FROM s.Orders o JOIN s.Cities c ON o.city = c.name",
+                    )
+                    .await
+                    .unwrap_err();
+                assert!(
+                    e.message
+                        .contains("Query exceeded memory limit of 200000 bytes in hash join"),
+                    "{}",
+                    e
+                );
+            })
+            .await
+    }
 
+    #[tokio::test]
+    async fn kill_query() {
+        Config::test("kill_query")
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
                 service
-                    .exec_query("CREATE TABLE foo.numbers (a int, num int) UNIQUE KEY (a)")
+                    .exec_query("CREATE TABLE s.Data (id int, n int)")
+                    .await
+                    .unwrap();
+                service
+                    .exec_query("INSERT INTO s.Data (id, n) VALUES (1, 1), (2, 2), (1, 3)")
                     .await
                     .unwrap();
 
-                for i in 0..6 {
-                    service
-                        .exec_query(&format!(
-                            "INSERT INTO foo.numbers (a, num, __seq) VALUES ({}, {}, {})",
-                            i, i, i
-                        ))
-                        .await
-                        .unwrap();
-                }
+                let e = service.exec_query("KILL QUERY 42").await.unwrap_err();
+                assert_eq!(e.message, "No running query on connection 42");
 
-                Delay::new(Duration::from_millis(500)).await;
+                let r = service
+                    .exec_query_with_context(
+                        SqlQueryContext {
+                            connection_id: Some(42),
+                            ..SqlQueryContext::default()
+                        },
+                        "SELECT CONNECTION_ID()",
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    r.get_rows(),
+                    &vec![Row::new(vec![TableValue::String("42".to_string())])]
+                );
 
-                let active_partitions = services
-                    .meta_store
-                    .get_active_partitions_by_index_id(1)
+                let context = SqlQueryContext {
+                    connection_id: Some(42),
+                    ..SqlQueryContext::default()
+                };
+                context.cancel_token.cancel();
+                let e = service
+                    .exec_query_with_context(context, "SELECT id, SUM(n) FROM s.Data GROUP BY 1")
+                    .await
+                    .unwrap_err();
+                assert_eq!(e, query_cancelled_error());
+
+                let r = service
+                    .exec_query("SELECT id, SUM(n) FROM s.Data GROUP BY 1 ORDER BY 1")
                     .await
                     .unwrap();
-                assert_eq!(active_partitions.len(), 1);
-                let partition = active_partitions.first().unwrap();
-                assert_eq!(partition.get_row().main_table_row_count(), 0);
-                let chunks = services
-                    .meta_store
-                    .get_chunks_by_partition(partition.get_id(), false)
+                assert_eq!(
+                    r.get_rows(),
+                    &vec![
+                        Row::new(vec![TableValue::Int(1), TableValue::Int(4)]),
+                        Row::new(vec![TableValue::Int(2), TableValue::Int(2)]),
+                    ]
+                );
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn system_query_log() {
+        Config::test("system_query_log")
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                service.exec_query("CREATE SCHEMA s").await.unwrap();
+                service
+                    .exec_query("CREATE TABLE s.Data (id int, n int)")
                     .await
                     .unwrap();
-                assert_eq!(chunks.len(), 1);
-                assert_eq!(chunks.first().unwrap().get_row().get_row_count(), 6);
-                //waiting for more then compaction_chunks_count_threshold
-                Delay::new(Duration::from_millis(2000)).await;
                 service
-                    .exec_query(&format!(
-                        "INSERT INTO foo.numbers (a, num, __seq) VALUES ({}, {}, {})",
-                        7, 7, 7
-                    ))
+                    .exec_query("INSERT INTO s.Data (id, n) VALUES (1, 1), (2, 2), (1, 3)")
                     .await
                     .unwrap();
-                Delay::new(Duration::from_millis(1000)).await;
-                let active_partitions = services
-                    .meta_store
-                    .get_active_partitions_by_index_id(1)
+
+                let query = "SELECT id, SUM(n) FROM s.Data GROUP BY 1";
+                service.exec_query(query).await.unwrap();
+                service
+                    .exec_query_with_context(
+                        SqlQueryContext::default().with_trace_obj(Some("{}".to_string())),
+                        query,
+                    )
                     .await
                     .unwrap();
-                assert_eq!(active_partitions.len(), 1);
-                let partition = active_partitions.first().unwrap();
-                assert_eq!(partition.get_row().main_table_row_count(), 6);
-                let chunks = services
-                    .meta_store
-                    .get_chunks_by_partition(partition.get_id(), false)
+
+                let r = service
+                    .exec_query(
+                        "SELECT query, trace_obj, status, rows, workers, cache_hit \
+                         FROM system.query_log ORDER BY id",
+                    )
                     .await
                     .unwrap();
-                assert_eq!(chunks.len(), 1);
-                assert_eq!(chunks.first().unwrap().get_row().get_row_count(), 1);
+                // Second query could miss the cache if compaction has happened in between.
+                let rows = r
+                    .get_rows()
+                    .iter()
+                    .map(|r| r.values()[0..5].to_vec())
+                    .collect::<Vec<_>>();
+                let row = |trace_obj: TableValue| {
+                    vec![
+                        TableValue::String(query.to_string()),
+                        trace_obj,
+                        TableValue::String("Success".to_string()),
+                        TableValue::Int(2),
+                        TableValue::Int(1),
+                    ]
+                };
+                assert_eq!(
+                    rows,
+                    vec![
+                        row(TableValue::Null),
+                        row(TableValue::String("{}".to_string())),
+                    ]
+                );
+                assert_eq!(r.get_rows()[0].values()[5], TableValue::Boolean(false));
+
+                let r = service
+                    .exec_query("SELECT id FROM system.queries")
+                    .await
+                    .unwrap();
+                assert_eq!(r.get_rows(), &vec![]);
             })
             .await
     }
@@ -3001,7 +4861,7 @@ mod tests {
                     .values()[2] {
                         TableValue::String(pp_plan) => {
                             let regex = Regex::new(
-                                r"PartialHas+hAggregate\s+Filter\s+Merge\s+Scan, index: default:1:\[1\], fields+: \[platform, age, amount\]\s+ParquetScan, files+: .*\.chunk\.parquet"
+                                r"PartialHas+hAggregate\s+Filter\s+Merge\s+Scan, index: default:1:\[1\], fields+: \[platform, age, amount\], pruned: 0/1 partitions, 0/1 chunks\s+ParquetScan, files+: .*\.chunk\.parquet"
                             ).unwrap();
                             let matches = regex.captures_iter(&pp_plan).count();
                             assert_eq!(matches, 1);
@@ -3121,10 +4981,121 @@ mod tests {
             })
             .await;
     }
+
+    #[tokio::test]
+    async fn rewrite_rows_across_partitions() {
+        async fn active_partitions(meta_store: &Arc<dyn MetaStore>) -> Vec<IdRow<Partition>> {
+            let table = meta_store
+                .get_table("foo".to_string(), "numbers".to_string())
+                .await
+                .unwrap();
+            let index = meta_store
+                .get_table_indexes(table.get_id())
+                .await
+                .unwrap()
+                .remove(0);
+            let mut partitions = meta_store
+                .get_active_partitions_by_index_id(index.get_id())
+                .await
+                .unwrap();
+            partitions.sort_by(|a, b| {
+                cmp_min_rows(
+                    1,
+                    a.get_row().get_min_val().as_ref(),
+                    b.get_row().get_min_val().as_ref(),
+                )
+            });
+            partitions
+        }
+
+        Config::test("rewrite_rows_across_partitions")
+            .update_config(|mut c| {
+                c.partition_split_threshold = 2;
+                c
+            })
+            .start_test(async move |services| {
+                let service = services.sql_service;
+                let compaction_service = services
+                    .injector
+                    .get_service_typed::<dyn CompactionService>()
+                    .await;
+                service.exec_query("CREATE SCHEMA foo").await.unwrap();
+                service
+                    .exec_query("CREATE TABLE foo.numbers (num int)")
+                    .await
+                    .unwrap();
+                service
+                    .exec_query("INSERT INTO foo.numbers (num) VALUES (1), (2), (3), (4)")
+                    .await
+                    .unwrap();
+                for p in active_partitions(&services.meta_store).await {
+                    compaction_service.compact(p.get_id()).await.unwrap();
+                }
+                assert_eq!(active_partitions(&services.meta_store).await.len(), 2);
+
+                // The updated row moves from the first partition to the last one.
+                service
+                    .exec_query("UPDATE foo.numbers SET num = num + 10 WHERE num = 1")
+                    .await
+                    .unwrap();
+                let result = service
+                    .exec_query("SELECT num FROM foo.numbers ORDER BY num")
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    result.get_rows(),
+                    &vec![
+                        Row::new(vec![TableValue::Int(2)]),
+                        Row::new(vec![TableValue::Int(3)]),
+                        Row::new(vec![TableValue::Int(4)]),
+                        Row::new(vec![TableValue::Int(11)]),
+                    ]
+                );
+
+                // Nothing is applied if any of the partitions changes after it was rewritten.
+                let partitions = active_partitions(&services.meta_store).await;
+                let mut rewrites = Vec::new();
+                for p in partitions.iter() {
+                    rewrites.push(
+                        compaction_service
+                            .prepare_rows_deletion(p.get_id(), "TRUE".to_string())
+                            .await
+                            .unwrap(),
+                    );
+                }
+                service
+                    .exec_query("INSERT INTO foo.numbers (num) VALUES (20)")
+                    .await
+                    .unwrap();
+                compaction_service
+                    .compact(partitions.last().unwrap().get_id())
+                    .await
+                    .unwrap();
+                services
+                    .meta_store
+                    .swap_rewritten_partitions(rewrites, Vec::new())
+                    .await
+                    .unwrap_err();
+                let result = service
+                    .exec_query("SELECT count(*) FROM foo.numbers")
+                    .await
+                    .unwrap();
+                assert_eq!(result.get_rows(), &vec![Row::new(vec![TableValue::Int(5)])]);
+            })
+            .await;
+    }
+}
+
+/// Connections that are not made over the MySQL protocol have no id.
+fn connection_id_value(context: &SqlQueryContext) -> TableValue {
+    match context.connection_id {
+        Some(id) => TableValue::String(id.to_string()),
+        None => TableValue::Null,
+    }
 }
 
 impl SqlServiceImpl {
-    fn handle_workbench_queries(q: &str) -> Option<DataFrame> {
+    fn handle_workbench_queries(q: &str, context: &SqlQueryContext) -> Option<DataFrame> {
         if q == "SHOW SESSION VARIABLES LIKE 'lower_case_table_names'" {
             return Some(DataFrame::new(
                 vec![
@@ -3156,25 +5127,23 @@ impl SqlServiceImpl {
             ));
         }
         if q.to_lowercase() == "select connection_id()" {
-            // TODO
             return Some(DataFrame::new(
                 vec![Column::new(
                     "connection_id".to_string(),
                     ColumnType::String,
                     0,
                 )],
-                vec![Row::new(vec![TableValue::String("1".to_string())])],
+                vec![Row::new(vec![connection_id_value(context)])],
             ));
         }
         if q.to_lowercase() == "select connection_id() as connectionid" {
-            // TODO
             return Some(DataFrame::new(
                 vec![Column::new(
                     "connectionId".to_string(),
                     ColumnType::String,
                     0,
                 )],
-                vec![Row::new(vec![TableValue::String("1".to_string())])],
+                vec![Row::new(vec![connection_id_value(context)])],
             ));
         }
         if q.to_lowercase() == "set character set utf8" {
@@ -1,5 +1,6 @@
 use sqlparser::ast::{
-    HiveDistributionStyle, Ident, ObjectName, Query, SqlOption, Statement as SQLStatement, Value,
+    DataType, Expr, HiveDistributionStyle, Ident, ObjectName, Query, SqlOption,
+    Statement as SQLStatement, Value,
 };
 use sqlparser::dialect::keywords::Keyword;
 use sqlparser::dialect::Dialect;
@@ -54,8 +55,22 @@ pub enum Statement {
         credentials: Vec<SqlOption>,
         or_update: bool,
     },
+    AlterTable {
+        table_name: ObjectName,
+        command: AlterTableCommand,
+    },
     System(SystemCommand),
     Dump(Box<Query>),
+    KillQuery {
+        connection_id: u32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterTableCommand {
+    Seal,
+    Pause,
+    Resume,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -63,6 +78,8 @@ pub enum SystemCommand {
     KillAllJobs,
     Repartition { partition_id: u64 },
     PanicWorker,
+    BackupMetaStore,
+    RestoreMetaStore { point: String },
 }
 
 pub struct CubeStoreParser<'a> {
@@ -90,6 +107,14 @@ impl<'a> CubeStoreParser<'a> {
                     self.parser.next_token();
                     self.parse_create()
                 }
+                Keyword::ALTER => {
+                    self.parser.next_token();
+                    self.parse_alter()
+                }
+                _ if w.value.eq_ignore_ascii_case("kill") => {
+                    self.parser.next_token();
+                    self.parse_kill()
+                }
                 _ if w.value.eq_ignore_ascii_case("dump") => {
                     self.parser.next_token();
                     let s = self.parser.parse_statement()?;
@@ -109,6 +134,14 @@ impl<'a> CubeStoreParser<'a> {
         }
     }
 
+    pub fn parse_expr(&mut self) -> Result<Expr, ParserError> {
+        self.parser.parse_expr()
+    }
+
+    pub fn parse_data_type(&mut self) -> Result<DataType, ParserError> {
+        self.parser.parse_data_type()
+    }
+
     pub fn parse_create(&mut self) -> Result<Statement, ParserError> {
         if self.parser.parse_keyword(Keyword::SCHEMA) {
             self.parse_create_schema()
@@ -123,6 +156,51 @@ impl<'a> CubeStoreParser<'a> {
         }
     }
 
+    pub fn parse_alter(&mut self) -> Result<Statement, ParserError> {
+        if self.parser.parse_keyword(Keyword::TABLE) {
+            let table_name = self.parser.parse_object_name()?;
+            let command = if self.parse_custom_token("seal") {
+                Some(AlterTableCommand::Seal)
+            } else if self.parse_custom_token("pause") {
+                Some(AlterTableCommand::Pause)
+            } else if self.parse_custom_token("resume") {
+                Some(AlterTableCommand::Resume)
+            } else {
+                None
+            };
+            if let Some(command) = command {
+                return Ok(Statement::AlterTable {
+                    table_name,
+                    command,
+                });
+            }
+            // Rewind table name with its separators and `TABLE` keyword for the generic parser.
+            for _ in 0..table_name.0.len() * 2 {
+                self.parser.prev_token();
+            }
+        }
+        Ok(Statement::Statement(self.parser.parse_alter()?))
+    }
+
+    fn parse_kill(&mut self) -> Result<Statement, ParserError> {
+        if !self.parse_custom_token("query") {
+            return Err(ParserError::ParserError(
+                "Only KILL QUERY is supported".to_string(),
+            ));
+        }
+        match self.parser.parse_number_value()? {
+            Value::Number(id, _) => Ok(Statement::KillQuery {
+                connection_id: id.parse::<u32>().map_err(|e| {
+                    ParserError::ParserError(format!("Can't parse connection id: {}", e))
+                })?,
+            }),
+            x => Err(ParserError::ParserError(format!(
+                "Connection id expected but {:?} found",
+                x
+            ))),
+        }
+    }
+
     fn parse_system(&mut self) -> Result<Statement, ParserError> {
         if self.parse_custom_token("kill")
             && self.parser.parse_keywords(&[Keyword::ALL])
@@ -143,6 +221,15 @@ impl<'a> CubeStoreParser<'a> {
             }
         } else if self.parse_custom_token("panic") && self.parse_custom_token("worker") {
             Ok(Statement::System(SystemCommand::PanicWorker))
+        } else if self.parse_custom_token("backup") && self.parse_custom_token("metastore") {
+            Ok(Statement::System(SystemCommand::BackupMetaStore))
+        } else if self.parse_custom_token("restore")
+            && self.parse_custom_token("metastore")
+            && self.parser.parse_keyword(Keyword::TO)
+        {
+            Ok(Statement::System(SystemCommand::RestoreMetaStore {
+                point: self.parser.parse_literal_string()?,
+            }))
         } else {
             Err(ParserError::ParserError(
                 "Unknown system command".to_string(),
@@ -389,4 +476,57 @@ mod tests {
             _ => {}
         }
     }
+
+    #[test]
+    fn parse_alter_table_state() {
+        for (query, expected) in [
+            ("ALTER TABLE foo.Orders SEAL", AlterTableCommand::Seal),
+            ("alter table foo.Orders pause", AlterTableCommand::Pause),
+            (
+                "ALTER TABLE `foo`.`Orders` RESUME;",
+                AlterTableCommand::Resume,
+            ),
+        ] {
+            let mut parser = CubeStoreParser::new(&query).unwrap();
+            match parser.parse_statement().unwrap() {
+                Statement::AlterTable {
+                    table_name,
+                    command,
+                } => {
+                    let names = table_name
+                        .0
+                        .iter()
+                        .map(|i| i.value.as_str())
+                        .collect::<Vec<_>>();
+                    assert_eq!(names, vec!["foo", "Orders"]);
+                    assert_eq!(command, expected);
+                }
+                s => panic!("Unexpected statement: {:?}", s),
+            }
+        }
+
+        let mut parser = CubeStoreParser::new("ALTER TABLE foo.Orders ADD COLUMN bar int").unwrap();
+        match parser.parse_statement().unwrap() {
+            Statement::Statement(SQLStatement::AlterTable { name, .. }) => {
+                assert_eq!(name.to_string(), "foo.Orders");
+            }
+            s => panic!("Unexpected statement: {:?}", s),
+        }
+    }
+
+    #[test]
+    fn parse_kill_query() {
+        let mut parser = CubeStoreParser::new("KILL QUERY 12").unwrap();
+        assert_eq!(
+            parser.parse_statement().unwrap(),
+            Statement::KillQuery { connection_id: 12 }
+        );
+        let mut parser = CubeStoreParser::new("kill query 3;").unwrap();
+        assert_eq!(
+            parser.parse_statement().unwrap(),
+            Statement::KillQuery { connection_id: 3 }
+        );
+        let mut parser = CubeStoreParser::new("KILL CONNECTION 12").unwrap();
+        assert!(parser.parse_statement().is_err());
+    }
 }
@@ -0,0 +1,324 @@
+use crate::cluster::query_cancelled_error;
+use crate::queryplanner::serialized_plan::SerializedPlan;
+use crate::sql::SqlQueryContext;
+use crate::store::DataFrame;
+use crate::CubeError;
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Utc};
+use datafusion::physical_plan::{RecordBatchStream, SendableRecordBatchStream};
+use futures::{ready, Stream, StreamExt};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueryStatus {
+    Running,
+    Success,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct QueryLogEntry {
+    pub id: u64,
+    pub query: String,
+    pub trace_obj: Option<String>,
+    pub connection_id: Option<u32>,
+    pub start_time: DateTime<Utc>,
+    /// Not set while the query is running.
+    pub duration: Option<Duration>,
+    pub status: QueryStatus,
+    pub error: Option<String>,
+    pub rows: Option<u64>,
+    /// Estimated from the file sizes of partitions and chunks the query reads.
+    /// Data statistics are reset to zero when the result is taken from the cache.
+    pub bytes_scanned: u64,
+    pub partitions: u64,
+    pub chunks: u64,
+    pub workers: u64,
+    /// Whether the result was taken from the result cache. Not set while the query is running.
+    pub cache_hit: Option<bool>,
+}
+
+/// Statistics of data selects processed by this router. Backs `system.queries`, which lists
+/// running queries, and `system.query_log`, which keeps the last completed ones.
+pub struct QueryLog {
+    capacity: usize,
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, QueryLogEntry>>,
+    completed: Mutex<VecDeque<QueryLogEntry>>,
+}
+
+crate::di_service!(QueryLog, []);
+
+impl QueryLog {
+    pub fn new(capacity: usize) -> Arc<QueryLog> {
+        Arc::new(QueryLog {
+            capacity,
+            next_id: AtomicU64::new(1),
+            running: Mutex::new(HashMap::new()),
+            completed: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    pub fn start(
+        self: &Arc<Self>,
+        query: &str,
+        context: &SqlQueryContext,
+        plan: &SerializedPlan,
+        workers: &[String],
+    ) -> LoggedQuery {
+        let mut partitions = HashSet::new();
+        let mut chunks = HashSet::new();
+        let mut bytes_scanned = 0;
+        for index in plan.index_snapshots().iter() {
+            for p in index.partitions.iter() {
+                if partitions.insert(p.partition.get_id()) {
+                    bytes_scanned += p.partition.get_row().file_size().unwrap_or(0);
+                }
+                for c in p.chunks.iter() {
+                    if chunks.insert(c.get_id()) {
+                        bytes_scanned += c.get_row().file_size().unwrap_or(0);
+                    }
+                }
+            }
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = QueryLogEntry {
+            id,
+            query: query.to_string(),
+            trace_obj: context.trace_obj.clone(),
+            connection_id: context.connection_id,
+            start_time: Utc::now(),
+            duration: None,
+            status: QueryStatus::Running,
+            error: None,
+            rows: None,
+            bytes_scanned,
+            partitions: partitions.len() as u64,
+            chunks: chunks.len() as u64,
+            workers: workers.len() as u64,
+            cache_hit: None,
+        };
+        self.running.lock().unwrap().insert(id, entry);
+        LoggedQuery {
+            log: self.clone(),
+            id,
+            start: Instant::now(),
+            finished: false,
+        }
+    }
+
+    pub fn running(&self) -> Vec<QueryLogEntry> {
+        let mut res = self
+            .running
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        res.sort_by_key(|e| e.id);
+        res
+    }
+
+    /// Completed queries, oldest first.
+    pub fn completed(&self) -> Vec<QueryLogEntry> {
+        self.completed.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn complete(&self, id: u64, update: impl FnOnce(&mut QueryLogEntry)) {
+        let entry = self.running.lock().unwrap().remove(&id);
+        let mut entry = match entry {
+            Some(e) => e,
+            None => return,
+        };
+        update(&mut entry);
+        if self.capacity == 0 {
+            return;
+        }
+        let mut completed = self.completed.lock().unwrap();
+        if completed.len() == self.capacity {
+            completed.pop_front();
+        }
+        completed.push_back(entry);
+    }
+}
+
+/// Moves the query from `system.queries` to `system.query_log` once finished or dropped.
+pub struct LoggedQuery {
+    log: Arc<QueryLog>,
+    id: u64,
+    start: Instant,
+    finished: bool,
+}
+
+impl LoggedQuery {
+    pub fn finish(self, result: &Result<Arc<DataFrame>, CubeError>, cache_hit: bool) {
+        let rows = result.as_ref().map(|df| df.get_rows().len() as u64);
+        self.finish_rows(rows, cache_hit)
+    }
+
+    /// Same as [finish] for results that are not collected into a data frame.
+    pub fn finish_rows(mut self, result: Result<u64, &CubeError>, cache_hit: bool) {
+        self.finished = true;
+        let duration = self.start.elapsed();
+        self.log.complete(self.id, |e| {
+            e.duration = Some(duration);
+            e.cache_hit = Some(cache_hit);
+            if cache_hit {
+                // Nothing was read by the query.
+                e.bytes_scanned = 0;
+                e.partitions = 0;
+                e.chunks = 0;
+                e.workers = 0;
+            }
+            match result {
+                Ok(rows) => {
+                    e.status = QueryStatus::Success;
+                    e.rows = Some(rows);
+                }
+                Err(err) => {
+                    e.status = QueryStatus::Error;
+                    e.error = Some(err.message.clone());
+                }
+            }
+        });
+    }
+}
+
+impl Drop for LoggedQuery {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let duration = self.start.elapsed();
+        self.log.complete(self.id, |e| {
+            e.duration = Some(duration);
+            e.status = QueryStatus::Error;
+            e.error = Some(query_cancelled_error().message);
+        });
+    }
+}
+
+/// Logs the query once all record batches of `input` are read. If the stream is dropped before
+/// that, the query is logged as cancelled and its remaining work is stopped.
+pub struct LoggedStream {
+    input: SendableRecordBatchStream,
+    query: Option<LoggedQuery>,
+    rows: u64,
+    cancel_token: CancellationToken,
+}
+
+impl LoggedStream {
+    pub fn new(
+        input: SendableRecordBatchStream,
+        query: LoggedQuery,
+        cancel_token: CancellationToken,
+    ) -> Self {
+        Self {
+            input,
+            query: Some(query),
+            rows: 0,
+            cancel_token,
+        }
+    }
+}
+
+impl Stream for LoggedStream {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.query.is_none() {
+            return Poll::Ready(None);
+        }
+        let r = ready!(self.input.poll_next_unpin(cx));
+        match &r {
+            Some(Ok(batch)) => self.rows += batch.num_rows() as u64,
+            Some(Err(e)) => {
+                let e = CubeError::internal(e.to_string());
+                self.query.take().unwrap().finish_rows(Err(&e), false)
+            }
+            None => {
+                let rows = self.rows;
+                self.query.take().unwrap().finish_rows(Ok(rows), false)
+            }
+        }
+        Poll::Ready(r)
+    }
+}
+
+impl RecordBatchStream for LoggedStream {
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+}
+
+impl Drop for LoggedStream {
+    fn drop(&mut self) {
+        self.cancel_token.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queryplanner::PlanningMeta;
+    use crate::table::{Row, TableValue};
+    use datafusion::logical_plan::{DFSchema, LogicalPlan};
+
+    #[tokio::test]
+    async fn log() -> Result<(), CubeError> {
+        let plan = SerializedPlan::try_new(
+            LogicalPlan::EmptyRelation {
+                produce_one_row: false,
+                schema: Arc::new(DFSchema::new(Vec::new())?),
+            },
+            PlanningMeta {
+                indices: Vec::new(),
+                multi_part_subtree: HashMap::new(),
+            },
+        )
+        .await?;
+        let log = QueryLog::new(2);
+        let context = SqlQueryContext::default();
+        let workers = vec!["w1".to_string(), "w2".to_string()];
+
+        let q1 = log.start("SELECT 1", &context, &plan, &workers);
+        let q2 = log.start("SELECT 2", &context, &plan, &workers);
+        let running = log.running();
+        assert_eq!(running.len(), 2);
+        assert_eq!(running[0].query, "SELECT 1");
+        assert_eq!(running[0].status, QueryStatus::Running);
+        assert_eq!(running[0].workers, 2);
+
+        let df = DataFrame::new(Vec::new(), vec![Row::new(vec![TableValue::Int(1)])]);
+        q1.finish(&Ok(Arc::new(df)), true);
+        drop(q2);
+        log.start("SELECT 3", &context, &plan, &workers)
+            .finish(&Err(CubeError::user("Failed".to_string())), false);
+
+        assert!(log.running().is_empty());
+        let completed = log.completed();
+        assert_eq!(completed.len(), 2);
+        assert_eq!(completed[0].query, "SELECT 2");
+        assert_eq!(completed[0].status, QueryStatus::Error);
+        assert_eq!(completed[0].cache_hit, None);
+        assert_eq!(completed[1].query, "SELECT 3");
+        assert_eq!(completed[1].error, Some("Failed".to_string()));
+        assert_eq!(completed[1].cache_hit, Some(false));
+        assert_eq!(completed[1].workers, 2);
+
+        log.start("SELECT 3", &context, &plan, &workers)
+            .finish(&Ok(Arc::new(DataFrame::new(Vec::new(), Vec::new()))), true);
+        let completed = log.completed();
+        assert_eq!(completed[1].cache_hit, Some(true));
+        assert_eq!(completed[1].workers, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::sql::SqlQueryContext;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// Keeps cancellation tokens of the queries running on client connections, so they can be
+/// stopped with `KILL QUERY <connection id>`.
+pub struct RunningQueries {
+    queries: Mutex<HashMap<u32, CancellationToken>>,
+}
+
+impl RunningQueries {
+    pub fn new() -> Self {
+        Self {
+            queries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The query is cancelled when the client connection is closed, the query is killed or the
+    /// returned value is dropped.
+    pub fn start(&self, context: &SqlQueryContext) -> RunningQuery<'_> {
+        let cancel_token = context.cancel_token.child_token();
+        if let Some(id) = context.connection_id {
+            self.queries
+                .lock()
+                .unwrap()
+                .insert(id, cancel_token.clone());
+        }
+        RunningQuery {
+            queries: self,
+            connection_id: context.connection_id,
+            cancel_token,
+        }
+    }
+
+    /// Returns false if there is no running query on the connection.
+    pub fn kill(&self, connection_id: u32) -> bool {
+        match self.queries.lock().unwrap().remove(&connection_id) {
+            Some(t) => {
+                t.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct RunningQuery<'a> {
+    queries: &'a RunningQueries,
+    connection_id: Option<u32>,
+    cancel_token: CancellationToken,
+}
+
+impl RunningQuery<'_> {
+    pub fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel_token
+    }
+}
+
+impl Drop for RunningQuery<'_> {
+    fn drop(&mut self) {
+        // Stops the remaining work on workers when the query has failed or timed out.
+        self.cancel_token.cancel();
+        if let Some(id) = self.connection_id {
+            let mut queries = self.queries.queries.lock().unwrap();
+            if queries.get(&id).map(|t| t.is_cancelled()).unwrap_or(false) {
+                queries.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kill() {
+        let queries = RunningQueries::new();
+        let context = SqlQueryContext {
+            connection_id: Some(1),
+            ..SqlQueryContext::default()
+        };
+        let q = queries.start(&context);
+        assert!(!queries.kill(2));
+        assert!(queries.kill(1));
+        assert!(q.cancel_token().is_cancelled());
+        assert!(!queries.kill(1));
+        drop(q);
+
+        let q = queries.start(&context);
+        drop(q);
+        assert!(!queries.kill(1));
+
+        let q = queries.start(&context);
+        context.cancel_token.cancel();
+        assert!(q.cancel_token().is_cancelled());
+    }
+}
@@ -5,33 +5,42 @@ use crate::metastore::partition::partition_file_name;
 use crate::metastore::table::AggregateColumn;
 use crate::metastore::{
     deactivate_table_on_corrupt_data, Chunk, IdRow, IndexType, MetaStore, Partition, PartitionData,
+    PartitionRewrite,
 };
+use crate::queryplanner::plan_filter_expr;
+use crate::remotefs::cache::FilePin;
 use crate::remotefs::{ensure_temp_file_is_dropped, RemoteFs};
+use crate::sql::parser::CubeStoreParser;
 use crate::store::{ChunkDataStore, ChunkStore, ROW_GROUP_SIZE};
-use crate::table::data::{cmp_min_rows, cmp_partition_key};
-use crate::table::parquet::{arrow_schema, ParquetTableStore};
+use crate::table::data::{cmp_min_rows, cmp_partition_key, evolve_plan};
+use crate::table::parquet::{arrow_schema, ParquetTableStore, ParquetWriterOptions};
 use crate::table::redistribute::redistribute;
+use crate::table::stats::{ColumnStats, ColumnStatsBuilder};
 use crate::table::{Row, TableValue};
 use crate::CubeError;
-use arrow::array::{ArrayRef, UInt64Array};
+use arrow::array::{ArrayRef, BooleanArray, UInt64Array};
 use arrow::compute::{lexsort_to_indices, SortColumn, SortOptions};
-use arrow::datatypes::DataType;
+use arrow::datatypes::{DataType, Schema as ArrowSchema};
 use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
 use chrono::Utc;
 use datafusion::cube_ext;
+use datafusion::execution::context::ExecutionContext;
+use datafusion::logical_plan::{Expr, ToDFSchema};
 use datafusion::physical_plan::common::collect;
 use datafusion::physical_plan::empty::EmptyExec;
 use datafusion::physical_plan::expressions::{Column, Count, Literal};
+use datafusion::physical_plan::filter::FilterExec;
 use datafusion::physical_plan::hash_aggregate::{
     AggregateMode, AggregateStrategy, HashAggregateExec,
 };
 use datafusion::physical_plan::memory::MemoryExec;
 use datafusion::physical_plan::merge_sort::{LastRowByUniqueKeyExec, MergeSortExec};
 use datafusion::physical_plan::parquet::ParquetExec;
+use datafusion::physical_plan::planner::DefaultPhysicalPlanner;
 use datafusion::physical_plan::union::UnionExec;
 use datafusion::physical_plan::{
-    AggregateExpr, ExecutionPlan, PhysicalExpr, SendableRecordBatchStream,
+    AggregateExpr, ExecutionPlan, PhysicalExpr, PhysicalPlanner, SendableRecordBatchStream,
 };
 use datafusion::scalar::ScalarValue;
 use futures::StreamExt;
@@ -48,6 +57,15 @@ use tokio::task::JoinHandle;
 #[async_trait]
 pub trait CompactionService: DIService + Send + Sync {
     async fn compact(&self, partition_id: u64) -> Result<(), CubeError>;
+    /// Rewrites the partition along with all its chunks without rows matching the SQL `predicate`.
+    async fn delete_rows(&self, partition_id: u64, predicate: String) -> Result<(), CubeError>;
+    /// Same as `delete_rows`, but the rewritten partition isn't activated. It's applied along with
+    /// rewrites of other partitions by [MetaStore::swap_rewritten_partitions].
+    async fn prepare_rows_deletion(
+        &self,
+        partition_id: u64,
+        predicate: String,
+    ) -> Result<PartitionRewrite, CubeError>;
     async fn compact_in_memory_chunks(&self, partition_id: u64) -> Result<(), CubeError>;
     /// Split multi-partition that has too many rows. Figures out the keys based on stored data.
     async fn split_multi_partition(&self, multi_partition_id: u64) -> Result<(), CubeError>;
@@ -82,22 +100,41 @@ impl CompactionServiceImpl {
             config,
         })
     }
-}
 
-#[async_trait]
-impl CompactionService for CompactionServiceImpl {
-    async fn compact(&self, partition_id: u64) -> Result<(), CubeError> {
+    /// Merges chunks into the partition. Rows matching `delete_predicate` are dropped if it's set,
+    /// in this case all chunks are merged regardless of compaction thresholds and the rewritten
+    /// partition is returned instead of being activated. The rewrite has no new partitions if
+    /// there is nothing to delete.
+    async fn compact_partition(
+        &self,
+        partition_id: u64,
+        delete_predicate: Option<&str>,
+    ) -> Result<Option<PartitionRewrite>, CubeError> {
         let (partition, index, table, multi_part) = self
             .meta_store
             .get_partition_for_compaction(partition_id)
             .await?;
 
+        if delete_predicate.is_some() {
+            if multi_part.is_some() {
+                return Err(CubeError::user(format!(
+                    "Can't delete rows from partition {} of partitioned index",
+                    partition_id
+                )));
+            }
+            if !partition.get_row().is_active() {
+                return Err(CubeError::user(format!(
+                    "Partition {} was compacted concurrently with rows deletion. Please retry.",
+                    partition_id
+                )));
+            }
+        }
         if !partition.get_row().is_active() && !multi_part.is_some() {
             log::trace!(
                 "Cannot compact inactive partition: {:?}",
                 partition.get_row()
             );
-            return Ok(());
+            return Ok(None);
         }
         if let Some(mp) = &multi_part {
             if mp.get_row().prepared_for_split() {
@@ -105,7 +142,7 @@ impl CompactionService for CompactionServiceImpl {
                     "Cancelled compaction of {}. It runs concurrently with multi-split",
                     partition_id
                 );
-                return Ok(());
+                return Ok(None);
             }
         }
         let mut all_pending_chunks = self
@@ -117,7 +154,8 @@ impl CompactionService for CompactionServiceImpl {
         let chunks = all_pending_chunks
             .iter()
             .filter(|c| {
-                !c.get_row().in_memory()
+                delete_predicate.is_some()
+                    || !c.get_row().in_memory()
                     || c.get_row().get_row_count()
                         > self.config.compaction_in_memory_chunks_size_limit()
                     || c.get_row()
@@ -132,7 +170,7 @@ impl CompactionService for CompactionServiceImpl {
                         .unwrap_or(false)
             })
             .take_while(|c| {
-                if size == 0 {
+                if size == 0 || delete_predicate.is_some() {
                     size += c.get_row().get_row_count();
                     true
                 } else {
@@ -143,8 +181,15 @@ impl CompactionService for CompactionServiceImpl {
             .map(|c| c.clone())
             .collect::<Vec<_>>();
 
-        if chunks.is_empty() {
-            return Ok(());
+        if chunks.is_empty()
+            && (delete_predicate.is_none() || partition.get_row().main_table_row_count() == 0)
+        {
+            // Nothing to delete, the partition is kept as is.
+            return Ok(delete_predicate.map(|_| PartitionRewrite {
+                current_active: (partition, Vec::new()),
+                new_active: Vec::new(),
+                new_active_min_max: Vec::new(),
+            }));
         }
 
         let partition_id = partition.get_id();
@@ -152,17 +197,24 @@ impl CompactionService for CompactionServiceImpl {
             .iter()
             .map(|c| c.get_row().get_row_count())
             .sum::<u64>();
+        let writer_options = ParquetWriterOptions::new(table.get_row(), self.config.as_ref());
         // For multi-partitions, we only compact chunks and never change the main table.
         // And we never split, multi-partitions have a different process for that.
         let new_chunk = match &multi_part {
             None => None,
             Some(_) => {
                 if chunks.len() < 2 {
-                    return Ok(());
+                    return Ok(None);
                 }
                 Some(
                     self.meta_store
-                        .create_chunk(partition_id, chunks_row_count as usize, false)
+                        .create_chunk(
+                            partition_id,
+                            chunks_row_count as usize,
+                            false,
+                            Some(writer_options.compression),
+                            None,
+                        )
                         .await?,
                 )
             }
@@ -212,11 +264,22 @@ impl CompactionService for CompactionServiceImpl {
             }
         }
 
-        let store = ParquetTableStore::new(index.get_row().clone(), ROW_GROUP_SIZE);
+        let store = ParquetTableStore::new(index.get_row().clone(), ROW_GROUP_SIZE)
+            .with_writer_options(writer_options)
+            .with_bloom_filter_columns(
+                table
+                    .get_row()
+                    .bloom_filter_columns()
+                    .clone()
+                    .unwrap_or_default(),
+            );
         let old_partition_remote = match &new_chunk {
             Some(_) => None,
             None => partition.get_row().get_full_name(partition.get_id()),
         };
+        let _old_partition_pin = old_partition_remote
+            .as_ref()
+            .map(|f| self.remote_fs.pin_file(f));
         let old_partition_local = if let Some(f) = old_partition_remote {
             let result = self
                 .remote_fs
@@ -247,6 +310,11 @@ impl CompactionService for CompactionServiceImpl {
         });
 
         let key_size = index.get_row().sort_key_size() as usize;
+        let schema = Arc::new(arrow_schema(index.get_row()));
+        if data.is_empty() {
+            // Deletion rewrites the main table even if there are no chunks.
+            data.push(RecordBatch::new_empty(schema.clone()));
+        }
         let (store, new) = cube_ext::spawn_blocking(move || -> Result<_, CubeError> {
             // Concat rows from all chunks.
             let mut columns = Vec::with_capacity(num_columns);
@@ -277,16 +345,19 @@ impl CompactionService for CompactionServiceImpl {
         .await??;
 
         // Merge and write rows.
-        let schema = Arc::new(arrow_schema(index.get_row()));
         let main_table: Arc<dyn ExecutionPlan> = match old_partition_local {
-            Some(file) => Arc::new(ParquetExec::try_from_path(
-                file.as_str(),
-                None,
-                None,
-                ROW_GROUP_SIZE,
-                1,
-                None,
-            )?),
+            // Partition might be written before the columns of the index were altered.
+            Some(file) => evolve_plan(
+                Arc::new(ParquetExec::try_from_path(
+                    file.as_str(),
+                    None,
+                    None,
+                    ROW_GROUP_SIZE,
+                    1,
+                    None,
+                )?),
+                index.get_row().columns(),
+            )?,
             None => Arc::new(EmptyExec::new(false, schema.clone())),
         };
 
@@ -299,9 +370,43 @@ impl CompactionService for CompactionServiceImpl {
             IndexType::Regular => None,
             IndexType::Aggregate => Some(table.get_row().aggregate_columns()),
         };
-        let records =
-            merge_chunks(key_size, main_table, new, unique_key, aggregate_columns).await?;
-        let count_and_min =
+        let rows_to_keep = delete_predicate
+            .map(|p| rows_to_keep_expr(p, schema.as_ref()))
+            .transpose()?;
+        if let (Some(rows_to_keep), true) = (&rows_to_keep, chunks.is_empty()) {
+            // Only the main table is rewritten, so check first that some of its rows are actually
+            // deleted. Otherwise keep the partition and record its stats, so the next expiration
+            // checks don't have to read it again.
+            let (kept_rows, column_stats) = scan_main_table(
+                main_table.clone(),
+                rows_to_keep.clone(),
+                store.column_stats_builder(),
+            )
+            .await?;
+            if kept_rows == partition.get_row().main_table_row_count() {
+                for p in new_partitions {
+                    self.meta_store.delete_partition(p.get_id()).await?;
+                }
+                self.meta_store
+                    .set_partition_column_stats(partition_id, Some(column_stats))
+                    .await?;
+                return Ok(Some(PartitionRewrite {
+                    current_active: (partition, Vec::new()),
+                    new_active: Vec::new(),
+                    new_active_min_max: Vec::new(),
+                }));
+            }
+        }
+        let records = merge_chunks(
+            key_size,
+            main_table,
+            new,
+            unique_key,
+            aggregate_columns,
+            rows_to_keep,
+        )
+        .await?;
+        let (count_and_min, mut column_stats) =
             write_to_files(records, total_rows as usize, store, new_local_files2).await?;
 
         if let Some(c) = &new_chunk {
@@ -314,7 +419,13 @@ impl CompactionService for CompactionServiceImpl {
             let chunk_ids = chunks.iter().map(|c| c.get_id()).collect_vec();
             let swapped = self
                 .meta_store
-                .swap_compacted_chunks(partition_id, chunk_ids, c.get_id(), file_size)
+                .swap_compacted_chunks(
+                    partition_id,
+                    chunk_ids,
+                    c.get_id(),
+                    file_size,
+                    Some(column_stats.remove(0)),
+                )
                 .await?;
             if !swapped {
                 log::debug!(
@@ -323,7 +434,40 @@ impl CompactionService for CompactionServiceImpl {
                 );
                 self.remote_fs.delete_file(&remote).await?;
             }
-            return Ok(());
+            return Ok(None);
+        }
+
+        if count_and_min.is_empty() {
+            // All rows were deleted. Partition range is kept by an empty partition without a file.
+            let mut new_partitions = new_partitions.into_iter();
+            let empty_partition = new_partitions.next().ok_or_else(|| {
+                CubeError::internal(format!(
+                    "No new partitions created for compaction of {}",
+                    partition_id
+                ))
+            })?;
+            for p in new_partitions {
+                self.meta_store.delete_partition(p.get_id()).await?;
+            }
+            for f in new_local_files.iter() {
+                let _ = tokio::fs::remove_file(f).await;
+            }
+            let min_max = (
+                partition.get_row().get_min_val().clone(),
+                partition.get_row().get_max_val().clone(),
+            );
+            let rewrite = PartitionRewrite {
+                current_active: (partition, chunks),
+                new_active: vec![(empty_partition, 0)],
+                new_active_min_max: vec![(0, min_max)],
+            };
+            if delete_predicate.is_some() {
+                return Ok(Some(rewrite));
+            }
+            self.meta_store
+                .swap_rewritten_partitions(vec![rewrite], Vec::new())
+                .await?;
+            return Ok(None);
         }
 
         let mut filtered_partitions = Vec::new();
@@ -339,6 +483,9 @@ impl CompactionService for CompactionServiceImpl {
                         .remote_fs
                         .upload_file(&new_local_files[i], new_remote_path.as_str())
                         .await?;
+                    self.meta_store
+                        .set_partition_column_stats(p.get_id(), Some(take(&mut column_stats[i])))
+                        .await?;
                     filtered_partitions.push((p, file_size));
                 }
                 EitherOrBoth::Left(p) => {
@@ -359,62 +506,99 @@ impl CompactionService for CompactionServiceImpl {
 
         let partition_min = partition.get_row().get_min_val().clone();
         let partition_max = partition.get_row().get_max_val().clone();
-        self.meta_store
-            .swap_active_partitions(
-                vec![(partition, chunks)],
-                filtered_partitions,
-                count_and_min
-                    .iter()
-                    .zip_longest(count_and_min.iter().skip(1 as usize))
-                    .enumerate()
-                    .map(|(i, item)| -> Result<_, CubeError> {
-                        match item {
-                            EitherOrBoth::Both((c, min), (_, next_min)) => {
-                                if i == 0 && partition_min.is_none() {
-                                    Ok((*c as u64, (None, Some(Row::new(next_min.clone())))))
-                                } else if i < num_filtered - 1 {
-                                    Ok((
-                                        *c as u64,
-                                        (
-                                            Some(Row::new(min.clone())),
-                                            Some(Row::new(next_min.clone())),
-                                        ),
-                                    ))
-                                } else {
-                                    Err(CubeError::internal(format!(
-                                        "Unexpected state for {} new partitions: {}, {:?}",
-                                        num_filtered, i, item
-                                    )))
-                                }
-                            }
-                            EitherOrBoth::Left((c, min)) => {
-                                if i == 0 && num_filtered == 1 {
-                                    Ok((*c as u64, (partition_min.clone(), partition_max.clone())))
-                                } else if i == num_filtered - 1 {
-                                    Ok((
-                                        *c as u64,
-                                        (Some(Row::new(min.clone())), partition_max.clone()),
-                                    ))
-                                } else {
-                                    Err(CubeError::internal(format!(
-                                        "Unexpected state for {} new partitions: {}, {:?}",
-                                        num_filtered, i, item
-                                    )))
-                                }
-                            }
-                            EitherOrBoth::Right(_) => Err(CubeError::internal(format!(
+        let new_min_max = count_and_min
+            .iter()
+            .zip_longest(count_and_min.iter().skip(1 as usize))
+            .enumerate()
+            .map(|(i, item)| -> Result<_, CubeError> {
+                match item {
+                    EitherOrBoth::Both((c, min), (_, next_min)) => {
+                        if i == 0 && partition_min.is_none() {
+                            Ok((*c as u64, (None, Some(Row::new(next_min.clone())))))
+                        } else if i < num_filtered - 1 {
+                            Ok((
+                                *c as u64,
+                                (
+                                    Some(Row::new(min.clone())),
+                                    Some(Row::new(next_min.clone())),
+                                ),
+                            ))
+                        } else {
+                            Err(CubeError::internal(format!(
                                 "Unexpected state for {} new partitions: {}, {:?}",
                                 num_filtered, i, item
-                            ))),
+                            )))
                         }
-                    })
-                    .collect::<Result<Vec<_>, CubeError>>()?,
-            )
+                    }
+                    EitherOrBoth::Left((c, min)) => {
+                        if i == 0 && num_filtered == 1 {
+                            Ok((*c as u64, (partition_min.clone(), partition_max.clone())))
+                        } else if i == num_filtered - 1 {
+                            Ok((
+                                *c as u64,
+                                (Some(Row::new(min.clone())), partition_max.clone()),
+                            ))
+                        } else {
+                            Err(CubeError::internal(format!(
+                                "Unexpected state for {} new partitions: {}, {:?}",
+                                num_filtered, i, item
+                            )))
+                        }
+                    }
+                    EitherOrBoth::Right(_) => Err(CubeError::internal(format!(
+                        "Unexpected state for {} new partitions: {}, {:?}",
+                        num_filtered, i, item
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, CubeError>>()?;
+        if delete_predicate.is_some() {
+            return Ok(Some(PartitionRewrite {
+                current_active: (partition, chunks),
+                new_active: filtered_partitions,
+                new_active_min_max: new_min_max,
+            }));
+        }
+        self.meta_store
+            .swap_active_partitions(vec![(partition, chunks)], filtered_partitions, new_min_max)
             .await?;
 
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl CompactionService for CompactionServiceImpl {
+    async fn compact(&self, partition_id: u64) -> Result<(), CubeError> {
+        self.compact_partition(partition_id, None).await?;
         Ok(())
     }
 
+    async fn delete_rows(&self, partition_id: u64, predicate: String) -> Result<(), CubeError> {
+        let rewrite = self.prepare_rows_deletion(partition_id, predicate).await?;
+        if rewrite.new_active.is_empty() {
+            return Ok(());
+        }
+        self.meta_store
+            .swap_rewritten_partitions(vec![rewrite], Vec::new())
+            .await
+    }
+
+    async fn prepare_rows_deletion(
+        &self,
+        partition_id: u64,
+        predicate: String,
+    ) -> Result<PartitionRewrite, CubeError> {
+        self.compact_partition(partition_id, Some(&predicate))
+            .await?
+            .ok_or_else(|| {
+                CubeError::internal(format!(
+                    "Rows deletion of partition {} wasn't prepared",
+                    partition_id
+                ))
+            })
+    }
+
     async fn compact_in_memory_chunks(&self, partition_id: u64) -> Result<(), CubeError> {
         let (partition, index, table, multi_part) = self
             .meta_store
@@ -502,6 +686,7 @@ impl CompactionService for CompactionServiceImpl {
             in_memory_columns,
             unique_key,
             aggregate_columns,
+            None,
         )
         .await?;
         let batches = collect(batches_stream).await?;
@@ -524,9 +709,21 @@ impl CompactionService for CompactionServiceImpl {
                 )
             })
             .min();
+        let columns = index.get_row().columns().clone();
+        let batch_copy = batch.clone();
+        let column_stats = cube_ext::spawn_blocking(move || {
+            ColumnStats::compute(&columns, batch_copy.columns(), &[])
+        })
+        .await?;
         let new_chunk = self
             .meta_store
-            .create_chunk(partition_id, old_chunks_size as usize, true)
+            .create_chunk(
+                partition_id,
+                old_chunks_size as usize,
+                true,
+                None,
+                Some(column_stats),
+            )
             .await?;
 
         // oldest_insert_at will be used to force compaction
@@ -563,7 +760,7 @@ impl CompactionService for CompactionServiceImpl {
 
         // Find key ranges for new partitions.
         // TODO deactivate corrupt tables
-        let files = download_files(&partitions, self.remote_fs.clone()).await?;
+        let (files, _pins) = download_files(&partitions, self.remote_fs.clone()).await?;
         let keys = find_partition_keys(
             keys_with_counts(&files, key_len).await?,
             key_len,
@@ -608,6 +805,7 @@ impl CompactionService for CompactionServiceImpl {
         let mut s = MultiSplit::new(
             self.meta_store.clone(),
             self.remote_fs.clone(),
+            self.config.clone(),
             keys,
             key_len,
             multi_partition_id,
@@ -647,9 +845,13 @@ impl CompactionService for CompactionServiceImpl {
             .map(|c| c.get_row().min_row().cloned().unwrap())
             .collect_vec();
 
+        // Local copies could have been evicted since the split started.
+        let (_, _pins) =
+            download_files(std::slice::from_ref(&data), self.remote_fs.clone()).await?;
         let mut s = MultiSplit::new(
             self.meta_store.clone(),
             self.remote_fs.clone(),
+            self.config.clone(),
             keys,
             key_len,
             multi_partition_id,
@@ -813,16 +1015,19 @@ fn collect_remote_files(p: &PartitionData, out: &mut Vec<(String, Option<u64>)>)
     }
 }
 
+/// Returns local paths of the files along with pins that keep them in the local cache.
 async fn download_files(
     ps: &[PartitionData],
     fs: Arc<dyn RemoteFs>,
-) -> Result<Vec<String>, CubeError> {
+) -> Result<(Vec<String>, Vec<FilePin>), CubeError> {
     let mut tasks = Vec::new();
+    let mut pins = Vec::new();
     let mut remote_files = Vec::new();
     for p in ps {
         collect_remote_files(p, &mut remote_files);
         for f in &mut remote_files {
             let (f, size) = take(f);
+            pins.push(fs.pin_file(&f));
             let fs = fs.clone();
             tasks.push(cube_ext::spawn(
                 async move { fs.download_file(&f, size).await },
@@ -835,18 +1040,20 @@ async fn download_files(
     for t in tasks {
         results.push(t.await??)
     }
-    Ok(results)
+    Ok((results, pins))
 }
 
 /// Writes [records] into [files], trying to split into equally-sized rows, with an additional
 /// restriction that files must have non-intersecting key ranges.
 /// [records] must be sorted and have exactly [num_rows] rows.
+/// Returns the row count and the first row of each written file, along with the column stats of
+/// all files, including empty ones.
 pub(crate) async fn write_to_files(
     records: SendableRecordBatchStream,
     num_rows: usize,
     store: ParquetTableStore,
     files: Vec<String>,
-) -> Result<Vec<(usize, Vec<TableValue>)>, CubeError> {
+) -> Result<(Vec<(usize, Vec<TableValue>)>, Vec<Vec<ColumnStats>>), CubeError> {
     let rows_per_file = div_ceil(num_rows as usize, files.len());
     let key_size = store.key_size() as usize;
     let partition_split_key_size = store.partition_split_key_size() as usize;
@@ -897,13 +1104,13 @@ pub(crate) async fn write_to_files(
         };
     };
 
-    write_to_files_impl(records, store, files, pick_writer).await?;
+    let column_stats = write_to_files_impl(records, store, files, pick_writer).await?;
 
     let mut stats = take(stats.lock().unwrap().deref_mut());
     if stats.last().unwrap().0 == 0 {
         stats.pop();
     }
-    Ok(stats)
+    Ok((stats, column_stats))
 }
 
 enum WriteBatchTo {
@@ -913,39 +1120,55 @@ enum WriteBatchTo {
     Next { rows_for_current: usize },
 }
 
+/// Returns column stats of each file in `files`.
 async fn write_to_files_impl(
     records: SendableRecordBatchStream,
     store: ParquetTableStore,
     files: Vec<String>,
     mut pick_writer: impl FnMut(&RecordBatch) -> WriteBatchTo,
-) -> Result<(), CubeError> {
+) -> Result<Vec<Vec<ColumnStats>>, CubeError> {
     let schema = Arc::new(store.arrow_schema());
-    let mut writers = files.into_iter().map(move |f| -> Result<_, CubeError> {
+    let mut column_stats = (0..files.len())
+        .map(|_| store.column_stats_builder())
+        .collect_vec();
+    let mut files = files.into_iter();
+    // Writers are created on the first batch of each file, which is used to pick the encodings.
+    let mut new_writer = move |sample: Option<&RecordBatch>| -> Result<_, CubeError> {
         Ok(ArrowWriter::try_new(
-            File::create(f)?,
+            File::create(files.next().unwrap())?,
             schema.clone(),
-            Some(store.writer_props()),
+            Some(store.writer_props(sample)),
         )?)
-    });
+    };
 
     let (write_tx, mut write_rx) = tokio::sync::mpsc::channel(1);
     let io_job = cube_ext::spawn_blocking(move || -> Result<_, CubeError> {
-        let mut writer = writers.next().transpose()?.unwrap();
+        let mut writer = None;
         let mut current_writer_i = 0;
         while let Some((writer_i, batch)) = write_rx.blocking_recv() {
             debug_assert!(current_writer_i <= writer_i);
             if current_writer_i != writer_i {
-                writer.close()?;
-
-                writer = writers.next().transpose()?.unwrap();
+                match writer.take() {
+                    Some(w) => w,
+                    None => new_writer(None)?,
+                }
+                .close()?;
                 current_writer_i = writer_i;
             }
 
-            writer.write(&batch)?;
+            if writer.is_none() {
+                writer = Some(new_writer(Some(&batch))?);
+            }
+            writer.as_mut().unwrap().write(&batch)?;
+            column_stats[writer_i].add(batch.columns());
         }
 
-        writer.close()?;
-        Ok(())
+        match writer {
+            Some(w) => w,
+            None => new_writer(None)?,
+        }
+        .close()?;
+        Ok(column_stats.into_iter().map(|b| b.finish()).collect())
     });
 
     let mut writer_i = 0;
@@ -976,10 +1199,10 @@ async fn write_to_files_impl(
     .await;
 
     // We want to report IO errors first, `err` will be unhelpful ("channel closed") when IO fails.
-    io_job.await??;
+    let column_stats = io_job.await??;
     err?;
 
-    Ok(())
+    Ok(column_stats)
 }
 
 async fn write_to_files_by_keys(
@@ -987,7 +1210,7 @@ async fn write_to_files_by_keys(
     store: ParquetTableStore,
     files: Vec<String>,
     keys: Vec<Row>,
-) -> Result<Vec<usize>, CubeError> {
+) -> Result<(Vec<usize>, Vec<Vec<ColumnStats>>), CubeError> {
     assert_eq!(files.len(), 1 + keys.len());
     let mut row_counts = Vec::with_capacity(files.len());
     row_counts.push(0);
@@ -1028,7 +1251,7 @@ async fn write_to_files_by_keys(
         panic!("impossible")
     };
     let num_files = files.len();
-    write_to_files_impl(records, store, files, pick_writer).await?;
+    let column_stats = write_to_files_impl(records, store, files, pick_writer).await?;
 
     let mut row_counts: Vec<usize> = take(row_counts.lock().unwrap().as_mut());
     assert!(
@@ -1038,7 +1261,7 @@ async fn write_to_files_by_keys(
         num_files
     );
     row_counts.resize(num_files, 0);
-    Ok(row_counts)
+    Ok((row_counts, column_stats))
 }
 
 ///Builds a `SendableRecordBatchStream` containing the result of merging a persistent chunk `l` with an in-memory chunk `r`
@@ -1048,6 +1271,7 @@ async fn merge_chunks(
     r: Vec<ArrayRef>,
     unique_key_columns: Option<Vec<&crate::metastore::Column>>,
     aggregate_columns: Option<Vec<AggregateColumn>>,
+    rows_to_keep: Option<Arc<dyn PhysicalExpr>>,
 ) -> Result<SendableRecordBatchStream, CubeError> {
     let schema = l.schema();
     let r = RecordBatch::try_new(schema.clone(), r)?;
@@ -1103,9 +1327,56 @@ async fn merge_chunks(
         )?);
     }
 
+    // Filter goes last so deleted rows can't be replaced by their older versions.
+    if let Some(rows_to_keep) = rows_to_keep {
+        res = Arc::new(FilterExec::try_new(rows_to_keep, res)?);
+    }
+
     Ok(res.execute(0).await?)
 }
 
+/// Reads `main_table` without writing it. Returns the number of rows kept by `rows_to_keep` and
+/// the stats of all rows.
+async fn scan_main_table(
+    main_table: Arc<dyn ExecutionPlan>,
+    rows_to_keep: Arc<dyn PhysicalExpr>,
+    mut stats: ColumnStatsBuilder,
+) -> Result<(u64, Vec<ColumnStats>), CubeError> {
+    let mut batches = main_table.execute(0).await?;
+    let mut kept_rows = 0;
+    while let Some(batch) = batches.next().await {
+        let batch = batch?;
+        let keep = rows_to_keep.evaluate(&batch)?.into_array(batch.num_rows());
+        let keep = keep
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .ok_or_else(|| {
+                CubeError::internal("Rows deletion filter is not boolean".to_string())
+            })?;
+        kept_rows += keep.iter().filter(|k| *k == Some(true)).count() as u64;
+        stats.add(batch.columns());
+    }
+    Ok((kept_rows, stats.finish()))
+}
+
+/// Rows are deleted only if `predicate` evaluates to true, so NULL results keep the row.
+fn rows_to_keep_expr(
+    predicate: &str,
+    schema: &ArrowSchema,
+) -> Result<Arc<dyn PhysicalExpr>, CubeError> {
+    let predicate = CubeStoreParser::new(predicate)?.parse_expr()?;
+    let predicate = plan_filter_expr(&predicate, schema)?;
+    let keep = Expr::Not(Box::new(predicate.clone())).or(Expr::IsNull(Box::new(predicate)));
+    let ctx = ExecutionContext::new();
+    let state = ctx.state.lock().unwrap().clone();
+    Ok(DefaultPhysicalPlanner::default().create_physical_expr(
+        &keep,
+        &schema.clone().to_dfschema()?,
+        schema,
+        &state,
+    )?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1147,23 +1418,27 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
+                None,
+                None,
+                None,
             )
             .await
             .unwrap();
         metastore.get_default_index(1).await.unwrap();
         let partition = metastore.get_partition(1).await.unwrap();
         metastore
-            .create_chunk(partition.get_id(), 10, false)
+            .create_chunk(partition.get_id(), 10, false, None, None)
             .await
             .unwrap();
         metastore.chunk_uploaded(1).await.unwrap();
         metastore
-            .create_chunk(partition.get_id(), 16, false)
+            .create_chunk(partition.get_id(), 16, false, None, None)
             .await
             .unwrap();
         metastore.chunk_uploaded(2).await.unwrap();
         metastore
-            .create_chunk(partition.get_id(), 20, false)
+            .create_chunk(partition.get_id(), 20, false, None, None)
             .await
             .unwrap();
         metastore.chunk_uploaded(3).await.unwrap();
@@ -1265,7 +1540,7 @@ mod tests {
             .unwrap()
             .get_id();
         metastore
-            .create_chunk(next_partition_id, 2, false)
+            .create_chunk(next_partition_id, 2, false, None, None)
             .await
             .unwrap();
         metastore.chunk_uploaded(4).await.unwrap();
@@ -1351,6 +1626,10 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
+                None,
+                None,
+                None,
             )
             .await
             .unwrap();
@@ -1360,7 +1639,7 @@ mod tests {
         let rows = (0..5)
             .map(|i| Row::new(vec![TableValue::String(format!("Foo {}", 4 - i))]))
             .collect::<Vec<_>>();
-        let data = rows_to_columns(&cols, &rows);
+        let data = rows_to_columns(&cols, &rows).unwrap();
         let index = metastore
             .get_index(partition.get_row().get_index_id())
             .await
@@ -1368,11 +1647,11 @@ mod tests {
         let schema = Arc::new(arrow_schema(index.get_row()));
         let batch = RecordBatch::try_new(schema.clone(), data).unwrap();
         let chunk_first = metastore
-            .create_chunk(partition.get_id(), 5, true)
+            .create_chunk(partition.get_id(), 5, true, None, None)
             .await
             .unwrap();
         let chunk_second = metastore
-            .create_chunk(partition.get_id(), 5, true)
+            .create_chunk(partition.get_id(), 5, true, None, None)
             .await
             .unwrap();
 
@@ -1505,6 +1784,10 @@ mod tests {
                 None,
                 Some(vec![("sum".to_string(), "sum_int".to_string())]),
                 None,
+                None,
+                None,
+                None,
+                None,
             )
             .await
             .unwrap();
@@ -1615,11 +1898,155 @@ mod tests {
         let _ = fs::remove_dir_all(chunk_store_path.clone());
         let _ = fs::remove_dir_all(chunk_remote_store_path.clone());
     }
+
+    #[tokio::test]
+    async fn list_values_compaction() {
+        let config = Config::test("list_values_compaction");
+        let path = "/tmp/test_list_values_compaction";
+        let chunk_store_path = path.to_string() + &"_store_chunk".to_string();
+        let chunk_remote_store_path = path.to_string() + &"_remote_store_chunk".to_string();
+
+        let _ = DB::destroy(&Options::default(), path);
+        let _ = fs::remove_dir_all(chunk_store_path.clone());
+        let _ = fs::remove_dir_all(chunk_remote_store_path.clone());
+
+        let remote_fs = LocalDirRemoteFs::new(
+            Some(PathBuf::from(chunk_remote_store_path.clone())),
+            PathBuf::from(chunk_store_path.clone()),
+        );
+        let metastore = RocksMetaStore::new(path, remote_fs.clone(), config.config_obj());
+        let chunk_store = ChunkStore::new(
+            metastore.clone(),
+            remote_fs.clone(),
+            Arc::new(MockCluster::new()),
+            config.config_obj(),
+            50,
+        );
+
+        metastore
+            .create_schema("foo".to_string(), false)
+            .await
+            .unwrap();
+        let cols = vec![
+            Column::new("id".to_string(), ColumnType::Int, 0),
+            Column::new(
+                "tags".to_string(),
+                ColumnType::List(Box::new(ColumnType::String)),
+                1,
+            ),
+        ];
+        let table = metastore
+            .create_table(
+                "foo".to_string(),
+                "bar".to_string(),
+                cols.clone(),
+                None,
+                None,
+                vec![],
+                true,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let index = metastore.get_default_index(table.get_id()).await.unwrap();
+        let partition = &metastore
+            .get_active_partitions_by_index_id(index.get_id())
+            .await
+            .unwrap()[0];
+
+        let list_value = |items: &[&str]| {
+            TableValue::List(
+                items
+                    .iter()
+                    .map(|i| TableValue::String(i.to_string()))
+                    .collect(),
+            )
+        };
+        let rows1 = vec![
+            Row::new(vec![TableValue::Int(1), list_value(&["a", "b"])]),
+            Row::new(vec![TableValue::Int(3), TableValue::Null]),
+        ];
+        let rows2 = vec![
+            Row::new(vec![TableValue::Int(2), list_value(&[])]),
+            Row::new(vec![TableValue::Int(4), list_value(&["c"])]),
+        ];
+        for rows in [rows1, rows2] {
+            let (chunk, _) = chunk_store
+                .add_chunk_columns(
+                    index.clone(),
+                    partition.clone(),
+                    rows_to_columns(&cols, &rows).unwrap(),
+                    false,
+                )
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+                .unwrap();
+            metastore.chunk_uploaded(chunk.get_id()).await.unwrap();
+        }
+
+        let compaction_service = CompactionServiceImpl::new(
+            metastore.clone(),
+            chunk_store.clone(),
+            remote_fs.clone(),
+            config.config_obj(),
+        );
+        compaction_service
+            .compact(partition.get_id())
+            .await
+            .unwrap();
+
+        let partitions = metastore
+            .get_active_partitions_by_index_id(index.get_id())
+            .await
+            .unwrap();
+        assert_eq!(partitions.len(), 1);
+        let partition = &partitions[0];
+        assert_eq!(partition.get_row().main_table_row_count(), 4);
+
+        let remote = partition
+            .get_row()
+            .get_full_name(partition.get_id())
+            .unwrap();
+        let local = remote_fs
+            .download_file(&remote, partition.get_row().file_size())
+            .await
+            .unwrap();
+        let reader = Arc::new(
+            ParquetExec::try_from_path(local.as_str(), None, None, ROW_GROUP_SIZE, 1, None)
+                .unwrap(),
+        );
+        let res_data = &collect(reader).await.unwrap()[0];
+        let rows = (0..res_data.num_rows())
+            .map(|i| Row::new(TableValue::from_columns(res_data.columns(), i)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                Row::new(vec![TableValue::Int(1), list_value(&["a", "b"])]),
+                Row::new(vec![TableValue::Int(2), list_value(&[])]),
+                Row::new(vec![TableValue::Int(3), TableValue::Null]),
+                Row::new(vec![TableValue::Int(4), list_value(&["c"])]),
+            ]
+        );
+
+        let _ = DB::destroy(&Options::default(), path);
+        let _ = fs::remove_dir_all(chunk_store_path.clone());
+        let _ = fs::remove_dir_all(chunk_remote_store_path.clone());
+    }
 }
 
 struct MultiSplit {
     meta: Arc<dyn MetaStore>,
     fs: Arc<dyn RemoteFs>,
+    config: Arc<dyn ConfigObj>,
     keys: Vec<Row>,
     key_len: usize,
     multi_partition_id: u64,
@@ -1635,6 +2062,7 @@ impl MultiSplit {
     fn new(
         meta: Arc<dyn MetaStore>,
         fs: Arc<dyn RemoteFs>,
+        config: Arc<dyn ConfigObj>,
         keys: Vec<Row>,
         key_len: usize,
         multi_partition_id: u64,
@@ -1644,6 +2072,7 @@ impl MultiSplit {
         MultiSplit {
             meta,
             fs,
+            config,
             keys,
             key_len,
             multi_partition_id,
@@ -1695,7 +2124,22 @@ impl MultiSplit {
             }
         });
 
-        let store = ParquetTableStore::new(p.index.get_row().clone(), ROW_GROUP_SIZE);
+        let table = self
+            .meta
+            .get_table_by_id(p.index.get_row().table_id())
+            .await?;
+        let store = ParquetTableStore::new(p.index.get_row().clone(), ROW_GROUP_SIZE)
+            .with_writer_options(ParquetWriterOptions::new(
+                table.get_row(),
+                self.config.as_ref(),
+            ))
+            .with_bloom_filter_columns(
+                table
+                    .get_row()
+                    .bloom_filter_columns()
+                    .clone()
+                    .unwrap_or_default(),
+            );
         let records = if !in_files.is_empty() {
             read_files(
                 &in_files.into_iter().map(|(f, _)| f).collect::<Vec<_>>(),
@@ -1710,8 +2154,15 @@ impl MultiSplit {
                 .execute(0)
                 .await?
         };
-        let row_counts =
+        let (row_counts, column_stats) =
             write_to_files_by_keys(records, store, out_files.to_vec(), self.keys.clone()).await?;
+        for ((c, n), stats) in children.iter().zip(row_counts.iter()).zip(column_stats) {
+            if *n != 0 {
+                self.meta
+                    .set_partition_column_stats(c.get_id(), Some(stats))
+                    .await?;
+            }
+        }
 
         for i in 0..row_counts.len() {
             mrow_counts[i] += row_counts[i] as u64;
@@ -31,8 +31,9 @@ use crate::cluster::Cluster;
 use crate::config::injection::DIService;
 use crate::config::ConfigObj;
 use crate::metastore::chunks::chunk_file_name;
-use crate::table::data::cmp_partition_key;
-use crate::table::parquet::{arrow_schema, ParquetTableStore};
+use crate::table::data::{cmp_partition_key, default_value_array, evolve_batch};
+use crate::table::parquet::{arrow_schema, ParquetTableStore, ParquetWriterOptions};
+use crate::table::stats::ColumnStats;
 use arrow::array::{Array, ArrayRef, Int64Builder, StringBuilder, UInt64Array};
 use arrow::record_batch::RecordBatch;
 use datafusion::cube_ext;
@@ -206,6 +207,13 @@ pub trait ChunkDataStore: DIService + Send + Sync {
         columns: &[Column],
         in_memory: bool,
     ) -> Result<Vec<ChunkUploadJob>, CubeError>;
+    /// Same as `partition_data`, but only writes chunks of the given `indexes`.
+    async fn partition_index_data(
+        &self,
+        indexes: Vec<IdRow<Index>>,
+        rows: Vec<ArrayRef>,
+        columns: &[Column],
+    ) -> Result<Vec<ChunkUploadJob>, CubeError>;
     async fn repartition(&self, partition_id: u64) -> Result<(), CubeError>;
     async fn repartition_chunk(&self, chunk_id: u64) -> Result<(), CubeError>;
     async fn get_chunk_columns(&self, chunk: IdRow<Chunk>) -> Result<Vec<RecordBatch>, CubeError>;
@@ -329,6 +337,16 @@ impl ChunkDataStore for ChunkStore {
             .await
     }
 
+    async fn partition_index_data(
+        &self,
+        indexes: Vec<IdRow<Index>>,
+        rows: Vec<ArrayRef>,
+        columns: &[Column],
+    ) -> Result<Vec<ChunkUploadJob>, CubeError> {
+        self.build_index_chunks(&indexes, rows.into(), columns, false)
+            .await
+    }
+
     async fn partition(&self, _wal_id: u64) -> Result<(), CubeError> {
         panic!("not used");
     }
@@ -465,13 +483,18 @@ impl ChunkDataStore for ChunkStore {
                 .get_index(partition.get_row().get_index_id())
                 .await?;
             let memory_chunks = self.memory_chunks.read().await;
-            Ok(vec![memory_chunks
+            let batch = memory_chunks
                 .get(&chunk.get_id())
                 .map(|b| b.clone())
-                .unwrap_or(RecordBatch::new_empty(Arc::new(
-                    arrow_schema(&index.get_row()),
-                )))])
+                .unwrap_or(RecordBatch::new_empty(Arc::new(arrow_schema(
+                    &index.get_row(),
+                ))));
+            Ok(vec![evolve_batch(batch, index.get_row().columns())?])
         } else {
+            let _pin = self.remote_fs.pin_file(&ChunkStore::chunk_remote_path(
+                chunk.get_id(),
+                chunk.get_row().suffix(),
+            ));
             let (local_file, index) = self.download_chunk(chunk).await?;
             Ok(cube_ext::spawn_blocking(move || -> Result<_, CubeError> {
                 let parquet = ParquetTableStore::new(index, ROW_GROUP_SIZE);
@@ -594,6 +617,10 @@ mod tests {
                     None,
                     None,
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .unwrap();
@@ -678,6 +705,10 @@ mod tests {
                     None,
                     None,
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .unwrap();
@@ -689,7 +720,7 @@ mod tests {
                 .unwrap();
             let partition = partitions[0].clone();
 
-            let data = rows_to_columns(&col, data_frame.get_rows().as_slice());
+            let data = rows_to_columns(&col, data_frame.get_rows().as_slice()).unwrap();
             let (chunk, file_size) = chunk_store
                 .add_chunk_columns(index, partition, data.clone(), false)
                 .await
@@ -776,6 +807,10 @@ mod tests {
                     None,
                     Some(vec![("sum".to_string(), "sum_int".to_string())]),
                     None,
+                    None,
+                    None,
+                    None,
+                    None,
                 )
                 .await
                 .unwrap();
@@ -972,9 +1007,41 @@ impl ChunkStore {
         data: Vec<ArrayRef>,
         in_memory: bool,
     ) -> Result<ChunkUploadJob, CubeError> {
+        // In-memory chunks are short-lived, so we do not build bloom filters for them.
+        let (writer_options, bloom_filter_columns) = if in_memory {
+            (None, Vec::new())
+        } else {
+            let table = self
+                .meta_store
+                .get_table_by_id(index.get_row().table_id())
+                .await?;
+            (
+                Some(ParquetWriterOptions::new(
+                    table.get_row(),
+                    self.config.as_ref(),
+                )),
+                table
+                    .get_row()
+                    .bloom_filter_columns()
+                    .clone()
+                    .unwrap_or_default(),
+            )
+        };
+        let columns = index.get_row().columns().clone();
+        let data_copy = data.clone();
+        let column_stats = cube_ext::spawn_blocking(move || {
+            ColumnStats::compute(&columns, &data_copy, &bloom_filter_columns)
+        })
+        .await?;
         let chunk = self
             .meta_store
-            .create_chunk(partition.get_id(), data[0].len(), in_memory)
+            .create_chunk(
+                partition.get_id(),
+                data[0].len(),
+                in_memory,
+                writer_options.map(|o| o.compression),
+                Some(column_stats),
+            )
             .await?;
         if in_memory {
             trace!(
@@ -999,7 +1066,8 @@ impl ChunkStore {
             let local_file = scopeguard::guard(local_file, ensure_temp_file_is_dropped);
             let local_file_copy = local_file.clone();
             cube_ext::spawn_blocking(move || -> Result<(), CubeError> {
-                let parquet = ParquetTableStore::new(index.get_row().clone(), ROW_GROUP_SIZE);
+                let parquet = ParquetTableStore::new(index.get_row().clone(), ROW_GROUP_SIZE)
+                    .with_writer_options(writer_options.unwrap());
                 parquet.write_data(&local_file_copy, data)?;
                 Ok(())
             })
@@ -1051,19 +1119,17 @@ fn remap_columns(
     new_columns: &[Column],
 ) -> Result<Vec<ArrayRef>, CubeError> {
     assert_eq!(old_columns.len(), old.len());
+    let num_rows = old.first().map(|a| a.len()).unwrap_or(0);
     let mut new = Vec::with_capacity(new_columns.len());
     for new_column in new_columns.iter() {
-        let old_column = old_columns
+        match old_columns
             .iter()
             .find(|c| c.get_name() == new_column.get_name())
-            .ok_or_else(|| {
-                CubeError::internal(format!(
-                    "Column '{}' not found in {:?}",
-                    new_column.get_name(),
-                    old_columns
-                ))
-            })?;
-        new.push(old[old_column.get_index()].clone());
+        {
+            Some(old_column) => new.push(old[old_column.get_index()].clone()),
+            // Data was prepared before the column was added to the table.
+            None => new.push(default_value_array(new_column, num_rows)?),
+        }
     }
     Ok(new)
 }
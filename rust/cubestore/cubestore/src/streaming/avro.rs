@@ -0,0 +1,646 @@
+use crate::CubeError;
+use bigdecimal::BigDecimal;
+use num::BigInt;
+use std::collections::HashMap;
+
+/// Items that take no bytes, e.g. nulls, can't be bounded by the datum size. So their total count
+/// in a single array is limited to avoid hanging on a corrupt datum.
+const MAX_ZERO_SIZE_ITEMS: usize = 1 << 20;
+
+/// Parsed Avro schema. Named types are resolved while parsing, so the schema is a plain tree.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AvroSchema {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Record(Vec<(String, AvroSchema)>),
+    Enum(Vec<String>),
+    Array(Box<AvroSchema>),
+    Map(Box<AvroSchema>),
+    Union(Vec<AvroSchema>),
+    Fixed(usize),
+    /// `int` annotated with the `date` logical type.
+    Date,
+    /// `long` annotated with the `timestamp-millis` logical type.
+    TimestampMillis,
+    /// `long` annotated with the `timestamp-micros` logical type.
+    TimestampMicros,
+    /// `bytes` or `fixed` annotated with the `decimal` logical type.
+    Decimal {
+        scale: i64,
+        fixed_size: Option<usize>,
+    },
+}
+
+/// Decoded Avro datum. Logical types are decoded into their logical values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AvroValue {
+    Null,
+    Boolean(bool),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Bytes(Vec<u8>),
+    String(String),
+    Record(Vec<(String, AvroValue)>),
+    Enum(String),
+    Array(Vec<AvroValue>),
+    Map(Vec<(String, AvroValue)>),
+    Fixed(Vec<u8>),
+    /// Days since the unix epoch.
+    Date(i32),
+    /// Microseconds since the unix epoch.
+    TimestampMicros(i64),
+    Decimal(BigDecimal),
+}
+
+impl AvroSchema {
+    pub fn parse(schema: &str) -> Result<AvroSchema, CubeError> {
+        let json = serde_json::from_str::<serde_json::Value>(schema)
+            .map_err(|e| CubeError::user(format!("Can't parse Avro schema: {}", e)))?;
+        SchemaParser::default().parse(&json, None)
+    }
+
+    /// Decodes a single datum in the Avro binary encoding. The whole `data` should be consumed.
+    pub fn decode(&self, data: &[u8]) -> Result<AvroValue, CubeError> {
+        let mut reader = Reader { data, pos: 0 };
+        let value = reader.read(self)?;
+        if reader.pos != data.len() {
+            return Err(CubeError::user(format!(
+                "Avro datum has {} trailing bytes",
+                data.len() - reader.pos
+            )));
+        }
+        Ok(value)
+    }
+
+    /// Minimal number of bytes taken by an encoded datum.
+    fn min_encoded_size(&self) -> usize {
+        match self {
+            AvroSchema::Null => 0,
+            AvroSchema::Float => 4,
+            AvroSchema::Double => 8,
+            AvroSchema::Fixed(size)
+            | AvroSchema::Decimal {
+                fixed_size: Some(size),
+                ..
+            } => *size,
+            AvroSchema::Record(fields) => fields
+                .iter()
+                .fold(0, |size, (_, f)| size.saturating_add(f.min_encoded_size())),
+            _ => 1,
+        }
+    }
+}
+
+impl AvroValue {
+    /// Nested values are represented as JSON to be stored into string columns.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            AvroValue::Null => serde_json::Value::Null,
+            AvroValue::Boolean(b) => serde_json::Value::from(*b),
+            AvroValue::Int(i) | AvroValue::Date(i) => serde_json::Value::from(*i),
+            AvroValue::Long(i) | AvroValue::TimestampMicros(i) => serde_json::Value::from(*i),
+            AvroValue::Float(f) => serde_json::Value::from(*f),
+            AvroValue::Double(f) => serde_json::Value::from(*f),
+            AvroValue::Bytes(b) | AvroValue::Fixed(b) => {
+                serde_json::Value::from(String::from_utf8_lossy(b).to_string())
+            }
+            AvroValue::String(s) | AvroValue::Enum(s) => serde_json::Value::from(s.as_str()),
+            AvroValue::Decimal(d) => serde_json::Value::from(d.to_string()),
+            AvroValue::Array(a) => {
+                serde_json::Value::Array(a.iter().map(|v| v.to_json()).collect())
+            }
+            AvroValue::Record(fields) | AvroValue::Map(fields) => serde_json::Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_json()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SchemaParser {
+    /// Named types by their full names. `None` marks types that are still being parsed.
+    named: HashMap<String, Option<AvroSchema>>,
+}
+
+impl SchemaParser {
+    fn parse(
+        &mut self,
+        json: &serde_json::Value,
+        namespace: Option<&str>,
+    ) -> Result<AvroSchema, CubeError> {
+        match json {
+            serde_json::Value::String(name) => self.parse_type_name(name, namespace),
+            serde_json::Value::Array(variants) => Ok(AvroSchema::Union(
+                variants
+                    .iter()
+                    .map(|v| self.parse(v, namespace))
+                    .collect::<Result<_, _>>()?,
+            )),
+            serde_json::Value::Object(object) => {
+                let type_name = object.get("type").ok_or_else(|| {
+                    CubeError::user(format!("Avro schema type is missing: {}", json))
+                })?;
+                let type_name = match type_name {
+                    serde_json::Value::String(t) => t.as_str(),
+                    // Nested definition like {"type": {"type": "array", ...}}.
+                    t => return self.parse(t, namespace),
+                };
+                let logical_type = object.get("logicalType").and_then(|t| t.as_str());
+                match (type_name, logical_type) {
+                    ("int", Some("date")) => Ok(AvroSchema::Date),
+                    ("long", Some("timestamp-millis")) => Ok(AvroSchema::TimestampMillis),
+                    ("long", Some("timestamp-micros")) => Ok(AvroSchema::TimestampMicros),
+                    ("bytes", Some("decimal")) => Ok(AvroSchema::Decimal {
+                        scale: Self::decimal_scale(object)?,
+                        fixed_size: None,
+                    }),
+                    ("record" | "error", _) => {
+                        let (full_name, namespace) = Self::full_name(object, namespace)?;
+                        self.define(&full_name, None)?;
+                        let fields = object
+                            .get("fields")
+                            .and_then(|f| f.as_array())
+                            .ok_or_else(|| {
+                                CubeError::user(format!("Avro record fields are missing: {}", json))
+                            })?
+                            .iter()
+                            .map(|f| -> Result<_, CubeError> {
+                                let name =
+                                    f.get("name").and_then(|n| n.as_str()).ok_or_else(|| {
+                                        CubeError::user(format!(
+                                            "Avro field name is missing: {}",
+                                            f
+                                        ))
+                                    })?;
+                                let field_type = f.get("type").ok_or_else(|| {
+                                    CubeError::user(format!("Avro field type is missing: {}", f))
+                                })?;
+                                Ok((name.to_string(), self.parse(field_type, Some(&namespace))?))
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let schema = AvroSchema::Record(fields);
+                        self.named.insert(full_name, Some(schema.clone()));
+                        Ok(schema)
+                    }
+                    ("enum", _) => {
+                        let (full_name, _) = Self::full_name(object, namespace)?;
+                        let symbols = object
+                            .get("symbols")
+                            .and_then(|s| s.as_array())
+                            .and_then(|s| {
+                                s.iter()
+                                    .map(|s| s.as_str().map(|s| s.to_string()))
+                                    .collect::<Option<Vec<_>>>()
+                            })
+                            .ok_or_else(|| {
+                                CubeError::user(format!("Avro enum symbols are missing: {}", json))
+                            })?;
+                        let schema = AvroSchema::Enum(symbols);
+                        self.define(&full_name, Some(schema.clone()))?;
+                        Ok(schema)
+                    }
+                    ("fixed", _) => {
+                        let (full_name, _) = Self::full_name(object, namespace)?;
+                        let size = object.get("size").and_then(|s| s.as_u64()).ok_or_else(|| {
+                            CubeError::user(format!("Avro fixed size is missing: {}", json))
+                        })? as usize;
+                        let schema = if logical_type == Some("decimal") {
+                            AvroSchema::Decimal {
+                                scale: Self::decimal_scale(object)?,
+                                fixed_size: Some(size),
+                            }
+                        } else {
+                            AvroSchema::Fixed(size)
+                        };
+                        self.define(&full_name, Some(schema.clone()))?;
+                        Ok(schema)
+                    }
+                    ("array", _) => {
+                        let items = object.get("items").ok_or_else(|| {
+                            CubeError::user(format!("Avro array items are missing: {}", json))
+                        })?;
+                        Ok(AvroSchema::Array(Box::new(self.parse(items, namespace)?)))
+                    }
+                    ("map", _) => {
+                        let values = object.get("values").ok_or_else(|| {
+                            CubeError::user(format!("Avro map values are missing: {}", json))
+                        })?;
+                        Ok(AvroSchema::Map(Box::new(self.parse(values, namespace)?)))
+                    }
+                    // Unknown logical types should be ignored according to the spec.
+                    (t, _) => self.parse_type_name(t, namespace),
+                }
+            }
+            _ => Err(CubeError::user(format!("Invalid Avro schema: {}", json))),
+        }
+    }
+
+    fn parse_type_name(
+        &mut self,
+        name: &str,
+        namespace: Option<&str>,
+    ) -> Result<AvroSchema, CubeError> {
+        Ok(match name {
+            "null" => AvroSchema::Null,
+            "boolean" => AvroSchema::Boolean,
+            "int" => AvroSchema::Int,
+            "long" => AvroSchema::Long,
+            "float" => AvroSchema::Float,
+            "double" => AvroSchema::Double,
+            "bytes" => AvroSchema::Bytes,
+            "string" => AvroSchema::String,
+            name => {
+                let named = namespace
+                    .filter(|ns| !ns.is_empty() && !name.contains('.'))
+                    .and_then(|ns| self.named.get(&format!("{}.{}", ns, name)))
+                    .or_else(|| self.named.get(name));
+                match named {
+                    Some(Some(schema)) => schema.clone(),
+                    Some(None) => {
+                        return Err(CubeError::user(format!(
+                            "Recursive Avro type '{}' is not supported",
+                            name
+                        )))
+                    }
+                    None => return Err(CubeError::user(format!("Unknown Avro type '{}'", name))),
+                }
+            }
+        })
+    }
+
+    fn define(&mut self, full_name: &str, schema: Option<AvroSchema>) -> Result<(), CubeError> {
+        if self.named.contains_key(full_name) {
+            return Err(CubeError::user(format!(
+                "Avro type '{}' is defined more than once",
+                full_name
+            )));
+        }
+        self.named.insert(full_name.to_string(), schema);
+        Ok(())
+    }
+
+    /// Returns the full name of the named type and the namespace for its nested definitions.
+    fn full_name(
+        object: &serde_json::Map<String, serde_json::Value>,
+        namespace: Option<&str>,
+    ) -> Result<(String, String), CubeError> {
+        let name = object
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or_else(|| CubeError::user("Avro named type has no name".to_string()))?;
+        if let Some(pos) = name.rfind('.') {
+            return Ok((name.to_string(), name[..pos].to_string()));
+        }
+        let namespace = object
+            .get("namespace")
+            .and_then(|n| n.as_str())
+            .or(namespace)
+            .unwrap_or("");
+        if namespace.is_empty() {
+            Ok((name.to_string(), String::new()))
+        } else {
+            Ok((format!("{}.{}", namespace, name), namespace.to_string()))
+        }
+    }
+
+    fn decimal_scale(
+        object: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<i64, CubeError> {
+        match object.get("scale") {
+            None => Ok(0),
+            Some(s) => s
+                .as_i64()
+                .ok_or_else(|| CubeError::user(format!("Invalid Avro decimal scale: {}", s))),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, schema: &AvroSchema) -> Result<AvroValue, CubeError> {
+        Ok(match schema {
+            AvroSchema::Null => AvroValue::Null,
+            AvroSchema::Boolean => match self.read_bytes(1)?[0] {
+                0 => AvroValue::Boolean(false),
+                1 => AvroValue::Boolean(true),
+                b => return Err(CubeError::user(format!("Invalid Avro boolean: {}", b))),
+            },
+            AvroSchema::Int => AvroValue::Int(self.read_int()?),
+            AvroSchema::Long => AvroValue::Long(self.read_long()?),
+            AvroSchema::Float => {
+                AvroValue::Float(f32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+            }
+            AvroSchema::Double => {
+                AvroValue::Double(f64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+            }
+            AvroSchema::Bytes => AvroValue::Bytes(self.read_length_prefixed()?.to_vec()),
+            AvroSchema::String => AvroValue::String(
+                String::from_utf8(self.read_length_prefixed()?.to_vec())
+                    .map_err(|e| CubeError::user(format!("Invalid Avro string: {}", e)))?,
+            ),
+            AvroSchema::Record(fields) => AvroValue::Record(
+                fields
+                    .iter()
+                    .map(|(name, schema)| Ok((name.to_string(), self.read(schema)?)))
+                    .collect::<Result<_, CubeError>>()?,
+            ),
+            AvroSchema::Enum(symbols) => {
+                let index = self.read_int()?;
+                let symbol = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| symbols.get(i))
+                    .ok_or_else(|| {
+                        CubeError::user(format!("Invalid Avro enum index: {}", index))
+                    })?;
+                AvroValue::Enum(symbol.to_string())
+            }
+            AvroSchema::Array(items) => {
+                let mut values = Vec::new();
+                self.read_blocks(items.min_encoded_size(), |reader| {
+                    values.push(reader.read(items)?);
+                    Ok(())
+                })?;
+                AvroValue::Array(values)
+            }
+            AvroSchema::Map(schema) => {
+                let mut values = Vec::new();
+                // Keys are strings that take at least a byte for their length.
+                let item_size = schema.min_encoded_size().saturating_add(1);
+                self.read_blocks(item_size, |reader| {
+                    let key = reader.read(&AvroSchema::String)?;
+                    let key = match key {
+                        AvroValue::String(k) => k,
+                        _ => unreachable!(),
+                    };
+                    values.push((key, reader.read(schema)?));
+                    Ok(())
+                })?;
+                AvroValue::Map(values)
+            }
+            AvroSchema::Union(variants) => {
+                let index = self.read_long()?;
+                let variant = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| variants.get(i))
+                    .ok_or_else(|| {
+                        CubeError::user(format!("Invalid Avro union index: {}", index))
+                    })?;
+                self.read(variant)?
+            }
+            AvroSchema::Fixed(size) => AvroValue::Fixed(self.read_bytes(*size)?.to_vec()),
+            AvroSchema::Date => AvroValue::Date(self.read_int()?),
+            AvroSchema::TimestampMillis => {
+                let millis = self.read_long()?;
+                AvroValue::TimestampMicros(millis.checked_mul(1000).ok_or_else(|| {
+                    CubeError::user(format!("Avro timestamp is out of range: {}", millis))
+                })?)
+            }
+            AvroSchema::TimestampMicros => AvroValue::TimestampMicros(self.read_long()?),
+            AvroSchema::Decimal { scale, fixed_size } => {
+                let bytes = match fixed_size {
+                    Some(size) => self.read_bytes(*size)?,
+                    None => self.read_length_prefixed()?,
+                };
+                AvroValue::Decimal(BigDecimal::new(BigInt::from_signed_bytes_be(bytes), *scale))
+            }
+        })
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], CubeError> {
+        if self.data.len() - self.pos < len {
+            return Err(CubeError::user("Unexpected end of Avro datum".to_string()));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_length_prefixed(&mut self) -> Result<&'a [u8], CubeError> {
+        let len = self.read_long()?;
+        let len = usize::try_from(len)
+            .map_err(|_| CubeError::user(format!("Invalid Avro length: {}", len)))?;
+        self.read_bytes(len)
+    }
+
+    /// Variable-length zig-zag encoded long.
+    fn read_long(&mut self) -> Result<i64, CubeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_bytes(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        Err(CubeError::user("Invalid Avro long".to_string()))
+    }
+
+    fn read_int(&mut self) -> Result<i32, CubeError> {
+        let value = self.read_long()?;
+        i32::try_from(value).map_err(|_| CubeError::user(format!("Invalid Avro int: {}", value)))
+    }
+
+    /// Arrays and maps are encoded as a series of blocks terminated by an empty one.
+    /// Negative block count means the count is followed by the block size in bytes.
+    /// Counts are checked against the remaining bytes as every item takes at least `item_size`.
+    fn read_blocks(
+        &mut self,
+        item_size: usize,
+        mut read_item: impl FnMut(&mut Self) -> Result<(), CubeError>,
+    ) -> Result<(), CubeError> {
+        let mut total_count = 0usize;
+        loop {
+            let count = self.read_long()?;
+            if count == 0 {
+                return Ok(());
+            }
+            if count < 0 {
+                self.read_long()?;
+            }
+            let items = count
+                .checked_abs()
+                .and_then(|c| usize::try_from(c).ok())
+                .unwrap_or(usize::MAX);
+            total_count = total_count.saturating_add(items);
+            let fits = match item_size {
+                0 => total_count <= MAX_ZERO_SIZE_ITEMS,
+                size => items <= (self.data.len() - self.pos) / size,
+            };
+            if !fits {
+                return Err(CubeError::user(format!(
+                    "Invalid Avro block count: {}",
+                    count
+                )));
+            }
+            for _ in 0..items {
+                read_item(self)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn long(v: i64, out: &mut Vec<u8>) {
+        let mut z = ((v << 1) ^ (v >> 63)) as u64;
+        loop {
+            if z < 0x80 {
+                out.push(z as u8);
+                break;
+            }
+            out.push((z as u8 & 0x7f) | 0x80);
+            z >>= 7;
+        }
+    }
+
+    fn string(s: &str, out: &mut Vec<u8>) {
+        long(s.len() as i64, out);
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn decode_record() {
+        let schema = AvroSchema::parse(
+            r#"{
+                "type": "record",
+                "name": "Event",
+                "namespace": "com.example",
+                "fields": [
+                    {"name": "id", "type": "long"},
+                    {"name": "name", "type": ["null", "string"]},
+                    {"name": "kind", "type": {"type": "enum", "name": "Kind", "symbols": ["LOAD", "CLICK"]}},
+                    {"name": "prev_kind", "type": "com.example.Kind"},
+                    {"name": "day", "type": {"type": "int", "logicalType": "date"}},
+                    {"name": "ts", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                    {"name": "amount", "type": {"type": "bytes", "logicalType": "decimal", "precision": 10, "scale": 2}},
+                    {"name": "score", "type": "double"},
+                    {"name": "tags", "type": {"type": "array", "items": "string"}},
+                    {"name": "attrs", "type": {"type": "map", "values": "int"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut data = Vec::new();
+        long(-3, &mut data);
+        long(1, &mut data);
+        string("test", &mut data);
+        long(1, &mut data);
+        long(0, &mut data);
+        long(19000, &mut data);
+        long(1_600_000_000_123, &mut data);
+        long(2, &mut data);
+        data.extend_from_slice(&(-324i16).to_be_bytes());
+        data.extend_from_slice(&1.5f64.to_le_bytes());
+        // Array with a sized block.
+        long(-2, &mut data);
+        long(4, &mut data);
+        string("a", &mut data);
+        string("b", &mut data);
+        long(0, &mut data);
+        long(1, &mut data);
+        string("x", &mut data);
+        long(-1, &mut data);
+        long(0, &mut data);
+
+        assert_eq!(
+            schema.decode(&data).unwrap(),
+            AvroValue::Record(vec![
+                ("id".to_string(), AvroValue::Long(-3)),
+                ("name".to_string(), AvroValue::String("test".to_string())),
+                ("kind".to_string(), AvroValue::Enum("CLICK".to_string())),
+                ("prev_kind".to_string(), AvroValue::Enum("LOAD".to_string())),
+                ("day".to_string(), AvroValue::Date(19000)),
+                (
+                    "ts".to_string(),
+                    AvroValue::TimestampMicros(1_600_000_000_123_000)
+                ),
+                (
+                    "amount".to_string(),
+                    AvroValue::Decimal(BigDecimal::from_str("-3.24").unwrap())
+                ),
+                ("score".to_string(), AvroValue::Double(1.5)),
+                (
+                    "tags".to_string(),
+                    AvroValue::Array(vec![
+                        AvroValue::String("a".to_string()),
+                        AvroValue::String("b".to_string())
+                    ])
+                ),
+                (
+                    "attrs".to_string(),
+                    AvroValue::Map(vec![("x".to_string(), AvroValue::Int(-1))])
+                ),
+            ])
+        );
+
+        assert!(schema.decode(&data[..data.len() - 1]).is_err());
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert!(schema.decode(&trailing).is_err());
+    }
+
+    #[test]
+    fn invalid_block_counts() {
+        let longs = AvroSchema::Array(Box::new(AvroSchema::Long));
+        let mut data = Vec::new();
+        long(i64::MIN, &mut data);
+        long(8, &mut data);
+        assert!(longs.decode(&data).is_err());
+
+        // Count doesn't fit the remaining bytes.
+        let mut data = Vec::new();
+        long(1000, &mut data);
+        long(1, &mut data);
+        long(0, &mut data);
+        assert!(longs.decode(&data).is_err());
+
+        let nulls = AvroSchema::Array(Box::new(AvroSchema::Null));
+        let mut data = Vec::new();
+        long(3, &mut data);
+        long(0, &mut data);
+        assert_eq!(
+            nulls.decode(&data).unwrap(),
+            AvroValue::Array(vec![AvroValue::Null; 3])
+        );
+        let mut data = Vec::new();
+        long(i64::MAX, &mut data);
+        long(0, &mut data);
+        assert!(nulls.decode(&data).is_err());
+
+        let map = AvroSchema::Map(Box::new(AvroSchema::Null));
+        let mut data = Vec::new();
+        long(-1000, &mut data);
+        long(0, &mut data);
+        long(0, &mut data);
+        assert!(map.decode(&data).is_err());
+    }
+
+    #[test]
+    fn invalid_schemas() {
+        assert!(AvroSchema::parse(r#""unknown""#).is_err());
+        assert!(AvroSchema::parse(
+            r#"{"type": "record", "name": "Node", "fields": [{"name": "next", "type": ["null", "Node"]}]}"#
+        )
+        .is_err());
+        assert!(AvroSchema::parse(r#"{"type": "array"}"#).is_err());
+    }
+}
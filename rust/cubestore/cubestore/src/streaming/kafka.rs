@@ -0,0 +1,610 @@
+use crate::import::{avro_record_to_values, json_object_to_values};
+use crate::metastore::source::KafkaMessageFormat;
+use crate::metastore::table::StreamOffsets;
+use crate::metastore::Column;
+use crate::streaming::avro::{AvroSchema, AvroValue};
+use crate::streaming::{partition_offset_seq, StreamBatch, StreamingSource};
+use crate::table::{Row, TableValue};
+use crate::CubeError;
+use async_trait::async_trait;
+use datafusion::cube_ext;
+use futures::stream::StreamExt;
+use futures::Stream;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::BorrowedMessage;
+use rdkafka::{Message, Offset, TopicPartitionList};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Reads JSON or Avro messages from a plain Kafka topic.
+/// Message fields are mapped onto table columns by name.
+/// Stream position is tracked per topic partition.
+#[derive(Clone)]
+pub struct KafkaStreamingSource {
+    brokers: String,
+    topic: String,
+    consumer_group: String,
+    user: Option<String>,
+    password: Option<String>,
+    use_ssl: bool,
+    format: MessageFormat,
+    skip_invalid_messages: bool,
+}
+
+#[derive(Clone)]
+enum MessageFormat {
+    Json,
+    Avro(Arc<AvroSchema>),
+    /// Confluent wire format: a zero magic byte and a big-endian schema id precede the datum.
+    ConfluentAvro {
+        schema_registry_url: String,
+        schemas: Arc<Mutex<HashMap<u32, Arc<AvroSchema>>>>,
+    },
+}
+
+impl KafkaStreamingSource {
+    pub fn new(
+        brokers: String,
+        topic: String,
+        consumer_group: String,
+        user: Option<String>,
+        password: Option<String>,
+        use_ssl: bool,
+        format: KafkaMessageFormat,
+        skip_invalid_messages: bool,
+    ) -> Result<Self, CubeError> {
+        let format = match format {
+            KafkaMessageFormat::Json => MessageFormat::Json,
+            KafkaMessageFormat::Avro {
+                schema: Some(schema),
+                ..
+            } => MessageFormat::Avro(Arc::new(AvroSchema::parse(&schema)?)),
+            KafkaMessageFormat::Avro {
+                schema: None,
+                schema_registry_url: Some(schema_registry_url),
+            } => MessageFormat::ConfluentAvro {
+                schema_registry_url,
+                schemas: Arc::new(Mutex::new(HashMap::new())),
+            },
+            KafkaMessageFormat::Avro {
+                schema: None,
+                schema_registry_url: None,
+            } => {
+                return Err(CubeError::user(
+                    "Avro schema or schema registry url should be set for kafka avro source"
+                        .to_string(),
+                ))
+            }
+        };
+        Ok(Self {
+            brokers,
+            topic,
+            consumer_group,
+            user,
+            password,
+            use_ssl,
+            format,
+            skip_invalid_messages,
+        })
+    }
+
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &self.brokers)
+            .set("group.id", &self.consumer_group)
+            .set("client.id", "cubestore")
+            .set("enable.partition.eof", "false")
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest");
+        match (&self.user, self.use_ssl) {
+            (Some(user), use_ssl) => {
+                config
+                    .set(
+                        "security.protocol",
+                        if use_ssl {
+                            "SASL_SSL"
+                        } else {
+                            "SASL_PLAINTEXT"
+                        },
+                    )
+                    .set("sasl.mechanisms", "PLAIN")
+                    .set("sasl.username", user)
+                    .set("sasl.password", self.password.as_deref().unwrap_or(""));
+            }
+            (None, true) => {
+                config.set("security.protocol", "SSL");
+            }
+            (None, false) => {}
+        }
+        config
+    }
+
+    async fn message_to_row(
+        format: &MessageFormat,
+        message: &BorrowedMessage<'_>,
+        columns: &[Column],
+        seq_column_index: usize,
+    ) -> Result<Option<Row>, CubeError> {
+        // Tombstones don't carry any data.
+        let payload = match message.payload() {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+        let mut values = Self::payload_to_values(format, payload, columns).await?;
+        values[seq_column_index] =
+            TableValue::Int(partition_offset_seq(message.partition(), message.offset())?);
+        Ok(Some(Row::new(values)))
+    }
+
+    async fn payload_to_values(
+        format: &MessageFormat,
+        payload: &[u8],
+        columns: &[Column],
+    ) -> Result<Vec<TableValue>, CubeError> {
+        Ok(match format {
+            MessageFormat::Json => {
+                let object = match serde_json::from_slice::<serde_json::Value>(payload)? {
+                    serde_json::Value::Object(o) => o,
+                    _ => {
+                        return Err(CubeError::user(format!(
+                            "Kafka message is expected to be a JSON object but found: {}",
+                            String::from_utf8_lossy(payload)
+                        )))
+                    }
+                };
+                json_object_to_values(columns, object)?
+            }
+            MessageFormat::Avro(schema) => Self::avro_to_values(schema, payload, columns)?,
+            MessageFormat::ConfluentAvro {
+                schema_registry_url,
+                schemas,
+            } => {
+                if payload.len() < 5 || payload[0] != 0 {
+                    return Err(CubeError::user(
+                        "Kafka message is not in the Confluent Avro wire format".to_string(),
+                    ));
+                }
+                let schema_id = u32::from_be_bytes(payload[1..5].try_into().unwrap());
+                let schema = Self::registry_schema(schema_registry_url, schemas, schema_id).await?;
+                Self::avro_to_values(&schema, &payload[5..], columns)?
+            }
+        })
+    }
+
+    fn avro_to_values(
+        schema: &AvroSchema,
+        payload: &[u8],
+        columns: &[Column],
+    ) -> Result<Vec<TableValue>, CubeError> {
+        match schema.decode(payload)? {
+            AvroValue::Record(fields) => avro_record_to_values(columns, fields),
+            v => Err(CubeError::user(format!(
+                "Kafka message is expected to be an Avro record but found: {:?}",
+                v
+            ))),
+        }
+    }
+
+    /// Schemas are immutable in the registry, so they're fetched once per source.
+    async fn registry_schema(
+        schema_registry_url: &str,
+        schemas: &Mutex<HashMap<u32, Arc<AvroSchema>>>,
+        schema_id: u32,
+    ) -> Result<Arc<AvroSchema>, CubeError> {
+        if let Some(schema) = schemas.lock().await.get(&schema_id) {
+            return Ok(schema.clone());
+        }
+        let url = format!(
+            "{}/schemas/ids/{}",
+            schema_registry_url.trim_end_matches('/'),
+            schema_id
+        );
+        let response = reqwest::get(&url).await?;
+        if !response.status().is_success() {
+            return Err(CubeError::user(format!(
+                "Can't fetch Avro schema {} from the schema registry: {}",
+                url,
+                response.status()
+            )));
+        }
+        let body = response.json::<serde_json::Value>().await?;
+        let schema = body.get("schema").and_then(|s| s.as_str()).ok_or_else(|| {
+            CubeError::user(format!(
+                "Schema registry response for {} has no schema: {}",
+                url, body
+            ))
+        })?;
+        let schema = Arc::new(AvroSchema::parse(schema)?);
+        schemas.lock().await.insert(schema_id, schema.clone());
+        Ok(schema)
+    }
+}
+
+#[async_trait]
+impl StreamingSource for KafkaStreamingSource {
+    async fn row_stream(
+        &self,
+        columns: Vec<Column>,
+        seq_column: Column,
+        initial_offsets: StreamOffsets,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamBatch, CubeError>> + Send>>, CubeError> {
+        let seq_column_index = columns
+            .iter()
+            .position(|c| c.get_name() == seq_column.get_name())
+            .ok_or_else(|| {
+                CubeError::internal(format!(
+                    "Sequence column {:?} is not found in {:?}",
+                    seq_column, columns
+                ))
+            })?;
+        let consumer: Arc<StreamConsumer> = Arc::new(self.client_config().create()?);
+
+        // Partitions are assigned explicitly as the position is stored in the metastore
+        // along with ingested data rather than committed to the consumer group.
+        let topic = self.topic.clone();
+        let consumer_to_move = consumer.clone();
+        let offsets_to_move = initial_offsets.clone();
+        let assignment = cube_ext::spawn_blocking(move || -> Result<_, CubeError> {
+            let metadata =
+                consumer_to_move.fetch_metadata(Some(&topic), Duration::from_secs(30))?;
+            let topic_metadata = metadata
+                .topics()
+                .iter()
+                .find(|t| t.name() == topic)
+                .ok_or_else(|| CubeError::user(format!("Kafka topic '{}' is not found", topic)))?;
+            if let Some(e) = topic_metadata.error() {
+                return Err(CubeError::user(format!(
+                    "Can't fetch kafka topic '{}' metadata: {:?}",
+                    topic, e
+                )));
+            }
+            let mut assignment = TopicPartitionList::new();
+            for partition in topic_metadata.partitions() {
+                let offset = match offsets_to_move.get(partition.id()) {
+                    Some(last_offset) => Offset::Offset(last_offset + 1),
+                    None => Offset::Beginning,
+                };
+                assignment.add_partition_offset(&topic, partition.id(), offset)?;
+            }
+            Ok(assignment)
+        })
+        .await??;
+        consumer.assign(&assignment)?;
+
+        let format = self.format.clone();
+        let skip_invalid_messages = self.skip_invalid_messages;
+        let rows = futures::stream::unfold(
+            (consumer, initial_offsets),
+            move |(consumer, mut offsets)| {
+                let columns = columns.clone();
+                let format = format.clone();
+                async move {
+                    let row = match consumer.recv().await {
+                        // Messages may be redelivered, the ones that are already ingested are skipped.
+                        Ok(message)
+                            if offsets
+                                .get(message.partition())
+                                .map(|last_offset| message.offset() <= last_offset)
+                                .unwrap_or(false) =>
+                        {
+                            Ok(None)
+                        }
+                        Ok(message) => {
+                            offsets.set(message.partition(), message.offset());
+                            match Self::message_to_row(
+                                &format,
+                                &message,
+                                &columns,
+                                seq_column_index,
+                            )
+                            .await
+                            {
+                                Ok(row) => Ok(row),
+                                Err(e) if skip_invalid_messages => {
+                                    log::warn!(
+                                        "Skipping invalid message at offset {} of kafka partition {}: {}",
+                                        message.offset(),
+                                        message.partition(),
+                                        e
+                                    );
+                                    Ok(None)
+                                }
+                                Err(e) => Err(CubeError::user(format!(
+                                    "Can't read message at offset {} of kafka partition {}: {}. Set skip_invalid_messages on the source to skip such messages.",
+                                    message.offset(),
+                                    message.partition(),
+                                    e
+                                ))),
+                            }
+                        }
+                        Err(e) => Err(CubeError::from(e)),
+                    };
+                    Some(((row, offsets.clone()), (consumer, offsets)))
+                }
+            },
+        );
+        Ok(Box::pin(rows.ready_chunks(16384).map(
+            |chunk| -> Result<StreamBatch, CubeError> {
+                let mut offsets = StreamOffsets::new();
+                let mut rows = Vec::with_capacity(chunk.len());
+                for (row, row_offsets) in chunk {
+                    if let Some(row) = row? {
+                        rows.push(row);
+                    }
+                    offsets = row_offsets;
+                }
+                Ok((rows, offsets))
+            },
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metastore::ColumnType;
+    use crate::table::TimestampValue;
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn avro_messages() {
+        let schema = r#"{"type": "record", "name": "Event", "fields": [
+            {"name": "event", "type": "string"},
+            {"name": "count", "type": ["null", "long"]},
+            {"name": "ts", "type": {"type": "long", "logicalType": "timestamp-millis"}}
+        ]}"#;
+        let columns = vec![
+            Column::new("event".to_string(), ColumnType::String, 0),
+            Column::new("count".to_string(), ColumnType::Int, 1),
+            Column::new("ts".to_string(), ColumnType::Timestamp, 2),
+        ];
+        // {"event": "load", "count": 1, "ts": 1000}
+        let datum = [&[0x08][..], b"load", &[0x02, 0x02, 0xd0, 0x0f]].concat();
+        let expected = vec![
+            TableValue::String("load".to_string()),
+            TableValue::Int(1),
+            TableValue::Timestamp(TimestampValue::new(1_000_000_000)),
+        ];
+
+        let format = KafkaStreamingSource::new(
+            String::new(),
+            String::new(),
+            String::new(),
+            None,
+            None,
+            false,
+            KafkaMessageFormat::Avro {
+                schema: Some(schema.to_string()),
+                schema_registry_url: None,
+            },
+            false,
+        )
+        .unwrap()
+        .format;
+        assert_eq!(
+            KafkaStreamingSource::payload_to_values(&format, &datum, &columns)
+                .await
+                .unwrap(),
+            expected
+        );
+
+        let registry = warp::path!("schemas" / "ids" / u32).map(move |id| {
+            assert_eq!(id, 7);
+            warp::reply::json(&serde_json::json!({ "schema": schema }))
+        });
+        let (addr, server) = warp::serve(registry).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let format = KafkaStreamingSource::new(
+            String::new(),
+            String::new(),
+            String::new(),
+            None,
+            None,
+            false,
+            KafkaMessageFormat::Avro {
+                schema: None,
+                schema_registry_url: Some(format!("http://{}/", addr)),
+            },
+            false,
+        )
+        .unwrap()
+        .format;
+        let payload = [&[0, 0, 0, 0, 7][..], &datum].concat();
+        for _ in 0..2 {
+            assert_eq!(
+                KafkaStreamingSource::payload_to_values(&format, &payload, &columns)
+                    .await
+                    .unwrap(),
+                expected
+            );
+        }
+        assert!(
+            KafkaStreamingSource::payload_to_values(&format, &datum, &columns)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn kafka_json_messages() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic("events", 1, 1).unwrap();
+
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .create()
+            .unwrap();
+        for payload in [
+            r#"{"event": "load", "count": 1}"#,
+            r#"{"event": "click", "count": "2", "extra": true}"#,
+        ] {
+            producer
+                .send(
+                    FutureRecord::<(), _>::to("events").payload(payload),
+                    Duration::from_secs(5),
+                )
+                .await
+                .unwrap();
+        }
+
+        let source = KafkaStreamingSource::new(
+            cluster.bootstrap_servers(),
+            "events".to_string(),
+            "cubestore-test".to_string(),
+            None,
+            None,
+            false,
+            KafkaMessageFormat::Json,
+            false,
+        )
+        .unwrap();
+        let columns = vec![
+            Column::new("event".to_string(), ColumnType::String, 0),
+            Column::new("count".to_string(), ColumnType::Int, 1),
+            Column::new("__seq".to_string(), ColumnType::Int, 2),
+        ];
+        let mut stream = source
+            .row_stream(columns.clone(), columns[2].clone(), StreamOffsets::new())
+            .await
+            .unwrap();
+
+        let mut rows = Vec::new();
+        let mut offsets = StreamOffsets::new();
+        while rows.len() < 2 {
+            let (batch, batch_offsets) =
+                tokio::time::timeout(Duration::from_secs(30), stream.next())
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+            rows.extend(batch);
+            offsets = batch_offsets;
+        }
+        assert_eq!(
+            rows,
+            vec![
+                Row::new(vec![
+                    TableValue::String("load".to_string()),
+                    TableValue::Int(1),
+                    TableValue::Int(0),
+                ]),
+                Row::new(vec![
+                    TableValue::String("click".to_string()),
+                    TableValue::Int(2),
+                    TableValue::Int(1),
+                ]),
+            ]
+        );
+        assert_eq!(offsets.get(0), Some(1));
+
+        // Resumed stream starts right after the stored position.
+        let mut resumed_offsets = StreamOffsets::new();
+        resumed_offsets.set(0, 0);
+        let mut stream = source
+            .row_stream(columns.clone(), columns[2].clone(), resumed_offsets)
+            .await
+            .unwrap();
+        let (batch, _) = tokio::time::timeout(Duration::from_secs(30), stream.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            batch,
+            vec![Row::new(vec![
+                TableValue::String("click".to_string()),
+                TableValue::Int(2),
+                TableValue::Int(1),
+            ])]
+        );
+    }
+
+    #[tokio::test]
+    async fn kafka_invalid_messages() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic("events", 1, 1).unwrap();
+
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .create()
+            .unwrap();
+        for payload in [r#"{"event": "load"}"#, "not json", r#"{"event": "click"}"#] {
+            producer
+                .send(
+                    FutureRecord::<(), _>::to("events").payload(payload),
+                    Duration::from_secs(5),
+                )
+                .await
+                .unwrap();
+        }
+        let columns = vec![
+            Column::new("event".to_string(), ColumnType::String, 0),
+            Column::new("__seq".to_string(), ColumnType::Int, 1),
+        ];
+        let source = |skip_invalid_messages| {
+            KafkaStreamingSource::new(
+                cluster.bootstrap_servers(),
+                "events".to_string(),
+                "cubestore-test".to_string(),
+                None,
+                None,
+                false,
+                KafkaMessageFormat::Json,
+                skip_invalid_messages,
+            )
+            .unwrap()
+        };
+
+        let mut stream = source(false)
+            .row_stream(columns.clone(), columns[1].clone(), StreamOffsets::new())
+            .await
+            .unwrap();
+        loop {
+            let batch = tokio::time::timeout(Duration::from_secs(30), stream.next())
+                .await
+                .unwrap()
+                .unwrap();
+            if let Err(e) = batch {
+                assert!(e.message.contains("offset 1"), "{}", e);
+                break;
+            }
+        }
+
+        let mut stream = source(true)
+            .row_stream(columns.clone(), columns[1].clone(), StreamOffsets::new())
+            .await
+            .unwrap();
+        let mut rows = Vec::new();
+        let mut offsets = StreamOffsets::new();
+        while offsets.get(0) != Some(2) {
+            let (batch, batch_offsets) =
+                tokio::time::timeout(Duration::from_secs(30), stream.next())
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+            rows.extend(batch);
+            offsets = batch_offsets;
+        }
+        assert_eq!(
+            rows,
+            vec![
+                Row::new(vec![
+                    TableValue::String("load".to_string()),
+                    TableValue::Int(0),
+                ]),
+                Row::new(vec![
+                    TableValue::String("click".to_string()),
+                    TableValue::Int(2),
+                ]),
+            ]
+        );
+    }
+}
@@ -1,12 +1,16 @@
+pub mod avro;
+pub mod kafka;
+
 use crate::config::injection::DIService;
 use crate::config::ConfigObj;
 use crate::metastore::source::SourceCredentials;
-use crate::metastore::table::Table;
+use crate::metastore::table::{StreamOffsets, Table, TableState};
 use crate::metastore::{Column, ColumnType, IdRow, MetaStore};
 use crate::sql::timestamp_from_string;
 use crate::store::ChunkDataStore;
+use crate::streaming::kafka::KafkaStreamingSource;
 use crate::table::data::{append_row, create_array_builders};
-use crate::table::{Row, TableValue};
+use crate::table::{DateValue, Row, TableValue};
 use crate::util::decimal::Decimal;
 use crate::CubeError;
 use arrow::array::ArrayBuilder;
@@ -17,15 +21,20 @@ use futures::stream::StreamExt;
 use futures::Stream;
 use itertools::{EitherOrBoth, Itertools};
 use json::JsonValue;
-use log::debug;
+use log::{debug, info};
 use reqwest::{Response, Url};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::{Cursor, Write};
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
+use tokio::time::Instant;
 use warp::hyper::body::Bytes;
 
+/// How often running streams check whether their table was paused or sealed.
+const STREAM_STATE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
 #[async_trait]
 pub trait StreamingService: DIService + Send + Sync {
     async fn stream_table(&self, table: IdRow<Table>, location: &str) -> Result<(), CubeError>;
@@ -52,7 +61,34 @@ impl StreamingServiceImpl {
         })
     }
 
-    async fn source_by(&self, location: &str) -> Result<Arc<dyn StreamingSource>, CubeError> {
+    async fn is_stream_stopped(
+        &self,
+        table: &IdRow<Table>,
+        location: &str,
+    ) -> Result<bool, CubeError> {
+        let state = self
+            .meta_store
+            .get_table_by_id(table.get_id())
+            .await?
+            .get_row()
+            .state();
+        if state != TableState::Active {
+            info!(
+                "Stopping stream {} for {} table '{}'",
+                location,
+                state.as_str(),
+                table.get_row().get_table_name()
+            );
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn source_by(
+        &self,
+        table: &IdRow<Table>,
+        location: &str,
+    ) -> Result<Arc<dyn StreamingSource>, CubeError> {
         let location_url = Url::parse(location)?;
         if location_url.scheme() != "stream" {
             return Err(CubeError::internal(format!(
@@ -83,6 +119,43 @@ impl StreamingServiceImpl {
                 table: location_url.path().to_string().replace("/", ""),
                 endpoint_url: url.to_string(),
             })),
+            SourceCredentials::Kafka {
+                brokers,
+                topic,
+                consumer_group,
+                user,
+                password,
+                use_ssl,
+                format,
+                skip_invalid_messages,
+            } => {
+                let location_topic = location_url.path().to_string().replace("/", "");
+                let topic = if !location_topic.is_empty() {
+                    location_topic
+                } else {
+                    topic.clone().ok_or_else(|| {
+                        CubeError::user(format!(
+                            "Topic should be set either in '{}' source or as stream://<source_name>/<topic> location but '{}' found",
+                            meta_source.get_row().name(),
+                            location
+                        ))
+                    })?
+                };
+                // Every table gets its own consumer group by default so it reads the whole topic.
+                let consumer_group = consumer_group
+                    .clone()
+                    .unwrap_or_else(|| format!("cubestore-{}-{}", topic, table.get_id()));
+                Ok(Arc::new(KafkaStreamingSource::new(
+                    brokers.to_string(),
+                    topic,
+                    consumer_group,
+                    user.clone(),
+                    password.clone(),
+                    *use_ssl,
+                    format.clone(),
+                    *skip_invalid_messages,
+                )?))
+            }
         }
     }
 }
@@ -90,22 +163,23 @@ impl StreamingServiceImpl {
 #[async_trait]
 impl StreamingService for StreamingServiceImpl {
     async fn stream_table(&self, table: IdRow<Table>, location: &str) -> Result<(), CubeError> {
-        let source = self.source_by(location).await?;
+        let source = self.source_by(&table, location).await?;
         let seq_column = table.get_row().seq_column().ok_or_else(|| {
             CubeError::internal(format!(
                 "Seq column is not defined for streaming table '{}'",
                 table.get_row().get_table_name()
             ))
         })?;
+        let initial_offsets = table
+            .get_row()
+            .stream_offsets(location)
+            .cloned()
+            .unwrap_or_default();
         let mut stream = source
             .row_stream(
                 table.get_row().get_columns().clone(),
                 seq_column.clone(),
-                (SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis()
-                    * 1000) as u64, // TODO store initial sequence number
+                initial_offsets,
             )
             .await?;
 
@@ -113,19 +187,32 @@ impl StreamingService for StreamingServiceImpl {
             builders.into_iter().map(|mut b| b.finish()).collect_vec()
         };
 
-        // TODO support sealing streaming tables through ALTER TABLE
-        while let Some(new_rows) = tokio::time::timeout(
-            Duration::from_secs(self.config_obj.stale_stream_timeout()),
-            stream.next(),
-        )
-        .await?
-        {
-            let rows = new_rows?;
+        let stale_stream_timeout = Duration::from_secs(self.config_obj.stale_stream_timeout());
+        let mut stale_deadline = Instant::now() + stale_stream_timeout;
+        let mut state_check = tokio::time::interval(STREAM_STATE_CHECK_INTERVAL);
+        loop {
+            let new_rows = tokio::select! {
+                new_rows = tokio::time::timeout_at(stale_deadline, stream.next()) => new_rows?,
+                _ = state_check.tick() => {
+                    if self.is_stream_stopped(&table, location).await? {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+            let (rows, offsets) = match new_rows {
+                Some(new_rows) => new_rows?,
+                None => break,
+            };
+            stale_deadline = Instant::now() + stale_stream_timeout;
             debug!("Received {} rows for {}", rows.len(), location);
+            if rows.is_empty() {
+                continue;
+            }
             let table_cols = table.get_row().get_columns().as_slice();
-            let mut builders = create_array_builders(table_cols);
+            let mut builders = create_array_builders(table_cols)?;
             for row in rows {
-                append_row(&mut builders, table_cols, &row);
+                append_row(&mut builders, table_cols, &row)?;
             }
             let new_chunks = self
                 .chunk_store
@@ -145,24 +232,60 @@ impl StreamingService for StreamingServiceImpl {
                     Ok((c.get_id(), file_size))
                 })
                 .collect();
-            self.meta_store
-                .activate_chunks(table.get_id(), new_chunk_ids?)
-                .await?;
+            // Stream position is committed along with the data, so restarted stream resumes
+            // right after the last ingested row.
+            // Activation is rejected if table was sealed or paused since the last state check.
+            if let Err(e) = self
+                .meta_store
+                .activate_stream_chunks(
+                    table.get_id(),
+                    location.to_string(),
+                    new_chunk_ids?,
+                    offsets,
+                )
+                .await
+            {
+                if self.is_stream_stopped(&table, location).await? {
+                    return Ok(());
+                }
+                return Err(e);
+            }
         }
         Ok(())
     }
 }
 
+/// Rows received from a stream along with the stream position right after them.
+pub type StreamBatch = (Vec<Row>, StreamOffsets);
+
+/// Kafka offsets are only unique within a partition, so the sequence keeps the partition in the
+/// upper 16 bits and the offset in the lower 48 bits. This keeps sequence values unique
+/// across partitions and ordered by offset within each of them.
+pub(crate) fn partition_offset_seq(partition: i32, offset: i64) -> Result<i64, CubeError> {
+    if !(0..=i16::MAX as i32).contains(&partition) || !(0..1 << 48).contains(&offset) {
+        return Err(CubeError::internal(format!(
+            "Partition {} offset {} can't be used as a sequence",
+            partition, offset
+        )));
+    }
+    Ok(((partition as i64) << 48) | offset)
+}
+
 #[async_trait]
 pub trait StreamingSource: Send + Sync {
+    /// Streams rows ingested after `initial_offsets`, which are empty for a fresh table.
+    /// Sources may redeliver rows and are responsible to drop ones with already ingested sequence values.
     async fn row_stream(
         &self,
         columns: Vec<Column>,
         seq_column: Column,
-        initial_seq_value: u64,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<Vec<Row>, CubeError>> + Send>>, CubeError>;
+        initial_offsets: StreamOffsets,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamBatch, CubeError>> + Send>>, CubeError>;
 }
 
+/// ksql pseudo columns with the source topic position of a row.
+const KSQL_POSITION_COLUMNS: [&str; 2] = ["ROWPARTITION", "ROWOFFSET"];
+
 #[derive(Clone)]
 pub struct KSqlStreamingSource {
     user: Option<String>,
@@ -179,6 +302,8 @@ pub struct KSqlError {
 #[derive(Serialize, Deserialize)]
 pub struct KSqlQuery {
     sql: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    properties: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -194,7 +319,7 @@ pub struct KSqlQuerySchema {
 impl KSqlStreamingSource {
     fn parse_lines(
         tail_bytes: &mut Bytes,
-        seq_value: &mut u64,
+        offsets: &mut StreamOffsets,
         bytes: Result<Bytes, reqwest::Error>,
         columns: Vec<Column>,
         seq_column: Column,
@@ -231,11 +356,18 @@ impl KSqlStreamingSource {
                     .filter(|c| c.get_name() != seq_column.get_name())
                     .map(|c| c.get_name().to_string())
                     .collect::<Vec<_>>();
-                let ksql_column_names = schema
+                let mut ksql_column_names = schema
                     .column_names
                     .iter()
                     .map(|c| c.to_string())
                     .collect::<Vec<_>>();
+                if !ksql_column_names.ends_with(&KSQL_POSITION_COLUMNS.map(|c| c.to_string())) {
+                    return Err(CubeError::internal(format!(
+                        "ksql stream columns are expected to end with {:?} but found: {:?}",
+                        KSQL_POSITION_COLUMNS, ksql_column_names
+                    )));
+                }
+                ksql_column_names.truncate(ksql_column_names.len() - KSQL_POSITION_COLUMNS.len());
                 if ksql_column_names != schema_column_names {
                     return Err(CubeError::user(format!(
                         "Column names of ksql stream and table doesn't match: {:?} and {:?}",
@@ -245,7 +377,39 @@ impl KSqlStreamingSource {
                 continue;
             }
             let row_values = match res {
-                JsonValue::Array(values) => values
+                JsonValue::Array(mut values) => {
+                    let (partition, offset) = match (values.pop(), values.pop()) {
+                        (Some(offset), Some(partition)) => (
+                            partition.as_i32().ok_or_else(|| {
+                                CubeError::internal(format!(
+                                    "ksql source returned {:?} as row partition",
+                                    partition
+                                ))
+                            })?,
+                            offset.as_i64().ok_or_else(|| {
+                                CubeError::internal(format!(
+                                    "ksql source returned {:?} as row offset",
+                                    offset
+                                ))
+                            })?,
+                        ),
+                        _ => {
+                            return Err(CubeError::internal(
+                                "ksql source returned row without position".to_string(),
+                            ))
+                        }
+                    };
+                    // Replayed rows that are already ingested are skipped.
+                    if offsets
+                        .get(partition)
+                        .map(|last_offset| offset <= last_offset)
+                        .unwrap_or(false)
+                    {
+                        continue;
+                    }
+                    offsets.set(partition, offset);
+                    let seq = partition_offset_seq(partition, offset)?;
+                    values
                     .into_iter()
                     .zip_longest(columns.iter())
                     .map(|zip| {
@@ -289,6 +453,13 @@ impl KSqlStreamingSource {
                                             ))),
                                         }
                                     }
+                                    ColumnType::TDigest => {
+                                        match value {
+                                            _ => Err(CubeError::internal(format!(
+                                                "ksql source t-digest import isn't supported"
+                                            ))),
+                                        }
+                                    }
                                     ColumnType::Timestamp => {
                                         match value {
                                             JsonValue::Short(v) => Ok(TableValue::Timestamp(timestamp_from_string(v.as_str())?)),
@@ -330,13 +501,59 @@ impl KSqlStreamingSource {
                                             ))),
                                         }
                                     }
+                                    ColumnType::Int32 => {
+                                        match value {
+                                            JsonValue::Number(v) => Ok(TableValue::Int(v.as_fixed_point_i64(0).filter(|v| i32::try_from(*v).is_ok()).ok_or(CubeError::user(format!("Can't convert {:?} to int32", v)))?)),
+                                            JsonValue::Null => Ok(TableValue::Null),
+                                            x => Err(CubeError::internal(format!(
+                                                "ksql source returned {:?} as row value but int expected",
+                                                x
+                                            ))),
+                                        }
+                                    }
+                                    ColumnType::Int16 => {
+                                        match value {
+                                            JsonValue::Number(v) => Ok(TableValue::Int(v.as_fixed_point_i64(0).filter(|v| i16::try_from(*v).is_ok()).ok_or(CubeError::user(format!("Can't convert {:?} to int16", v)))?)),
+                                            JsonValue::Null => Ok(TableValue::Null),
+                                            x => Err(CubeError::internal(format!(
+                                                "ksql source returned {:?} as row value but int expected",
+                                                x
+                                            ))),
+                                        }
+                                    }
+                                    ColumnType::Int8 => {
+                                        match value {
+                                            JsonValue::Number(v) => Ok(TableValue::Int(v.as_fixed_point_i64(0).filter(|v| i8::try_from(*v).is_ok()).ok_or(CubeError::user(format!("Can't convert {:?} to tinyint", v)))?)),
+                                            JsonValue::Null => Ok(TableValue::Null),
+                                            x => Err(CubeError::internal(format!(
+                                                "ksql source returned {:?} as row value but int expected",
+                                                x
+                                            ))),
+                                        }
+                                    }
+                                    ColumnType::Date => {
+                                        match value {
+                                            JsonValue::Short(v) => Ok(TableValue::Date(DateValue::parse(v.as_str())?)),
+                                            JsonValue::String(v) => Ok(TableValue::Date(DateValue::parse(v.as_str())?)),
+                                            JsonValue::Null => Ok(TableValue::Null),
+                                            x => Err(CubeError::internal(format!(
+                                                "ksql source returned {:?} as row value but date string expected",
+                                                x
+                                            ))),
+                                        }
+                                    }
+                                    ColumnType::List(_) => {
+                                        match value {
+                                            _ => Err(CubeError::internal(format!(
+                                                "ksql source array import isn't supported"
+                                            ))),
+                                        }
+                                    }
                                 }
                             }
                             EitherOrBoth::Right(col) => {
                                 if col.get_name() == seq_column.get_name() {
-                                    let res = TableValue::Int(*seq_value as i64);
-                                    *seq_value += 1;
-                                    Ok(res)
+                                    Ok(TableValue::Int(seq))
                                 } else {
                                     Err(CubeError::internal(format!(
                                         "Sequence column is expected but {:?} is found",
@@ -353,7 +570,8 @@ impl KSqlStreamingSource {
                         }
 
                     })
-                    .collect::<Result<Vec<TableValue>, CubeError>>(),
+                    .collect::<Result<Vec<TableValue>, CubeError>>()
+                }
                 x => Err(CubeError::internal(format!(
                     "ksql source returned {:?} but array was expected",
                     x
@@ -398,60 +616,132 @@ impl StreamingSource for KSqlStreamingSource {
         &self,
         columns: Vec<Column>,
         seq_column: Column,
-        initial_seq_value: u64,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<Vec<Row>, CubeError>> + Send>>, CubeError> {
+        initial_offsets: StreamOffsets,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamBatch, CubeError>> + Send>>, CubeError> {
+        // ksql push queries can't be resumed from a position. Resumed stream is replayed from
+        // the earliest offset instead and rows up to the stored source offsets are skipped.
+        // Sequence is derived from the source partition and offset, so replayed rows get the
+        // same sequence values.
+        let properties = if initial_offsets.is_empty() {
+            None
+        } else {
+            Some(
+                vec![("auto.offset.reset".to_string(), "earliest".to_string())]
+                    .into_iter()
+                    .collect(),
+            )
+        };
         let res = self
             .post_req(
                 "/query-stream",
                 &KSqlQuery {
-                    sql: format!("SELECT * FROM `{}` EMIT CHANGES;", self.table),
+                    sql: format!(
+                        "SELECT *, {} FROM `{}` EMIT CHANGES;",
+                        KSQL_POSITION_COLUMNS.join(", "),
+                        self.table
+                    ),
+                    properties,
                 },
             )
             .await?;
         let column_to_move = columns.clone();
         let seq_column_to_move = seq_column.clone();
-        Ok(
-            Box::pin(
-                res.bytes_stream()
-                    .scan(
-                        (Bytes::new(), initial_seq_value),
-                        move |(tail_bytes, seq_value),
-                              bytes: Result<_, _>|
-                              -> futures_util::future::Ready<
-                            Option<Result<Vec<Row>, CubeError>>,
-                        > {
-                            let rows = Self::parse_lines(
-                                tail_bytes,
-                                seq_value,
-                                bytes,
-                                column_to_move.clone(),
-                                seq_column_to_move.clone(),
-                            )
-                            .map_err(|e| {
-                                CubeError::internal(format!(
-                                    "Error during parsing ksql response: {}",
-                                    e
-                                ))
-                            });
-                            futures_util::future::ready(Some(rows))
-                        },
-                    )
-                    .ready_chunks(16384)
-                    .map(move |chunks| -> Result<Vec<Row>, CubeError> {
-                        let mut rows = Vec::new();
-                        for chunk in chunks.into_iter() {
-                            match chunk {
-                                Ok(mut vec) => {
-                                    rows.append(&mut vec);
-                                }
-                                Err(e) => {
-                                    return Err(e);
-                                }
-                            }
-                        }
-                        Ok(rows)
-                    }),
-            ),
+        Ok(Box::pin(
+            res.bytes_stream()
+                .scan(
+                    (Bytes::new(), initial_offsets),
+                    move |(tail_bytes, offsets),
+                          bytes: Result<_, _>|
+                          -> futures_util::future::Ready<
+                        Option<Result<StreamBatch, CubeError>>,
+                    > {
+                        let rows = Self::parse_lines(
+                            tail_bytes,
+                            offsets,
+                            bytes,
+                            column_to_move.clone(),
+                            seq_column_to_move.clone(),
+                        )
+                        .map(|rows| (rows, offsets.clone()))
+                        .map_err(|e| {
+                            CubeError::internal(format!(
+                                "Error during parsing ksql response: {}",
+                                e
+                            ))
+                        });
+                        futures_util::future::ready(Some(rows))
+                    },
+                )
+                .ready_chunks(16384)
+                .map(move |chunks| -> Result<StreamBatch, CubeError> {
+                    let mut rows = Vec::new();
+                    let mut offsets = StreamOffsets::new();
+                    for chunk in chunks.into_iter() {
+                        let (mut vec, chunk_offsets) = chunk?;
+                        rows.append(&mut vec);
+                        offsets = chunk_offsets;
+                    }
+                    Ok((rows, offsets))
+                }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partition_offset_seq_order() {
+        assert_eq!(partition_offset_seq(0, 5).unwrap(), 5);
+        assert_eq!(partition_offset_seq(1, 0).unwrap(), 1 << 48);
+        assert!(partition_offset_seq(0, 7).unwrap() < partition_offset_seq(0, 8).unwrap());
+        assert!(partition_offset_seq(0, 1 << 48).is_err());
+        assert!(partition_offset_seq(-1, 0).is_err());
+    }
+
+    #[test]
+    fn ksql_rows_are_resumed_by_offsets() {
+        let columns = vec![
+            Column::new("A".to_string(), ColumnType::String, 0),
+            Column::new("__seq".to_string(), ColumnType::Int, 1),
+        ];
+        let mut offsets = StreamOffsets::new();
+        offsets.set(0, 4);
+        let mut tail_bytes = Bytes::new();
+        let rows = KSqlStreamingSource::parse_lines(
+            &mut tail_bytes,
+            &mut offsets,
+            Ok(Bytes::from(concat!(
+                r#"{"queryId":"q1","columnNames":["A","ROWPARTITION","ROWOFFSET"],"columnTypes":["STRING","INTEGER","BIGINT"]}"#,
+                "\n",
+                r#"["replayed",0,4]"#,
+                "\n",
+                r#"["new",0,5]"#,
+                "\n",
+                r#"["other",1,0]"#,
+                "\n",
+                r#"["partial""#,
+            ))),
+            columns.clone(),
+            columns[1].clone(),
         )
+        .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                Row::new(vec![
+                    TableValue::String("new".to_string()),
+                    TableValue::Int(5)
+                ]),
+                Row::new(vec![
+                    TableValue::String("other".to_string()),
+                    TableValue::Int(1 << 48)
+                ]),
+            ]
+        );
+        assert_eq!(offsets.get(0), Some(5));
+        assert_eq!(offsets.get(1), Some(0));
+        assert_eq!(tail_bytes, Bytes::from(r#"["partial""#));
     }
 }
@@ -1,15 +1,24 @@
 use crate::metastore::{Column, ColumnType};
-use crate::table::{Row, TableValue, TimestampValue};
+use crate::table::{DateValue, Row, TableValue, TimestampValue};
 use crate::util::decimal::Decimal;
-use arrow::array::{Array, ArrayBuilder, ArrayRef, StringArray};
+use crate::CubeError;
+use arrow::array::{Array, ArrayBuilder, ArrayRef, ListBuilder, StringArray};
+use arrow::datatypes::{Field, Schema};
 use arrow::record_batch::RecordBatch;
 use itertools::Itertools;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 
 use datafusion::cube_ext::ordfloat::OrdF64;
+use datafusion::error::DataFusionError;
+use datafusion::logical_plan::{Expr, ExprRewriter};
+use datafusion::physical_plan::expressions::{Column as FusionColumn, Literal};
 use datafusion::physical_plan::memory::MemoryExec;
-use datafusion::physical_plan::{ExecutionPlan, SendableRecordBatchStream};
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::{ExecutionPlan, PhysicalExpr, SendableRecordBatchStream};
+use datafusion::scalar::ScalarValue;
 use std::fmt;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum TableValueR<'a> {
@@ -21,6 +30,8 @@ pub enum TableValueR<'a> {
     Bytes(&'a [u8]),
     Timestamp(TimestampValue),
     Boolean(bool),
+    Date(DateValue),
+    List(&'a [TableValue]),
 }
 
 impl TableValueR<'_> {
@@ -34,6 +45,8 @@ impl TableValueR<'_> {
             TableValue::Bytes(b) => TableValueR::Bytes(&b),
             TableValue::Timestamp(v) => TableValueR::Timestamp(v.clone()),
             TableValue::Boolean(v) => TableValueR::Boolean(*v),
+            TableValue::Date(v) => TableValueR::Date(*v),
+            TableValue::List(v) => TableValueR::List(v.as_slice()),
         }
     }
 }
@@ -122,6 +135,11 @@ pub fn cmp_same_types(l: &TableValueR, r: &TableValueR) -> Ordering {
         (TableValueR::Bytes(a), TableValueR::Bytes(b)) => a.cmp(b),
         (TableValueR::Timestamp(a), TableValueR::Timestamp(b)) => a.cmp(b),
         (TableValueR::Boolean(a), TableValueR::Boolean(b)) => a.cmp(b),
+        (TableValueR::Date(a), TableValueR::Date(b)) => a.cmp(b),
+        (TableValueR::List(a), TableValueR::List(b)) => crate::table::cmp_same_types(
+            &TableValue::List(a.to_vec()),
+            &TableValue::List(b.to_vec()),
+        ),
         (a, b) => panic!("Can't compare {:?} to {:?}", a, b),
     }
 }
@@ -149,34 +167,62 @@ macro_rules! match_column_type {
                 n => panic!("unhandled target scale: {}", n),
             },
             ColumnType::Float => $matcher!(Float, Float64Builder, Float),
+            ColumnType::Int32 => $matcher!(Int32, Int32Builder, Int),
+            ColumnType::Int16 => $matcher!(Int16, Int16Builder, Int),
+            ColumnType::Int8 => $matcher!(Int8, Int8Builder, Int),
+            ColumnType::Date => $matcher!(Date, Date32Builder, Date),
+            ColumnType::List(_) => {
+                return Err(CubeError::internal(format!(
+                    "{:?} column must be matched on its item type",
+                    t
+                )))
+            }
+            ColumnType::TDigest => $matcher!(TDigest, BinaryBuilder, Bytes),
         }
     }};
 }
 
-pub fn create_array_builder(t: &ColumnType) -> Box<dyn ArrayBuilder> {
+pub fn create_array_builder(t: &ColumnType) -> Result<Box<dyn ArrayBuilder>, CubeError> {
     macro_rules! create_builder {
         ($type: tt, $builder: tt $(,$arg: tt)*) => {
-            Box::new($builder::new(0))
+            Ok(Box::new($builder::new(0)) as Box<dyn ArrayBuilder>)
+        };
+    }
+    macro_rules! create_list_builder {
+        ($type: tt, $builder: tt $(,$arg: tt)*) => {
+            Ok(Box::new(ListBuilder::new($builder::new(0))) as Box<dyn ArrayBuilder>)
         };
     }
+    if let ColumnType::List(item) = t {
+        return match_column_type!(item.as_ref(), create_list_builder);
+    }
     match_column_type!(t, create_builder)
 }
 
-pub fn create_array_builders(cs: &[Column]) -> Vec<Box<dyn ArrayBuilder>> {
+pub fn create_array_builders(cs: &[Column]) -> Result<Vec<Box<dyn ArrayBuilder>>, CubeError> {
     cs.iter()
         .map(|c| create_array_builder(c.get_column_type()))
-        .collect_vec()
+        .collect()
 }
 
-pub fn append_row(bs: &mut [Box<dyn ArrayBuilder>], cs: &[Column], r: &Row) {
+pub fn append_row(
+    bs: &mut [Box<dyn ArrayBuilder>],
+    cs: &[Column],
+    r: &Row,
+) -> Result<(), CubeError> {
     assert_eq!(bs.len(), r.len());
     assert_eq!(cs.len(), r.len());
     for i in 0..r.len() {
-        append_value(bs[i].as_mut(), cs[i].get_column_type(), &r.values()[i]);
+        append_value(bs[i].as_mut(), cs[i].get_column_type(), &r.values()[i])?;
     }
+    Ok(())
 }
 
-pub fn append_value(b: &mut dyn ArrayBuilder, c: &ColumnType, v: &TableValue) {
+pub fn append_value(
+    b: &mut dyn ArrayBuilder,
+    c: &ColumnType,
+    v: &TableValue,
+) -> Result<(), CubeError> {
     let is_null = matches!(v, TableValue::Null);
     macro_rules! convert_value {
         (Decimal, $v: expr) => {{
@@ -194,7 +240,25 @@ pub fn append_value(b: &mut dyn ArrayBuilder, c: &ColumnType, v: &TableValue) {
         (Bytes, $v: expr) => {{
             $v.as_slice()
         }};
-        ($tv_enum: tt, $v: expr) => {{
+        (HyperLogLog, $v: expr) => {{
+            $v.as_slice()
+        }};
+        (TDigest, $v: expr) => {{
+            $v.as_slice()
+        }};
+        (Int32, $v: expr) => {{
+            *$v as i32
+        }};
+        (Int16, $v: expr) => {{
+            *$v as i16
+        }};
+        (Int8, $v: expr) => {{
+            *$v as i8
+        }};
+        (Date, $v: expr) => {{
+            $v.get_days_since_epoch()
+        }};
+        ($type: tt, $v: expr) => {{
             *$v
         }};
     }
@@ -202,25 +266,163 @@ pub fn append_value(b: &mut dyn ArrayBuilder, c: &ColumnType, v: &TableValue) {
         ($type: tt, $builder: tt, $tv_enum: tt $(, $arg:tt)*) => {{
             let b = b.as_any_mut().downcast_mut::<$builder>().unwrap();
             if is_null {
-                b.append_null().unwrap();
-                return;
+                b.append_null()?;
+                return Ok(());
             }
             let v = match v {
-                TableValue::$tv_enum(v) => convert_value!($tv_enum, v),
-                other => panic!("unexpected value {:?} for type {:?}", other, c),
+                TableValue::$tv_enum(v) => convert_value!($type, v),
+                other => {
+                    return Err(CubeError::internal(format!(
+                        "Unexpected value {:?} for type {:?}",
+                        other, c
+                    )))
+                }
             };
-            b.append_value(v).unwrap();
+            b.append_value(v)?;
+            Ok(())
+        }};
+    }
+    macro_rules! append_list {
+        ($type: tt, $builder: tt, $tv_enum: tt $(, $arg:tt)*) => {{
+            let b = b
+                .as_any_mut()
+                .downcast_mut::<ListBuilder<$builder>>()
+                .unwrap();
+            match v {
+                TableValue::Null => b.append(false)?,
+                TableValue::List(items) => {
+                    for item in items {
+                        append_value(b.values(), item_type, item)?;
+                    }
+                    b.append(true)?;
+                }
+                other => {
+                    return Err(CubeError::internal(format!(
+                        "Unexpected value {:?} for type {:?}",
+                        other, c
+                    )))
+                }
+            }
+            Ok(())
         }};
     }
+    if let ColumnType::List(item_type) = c {
+        let item_type = item_type.as_ref();
+        return match_column_type!(item_type, append_list);
+    }
     match_column_type!(c, append)
 }
 
-pub fn rows_to_columns(cols: &[Column], rows: &[Row]) -> Vec<ArrayRef> {
-    let mut builders = create_array_builders(&cols);
+pub fn rows_to_columns(cols: &[Column], rows: &[Row]) -> Result<Vec<ArrayRef>, CubeError> {
+    let mut builders = create_array_builders(&cols)?;
     for r in rows {
-        append_row(&mut builders, &cols, r);
+        append_row(&mut builders, &cols, r)?;
+    }
+    Ok(builders.into_iter().map(|mut b| b.finish()).collect_vec())
+}
+
+/// Column values for rows written before the column was added to the table.
+pub fn default_value_array(column: &Column, len: usize) -> Result<ArrayRef, CubeError> {
+    let mut b = create_array_builder(column.get_column_type())?;
+    let value = column.get_default_value().unwrap_or(&TableValue::Null);
+    for _ in 0..len {
+        append_value(b.as_mut(), column.get_column_type(), value)?;
+    }
+    Ok(b.finish())
+}
+
+/// Position of `column` in the data written with older columns of the index.
+fn evolved_column_position(schema: &Schema, column: &Column) -> Option<usize> {
+    let field: Field = column.into();
+    schema
+        .index_of(column.get_name())
+        .ok()
+        .filter(|i| schema.field(*i).data_type() == field.data_type())
+}
+
+fn is_evolved(schema: &Schema, columns: &[Column]) -> bool {
+    schema.fields().len() != columns.len()
+        || schema
+            .fields()
+            .iter()
+            .zip(columns)
+            .any(|(f, c)| f.name() != c.get_name())
+}
+
+/// Converts data written before the columns of the index were altered to `columns`.
+/// Added columns get their default values and dropped ones are skipped.
+pub fn evolve_batch(batch: RecordBatch, columns: &[Column]) -> Result<RecordBatch, CubeError> {
+    let schema = batch.schema();
+    if !is_evolved(schema.as_ref(), columns) {
+        return Ok(batch);
+    }
+    let arrays = columns
+        .iter()
+        .map(|c| match evolved_column_position(schema.as_ref(), c) {
+            Some(i) => Ok(batch.column(i).clone()),
+            None => default_value_array(c, batch.num_rows()),
+        })
+        .collect::<Result<Vec<_>, CubeError>>()?;
+    let schema = Arc::new(Schema::new(columns.iter().map(|c| c.into()).collect()));
+    Ok(RecordBatch::try_new(schema, arrays)?)
+}
+
+/// Same as [evolve_batch], but for the data produced by `plan`.
+pub fn evolve_plan(
+    plan: Arc<dyn ExecutionPlan>,
+    columns: &[Column],
+) -> Result<Arc<dyn ExecutionPlan>, CubeError> {
+    let schema = plan.schema();
+    if !is_evolved(schema.as_ref(), columns) {
+        return Ok(plan);
+    }
+    let mut exprs = Vec::with_capacity(columns.len());
+    for c in columns {
+        let expr: Arc<dyn PhysicalExpr> = match evolved_column_position(schema.as_ref(), c) {
+            Some(i) => Arc::new(FusionColumn::new(c.get_name(), i)),
+            None => Arc::new(Literal::new(ScalarValue::try_from_array(
+                &default_value_array(c, 1)?,
+                0,
+            )?)),
+        };
+        exprs.push((expr, c.get_name().clone()));
+    }
+    Ok(Arc::new(ProjectionExec::try_new(exprs, plan)?))
+}
+
+/// Rewrites `predicate` on `columns` to the data written before the columns were altered, so it
+/// can still be used to prune the data. Columns missing in `schema` are replaced by their default values.
+pub fn evolve_predicate(
+    predicate: Expr,
+    schema: &Schema,
+    columns: &[Column],
+) -> Result<Expr, CubeError> {
+    let mut defaults = HashMap::new();
+    for c in columns {
+        if evolved_column_position(schema, c).is_none() {
+            let value = ScalarValue::try_from_array(&default_value_array(c, 1)?, 0)?;
+            defaults.insert(c.get_name().clone(), value);
+        }
+    }
+    if defaults.is_empty() {
+        return Ok(predicate);
+    }
+
+    struct Rewriter {
+        defaults: HashMap<String, ScalarValue>,
+    }
+    impl ExprRewriter for Rewriter {
+        fn mutate(&mut self, expr: Expr) -> Result<Expr, DataFusionError> {
+            match &expr {
+                Expr::Column(c) => match self.defaults.get(&c.name) {
+                    Some(v) => Ok(Expr::Literal(v.clone())),
+                    None => Ok(expr),
+                },
+                _ => Ok(expr),
+            }
+        }
     }
-    builders.into_iter().map(|mut b| b.finish()).collect_vec()
+    Ok(predicate.rewrite(&mut Rewriter { defaults })?)
 }
 
 pub async fn to_stream(r: RecordBatch) -> SendableRecordBatchStream {
@@ -1,14 +1,18 @@
+use crate::metastore::ColumnType;
 use crate::util::decimal::Decimal;
+use crate::CubeError;
 
 use arrow::array::{
-    Array, ArrayRef, BinaryArray, BooleanArray, Float64Array, Int64Array, Int64Decimal0Array,
-    Int64Decimal10Array, Int64Decimal1Array, Int64Decimal2Array, Int64Decimal3Array,
-    Int64Decimal4Array, Int64Decimal5Array, StringArray, TimestampMicrosecondArray,
+    Array, ArrayRef, BinaryArray, BooleanArray, Date32Array, Float64Array, Int16Array, Int32Array,
+    Int64Array, Int64Decimal0Array, Int64Decimal10Array, Int64Decimal1Array, Int64Decimal2Array,
+    Int64Decimal3Array, Int64Decimal4Array, Int64Decimal5Array, Int8Array, ListArray, StringArray,
+    TimestampMicrosecondArray,
 };
 use arrow::datatypes::{DataType, TimeUnit};
 
-use chrono::{SecondsFormat, TimeZone, Utc};
+use chrono::{Duration, NaiveDate, SecondsFormat, TimeZone, Utc};
 use datafusion::cube_ext::ordfloat::OrdF64;
+use hex::ToHex;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
@@ -18,6 +22,7 @@ use std::fmt::{Debug, Formatter};
 pub mod data;
 pub(crate) mod parquet;
 pub mod redistribute;
+pub mod stats;
 
 #[derive(Clone, Serialize, Deserialize, Eq, PartialEq, Debug, Hash)]
 pub enum TableValue {
@@ -29,6 +34,8 @@ pub enum TableValue {
     Bytes(Vec<u8>),
     Timestamp(TimestampValue),
     Boolean(bool),
+    Date(DateValue),
+    List(Vec<TableValue>),
 }
 
 impl TableValue {
@@ -46,6 +53,26 @@ impl TableValue {
             DataType::Int64 => {
                 TableValue::Int(a.as_any().downcast_ref::<Int64Array>().unwrap().value(row))
             }
+            DataType::Int32 => {
+                TableValue::Int(a.as_any().downcast_ref::<Int32Array>().unwrap().value(row) as i64)
+            }
+            DataType::Int16 => {
+                TableValue::Int(a.as_any().downcast_ref::<Int16Array>().unwrap().value(row) as i64)
+            }
+            DataType::Int8 => {
+                TableValue::Int(a.as_any().downcast_ref::<Int8Array>().unwrap().value(row) as i64)
+            }
+            DataType::Date32 => TableValue::Date(DateValue::new(
+                a.as_any().downcast_ref::<Date32Array>().unwrap().value(row),
+            )),
+            DataType::List(_) => {
+                let items = a.as_any().downcast_ref::<ListArray>().unwrap().value(row);
+                TableValue::List(
+                    (0..items.len())
+                        .map(|i| TableValue::from_array(items.as_ref(), i))
+                        .collect(),
+                )
+            }
             DataType::Utf8 => TableValue::String(
                 a.as_any()
                     .downcast_ref::<StringArray>()
@@ -130,6 +157,32 @@ impl TableValue {
             ),
         }
     }
+
+    /// Formats list values as a JSON array. Nested values are formatted the same way as they are
+    /// in query results, `item_type` is required to get the scale of decimals.
+    pub fn list_to_string(items: &[TableValue], item_type: &ColumnType) -> String {
+        let items = items
+            .iter()
+            .map(|v| match v {
+                TableValue::Null => "null".to_string(),
+                TableValue::String(s) => serde_json::to_string(s).unwrap(),
+                TableValue::Int(i) => i.to_string(),
+                TableValue::Decimal(d) => {
+                    d.to_string(u8::try_from(item_type.target_scale()).unwrap())
+                }
+                TableValue::Float(f) => f.to_string(),
+                TableValue::Bytes(b) => format!("\"0x{}\"", b.encode_hex_upper::<String>()),
+                TableValue::Timestamp(t) => format!("\"{}\"", t.to_string()),
+                TableValue::Date(d) => format!("\"{}\"", d.to_string()),
+                TableValue::Boolean(b) => b.to_string(),
+                TableValue::List(l) => match item_type {
+                    ColumnType::List(t) => TableValue::list_to_string(l, t),
+                    t => panic!("list value for {} column", t),
+                },
+            })
+            .join(",");
+        format!("[{}]", items)
+    }
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -166,6 +219,53 @@ impl ToString for TimestampValue {
     }
 }
 
+/// Date without a time zone, stored as the number of days since the UNIX epoch.
+#[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct DateValue {
+    days_since_epoch: i32,
+}
+
+impl DateValue {
+    pub fn new(days_since_epoch: i32) -> DateValue {
+        DateValue { days_since_epoch }
+    }
+
+    pub fn get_days_since_epoch(&self) -> i32 {
+        self.days_since_epoch
+    }
+
+    /// Parses dates in the `YYYY-MM-DD` format. Time part of timestamps, if any, is ignored.
+    pub fn parse(s: &str) -> Result<DateValue, CubeError> {
+        let date_part = match s.char_indices().nth(10) {
+            Some((i, 'T' | ' ')) => &s[..i],
+            _ => s,
+        };
+        let date = NaiveDate::parse_from_str(date_part, "%Y-%m-%d")
+            .map_err(|e| CubeError::user(format!("Can't parse date '{}': {}", s, e)))?;
+        Ok(DateValue::new(
+            date.signed_duration_since(NaiveDate::from_ymd(1970, 1, 1))
+                .num_days() as i32,
+        ))
+    }
+}
+
+impl Debug for DateValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DateValue")
+            .field("days_since_epoch", &self.days_since_epoch)
+            .field("str", &self.to_string())
+            .finish()
+    }
+}
+
+impl ToString for DateValue {
+    fn to_string(&self) -> String {
+        (NaiveDate::from_ymd(1970, 1, 1) + Duration::days(self.days_since_epoch as i64))
+            .format("%Y-%m-%d")
+            .to_string()
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
 pub struct Row {
     values: Vec<TableValue>,
@@ -201,13 +301,24 @@ pub fn cmp_same_types(l: &TableValue, r: &TableValue) -> Ordering {
         (TableValue::Bytes(a), TableValue::Bytes(b)) => a.cmp(b),
         (TableValue::Timestamp(a), TableValue::Timestamp(b)) => a.cmp(b),
         (TableValue::Boolean(a), TableValue::Boolean(b)) => a.cmp(b),
+        (TableValue::Date(a), TableValue::Date(b)) => a.cmp(b),
+        (TableValue::List(a), TableValue::List(b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                let ord = cmp_same_types(a, b);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            a.len().cmp(&b.len())
+        }
         (a, b) => panic!("Can't compare {:?} to {:?}", a, b),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::table::{TableValue, TimestampValue};
+    use crate::metastore::ColumnType;
+    use crate::table::{DateValue, TableValue, TimestampValue};
     use crate::util::decimal::Decimal;
     use serde::{Deserialize, Serialize};
 
@@ -222,6 +333,8 @@ mod tests {
             TableValue::Bytes(vec![1, 2, 3]),
             TableValue::Timestamp(TimestampValue::new(123)),
             TableValue::Boolean(false),
+            TableValue::Date(DateValue::new(19000)),
+            TableValue::List(vec![TableValue::Int(1), TableValue::Null]),
         ] {
             let b = bincode::serialize(v).expect(&format!("could not serialize {:?}", v));
             let v2: TableValue =
@@ -237,4 +350,31 @@ mod tests {
             assert_eq!(v, &v2);
         }
     }
+
+    #[test]
+    fn dates() {
+        let d = DateValue::parse("2022-01-31").unwrap();
+        assert_eq!(d.get_days_since_epoch(), 19023);
+        assert_eq!(d.to_string(), "2022-01-31");
+        assert_eq!(DateValue::parse("1969-12-31").unwrap(), DateValue::new(-1));
+        assert_eq!(
+            DateValue::parse("2022-01-31T10:00:00.000Z").unwrap(),
+            DateValue::new(19023)
+        );
+        assert!(DateValue::parse("2022-02-30").is_err());
+    }
+
+    #[test]
+    fn list_to_string() {
+        let items = vec![
+            TableValue::String("a\"b".to_string()),
+            TableValue::Null,
+            TableValue::String("c".to_string()),
+        ];
+        assert_eq!(
+            TableValue::list_to_string(&items, &ColumnType::String),
+            r#"["a\"b",null,"c"]"#
+        );
+        assert_eq!(TableValue::list_to_string(&[], &ColumnType::Int), "[]");
+    }
 }
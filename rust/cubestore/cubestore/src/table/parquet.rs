@@ -1,12 +1,19 @@
 use crate::config::injection::DIService;
-use crate::metastore::Index;
+use crate::config::ConfigObj;
+use crate::metastore::table::{ParquetCompression, Table};
+use crate::metastore::{ColumnType, Index};
+use crate::table::data::evolve_batch;
+use crate::table::stats::ColumnStatsBuilder;
 use crate::CubeError;
-use arrow::array::ArrayRef;
+use arrow::array::{Array, ArrayRef, StringArray};
 use arrow::datatypes::Schema;
 use arrow::record_batch::RecordBatch;
 use datafusion::physical_plan::parquet::{NoopParquetMetadataCache, ParquetMetadataCache};
 use parquet::arrow::{ArrowReader, ArrowWriter, ParquetFileArrowReader};
+use parquet::basic::Compression;
 use parquet::file::properties::{WriterProperties, WriterVersion};
+use parquet::schema::types::ColumnPath;
+use std::collections::HashSet;
 use std::fs::File;
 use std::sync::Arc;
 
@@ -36,9 +43,42 @@ impl CubestoreParquetMetadataCache for CubestoreParquetMetadataCacheImpl {
     }
 }
 
+/// Settings for writing the files of a table, see [ParquetWriterOptions::new].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParquetWriterOptions {
+    pub compression: ParquetCompression,
+    /// If not set, dictionary encoding is picked for low-cardinality string columns.
+    pub dictionary: Option<bool>,
+}
+
+impl Default for ParquetWriterOptions {
+    fn default() -> Self {
+        ParquetWriterOptions {
+            compression: ParquetCompression::Uncompressed,
+            dictionary: None,
+        }
+    }
+}
+
+impl ParquetWriterOptions {
+    /// Table options take precedence over server defaults.
+    pub fn new(table: &Table, config: &dyn ConfigObj) -> Self {
+        ParquetWriterOptions {
+            compression: table.compression().unwrap_or(config.parquet_compression()),
+            dictionary: table.dictionary().or(config.parquet_dictionary()),
+        }
+    }
+}
+
+/// String columns where distinct values make up at most this fraction of sampled values are
+/// dictionary-encoded when the encoding is picked automatically.
+const DICTIONARY_MAX_DISTINCT_RATIO: f64 = 0.1;
+
 pub struct ParquetTableStore {
     table: Index,
     row_group_size: usize,
+    options: ParquetWriterOptions,
+    bloom_filter_columns: Vec<String>,
 }
 
 impl ParquetTableStore {
@@ -48,7 +88,7 @@ impl ParquetTableStore {
         ));
         let mut batches = Vec::new();
         for b in r.get_record_reader(self.row_group_size)? {
-            batches.push(b?)
+            batches.push(evolve_batch(b?, self.table.columns())?)
         }
         Ok(batches)
     }
@@ -59,9 +99,26 @@ impl ParquetTableStore {
         ParquetTableStore {
             table,
             row_group_size,
+            options: ParquetWriterOptions::default(),
+            bloom_filter_columns: Vec::new(),
         }
     }
 
+    pub fn with_writer_options(mut self, options: ParquetWriterOptions) -> ParquetTableStore {
+        self.options = options;
+        self
+    }
+
+    /// Columns that get bloom filters in the stats of written files.
+    pub fn with_bloom_filter_columns(mut self, columns: Vec<String>) -> ParquetTableStore {
+        self.bloom_filter_columns = columns;
+        self
+    }
+
+    pub fn column_stats_builder(&self) -> ColumnStatsBuilder {
+        ColumnStatsBuilder::new(self.table.columns(), &self.bloom_filter_columns)
+    }
+
     pub fn key_size(&self) -> u64 {
         self.table.sort_key_size()
     }
@@ -76,19 +133,45 @@ impl ParquetTableStore {
         arrow_schema(&self.table)
     }
 
-    pub fn writer_props(&self) -> WriterProperties {
-        WriterProperties::builder()
+    /// Dictionary encoding of string columns is chosen by the values of `sample` unless it is
+    /// set explicitly. Parquet defaults are used for the other columns.
+    pub fn writer_props(&self, sample: Option<&RecordBatch>) -> WriterProperties {
+        let mut props = WriterProperties::builder()
             .set_max_row_group_size(self.row_group_size)
             .set_writer_version(WriterVersion::PARQUET_2_0)
-            .build()
+            .set_compression(parquet_compression(self.options.compression));
+        match (self.options.dictionary, sample) {
+            (Some(enabled), _) => props = props.set_dictionary_enabled(enabled),
+            (None, Some(sample)) => {
+                for (i, c) in self.table.columns().iter().enumerate() {
+                    if c.get_column_type() != &ColumnType::String {
+                        continue;
+                    }
+                    let values = sample
+                        .column(i)
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap();
+                    props = props.set_column_dictionary_enabled(
+                        ColumnPath::from(c.get_name().as_str()),
+                        is_low_cardinality(values, self.row_group_size),
+                    );
+                }
+            }
+            (None, None) => {}
+        }
+        props.build()
     }
 
     pub fn write_data(&self, dest_file: &str, columns: Vec<ArrayRef>) -> Result<(), CubeError> {
         let schema = Arc::new(arrow_schema(&self.table));
         let batch = RecordBatch::try_new(schema.clone(), columns.to_vec())?;
 
-        let mut w =
-            ArrowWriter::try_new(File::create(dest_file)?, schema, Some(self.writer_props()))?;
+        let mut w = ArrowWriter::try_new(
+            File::create(dest_file)?,
+            schema,
+            Some(self.writer_props(Some(&batch))),
+        )?;
         w.write(&batch)?;
         w.close()?;
 
@@ -100,16 +183,42 @@ pub fn arrow_schema(i: &Index) -> Schema {
     Schema::new(i.columns().iter().map(|c| c.into()).collect())
 }
 
+fn parquet_compression(c: ParquetCompression) -> Compression {
+    match c {
+        ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+        ParquetCompression::Snappy => Compression::SNAPPY,
+        ParquetCompression::Gzip => Compression::GZIP,
+        ParquetCompression::Lz4 => Compression::LZ4,
+        ParquetCompression::Zstd => Compression::ZSTD,
+        ParquetCompression::Brotli => Compression::BROTLI,
+    }
+}
+
+/// Looks at the first `max_rows` values only.
+fn is_low_cardinality(values: &StringArray, max_rows: usize) -> bool {
+    let mut distinct = HashSet::new();
+    let mut non_null = 0;
+    for i in 0..values.len().min(max_rows) {
+        if values.is_null(i) {
+            continue;
+        }
+        non_null += 1;
+        distinct.insert(values.value(i));
+    }
+    distinct.len() as f64 <= non_null as f64 * DICTIONARY_MAX_DISTINCT_RATIO
+}
+
 #[cfg(test)]
 mod tests {
     extern crate test;
 
     use crate::assert_eq_columns;
+    use crate::metastore::table::ParquetCompression;
     use crate::metastore::{Column, ColumnType, Index};
     use crate::store::{compaction, ROW_GROUP_SIZE};
     use crate::table::data::{cmp_row_key_heap, concat_record_batches, rows_to_columns, to_stream};
-    use crate::table::parquet::{arrow_schema, ParquetTableStore};
-    use crate::table::{Row, TableValue};
+    use crate::table::parquet::{arrow_schema, ParquetTableStore, ParquetWriterOptions};
+    use crate::table::{DateValue, Row, TableValue};
     use crate::util::decimal::Decimal;
     use arrow::array::{
         ArrayRef, BooleanArray, Float64Array, Int64Array, Int64Decimal4Array, StringArray,
@@ -117,6 +226,7 @@ mod tests {
     };
     use arrow::record_batch::RecordBatch;
     use itertools::Itertools;
+    use parquet::basic::{Compression, Encoding};
     use parquet::data_type::DataType;
     use parquet::file::reader::FileReader;
     use parquet::file::reader::SerializedFileReader;
@@ -216,6 +326,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn writer_options() {
+        let index = Index::try_new(
+            "table".to_string(),
+            1,
+            vec![
+                Column::new("low".to_string(), ColumnType::String, 0),
+                Column::new("high".to_string(), ColumnType::String, 1),
+                Column::new("int".to_string(), ColumnType::Int, 2),
+            ],
+            3,
+            None,
+            None,
+            Index::index_type_default(),
+        )
+        .unwrap();
+        let data: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(
+                (0..1000)
+                    .map(|i| if i < 500 { "a" } else { "b" })
+                    .collect_vec(),
+            )),
+            Arc::new(StringArray::from(
+                (0..1000).map(|i| format!("value {}", i)).collect_vec(),
+            )),
+            Arc::new(Int64Array::from((0..1000).collect_vec())),
+        ];
+
+        // (compression, has dictionary) for every column.
+        let write = |options: ParquetWriterOptions| -> Vec<(Compression, bool)> {
+            let dest_file = NamedTempFile::new().unwrap();
+            ParquetTableStore::new(index.clone(), ROW_GROUP_SIZE)
+                .with_writer_options(options)
+                .write_data(dest_file.path().to_str().unwrap(), data.clone())
+                .unwrap();
+            let r = SerializedFileReader::new(dest_file.into_file()).unwrap();
+            let metadata = r.metadata();
+            metadata
+                .row_group(0)
+                .columns()
+                .iter()
+                .map(|c| {
+                    let dictionary = c.encodings().iter().any(|e| {
+                        *e == Encoding::RLE_DICTIONARY || *e == Encoding::PLAIN_DICTIONARY
+                    });
+                    (c.compression(), dictionary)
+                })
+                .collect_vec()
+        };
+
+        assert_eq!(
+            write(ParquetWriterOptions {
+                compression: ParquetCompression::Zstd,
+                dictionary: None,
+            }),
+            vec![
+                (Compression::ZSTD, true),
+                (Compression::ZSTD, false),
+                (Compression::ZSTD, true),
+            ]
+        );
+        assert_eq!(
+            write(ParquetWriterOptions {
+                compression: ParquetCompression::Snappy,
+                dictionary: Some(false),
+            }),
+            vec![
+                (Compression::SNAPPY, false),
+                (Compression::SNAPPY, false),
+                (Compression::SNAPPY, false),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn gutter() {
         let store = ParquetTableStore {
@@ -243,6 +427,8 @@ mod tests {
             )
             .unwrap(),
             row_group_size: 10,
+            options: ParquetWriterOptions::default(),
+            bloom_filter_columns: Vec::new(),
         };
         let file = NamedTempFile::new().unwrap();
         let file_name = file.path().to_str().unwrap();
@@ -271,7 +457,7 @@ mod tests {
             })
             .collect::<Vec<_>>();
         first_rows.sort_by(|a, b| cmp_row_key_heap(3, &a.values(), &b.values()));
-        let first_cols = rows_to_columns(&store.table.columns(), &first_rows);
+        let first_cols = rows_to_columns(&store.table.columns(), &first_rows).unwrap();
         store.write_data(file_name, first_cols.clone()).unwrap();
 
         let read_rows = concat_record_batches(&store.read_columns(file_name).unwrap());
@@ -296,10 +482,10 @@ mod tests {
         }
         to_split.sort_by(|a, b| cmp_row_key_heap(3, &a.values(), &b.values()));
 
-        let to_split_cols = rows_to_columns(&store.table.columns(), &to_split);
+        let to_split_cols = rows_to_columns(&store.table.columns(), &to_split).unwrap();
         let schema = Arc::new(arrow_schema(&store.table));
         let to_split_batch = RecordBatch::try_new(schema.clone(), to_split_cols.clone()).unwrap();
-        let count_min = compaction::write_to_files(
+        let (count_min, column_stats) = compaction::write_to_files(
             to_stream(to_split_batch).await,
             to_split.len(),
             ParquetTableStore::new(store.table.clone(), store.row_group_size),
@@ -336,6 +522,15 @@ mod tests {
                 )
             ]
         );
+
+        assert_eq!(column_stats.len(), 2);
+        assert_eq!(column_stats[1][0].min(), &Some(TableValue::Int(75)));
+        assert_eq!(column_stats[1][0].max(), &Some(TableValue::Int(149)));
+        assert_eq!(column_stats[1][0].null_count(), 0);
+        assert_eq!(
+            column_stats[0][0].null_count() + column_stats[1][0].null_count(),
+            read.column(0).null_count() as u64
+        );
     }
 
     #[test]
@@ -409,7 +604,53 @@ mod tests {
             Row::new(vec![TableValue::Int(2), TableValue::Bytes(vec![5, 6, 7])]),
         ];
 
-        let data = rows_to_columns(&index.columns(), &rows);
+        let data = rows_to_columns(&index.columns(), &rows).unwrap();
+
+        let w = ParquetTableStore::new(index.clone(), NUM_ROWS);
+        w.write_data(file, data.clone()).unwrap();
+        let r = concat_record_batches(&w.read_columns(file).unwrap());
+        assert_eq_columns!(r.columns(), &data);
+    }
+
+    #[test]
+    fn read_dates_int32_and_lists() {
+        const NUM_ROWS: usize = 8;
+        let index = Index::try_new(
+            "index".into(),
+            0,
+            vec![
+                Column::new("date".into(), ColumnType::Date, 0),
+                Column::new("int32".into(), ColumnType::Int32, 1),
+                Column::new(
+                    "list".into(),
+                    ColumnType::List(Box::new(ColumnType::String)),
+                    2,
+                ),
+            ],
+            2,
+            None,
+            None,
+            Index::index_type_default(),
+        )
+        .unwrap();
+
+        let file = NamedTempFile::new().unwrap();
+        let file = file.path().to_str().unwrap();
+        let rows = vec![
+            Row::new(vec![
+                TableValue::Date(DateValue::new(-1)),
+                TableValue::Int(-5),
+                TableValue::List(vec![TableValue::String("a".to_string()), TableValue::Null]),
+            ]),
+            Row::new(vec![
+                TableValue::Date(DateValue::new(19000)),
+                TableValue::Null,
+                TableValue::List(vec![]),
+            ]),
+            Row::new(vec![TableValue::Null, TableValue::Int(7), TableValue::Null]),
+        ];
+
+        let data = rows_to_columns(&index.columns(), &rows).unwrap();
 
         let w = ParquetTableStore::new(index.clone(), NUM_ROWS);
         w.write_data(file, data.clone()).unwrap();
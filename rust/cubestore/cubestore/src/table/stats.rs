@@ -0,0 +1,478 @@
+use crate::metastore::{Column, ColumnType};
+use crate::table::{cmp_same_types, TableValue, TimestampValue};
+use crate::util::bloom_filter::BloomFilter;
+use crate::util::decimal::Decimal;
+use arrow::array::{
+    Array, ArrayRef, BooleanArray, Int16Array, Int32Array, Int64Array, Int64Decimal0Array,
+    Int64Decimal10Array, Int64Decimal1Array, Int64Decimal2Array, Int64Decimal3Array,
+    Int64Decimal4Array, Int64Decimal5Array, Int8Array, StringArray, TimestampMicrosecondArray,
+};
+use arrow::compute::{max, max_string, min, min_string};
+use arrow::datatypes::{DataType, TimeUnit};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Bloom filters are not built for columns of a file with more distinct values than this. Such
+/// filters would be too large to keep in the metastore and rarely help with pruning.
+pub const BLOOM_FILTER_MAX_DISTINCT_VALUES: usize = 10_000;
+pub const BLOOM_FILTER_FALSE_POSITIVE_RATE: f64 = 0.01;
+/// Min and max of strings are truncated to about this many bytes to keep the metastore small. The
+/// min is rounded down and the max is rounded up, so these still bound the values.
+pub const MIN_MAX_STRING_MAX_BYTES: usize = 64;
+
+/// Statistics of a single column inside a chunk or the main table file of a partition, used by the
+/// query planner to skip chunks and partitions that can't match the filters. Min and max do not
+/// account for NULLs and are not exact for long strings.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub struct ColumnStats {
+    name: String,
+    column_type: ColumnType,
+    min: Option<TableValue>,
+    max: Option<TableValue>,
+    null_count: u64,
+    bloom_filter: Option<BloomFilter>,
+}
+
+impl ColumnStats {
+    /// Computes stats for `columns` from their `data`. Bloom filters are built only for columns
+    /// with names in `bloom_filter_columns`.
+    pub fn compute(
+        columns: &[Column],
+        data: &[ArrayRef],
+        bloom_filter_columns: &[String],
+    ) -> Vec<ColumnStats> {
+        let mut b = ColumnStatsBuilder::new(columns, bloom_filter_columns);
+        b.add(data);
+        b.finish()
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn column_type(&self) -> &ColumnType {
+        &self.column_type
+    }
+
+    pub fn min(&self) -> &Option<TableValue> {
+        &self.min
+    }
+
+    pub fn max(&self) -> &Option<TableValue> {
+        &self.max
+    }
+
+    pub fn null_count(&self) -> u64 {
+        self.null_count
+    }
+
+    pub fn bloom_filter(&self) -> &Option<BloomFilter> {
+        &self.bloom_filter
+    }
+
+    /// Returns false only if the column definitely has no rows with value `v`.
+    pub fn may_contain(&self, v: &TableValue) -> bool {
+        match v {
+            TableValue::Null => return self.null_count != 0,
+            _ => {}
+        }
+        if let (Some(min), Some(max)) = (&self.min, &self.max) {
+            if cmp_same_types(v, min) == Ordering::Less
+                || cmp_same_types(v, max) == Ordering::Greater
+            {
+                return false;
+            }
+        }
+        match &self.bloom_filter {
+            Some(f) => f.contains(&bloom_filter_key(v)),
+            None => true,
+        }
+    }
+}
+
+/// Accumulates [ColumnStats] of data that arrives in batches, e.g. while writing a file.
+pub struct ColumnStatsBuilder {
+    stats: Vec<ColumnStats>,
+    /// Distinct values for bloom filters. Set to `None` for columns without bloom filters and
+    /// once there are too many values.
+    distinct: Vec<Option<HashSet<TableValue>>>,
+}
+
+impl ColumnStatsBuilder {
+    pub fn new(columns: &[Column], bloom_filter_columns: &[String]) -> ColumnStatsBuilder {
+        let stats = columns
+            .iter()
+            .map(|c| ColumnStats {
+                name: c.get_name().clone(),
+                column_type: c.get_column_type().clone(),
+                min: None,
+                max: None,
+                null_count: 0,
+                bloom_filter: None,
+            })
+            .collect();
+        let distinct = columns
+            .iter()
+            .map(|c| {
+                let with_bloom_filter = supports_bloom_filter(c.get_column_type())
+                    && bloom_filter_columns.iter().any(|n| n == c.get_name());
+                with_bloom_filter.then(|| HashSet::new())
+            })
+            .collect();
+        ColumnStatsBuilder { stats, distinct }
+    }
+
+    pub fn add(&mut self, data: &[ArrayRef]) {
+        assert_eq!(self.stats.len(), data.len());
+        for ((stats, distinct), a) in self
+            .stats
+            .iter_mut()
+            .zip(self.distinct.iter_mut())
+            .zip(data.iter())
+        {
+            Self::add_column(stats, distinct, a)
+        }
+    }
+
+    fn add_column(
+        stats: &mut ColumnStats,
+        distinct: &mut Option<HashSet<TableValue>>,
+        a: &ArrayRef,
+    ) {
+        stats.null_count += a.null_count() as u64;
+        if !supports_min_max(&stats.column_type) || a.null_count() == a.len() {
+            return;
+        }
+        let (min, max) = batch_min_max(a.as_ref());
+        if stats
+            .min
+            .as_ref()
+            .map(|m| cmp_same_types(&min, m) == Ordering::Less)
+            .unwrap_or(true)
+        {
+            stats.min = Some(min);
+        }
+        if stats
+            .max
+            .as_ref()
+            .map(|m| cmp_same_types(&max, m) == Ordering::Greater)
+            .unwrap_or(true)
+        {
+            stats.max = Some(max);
+        }
+        if let Some(values) = distinct {
+            for i in 0..a.len() {
+                if !a.is_valid(i) {
+                    continue;
+                }
+                values.insert(TableValue::from_array(a.as_ref(), i));
+                if values.len() > BLOOM_FILTER_MAX_DISTINCT_VALUES {
+                    *distinct = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) -> Vec<ColumnStats> {
+        self.stats
+            .into_iter()
+            .zip(self.distinct.into_iter())
+            .map(|(mut stats, distinct)| {
+                if let Some(values) = distinct {
+                    if stats.min.is_some() {
+                        let mut filter =
+                            BloomFilter::new(values.len(), BLOOM_FILTER_FALSE_POSITIVE_RATE);
+                        for v in values.iter() {
+                            filter.insert(&bloom_filter_key(v));
+                        }
+                        stats.bloom_filter = Some(filter);
+                    }
+                }
+                stats
+            })
+            .collect()
+    }
+}
+
+/// Min and max values are collected for types supported by
+/// [crate::queryplanner::partition_filter::PartitionFilter] and for timestamps, which expire rows
+/// by TTL.
+fn supports_min_max(t: &ColumnType) -> bool {
+    match t {
+        ColumnType::String
+        | ColumnType::Timestamp
+        | ColumnType::Int
+        | ColumnType::Int32
+        | ColumnType::Int16
+        | ColumnType::Int8
+        | ColumnType::Decimal { .. }
+        | ColumnType::Boolean => true,
+        _ => false,
+    }
+}
+
+/// Min and max of the values of `a` that has at least one non-NULL value. Strings are truncated
+/// to [MIN_MAX_STRING_MAX_BYTES].
+fn batch_min_max(a: &dyn Array) -> (TableValue, TableValue) {
+    macro_rules! primitive {
+        ($array: ty, $value: expr) => {{
+            let a = a.as_any().downcast_ref::<$array>().unwrap();
+            ($value(min(a).unwrap()), $value(max(a).unwrap()))
+        }};
+    }
+    match a.data_type() {
+        DataType::Int64 => primitive!(Int64Array, TableValue::Int),
+        DataType::Int32 => primitive!(Int32Array, |v| TableValue::Int(v as i64)),
+        DataType::Int16 => primitive!(Int16Array, |v| TableValue::Int(v as i64)),
+        DataType::Int8 => primitive!(Int8Array, |v| TableValue::Int(v as i64)),
+        DataType::Int64Decimal(0) => primitive!(Int64Decimal0Array, |v| {
+            TableValue::Decimal(Decimal::new(v))
+        }),
+        DataType::Int64Decimal(1) => primitive!(Int64Decimal1Array, |v| {
+            TableValue::Decimal(Decimal::new(v))
+        }),
+        DataType::Int64Decimal(2) => primitive!(Int64Decimal2Array, |v| {
+            TableValue::Decimal(Decimal::new(v))
+        }),
+        DataType::Int64Decimal(3) => primitive!(Int64Decimal3Array, |v| {
+            TableValue::Decimal(Decimal::new(v))
+        }),
+        DataType::Int64Decimal(4) => primitive!(Int64Decimal4Array, |v| {
+            TableValue::Decimal(Decimal::new(v))
+        }),
+        DataType::Int64Decimal(5) => primitive!(Int64Decimal5Array, |v| {
+            TableValue::Decimal(Decimal::new(v))
+        }),
+        DataType::Int64Decimal(10) => primitive!(Int64Decimal10Array, |v| {
+            TableValue::Decimal(Decimal::new(v))
+        }),
+        DataType::Timestamp(TimeUnit::Microsecond, None) => {
+            primitive!(TimestampMicrosecondArray, |v| {
+                TableValue::Timestamp(TimestampValue::new(1000 * v))
+            })
+        }
+        DataType::Utf8 => {
+            let a = a.as_any().downcast_ref::<StringArray>().unwrap();
+            (
+                TableValue::String(truncate_min(min_string(a).unwrap())),
+                TableValue::String(truncate_max(max_string(a).unwrap())),
+            )
+        }
+        DataType::Boolean => {
+            let a = a.as_any().downcast_ref::<BooleanArray>().unwrap();
+            let has = |b: bool| (0..a.len()).any(|i| a.is_valid(i) && a.value(i) == b);
+            (
+                TableValue::Boolean(!has(false)),
+                TableValue::Boolean(has(true)),
+            )
+        }
+        t => panic!("Min and max are not supported for {:?}", t),
+    }
+}
+
+/// Longest prefix of `s` that fits into [MIN_MAX_STRING_MAX_BYTES]. It is never greater than `s`.
+fn truncate_min(s: &str) -> String {
+    let mut end = s.len().min(MIN_MAX_STRING_MAX_BYTES);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s[..end].to_string()
+}
+
+/// Truncated `s` with the last character incremented, so it is greater than `s`. Returns `s` as is
+/// if it's short enough or none of the characters can be incremented.
+fn truncate_max(s: &str) -> String {
+    if s.len() <= MIN_MAX_STRING_MAX_BYTES {
+        return s.to_string();
+    }
+    let mut prefix = truncate_min(s).chars().collect::<Vec<_>>();
+    while let Some(c) = prefix.pop() {
+        let next = match c {
+            // Skip surrogates, these are not valid characters.
+            '\u{D7FF}' => Some('\u{E000}'),
+            c => char::from_u32(c as u32 + 1),
+        };
+        if let Some(next) = next {
+            prefix.push(next);
+            return prefix.into_iter().collect();
+        }
+    }
+    s.to_string()
+}
+
+pub fn supports_bloom_filter(t: &ColumnType) -> bool {
+    match t {
+        ColumnType::String
+        | ColumnType::Int
+        | ColumnType::Int32
+        | ColumnType::Int16
+        | ColumnType::Int8
+        | ColumnType::Decimal { .. } => true,
+        _ => false,
+    }
+}
+
+fn bloom_filter_key(v: &TableValue) -> Vec<u8> {
+    match v {
+        TableValue::String(s) => s.as_bytes().to_vec(),
+        TableValue::Int(i) => i.to_le_bytes().to_vec(),
+        TableValue::Decimal(d) => d.raw_value().to_le_bytes().to_vec(),
+        TableValue::Boolean(b) => vec![*b as u8],
+        v => panic!("Bloom filters are not supported for {:?}", v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::TimestampValue;
+    use arrow::array::{Float64Array, Int64Array, StringArray, TimestampMicrosecondArray};
+    use std::sync::Arc;
+
+    #[test]
+    fn compute_stats() {
+        let columns = vec![
+            Column::new("id".to_string(), ColumnType::Int, 0),
+            Column::new("country".to_string(), ColumnType::String, 1),
+            Column::new("amount".to_string(), ColumnType::Float, 2),
+        ];
+        let data: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from(vec![Some(5), None, Some(-3), Some(10)])),
+            Arc::new(StringArray::from(vec![
+                Some("FR"),
+                Some("DE"),
+                Some("US"),
+                Some("DE"),
+            ])),
+            Arc::new(Float64Array::from(vec![None, Some(1.), None, Some(2.)])),
+        ];
+        let stats = ColumnStats::compute(&columns, &data, &["country".to_string()]);
+        assert_eq!(stats.len(), 3);
+
+        assert_eq!(stats[0].name(), "id");
+        assert_eq!(stats[0].min(), &Some(TableValue::Int(-3)));
+        assert_eq!(stats[0].max(), &Some(TableValue::Int(10)));
+        assert_eq!(stats[0].null_count(), 1);
+        assert!(stats[0].bloom_filter().is_none());
+        assert!(stats[0].may_contain(&TableValue::Int(7)));
+        assert!(stats[0].may_contain(&TableValue::Null));
+        assert!(!stats[0].may_contain(&TableValue::Int(11)));
+
+        assert_eq!(stats[1].min(), &Some(TableValue::String("DE".to_string())));
+        assert_eq!(stats[1].max(), &Some(TableValue::String("US".to_string())));
+        assert_eq!(stats[1].null_count(), 0);
+        assert!(stats[1].bloom_filter().is_some());
+        assert!(stats[1].may_contain(&TableValue::String("FR".to_string())));
+        assert!(!stats[1].may_contain(&TableValue::String("AA".to_string())));
+        assert!(!stats[1].may_contain(&TableValue::Null));
+
+        // No min/max for floats, only null counts.
+        assert_eq!(stats[2].min(), &None);
+        assert_eq!(stats[2].max(), &None);
+        assert_eq!(stats[2].null_count(), 2);
+    }
+
+    #[test]
+    fn bloom_filter_limit() {
+        let columns = vec![Column::new("id".to_string(), ColumnType::Int, 0)];
+        let data: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(
+            (0..BLOOM_FILTER_MAX_DISTINCT_VALUES as i64 + 1).collect::<Vec<_>>(),
+        ))];
+        let stats = ColumnStats::compute(&columns, &data, &["id".to_string()]);
+        assert!(stats[0].bloom_filter().is_none());
+        assert_eq!(
+            stats[0].max(),
+            &Some(TableValue::Int(BLOOM_FILTER_MAX_DISTINCT_VALUES as i64))
+        );
+    }
+
+    #[test]
+    fn truncated_strings() {
+        let columns = vec![Column::new("s".to_string(), ColumnType::String, 0)];
+        let long = |c: char| c.to_string().repeat(MIN_MAX_STRING_MAX_BYTES + 10);
+        let data: Vec<ArrayRef> = vec![Arc::new(StringArray::from(vec![
+            Some(long('b').as_str()),
+            None,
+            Some(long('y').as_str()),
+            Some("c"),
+        ]))];
+        let stats = ColumnStats::compute(&columns, &data, &[]);
+        let prefix = |c: char| c.to_string().repeat(MIN_MAX_STRING_MAX_BYTES - 1);
+        assert_eq!(
+            stats[0].min(),
+            &Some(TableValue::String(format!("{}b", prefix('b'))))
+        );
+        assert_eq!(
+            stats[0].max(),
+            &Some(TableValue::String(format!("{}z", prefix('y'))))
+        );
+        assert!(stats[0].may_contain(&TableValue::String(long('b'))));
+        assert!(stats[0].may_contain(&TableValue::String(long('y'))));
+        assert!(!stats[0].may_contain(&TableValue::String("a".to_string())));
+        assert!(!stats[0].may_contain(&TableValue::String("z".to_string())));
+
+        // Multi-byte characters are not split and can't be incremented past the last one.
+        assert_eq!(
+            truncate_min(&"é".repeat(MIN_MAX_STRING_MAX_BYTES)).len(),
+            MIN_MAX_STRING_MAX_BYTES
+        );
+        assert_eq!(
+            truncate_min(&format!("a{}", "é".repeat(MIN_MAX_STRING_MAX_BYTES))).len(),
+            MIN_MAX_STRING_MAX_BYTES - 1
+        );
+        let max = char::MAX.to_string().repeat(20);
+        assert_eq!(truncate_max(&max), max);
+    }
+
+    #[test]
+    fn build_stats_from_batches() {
+        let columns = vec![
+            Column::new("id".to_string(), ColumnType::Int, 0),
+            Column::new("ts".to_string(), ColumnType::Timestamp, 1),
+        ];
+        let batches: Vec<Vec<ArrayRef>> = vec![
+            vec![
+                Arc::new(Int64Array::from(vec![Some(3), None])),
+                Arc::new(TimestampMicrosecondArray::from(vec![Some(2_000), None])),
+            ],
+            vec![
+                Arc::new(Int64Array::from(vec![Some(1), Some(7)])),
+                Arc::new(TimestampMicrosecondArray::from(vec![
+                    Some(5_000),
+                    Some(1_000),
+                ])),
+            ],
+        ];
+        let mut builder = ColumnStatsBuilder::new(&columns, &["id".to_string()]);
+        for b in &batches {
+            builder.add(b);
+        }
+        let stats = builder.finish();
+
+        let all: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from(vec![Some(3), None, Some(1), Some(7)])),
+            Arc::new(TimestampMicrosecondArray::from(vec![
+                Some(2_000),
+                None,
+                Some(5_000),
+                Some(1_000),
+            ])),
+        ];
+        assert_eq!(
+            stats,
+            ColumnStats::compute(&columns, &all, &["id".to_string()])
+        );
+        assert_eq!(stats[0].null_count(), 1);
+        assert!(stats[0].may_contain(&TableValue::Int(7)));
+        assert_eq!(
+            stats[1].min(),
+            &Some(TableValue::Timestamp(TimestampValue::new(1_000_000)))
+        );
+        assert_eq!(
+            stats[1].max(),
+            &Some(TableValue::Timestamp(TimestampValue::new(5_000_000)))
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+/// Probabilistic set of byte strings. [BloomFilter::contains] never returns false for inserted
+/// keys, but may return true for keys that were never inserted.
+///
+/// Filters are persisted in the metastore, so hashing must stay stable between versions. We use
+/// two seeded FNV-1a hashes combined with double hashing instead of [std::hash::Hasher], whose
+/// output is not guaranteed to be stable.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    const MAX_HASHES: u32 = 16;
+
+    /// Creates a filter able to hold `num_items` keys with the given false positive rate.
+    pub fn new(num_items: usize, false_positive_rate: f64) -> BloomFilter {
+        assert!(0. < false_positive_rate && false_positive_rate < 1.);
+        let num_items = num_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-num_items * false_positive_rate.ln() / (ln2 * ln2)).ceil() as usize;
+        let num_words = (num_bits + 63) / 64;
+        let num_hashes = ((64 * num_words) as f64 / num_items * ln2).round() as u32;
+        BloomFilter {
+            bits: vec![0; num_words.max(1)],
+            num_hashes: num_hashes.max(1).min(Self::MAX_HASHES),
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        let num_bits = self.num_bits();
+        let (h1, h2) = Self::hashes(key);
+        for i in 0..self.num_hashes as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % num_bits;
+            self.bits[(bit / 64) as usize] |= 1u64 << (bit % 64);
+        }
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        let num_bits = self.num_bits();
+        let (h1, h2) = Self::hashes(key);
+        (0..self.num_hashes as u64).all(|i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % num_bits;
+            self.bits[(bit / 64) as usize] & (1u64 << (bit % 64)) != 0
+        })
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    fn num_bits(&self) -> u64 {
+        self.bits.len() as u64 * 64
+    }
+
+    fn hashes(key: &[u8]) -> (u64, u64) {
+        let h1 = fnv1a(key, 0xcbf29ce484222325);
+        // Odd step, so that probes do not collapse into a single bit.
+        let h2 = fnv1a(key, 0x84222325cbf29ce4) | 1;
+        (h1, h2)
+    }
+}
+
+fn fnv1a(key: &[u8], seed: u64) -> u64 {
+    const PRIME: u64 = 0x100000001b3;
+    let mut h = seed;
+    for b in key {
+        h ^= *b as u64;
+        h = h.wrapping_mul(PRIME);
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives() {
+        let mut f = BloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            f.insert(format!("key-{}", i).as_bytes());
+        }
+        for i in 0..1000 {
+            assert!(f.contains(format!("key-{}", i).as_bytes()));
+        }
+
+        let false_positives = (1000..11000)
+            .filter(|i| f.contains(format!("key-{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn sizing() {
+        let f = BloomFilter::new(0, 0.01);
+        assert_eq!(f.size_bytes(), 8);
+        assert!(!f.contains(b"a"));
+
+        let f = BloomFilter::new(10000, 0.01);
+        assert_eq!(f.size_bytes(), 11984);
+        assert_eq!(f.num_hashes, 7);
+    }
+}
@@ -3,8 +3,8 @@
 //! Can operate in `statsd`-compatible mode, see [init_metrics].
 //!
 //! Applications should call [init_metrics] once during global init. To report metrics, create one
-//! with [counter], [gauge], [histogram] or [distribution] and send updates by calling corresponding
-//! methods on the created objects. See DataDog documentation for more information on different
+//! with [counter], [gauge], [labeled_gauge], [histogram] or [distribution] and send updates by
+//! calling corresponding methods on the created objects. See DataDog documentation for more information on different
 //! metric types.
 //!
 //! Code does not do any sampling or buffering at the time. Too frequent metric updates can cause
@@ -14,10 +14,16 @@
 //!
 //! Note that misconfiguration (invalid port, address, etc) can cause metric updates to be silently
 //! ignored. This is by design to avoid interrupting normal operation.
+//!
+//! Independently of the UDP client, reported values are accumulated in-process and can be scraped
+//! in the Prometheus text format, see [write_prometheus].
 use crate::CubeError;
+use std::collections::BTreeMap;
+use std::fmt::Display;
 use std::net::ToSocketAddrs;
 use std::net::UdpSocket;
-use std::sync::Once;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Mutex, Once};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Compatibility {
@@ -48,6 +54,11 @@ pub const fn gauge(name: &'static str) -> IntMetric {
     }
 }
 
+/// A gauge with one value per combination of `labels`, e.g. per table.
+pub const fn labeled_gauge(name: &'static str, labels: &'static [&'static str]) -> LabeledGauge {
+    LabeledGauge { name, labels }
+}
+
 pub const fn histogram(name: &'static str) -> IntMetric {
     IntMetric {
         metric: Metric::new(name, MetricType::Histogram),
@@ -65,15 +76,21 @@ pub struct Counter {
 }
 
 impl Counter {
-    pub fn add(&self, v: i64) {
+    pub fn add(&'static self, v: i64) {
+        self.metric.record(v);
         if let Some(s) = sink() {
             s.send(&self.metric, v)
         }
     }
 
-    pub fn increment(&self) {
+    pub fn increment(&'static self) {
         self.add(1)
     }
+
+    /// Exposes the metric to Prometheus before anything is reported.
+    pub fn register(&'static self) {
+        self.metric.register()
+    }
 }
 
 pub struct IntMetric {
@@ -81,11 +98,54 @@ pub struct IntMetric {
 }
 
 impl IntMetric {
-    pub fn report(&self, v: i64) {
+    pub fn report(&'static self, v: i64) {
+        self.metric.record(v);
         if let Some(s) = sink() {
             s.send(&self.metric, v)
         }
     }
+
+    /// Exposes the metric to Prometheus before anything is reported.
+    pub fn register(&'static self) {
+        self.metric.register()
+    }
+}
+
+pub struct LabeledGauge {
+    name: &'static str,
+    labels: &'static [&'static str],
+}
+
+impl LabeledGauge {
+    /// Replaces all values of the gauge. Label sets missing in `values` stop being reported.
+    /// Each value must have one label value per label name.
+    pub fn report_all(&'static self, values: Vec<(Vec<String>, i64)>) {
+        if let Some(s) = sink() {
+            for (label_values, v) in values.iter() {
+                s.send_labeled(self, label_values, *v)
+            }
+        }
+        registry::set_labeled(self, values.into_iter().collect());
+    }
+
+    /// Exposes the metric to Prometheus before anything is reported.
+    pub fn register(&'static self) {
+        registry::register_labeled(self)
+    }
+
+    fn write_prometheus(&self, values: &BTreeMap<Vec<String>, i64>, out: &mut PrometheusWriter) {
+        let name = prometheus_name(self.name);
+        out.header(&name, "gauge");
+        for (label_values, v) in values {
+            let labels = self
+                .labels
+                .iter()
+                .zip(label_values.iter())
+                .map(|(k, v)| (*k, v.as_str()))
+                .collect::<Vec<_>>();
+            out.sample(&name, &labels, v);
+        }
+    }
 }
 
 pub type Gauge = IntMetric;
@@ -102,11 +162,192 @@ enum MetricType {
 pub struct Metric {
     name: &'static str,
     kind: MetricType,
+    local: LocalValue,
 }
 
 impl Metric {
     const fn new(name: &'static str, kind: MetricType) -> Metric {
-        Metric { name, kind }
+        Metric {
+            name,
+            kind,
+            local: LocalValue::new(),
+        }
+    }
+
+    fn register(&'static self) {
+        if !self.local.registered.swap(true, Ordering::Relaxed) {
+            registry::register(self);
+        }
+    }
+
+    fn record(&'static self, v: i64) {
+        self.register();
+        let l = &self.local;
+        match self.kind {
+            MetricType::Counter => {
+                l.value.fetch_add(v, Ordering::Relaxed);
+            }
+            MetricType::Gauge => l.value.store(v, Ordering::Relaxed),
+            MetricType::Histogram | MetricType::Distribution => {
+                l.value.fetch_add(v, Ordering::Relaxed);
+                l.count.fetch_add(1, Ordering::Relaxed);
+                if let Some(i) = BUCKETS.iter().position(|b| v <= *b) {
+                    l.buckets[i].fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    fn write_prometheus(&self, out: &mut PrometheusWriter) {
+        let name = prometheus_name(self.name);
+        let l = &self.local;
+        let value = l.value.load(Ordering::Relaxed);
+        match self.kind {
+            MetricType::Counter => {
+                out.header(&name, "counter");
+                out.sample(&name, &[], value);
+            }
+            MetricType::Gauge => {
+                out.header(&name, "gauge");
+                out.sample(&name, &[], value);
+            }
+            MetricType::Histogram | MetricType::Distribution => {
+                // Load the count first, so buckets never go above it.
+                let count = l.count.load(Ordering::Relaxed);
+                out.header(&name, "histogram");
+                let bucket_name = format!("{}_bucket", name);
+                let mut cumulative = 0;
+                for (b, c) in BUCKETS.iter().zip(l.buckets.iter()) {
+                    cumulative += c.load(Ordering::Relaxed);
+                    out.sample(
+                        &bucket_name,
+                        &[("le", &b.to_string())],
+                        cumulative.min(count),
+                    );
+                }
+                out.sample(&bucket_name, &[("le", "+Inf")], count);
+                out.sample(&format!("{}_sum", name), &[], value);
+                out.sample(&format!("{}_count", name), &[], count);
+            }
+        }
+    }
+}
+
+/// Upper bounds of the Prometheus histogram buckets. Most of our histograms report milliseconds.
+const BUCKETS: [i64; 14] = [
+    1, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000,
+];
+
+/// Values accumulated for the Prometheus endpoint.
+struct LocalValue {
+    registered: AtomicBool,
+    /// Total for counters, last value for gauges, sum of values for histograms.
+    value: AtomicI64,
+    count: AtomicU64,
+    buckets: [AtomicU64; BUCKETS.len()],
+}
+
+impl LocalValue {
+    const fn new() -> LocalValue {
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        LocalValue {
+            registered: AtomicBool::new(false),
+            value: AtomicI64::new(0),
+            count: AtomicU64::new(0),
+            buckets: [ZERO; BUCKETS.len()],
+        }
+    }
+}
+
+/// Writes all metrics reported so far in the Prometheus text format.
+pub fn write_prometheus(out: &mut PrometheusWriter) {
+    let mut metrics = registry::all();
+    metrics.sort_by_key(|m| m.name);
+    for m in metrics {
+        m.write_prometheus(out)
+    }
+    for (m, values) in registry::all_labeled() {
+        m.write_prometheus(&values, out)
+    }
+}
+
+/// Converts our dot-separated names, e.g. `cs.sql.query.data`, into valid Prometheus names.
+pub fn prometheus_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Builds a response in the Prometheus text exposition format.
+pub struct PrometheusWriter {
+    out: String,
+}
+
+impl PrometheusWriter {
+    pub fn new() -> PrometheusWriter {
+        PrometheusWriter { out: String::new() }
+    }
+
+    pub fn header(&mut self, name: &str, kind: &str) {
+        self.out += &format!("# TYPE {} {}\n", name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.out += name;
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+                .collect::<Vec<_>>();
+            self.out += &format!("{{{}}}", labels.join(","));
+        }
+        self.out += &format!(" {}\n", value);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape_label_value(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+mod registry {
+    use super::*;
+
+    pub(super) type LabeledValues = (&'static LabeledGauge, BTreeMap<Vec<String>, i64>);
+
+    lazy_static! {
+        static ref METRICS: Mutex<Vec<&'static Metric>> = Mutex::new(Vec::new());
+        static ref LABELED: Mutex<BTreeMap<&'static str, LabeledValues>> =
+            Mutex::new(BTreeMap::new());
+    }
+
+    pub(super) fn register(m: &'static Metric) {
+        METRICS.lock().unwrap().push(m)
+    }
+
+    pub(super) fn all() -> Vec<&'static Metric> {
+        METRICS.lock().unwrap().clone()
+    }
+
+    pub(super) fn register_labeled(m: &'static LabeledGauge) {
+        LABELED
+            .lock()
+            .unwrap()
+            .entry(m.name)
+            .or_insert_with(|| (m, BTreeMap::new()));
+    }
+
+    pub(super) fn set_labeled(m: &'static LabeledGauge, values: BTreeMap<Vec<String>, i64>) {
+        LABELED.lock().unwrap().insert(m.name, (m, values));
+    }
+
+    pub(super) fn all_labeled() -> Vec<LabeledValues> {
+        LABELED.lock().unwrap().values().cloned().collect()
     }
 }
 
@@ -142,6 +383,23 @@ impl Sink {
             .socket
             .send(format!("{}:{}|{}", m.name, value, kind).as_bytes());
     }
+
+    fn send_labeled(&self, m: &LabeledGauge, label_values: &[String], value: i64) {
+        // StatsD has no tags, values with different labels would overwrite each other.
+        if self.mode == Compatibility::StatsD {
+            return;
+        }
+        let tags = m
+            .labels
+            .iter()
+            .zip(label_values.iter())
+            .map(|(k, v)| format!("{}:{}", k, v))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = self
+            .socket
+            .send(format!("{}:{}|g|#{}", m.name, value, tags).as_bytes());
+    }
 }
 
 mod global_sink {
@@ -177,3 +435,57 @@ mod global_sink {
 }
 
 use global_sink::sink;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prometheus() {
+        static COUNTER: Counter = counter("cs.test.counter");
+        static GAUGE: Gauge = gauge("cs.test.gauge");
+        static HISTOGRAM: Histogram = histogram("cs.test.histogram");
+        COUNTER.add(2);
+        COUNTER.increment();
+        GAUGE.report(10);
+        GAUGE.report(7);
+        HISTOGRAM.report(3);
+        HISTOGRAM.report(7);
+        HISTOGRAM.report(100_000);
+
+        let mut w = PrometheusWriter::new();
+        COUNTER.metric.write_prometheus(&mut w);
+        GAUGE.metric.write_prometheus(&mut w);
+        HISTOGRAM.metric.write_prometheus(&mut w);
+        w.sample("cs_test_labels", &[("table", "a\"b")], 1);
+        static UNREPORTED: Counter = counter("cs.test.unreported");
+        UNREPORTED.register();
+        static LABELED: LabeledGauge = labeled_gauge("cs.test.labeled", &["schema", "table"]);
+        LABELED.report_all(vec![
+            (vec!["s".to_string(), "t1".to_string()], 5),
+            (vec!["s".to_string(), "t_dropped".to_string()], 0),
+        ]);
+        LABELED.report_all(vec![(vec!["s".to_string(), "t1".to_string()], 3)]);
+        let text = w.finish();
+        assert!(text.contains("# TYPE cs_test_counter counter\ncs_test_counter 3\n"));
+        assert!(text.contains("# TYPE cs_test_gauge gauge\ncs_test_gauge 7\n"));
+        assert!(text.contains("# TYPE cs_test_histogram histogram\n"));
+        assert!(text.contains("cs_test_histogram_bucket{le=\"1\"} 0\n"));
+        assert!(text.contains("cs_test_histogram_bucket{le=\"5\"} 1\n"));
+        assert!(text.contains("cs_test_histogram_bucket{le=\"60000\"} 2\n"));
+        assert!(text.contains("cs_test_histogram_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("cs_test_histogram_sum 100010\n"));
+        assert!(text.contains("cs_test_histogram_count 3\n"));
+        assert!(text.contains("cs_test_labels{table=\"a\\\"b\"} 1\n"));
+
+        let mut w = PrometheusWriter::new();
+        write_prometheus(&mut w);
+        let text = w.finish();
+        assert!(text.contains("cs_test_counter 3"));
+        assert!(text.contains("# TYPE cs_test_unreported counter\ncs_test_unreported 0\n"));
+        assert!(text.contains(
+            "# TYPE cs_test_labeled gauge\ncs_test_labeled{schema=\"s\",table=\"t1\"} 3\n"
+        ));
+        assert!(!text.contains("t_dropped"));
+    }
+}
@@ -33,6 +33,17 @@ impl NormalEncoding {
          "valid index and rhoW can only be determined for precisions in the range [1, 63], but got {}", precision);
         return NormalEncoding { precision };
     }
+
+    /// Computes the HyperLogLog++ index of a uniform hash, i.e. its upper *p* bits.
+    pub fn index(&self, hash: u64) -> i32 {
+        return (hash >> (64 - self.precision)) as i32;
+    }
+
+    /// Computes the HyperLogLog++ *ρ(w)* of a uniform hash, i.e. the number of leading zeros + 1
+    /// in the bits following the index.
+    pub fn rho_w(&self, hash: u64) -> u8 {
+        return compute_rho_w(hash, 64 - self.precision);
+    }
 }
 
 /// An object that computes HyperLogLog++ properties for the sparse encoding at a given precision.
@@ -119,6 +130,24 @@ impl SparseEncoding {
         );
     }
 
+    /// Encodes a uniform hash into a sparse value. See the struct docs for details on the two
+    /// representations with which sparse values are encoded.
+    pub fn encode(&self, hash: u64) -> i32 {
+        let sparse_index = (hash >> (64 - self.sparse_precision)) as i32;
+
+        // If the last sp-p bits of the sparse index are not all zero, the normal rhoW can be
+        // determined from them and the sparse index is stored as is.
+        let mask = (1 << (self.sparse_precision - self.normal_precision)) - 1;
+        if (sparse_index & mask) != 0 {
+            return sparse_index;
+        }
+
+        // Otherwise, store the normal index along with the rhoW' relative to the sparse precision.
+        let normal_index = sparse_index >> (self.sparse_precision - self.normal_precision);
+        let sparse_rho_w = compute_rho_w(hash, 64 - self.sparse_precision) as i32;
+        return self.rho_encoded_flag | normal_index << Self::RHOW_BITS | sparse_rho_w;
+    }
+
     /// Decodes the sparse index from an encoded sparse value. See the class Javadoc for details on
     /// the two representations with which sparse values are encoded.
     pub(crate) fn decode_sparse_index(&self, sparse_value: i32) -> i32 {
@@ -95,6 +95,25 @@ impl HyperLogLogPlusPlus {
     /** The encoding version of the `AggregatorStateProto`. We only support v2. */
     const ENCODING_VERSION: i32 = 2;
 
+    /// Value type of the sketches filled by [add_hash] with Murmur3 hashes. Never used by
+    /// ZetaSketch, so such sketches can't be mixed up with the ones hashed with Fingerprint2011.
+    pub const MURMUR3_VALUE_TYPE: i32 = 0x4d55_5233; // "MUR3" in ASCII.
+
+    /// Creates an empty aggregator with the given normal precision and the default sparse
+    /// precision. It's marked with [MURMUR3_VALUE_TYPE] and is only compatible with other such
+    /// aggregators.
+    pub fn new(precision: i32) -> Result<HyperLogLogPlusPlus> {
+        let state = State {
+            type_: AGGREGATOR_TYPE_HYPERLOGLOG_PLUS_UNIQUE,
+            encoding_version: Self::ENCODING_VERSION,
+            value_type: Self::MURMUR3_VALUE_TYPE,
+            precision,
+            sparse_precision: precision + Self::DEFAULT_SPARSE_PRECISION_DELTA,
+            ..State::default()
+        };
+        return Self::from_state(state);
+    }
+
     /// Creates a new HyperLogLog++ aggregator from the serialized `proto`.
     ///
     /// `proto` is a valid aggregator state of type `AggregatorType::HYPERLOGLOG_PLUS_UNIQUE`.
@@ -121,9 +140,34 @@ impl HyperLogLogPlusPlus {
         }
     }
 
+    /// Adds an element with a precomputed 64-bit uniform hash.
+    ///
+    /// Note that hashes are not compatible with sketches built by BigQuery or ZetaSketch, which hash
+    /// values with Fingerprint2011. Use it only on aggregators created with [new], they can't be
+    /// merged with such sketches.
+    pub fn add_hash(&mut self, hash: u64) -> Result<()> {
+        self.state.num_values += 1;
+        let new_repr = match &mut self.representation {
+            Representation::Sparse(r) => r.add_hash(&mut self.state, hash)?,
+            Representation::Normal(r) => {
+                r.add_hash(&mut self.state, hash);
+                return Ok(());
+            }
+        };
+        if let Some(n) = new_repr {
+            self.representation = Representation::Normal(n)
+        }
+        return Ok(());
+    }
+
     pub fn is_compatible(&self, other: &HyperLogLogPlusPlus) -> bool {
         return self.state.precision == other.state.precision
-            && self.state.sparse_precision == other.state.sparse_precision;
+            && self.state.sparse_precision == other.state.sparse_precision
+            && self.has_murmur3_hashes() == other.has_murmur3_hashes();
+    }
+
+    fn has_murmur3_hashes(&self) -> bool {
+        return self.state.value_type == Self::MURMUR3_VALUE_TYPE;
     }
 
     /// Will crash if `self.is_compatible(other)` returns false.
@@ -135,6 +179,11 @@ impl HyperLogLogPlusPlus {
       other.state.sparse_precision, other
                                             .state.precision)));
         }
+        if self.has_murmur3_hashes() != other.has_murmur3_hashes() {
+            return Err(ZetaError::new(
+                "Sketches built from Murmur3 hashes can't be merged with ZetaSketch ones",
+            ));
+        }
         self.state.num_values += other.state.num_values;
 
         let new_repr: Option<NormalRepresentation>;